@@ -0,0 +1,106 @@
+// Time and allocations per message for parse_dns_message_full; run with
+// cargo bench --bench dns
+extern crate blosh;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use blosh::dns::{parse_dns_message_full, parse_dns_message_full_in, Arena, Mode};
+use blosh::testgen::{self, Endpoint};
+
+struct Counting;
+
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const ITERATIONS: usize = 100_000;
+
+fn name(out: &mut Vec<u8>, labels: &[&str], pointer: Option<u16>) {
+    for label in labels {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    match pointer {
+        Some(off) => out.extend_from_slice(&(0xc000 | off).to_be_bytes()),
+        None => out.push(0),
+    }
+}
+
+// a referral for example.com: 13 NS records and an A record of glue for
+// each, every name compressed against the question or an earlier record
+fn referral() -> Vec<u8> {
+    let mut msg = vec![0x12, 0x34, 0x81, 0x00, 0, 1, 0, 0, 0, 13, 0, 13];
+    name(&mut msg, &["www", "example", "com"], None);
+    msg.extend_from_slice(&[0, 1, 0, 1]);
+    let mut servers = vec![];
+    for i in 0..13u8 {
+        name(&mut msg, &[], Some(16));
+        msg.extend_from_slice(&[0, 2, 0, 1, 0, 2, 0xa3, 0]);
+        let mut rdata = vec![];
+        name(&mut rdata, &[&((b'a' + i) as char).to_string(), "ns"], Some(16));
+        msg.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        servers.push(msg.len() as u16);
+        msg.extend_from_slice(&rdata);
+    }
+    for (i, &server) in servers.iter().enumerate() {
+        name(&mut msg, &[], Some(server));
+        msg.extend_from_slice(&[0, 1, 0, 1, 0, 2, 0xa3, 0, 0, 4, 192, 0, 2, i as u8]);
+    }
+    msg
+}
+
+fn bench<F: FnMut(&[u8])>(label: &str, msg: &[u8], mut parse: F) {
+    assert!(parse_dns_message_full(msg).is_ok(), "{} doesn't parse", label);
+    parse(msg);
+    let allocs = ALLOCS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        parse(black_box(msg));
+    }
+    let elapsed = start.elapsed();
+    let allocs = ALLOCS.load(Ordering::Relaxed) - allocs;
+    println!("{:<18} {:>4} bytes {:>8.0} ns/msg {:>8.1} allocs/msg", label, msg.len(),
+             elapsed.as_nanos() as f64 / ITERATIONS as f64, allocs as f64 / ITERATIONS as f64);
+}
+
+fn main() {
+    let (query, response) = testgen::dns_exchange(&Endpoint::client(), &Endpoint::server(),
+                                                  1, "www.example.com", Ipv4Addr::new(93, 184, 216, 34));
+    // past the ethernet, ip and udp headers
+    let full = |msg: &[u8]| {
+        black_box(parse_dns_message_full(msg).ok());
+    };
+    // recycling each message, like a capture loop would
+    let mut arena = Arena::new();
+    let mut in_arena = |msg: &[u8]| {
+        if let Ok((_, msg)) = parse_dns_message_full_in(Mode::Strict, &mut arena, msg) {
+            arena.recycle(black_box(msg));
+        }
+    };
+    let referral = referral();
+    for &(label, msg) in &[("query", &query[42..]), ("cname", &response[42..]), ("referral", &referral[..])] {
+        bench(label, msg, full);
+        bench(&format!("{} (arena)", label), msg, &mut in_arena);
+    }
+}
@@ -0,0 +1,109 @@
+/* C bindings for blosh's parsers, built with the "ffi" feature:
+ *
+ *     cargo rustc --release --features ffi --crate-type cdylib
+ *
+ * Header structs are filled in by value. DNS messages are opaque handles,
+ * read through accessors and freed with blosh_dns_free. Names and record
+ * data are written snprintf style: as much as fits, NUL terminated, and
+ * the full length is returned.
+ */
+#ifndef BLOSH_H
+#define BLOSH_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define BLOSH_OK 0
+#define BLOSH_EINVAL -1
+#define BLOSH_EPARSE -2
+
+#define BLOSH_SECTION_ANSWER 0
+#define BLOSH_SECTION_AUTHORITY 1
+#define BLOSH_SECTION_ADDITIONAL 2
+
+typedef struct {
+    uint8_t header_len;
+    uint8_t dscp;
+    uint8_t ecn;
+    uint8_t df;
+    uint8_t mf;
+    uint8_t ttl;
+    uint8_t proto;
+    uint16_t total_len;
+    uint16_t id;
+    uint16_t fragment_off;
+    uint16_t checksum;
+    uint8_t src[4];
+    uint8_t dst[4];
+} BloshIpv4Header;
+
+typedef struct {
+    uint8_t traffic_class;
+    uint8_t next_header;
+    uint8_t hop_limit;
+    uint32_t flow_label;
+    uint16_t payload_len;
+    uint16_t body_offset;
+    uint8_t src[16];
+    uint8_t dst[16];
+} BloshIpv6Header;
+
+typedef struct {
+    uint16_t src_port;
+    uint16_t dst_port;
+    uint32_t seq;
+    uint32_t ack;
+    uint8_t header_len;
+    uint8_t flags;
+    uint8_t ns;
+    uint16_t window;
+    uint16_t checksum;
+    uint16_t urgent;
+} BloshTcpHeader;
+
+typedef struct {
+    uint16_t src_port;
+    uint16_t dst_port;
+    uint16_t len;
+    uint16_t checksum;
+} BloshUdpHeader;
+
+typedef struct {
+    uint16_t qtype;
+    uint16_t qclass;
+} BloshDnsQuestion;
+
+typedef struct {
+    uint16_t rtype;
+    uint16_t rclass;
+    uint32_t ttl;
+} BloshDnsRecord;
+
+typedef struct BloshDnsMessage BloshDnsMessage;
+
+int blosh_parse_ipv4(const uint8_t *data, size_t len, BloshIpv4Header *out);
+int blosh_parse_ipv6(const uint8_t *data, size_t len, BloshIpv6Header *out);
+int blosh_parse_tcp(const uint8_t *data, size_t len, BloshTcpHeader *out);
+int blosh_parse_udp(const uint8_t *data, size_t len, BloshUdpHeader *out);
+
+BloshDnsMessage *blosh_parse_dns(const uint8_t *data, size_t len, int lenient);
+void blosh_dns_free(BloshDnsMessage *msg);
+int blosh_dns_id(const BloshDnsMessage *msg);
+int blosh_dns_flags(const BloshDnsMessage *msg);
+int blosh_dns_question_count(const BloshDnsMessage *msg);
+int blosh_dns_question(const BloshDnsMessage *msg, size_t i, BloshDnsQuestion *out);
+int blosh_dns_question_name(const BloshDnsMessage *msg, size_t i, char *buf, size_t buf_len);
+int blosh_dns_record_count(const BloshDnsMessage *msg, int section);
+int blosh_dns_record(const BloshDnsMessage *msg, int section, size_t i, BloshDnsRecord *out);
+int blosh_dns_record_name(const BloshDnsMessage *msg, int section, size_t i, char *buf, size_t buf_len);
+int blosh_dns_record_data(const BloshDnsMessage *msg, int section, size_t i, char *buf, size_t buf_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,318 @@
+use dissect::{self, ApplicationLayer, LinkType, Packet};
+use {IpPacket, TransportLayerPacket};
+
+/// Where a parsed field sits in the original buffer. Fields that share
+/// bytes with others, like the IPv4 version and header length, carry a
+/// `mask` over the big endian value of their `len` bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Field {
+    // named like the JSON export's fields
+    pub name: &'static str,
+    pub offset: usize,
+    pub len: usize,
+    pub mask: Option<u32>,
+}
+
+impl Field {
+    /// The bytes of the field
+    pub fn bytes<'a>(&self, bs: &'a [u8]) -> &'a [u8] {
+        &bs[self.offset..self.offset + self.len]
+    }
+
+    pub fn contains(&self, offset: usize) -> bool {
+        self.offset <= offset && offset < self.offset + self.len
+    }
+}
+
+/// Like `parse_packet`, but also returns the byte range of every field
+pub fn parse_packet_annotated<'a>(link_type: LinkType, bs: &'a [u8]) -> (Packet<'a>, Vec<Field>) {
+    let packet = dissect::parse_packet(link_type, bs);
+    let fields = annotate(&packet, bs);
+    (packet, fields)
+}
+
+/// The fields that cover byte `offset`, outermost layer first
+pub fn fields_at(fields: &[Field], offset: usize) -> Vec<&Field> {
+    fields.iter().filter(|f| f.contains(offset)).collect()
+}
+
+// offset of a subslice within the buffer it was parsed from
+fn offset_of(bs: &[u8], sub: &[u8]) -> Option<usize> {
+    let start = bs.as_ptr() as usize;
+    let sub_start = sub.as_ptr() as usize;
+    if sub_start >= start && sub_start + sub.len() <= start + bs.len() {
+        Some(sub_start - start)
+    } else {
+        None
+    }
+}
+
+struct Fields {
+    fields: Vec<Field>,
+    base: usize,
+}
+
+impl Fields {
+    fn at(&mut self, base: usize) -> &mut Fields {
+        self.base = base;
+        self
+    }
+
+    fn add(&mut self, name: &'static str, offset: usize, len: usize) -> &mut Fields {
+        self.fields.push(Field {
+            name,
+            offset: self.base + offset,
+            len,
+            mask: None,
+        });
+        self
+    }
+
+    fn bits(&mut self, name: &'static str, offset: usize, len: usize, mask: u32) -> &mut Fields {
+        self.fields.push(Field {
+            name,
+            offset: self.base + offset,
+            len,
+            mask: Some(mask),
+        });
+        self
+    }
+}
+
+/// Works out the byte range of each field of an already dissected packet.
+/// `bs` has to be the buffer the packet was parsed from.
+pub fn annotate(packet: &Packet, bs: &[u8]) -> Vec<Field> {
+    let mut f = Fields { fields: vec![], base: 0 };
+
+    // where the IP header starts
+    let mut ip_start = 0;
+    if let Some(ref eth) = packet.ethernet {
+        f.at(0).add("eth.dst", 0, 6).add("eth.src", 6, 6);
+        let mut off = 12;
+        for _ in &eth.vlans {
+            f.at(off)
+                .add("vlan.etype", 0, 2)
+                .bits("vlan.priority", 2, 2, 0xe000)
+                .bits("vlan.dei", 2, 2, 0x1000)
+                .bits("vlan.id", 2, 2, 0x0fff);
+            off += 4;
+        }
+        f.at(off).add("eth.type", 0, 2);
+        ip_start = off + 2;
+    }
+
+    let ip = match packet.ip {
+        Some(ref ip) => ip,
+        None => return finish(f, packet, bs),
+    };
+    let ip_body = offset_of(bs, ip.body());
+    match *ip {
+        IpPacket::V4(ref ip4) => {
+            f.at(ip_start)
+                .bits("ip.version", 0, 1, 0xf0)
+                .bits("ip.hdr_len", 0, 1, 0x0f)
+                .bits("ip.dsfield.dscp", 1, 1, 0xfc)
+                .bits("ip.dsfield.ecn", 1, 1, 0x03)
+                .add("ip.len", 2, 2)
+                .add("ip.id", 4, 2)
+                .bits("ip.flags.df", 6, 1, 0x40)
+                .bits("ip.flags.mf", 6, 1, 0x20)
+                .bits("ip.frag_offset", 6, 2, 0x1fff)
+                .add("ip.ttl", 8, 1)
+                .add("ip.proto", 9, 1)
+                .add("ip.checksum", 10, 2)
+                .add("ip.src", 12, 4)
+                .add("ip.dst", 16, 4);
+            let header_len = 4*ip4.header.len as usize;
+            if header_len > 20 {
+                f.add("ip.options", 20, header_len - 20);
+            }
+        },
+        IpPacket::V6(_) => {
+            f.at(ip_start)
+                .bits("ipv6.version", 0, 1, 0xf0)
+                .bits("ipv6.tclass", 0, 2, 0x0ff0)
+                .bits("ipv6.flow", 1, 3, 0x0fffff)
+                .add("ipv6.plen", 4, 2)
+                .add("ipv6.nxt", 6, 1)
+                .add("ipv6.hlim", 7, 1)
+                .add("ipv6.src", 8, 16)
+                .add("ipv6.dst", 24, 16);
+            if let Some(body) = ip_body {
+                if body > ip_start + 40 {
+                    f.add("ipv6.extensions", 40, body - ip_start - 40);
+                }
+            }
+        },
+    }
+
+    let transport_start = match ip_body {
+        Some(start) => start,
+        None => return finish(f, packet, bs),
+    };
+    match packet.transport {
+        Some(TransportLayerPacket::Tcp(ref tcp)) => {
+            f.at(transport_start)
+                .add("tcp.srcport", 0, 2)
+                .add("tcp.dstport", 2, 2)
+                .add("tcp.seq", 4, 4)
+                .add("tcp.ack", 8, 4)
+                .bits("tcp.hdr_len", 12, 1, 0xf0)
+                .bits("tcp.flags", 12, 2, 0x01ff)
+                .add("tcp.window_size", 14, 2)
+                .add("tcp.checksum", 16, 2)
+                .add("tcp.urgent_pointer", 18, 2);
+            let header_len = 4*tcp.header.flags.offset as usize;
+            if header_len > 20 {
+                f.add("tcp.options", 20, header_len - 20);
+            }
+        },
+        Some(TransportLayerPacket::Udp(_)) => {
+            f.at(transport_start)
+                .add("udp.srcport", 0, 2)
+                .add("udp.dstport", 2, 2)
+                .add("udp.length", 4, 2)
+                .add("udp.checksum", 6, 2);
+        },
+        Some(TransportLayerPacket::Icmp(ref icmp)) => {
+            f.at(transport_start)
+                .add("icmp.type", 0, 1)
+                .add("icmp.code", 1, 1)
+                .add("icmp.checksum", 2, 2);
+            if icmp.header.echo_id_seq().is_some() {
+                f.add("icmp.ident", 4, 2).add("icmp.seq", 6, 2);
+            } else {
+                f.add("icmp.rest_of_header", 4, 4);
+            }
+        },
+        None => {},
+    }
+
+    finish(f, packet, bs)
+}
+
+// the application layer, or whatever's left over as data
+fn finish(mut f: Fields, packet: &Packet, bs: &[u8]) -> Vec<Field> {
+    let start = match offset_of(bs, packet.payload) {
+        Some(start) => start,
+        None => return f.fields,
+    };
+    let len = packet.payload.len();
+    // the application layer runs from the start of the transport body up to
+    // the payload left after it
+    let app_start = match packet.transport {
+        Some(TransportLayerPacket::Tcp(ref tcp)) => offset_of(bs, tcp.body),
+        Some(TransportLayerPacket::Udp(ref udp)) => offset_of(bs, udp.body),
+        _ => None,
+    };
+    match (&packet.application, app_start) {
+        (&Some(ApplicationLayer::Dns(_)), Some(app_start)) => {
+            let end = start;
+            // DNS over TCP has a length prefix in front of the message
+            let start = match packet.transport {
+                Some(TransportLayerPacket::Tcp(_)) => {
+                    f.at(app_start).add("dns.length", 0, 2);
+                    app_start + 2
+                },
+                _ => app_start,
+            };
+            f.at(start)
+                .add("dns.id", 0, 2)
+                .bits("dns.flags.response", 2, 2, 0x8000)
+                .bits("dns.flags.opcode", 2, 2, 0x7800)
+                .bits("dns.flags.authoritative", 2, 2, 0x0400)
+                .bits("dns.flags.truncated", 2, 2, 0x0200)
+                .bits("dns.flags.recdesired", 2, 2, 0x0100)
+                .bits("dns.flags.recavail", 2, 2, 0x0080)
+                .bits("dns.flags.rcode", 2, 2, 0x000f)
+                .add("dns.count.queries", 4, 2)
+                .add("dns.count.answers", 6, 2)
+                .add("dns.count.auth_rr", 8, 2)
+                .add("dns.count.add_rr", 10, 2);
+            if end > start + 12 {
+                f.add("dns.records", 12, end - start - 12);
+            }
+        },
+        (&Some(ApplicationLayer::Dhcp(ref msg)), Some(app_start)) => {
+            let len = start - app_start;
+            f.at(app_start)
+                .add("dhcp.type", 0, 1)
+                .add("dhcp.hw.type", 1, 1)
+                .add("dhcp.hw.len", 2, 1)
+                .add("dhcp.hops", 3, 1)
+                .add("dhcp.id", 4, 4)
+                .add("dhcp.secs", 8, 2)
+                .bits("dhcp.flags.bc", 10, 2, 0x8000)
+                .add("dhcp.ip.client", 12, 4)
+                .add("dhcp.ip.your", 16, 4)
+                .add("dhcp.ip.server", 20, 4)
+                .add("dhcp.ip.relay", 24, 4)
+                .add("dhcp.hw.mac_addr", 28, msg.chaddr.len())
+                .add("dhcp.server", 44, 64)
+                .add("dhcp.file", 108, 128);
+            if len > 240 {
+                f.add("dhcp.cookie", 236, 4).add("dhcp.options", 240, len - 240);
+            }
+        },
+        (&Some(ApplicationLayer::HttpRequest(_)), Some(app_start)) |
+        (&Some(ApplicationLayer::HttpResponse(_)), Some(app_start)) => {
+            // the payload is what follows the head in the segment
+            let name = match packet.application {
+                Some(ApplicationLayer::HttpRequest(_)) => "http.request",
+                _ => "http.response",
+            };
+            f.at(app_start).add(name, 0, start - app_start);
+            if len > 0 {
+                f.at(start).add("http.file_data", 0, len);
+            }
+        },
+        (&Some(_), _) => {},
+        (&None, _) => if len > 0 {
+            f.at(start).add("data", 0, len);
+        },
+    }
+    f.fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_annotate_udp() {
+        let frame = [
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0x81, 0x00, 0x20, 0x64,
+            0x08, 0x00,
+
+            0x45, 0x00, 0x00, 0x1e, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x01,
+            0x08, 0x08, 0x08, 0x08,
+
+            0x10, 0xd8, 0x1f, 0x90, 0x00, 0x0a, 0x00, 0x00,
+
+            0xca, 0xfe,
+        ];
+        let (_, fields) = parse_packet_annotated(LinkType::Ethernet, &frame);
+        let field = |name| *fields.iter().find(|f| f.name == name).unwrap();
+
+        assert_eq!(field("vlan.id"), Field { name: "vlan.id", offset: 14, len: 2, mask: Some(0x0fff) });
+        assert_eq!(field("ip.ttl").bytes(&frame), &[0x40]);
+        assert_eq!(field("ip.src").bytes(&frame), &[0x0a, 0x00, 0x00, 0x01]);
+        assert_eq!(field("udp.dstport").bytes(&frame), &[0x1f, 0x90]);
+        assert_eq!(field("data").offset, 46);
+
+        let names: Vec<_> = fields_at(&fields, 18).iter().map(|f| f.name).collect();
+        assert_eq!(names, vec!["ip.version", "ip.hdr_len"]);
+    }
+
+    #[test]
+    fn test_annotate_dns() {
+        let (_, response) = ::testgen::dns_exchange(&::testgen::Endpoint::client(), &::testgen::Endpoint::server(), 1,
+                                                    "www.example.com", "93.184.216.34".parse().unwrap());
+        let (_, fields) = parse_packet_annotated(LinkType::Ethernet, &response);
+        let field = |name| *fields.iter().find(|f| f.name == name).unwrap();
+
+        assert_eq!(field("dns.id").offset, 42);
+        assert_eq!(field("dns.records").offset + field("dns.records").len, response.len());
+        assert!(fields.iter().all(|f| f.name != "data"));
+    }
+}
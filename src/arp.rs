@@ -0,0 +1,236 @@
+use std::fmt;
+use std::net::Ipv4Addr;
+
+use nom::bytes::complete::take;
+use nom::error::context;
+use nom::number::complete::{be_u8, be_u16};
+
+use dissect::Packet;
+use error::IResult;
+use ethernet::{EtherType, MacAddr};
+use {Fixup, ToBytes};
+
+// https://tools.ietf.org/html/rfc826
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ArpPacket<'a> {
+    // 1 for Ethernet
+    pub hardware_type: u16,
+    pub protocol_type: EtherType,
+    pub operation: Operation,
+    pub sender_hw: &'a [u8],
+    pub sender_proto: &'a [u8],
+    pub target_hw: &'a [u8],
+    pub target_proto: &'a [u8],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Operation {
+    Request,
+    Reply,
+    Other(u16),
+}
+
+impl Operation {
+    pub fn from_u16(v: u16) -> Operation {
+        match v {
+            1 => Operation::Request,
+            2 => Operation::Reply,
+            x => Operation::Other(x),
+        }
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        match *self {
+            Operation::Request => 1,
+            Operation::Reply => 2,
+            Operation::Other(x) => x,
+        }
+    }
+}
+
+/// What an ARP packet is for, as RFC 5227 tells them apart
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ArpKind {
+    Request,
+    Reply,
+    // a request from 0.0.0.0, checking whether the target address is taken
+    Probe,
+    // a request for the sender's own address, claiming it
+    Announcement,
+    // a reply for the sender's own address, which some hosts send instead
+    // of announcements
+    GratuitousReply,
+}
+
+pub fn parse_arp_packet(bs: &[u8]) -> IResult<'_, ArpPacket<'_>> {
+    context("arp", arp_packet)(bs)
+}
+
+fn arp_packet(bs: &[u8]) -> IResult<'_, ArpPacket<'_>> {
+    let (bs, hardware_type) = be_u16(bs)?;
+    let (bs, protocol_type) = be_u16(bs)?;
+    let (bs, hlen) = be_u8(bs)?;
+    let (bs, plen) = be_u8(bs)?;
+    let (bs, operation) = be_u16(bs)?;
+    let (bs, sender_hw) = take(hlen)(bs)?;
+    let (bs, sender_proto) = take(plen)(bs)?;
+    let (bs, target_hw) = take(hlen)(bs)?;
+    let (bs, target_proto) = take(plen)(bs)?;
+    Ok((bs, ArpPacket {
+        hardware_type,
+        protocol_type: EtherType::from_u16(protocol_type),
+        operation: Operation::from_u16(operation),
+        sender_hw,
+        sender_proto,
+        target_hw,
+        target_proto,
+    }))
+}
+
+fn ipv4(bs: &[u8]) -> Option<Ipv4Addr> {
+    if bs.len() == 4 {
+        Some(Ipv4Addr::new(bs[0], bs[1], bs[2], bs[3]))
+    } else {
+        None
+    }
+}
+
+impl <'a> ArpPacket<'a> {
+    /// An Ethernet and IPv4 packet, ready to be written out with `ToBytes`
+    pub fn new(operation: Operation, sender_hw: &'a [u8], sender_proto: &'a [u8],
+               target_hw: &'a [u8], target_proto: &'a [u8]) -> ArpPacket<'a> {
+        ArpPacket {
+            hardware_type: 1,
+            protocol_type: EtherType::Ipv4,
+            operation,
+            sender_hw,
+            sender_proto,
+            target_hw,
+            target_proto,
+        }
+    }
+
+    /// The sender's MAC address, if the hardware addresses are Ethernet ones
+    pub fn sender_mac(&self) -> Option<MacAddr> {
+        MacAddr::from_slice(self.sender_hw)
+    }
+
+    pub fn target_mac(&self) -> Option<MacAddr> {
+        MacAddr::from_slice(self.target_hw)
+    }
+
+    /// The sender's IPv4 address, if the protocol addresses are IPv4 ones
+    pub fn sender_ip(&self) -> Option<Ipv4Addr> {
+        ipv4(self.sender_proto)
+    }
+
+    pub fn target_ip(&self) -> Option<Ipv4Addr> {
+        ipv4(self.target_proto)
+    }
+
+    /// Classifies an IPv4 ARP packet per RFC 5227, section 2.1 and 2.3
+    pub fn kind(&self) -> Option<ArpKind> {
+        let (sender, target) = match (self.sender_ip(), self.target_ip()) {
+            (Some(sender), Some(target)) => (sender, target),
+            _ => return None,
+        };
+        match self.operation {
+            Operation::Request if sender.is_unspecified() => Some(ArpKind::Probe),
+            Operation::Request if sender == target => Some(ArpKind::Announcement),
+            Operation::Request => Some(ArpKind::Request),
+            Operation::Reply if sender == target => Some(ArpKind::GratuitousReply),
+            Operation::Reply => Some(ArpKind::Reply),
+            Operation::Other(_) => None,
+        }
+    }
+
+    /// Whether the sender is telling everyone about its own address, rather
+    /// than asking about someone else's: an announcement or gratuitous reply
+    pub fn is_gratuitous(&self) -> bool {
+        matches!(self.kind(), Some(ArpKind::Announcement) | Some(ArpKind::GratuitousReply))
+    }
+
+    pub fn is_probe(&self) -> bool {
+        self.kind() == Some(ArpKind::Probe)
+    }
+
+    pub fn is_announcement(&self) -> bool {
+        self.kind() == Some(ArpKind::Announcement)
+    }
+}
+
+/// tcpdump style summary, like "Request who-has 10.0.0.2 tell 10.0.0.1"
+impl <'a> fmt::Display for ArpPacket<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (sender, target) = match (self.sender_ip(), self.target_ip()) {
+            (Some(sender), Some(target)) => (sender, target),
+            _ => return write!(f, "ARP, {:?}", self.operation),
+        };
+        match self.kind() {
+            Some(ArpKind::Probe) => write!(f, "Probe who-has {}", target),
+            Some(ArpKind::Announcement) => write!(f, "Announcement {}", sender),
+            Some(ArpKind::Request) => write!(f, "Request who-has {} tell {}", target, sender),
+            Some(ArpKind::Reply) | Some(ArpKind::GratuitousReply) => match self.sender_mac() {
+                Some(mac) => write!(f, "Reply {} is-at {}", sender, mac),
+                None => write!(f, "Reply {}", sender),
+            },
+            None => write!(f, "ARP, {:?}", self.operation),
+        }
+    }
+}
+
+impl <'a> ToBytes for ArpPacket<'a> {
+    fn write_with(&self, _fixup: Fixup, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.hardware_type.to_be_bytes());
+        out.extend_from_slice(&self.protocol_type.to_u16().to_be_bytes());
+        out.push(self.sender_hw.len() as u8);
+        out.push(self.sender_proto.len() as u8);
+        out.extend_from_slice(&self.operation.to_u16().to_be_bytes());
+        out.extend_from_slice(self.sender_hw);
+        out.extend_from_slice(self.sender_proto);
+        out.extend_from_slice(self.target_hw);
+        out.extend_from_slice(self.target_proto);
+    }
+}
+
+/// Parses the body of an ARP Ethernet frame
+pub fn detect<'a>(packet: &Packet<'a>) -> Option<ArpPacket<'a>> {
+    match packet.ethernet {
+        Some(ref eth) if eth.ethertype == EtherType::Arp => parse_arp_packet(eth.body).ok().map(|(_, arp)| arp),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dissect::{parse_packet, LinkType};
+    use ethernet::EthernetIIPacket;
+
+    #[test]
+    fn test_classify() {
+        let mac = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55];
+        let zero = [0; 6];
+        let ip = [192, 168, 1, 10];
+        let probe = ArpPacket::new(Operation::Request, &mac, &[0, 0, 0, 0], &zero, &ip).to_bytes();
+        let frame = EthernetIIPacket::new(MacAddr::BROADCAST, MacAddr(mac), EtherType::Arp, &probe).to_bytes();
+        let arp = detect(&parse_packet(LinkType::Ethernet, &frame)).unwrap();
+        assert_eq!(arp.kind(), Some(ArpKind::Probe));
+        assert!(arp.is_probe() && !arp.is_gratuitous());
+        assert_eq!(arp.to_string(), "Probe who-has 192.168.1.10");
+
+        let announcement = ArpPacket::new(Operation::Request, &mac, &ip, &zero, &ip);
+        assert!(announcement.is_announcement() && announcement.is_gratuitous());
+        let reply = ArpPacket::new(Operation::Reply, &mac, &ip, &MacAddr::BROADCAST.0, &ip);
+        assert_eq!(reply.kind(), Some(ArpKind::GratuitousReply));
+        assert_eq!(reply.to_string(), "Reply 192.168.1.10 is-at 00:11:22:33:44:55");
+        let request = ArpPacket::new(Operation::Request, &mac, &ip, &zero, &[192, 168, 1, 1]);
+        assert_eq!(request.kind(), Some(ArpKind::Request));
+        let bytes = request.to_bytes();
+        let (_, parsed) = parse_arp_packet(&bytes).unwrap();
+        assert_eq!(parsed, request);
+    }
+}
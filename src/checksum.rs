@@ -0,0 +1,99 @@
+// https://tools.ietf.org/html/rfc1071
+use ipv4::Ipv4Protocol;
+use icmpv6;
+use tcp;
+use udp;
+use IpHeader;
+
+/// Adds `data` to a running ones' complement sum, treating it as a sequence
+/// of big-endian 16-bit words. Odd-length data is padded with a zero byte.
+pub fn ones_complement_sum(mut sum: u32, data: &[u8]) -> u32 {
+    for chunk in data.chunks(2) {
+        let word = if chunk.len() == 2 {
+            ((chunk[0] as u32) << 8) | (chunk[1] as u32)
+        } else {
+            (chunk[0] as u32) << 8
+        };
+        sum += word;
+        if sum > 0xffff {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+    }
+    sum
+}
+
+/// Folds a running sum down to 16 bits and takes its complement
+pub fn finish(mut sum: u32) -> u16 {
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+pub fn internet_checksum(data: &[u8]) -> u16 {
+    finish(ones_complement_sum(0, data))
+}
+
+/// The CRC-32 of ISO 3309, used by the Ethernet FCS, STUN fingerprints and
+/// zlib among others
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Sum of the TCP/UDP pseudo-header for the given IP header, upper layer
+/// protocol number, and upper layer length
+pub fn pseudo_header_sum(ip: &IpHeader, proto: u8, len: u32) -> u32 {
+    match *ip {
+        IpHeader::V4(header) => {
+            let mut sum = ones_complement_sum(0, &header.src_ip.octets());
+            sum = ones_complement_sum(sum, &header.dst_ip.octets());
+            ones_complement_sum(sum, &[0, proto, (len >> 8) as u8, len as u8])
+        },
+        IpHeader::V6(header) => {
+            let mut sum = ones_complement_sum(0, &header.src_ip.octets());
+            sum = ones_complement_sum(sum, &header.dst_ip.octets());
+            ones_complement_sum(sum, &[
+                (len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8,
+                0, 0, 0, proto,
+            ])
+        },
+    }
+}
+
+/// Computes the checksum of a transport segment as if the checksum field at
+/// `checksum_offset` were zero
+pub fn transport_checksum(ip: &IpHeader, proto: u8, segment: &[u8], checksum_offset: usize) -> u16 {
+    let mut sum = pseudo_header_sum(ip, proto, segment.len() as u32);
+    sum = ones_complement_sum(sum, &segment[..checksum_offset]);
+    sum = ones_complement_sum(sum, &segment[checksum_offset + 2..]);
+    finish(sum)
+}
+
+/// Checks a transport segment, checksum field included, against its
+/// pseudo-header
+pub fn verify_transport_checksum(ip: &IpHeader, proto: u8, segment: &[u8]) -> bool {
+    let sum = pseudo_header_sum(ip, proto, segment.len() as u32);
+    finish(ones_complement_sum(sum, segment)) == 0
+}
+
+/// Rewrites the checksum of a TCP or UDP segment, or an ICMPv6 message, in
+/// place to match its contents and pseudo-header. Other protocols, and
+/// segments too short to hold a header, are left alone.
+pub fn fix_transport_checksum(ip: &IpHeader, proto: Ipv4Protocol, segment: &mut [u8]) {
+    let (sum, offset) = match proto {
+        Ipv4Protocol::Tcp => (tcp::compute_checksum(ip, segment), 16),
+        Ipv4Protocol::Udp => (udp::compute_checksum(ip, segment), 6),
+        Ipv4Protocol::Other(58) => (icmpv6::compute_checksum(ip, segment), 2),
+        _ => return,
+    };
+    if let Some(sum) = sum {
+        segment[offset..offset + 2].copy_from_slice(&sum.to_be_bytes());
+    }
+}
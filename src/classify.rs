@@ -0,0 +1,294 @@
+use std::cmp::Reverse;
+use std::fmt;
+
+use dns;
+use ipv4::Ipv4Protocol;
+use rtp;
+use ssh;
+use stun;
+use tcp;
+use tls::{self, ContentType, HandshakeType};
+use udp;
+use IpPacket;
+
+// Guesses at the protocol of a flow from the first bytes of a payload,
+// without looking at ports. Each check only needs one payload, ideally the
+// first one in its direction; later ones in the middle of a stream will
+// mostly come back empty.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Protocol {
+    Tls,
+    Http,
+    Ssh,
+    Dns,
+    Quic,
+    Rtp,
+    BitTorrent,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Protocol::Tls => "TLS",
+            Protocol::Http => "HTTP",
+            Protocol::Ssh => "SSH",
+            Protocol::Dns => "DNS",
+            Protocol::Quic => "QUIC",
+            Protocol::Rtp => "RTP",
+            Protocol::BitTorrent => "BitTorrent",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// How sure a guess is. `High` means a whole message parsed, or a magic
+/// string matched; `Low` means the payload merely fits, which lots of
+/// random bytes would too.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Guess {
+    pub protocol: Protocol,
+    pub confidence: Confidence,
+}
+
+impl fmt::Display for Guess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({:?})", self.protocol, self.confidence)
+    }
+}
+
+/// Guesses at the protocol of a TCP or UDP packet's payload, most likely
+/// first. Empty for anything else, and for payloads nothing matched.
+pub fn classify(packet: &IpPacket) -> Vec<Guess> {
+    match packet.protocol() {
+        Some(Ipv4Protocol::Tcp) => match tcp::parse_tcp_packet(packet.body()) {
+            Ok((_, segment)) => classify_tcp(segment.body),
+            Err(_) => vec![],
+        },
+        Some(Ipv4Protocol::Udp) => match udp::parse_udp_packet(packet.body()) {
+            Ok((_, datagram)) => classify_udp(datagram.body),
+            Err(_) => vec![],
+        },
+        _ => vec![],
+    }
+}
+
+/// Guesses at the protocol of a TCP payload, most likely first
+pub fn classify_tcp(payload: &[u8]) -> Vec<Guess> {
+    let checks: [Check; 5] = [tls, http, ssh, dns_tcp, bittorrent_tcp];
+    rank(&checks, payload)
+}
+
+/// Guesses at the protocol of a UDP payload, most likely first
+pub fn classify_udp(payload: &[u8]) -> Vec<Guess> {
+    let checks: [Check; 4] = [quic, dns, bittorrent_udp, rtp];
+    rank(&checks, payload)
+}
+
+type Check = fn(&[u8]) -> Option<Guess>;
+
+// every check that matched, best first; ties keep the order of `checks`
+fn rank(checks: &[Check], payload: &[u8]) -> Vec<Guess> {
+    let mut guesses: Vec<Guess> = checks.iter().filter_map(|check| check(payload)).collect();
+    guesses.sort_by_key(|guess| Reverse(guess.confidence));
+    guesses
+}
+
+fn guess(protocol: Protocol, confidence: Confidence) -> Option<Guess> {
+    Some(Guess { protocol, confidence })
+}
+
+fn tls(bs: &[u8]) -> Option<Guess> {
+    // a record header with a version from SSL 3.0 to TLS 1.2, which is the
+    // most TLS 1.3 puts there, and a length no bigger than a record can be
+    let plausible = bs.len() >= 5 && bs[1] == 3 && bs[2] <= 4
+        && u16::from_be_bytes([bs[3], bs[4]]) <= 16384 + 2048;
+    if !plausible {
+        return None;
+    }
+    match tls::parse_tls_record(bs) {
+        Ok((_, record)) if record.content_type == ContentType::Handshake => {
+            match tls::parse_handshake(record.fragment) {
+                Ok((_, handshake)) if handshake.typ == HandshakeType::ClientHello
+                    && tls::parse_client_hello(handshake.body).is_ok() => guess(Protocol::Tls, Confidence::High),
+                Ok((_, handshake)) if handshake.typ == HandshakeType::ServerHello
+                    && tls::parse_server_hello(handshake.body).is_ok() => guess(Protocol::Tls, Confidence::High),
+                _ => guess(Protocol::Tls, Confidence::Medium),
+            }
+        },
+        // a handshake record that hasn't all arrived yet
+        Err(nom::Err::Incomplete(_)) if bs[0] == ContentType::Handshake.to_u8() => {
+            guess(Protocol::Tls, Confidence::Medium)
+        },
+        _ if (20..=24).contains(&bs[0]) => guess(Protocol::Tls, Confidence::Low),
+        _ => None,
+    }
+}
+
+const METHODS: [&[u8]; 9] = [b"GET", b"POST", b"HEAD", b"PUT", b"DELETE", b"OPTIONS", b"PATCH", b"CONNECT", b"TRACE"];
+
+fn http(bs: &[u8]) -> Option<Guess> {
+    if bs.starts_with(b"HTTP/1.") {
+        return guess(Protocol::Http, Confidence::High);
+    }
+    let method = METHODS.iter().find(|m| bs.starts_with(m) && bs.get(m.len()) == Some(&b' '))?;
+    // the version at the end of the request line, if it's there yet
+    let line = &bs[..bs.iter().position(|&b| b == b'\r').unwrap_or(bs.len())];
+    if line.windows(6).any(|w| w == b"HTTP/1") || line.ends_with(b"HTTP/2.0") {
+        guess(Protocol::Http, Confidence::High)
+    } else {
+        guess(Protocol::Http, if method.len() > 3 { Confidence::Medium } else { Confidence::Low })
+    }
+}
+
+fn ssh(bs: &[u8]) -> Option<Guess> {
+    if !bs.starts_with(b"SSH-") {
+        return None;
+    }
+    match ssh::parse_banner(bs) {
+        Ok(_) => guess(Protocol::Ssh, Confidence::High),
+        Err(_) => guess(Protocol::Ssh, Confidence::Medium),
+    }
+}
+
+// a query or response that parses and fills the payload exactly, and asks
+// about one name, which is all anything sends in practice. Over UDP it's
+// the whole payload.
+fn dns(bs: &[u8]) -> Option<Guess> {
+    let (rest, message) = dns::parse_dns_message(bs).ok()?;
+    if !rest.is_empty() {
+        return None;
+    }
+    match message.questions.len() {
+        1 => guess(Protocol::Dns, Confidence::High),
+        _ => guess(Protocol::Dns, Confidence::Low),
+    }
+}
+
+// over TCP, with the two byte length in front
+fn dns_tcp(bs: &[u8]) -> Option<Guess> {
+    if bs.len() < 2 || usize::from(u16::from_be_bytes([bs[0], bs[1]])) != bs.len() - 2 {
+        return None;
+    }
+    dns(&bs[2..])
+}
+
+fn bittorrent_tcp(bs: &[u8]) -> Option<Guess> {
+    // the peer wire handshake
+    if bs.starts_with(b"\x13BitTorrent protocol") {
+        return guess(Protocol::BitTorrent, Confidence::High);
+    }
+    None
+}
+
+fn bittorrent_udp(bs: &[u8]) -> Option<Guess> {
+    // a UDP tracker connect request, BEP 15
+    if bs.len() >= 16 && bs[..8] == [0x00, 0x00, 0x04, 0x17, 0x27, 0x10, 0x19, 0x80] {
+        return guess(Protocol::BitTorrent, Confidence::High);
+    }
+    // a DHT message, BEP 5, which is a bencoded dictionary with a "y" key
+    if bs.starts_with(b"d1:") && bs.ends_with(b"e") && bs.windows(5).any(|w| w == b"1:y1:") {
+        return guess(Protocol::BitTorrent, Confidence::High);
+    }
+    // a uTP header, BEP 29: version 1, one of the five packet types, and
+    // an extension type that exists
+    if bs.len() >= 20 && bs[0] & 0x0f == 1 && bs[0] >> 4 <= 4 && bs[1] <= 2 {
+        return guess(Protocol::BitTorrent, Confidence::Low);
+    }
+    None
+}
+
+// https://tools.ietf.org/html/rfc9000#section-17.2 and RFC 8999; short
+// header packets have nothing to go on
+fn quic(bs: &[u8]) -> Option<Guess> {
+    // the long header form and fixed bits, a version and a destination
+    // connection ID no longer than RFC 9000 allows
+    if bs.len() < 7 || bs[0] & 0x80 == 0 || bs[5] > 20 {
+        return None;
+    }
+    let version = u32::from_be_bytes([bs[1], bs[2], bs[3], bs[4]]);
+    match version {
+        // version negotiation, which leaves the fixed bit out
+        0 => guess(Protocol::Quic, Confidence::Medium),
+        _ if bs[0] & 0x40 == 0 => None,
+        // v1, v2 and the drafts of v1
+        0x0000_0001 | 0x6b33_43cf | 0xff00_0000..=0xff00_00ff => guess(Protocol::Quic, Confidence::High),
+        _ => guess(Protocol::Quic, Confidence::Low),
+    }
+}
+
+fn rtp(bs: &[u8]) -> Option<Guess> {
+    if stun::is_stun(bs) {
+        return None;
+    }
+    let (_, packet) = rtp::parse_rtp_packet(bs).ok()?;
+    match packet.header.payload_type {
+        // RTCP's packet types, which look like these with the marker bit
+        72..=76 => None,
+        // the static audio and video types and the dynamic range
+        0..=34 | 96..=127 => guess(Protocol::Rtp, Confidence::Medium),
+        _ => guess(Protocol::Rtp, Confidence::Low),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_tcp() {
+        let high = |protocol| vec![Guess { protocol, confidence: Confidence::High }];
+        assert_eq!(classify_tcp(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n"), high(Protocol::Http));
+        assert_eq!(classify_tcp(b"HTTP/1.1 200 OK\r\n"), high(Protocol::Http));
+        assert_eq!(classify_tcp(b"SSH-2.0-OpenSSH_9.6\r\n"), high(Protocol::Ssh));
+        assert_eq!(classify_tcp(b"\x13BitTorrent protocol\0\0\0\0\0\0\0\0"), high(Protocol::BitTorrent));
+
+        // a ClientHello with no extensions, then the same cut short
+        let hello = [
+            0x16, 0x03, 0x01, 0x00, 0x2d, 0x01, 0x00, 0x00, 0x29, 0x03, 0x03,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x02, 0x13, 0x01, 0x01, 0x00,
+        ];
+        assert_eq!(classify_tcp(&hello), high(Protocol::Tls));
+        assert_eq!(classify_tcp(&hello[..20]), vec![Guess { protocol: Protocol::Tls, confidence: Confidence::Medium }]);
+
+        // a DNS query for example.com A, with its length
+        let query = [
+            0x00, 0x1d, 0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00, 0x00, 0x01, 0x00, 0x01,
+        ];
+        assert_eq!(classify_tcp(&query), high(Protocol::Dns));
+        assert_eq!(classify_udp(&query[2..]), high(Protocol::Dns));
+
+        assert!(classify_tcp(b"").is_empty());
+        assert!(classify_tcp(b"hello there").is_empty());
+    }
+
+    #[test]
+    fn test_classify_udp() {
+        // a QUIC v1 Initial, as far as the connection IDs
+        let initial = [0xc3, 0x00, 0x00, 0x00, 0x01, 0x08, 1, 2, 3, 4, 5, 6, 7, 8, 0x00, 0x00];
+        assert_eq!(classify_udp(&initial), vec![Guess { protocol: Protocol::Quic, confidence: Confidence::High }]);
+
+        let dht = b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe";
+        assert_eq!(classify_udp(dht), vec![Guess { protocol: Protocol::BitTorrent, confidence: Confidence::High }]);
+
+        // RTP with PCMU and a few payload bytes
+        let rtp = [0x80, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0xa0, 0xde, 0xad, 0xbe, 0xef, 0xff, 0xff, 0xff, 0xff];
+        assert_eq!(classify_udp(&rtp), vec![Guess { protocol: Protocol::Rtp, confidence: Confidence::Medium }]);
+        // and RTCP isn't
+        let rtcp = [0x80, 0xc8, 0x00, 0x06, 0xde, 0xad, 0xbe, 0xef, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert!(classify_udp(&rtcp).is_empty());
+    }
+}
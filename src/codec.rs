@@ -0,0 +1,233 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use dissect::{self, LinkType, OwnedPacket, Packet};
+use dns;
+use stream::{Decode, DecodeError};
+use {IntoOwned, ToBytes};
+
+// the most a DnsTcpCodec message can be
+const DNS_MAX_LEN: usize = 0xffff;
+
+// the default limit on a PacketCodec frame, past jumbo frames with room
+// to spare
+const DEFAULT_MAX_PACKET_LEN: usize = 1 << 18;
+
+/// Why a codec gave up on a stream
+#[derive(Debug)]
+pub enum CodecError {
+    Io(io::Error),
+    // offsets are counted from the start of the frame
+    Decode(DecodeError),
+    // a frame was longer than the codec allows
+    TooLong(usize),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CodecError::Io(ref e) => write!(f, "{}", e),
+            CodecError::Decode(ref e) => write!(f, "{}", e),
+            CodecError::TooLong(len) => write!(f, "frame of {} bytes is too long", len),
+        }
+    }
+}
+
+impl error::Error for CodecError {}
+
+impl From<io::Error> for CodecError {
+    fn from(e: io::Error) -> CodecError {
+        CodecError::Io(e)
+    }
+}
+
+/// DNS over TCP framing, each message behind a two byte length, for use
+/// with `tokio_util::codec::Framed`. Decodes into owned messages with
+/// `dns::TcpDecoder`, and encodes borrowed ones.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DnsTcpCodec {
+    decoder: dns::TcpDecoder,
+}
+
+impl DnsTcpCodec {
+    pub fn new(mode: dns::Mode) -> DnsTcpCodec {
+        DnsTcpCodec {
+            decoder: dns::TcpDecoder { mode },
+        }
+    }
+}
+
+impl Decoder for DnsTcpCodec {
+    type Item = dns::OwnedMessage;
+    type Error = CodecError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<dns::OwnedMessage>, CodecError> {
+        let bs = &src[..];
+        match self.decoder.decode(bs) {
+            Ok((rest, msg)) => {
+                let used = bs.len() - rest.len();
+                src.advance(used);
+                Ok(msg)
+            },
+            Err(nom::Err::Incomplete(_)) => {
+                if bs.len() >= 2 {
+                    src.reserve(2 + u16::from_be_bytes([bs[0], bs[1]]) as usize - bs.len());
+                }
+                Ok(None)
+            },
+            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => Err(CodecError::Decode(DecodeError {
+                offset: e.offset(bs).unwrap_or(0),
+                kind: e.kind,
+                context: e.context,
+            })),
+        }
+    }
+}
+
+impl <'a, 'b> Encoder<&'a dns::Message<'b>> for DnsTcpCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, msg: &'a dns::Message<'b>, dst: &mut BytesMut) -> Result<(), CodecError> {
+        let bs = msg.to_bytes();
+        if bs.len() > DNS_MAX_LEN {
+            return Err(CodecError::TooLong(bs.len()));
+        }
+        dst.reserve(2 + bs.len());
+        dst.put_u16(bs.len() as u16);
+        dst.put_slice(&bs);
+        Ok(())
+    }
+}
+
+/// Whole packets behind a four byte big endian length, the way packets are
+/// often passed between capture agents and collectors. Decoded frames are
+/// dissected as `link_type`, and packets are encoded with `ToBytes`, or as
+/// they are when given as bytes.
+#[derive(Clone, Copy, Debug)]
+pub struct PacketCodec {
+    link_type: LinkType,
+    max_len: usize,
+}
+
+impl PacketCodec {
+    pub fn new(link_type: LinkType) -> PacketCodec {
+        PacketCodec {
+            link_type,
+            max_len: DEFAULT_MAX_PACKET_LEN,
+        }
+    }
+
+    /// Sets the longest frame accepted either way, 256 KiB by default
+    pub fn set_max_len(&mut self, max_len: usize) {
+        self.max_len = max_len;
+    }
+
+    fn put(&self, bs: &[u8], dst: &mut BytesMut) -> Result<(), CodecError> {
+        if bs.len() > self.max_len || bs.len() > u32::MAX as usize {
+            return Err(CodecError::TooLong(bs.len()));
+        }
+        dst.reserve(4 + bs.len());
+        dst.put_u32(bs.len() as u32);
+        dst.put_slice(bs);
+        Ok(())
+    }
+}
+
+impl Decoder for PacketCodec {
+    type Item = OwnedPacket;
+    type Error = CodecError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<OwnedPacket>, CodecError> {
+        if src.len() < 4 {
+            return Ok(None);
+        }
+        let len = u32::from_be_bytes([src[0], src[1], src[2], src[3]]) as usize;
+        if len > self.max_len {
+            return Err(CodecError::TooLong(len));
+        }
+        if src.len() < 4 + len {
+            src.reserve(4 + len - src.len());
+            return Ok(None);
+        }
+        src.advance(4);
+        let frame = src.split_to(len);
+        Ok(Some(dissect::parse_packet(self.link_type, &frame).into_owned()))
+    }
+}
+
+impl <'a> Encoder<&'a [u8]> for PacketCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, bs: &'a [u8], dst: &mut BytesMut) -> Result<(), CodecError> {
+        self.put(bs, dst)
+    }
+}
+
+impl <'a, 'b> Encoder<&'a Packet<'b>> for PacketCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, packet: &'a Packet<'b>, dst: &mut BytesMut) -> Result<(), CodecError> {
+        self.put(&packet.to_bytes(), dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dns_tcp_codec() {
+        let query = [
+            0x24, 0x1a, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x77, 0x77, 0x77,
+            0x06, 0x67, 0x6f, 0x6f, 0x67, 0x6c, 0x65, 0x03, 0x63, 0x6f, 0x6d, 0x00, 0x00, 0x01, 0x00, 0x01,
+        ];
+        let (_, msg) = dns::parse_dns_message_full(&query).unwrap();
+        let mut codec = DnsTcpCodec::default();
+        let mut buf = BytesMut::new();
+        codec.encode(&msg, &mut buf).unwrap();
+        codec.encode(&msg, &mut buf).unwrap();
+        assert_eq!(&buf[..2], &[0x00, 0x20]);
+
+        // arrives a few bytes at a time
+        let mut input = BytesMut::new();
+        let mut decoded = vec![];
+        for chunk in buf.chunks(5) {
+            input.extend_from_slice(chunk);
+            while let Some(msg) = codec.decode(&mut input).unwrap() {
+                decoded.push(msg);
+            }
+        }
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0], msg.clone().into_owned());
+        assert!(input.is_empty());
+
+        let mut garbage = BytesMut::from(&[0x00, 0x02, 0xff, 0xff][..]);
+        assert!(codec.decode(&mut garbage).is_err());
+    }
+
+    #[test]
+    fn test_packet_codec() {
+        let packet = [
+            0x45, 0x00, 0x00, 0x1c, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, 0x7a, 0xcc, 0x0a, 0x00, 0x00, 0x01,
+            0x0a, 0x00, 0x00, 0x02,
+            0x10, 0xd8, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00,
+        ];
+        let mut codec = PacketCodec::new(LinkType::Raw);
+        let mut buf = BytesMut::new();
+        codec.encode(&packet[..], &mut buf).unwrap();
+        assert_eq!(&buf[..4], &[0, 0, 0, 28]);
+
+        let mut partial = buf.split_to(10);
+        assert!(codec.decode(&mut partial).unwrap().is_none());
+        partial.unsplit(buf);
+        let decoded = codec.decode(&mut partial).unwrap().unwrap();
+        assert!(decoded.ip.is_some() && decoded.transport.is_some());
+
+        codec.set_max_len(16);
+        assert!(codec.encode(&packet[..], &mut BytesMut::new()).is_err());
+    }
+}
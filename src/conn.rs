@@ -0,0 +1,522 @@
+use std::collections::HashMap;
+use std::collections::hash_map;
+use std::time::Duration;
+
+use flow::FlowKey;
+use tcp::{seq_diff, TcpHeader, TcpOption};
+use {Direction, IpPacket, Timestamp, TransportLayerPacket};
+
+// holes remembered per direction; past this the oldest are forgotten, and
+// segments filling them count as retransmissions
+const MAX_HOLES: usize = 64;
+
+// timestamp values waiting to be echoed per direction
+const MAX_PENDING_TS: usize = 32;
+
+/// Something noteworthy about a segment. Directions are relative to the
+/// connection's first packet, like in `Flow`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TcpEvent {
+    // data that had already been seen
+    Retransmission { dir: Direction, seq: u32 },
+    // data that fills a hole left by a later segment arriving first
+    OutOfOrder { dir: Direction, seq: u32 },
+    // an ACK repeating the last one, with `count` repeats so far
+    DupAck { dir: Direction, ack: u32, count: u32 },
+    // the third duplicate ACK, which has the sender retransmit
+    FastRetransmit { dir: Direction, ack: u32 },
+}
+
+/// Loss indicators for one direction of a connection; `dup_acks` and
+/// `fast_retransmits` count the ACKs sent in that direction
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TcpStats {
+    pub segments: u64,
+    pub retransmissions: u64,
+    pub out_of_order: u64,
+    pub dup_acks: u64,
+    pub fast_retransmits: u64,
+}
+
+/// Round trip time samples
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RttStats {
+    pub samples: u64,
+    pub min: Option<Duration>,
+    pub max: Option<Duration>,
+    pub total: Duration,
+}
+
+impl RttStats {
+    pub fn avg(&self) -> Option<Duration> {
+        if self.samples > 0 {
+            let nanos = self.total.as_nanos() / self.samples as u128;
+            Some(Duration::new((nanos / 1_000_000_000) as u64, (nanos % 1_000_000_000) as u32))
+        } else {
+            None
+        }
+    }
+
+    fn record(&mut self, rtt: Duration) {
+        self.samples += 1;
+        // stuck at the most a Duration holds, rather than wrapping
+        self.total = self.total.saturating_add(rtt);
+        self.min = Some(self.min.map_or(rtt, |min| min.min(rtt)));
+        self.max = Some(self.max.map_or(rtt, |max| max.max(rtt)));
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct HalfConnection {
+    // one past the highest sequence number seen
+    next_seq: Option<u32>,
+    // ranges skipped over by segments that arrived early
+    holes: Vec<(u32, u32)>,
+    last_ack: Option<(u32, u16)>,
+    dup_acks: u32,
+    // TSvals sent this way, with when each was first seen
+    pending_ts: Vec<(u32, Timestamp)>,
+}
+
+impl HalfConnection {
+    /// Classifies a segment's data against what was seen before it
+    fn data(&mut self, seq: u32, end: u32) -> Option<bool> {
+        let next = match self.next_seq {
+            Some(next) => next,
+            None => {
+                self.next_seq = Some(end);
+                return None;
+            },
+        };
+        if seq_diff(seq, next) >= 0 {
+            if seq != next {
+                if self.holes.len() == MAX_HOLES {
+                    self.holes.remove(0);
+                }
+                self.holes.push((next, seq));
+            }
+            self.next_seq = Some(end);
+            return None;
+        }
+        if seq_diff(end, next) > 0 {
+            self.next_seq = Some(end);
+        }
+        // trims whatever part of a hole the segment covers
+        let mut filled = false;
+        let mut holes = vec![];
+        for &(start, stop) in &self.holes {
+            if seq_diff(seq, stop) >= 0 || seq_diff(end, start) <= 0 {
+                holes.push((start, stop));
+                continue;
+            }
+            filled = true;
+            if seq_diff(seq, start) > 0 {
+                holes.push((start, seq));
+            }
+            if seq_diff(stop, end) > 0 {
+                holes.push((end, stop));
+            }
+        }
+        self.holes = holes;
+        Some(filled)
+    }
+
+    /// Counts repeats of a bare ACK, returning how many there have been
+    fn ack(&mut self, ack: u32, window: u16, bare: bool) -> u32 {
+        if bare && self.last_ack == Some((ack, window)) {
+            self.dup_acks += 1;
+        } else {
+            self.last_ack = Some((ack, window));
+            self.dup_acks = 0;
+        }
+        self.dup_acks
+    }
+
+    fn sent_ts(&mut self, tsval: u32, now: Timestamp) {
+        // only the first segment with a value counts, so retransmissions
+        // don't give short samples
+        if self.pending_ts.iter().any(|&(val, _)| val == tsval) {
+            return;
+        }
+        if self.pending_ts.len() == MAX_PENDING_TS {
+            self.pending_ts.remove(0);
+        }
+        self.pending_ts.push((tsval, now));
+    }
+
+    /// Matches an echoed TSecr to when it was sent, using each value once
+    fn echoed_ts(&mut self, tsecr: u32, now: Timestamp) -> Option<Duration> {
+        let idx = self.pending_ts.iter().position(|&(val, _)| val == tsecr)?;
+        let sent = self.pending_ts[idx].1;
+        // anything older was echoed by an ACK that wasn't seen
+        self.pending_ts.retain(|&(val, _)| seq_diff(val, tsecr) > 0);
+        Some(now.saturating_duration_since(sent))
+    }
+}
+
+/// A TCP connection, with `key` oriented the way its first packet went.
+/// RTTs are measured from the capture point, so `forward_rtt` is how long
+/// the far end of the forward direction takes to answer, and the two
+/// added together make the end to end RTT.
+#[derive(Clone, Debug)]
+pub struct Connection {
+    pub key: FlowKey,
+    pub first_seen: Timestamp,
+    pub last_seen: Timestamp,
+    pub forward: TcpStats,
+    pub reverse: TcpStats,
+    pub forward_rtt: RttStats,
+    pub reverse_rtt: RttStats,
+    // when the SYN, SYN+ACK and the ACK finishing the handshake were seen
+    pub syn: Option<Timestamp>,
+    pub syn_ack: Option<Timestamp>,
+    pub established: Option<Timestamp>,
+    halves: [HalfConnection; 2],
+}
+
+impl Connection {
+    pub fn new(key: FlowKey, now: Timestamp) -> Connection {
+        Connection {
+            key,
+            first_seen: now,
+            last_seen: now,
+            forward: TcpStats::default(),
+            reverse: TcpStats::default(),
+            forward_rtt: RttStats::default(),
+            reverse_rtt: RttStats::default(),
+            syn: None,
+            syn_ack: None,
+            established: None,
+            halves: [HalfConnection::default(), HalfConnection::default()],
+        }
+    }
+
+    pub fn stats(&self, dir: Direction) -> &TcpStats {
+        match dir {
+            Direction::Forward => &self.forward,
+            Direction::Reverse => &self.reverse,
+        }
+    }
+
+    fn stats_mut(&mut self, dir: Direction) -> &mut TcpStats {
+        match dir {
+            Direction::Forward => &mut self.forward,
+            Direction::Reverse => &mut self.reverse,
+        }
+    }
+
+    pub fn rtt(&self, dir: Direction) -> &RttStats {
+        match dir {
+            Direction::Forward => &self.forward_rtt,
+            Direction::Reverse => &self.reverse_rtt,
+        }
+    }
+
+    fn rtt_mut(&mut self, dir: Direction) -> &mut RttStats {
+        match dir {
+            Direction::Forward => &mut self.forward_rtt,
+            Direction::Reverse => &mut self.reverse_rtt,
+        }
+    }
+
+    /// The time from the SYN to the ACK of the SYN+ACK, a full round trip
+    pub fn handshake_rtt(&self) -> Option<Duration> {
+        Some(self.established?.saturating_duration_since(self.syn?))
+    }
+
+    /// End to end RTTs as (min, avg, max), adding up the two directions'
+    /// samples, once both have some. Sums too long for a `Duration` are
+    /// `Duration::MAX`.
+    pub fn round_trip(&self) -> Option<(Duration, Duration, Duration)> {
+        let (fwd, rev) = (&self.forward_rtt, &self.reverse_rtt);
+        Some((fwd.min?.saturating_add(rev.min?),
+              fwd.avg()?.saturating_add(rev.avg()?),
+              fwd.max?.saturating_add(rev.max?)))
+    }
+
+    fn time(&mut self, dir: Direction, header: &TcpHeader, now: Timestamp) {
+        let flags = header.flags;
+        if flags.syn && !flags.ack && self.syn.is_none() {
+            self.syn = Some(now);
+        } else if flags.syn && flags.ack && self.syn_ack.is_none() {
+            self.syn_ack = Some(now);
+            if let Some(syn) = self.syn {
+                self.rtt_mut(dir.reverse()).record(now.saturating_duration_since(syn));
+            }
+        } else if flags.ack && self.established.is_none() {
+            if let Some(syn_ack) = self.syn_ack {
+                self.established = Some(now);
+                self.rtt_mut(dir.reverse()).record(now.saturating_duration_since(syn_ack));
+            }
+        }
+
+        // handshake segments were timed above already
+        if flags.syn || (self.established.is_none() && self.syn.is_some()) {
+            return;
+        }
+        let timestamps = header.options.iter().filter_map(|option| match *option {
+            TcpOption::Timestamps(tsval, tsecr) => Some((tsval, tsecr)),
+            _ => None,
+        }).next();
+        if let Some((tsval, tsecr)) = timestamps {
+            self.halves[dir as usize].sent_ts(tsval, now);
+            if flags.ack {
+                if let Some(rtt) = self.halves[dir.reverse() as usize].echoed_ts(tsecr, now) {
+                    self.rtt_mut(dir.reverse()).record(rtt);
+                }
+            }
+        }
+    }
+
+    /// Accounts a segment going in `dir` carrying `len` bytes of data.
+    /// Retransmissions and out of order segments can't always be told
+    /// apart from one capture point; data filling a gap the capture saw
+    /// being skipped is taken to be out of order, anything else already
+    /// covered is a retransmission.
+    pub fn ingest(&mut self, dir: Direction, header: &TcpHeader, len: usize, now: Timestamp) -> Vec<TcpEvent> {
+        let mut events = vec![];
+        if now > self.last_seen {
+            self.last_seen = now;
+        }
+        self.stats_mut(dir).segments += 1;
+        self.time(dir, header, now);
+
+        let flags = header.flags;
+        let seq = header.seq;
+        let span = len as u32 + flags.syn as u32 + flags.fin as u32;
+        if span > 0 {
+            match self.halves[dir as usize].data(seq, seq.wrapping_add(span)) {
+                Some(true) => {
+                    self.stats_mut(dir).out_of_order += 1;
+                    events.push(TcpEvent::OutOfOrder { dir, seq });
+                },
+                Some(false) => {
+                    self.stats_mut(dir).retransmissions += 1;
+                    events.push(TcpEvent::Retransmission { dir, seq });
+                },
+                None => {},
+            }
+        }
+
+        if flags.ack && !flags.rst {
+            let ack = header.ack;
+            let count = self.halves[dir as usize].ack(ack, header.window_sz, span == 0);
+            if count > 0 {
+                self.stats_mut(dir).dup_acks += 1;
+                events.push(TcpEvent::DupAck { dir, ack, count });
+            }
+            if count == 3 {
+                self.stats_mut(dir).fast_retransmits += 1;
+                events.push(TcpEvent::FastRetransmit { dir, ack });
+            }
+        }
+        events
+    }
+}
+
+/// Tracks TCP connections, flagging retransmissions, out of order segments
+/// and duplicate ACKs as they go past, and timing round trips from the
+/// handshake and the timestamps option. Connections are looked up in either
+/// direction and expired when idle, like flows in a `FlowTable`.
+pub struct ConnTracker {
+    conns: HashMap<FlowKey, Connection>,
+    idle_timeout: Duration,
+}
+
+impl ConnTracker {
+    pub fn new(idle_timeout: Duration) -> ConnTracker {
+        ConnTracker {
+            conns: HashMap::new(),
+            idle_timeout,
+        }
+    }
+
+    /// Feeds an IP packet to its connection; anything but TCP is ignored
+    pub fn track(&mut self, packet: &IpPacket, now: Timestamp) -> Vec<TcpEvent> {
+        let inner = match packet.parse_inner() {
+            Some(inner) => inner,
+            None => return vec![],
+        };
+        let tcp = match inner {
+            TransportLayerPacket::Tcp(ref tcp) => tcp,
+            _ => return vec![],
+        };
+        let key = FlowKey::from_packets(packet, &inner);
+        let conn = self.conns.entry(key.canonical().0)
+            .or_insert_with(|| Connection::new(key, now));
+        let dir = if conn.key == key { Direction::Forward } else { Direction::Reverse };
+        conn.ingest(dir, &tcp.header, tcp.body.len(), now)
+    }
+
+    /// Looks up a connection by its key in either direction
+    pub fn get(&self, key: &FlowKey) -> Option<&Connection> {
+        self.conns.get(&key.canonical().0)
+    }
+
+    pub fn remove(&mut self, key: &FlowKey) -> Option<Connection> {
+        self.conns.remove(&key.canonical().0)
+    }
+
+    /// Removes and returns every connection that hasn't seen a packet
+    /// within the idle timeout as of `now`
+    pub fn expire(&mut self, now: Timestamp) -> Vec<Connection> {
+        let idle_timeout = self.idle_timeout;
+        let expired: Vec<FlowKey> = self.conns.iter()
+            .filter(|&(_, conn)| now >= conn.last_seen + idle_timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        expired.iter()
+            .filter_map(|key| self.conns.remove(key))
+            .collect()
+    }
+
+    pub fn iter(&self) -> hash_map::Values<'_, FlowKey, Connection> {
+        self.conns.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.conns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conns.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(seq: u32, ack: u32) -> TcpHeader<'static> {
+        let mut header = TcpHeader::new(40000, 80);
+        header.seq = seq;
+        header.ack = ack;
+        header.flags.ack = true;
+        header.window_sz = 512;
+        header
+    }
+
+    #[test]
+    fn test_loss_events() {
+        let key = FlowKey {
+            src_ip: "10.0.0.2".parse().unwrap(),
+            dst_ip: "10.0.0.1".parse().unwrap(),
+            src_port: 40000,
+            dst_port: 80,
+            proto: ::ipv4::Ipv4Protocol::Tcp,
+        };
+        let mut conn = Connection::new(key, Timestamp::new(0, 0));
+        let now = Timestamp::new(1, 0);
+        let (fwd, rev) = (Direction::Forward, Direction::Reverse);
+
+        assert!(conn.ingest(fwd, &segment(1000, 1), 100, now).is_empty());
+        // 1100..1200 goes missing
+        assert!(conn.ingest(fwd, &segment(1200, 1), 100, now).is_empty());
+        assert!(conn.ingest(rev, &segment(1, 1100), 0, now).is_empty());
+        assert_eq!(conn.ingest(rev, &segment(1, 1100), 0, now), vec![TcpEvent::DupAck { dir: rev, ack: 1100, count: 1 }]);
+        conn.ingest(rev, &segment(1, 1100), 0, now);
+        assert_eq!(conn.ingest(rev, &segment(1, 1100), 0, now), vec![
+            TcpEvent::DupAck { dir: rev, ack: 1100, count: 3 },
+            TcpEvent::FastRetransmit { dir: rev, ack: 1100 },
+        ]);
+        assert_eq!(conn.ingest(fwd, &segment(1100, 1), 100, now), vec![TcpEvent::OutOfOrder { dir: fwd, seq: 1100 }]);
+        assert_eq!(conn.ingest(fwd, &segment(1100, 1), 100, now), vec![TcpEvent::Retransmission { dir: fwd, seq: 1100 }]);
+        // the ACK moving on resets the count
+        assert!(conn.ingest(rev, &segment(1, 1300), 0, now).is_empty());
+
+        assert_eq!(conn.forward, TcpStats { segments: 4, retransmissions: 1, out_of_order: 1, dup_acks: 0, fast_retransmits: 0 });
+        assert_eq!(conn.reverse, TcpStats { segments: 5, retransmissions: 0, out_of_order: 0, dup_acks: 3, fast_retransmits: 1 });
+    }
+
+    #[test]
+    fn test_rtt() {
+        let key = FlowKey {
+            src_ip: "10.0.0.2".parse().unwrap(),
+            dst_ip: "10.0.0.1".parse().unwrap(),
+            src_port: 40000,
+            dst_port: 80,
+            proto: ::ipv4::Ipv4Protocol::Tcp,
+        };
+        let ms = Duration::from_millis;
+        let at = |ms: u64| Timestamp::from_micros(ms * 1000);
+        let (fwd, rev) = (Direction::Forward, Direction::Reverse);
+        let with_ts = |mut header: TcpHeader<'static>, tsval, tsecr| {
+            header.options.push(TcpOption::Timestamps(tsval, tsecr));
+            header
+        };
+        let mut conn = Connection::new(key, at(0));
+
+        let mut syn = segment(1000, 0);
+        syn.flags.syn = true;
+        syn.flags.ack = false;
+        conn.ingest(fwd, &with_ts(syn, 100, 0), 0, at(0));
+        let mut syn_ack = segment(5000, 1001);
+        syn_ack.flags.syn = true;
+        conn.ingest(rev, &with_ts(syn_ack, 900, 100), 0, at(30));
+        conn.ingest(fwd, &with_ts(segment(1001, 5001), 101, 900), 0, at(32));
+        assert_eq!(conn.handshake_rtt(), Some(ms(32)));
+
+        // data out, echoed by the ACK coming back 40ms later; the
+        // retransmission with the same TSval isn't timed again
+        conn.ingest(fwd, &with_ts(segment(1001, 5001), 102, 900), 100, at(100));
+        conn.ingest(fwd, &with_ts(segment(1001, 5001), 102, 900), 100, at(120));
+        conn.ingest(rev, &with_ts(segment(5001, 1101), 901, 102), 0, at(140));
+        conn.ingest(rev, &with_ts(segment(5001, 1101), 901, 102), 0, at(150));
+
+        assert_eq!(conn.forward_rtt, RttStats { samples: 2, min: Some(ms(30)), max: Some(ms(40)), total: ms(70) });
+        assert_eq!(conn.reverse_rtt, RttStats { samples: 1, min: Some(ms(2)), max: Some(ms(2)), total: ms(2) });
+        assert_eq!(conn.round_trip(), Some((ms(32), ms(37), ms(42))));
+    }
+
+    #[test]
+    fn test_rtt_backwards() {
+        let key = FlowKey {
+            src_ip: "10.0.0.2".parse().unwrap(),
+            dst_ip: "10.0.0.1".parse().unwrap(),
+            src_port: 40000,
+            dst_port: 80,
+            proto: ::ipv4::Ipv4Protocol::Tcp,
+        };
+        let (fwd, rev) = (Direction::Forward, Direction::Reverse);
+        let (start, end) = (Timestamp::new(0, 0), Timestamp::new(u64::MAX, 0));
+        let mut conn = Connection::new(key, start);
+
+        // a capture that jumps to the end of time and back
+        let mut syn = segment(1000, 0);
+        syn.flags.syn = true;
+        syn.flags.ack = false;
+        conn.ingest(fwd, &syn, 0, start);
+        let mut syn_ack = segment(5000, 1001);
+        syn_ack.flags.syn = true;
+        conn.ingest(rev, &syn_ack, 0, end);
+        conn.ingest(fwd, &segment(1001, 5001), 0, start);
+        let mut data = segment(5001, 1001);
+        data.options.push(TcpOption::Timestamps(7, 0));
+        conn.ingest(rev, &data, 100, start);
+        let mut ack = segment(1001, 5101);
+        ack.options.push(TcpOption::Timestamps(1, 7));
+        conn.ingest(fwd, &ack, 0, end);
+
+        let longest = Duration::from_secs(u64::MAX);
+        assert_eq!(conn.forward_rtt.max, Some(longest));
+        assert_eq!(conn.reverse_rtt.max, Some(longest));
+        assert_eq!(conn.round_trip(), Some((longest, Duration::MAX, Duration::MAX)));
+    }
+
+    #[test]
+    fn test_rtt_avg() {
+        let mut stats = RttStats { samples: 1 << 32, min: None, max: None, total: Duration::from_secs(1 << 32) };
+        assert_eq!(stats.avg(), Some(Duration::from_secs(1)));
+        stats.samples = (1 << 32) + 1;
+        assert!(stats.avg().unwrap() < Duration::from_secs(1));
+
+        let mut stats = RttStats::default();
+        stats.record(Duration::MAX);
+        stats.record(Duration::from_secs(1));
+        assert_eq!(stats.total, Duration::MAX);
+        assert_eq!(stats.avg(), Some(Duration::MAX / 2));
+    }
+}
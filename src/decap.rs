@@ -0,0 +1,569 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4};
+
+use nom::bytes::complete::{tag, take};
+use nom::combinator::{opt, verify};
+use nom::error::context;
+use nom::number::complete::{be_u8, be_u16, be_u32};
+
+use dissect::{parse_packet, LinkType, Packet};
+use ethernet::{parse_pbb_frame, EtherType, ITag};
+use error::IResult;
+use {IpPacket, TransportLayerPacket};
+
+const PROTO_IPIP: u8 = 4;
+const PROTO_IPV6: u8 = 41;
+const PROTO_GRE: u8 = 47;
+
+const VXLAN_PORT: u16 = 4789;
+const GENEVE_PORT: u16 = 6081;
+const GTPU_PORT: u16 = 2152;
+const TEREDO_PORT: u16 = 3544;
+
+// https://tools.ietf.org/html/rfc2784 and https://tools.ietf.org/html/rfc2890,
+// plus the acknowledgment number of PPTP's version 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GreHeader {
+    pub version: u8,
+    pub protocol: EtherType,
+    pub checksum: Option<u16>,
+    pub key: Option<u32>,
+    pub sequence: Option<u32>,
+    pub ack: Option<u32>,
+}
+
+pub fn parse_gre_header(bs: &[u8]) -> IResult<'_, GreHeader> {
+    context("gre", gre_header)(bs)
+}
+
+fn gre_header(bs: &[u8]) -> IResult<'_, GreHeader> {
+    let (bs, flags) = be_u16(bs)?;
+    let (bs, protocol) = be_u16(bs)?;
+    let (bs, checksum) = if flags & 0x8000 != 0 {
+        let (bs, checksum) = be_u16(bs)?;
+        let (bs, _) = be_u16(bs)?;
+        (bs, Some(checksum))
+    } else {
+        (bs, None)
+    };
+    let (bs, key) = optional_u32(flags & 0x2000 != 0, bs)?;
+    let (bs, sequence) = optional_u32(flags & 0x1000 != 0, bs)?;
+    let (bs, ack) = optional_u32(flags & 0x0080 != 0, bs)?;
+    Ok((bs, GreHeader {
+        version: (flags & 0x0007) as u8,
+        protocol: EtherType::from_u16(protocol),
+        checksum,
+        key,
+        sequence,
+        ack,
+    }))
+}
+
+fn optional_u32(present: bool, bs: &[u8]) -> IResult<'_, Option<u32>> {
+    if present {
+        let (bs, v) = be_u32(bs)?;
+        Ok((bs, Some(v)))
+    } else {
+        Ok((bs, None))
+    }
+}
+
+// https://tools.ietf.org/html/rfc7348#section-5
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct VxlanHeader {
+    pub flags: u8,
+    pub vni: u32,
+}
+
+pub fn parse_vxlan_header(bs: &[u8]) -> IResult<'_, VxlanHeader> {
+    context("vxlan", vxlan_header)(bs)
+}
+
+fn vxlan_header(bs: &[u8]) -> IResult<'_, VxlanHeader> {
+    // the I flag says the VNI is valid
+    let (bs, flags) = verify(be_u8, |&f| f & 0x08 != 0)(bs)?;
+    let (bs, _) = take(3usize)(bs)?;
+    let (bs, vni) = be_u32(bs)?;
+    Ok((bs, VxlanHeader {
+        flags,
+        vni: vni >> 8,
+    }))
+}
+
+// https://tools.ietf.org/html/rfc8926#section-3.4
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct GeneveHeader<'a> {
+    pub version: u8,
+    // control packets don't carry any data
+    pub oam: bool,
+    pub critical: bool,
+    pub protocol: EtherType,
+    pub vni: u32,
+    // left unparsed, since they're mostly vendor specific
+    pub options: &'a [u8],
+}
+
+pub fn parse_geneve_header(bs: &[u8]) -> IResult<'_, GeneveHeader<'_>> {
+    context("geneve", geneve_header)(bs)
+}
+
+fn geneve_header(bs: &[u8]) -> IResult<'_, GeneveHeader<'_>> {
+    let (bs, first) = verify(be_u8, |&b| b >> 6 == 0)(bs)?;
+    let (bs, flags) = be_u8(bs)?;
+    let (bs, protocol) = be_u16(bs)?;
+    let (bs, vni) = be_u32(bs)?;
+    let (bs, options) = context("geneve.options", take((first & 0x3f) as usize * 4))(bs)?;
+    Ok((bs, GeneveHeader {
+        version: first >> 6,
+        oam: flags & 0x80 != 0,
+        critical: flags & 0x40 != 0,
+        protocol: EtherType::from_u16(protocol),
+        vni: vni >> 8,
+        options,
+    }))
+}
+
+// https://www.3gpp.org/DynaReport/29281.htm, version 1 only
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct GtpUHeader<'a> {
+    pub message_type: u8,
+    pub teid: u32,
+    pub sequence: Option<u16>,
+    pub npdu: Option<u8>,
+    // type and contents of each extension header
+    pub extensions: Vec<(u8, &'a [u8])>,
+}
+
+/// Message type of a G-PDU, the only kind that carries user data
+pub const GTPU_GPDU: u8 = 0xff;
+
+pub fn parse_gtpu_header(bs: &[u8]) -> IResult<'_, GtpUHeader<'_>> {
+    context("gtpu", gtpu_header)(bs)
+}
+
+fn gtpu_header(bs: &[u8]) -> IResult<'_, GtpUHeader<'_>> {
+    // version 1, and the protocol type bit set for GTP rather than GTP'
+    let (bs, flags) = verify(be_u8, |&f| f & 0xf0 == 0x30)(bs)?;
+    let (bs, message_type) = be_u8(bs)?;
+    let (bs, length) = be_u16(bs)?;
+    let (bs, teid) = be_u32(bs)?;
+    // anything past the length is padding, and what's left of the message
+    // after the header is the tunneled packet
+    let (_, bs) = context("gtpu.length", take(length))(bs)?;
+    let mut header = GtpUHeader {
+        message_type,
+        teid,
+        sequence: None,
+        npdu: None,
+        extensions: vec![],
+    };
+    // the optional fields are there if any of E, S or PN is set
+    if flags & 0x07 == 0 {
+        return Ok((bs, header));
+    }
+    let (bs, sequence) = be_u16(bs)?;
+    let (bs, npdu) = be_u8(bs)?;
+    let (mut bs, mut next) = be_u8(bs)?;
+    if flags & 0x02 != 0 {
+        header.sequence = Some(sequence);
+    }
+    if flags & 0x01 != 0 {
+        header.npdu = Some(npdu);
+    }
+    while flags & 0x04 != 0 && next != 0 {
+        let (b, len) = verify(be_u8, |&l| l > 0)(bs)?;
+        let (b, contents) = context("gtpu.extension", take(len as usize * 4 - 2))(b)?;
+        let (b, n) = be_u8(b)?;
+        header.extensions.push((next, contents));
+        next = n;
+        bs = b;
+    }
+    Ok((bs, header))
+}
+
+// https://tools.ietf.org/html/rfc4380#section-5.1.1
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct TeredoHeader<'a> {
+    pub auth: Option<TeredoAuth<'a>>,
+    // the client's mapped address, sent by servers and relays
+    pub origin: Option<SocketAddrV4>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct TeredoAuth<'a> {
+    pub client_id: &'a [u8],
+    pub value: &'a [u8],
+    pub nonce: &'a [u8],
+    pub confirmation: u8,
+}
+
+/// Parses the indicators in front of the IPv6 packet in a Teredo datagram,
+/// leaving the IPv6 packet itself
+pub fn parse_teredo_header(bs: &[u8]) -> IResult<'_, TeredoHeader<'_>> {
+    context("teredo", teredo_header)(bs)
+}
+
+fn teredo_header(bs: &[u8]) -> IResult<'_, TeredoHeader<'_>> {
+    let (bs, auth) = opt(teredo_auth)(bs)?;
+    let (bs, origin) = opt(teredo_origin)(bs)?;
+    // whatever's left has to at least look like IPv6
+    let (_, _) = verify(be_u8, |&b| b >> 4 == 6)(bs)?;
+    Ok((bs, TeredoHeader {
+        auth,
+        origin,
+    }))
+}
+
+fn teredo_auth(bs: &[u8]) -> IResult<'_, TeredoAuth<'_>> {
+    let (bs, _) = tag([0x00, 0x01])(bs)?;
+    let (bs, id_len) = be_u8(bs)?;
+    let (bs, auth_len) = be_u8(bs)?;
+    let (bs, client_id) = take(id_len)(bs)?;
+    let (bs, value) = take(auth_len)(bs)?;
+    let (bs, nonce) = take(8usize)(bs)?;
+    let (bs, confirmation) = be_u8(bs)?;
+    Ok((bs, TeredoAuth {
+        client_id,
+        value,
+        nonce,
+        confirmation,
+    }))
+}
+
+fn teredo_origin(bs: &[u8]) -> IResult<'_, SocketAddrV4> {
+    let (bs, _) = tag([0x00, 0x00])(bs)?;
+    // both are sent inverted, so NATs don't rewrite them
+    let (bs, port) = be_u16(bs)?;
+    let (bs, ip) = be_u32(bs)?;
+    Ok((bs, SocketAddrV4::new(Ipv4Addr::from(!ip), !port)))
+}
+
+/// The server and the client's mapped address and port embedded in a
+/// Teredo address, one in 2001::/32
+pub fn teredo_address(addr: Ipv6Addr) -> Option<(Ipv4Addr, SocketAddrV4)> {
+    let s = addr.segments();
+    if s[0] != 0x2001 || s[1] != 0 {
+        return None;
+    }
+    let server = Ipv4Addr::new((s[2] >> 8) as u8, s[2] as u8, (s[3] >> 8) as u8, s[3] as u8);
+    let client = !(((s[6] as u32) << 16) | s[7] as u32);
+    Some((server, SocketAddrV4::new(Ipv4Addr::from(client), !s[5])))
+}
+
+/// The IPv4 address of the site a 6to4 address in 2002::/16 belongs to
+pub fn six_to_four_address(addr: Ipv6Addr) -> Option<Ipv4Addr> {
+    let s = addr.segments();
+    if s[0] != 0x2002 {
+        return None;
+    }
+    Some(Ipv4Addr::new((s[1] >> 8) as u8, s[1] as u8, (s[2] >> 8) as u8, s[2] as u8))
+}
+
+/// The encapsulation a packet was found inside
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Tunnel<'a> {
+    // IPv4 straight inside IPv4 or IPv6, protocol number 4, or IPv6 inside
+    // IPv6, protocol number 41
+    IpInIp,
+    // IPv6 inside IPv4, protocol number 41, on a configured tunnel
+    SixInFour,
+    // the same, but between 6to4 addresses
+    SixToFour,
+    Teredo(TeredoHeader<'a>),
+    Gre(GreHeader),
+    Vxlan(VxlanHeader),
+    Geneve(GeneveHeader<'a>),
+    GtpU(GtpUHeader<'a>),
+    // 802.1ah, a customer's Ethernet frame inside a provider's
+    Pbb(ITag),
+}
+
+/// A packet that was carried inside a tunnel, and the tunnel's header
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Encapsulated<'a> {
+    pub tunnel: Tunnel<'a>,
+    pub packet: Packet<'a>,
+}
+
+/// A packet and everything tunneled inside it, outermost first
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct LayerStack<'a> {
+    pub outer: Packet<'a>,
+    pub tunnels: Vec<Encapsulated<'a>>,
+}
+
+impl <'a> LayerStack<'a> {
+    /// The packet at the bottom of all the tunnels, which is the outer
+    /// packet itself if it wasn't tunneling anything
+    pub fn innermost(&self) -> &Packet<'a> {
+        self.tunnels.last().map(|e| &e.packet).unwrap_or(&self.outer)
+    }
+
+    /// How many tunnels deep the innermost packet is
+    pub fn depth(&self) -> usize {
+        self.tunnels.len()
+    }
+}
+
+/// Unwraps tunneled packets: MAC-in-MAC by its EtherType, IP-in-IP, 6in4
+/// and GRE by their IP protocol numbers, and VXLAN, Geneve, GTP-U and
+/// Teredo by their well known UDP ports.
+/// Each inner packet is dissected like a captured one, and then checked for
+/// tunnels of its own.
+pub struct Decapsulator {
+    max_depth: usize,
+}
+
+impl Default for Decapsulator {
+    fn default() -> Decapsulator {
+        Decapsulator {
+            max_depth: 8,
+        }
+    }
+}
+
+impl Decapsulator {
+    pub fn new() -> Decapsulator {
+        Decapsulator::default()
+    }
+
+    /// How many tunnels deep to go, 8 by default. Anything past that is
+    /// left as the innermost packet's payload.
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+    }
+
+    pub fn decap<'a>(&self, packet: Packet<'a>) -> LayerStack<'a> {
+        let mut tunnels: Vec<Encapsulated<'a>> = vec![];
+        let mut next = tunnel(&packet);
+        while let Some((tunnel_header, link_type, bs)) = next {
+            if tunnels.len() >= self.max_depth {
+                break;
+            }
+            let inner = parse_packet(link_type, bs);
+            next = tunnel(&inner);
+            tunnels.push(Encapsulated {
+                tunnel: tunnel_header,
+                packet: inner,
+            });
+        }
+        LayerStack {
+            outer: packet,
+            tunnels,
+        }
+    }
+}
+
+fn by_ethertype(ethertype: EtherType) -> Option<LinkType> {
+    match ethertype {
+        EtherType::Ipv4 | EtherType::Ipv6 => Some(LinkType::Raw),
+        EtherType::Teb => Some(LinkType::Ethernet),
+        _ => None,
+    }
+}
+
+// the tunnel a packet is carrying, how its contents are framed, and the
+// contents themselves
+fn tunnel<'a>(packet: &Packet<'a>) -> Option<(Tunnel<'a>, LinkType, &'a [u8])> {
+    if let Some(ref eth) = packet.ethernet {
+        if eth.ethertype == EtherType::Pbb {
+            // the customer frame is parsed again, as a packet of its own
+            let (_, pbb) = parse_pbb_frame(eth.body).ok()?;
+            return Some((Tunnel::Pbb(pbb.itag), LinkType::Ethernet, &eth.body[4..]));
+        }
+    }
+    let ip = packet.ip.as_ref()?;
+    match packet.transport {
+        Some(TransportLayerPacket::Udp(ref udp)) => {
+            let (src, dst) = (udp.header.src, udp.header.dst);
+            if dst == VXLAN_PORT {
+                let (bs, vxlan) = parse_vxlan_header(udp.body).ok()?;
+                Some((Tunnel::Vxlan(vxlan), LinkType::Ethernet, bs))
+            } else if dst == GENEVE_PORT {
+                let (bs, geneve) = parse_geneve_header(udp.body).ok()?;
+                let link_type = by_ethertype(geneve.protocol)?;
+                if geneve.oam {
+                    return None;
+                }
+                Some((Tunnel::Geneve(geneve), link_type, bs))
+            } else if src == GTPU_PORT || dst == GTPU_PORT {
+                let (bs, gtpu) = parse_gtpu_header(udp.body).ok()?;
+                if gtpu.message_type != GTPU_GPDU {
+                    return None;
+                }
+                Some((Tunnel::GtpU(gtpu), LinkType::Raw, bs))
+            } else if src == TEREDO_PORT || dst == TEREDO_PORT {
+                let (bs, teredo) = parse_teredo_header(udp.body).ok()?;
+                Some((Tunnel::Teredo(teredo), LinkType::Ipv6, bs))
+            } else {
+                None
+            }
+        },
+        Some(_) => None,
+        None => match ip.protocol()?.to_u8() {
+            PROTO_IPIP => Some((Tunnel::IpInIp, LinkType::Raw, ip.body())),
+            PROTO_IPV6 => match *ip {
+                IpPacket::V4(_) => {
+                    let (_, inner) = ::ipv6::parse_ipv6_header(ip.body()).ok()?;
+                    let tunnel = if six_to_four_address(inner.src_ip).is_some()
+                        || six_to_four_address(inner.dst_ip).is_some() {
+                        Tunnel::SixToFour
+                    } else {
+                        Tunnel::SixInFour
+                    };
+                    Some((tunnel, LinkType::Ipv6, ip.body()))
+                },
+                IpPacket::V6(_) => Some((Tunnel::IpInIp, LinkType::Ipv6, ip.body())),
+            },
+            PROTO_GRE => {
+                let (bs, gre) = parse_gre_header(ip.body()).ok()?;
+                let link_type = by_ethertype(gre.protocol)?;
+                Some((Tunnel::Gre(gre), link_type, bs))
+            },
+            _ => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use IpPacket;
+
+    fn ipv4(proto: u8, body: &[u8]) -> Vec<u8> {
+        let len = 20 + body.len();
+        let mut out = vec![
+            0x45, 0x00, (len >> 8) as u8, len as u8, 0x00, 0x01, 0x00, 0x00, 0x40, proto, 0x00, 0x00,
+            0x0a, 0x00, 0x00, 0x01, 0x0a, 0x00, 0x00, 0x02,
+        ];
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn udp(dst: u16, body: &[u8]) -> Vec<u8> {
+        let len = 8 + body.len();
+        let mut out = vec![0x30, 0x39, (dst >> 8) as u8, dst as u8, (len >> 8) as u8, len as u8, 0x00, 0x00];
+        out.extend_from_slice(body);
+        out
+    }
+
+    #[test]
+    fn test_gre_vxlan() {
+        let innermost = ipv4(17, &udp(8080, b"hello"));
+        let mut frame = vec![
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0x08, 0x00,
+        ];
+        frame.extend_from_slice(&innermost);
+        let mut vxlan = vec![0x08, 0x00, 0x00, 0x00, 0x00, 0x12, 0x34, 0x00];
+        vxlan.extend_from_slice(&frame);
+        // GRE with a key
+        let mut gre = vec![0x20, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x2a];
+        gre.extend_from_slice(&ipv4(17, &udp(VXLAN_PORT, &vxlan)));
+        let outer = ipv4(PROTO_GRE, &gre);
+
+        let stack = Decapsulator::new().decap(parse_packet(LinkType::Raw, &outer));
+        assert_eq!(stack.depth(), 2);
+        match stack.tunnels[0].tunnel {
+            Tunnel::Gre(ref gre) => {
+                assert_eq!(gre.protocol, EtherType::Ipv4);
+                assert_eq!(gre.key, Some(42));
+            },
+            ref x => panic!("expected GRE, got {:?}", x),
+        }
+        assert_eq!(stack.tunnels[1].tunnel, Tunnel::Vxlan(VxlanHeader { flags: 0x08, vni: 0x1234 }));
+        let inner = stack.innermost();
+        assert_eq!(inner.ethernet.as_ref().unwrap().ethertype, EtherType::Ipv4);
+        assert!(matches!(inner.ip, Some(IpPacket::V4(_))));
+        assert_eq!(inner.payload, b"hello");
+
+        let mut shallow = Decapsulator::new();
+        shallow.set_max_depth(1);
+        let stack = shallow.decap(parse_packet(LinkType::Raw, &outer));
+        assert_eq!(stack.depth(), 1);
+        assert_eq!(stack.innermost().payload, &vxlan[..]);
+    }
+
+    #[test]
+    fn test_gtpu() {
+        // a G-PDU with a sequence number and a PDU session container extension
+        let inner = ipv4(17, &udp(53, b"hi"));
+        let len = 4 + 4 + inner.len();
+        let mut gtpu = vec![
+            0x36, 0xff, (len >> 8) as u8, len as u8, 0x00, 0x00, 0x00, 0x07,
+            0x00, 0x05, 0x00, 0x85, 0x01, 0x00, 0x09, 0x00,
+        ];
+        gtpu.extend_from_slice(&inner);
+        let outer = ipv4(17, &udp(GTPU_PORT, &gtpu));
+        let stack = Decapsulator::new().decap(parse_packet(LinkType::Raw, &outer));
+        match stack.tunnels[0].tunnel {
+            Tunnel::GtpU(ref gtpu) => {
+                assert_eq!(gtpu.teid, 7);
+                assert_eq!(gtpu.sequence, Some(5));
+                assert_eq!(gtpu.extensions, vec![(0x85, &[0x00, 0x09][..])]);
+            },
+            ref x => panic!("expected GTP-U, got {:?}", x),
+        }
+        assert_eq!(stack.innermost().payload, b"hi");
+    }
+
+    #[test]
+    fn test_pbb() {
+        // a backbone frame with a B-tag, then the I-tag and customer frame
+        let mut frame = vec![
+            0x00, 0x1e, 0x83, 0x00, 0x00, 0x01, 0x00, 0x1e, 0x83, 0x00, 0x00, 0x02, 0x88, 0xa8, 0x00, 0x64,
+            0x88, 0xe7, 0x60, 0x01, 0x00, 0x2a,
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0x08, 0x00,
+        ];
+        frame.extend_from_slice(&ipv4(17, &udp(8080, b"customer")));
+        let packet = parse_packet(LinkType::Ethernet, &frame);
+        assert_eq!(packet.ethernet.as_ref().unwrap().ethertype, EtherType::Pbb);
+        let stack = Decapsulator::new().decap(packet);
+        assert_eq!(stack.tunnels[0].tunnel, Tunnel::Pbb(ITag { pcp: 3, dei: false, uca: false, isid: 0x01002a }));
+        let customer = stack.innermost();
+        assert_eq!(customer.ethernet.as_ref().unwrap().source_mac.to_string(), "66:77:88:99:aa:bb");
+        assert_eq!(customer.payload, b"customer");
+    }
+
+    fn ipv6(src: Ipv6Addr, dst: Ipv6Addr, body: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x60, 0x00, 0x00, 0x00, (body.len() >> 8) as u8, body.len() as u8, 17, 64];
+        out.extend_from_slice(&src.octets());
+        out.extend_from_slice(&dst.octets());
+        out.extend_from_slice(body);
+        out
+    }
+
+    #[test]
+    fn test_ipv6_transition() {
+        let src: Ipv6Addr = "2002:c000:0201::1".parse().unwrap();
+        let dst: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let inner = ipv6(src, dst, &udp(8080, b"six"));
+        let outer = ipv4(PROTO_IPV6, &inner);
+        let stack = Decapsulator::new().decap(parse_packet(LinkType::Raw, &outer));
+        assert_eq!(stack.tunnels[0].tunnel, Tunnel::SixToFour);
+        assert!(matches!(stack.innermost().ip, Some(IpPacket::V6(_))));
+        assert_eq!(stack.innermost().payload, b"six");
+        assert_eq!(six_to_four_address(src), Some(Ipv4Addr::new(192, 0, 2, 1)));
+
+        // the example from RFC 4380, section 4
+        let client: Ipv6Addr = "2001:0:4136:e378:8000:63bf:3fff:fdd2".parse().unwrap();
+        assert_eq!(teredo_address(client),
+            Some((Ipv4Addr::new(65, 54, 227, 120), "192.0.2.45:40000".parse().unwrap())));
+        let mut teredo = vec![0x00, 0x00, 0x63, 0xbf, 0x3f, 0xff, 0xfd, 0xd2];
+        teredo.extend_from_slice(&ipv6(dst, client, &udp(8080, b"teredo")));
+        let outer = ipv4(17, &udp(TEREDO_PORT, &teredo));
+        let stack = Decapsulator::new().decap(parse_packet(LinkType::Raw, &outer));
+        match stack.tunnels[0].tunnel {
+            Tunnel::Teredo(ref t) => assert_eq!(t.origin, Some("192.0.2.45:40000".parse().unwrap())),
+            ref x => panic!("expected Teredo, got {:?}", x),
+        }
+        assert_eq!(stack.innermost().payload, b"teredo");
+    }
+}
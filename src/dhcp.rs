@@ -0,0 +1,702 @@
+use std::fmt;
+use std::net::Ipv4Addr;
+
+use nom::bytes::complete::{tag, take};
+use nom::combinator::{all_consuming, opt};
+use nom::error::context;
+use nom::multi::{count, many0};
+use nom::number::complete::{be_u8, be_u16, be_u32};
+
+use error::{IResult, ParseError};
+use ethernet::MacAddr;
+use {Fixup, IntoOwned, ToBytes};
+
+// https://tools.ietf.org/html/rfc2131, options in
+// https://tools.ietf.org/html/rfc2132
+
+pub const SERVER_PORT: u16 = 67;
+pub const CLIENT_PORT: u16 = 68;
+
+/// Starts the options, telling DHCP apart from plain BOOTP
+pub const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+pub const PAD: u8 = 0;
+pub const HOST_NAME: u8 = 12;
+pub const MESSAGE_TYPE: u8 = 53;
+pub const PARAMETER_REQUEST_LIST: u8 = 55;
+pub const VENDOR_SPECIFIC: u8 = 43;
+pub const VENDOR_CLASS_IDENTIFIER: u8 = 60;
+pub const TFTP_SERVER_NAME: u8 = 66;
+pub const BOOTFILE_NAME: u8 = 67;
+pub const CLIENT_ARCHITECTURE: u8 = 93;
+pub const CLIENT_INTERFACE_ID: u8 = 94;
+pub const CLIENT_MACHINE_ID: u8 = 97;
+pub const END: u8 = 255;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DhcpMessage<'a> {
+    pub op: Op,
+    /// The hardware type, 1 for Ethernet
+    pub htype: u8,
+    pub hops: u8,
+    pub xid: u32,
+    pub secs: u16,
+    /// The client can't take unicast replies until it's configured
+    pub broadcast: bool,
+    pub ciaddr: Ipv4Addr,
+    /// The address the server is offering or assigning
+    pub yiaddr: Ipv4Addr,
+    pub siaddr: Ipv4Addr,
+    /// The relay agent's address, if one passed the message on
+    pub giaddr: Ipv4Addr,
+    /// Only the hardware address length's worth of the field
+    pub chaddr: &'a [u8],
+    /// Still NUL padded
+    pub sname: &'a [u8],
+    /// Still NUL padded
+    pub file: &'a [u8],
+    /// Empty for BOOTP, which has no magic cookie. Pads and the end option
+    /// are left out.
+    pub options: Vec<DhcpOption<'a>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Op {
+    Request,
+    Reply,
+    Other(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DhcpOption<'a> {
+    pub code: u8,
+    pub data: &'a [u8],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MessageType {
+    Discover,
+    Offer,
+    Request,
+    Decline,
+    Ack,
+    Nak,
+    Release,
+    Inform,
+    Other(u8),
+}
+
+impl MessageType {
+    pub fn from_u8(v: u8) -> MessageType {
+        match v {
+            1 => MessageType::Discover,
+            2 => MessageType::Offer,
+            3 => MessageType::Request,
+            4 => MessageType::Decline,
+            5 => MessageType::Ack,
+            6 => MessageType::Nak,
+            7 => MessageType::Release,
+            8 => MessageType::Inform,
+            x => MessageType::Other(x),
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            MessageType::Discover => 1,
+            MessageType::Offer => 2,
+            MessageType::Request => 3,
+            MessageType::Decline => 4,
+            MessageType::Ack => 5,
+            MessageType::Nak => 6,
+            MessageType::Release => 7,
+            MessageType::Inform => 8,
+            MessageType::Other(x) => x,
+        }
+    }
+}
+
+impl <'a> DhcpMessage<'a> {
+    /// The data of the first option with the given code
+    pub fn option(&self, code: u8) -> Option<&'a [u8]> {
+        self.options.iter().find(|o| o.code == code).map(|o| o.data)
+    }
+
+    /// `None` for BOOTP
+    pub fn message_type(&self) -> Option<MessageType> {
+        match self.option(MESSAGE_TYPE) {
+            Some(&[typ]) => Some(MessageType::from_u8(typ)),
+            _ => None,
+        }
+    }
+
+    /// The option codes the client asked for, in the order it asked
+    pub fn parameter_request_list(&self) -> Option<&'a [u8]> {
+        self.option(PARAMETER_REQUEST_LIST)
+    }
+
+    /// Often names the client's DHCP stack, like "MSFT 5.0" or
+    /// "android-dhcp-13"
+    pub fn vendor_class(&self) -> Option<&'a [u8]> {
+        self.option(VENDOR_CLASS_IDENTIFIER)
+    }
+
+    pub fn host_name(&self) -> Option<&'a [u8]> {
+        self.option(HOST_NAME)
+    }
+
+    /// The client's MAC address, if its hardware is Ethernet
+    pub fn client_mac(&self) -> Option<MacAddr> {
+        match self.htype {
+            1 => MacAddr::from_slice(self.chaddr),
+            _ => None,
+        }
+    }
+
+    /// Sent by PXE clients, and by servers answering them
+    pub fn is_pxe(&self) -> bool {
+        self.vendor_class().is_some_and(|class| class.starts_with(b"PXEClient"))
+    }
+
+    /// The PXE sub-options in option 43, which only means PXE when
+    /// `is_pxe`. `None` if there aren't any, or they're malformed.
+    pub fn pxe_options(&self) -> Option<Vec<PxeOption<'a>>> {
+        if !self.is_pxe() {
+            return None;
+        }
+        let data = self.option(VENDOR_SPECIFIC)?;
+        all_consuming(context("dhcp.pxe", pxe_options))(data).ok().map(|(_, options)| options)
+    }
+
+    /// Option 66, or else the server host name field, which BOOTP clients
+    /// take the TFTP server from
+    pub fn tftp_server_name(&self) -> Option<&'a [u8]> {
+        self.option(TFTP_SERVER_NAME).or_else(|| nul_terminated(self.sname))
+    }
+
+    /// Option 67, or else the boot file name field
+    pub fn bootfile_name(&self) -> Option<&'a [u8]> {
+        self.option(BOOTFILE_NAME).or_else(|| nul_terminated(self.file))
+    }
+
+    /// Option 93, the architectures a PXE client can boot, RFC 4578
+    pub fn client_architectures(&self) -> Option<Vec<Architecture>> {
+        let data = self.option(CLIENT_ARCHITECTURE)?;
+        let (_, archs) = all_consuming(many0(be_u16::<_, ParseError>))(data).ok()?;
+        Some(archs.into_iter().map(Architecture::from).collect())
+    }
+
+    /// Option 94, the version of the client's UNDI network driver
+    pub fn client_interface_id(&self) -> Option<InterfaceId> {
+        match self.option(CLIENT_INTERFACE_ID) {
+            Some(&[typ, major, minor]) => Some(InterfaceId { typ, major, minor }),
+            _ => None,
+        }
+    }
+
+    /// Option 97, the client's SMBIOS UUID
+    pub fn client_machine_id(&self) -> Option<MachineId> {
+        match self.option(CLIENT_MACHINE_ID) {
+            Some(&[0, ref uuid @ ..]) if uuid.len() == 16 => {
+                let mut id = [0; 16];
+                id.copy_from_slice(uuid);
+                Some(MachineId(id))
+            },
+            _ => None,
+        }
+    }
+}
+
+// sname and file, which are empty when they're all zeros or when option
+// overloading has put options in them instead
+fn nul_terminated(field: &[u8]) -> Option<&[u8]> {
+    let name = &field[..field.iter().position(|&b| b == 0).unwrap_or(field.len())];
+    if name.is_empty() { None } else { Some(name) }
+}
+
+/// A client system architecture, from the IANA registry that RFC 4578
+/// started
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Architecture {
+    X86Bios,
+    NecPc98,
+    Itanium,
+    DecAlpha,
+    ArcX86,
+    IntelLean,
+    X86Uefi,
+    X64Uefi,
+    XscaleUefi,
+    EbcUefi,
+    Arm32Uefi,
+    Arm64Uefi,
+    X86UefiHttp,
+    X64UefiHttp,
+    EbcUefiHttp,
+    Arm32UefiHttp,
+    Arm64UefiHttp,
+    Other(u16),
+}
+
+impl Architecture {
+    pub fn to_u16(&self) -> u16 {
+        match *self {
+            Architecture::X86Bios => 0,
+            Architecture::NecPc98 => 1,
+            Architecture::Itanium => 2,
+            Architecture::DecAlpha => 3,
+            Architecture::ArcX86 => 4,
+            Architecture::IntelLean => 5,
+            Architecture::X86Uefi => 6,
+            Architecture::X64Uefi => 7,
+            Architecture::XscaleUefi => 8,
+            Architecture::EbcUefi => 9,
+            Architecture::Arm32Uefi => 10,
+            Architecture::Arm64Uefi => 11,
+            Architecture::X86UefiHttp => 15,
+            Architecture::X64UefiHttp => 16,
+            Architecture::EbcUefiHttp => 17,
+            Architecture::Arm32UefiHttp => 18,
+            Architecture::Arm64UefiHttp => 19,
+            Architecture::Other(x) => x,
+        }
+    }
+}
+
+impl From<u16> for Architecture {
+    fn from(v: u16) -> Architecture {
+        match v {
+            0 => Architecture::X86Bios,
+            1 => Architecture::NecPc98,
+            2 => Architecture::Itanium,
+            3 => Architecture::DecAlpha,
+            4 => Architecture::ArcX86,
+            5 => Architecture::IntelLean,
+            6 => Architecture::X86Uefi,
+            7 => Architecture::X64Uefi,
+            8 => Architecture::XscaleUefi,
+            9 => Architecture::EbcUefi,
+            10 => Architecture::Arm32Uefi,
+            11 => Architecture::Arm64Uefi,
+            15 => Architecture::X86UefiHttp,
+            16 => Architecture::X64UefiHttp,
+            17 => Architecture::EbcUefiHttp,
+            18 => Architecture::Arm32UefiHttp,
+            19 => Architecture::Arm64UefiHttp,
+            x => Architecture::Other(x),
+        }
+    }
+}
+
+/// The UNDI version of a PXE client's network driver, like 2.1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InterfaceId {
+    /// 1 for UNDI
+    pub typ: u8,
+    pub major: u8,
+    pub minor: u8,
+}
+
+/// A PXE client's UUID, in the byte order it was sent
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MachineId(pub [u8; 16]);
+
+impl fmt::Display for MachineId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, b) in self.0.iter().enumerate() {
+            if i == 4 || i == 6 || i == 8 || i == 10 {
+                write!(f, "-")?;
+            }
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// A PXE sub-option of option 43, PXE 2.1 section 2.4
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum PxeOption<'a> {
+    MtftpAddress(Ipv4Addr),
+    MtftpClientPort(u16),
+    MtftpServerPort(u16),
+    /// In seconds
+    MtftpTimeout(u8),
+    /// In seconds
+    MtftpDelay(u8),
+    /// Bit 3 set means boot the file given without boot server discovery
+    DiscoveryControl(u8),
+    DiscoveryMulticastAddress(Ipv4Addr),
+    BootServers(Vec<BootServer>),
+    BootMenu(Vec<BootMenuItem<'a>>),
+    /// How long to show the prompt before taking the first menu item, in
+    /// seconds; zero boots straight away and 255 waits for the user
+    MenuPrompt { timeout: u8, prompt: &'a [u8] },
+    /// The boot server type and layer being asked for or answered with
+    BootItem { typ: u16, layer: u16 },
+    Other { code: u8, data: &'a [u8] },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BootServer {
+    /// Zero for the PXE bootstrap server
+    pub typ: u16,
+    pub addrs: Vec<Ipv4Addr>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct BootMenuItem<'a> {
+    /// The boot server type it selects
+    pub typ: u16,
+    pub description: &'a [u8],
+}
+
+// the same code, length and data layout as the options they're in
+fn pxe_options(bs: &[u8]) -> IResult<'_, Vec<PxeOption<'_>>> {
+    let (bs, raw) = options(bs)?;
+    let options = raw.into_iter().map(|DhcpOption { code, data }| {
+        let option = match code {
+            1 => PxeOption::MtftpAddress(all_consuming(ipv4_addr)(data)?.1),
+            2 => PxeOption::MtftpClientPort(all_consuming(be_u16)(data)?.1),
+            3 => PxeOption::MtftpServerPort(all_consuming(be_u16)(data)?.1),
+            4 => PxeOption::MtftpTimeout(all_consuming(be_u8)(data)?.1),
+            5 => PxeOption::MtftpDelay(all_consuming(be_u8)(data)?.1),
+            6 => PxeOption::DiscoveryControl(all_consuming(be_u8)(data)?.1),
+            7 => PxeOption::DiscoveryMulticastAddress(all_consuming(ipv4_addr)(data)?.1),
+            8 => PxeOption::BootServers(all_consuming(many0(boot_server))(data)?.1),
+            9 => PxeOption::BootMenu(all_consuming(many0(boot_menu_item))(data)?.1),
+            10 => {
+                let (prompt, timeout) = be_u8(data)?;
+                PxeOption::MenuPrompt { timeout, prompt }
+            },
+            71 => {
+                let (_, (typ, layer)) = all_consuming(|bs| {
+                    let (bs, typ) = be_u16(bs)?;
+                    let (bs, layer) = be_u16(bs)?;
+                    Ok((bs, (typ, layer)))
+                })(data)?;
+                PxeOption::BootItem { typ, layer }
+            },
+            _ => PxeOption::Other { code, data },
+        };
+        Ok(option)
+    }).collect::<Result<_, _>>()?;
+    Ok((bs, options))
+}
+
+fn boot_server(bs: &[u8]) -> IResult<'_, BootServer> {
+    let (bs, typ) = be_u16(bs)?;
+    let (bs, n) = be_u8(bs)?;
+    let (bs, addrs) = count(ipv4_addr, n as usize)(bs)?;
+    Ok((bs, BootServer { typ, addrs }))
+}
+
+fn boot_menu_item(bs: &[u8]) -> IResult<'_, BootMenuItem<'_>> {
+    let (bs, typ) = be_u16(bs)?;
+    let (bs, len) = be_u8(bs)?;
+    let (bs, description) = take(len)(bs)?;
+    Ok((bs, BootMenuItem { typ, description }))
+}
+
+impl <'a> fmt::Display for DhcpMessage<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.message_type() {
+            Some(typ) => write!(f, "DHCP {:?}", typ)?,
+            None => write!(f, "BOOTP {:?}", self.op)?,
+        }
+        write!(f, ", xid {:#010x}", self.xid)
+    }
+}
+
+pub fn parse_dhcp_message(bs: &[u8]) -> IResult<'_, DhcpMessage<'_>> {
+    context("dhcp", dhcp_message)(bs)
+}
+
+fn ipv4_addr(bs: &[u8]) -> IResult<'_, Ipv4Addr> {
+    let (bs, addr) = be_u32(bs)?;
+    Ok((bs, Ipv4Addr::from(addr)))
+}
+
+fn dhcp_message(bs: &[u8]) -> IResult<'_, DhcpMessage<'_>> {
+    let (bs, op) = be_u8(bs)?;
+    let (bs, htype) = be_u8(bs)?;
+    let (bs, hlen) = be_u8(bs)?;
+    let (bs, hops) = be_u8(bs)?;
+    let (bs, xid) = be_u32(bs)?;
+    let (bs, secs) = be_u16(bs)?;
+    let (bs, flags) = be_u16(bs)?;
+    let (bs, ciaddr) = ipv4_addr(bs)?;
+    let (bs, yiaddr) = ipv4_addr(bs)?;
+    let (bs, siaddr) = ipv4_addr(bs)?;
+    let (bs, giaddr) = ipv4_addr(bs)?;
+    let (bs, chaddr) = take(16usize)(bs)?;
+    let (bs, sname) = take(64usize)(bs)?;
+    let (bs, file) = take(128usize)(bs)?;
+    let (bs, cookie) = opt(tag(MAGIC_COOKIE))(bs)?;
+    let (bs, options) = match cookie {
+        Some(_) => context("dhcp.options", options)(bs)?,
+        None => (bs, vec![]),
+    };
+    Ok((bs, DhcpMessage {
+        op: match op {
+            1 => Op::Request,
+            2 => Op::Reply,
+            x => Op::Other(x),
+        },
+        htype,
+        hops,
+        xid,
+        secs,
+        broadcast: flags & 0x8000 != 0,
+        ciaddr,
+        yiaddr,
+        siaddr,
+        giaddr,
+        chaddr: &chaddr[..(hlen as usize).min(16)],
+        sname,
+        file,
+        options,
+    }))
+}
+
+// up to the end option, or the end of the input since plenty of clients
+// leave it off; anything after the end option is padding
+fn options(mut bs: &[u8]) -> IResult<'_, Vec<DhcpOption<'_>>> {
+    let mut options = vec![];
+    while let Some((&code, rest)) = bs.split_first() {
+        bs = rest;
+        match code {
+            PAD => continue,
+            END => return Ok((&bs[bs.len()..], options)),
+            _ => {
+                let (rest, len) = be_u8(bs)?;
+                let (rest, data) = take(len)(rest)?;
+                options.push(DhcpOption { code, data });
+                bs = rest;
+            },
+        }
+    }
+    Ok((bs, options))
+}
+
+/// Writes the message back out. The end option follows the options, and
+/// the message is padded to the 300 bytes BOOTP relays expect.
+impl <'a> ToBytes for DhcpMessage<'a> {
+    fn write_with(&self, _fixup: Fixup, out: &mut Vec<u8>) {
+        let start = out.len();
+        let op = match self.op {
+            Op::Request => 1,
+            Op::Reply => 2,
+            Op::Other(x) => x,
+        };
+        out.extend_from_slice(&[op, self.htype, self.chaddr.len() as u8, self.hops]);
+        out.extend_from_slice(&self.xid.to_be_bytes());
+        out.extend_from_slice(&self.secs.to_be_bytes());
+        out.extend_from_slice(&(if self.broadcast { 0x8000u16 } else { 0 }).to_be_bytes());
+        for addr in &[self.ciaddr, self.yiaddr, self.siaddr, self.giaddr] {
+            out.extend_from_slice(&addr.octets());
+        }
+        for &(field, len) in &[(self.chaddr, 16), (self.sname, 64), (self.file, 128)] {
+            let field = &field[..field.len().min(len)];
+            out.extend_from_slice(field);
+            out.resize(out.len() + len - field.len(), 0);
+        }
+        if !self.options.is_empty() {
+            out.extend_from_slice(&MAGIC_COOKIE);
+            for option in &self.options {
+                out.push(option.code);
+                out.push(option.data.len() as u8);
+                out.extend_from_slice(option.data);
+            }
+            out.push(END);
+        }
+        if out.len() - start < 300 {
+            out.resize(start + 300, PAD);
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedDhcpMessage {
+    pub op: Op,
+    pub htype: u8,
+    pub hops: u8,
+    pub xid: u32,
+    pub secs: u16,
+    pub broadcast: bool,
+    pub ciaddr: Ipv4Addr,
+    pub yiaddr: Ipv4Addr,
+    pub siaddr: Ipv4Addr,
+    pub giaddr: Ipv4Addr,
+    pub chaddr: Vec<u8>,
+    pub sname: Vec<u8>,
+    pub file: Vec<u8>,
+    pub options: Vec<OwnedDhcpOption>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedDhcpOption {
+    pub code: u8,
+    pub data: Vec<u8>,
+}
+
+impl <'a> IntoOwned for DhcpMessage<'a> {
+    type Owned = OwnedDhcpMessage;
+
+    fn into_owned(self) -> OwnedDhcpMessage {
+        OwnedDhcpMessage {
+            op: self.op,
+            htype: self.htype,
+            hops: self.hops,
+            xid: self.xid,
+            secs: self.secs,
+            broadcast: self.broadcast,
+            ciaddr: self.ciaddr,
+            yiaddr: self.yiaddr,
+            siaddr: self.siaddr,
+            giaddr: self.giaddr,
+            chaddr: self.chaddr.into_owned(),
+            sname: self.sname.into_owned(),
+            file: self.file.into_owned(),
+            options: self.options.into_iter().map(|option| OwnedDhcpOption {
+                code: option.code,
+                data: option.data.into_owned(),
+            }).collect(),
+        }
+    }
+}
+
+impl OwnedDhcpMessage {
+    pub fn to_borrowed(&self) -> DhcpMessage<'_> {
+        DhcpMessage {
+            op: self.op,
+            htype: self.htype,
+            hops: self.hops,
+            xid: self.xid,
+            secs: self.secs,
+            broadcast: self.broadcast,
+            ciaddr: self.ciaddr,
+            yiaddr: self.yiaddr,
+            siaddr: self.siaddr,
+            giaddr: self.giaddr,
+            chaddr: &self.chaddr,
+            sname: &self.sname,
+            file: &self.file,
+            options: self.options.iter().map(|option| DhcpOption { code: option.code, data: &option.data }).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a DHCPDISCOVER from 00:11:22:33:44:55 with the given options after
+    // the magic cookie
+    fn discover(options: &[u8]) -> Vec<u8> {
+        let mut message = vec![0x01, 0x01, 0x06, 0x00, 0xde, 0xad, 0xbe, 0xef, 0x00, 0x00, 0x80, 0x00];
+        message.extend_from_slice(&[0; 16]);
+        message.extend_from_slice(&[0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        message.extend_from_slice(&[0; 10 + 64 + 128]);
+        message.extend_from_slice(&MAGIC_COOKIE);
+        message.extend_from_slice(options);
+        message
+    }
+
+    #[test]
+    fn test_dhcp_discover() {
+        let message = discover(&[
+            0x35, 0x01, 0x01,
+            0x37, 0x04, 0x01, 0x03, 0x06, 0x0f,
+            0x00,
+            0x3c, 0x08, b'M', b'S', b'F', b'T', b' ', b'5', b'.', b'0',
+            0x0c, 0x04, b'h', b'o', b's', b't',
+            0xff, 0x00, 0x00,
+        ]);
+        let (rest, dhcp) = parse_dhcp_message(&message).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!((dhcp.op, dhcp.xid, dhcp.broadcast), (Op::Request, 0xdeadbeef, true));
+        assert_eq!(dhcp.client_mac(), Some(MacAddr([0x00, 0x11, 0x22, 0x33, 0x44, 0x55])));
+        assert_eq!(dhcp.message_type(), Some(MessageType::Discover));
+        assert_eq!(dhcp.parameter_request_list(), Some(&[1, 3, 6, 15][..]));
+        assert_eq!(dhcp.vendor_class(), Some(&b"MSFT 5.0"[..]));
+        assert_eq!(dhcp.host_name(), Some(&b"host"[..]));
+        assert_eq!(dhcp.options.len(), 4);
+        assert_eq!(dhcp.to_string(), "DHCP Discover, xid 0xdeadbeef");
+
+        // written back out, without the pad option and padded to 300 bytes
+        let bytes = dhcp.to_bytes();
+        assert_eq!(bytes.len(), 300);
+        assert_eq!(parse_dhcp_message(&bytes).unwrap().1, dhcp);
+        assert_eq!(dhcp.clone().into_owned().to_borrowed(), dhcp);
+
+        // plain BOOTP, and an option running off the end
+        let (_, bootp) = parse_dhcp_message(&message[..236]).unwrap();
+        assert_eq!((bootp.message_type(), bootp.options.len()), (None, 0));
+        assert_eq!(bootp.to_string(), "BOOTP Request, xid 0xdeadbeef");
+        assert!(parse_dhcp_message(&discover(&[0x37, 0x04, 0x01])).is_err());
+    }
+
+    #[test]
+    fn test_pxe() {
+        let mut options = vec![0x35, 0x01, 0x01, 0x5d, 0x02, 0x00, 0x07, 0x5e, 0x03, 0x01, 0x03, 0x10];
+        options.extend_from_slice(&[0x61, 0x11, 0x00]);
+        options.extend_from_slice(&[0x4c, 0x4c, 0x45, 0x44, 0x00, 0x51, 0x10, 0x80, 0x80, 0x31, 0xb4, 0xc0, 0x4f, 0x30, 0x50, 0x32]);
+        options.extend_from_slice(b"\x3c\x20PXEClient:Arch:00007:UNDI:003016\xff");
+        let message = discover(&options);
+        let (_, request) = parse_dhcp_message(&message).unwrap();
+        assert!(request.is_pxe());
+        assert_eq!(request.client_architectures(), Some(vec![Architecture::X64Uefi]));
+        assert_eq!(request.client_interface_id(), Some(InterfaceId { typ: 1, major: 3, minor: 16 }));
+        assert_eq!(request.client_machine_id().unwrap().to_string(), "4c4c4544-0051-1080-8031-b4c04f305032");
+        // a client asking for nothing in particular
+        assert_eq!(request.pxe_options(), None);
+        assert_eq!((request.tftp_server_name(), request.bootfile_name()), (None, None));
+
+        // the offer back, with boot servers, a menu and a prompt
+        let mut options = b"\x35\x01\x02\x3c\x09PXEClient\x42\x0a192.0.2.10\x43\x0bpxelinux.0\x00".to_vec();
+        let vendor = [
+            0x06, 0x01, 0x03,
+            0x08, 0x07, 0x80, 0x00, 0x01, 0xc0, 0x00, 0x02, 0x0a,
+            0x09, 0x08, 0x80, 0x00, 0x05, b'L', b'i', b'n', b'u', b'x',
+            0x0a, 0x05, 0x0a, b'b', b'o', b'o', b't',
+            0x47, 0x04, 0x80, 0x00, 0x00, 0x00,
+            0xff,
+        ];
+        options.push(0x2b);
+        options.push(vendor.len() as u8);
+        options.extend_from_slice(&vendor);
+        let message = discover(&options);
+        let (_, offer) = parse_dhcp_message(&message).unwrap();
+        assert_eq!(offer.tftp_server_name(), Some(&b"192.0.2.10"[..]));
+        assert_eq!(offer.bootfile_name(), Some(&b"pxelinux.0\x00"[..]));
+        assert_eq!(offer.pxe_options().unwrap(), vec![
+            PxeOption::DiscoveryControl(3),
+            PxeOption::BootServers(vec![BootServer { typ: 0x8000, addrs: vec![Ipv4Addr::new(192, 0, 2, 10)] }]),
+            PxeOption::BootMenu(vec![BootMenuItem { typ: 0x8000, description: b"Linux" }]),
+            PxeOption::MenuPrompt { timeout: 10, prompt: b"boot" },
+            PxeOption::BootItem { typ: 0x8000, layer: 0 },
+        ]);
+
+        // plain BOOTP names its boot file in the header, and a boot server
+        // list claiming more addresses than it has is malformed
+        let mut message = discover(&[]);
+        message[108..117].copy_from_slice(b"boot.img\x00");
+        let (_, bootp) = parse_dhcp_message(&message[..236]).unwrap();
+        assert_eq!(bootp.bootfile_name(), Some(&b"boot.img"[..]));
+        let message = discover(b"\x3c\x09PXEClient\x2b\x05\x08\x03\x80\x00\x02");
+        let (_, offer) = parse_dhcp_message(&message).unwrap();
+        assert_eq!(offer.pxe_options(), None);
+    }
+}
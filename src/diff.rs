@@ -0,0 +1,139 @@
+use std::fmt;
+
+use dissect::Packet;
+use dns;
+use json::{self, Value};
+
+/// A field that differs between two packets. `path` leads to it through
+/// the JSON export, like "ip/ip.ttl" or "dns/Answers/0/dns.resp.ttl", and
+/// it's `None` on whichever side doesn't have the field at all.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Difference {
+    pub path: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+impl Difference {
+    /// The field's Wireshark style name, the last part of `path`
+    pub fn field(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+}
+
+/// Like "ip/ip.ttl: 64 -> 10", with "-" for a missing side
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn side(f: &mut fmt::Formatter, v: &Option<Value>) -> fmt::Result {
+            match *v {
+                Some(ref v) => write!(f, "{}", v),
+                None => write!(f, "-"),
+            }
+        }
+        write!(f, "{}: ", self.path)?;
+        side(f, &self.before)?;
+        write!(f, " -> ")?;
+        side(f, &self.after)
+    }
+}
+
+/// Compares two dissected packets field by field, in the order the fields
+/// are on the wire. Layers only one of them has show up as a single
+/// difference for the whole layer.
+pub fn diff_packets(before: &Packet, after: &Packet) -> Vec<Difference> {
+    let layers = |packet: &Packet| json::to_json(packet)
+        .get("_source")
+        .and_then(|s| s.get("layers"))
+        .cloned()
+        .unwrap_or(Value::Null);
+    diff_values(&layers(before), &layers(after))
+}
+
+/// Compares two DNS messages field by field
+pub fn diff_dns(before: &dns::Message, after: &dns::Message) -> Vec<Difference> {
+    diff_values(&json::dns_to_json(before), &json::dns_to_json(after))
+}
+
+/// Compares two JSON values, recursing into objects by key and arrays by
+/// index. The human readable `_show` fields are skipped, since they only
+/// ever change along with the field they describe.
+pub fn diff_values(before: &Value, after: &Value) -> Vec<Difference> {
+    let mut diffs = vec![];
+    walk(&mut diffs, String::new(), Some(before), Some(after));
+    diffs
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}/{}", path, key)
+    }
+}
+
+fn find<'a>(fields: &'a [(String, Value)], key: &str) -> Option<&'a Value> {
+    fields.iter().find(|f| f.0 == key).map(|f| &f.1)
+}
+
+fn walk(diffs: &mut Vec<Difference>, path: String, before: Option<&Value>, after: Option<&Value>) {
+    match (before, after) {
+        (Some(Value::Object(a)), Some(Value::Object(b))) => {
+            let keys = a.iter().map(|f| &f.0)
+                .chain(b.iter().map(|f| &f.0).filter(|k| !a.iter().any(|f| f.0 == **k)));
+            for key in keys.filter(|k| !k.ends_with("_show")) {
+                walk(diffs, join(&path, key), find(a, key), find(b, key));
+            }
+        },
+        (Some(Value::Array(a)), Some(Value::Array(b))) => {
+            for i in 0..a.len().max(b.len()) {
+                walk(diffs, join(&path, &i.to_string()), a.get(i), b.get(i));
+            }
+        },
+        (a, b) if a != b => diffs.push(Difference {
+            path,
+            before: a.cloned(),
+            after: b.cloned(),
+        }),
+        _ => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dissect::{parse_packet, LinkType};
+
+    #[test]
+    fn test_diff_nat() {
+        let frame = [
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0x08, 0x00,
+
+            0x45, 0x00, 0x00, 0x1e, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x01,
+            0x08, 0x08, 0x08, 0x08,
+
+            0x10, 0xd8, 0x1f, 0x90, 0x00, 0x0a, 0x00, 0x00,
+
+            0xca, 0xfe,
+        ];
+        let mut translated = frame;
+        // source address and port rewritten, checksums left alone
+        translated[26..30].copy_from_slice(&[203, 0, 113, 7]);
+        translated[34..36].copy_from_slice(&[0x9c, 0x40]);
+        let before = parse_packet(LinkType::Ethernet, &frame);
+        let after = parse_packet(LinkType::Ethernet, &translated);
+        let diffs = diff_packets(&before, &after);
+        let shown: Vec<String> = diffs.iter().map(|d| d.to_string()).collect();
+        assert_eq!(shown, vec![
+            "ip/ip.src: \"10.0.0.1\" -> \"203.0.113.7\"",
+            "udp/udp.srcport: 4312 -> 40000",
+        ]);
+        assert_eq!(diffs[1].field(), "udp.srcport");
+        assert!(diff_packets(&before, &before).is_empty());
+
+        let raw = parse_packet(LinkType::Raw, &frame[14..]);
+        let diffs = diff_packets(&before, &raw);
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].path, "eth");
+        assert!(diffs[0].after.is_none());
+    }
+}
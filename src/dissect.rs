@@ -0,0 +1,1072 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::rc::Rc;
+
+use nom::combinator::all_consuming;
+
+use dhcp;
+use dns;
+use enrich::{Enricher, Enrichment, Subject};
+use ethernet;
+use ethernet::EtherType;
+use http;
+use ipv4;
+use ipv6;
+use json;
+use {Fixup, IntoOwned, IpPacket, OwnedIpPacket, OwnedTransportLayerPacket, Timestamp, ToBytes, TransportLayerPacket};
+
+/// Link layer framing of a captured packet, numbered as in pcap's
+/// LINKTYPE_ values
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LinkType {
+    Ethernet,
+    // raw IPv4 or IPv6, told apart by the version field
+    Raw,
+    Ipv4,
+    Ipv6,
+    Other(u32),
+}
+
+impl LinkType {
+    pub fn from_u32(v: u32) -> LinkType {
+        match v {
+            1 => LinkType::Ethernet,
+            101 => LinkType::Raw,
+            228 => LinkType::Ipv4,
+            229 => LinkType::Ipv6,
+            x => LinkType::Other(x),
+        }
+    }
+
+    pub fn to_u32(&self) -> u32 {
+        match *self {
+            LinkType::Ethernet => 1,
+            LinkType::Raw => 101,
+            LinkType::Ipv4 => 228,
+            LinkType::Ipv6 => 229,
+            LinkType::Other(x) => x,
+        }
+    }
+}
+
+/// Whether Ethernet frames end with their FCS, which most captures strip
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FcsMode {
+    #[default]
+    Absent,
+    // it's always there, as a pcapng if_fcslen of 4 says
+    Present,
+    // only taken to be there if the last four bytes are the CRC of the rest
+    Detect,
+}
+
+impl FcsMode {
+    /// The mode for a pcapng interface's if_fcslen option
+    pub fn from_fcs_len(fcs_len: Option<u8>) -> FcsMode {
+        match fcs_len {
+            Some(4) => FcsMode::Present,
+            Some(_) => FcsMode::Absent,
+            None => FcsMode::Detect,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum ApplicationLayer<'a> {
+    Dns(dns::Message<'a>),
+    Dhcp(dhcp::DhcpMessage<'a>),
+    /// A request head; `Packet::payload` is whatever of the body follows
+    /// it in the segment
+    HttpRequest(http::Request<'a>),
+    /// A response head, with the body after it in `Packet::payload`
+    HttpResponse(http::Response<'a>),
+}
+
+/// A packet dissected as far down as the parsers could go. Each layer is
+/// `None` if it wasn't present or couldn't be parsed, and `payload` is the
+/// innermost data that wasn't dissected any further, which is whatever
+/// follows the application layer if there is one: nothing for DNS and DHCP,
+/// and the start of the body for HTTP. `custom` holds the
+/// output of a user dissector from a `Registry`, if one claimed the payload.
+/// `fcs` is only set if the `Registry`'s `FcsMode` found one, and it's
+/// never part of the Ethernet body or the payload. `enrichment` holds what
+/// the `Registry`'s `Enricher` had to say, if it has one. `timestamp` is
+/// when the packet was captured, for packets that come from a capture.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Packet<'a> {
+    pub timestamp: Option<Timestamp>,
+    pub ethernet: Option<ethernet::EthernetIIPacket<'a>>,
+    pub fcs: Option<ethernet::Fcs>,
+    pub ip: Option<IpPacket<'a>>,
+    pub transport: Option<TransportLayerPacket<'a>>,
+    pub application: Option<ApplicationLayer<'a>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub custom: Option<Rc<dyn CustomLayer>>,
+    pub payload: &'a [u8],
+    pub enrichment: Vec<(Subject, Enrichment)>,
+}
+
+impl <'a> Packet<'a> {
+    /// The packet as tshark style JSON, see `json::to_json`
+    pub fn to_json(&self) -> json::Value {
+        json::to_json(self)
+    }
+
+    /// Sets the IPv4 TTL or IPv6 hop limit
+    pub fn set_ttl(&mut self, ttl: u8) {
+        match self.ip {
+            Some(IpPacket::V4(ref mut ip4)) => ip4.header.ttl = ttl,
+            Some(IpPacket::V6(ref mut ip6)) => ip6.header.hop_limit = ttl,
+            None => {},
+        }
+    }
+
+    /// Sets the source address. Returns false if there's no IP layer, or
+    /// it's of the other version.
+    pub fn set_src_ip(&mut self, addr: IpAddr) -> bool {
+        self.set_ip(addr, true)
+    }
+
+    pub fn set_dst_ip(&mut self, addr: IpAddr) -> bool {
+        self.set_ip(addr, false)
+    }
+
+    fn set_ip(&mut self, addr: IpAddr, src: bool) -> bool {
+        match (&mut self.ip, addr) {
+            (&mut Some(IpPacket::V4(ref mut ip4)), IpAddr::V4(addr)) => if src {
+                ip4.header.src_ip = addr;
+            } else {
+                ip4.header.dst_ip = addr;
+            },
+            (&mut Some(IpPacket::V6(ref mut ip6)), IpAddr::V6(addr)) => if src {
+                ip6.header.src_ip = addr;
+            } else {
+                ip6.header.dst_ip = addr;
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    /// Sets the TCP or UDP ports. Returns false if there are none.
+    pub fn set_ports(&mut self, src: u16, dst: u16) -> bool {
+        match self.transport {
+            Some(TransportLayerPacket::Tcp(ref mut tcp)) => {
+                tcp.header.src = src;
+                tcp.header.dst = dst;
+            },
+            Some(TransportLayerPacket::Udp(ref mut udp)) => {
+                udp.header.src = src;
+                udp.header.dst = dst;
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    /// The DNS message, for changing its answers and the like
+    pub fn dns_mut(&mut self) -> Option<&mut dns::Message<'a>> {
+        match self.application {
+            Some(ApplicationLayer::Dns(ref mut msg)) => Some(msg),
+            _ => None,
+        }
+    }
+
+    /// The user dissector's output, if there is one of type `T`
+    pub fn custom_layer<T: Any>(&self) -> Option<&T> {
+        // deref through the Rc, which is itself a CustomLayer by the blanket impl
+        self.custom.as_ref().and_then(|layer| (**layer).as_any().downcast_ref::<T>())
+    }
+
+    /// What the `Enricher` found for an address or name in the packet
+    pub fn enrichment_for(&self, subject: &Subject) -> Option<&Enrichment> {
+        self.enrichment.iter().find(|e| e.0 == *subject).map(|e| &e.1)
+    }
+}
+
+/// Writes the packet back out, each layer rebuilt from the ones inside it,
+/// so that changes to any of them are kept. Lengths and checksums are
+/// fixed up on the way out like for the layers themselves, and so is the
+/// FCS, if the packet had one. User dissectors' layers are written as
+/// they were captured.
+impl <'a> ToBytes for Packet<'a> {
+    fn write_with(&self, fixup: Fixup, out: &mut Vec<u8>) {
+        let application = self.application.as_ref().map(|app| {
+            let mut msg = app.to_bytes_with(fixup);
+            match (app, &self.transport) {
+                // DNS over TCP carries a two byte length prefix
+                (ApplicationLayer::Dns(_), Some(TransportLayerPacket::Tcp(_))) => {
+                    let mut prefixed = (msg.len() as u16).to_be_bytes().to_vec();
+                    prefixed.extend_from_slice(&msg);
+                    prefixed
+                },
+                (ApplicationLayer::HttpRequest(_), _) | (ApplicationLayer::HttpResponse(_), _) => {
+                    msg.extend_from_slice(self.payload);
+                    msg
+                },
+                _ => msg,
+            }
+        });
+        let transport = self.transport.as_ref().map(|transport| {
+            let mut transport = transport.clone();
+            if let Some(ref body) = application {
+                match transport {
+                    TransportLayerPacket::Tcp(ref mut tcp) => tcp.body = body,
+                    TransportLayerPacket::Udp(ref mut udp) => udp.body = body,
+                    TransportLayerPacket::Icmp(ref mut icmp) => icmp.body = body,
+                }
+            }
+            transport.to_bytes_with(fixup)
+        });
+        let ip = self.ip.as_ref().map(|ip| {
+            let mut ip = ip.clone();
+            if let Some(ref body) = transport {
+                match ip {
+                    IpPacket::V4(ref mut ip4) => ip4.body = body,
+                    IpPacket::V6(ref mut ip6) => ip6.body = body,
+                }
+            }
+            ip.to_bytes_with(fixup)
+        });
+        let start = out.len();
+        match self.ethernet {
+            Some(ref eth) => {
+                let mut eth = eth.clone();
+                if let Some(ref body) = ip {
+                    eth.body = body;
+                }
+                eth.write_with(fixup, out);
+            },
+            None => out.extend_from_slice(ip.as_ref().map_or(self.payload, |ip| ip)),
+        }
+        if let Some(fcs) = self.fcs {
+            let value = if fixup.checksums { ::checksum::crc32(&out[start..]) } else { fcs.value };
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+}
+
+impl <'a> ToBytes for ApplicationLayer<'a> {
+    fn write_with(&self, fixup: Fixup, out: &mut Vec<u8>) {
+        match *self {
+            ApplicationLayer::Dns(ref msg) => msg.write_with(fixup, out),
+            ApplicationLayer::Dhcp(ref msg) => msg.write_with(fixup, out),
+            ApplicationLayer::HttpRequest(ref request) => request.write_with(fixup, out),
+            ApplicationLayer::HttpResponse(ref response) => response.write_with(fixup, out),
+        }
+    }
+}
+
+/// tcpdump style one line summary, like
+/// "IP 10.0.0.1.4312 > 8.8.8.8.53: UDP, DNS query A www.example.com"
+impl <'a> fmt::Display for Packet<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn endpoint(f: &mut fmt::Formatter, addr: IpAddr, port: u16) -> fmt::Result {
+            write!(f, "{}.{}", addr, port)
+        }
+        let ip = match self.ip {
+            Some(ref ip) => ip,
+            None => return match self.ethernet {
+                Some(ref eth) => write!(f, "{}", eth),
+                None => write!(f, "unknown, length {}", self.payload.len()),
+            },
+        };
+        let version = match *ip {
+            IpPacket::V4(_) => "IP",
+            IpPacket::V6(_) => "IP6",
+        };
+        let ports = match self.transport {
+            Some(TransportLayerPacket::Tcp(ref tcp)) => Some((tcp.header.src, tcp.header.dst)),
+            Some(TransportLayerPacket::Udp(ref udp)) => Some((udp.header.src, udp.header.dst)),
+            _ => None,
+        };
+        match ports {
+            Some((src, dst)) => {
+                write!(f, "{} ", version)?;
+                endpoint(f, ip.src_ip(), src)?;
+                write!(f, " > ")?;
+                endpoint(f, ip.dst_ip(), dst)?;
+                write!(f, ": ")?;
+            },
+            None => write!(f, "{} {} > {}: ", version, ip.src_ip(), ip.dst_ip())?,
+        }
+        match (&self.transport, &self.application) {
+            (&Some(TransportLayerPacket::Udp(_)), &Some(ApplicationLayer::Dns(ref msg))) =>
+                write!(f, "UDP, DNS {}", msg),
+            (&Some(TransportLayerPacket::Tcp(ref tcp)), &Some(ApplicationLayer::Dns(ref msg))) =>
+                write!(f, "{}, DNS {}", tcp, msg),
+            (&Some(TransportLayerPacket::Udp(_)), &Some(ApplicationLayer::Dhcp(ref msg))) =>
+                write!(f, "UDP, {}", msg),
+            (&Some(TransportLayerPacket::Tcp(ref tcp)), &Some(ApplicationLayer::HttpRequest(ref request))) =>
+                write!(f, "{}, {}", tcp, request),
+            (&Some(TransportLayerPacket::Tcp(ref tcp)), &Some(ApplicationLayer::HttpResponse(ref response))) =>
+                write!(f, "{}, {}", tcp, response),
+            (&Some(TransportLayerPacket::Tcp(ref tcp)), _) => write!(f, "{}", tcp),
+            (&Some(TransportLayerPacket::Udp(ref udp)), _) => write!(f, "{}", udp),
+            (&Some(TransportLayerPacket::Icmp(ref icmp)), _) => write!(f, "{}", icmp),
+            (&None, _) => match ip.protocol() {
+                Some(proto) => write!(f, "{}, length {}", format!("{:?}", proto).to_uppercase(), self.payload.len()),
+                None => write!(f, "length {}", self.payload.len()),
+            },
+        }
+    }
+}
+
+/// Anything a user dissector produces
+pub trait CustomLayer: Any + fmt::Debug {
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl <T: Any + fmt::Debug> CustomLayer for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+type CustomDissector = Box<dyn Fn(&[u8]) -> Option<Rc<dyn CustomLayer>>>;
+
+fn boxed<F, T>(f: F) -> CustomDissector
+    where F: Fn(&[u8]) -> Option<T> + 'static, T: CustomLayer {
+    Box::new(move |bs| f(bs).map(|layer| Rc::new(layer) as Rc<dyn CustomLayer>))
+}
+
+/// User dissectors for protocols blosh doesn't know about, keyed by where
+/// they show up. They're only consulted for payloads the built in parsers
+/// didn't handle. Also holds settings for the built in parsers.
+#[derive(Default)]
+pub struct Registry {
+    ethertypes: HashMap<u16, CustomDissector>,
+    ip_protocols: HashMap<u8, CustomDissector>,
+    udp_ports: HashMap<u16, CustomDissector>,
+    tcp_ports: HashMap<u16, CustomDissector>,
+    dns_mode: dns::Mode,
+    fcs_mode: FcsMode,
+    port_map: PortMap,
+    enricher: Option<Box<dyn Enricher>>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    /// Dissector for the body of Ethernet frames with this EtherType
+    pub fn register_ethertype<F, T>(&mut self, ethertype: u16, f: F)
+        where F: Fn(&[u8]) -> Option<T> + 'static, T: CustomLayer {
+        self.ethertypes.insert(ethertype, boxed(f));
+    }
+
+    /// Dissector for the payload of IP packets with this protocol number
+    pub fn register_ip_protocol<F, T>(&mut self, proto: u8, f: F)
+        where F: Fn(&[u8]) -> Option<T> + 'static, T: CustomLayer {
+        self.ip_protocols.insert(proto, boxed(f));
+    }
+
+    /// Dissector for UDP payloads to or from this port
+    pub fn register_udp_port<F, T>(&mut self, port: u16, f: F)
+        where F: Fn(&[u8]) -> Option<T> + 'static, T: CustomLayer {
+        self.udp_ports.insert(port, boxed(f));
+    }
+
+    /// Dissector for TCP payloads to or from this port
+    pub fn register_tcp_port<F, T>(&mut self, port: u16, f: F)
+        where F: Fn(&[u8]) -> Option<T> + 'static, T: CustomLayer {
+        self.tcp_ports.insert(port, boxed(f));
+    }
+
+    /// How strictly DNS messages are parsed, `dns::Mode::Strict` by default
+    pub fn set_dns_mode(&mut self, mode: dns::Mode) {
+        self.dns_mode = mode;
+    }
+
+    /// Which built in parser runs on which ports, `PortMap::default()` by
+    /// default
+    pub fn set_port_map(&mut self, port_map: PortMap) {
+        self.port_map = port_map;
+    }
+
+    pub fn port_map_mut(&mut self) -> &mut PortMap {
+        &mut self.port_map
+    }
+
+    /// Whether Ethernet frames end with an FCS, `FcsMode::Absent` by default
+    pub fn set_fcs_mode(&mut self, mode: FcsMode) {
+        self.fcs_mode = mode;
+    }
+
+    /// Lookups to attach to every packet dissected, none by default
+    pub fn set_enricher<E: Enricher + 'static>(&mut self, enricher: E) {
+        self.enricher = Some(Box::new(enricher));
+    }
+
+    fn by_ports(ports: &HashMap<u16, CustomDissector>, src: u16, dst: u16, bs: &[u8]) -> Option<Rc<dyn CustomLayer>> {
+        // the destination port is more likely to be the well known one
+        ports.get(&dst).or_else(|| ports.get(&src)).and_then(|f| f(bs))
+    }
+}
+
+/// The application protocols the built in parsers handle
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AppProtocol {
+    Dns,
+    /// DNS parsed leniently, as mDNS sets the top bit of record classes
+    /// for cache flushes and of question classes for unicast responses
+    Mdns,
+    Dhcp,
+    Http,
+}
+
+/// Which application parser runs on the payload of a TCP or UDP packet,
+/// by port. Out of the box that's DNS on TCP and UDP port 53 and mDNS on
+/// UDP 5353, DHCP on UDP 67 and 68, and HTTP on TCP 80; `set_tcp` and
+/// `set_udp` add ports for nonstandard deployments, or turn the defaults
+/// off. The destination port is tried before the source port.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PortMap {
+    // overrides of the defaults, None meaning no parser
+    tcp: HashMap<u16, Option<AppProtocol>>,
+    udp: HashMap<u16, Option<AppProtocol>>,
+}
+
+impl PortMap {
+    pub fn new() -> PortMap {
+        PortMap::default()
+    }
+
+    /// Runs `protocol`'s parser on TCP payloads to or from `port`, or none
+    /// at all if it's `None`
+    pub fn set_tcp(&mut self, port: u16, protocol: Option<AppProtocol>) {
+        self.tcp.insert(port, protocol);
+    }
+
+    /// Runs `protocol`'s parser on UDP payloads to or from `port`, or none
+    /// at all if it's `None`
+    pub fn set_udp(&mut self, port: u16, protocol: Option<AppProtocol>) {
+        self.udp.insert(port, protocol);
+    }
+
+    pub fn tcp(&self, port: u16) -> Option<AppProtocol> {
+        match self.tcp.get(&port) {
+            Some(&protocol) => protocol,
+            None => match port {
+                DNS_PORT => Some(AppProtocol::Dns),
+                HTTP_PORT => Some(AppProtocol::Http),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn udp(&self, port: u16) -> Option<AppProtocol> {
+        match self.udp.get(&port) {
+            Some(&protocol) => protocol,
+            None => match port {
+                DNS_PORT => Some(AppProtocol::Dns),
+                MDNS_PORT => Some(AppProtocol::Mdns),
+                dhcp::SERVER_PORT | dhcp::CLIENT_PORT => Some(AppProtocol::Dhcp),
+                _ => None,
+            },
+        }
+    }
+}
+
+const DNS_PORT: u16 = 53;
+const MDNS_PORT: u16 = 5353;
+const HTTP_PORT: u16 = 80;
+
+/// Dissects a captured packet layer by layer: Ethernet (with any VLAN tags),
+/// IPv4 or IPv6, TCP, UDP or ICMP, and DNS, DHCP and HTTP on their well
+/// known ports.
+pub fn parse_packet<'a>(link_type: LinkType, bs: &'a [u8]) -> Packet<'a> {
+    dissect(link_type, bs, None)
+}
+
+/// Like `parse_packet`, but hands payloads the built in parsers don't
+/// understand to the user dissectors in `registry`
+pub fn parse_packet_with<'a>(registry: &Registry, link_type: LinkType, bs: &'a [u8]) -> Packet<'a> {
+    dissect(link_type, bs, Some(registry))
+}
+
+fn dissect<'a>(link_type: LinkType, bs: &'a [u8], registry: Option<&Registry>) -> Packet<'a> {
+    let mut packet = Packet {
+        payload: bs,
+        ..Packet::default()
+    };
+
+    let ip_bytes = match link_type {
+        LinkType::Ethernet => match ethernet::parse_eth2_packet(strip_fcs(&mut packet, registry, bs)) {
+            Ok((_, eth)) => {
+                let ethertype = eth.ethertype;
+                let body = eth.body;
+                packet.payload = body;
+                packet.ethernet = Some(eth);
+                match ethertype {
+                    EtherType::Ipv4 | EtherType::Ipv6 => Some(body),
+                    _ => {
+                        packet.custom = registry
+                            .and_then(|r| r.ethertypes.get(&ethertype.to_u16()))
+                            .and_then(|f| f(body));
+                        None
+                    },
+                }
+            },
+            _ => None,
+        },
+        LinkType::Raw | LinkType::Ipv4 | LinkType::Ipv6 => Some(bs),
+        LinkType::Other(_) => None,
+    };
+
+    packet.ip = ip_bytes.and_then(parse_ip);
+    dissect_transport(&mut packet, registry);
+
+    if let Some(enricher) = registry.and_then(|r| r.enricher.as_ref()) {
+        enrich(&mut packet, &**enricher);
+    }
+    packet
+}
+
+/// Dissects what's inside an IP packet that's already been parsed, for
+/// when something else took care of the link layer
+pub fn parse_ip_layers(ip: IpPacket<'_>) -> Packet<'_> {
+    let mut packet = Packet {
+        payload: ip.body(),
+        ip: Some(ip),
+        ..Packet::default()
+    };
+    dissect_transport(&mut packet, None);
+    packet
+}
+
+fn dissect_transport<'a>(packet: &mut Packet<'a>, registry: Option<&Registry>) {
+    let transport = match packet.ip {
+        Some(ref ip) => {
+            packet.payload = ip.body();
+            let transport = ip.parse_inner();
+            if transport.is_none() {
+                packet.custom = match (registry, ip.protocol()) {
+                    (Some(r), Some(proto)) => r.ip_protocols.get(&proto.to_u8()).and_then(|f| f(ip.body())),
+                    _ => None,
+                };
+            }
+            transport
+        },
+        None => None,
+    };
+    if let Some(ref transport) = transport {
+        packet.payload = transport_body(transport);
+        let dns_mode = registry.map(|r| r.dns_mode).unwrap_or_default();
+        let default_ports = PortMap::default();
+        let port_map = registry.map_or(&default_ports, |r| &r.port_map);
+        if let Some((application, payload)) = parse_application(transport, port_map, dns_mode) {
+            packet.application = Some(application);
+            packet.payload = payload;
+        }
+        if packet.application.is_none() {
+            packet.custom = registry.and_then(|r| match transport {
+                TransportLayerPacket::Udp(udp) =>
+                    Registry::by_ports(&r.udp_ports, udp.header.src, udp.header.dst, udp.body),
+                TransportLayerPacket::Tcp(tcp) =>
+                    Registry::by_ports(&r.tcp_ports, tcp.header.src, tcp.header.dst, tcp.body),
+                &TransportLayerPacket::Icmp(_) => None,
+            });
+        }
+    }
+    packet.transport = transport;
+}
+
+fn enrich(packet: &mut Packet, enricher: &dyn Enricher) {
+    let mut subjects = vec![];
+    if let Some(ref ip) = packet.ip {
+        subjects.push(Subject::Addr(ip.src_ip()));
+        subjects.push(Subject::Addr(ip.dst_ip()));
+    }
+    if let Some(ApplicationLayer::Dns(ref msg)) = packet.application {
+        subjects.extend(msg.questions.iter().map(|q| Subject::Name(q.qname.to_string())));
+    }
+    for subject in subjects {
+        if packet.enrichment.iter().any(|e| e.0 == subject) {
+            continue;
+        }
+        let found = match subject {
+            Subject::Addr(addr) => enricher.lookup_addr(addr),
+            Subject::Name(ref name) => enricher.lookup_name(name),
+        };
+        if let Some(found) = found {
+            packet.enrichment.push((subject, found));
+        }
+    }
+}
+
+// takes the FCS off the end of an Ethernet frame, if the registry says to
+fn strip_fcs<'a>(packet: &mut Packet<'a>, registry: Option<&Registry>, bs: &'a [u8]) -> &'a [u8] {
+    let mode = registry.map(|r| r.fcs_mode).unwrap_or_default();
+    match (mode, ethernet::split_fcs(bs)) {
+        (FcsMode::Present, Some((frame, fcs))) => {
+            packet.fcs = Some(fcs);
+            frame
+        },
+        (FcsMode::Detect, Some((frame, fcs))) if fcs.valid => {
+            packet.fcs = Some(fcs);
+            frame
+        },
+        _ => bs,
+    }
+}
+
+fn parse_ip<'a>(bs: &'a [u8]) -> Option<IpPacket<'a>> {
+    match bs.first().map(|b| b >> 4) {
+        Some(4) => all_consuming(ipv4::parse_ipv4_packet)(bs).ok().map(|(_, ip)| IpPacket::V4(ip)),
+        Some(6) => all_consuming(ipv6::parse_ipv6_packet)(bs).ok().map(|(_, ip)| IpPacket::V6(ip)),
+        _ => None,
+    }
+}
+
+fn transport_body<'a>(transport: &TransportLayerPacket<'a>) -> &'a [u8] {
+    match transport {
+        TransportLayerPacket::Tcp(tcp) => tcp.body,
+        TransportLayerPacket::Udp(udp) => udp.body,
+        TransportLayerPacket::Icmp(icmp) => icmp.body,
+    }
+}
+
+// the application layer, and the payload left after it
+fn parse_application<'a>(transport: &TransportLayerPacket<'a>, port_map: &PortMap, dns_mode: dns::Mode)
+    -> Option<(ApplicationLayer<'a>, &'a [u8])>
+{
+    let (ports, body) = match transport {
+        TransportLayerPacket::Udp(udp) => ([port_map.udp(udp.header.dst), port_map.udp(udp.header.src)], udp.body),
+        TransportLayerPacket::Tcp(tcp) => ([port_map.tcp(tcp.header.dst), port_map.tcp(tcp.header.src)], tcp.body),
+        &TransportLayerPacket::Icmp(_) => return None,
+    };
+    let is_tcp = matches!(transport, TransportLayerPacket::Tcp(_));
+    let parse = |protocol| match protocol {
+        AppProtocol::Dns | AppProtocol::Mdns => {
+            let dns_mode = if protocol == AppProtocol::Mdns { dns::Mode::Lenient } else { dns_mode };
+            // DNS over TCP carries a two byte length prefix. Segments
+            // holding anything but exactly one message, like pipelined
+            // queries, are left to dns::TcpDecoder on the reassembled stream
+            let msg = if !is_tcp {
+                body
+            } else if body.len() > 2 && usize::from(u16::from_be_bytes([body[0], body[1]])) == body.len() - 2 {
+                &body[2..]
+            } else {
+                return None;
+            };
+            let parse_dns = move |bs| dns::parse_dns_message_full_with(dns_mode, bs);
+            all_consuming(parse_dns)(msg).ok().map(|(rest, msg)| (ApplicationLayer::Dns(msg), rest))
+        },
+        AppProtocol::Dhcp if !is_tcp => {
+            all_consuming(dhcp::parse_dhcp_message)(body).ok().map(|(rest, msg)| (ApplicationLayer::Dhcp(msg), rest))
+        },
+        // only segments that start with a whole head, which leaves out the
+        // ones in the middle of a body
+        AppProtocol::Http if is_tcp => match http::parse_request(body) {
+            Ok((rest, request)) => Some((ApplicationLayer::HttpRequest(request), rest)),
+            Err(_) => http::parse_response(body).ok().map(|(rest, response)| (ApplicationLayer::HttpResponse(response), rest)),
+        },
+        _ => None,
+    };
+    match ports {
+        [Some(dst), Some(src)] if dst != src => parse(dst).or_else(|| parse(src)),
+        [Some(protocol), _] | [None, Some(protocol)] => parse(protocol),
+        [None, None] => None,
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OwnedApplicationLayer {
+    Dns(dns::OwnedMessage),
+    Dhcp(dhcp::OwnedDhcpMessage),
+    HttpRequest(http::OwnedRequest),
+    HttpResponse(http::OwnedResponse),
+}
+
+/// A `Packet` that owns its data, for keeping around after the capture
+/// buffer is gone
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedPacket {
+    pub timestamp: Option<Timestamp>,
+    pub ethernet: Option<ethernet::OwnedEthernetIIPacket>,
+    pub fcs: Option<ethernet::Fcs>,
+    pub ip: Option<OwnedIpPacket>,
+    pub transport: Option<OwnedTransportLayerPacket>,
+    pub application: Option<OwnedApplicationLayer>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub custom: Option<Rc<dyn CustomLayer>>,
+    pub payload: Vec<u8>,
+    pub enrichment: Vec<(Subject, Enrichment)>,
+}
+
+impl OwnedPacket {
+    /// The user dissector's output, if there is one of type `T`
+    pub fn custom_layer<T: Any>(&self) -> Option<&T> {
+        self.custom.as_ref().and_then(|layer| (**layer).as_any().downcast_ref::<T>())
+    }
+}
+
+impl <'a> IntoOwned for ApplicationLayer<'a> {
+    type Owned = OwnedApplicationLayer;
+
+    fn into_owned(self) -> OwnedApplicationLayer {
+        match self {
+            ApplicationLayer::Dns(msg) => OwnedApplicationLayer::Dns(msg.into_owned()),
+            ApplicationLayer::Dhcp(msg) => OwnedApplicationLayer::Dhcp(msg.into_owned()),
+            ApplicationLayer::HttpRequest(request) => OwnedApplicationLayer::HttpRequest(request.into_owned()),
+            ApplicationLayer::HttpResponse(response) => OwnedApplicationLayer::HttpResponse(response.into_owned()),
+        }
+    }
+}
+
+impl <'a> IntoOwned for Packet<'a> {
+    type Owned = OwnedPacket;
+
+    fn into_owned(self) -> OwnedPacket {
+        OwnedPacket {
+            timestamp: self.timestamp,
+            ethernet: self.ethernet.into_owned(),
+            fcs: self.fcs,
+            ip: self.ip.into_owned(),
+            transport: self.transport.into_owned(),
+            application: self.application.into_owned(),
+            custom: self.custom,
+            payload: self.payload.into_owned(),
+            enrichment: self.enrichment,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vlan_udp_dns() {
+        let frame = [
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0x81, 0x00, 0x20, 0x64,
+            0x08, 0x00,
+
+            0x45, 0x00, 0x00, 0x3c, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x01,
+            0x08, 0x08, 0x08, 0x08,
+
+            0x10, 0xd8, 0x00, 0x35, 0x00, 0x28, 0x00, 0x00,
+
+            0x24, 0x1a, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x77, 0x77, 0x77,
+            0x06, 0x67, 0x6f, 0x6f, 0x67, 0x6c, 0x65, 0x03, 0x63, 0x6f, 0x6d, 0x00, 0x00, 0x01, 0x00, 0x01,
+        ];
+        let packet = parse_packet(LinkType::Ethernet, &frame);
+        let eth = packet.ethernet.unwrap();
+        assert_eq!(eth.vlans.len(), 1);
+        assert_eq!(eth.vlans[0].vid, 100);
+        assert_eq!(eth.vlans[0].pcp, 1);
+        assert_eq!(eth.ethertype, EtherType::Ipv4);
+        match packet.ip {
+            Some(IpPacket::V4(ref ip4)) => assert_eq!(ip4.header.ttl, 64),
+            ref x => panic!("expected an IPv4 packet, got {:?}", x),
+        }
+        match packet.transport {
+            Some(TransportLayerPacket::Udp(ref udp)) => assert_eq!(udp.header.dst, 53),
+            ref x => panic!("expected a UDP packet, got {:?}", x),
+        }
+        match packet.application {
+            Some(ApplicationLayer::Dns(ref msg)) => assert_eq!(msg.header.id, 0x241a),
+            ref x => panic!("expected a DNS message, got {:?}", x),
+        }
+        assert!(packet.payload.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_ethertype() {
+        let frame = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0x88, 0xcc, 0x01, 0x02,
+        ];
+        let packet = parse_packet(LinkType::Ethernet, &frame);
+        assert_eq!(packet.ethernet.unwrap().ethertype, EtherType::Lldp);
+        assert!(packet.ip.is_none());
+        assert_eq!(packet.payload, &[0x01, 0x02]);
+    }
+
+    #[test]
+    fn test_mdns() {
+        use mdns::{Service, IN_FLUSH, MDNS_PORT};
+        use testgen::{udp_frame, Endpoint};
+
+        let mut printer = Service::new("Office Printer", "_ipp._tcp.local.", "printer.local", 631);
+        printer.add_addr(IpAddr::from([192, 168, 1, 20]));
+        let frame = udp_frame(&Endpoint::server(), &Endpoint::client(), MDNS_PORT, MDNS_PORT,
+                              &printer.announcement().to_bytes());
+        let msg = match parse_packet(LinkType::Ethernet, &frame).application {
+            Some(ApplicationLayer::Dns(msg)) => msg,
+            other => panic!("unexpected application layer {:?}", other),
+        };
+        assert_eq!(msg.answers[1].class, IN_FLUSH);
+
+        // a strict registry still takes mDNS leniently
+        let mut registry = Registry::new();
+        registry.set_dns_mode(dns::Mode::Strict);
+        assert!(parse_packet_with(&registry, LinkType::Ethernet, &frame).dns_mut().is_some());
+    }
+
+    #[test]
+    fn test_tcp_dns() {
+        use tcp::TcpHeader;
+        use testgen::{tcp_frame, Endpoint};
+
+        let query = [
+            0x24, 0x1a, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x77, 0x77, 0x77,
+            0x06, 0x67, 0x6f, 0x6f, 0x67, 0x6c, 0x65, 0x03, 0x63, 0x6f, 0x6d, 0x00, 0x00, 0x01, 0x00, 0x01,
+        ];
+        let segment = |body: &[u8]| tcp_frame(&Endpoint::client(), &Endpoint::server(), TcpHeader::new(40000, 53), body);
+        let mut body = vec![0x00, 0x20];
+        body.extend_from_slice(&query);
+        let frame = segment(&body);
+        let mut packet = parse_packet(LinkType::Ethernet, &frame);
+        assert_eq!(packet.dns_mut().map(|msg| msg.header.id), Some(0x241a));
+        assert_eq!(packet.to_bytes_with(Fixup::none()), frame);
+
+        // a prefix that doesn't match what follows
+        body[1] = 0x1f;
+        assert!(parse_packet(LinkType::Ethernet, &segment(&body)).application.is_none());
+
+        // two pipelined queries
+        body[1] = 0x20;
+        let pipelined = [&body[..], &body[..]].concat();
+        let frame = segment(&pipelined);
+        let packet = parse_packet(LinkType::Ethernet, &frame);
+        assert!(packet.application.is_none());
+        assert_eq!(packet.payload, &pipelined[..]);
+    }
+
+    #[test]
+    fn test_bad_ipv4_lengths() {
+        // an IHL of 2 and a total length of 0
+        let raw = [
+            0x42, 0x7c, 0x00, 0x00, 0x52, 0x10, 0x00, 0x59, 0x00, 0x00, 0x5b, 0x01, 0x5d, 0x2b, 0x00, 0x00,
+            0x07, 0x00, 0x04, 0x02,
+        ];
+        assert!(ipv4::parse_ipv4_packet(&raw).is_err());
+        let packet = parse_packet(LinkType::Raw, &raw);
+        assert!(packet.ip.is_none());
+        assert_eq!(packet.payload, &raw[..]);
+
+        // a total length shorter than a 24 byte header
+        let mut raw = raw;
+        raw[0] = 0x46;
+        raw[3] = 20;
+        assert!(ipv4::parse_ipv4_packet(&raw).is_err());
+        assert!(parse_packet(LinkType::Raw, &raw).ip.is_none());
+    }
+
+    #[test]
+    fn test_display() {
+        let frame = [
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0x81, 0x00, 0x20, 0x64,
+            0x08, 0x00,
+
+            0x45, 0x00, 0x00, 0x3c, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x01,
+            0x08, 0x08, 0x08, 0x08,
+
+            0x10, 0xd8, 0x00, 0x35, 0x00, 0x28, 0x00, 0x00,
+
+            0x24, 0x1a, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x77, 0x77, 0x77,
+            0x06, 0x67, 0x6f, 0x6f, 0x67, 0x6c, 0x65, 0x03, 0x63, 0x6f, 0x6d, 0x00, 0x00, 0x01, 0x00, 0x01,
+        ];
+        let packet = parse_packet(LinkType::Ethernet, &frame);
+        assert_eq!(packet.to_string(), "IP 10.0.0.1.4312 > 8.8.8.8.53: UDP, DNS query A www.google.com");
+        assert_eq!(packet.ethernet.unwrap().to_string(),
+            "66:77:88:99:aa:bb > 00:11:22:33:44:55, vlan 100, p 1, ethertype IPv4 (0x0800), length 60");
+        assert_eq!(packet.ip.unwrap().to_string(), "IP 10.0.0.1.4312 > 8.8.8.8.53: UDP, length 32");
+
+        // a protocol with no parser is named, without ports
+        let mut ip = frame[18..].to_vec();
+        ip[9] = 2;
+        let ip = ::parse_ip_packet(&ip).unwrap();
+        assert_eq!(ip.to_string(), "IP 10.0.0.1 > 8.8.8.8: IGMP, length 40");
+    }
+
+    #[test]
+    fn test_into_owned() {
+        let owned = {
+            let frame = vec![
+                0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0x08, 0x00,
+
+                0x45, 0x00, 0x00, 0x1e, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x01,
+                0x08, 0x08, 0x08, 0x08,
+
+                0x10, 0xd8, 0x1f, 0x90, 0x00, 0x0a, 0x00, 0x00,
+
+                0xca, 0xfe,
+            ];
+            parse_packet(LinkType::Ethernet, &frame).into_owned()
+        };
+        match owned.transport {
+            Some(OwnedTransportLayerPacket::Udp(ref udp)) => {
+                assert_eq!(udp.header.dst, 8080);
+                assert_eq!(udp.body, vec![0xca, 0xfe]);
+            },
+            _ => panic!("expected a UDP packet"),
+        }
+        assert_eq!(owned.payload, vec![0xca, 0xfe]);
+        assert_eq!(owned.ethernet.unwrap().ethertype, EtherType::Ipv4);
+        assert!(matches!(owned.ip, Some(OwnedIpPacket::V4(_))));
+    }
+
+    #[test]
+    fn test_fcs() {
+        let mut frame = vec![
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0x08, 0x00,
+
+            0x45, 0x00, 0x00, 0x1e, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x01,
+            0x08, 0x08, 0x08, 0x08,
+
+            0x10, 0xd8, 0x1f, 0x90, 0x00, 0x0a, 0x00, 0x00,
+
+            0xca, 0xfe,
+        ];
+        let crc = ::checksum::crc32(&frame);
+        frame.extend_from_slice(&crc.to_le_bytes());
+
+        let mut registry = Registry::new();
+        registry.set_fcs_mode(FcsMode::Detect);
+        {
+            let packet = parse_packet_with(&registry, LinkType::Ethernet, &frame);
+            assert_eq!(packet.fcs, Some(ethernet::Fcs { value: crc, valid: true }));
+            assert_eq!(packet.payload, &[0xca, 0xfe]);
+            assert!(parse_packet(LinkType::Ethernet, &frame).fcs.is_none());
+        }
+
+        frame[14 + 20 + 8] ^= 0xff;
+        let packet = parse_packet_with(&registry, LinkType::Ethernet, &frame);
+        assert!(packet.fcs.is_none());
+        registry.set_fcs_mode(FcsMode::from_fcs_len(Some(4)));
+        let packet = parse_packet_with(&registry, LinkType::Ethernet, &frame);
+        assert_eq!(packet.fcs.map(|fcs| fcs.valid), Some(false));
+        assert_eq!(packet.payload, &[0x35, 0xfe]);
+    }
+
+    #[test]
+    fn test_edit() {
+        let frame = [
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0x08, 0x00,
+
+            0x45, 0x00, 0x00, 0x3c, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x01,
+            0x08, 0x08, 0x08, 0x08,
+
+            0x10, 0xd8, 0x00, 0x35, 0x00, 0x28, 0x00, 0x00,
+
+            0x24, 0x1a, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x77, 0x77, 0x77,
+            0x06, 0x67, 0x6f, 0x6f, 0x67, 0x6c, 0x65, 0x03, 0x63, 0x6f, 0x6d, 0x00, 0x00, 0x01, 0x00, 0x01,
+        ];
+        let answer_ip = [1, 2, 3, 4];
+        let mut packet = parse_packet(LinkType::Ethernet, &frame);
+        // untouched, and with nothing fixed up, it comes back byte for byte
+        let rewritten = packet.to_bytes_with(Fixup::none());
+        assert_eq!(&rewritten[..], &frame[..]);
+
+        packet.set_ttl(10);
+        assert!(packet.set_dst_ip("192.168.0.53".parse().unwrap()));
+        assert!(!packet.set_src_ip("::1".parse().unwrap()));
+        assert!(packet.set_ports(4000, 53));
+        {
+            let msg = packet.dns_mut().unwrap();
+            msg.header.qr = dns::QR::Response;
+            let name = msg.questions[0].qname.clone();
+            msg.answers.push(dns::ResourceRecord {
+                name,
+                typ: dns::Type::A,
+                class: dns::Class::IN,
+                ttl: 300,
+                rdata: dns::Rdata::A(&answer_ip),
+            });
+        }
+        let rewritten = packet.to_bytes();
+        let reparsed = parse_packet(LinkType::Ethernet, &rewritten);
+        assert_eq!(reparsed.to_string(), "IP 10.0.0.1.4000 > 192.168.0.53.53: UDP, DNS response A www.google.com: A 1.2.3.4");
+        match reparsed.ip {
+            Some(IpPacket::V4(ref ip4)) => {
+                assert_eq!(ip4.header.ttl, 10);
+                assert_eq!(ip4.header.total_len as usize, rewritten.len() - 14);
+                assert_eq!(::checksum::internet_checksum(&rewritten[14..34]), 0);
+            },
+            ref x => panic!("expected an IPv4 packet, got {:?}", x),
+        }
+        let ip = reparsed.ip.as_ref().unwrap();
+        assert!(::udp::verify_checksum(&ip.header(), ip.body()));
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Lldpish(u8, u8);
+
+    #[test]
+    fn test_registry() {
+        let frame = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0x88, 0xcc, 0x01, 0x02,
+        ];
+        let mut registry = Registry::new();
+        registry.register_ethertype(0x88cc, |bs: &[u8]| {
+            if bs.len() >= 2 {
+                Some(Lldpish(bs[0], bs[1]))
+            } else {
+                None
+            }
+        });
+        let packet = parse_packet_with(&registry, LinkType::Ethernet, &frame);
+        assert_eq!(packet.custom_layer::<Lldpish>(), Some(&Lldpish(1, 2)));
+        assert_eq!(packet.custom_layer::<u32>(), None);
+        assert!(parse_packet(LinkType::Ethernet, &frame).custom.is_none());
+    }
+
+    #[test]
+    fn test_port_map() {
+        use tcp::TcpHeader;
+        use testgen::{tcp_frame, udp_frame, Endpoint};
+        let (client, server) = (Endpoint::client(), Endpoint::server());
+
+        // HTTP on port 80, with the start of the body left as the payload
+        let request = b"POST /api HTTP/1.1\r\nHost: example.com\r\nContent-Length: 10\r\n\r\n{\"a\": 1}";
+        let frame = tcp_frame(&client, &server, TcpHeader::new(40000, 80), request);
+        let packet = parse_packet(LinkType::Ethernet, &frame);
+        match packet.application {
+            Some(ApplicationLayer::HttpRequest(ref request)) => assert_eq!(request.header(b"host"), Some(&b"example.com"[..])),
+            ref x => panic!("expected an HTTP request, got {:?}", x),
+        }
+        assert_eq!(packet.payload, b"{\"a\": 1}");
+        assert!(packet.to_string().ends_with(", POST /api HTTP/1.1"));
+        assert_eq!(packet.to_bytes(), frame);
+
+        // nothing on port 8080 until it's mapped
+        let frame = tcp_frame(&server, &client, TcpHeader::new(8080, 40000), b"HTTP/1.1 204 No Content\r\n\r\n");
+        assert!(parse_packet(LinkType::Ethernet, &frame).application.is_none());
+        let mut registry = Registry::new();
+        registry.port_map_mut().set_tcp(8080, Some(AppProtocol::Http));
+        match parse_packet_with(&registry, LinkType::Ethernet, &frame).application {
+            Some(ApplicationLayer::HttpResponse(ref response)) => assert_eq!(response.status, 204),
+            ref x => panic!("expected an HTTP response, got {:?}", x),
+        }
+
+        // DHCP on 68 and 67, unless that's turned off
+        let mut discover = vec![0x01, 0x01, 0x06, 0x00, 0xde, 0xad, 0xbe, 0xef, 0x00, 0x00, 0x80, 0x00];
+        discover.resize(236, 0);
+        discover.extend_from_slice(&dhcp::MAGIC_COOKIE);
+        discover.extend_from_slice(&[0x35, 0x01, 0x01, 0xff]);
+        let frame = udp_frame(&client, &server, 68, 67, &discover);
+        let packet = parse_packet(LinkType::Ethernet, &frame);
+        match packet.application {
+            Some(ApplicationLayer::Dhcp(ref msg)) => assert_eq!(msg.message_type(), Some(dhcp::MessageType::Discover)),
+            ref x => panic!("expected DHCP, got {:?}", x),
+        }
+        assert!(packet.to_string().ends_with("UDP, DHCP Discover, xid 0xdeadbeef"));
+        let mut ports = PortMap::new();
+        ports.set_udp(67, None);
+        ports.set_udp(68, None);
+        registry.set_port_map(ports);
+        assert!(parse_packet_with(&registry, LinkType::Ethernet, &frame).application.is_none());
+    }
+}
@@ -1,6 +1,22 @@
-use nom::{be_u8, be_u16, be_u32, rest, IResult};
+use std::convert::TryFrom;
+use std::fmt;
+
+use nom::bits::bits;
+use nom::bits::complete::take as take_bits;
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take};
+use nom::combinator::{all_consuming, map, map_opt, rest, verify};
+use nom::error::{context, ErrorKind};
+use nom::number::complete::{be_u8, be_u16, be_u32};
+#[cfg(feature = "arbitrary")]
+use arbitrary::{Arbitrary, Unstructured};
+
+use error::{IResult, ParseError};
+use stream::Decode;
+use {list_from_vec, list_into_vec, many_till_list, Fixup, IntoOwned, List, ToBytes};
 
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct Message<'a> {
     pub header: Header,
     pub questions: Vec<Query<'a>>,
@@ -9,167 +25,692 @@ pub struct Message<'a> {
     pub additional: Vec<ResourceRecord<'a>>,
 }
 
-named!(pub parse_dns_message<Message>,
-    do_parse!(
-        header: parse_dns_header >>
-        questions: count!(query, header.qdcount as usize) >>
-        answers: count!(resource_record, header.ancount as usize) >>
-        authorities: count!(resource_record, header.nscount as usize) >>
-        additional: count!(resource_record, header.arcount as usize) >>
-        (Message {
-            header: header,
-            questions: questions,
-            answers: answers,
-            authorities: authorities,
-            additional: additional,
+// counts that match the sections
+#[cfg(feature = "arbitrary")]
+impl <'a> Arbitrary<'a> for Message<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Message<'a>> {
+        let mut header = Header::arbitrary(u)?;
+        let questions: Vec<Query> = u.arbitrary()?;
+        let answers: Vec<ResourceRecord> = u.arbitrary()?;
+        let authorities: Vec<ResourceRecord> = u.arbitrary()?;
+        let additional: Vec<ResourceRecord> = u.arbitrary()?;
+        header.qdcount = questions.len() as u16;
+        header.ancount = answers.len() as u16;
+        header.nscount = authorities.len() as u16;
+        header.arcount = additional.len() as u16;
+        Ok(Message {
+            header,
+            questions,
+            answers,
+            authorities,
+            additional,
         })
-    )
-);
+    }
+}
 
-/// Convert domain name pointers to byte slices
-pub fn parse_dns_message_full<'a>(bytestr: &'a [u8]) -> IResult<&'a [u8], Message<'a>, u32> {
-    use std::collections::HashMap;
-
-    fn deref_helper<'a>(domain: &DomainName<'a>, dict: &mut HashMap<u16, DomainName<'a>>, bytestr: &'a [u8]) -> Option<DomainName<'a>> {
-        match domain {
-            &DomainName::Pointer(ref off) => {
-                if dict.contains_key(off) {
-                    Some(dict[off].clone())
-                } else {
-                    let new_domain_ref = domain_name(&bytestr[*off as usize..]);
-                    match new_domain_ref {
-                        IResult::Done(_, domain) => {
-                            dict.insert(*off, domain.clone());
-                            Some(domain)
-                        },
-                        _ => None,
-                    }
-                }
+impl <'a> Message<'a> {
+    /// A recursive query for one name, like a stub resolver sends.
+    /// `name` is split on dots, and a trailing dot is fine.
+    pub fn query(id: u16, name: &'a str, qtype: Qtype) -> Message<'a> {
+        let labels = name.split('.').filter(|label| !label.is_empty()).map(str::as_bytes).collect();
+        Message {
+            header: Header {
+                id,
+                qr: QR::Query,
+                opcode: Opcode::Query,
+                aa: false,
+                tc: false,
+                rd: true,
+                ra: false,
+                rcode: Rcode::NoError,
+                qdcount: 1,
+                ancount: 0,
+                nscount: 0,
+                arcount: 0,
             },
-            &DomainName::LabelWithPointer(ref list, ref off) => {
-                let mut list = list.clone();
-                let to_add = if dict.contains_key(off) {
-                    dict[off].clone()
-                } else {
-                    let new_domain_ref = domain_name(&bytestr[*off as usize..]);
-                    match new_domain_ref {
-                        IResult::Done(_, domain_name) => {
-                            dict.insert(*off, domain_name.clone());
-                            domain_name
-                        },
-                        _  => {
-                            return None;
-                        },
-                    }
-                };
-                if let DomainName::Labels(ref to_add) = to_add {
-                    list.extend(to_add);
-                    Some(DomainName::Labels(list))
-                } else {
-                    None
-                }
+            questions: vec![Query {
+                qname: DomainName::Labels(labels),
+                qtype,
+                qclass: Qclass::Class(Class::IN),
+            }],
+            answers: vec![],
+            authorities: vec![],
+            additional: vec![],
+        }
+    }
+
+    /// A CH TXT query for something a server says about itself, like
+    /// `VERSION_BIND` or `ID_SERVER`. They're about the server that gets
+    /// them, so recursion isn't asked for.
+    pub fn chaos_query(id: u16, name: &'a str) -> Message<'a> {
+        let mut msg = Message::query(id, name, Qtype::Type(Type::Txt));
+        msg.header.rd = false;
+        msg.questions[0].qclass = Qclass::Class(Class::CH);
+        msg
+    }
+
+    /// The text a server answered a `chaos_query` with, its strings run
+    /// together. Only CH TXT answers for the name that was asked about
+    /// count, compared ignoring case.
+    pub fn chaos_text(&self) -> Option<String> {
+        let qname = &self.questions.first()?.qname;
+        let same_name = |name: &DomainName| {
+            name.label_count() == qname.label_count()
+                && name.labels().zip(qname.labels()).all(|(a, b)| a.eq_ignore_ascii_case(b))
+        };
+        self.answers.iter().find_map(|rr| match rr.rdata {
+            Rdata::Txt(ref strings) if rr.class == Class::CH && same_name(&rr.name) => {
+                let text: Vec<u8> = strings.iter().flat_map(|s| s.as_bytes()).cloned().collect();
+                Some(String::from_utf8_lossy(&text).into_owned())
+            },
+            _ => None,
+        })
+    }
+}
+
+/// Names for `Message::chaos_query`: the server software's version, and
+/// which server it is, for telling anycast nodes apart. The `.bind` names
+/// started with BIND, and the `.server` ones were standardized in RFC 4892.
+pub const VERSION_BIND: &str = "version.bind";
+pub const HOSTNAME_BIND: &str = "hostname.bind";
+pub const VERSION_SERVER: &str = "version.server";
+pub const ID_SERVER: &str = "id.server";
+
+impl <'a> Message<'a> {
+    /// The options in the message's OPT record, empty if it has none
+    pub fn edns_options(&self) -> &[EdnsOption<'a>] {
+        self.additional.iter().find_map(|rr| match rr.rdata {
+            Rdata::Opt(ref options) => Some(&options[..]),
+            _ => None,
+        }).unwrap_or(&[])
+    }
+
+    /// The message in wire format, padded out to a multiple of `block`
+    /// bytes with a Padding option, RFC 7830. Any Padding already there is
+    /// replaced. Messages without an OPT record get one, saying they take
+    /// the plain DNS limit of 512 bytes so nothing else changes.
+    pub fn to_padded_bytes(&self, block: usize) -> Vec<u8> {
+        let block = block.max(1);
+        let len = self.with_padding(&[]).to_bytes().len();
+        let zeros = vec![0; (block - len % block) % block];
+        let padded = self.with_padding(&zeros);
+        padded.to_bytes()
+    }
+
+    // a copy with `padding` as its only Padding option
+    fn with_padding<'b>(&self, padding: &'b [u8]) -> Message<'b> where 'a: 'b {
+        let mut msg = self.clone();
+        let opt = match msg.additional.iter().position(|rr| matches!(rr.rdata, Rdata::Opt(_))) {
+            Some(i) => i,
+            None => {
+                msg.additional.push(ResourceRecord::opt(512, vec![]));
+                msg.additional.len() - 1
             },
-            x => Some(x.clone()),
+        };
+        if let Rdata::Opt(ref mut options) = msg.additional[opt].rdata {
+            options.retain(|option| !matches!(*option, EdnsOption::Padding(_)));
+            options.push(EdnsOption::Padding(padding));
         }
+        msg
     }
 
-    fn domain_deref<'a>(domain: &DomainName<'a>, dict: &mut HashMap<u16, DomainName<'a>>, bytestr: &'a [u8]) -> Option<DomainName<'a>> {
-        let mut out = deref_helper(domain, dict, bytestr);
-        fn recurse<'a>(d: &Option<DomainName<'a>>) -> bool {
-            match d {
-                &Some(DomainName::Labels(_)) => false,
-                &Some(_) => true,
-                &None => false,
-            }
+    /// The server's NSID, if the message carries one
+    pub fn nsid(&self) -> Option<&'a [u8]> {
+        self.edns_options().iter().find_map(|option| match *option {
+            EdnsOption::Nsid(data) => Some(data),
+            _ => None,
+        })
+    }
+
+    /// The Extended DNS Errors the message carries, in order
+    pub fn extended_errors(&self) -> Vec<&ExtendedError<'a>> {
+        self.edns_options().iter().filter_map(|option| match *option {
+            EdnsOption::ExtendedError(ref ede) => Some(ede),
+            _ => None,
+        }).collect()
+    }
+
+    /// The COOKIE option, if the message carries one
+    pub fn cookie(&self) -> Option<&Cookie<'a>> {
+        self.edns_options().iter().find_map(|option| match *option {
+            EdnsOption::Cookie(ref cookie) => Some(cookie),
+            _ => None,
+        })
+    }
+}
+
+/// RFC 8467's recommended `to_padded_bytes` block sizes, for queries and
+/// responses
+pub const QUERY_BLOCK: usize = 128;
+pub const RESPONSE_BLOCK: usize = 468;
+
+/// How much the parsers put up with. `Strict` rejects anything the RFCs
+/// don't allow, `Lenient` turns unknown types and classes, reserved bits
+/// and rdata that doesn't match its type into `Unknown` values or ignores
+/// them, so noncompliant traffic can still be looked at.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Mode {
+    #[default]
+    Strict,
+    Lenient,
+}
+
+pub fn parse_dns_message(bs: &[u8]) -> IResult<'_, Message<'_>> {
+    parse_dns_message_with(Mode::Strict, bs)
+}
+
+/// Like `parse_dns_message`, but in the given `Mode`
+pub fn parse_dns_message_with(mode: Mode, bs: &[u8]) -> IResult<'_, Message<'_>> {
+    context("dns", |bs| dns_message(mode, &mut Arena::new(), None, None, bs))(bs)
+}
+
+// Parses into Vecs from `arena`, and follows compression pointers as names
+// are parsed if there are `names` to keep track of them with. Records that
+// don't parse go in `errors` instead of failing the message, if there are any.
+fn dns_message<'a>(mode: Mode, arena: &mut Arena, mut names: Option<&mut Names<'a>>,
+                   mut errors: Option<&mut Vec<RecordError<'a>>>, bs: &'a [u8])
+    -> IResult<'a, Message<'a>>
+{
+    let (bs, header) = dns_header(mode, bs)?;
+    let (mut questions, mut answers) = (arena.queries(), arena.records());
+    let (mut authorities, mut additional) = (arena.records(), arena.records());
+    let (bs, ()) = context("dns.queries", |bs| section(&mut questions, header.qdcount, |bs| {
+        let (bs, mut q) = query(mode, arena, bs)?;
+        if let Some(ref mut names) = names {
+            names.resolve(&mut q.qname);
         }
-        let mut should_recurse = recurse(&out);
-        while should_recurse {
-            let new_out = match out {
-                Some(domain) => deref_helper(&domain, dict, bytestr),
-                _ => None,
-            };
-            out = new_out;
-            should_recurse = recurse(&out);
-        }
-        out
-    }
-
-    fn fix_record<'a>(record: &mut ResourceRecord<'a>, dict: &mut HashMap<u16, DomainName<'a>>,
-                      bytestr: &'a [u8]) {
-        match domain_deref(&record.name, dict, bytestr) {
-            Some(domain) => record.name = domain,
-            _ => {},
-        }
-
-        // TODO: check the rdata field to see if it's a domain name
-        match &mut record.rdata {
-            &mut Rdata::Cname(ref mut domain) | &mut Rdata::MB(ref mut domain) |
-                &mut Rdata::MD(ref mut domain) | &mut Rdata::MF(ref mut domain) |
-                &mut Rdata::MG(ref mut domain) | &mut Rdata::MR(ref mut domain) |
-                &mut Rdata::NS(ref mut domain) | &mut Rdata::Ptr(ref mut domain) => {
-                    match domain_deref(&domain, dict, bytestr) {
-                        Some(new_domain) => *domain = new_domain,
-                        _ => {},
-                    }
+        Ok((bs, q))
+    }, bs))(bs)?;
+    let mut record = |bs| {
+        let (bs, mut rr) = resource_record(mode, arena, bs)?;
+        if let Some(ref mut names) = names {
+            names.resolve(&mut rr.name);
+            rr.rdata.for_each_name_mut(|name| names.resolve(name));
+        }
+        Ok((bs, rr))
+    };
+    let bs = match errors {
+        Some(ref mut errors) => {
+            // once a record can't be skipped, there's nothing left to find
+            // the sections after it in
+            let end = &bs[bs.len()..];
+            let bs = recover_section(&mut answers, Section::Answer, header.ancount, &mut record, errors, bs)
+                .and_then(|bs| recover_section(&mut authorities, Section::Authority, header.nscount, &mut record, errors, bs))
+                .and_then(|bs| recover_section(&mut additional, Section::Additional, header.arcount, &mut record, errors, bs));
+            bs.unwrap_or(end)
+        },
+        None => {
+            let (bs, ()) = context("dns.answers", |bs| section(&mut answers, header.ancount, &mut record, bs))(bs)?;
+            let (bs, ()) = context("dns.authorities", |bs| section(&mut authorities, header.nscount, &mut record, bs))(bs)?;
+            let (bs, ()) = context("dns.additional", |bs| section(&mut additional, header.arcount, &mut record, bs))(bs)?;
+            bs
+        },
+    };
+    Ok((bs, Message {
+        header,
+        questions,
+        answers,
+        authorities,
+        additional,
+    }))
+}
+
+// like nom's count, into a Vec that might already have room
+fn section<'a, T, F>(items: &mut Vec<T>, n: u16, mut item: F, mut bs: &'a [u8]) -> IResult<'a, ()>
+    where F: FnMut(&'a [u8]) -> IResult<'a, T>
+{
+    items.reserve(room(n, bs));
+    for _ in 0..n {
+        let (rest, x) = item(bs)?;
+        items.push(x);
+        bs = rest;
+    }
+    Ok((bs, ()))
+}
+
+// how many items to make room for: each takes at least a byte, so a count
+// from a crafted header can't reserve more than the input could hold
+fn room(n: u16, bs: &[u8]) -> usize {
+    (n as usize).min(bs.len())
+}
+
+// like section, but a record that doesn't parse is skipped over by its
+// rdlength and kept in `errors`. If it can't be skipped either, the rest of
+// the message goes with it, marked as truncated, and there's no input left
+// for the records after it.
+fn recover_section<'a, F>(records: &mut Vec<ResourceRecord<'a>>, section: Section, n: u16, mut record: F,
+                          errors: &mut Vec<RecordError<'a>>, mut bs: &'a [u8]) -> Option<&'a [u8]>
+    where F: FnMut(&'a [u8]) -> IResult<'a, ResourceRecord<'a>>
+{
+    records.reserve(room(n, bs));
+    for index in 0..n as usize {
+        match record(bs) {
+            Ok((rest, rr)) => {
+                records.push(rr);
+                bs = rest;
             },
-            &mut Rdata::Minfo(ref mut minfo) => {
-                match domain_deref(&minfo.rmailbox, dict, bytestr) {
-                    Some(new_domain) => minfo.rmailbox = new_domain,
-                    _ => {},
-                }
-                match domain_deref(&minfo.emailbox, dict, bytestr) {
-                    Some(new_domain) => minfo.emailbox = new_domain,
-                    _ => {},
-                }
+            Err(e) => {
+                let error = match e {
+                    nom::Err::Error(e) | nom::Err::Failure(e) => e,
+                    nom::Err::Incomplete(_) => ParseError::new(bs, ErrorKind::Complete),
+                };
+                let rest = skip_record(bs);
+                let raw = &bs[..bs.len() - rest.map_or(0, <[u8]>::len)];
+                errors.push(RecordError { section, index, raw, error, truncated: rest.is_none() });
+                bs = rest?;
             },
-            &mut Rdata::MX(ref mut mx) => {
-                match domain_deref(&mx.exchange, dict, bytestr) {
-                    Some(new_domain) => mx.exchange = new_domain,
-                    _ => {},
-                }
+        }
+    }
+    Some(bs)
+}
+
+// what's left after the record at the start of `bs`, going by nothing but
+// its name's length bytes and its rdlength
+fn skip_record(bs: &[u8]) -> Option<&[u8]> {
+    let mut pos = 0;
+    loop {
+        match *bs.get(pos)? {
+            0 => {
+                pos += 1;
+                break;
             },
-            &mut Rdata::Soa(ref mut soa) => {
-                match domain_deref(&soa.mname, dict, bytestr) {
-                    Some(new_domain) => soa.mname= new_domain,
-                    _ => {},
-                }
-                match domain_deref(&soa.rname, dict, bytestr) {
-                    Some(new_domain) => soa.rname = new_domain,
-                    _ => {},
-                }
+            len if len & 0xc0 == 0xc0 => {
+                pos += 2;
+                break;
             },
-            &mut Rdata::Hinfo(_) | &mut Rdata::Null(_) | &mut Rdata::Txt(_) |
-                &mut Rdata::A(_) | &mut Rdata::Wks(_) | &mut Rdata::AAAA(_) |
-                &mut Rdata::Unknown(_) => {},
+            len => pos += 1 + len as usize,
         }
     }
+    // type, class and ttl come before the rdlength
+    let rdlength = u16::from_be_bytes([*bs.get(pos + 8)?, *bs.get(pos + 9)?]);
+    bs.get(pos + 10 + rdlength as usize..)
+}
 
-    parse_dns_message(bytestr)
-        .map(|mut msg| {
-            let mut parsed_pointers: HashMap<u16, DomainName<'a>> = HashMap::new();
-            for query in msg.questions.iter_mut() {
-                let change_name = match &query.qname {
-                    &DomainName::Pointer(_) | &DomainName::LabelWithPointer(_, _) => true,
-                    _ => false,
-                };
-                if change_name {
-                    match domain_deref(&query.qname, &mut parsed_pointers, bytestr) {
-                        Some(domain) => query.qname = domain,
-                        _ => {},
-                    }
-                }
-            }
-            for answer in msg.answers.iter_mut() {
-                fix_record(answer, &mut parsed_pointers, bytestr);
+/// Whether `response` asks about the same names as `query`, down to the
+/// case of every letter. Resolvers that randomize the case of the names
+/// they send (DNS 0x20) check this to make a spoofer guess the case too,
+/// on top of the ID and port. Names should be resolved, as with
+/// `parse_dns_message_full`, so a pointer doesn't hide the labels.
+pub fn case_matches(query: &Message, response: &Message) -> bool {
+    query.questions.len() == response.questions.len()
+        && query.questions.iter().zip(&response.questions).all(|(q, r)| q.qname == r.qname)
+}
+
+/// Short summary, like "query A www.example.com" or
+/// "response A www.example.com: A 93.184.216.34"
+impl <'a> fmt::Display for Message<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.header.qr {
+            QR::Query => write!(f, "query")?,
+            QR::Response => write!(f, "response")?,
+        }
+        for (i, q) in self.questions.iter().enumerate() {
+            let sep = if i == 0 { " " } else { ", " };
+            match q.qtype {
+                Qtype::Type(typ) => write!(f, "{}{:?} {}", sep, typ, q.qname)?,
+                typ => write!(f, "{}{:?} {}", sep, typ, q.qname)?,
             }
-            for authority in msg.authorities.iter_mut() {
-                fix_record(authority, &mut parsed_pointers, bytestr);
+        }
+        if self.header.rcode != Rcode::NoError {
+            write!(f, " {:?}", self.header.rcode)?;
+        }
+        for (i, rr) in self.answers.iter().enumerate() {
+            let sep = if i == 0 { ": " } else { ", " };
+            write!(f, "{}{:?} {}", sep, rr.typ, rr.rdata)?;
+        }
+        Ok(())
+    }
+}
+
+/// Convert domain name pointers to byte slices
+pub fn parse_dns_message_full(bytestr: &[u8]) -> IResult<'_, Message<'_>> {
+    parse_dns_message_full_with(Mode::Strict, bytestr)
+}
+
+/// Like `parse_dns_message_full`, but in the given `Mode`
+pub fn parse_dns_message_full_with(mode: Mode, bytestr: &[u8]) -> IResult<'_, Message<'_>> {
+    parse_dns_message_full_in(mode, &mut Arena::new(), bytestr)
+}
+
+/// Like `parse_dns_message_full_with`, but the message's `Vec`s come from
+/// `arena`, so a capture loop that recycles each message when it's done
+/// with it stops going back to the allocator once the arena's warmed up
+pub fn parse_dns_message_full_in<'a>(mode: Mode, arena: &mut Arena, bytestr: &'a [u8]) -> IResult<'a, Message<'a>> {
+    full_message(mode, arena, None, bytestr)
+}
+
+/// Which section of a message a record is in
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Section {
+    Answer,
+    Authority,
+    Additional,
+}
+
+/// A record that `parse_dns_message_partial` left out of its message
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecordError<'a> {
+    pub section: Section,
+    /// Where the record was in its section, counting the ones that parsed
+    pub index: usize,
+    /// The record's bytes, or the rest of the message if where it ends
+    /// couldn't be told either
+    pub raw: &'a [u8],
+    pub error: ParseError<'a>,
+    /// Whether `raw` took the rest of the message with it, leaving out
+    /// whatever records the header counts after this one
+    pub truncated: bool,
+}
+
+/// Like `parse_dns_message_full_with`, but a resource record that doesn't
+/// parse is left out of the message and returned alongside it, instead of
+/// failing the whole message. The header and questions still have to parse.
+pub fn parse_dns_message_partial(mode: Mode, bytestr: &[u8]) -> IResult<'_, (Message<'_>, Vec<RecordError<'_>>)> {
+    let mut errors = Vec::new();
+    let (rest, msg) = full_message(mode, &mut Arena::new(), Some(&mut errors), bytestr)?;
+    Ok((rest, (msg, errors)))
+}
+
+/// A piece of a message handed out by `walk_dns_message`
+#[derive(Debug)]
+pub enum Entry<'r, 'a> {
+    Header(&'r Header),
+    Question(&'r Query<'a>),
+    Record(Section, &'r ResourceRecord<'a>),
+}
+
+/// Parses a message a piece at a time, handing the header and then each
+/// question and record to `f` instead of collecting them into a `Message`.
+/// Each piece's memory goes back to `arena` once `f` is done with it, and
+/// names are left as they are on the wire, compression pointers and all.
+/// Stops after the first piece `f` returns false for.
+pub fn walk_dns_message<'a, F>(mode: Mode, arena: &mut Arena, bs: &'a [u8], mut f: F) -> IResult<'a, ()>
+    where F: FnMut(Entry<'_, 'a>) -> bool
+{
+    context("dns", |bs| walk_message(mode, arena, &mut f, bs))(bs)
+}
+
+fn walk_message<'a, F>(mode: Mode, arena: &mut Arena, f: &mut F, bs: &'a [u8]) -> IResult<'a, ()>
+    where F: FnMut(Entry<'_, 'a>) -> bool
+{
+    let (mut bs, header) = dns_header(mode, bs)?;
+    if !f(Entry::Header(&header)) {
+        return Ok((bs, ()));
+    }
+    for _ in 0..header.qdcount {
+        let (rest, q) = context("dns.queries", |bs| query(mode, arena, bs))(bs)?;
+        bs = rest;
+        let more = f(Entry::Question(&q));
+        arena.recycle_name(q.qname);
+        if !more {
+            return Ok((bs, ()));
+        }
+    }
+    let sections = [
+        (Section::Answer, header.ancount, "dns.answers"),
+        (Section::Authority, header.nscount, "dns.authorities"),
+        (Section::Additional, header.arcount, "dns.additional"),
+    ];
+    for &(section, n, field) in &sections {
+        for _ in 0..n {
+            let (rest, rr) = context(field, |bs| resource_record(mode, arena, bs))(bs)?;
+            bs = rest;
+            let more = f(Entry::Record(section, &rr));
+            arena.recycle_record(rr);
+            if !more {
+                return Ok((bs, ()));
             }
-            for record in msg.additional.iter_mut() {
-                fix_record(record, &mut parsed_pointers, bytestr);
+        }
+    }
+    Ok((bs, ()))
+}
+
+fn full_message<'a>(mode: Mode, arena: &mut Arena, mut errors: Option<&mut Vec<RecordError<'a>>>, bytestr: &'a [u8])
+    -> IResult<'a, Message<'a>>
+{
+    let mut names = Names::new(bytestr, std::mem::take(&mut arena.seen));
+    let parsed = context("dns", |bs| dns_message(mode, arena, Some(&mut names), errors.as_deref_mut(), bs))(bytestr);
+    names.seen.clear();
+    arena.seen = names.seen;
+    let (rest, msg) = parsed?;
+    if names.too_long {
+        arena.recycle(msg);
+        return context("dns", context("dns.name", too_large))(bytestr);
+    }
+    Ok((rest, msg))
+}
+
+/// `Vec`s from messages that are done with, for `parse_dns_message_full_in`
+/// to parse the next ones into
+#[derive(Debug, Default)]
+pub struct Arena {
+    queries: Vec<Vec<Query<'static>>>,
+    records: Vec<Vec<ResourceRecord<'static>>>,
+    labels: Vec<Vec<Label<'static>>>,
+    strings: Vec<Vec<CharacterString<'static>>>,
+    options: Vec<Vec<EdnsOption<'static>>>,
+    seen: Vec<(u16, Option<usize>)>,
+}
+
+// An empty Vec with the same buffer, for elements with other lifetimes.
+// Collecting in place keeps the buffer, as the element types only differ
+// in lifetimes.
+fn relabel<T, U>(mut items: Vec<T>) -> Vec<U> {
+    items.clear();
+    items.into_iter().map(|_| -> U { unreachable!() }).collect()
+}
+
+fn reuse<T, U>(items: Vec<T>, pool: &mut Vec<Vec<U>>) {
+    if items.capacity() > 0 {
+        pool.push(relabel(items));
+    }
+}
+
+fn pop<T, U>(pool: &mut Vec<Vec<T>>) -> Vec<U> {
+    pool.pop().map(relabel).unwrap_or_default()
+}
+
+impl Arena {
+    pub fn new() -> Arena {
+        Arena::default()
+    }
+
+    /// Keeps `msg`'s memory for the messages parsed after it
+    pub fn recycle(&mut self, mut msg: Message) {
+        for q in msg.questions.drain(..) {
+            self.recycle_name(q.qname);
+        }
+        reuse(msg.questions, &mut self.queries);
+        self.recycle_records(msg.answers);
+        self.recycle_records(msg.authorities);
+        self.recycle_records(msg.additional);
+    }
+
+    fn recycle_records(&mut self, mut records: Vec<ResourceRecord>) {
+        for rr in records.drain(..) {
+            self.recycle_record(rr);
+        }
+        reuse(records, &mut self.records);
+    }
+
+    fn recycle_record(&mut self, rr: ResourceRecord) {
+        self.recycle_name(rr.name);
+        match rr.rdata {
+            Rdata::Cname(name) | Rdata::MB(name) | Rdata::MD(name) | Rdata::MF(name)
+                | Rdata::MG(name) | Rdata::MR(name) | Rdata::NS(name) | Rdata::Ptr(name) => self.recycle_name(name),
+            Rdata::Minfo(minfo) => {
+                self.recycle_name(minfo.rmailbox);
+                self.recycle_name(minfo.emailbox);
+            },
+            Rdata::MX(mx) => self.recycle_name(mx.exchange),
+            Rdata::Srv(srv) => self.recycle_name(srv.target),
+            Rdata::Soa(soa) => {
+                self.recycle_name(soa.mname);
+                self.recycle_name(soa.rname);
+            },
+            Rdata::Txt(strings) => reuse(strings, &mut self.strings),
+            Rdata::Opt(options) => reuse(options, &mut self.options),
+            Rdata::Hinfo(_) | Rdata::Null(_) | Rdata::A(_) | Rdata::Wks(_)
+                | Rdata::AAAA(_) | Rdata::Unknown(_) => {},
+        }
+    }
+
+    fn recycle_name(&mut self, name: DomainName) {
+        match name {
+            DomainName::Labels(labels) | DomainName::LabelWithPointer(labels, _)
+                | DomainName::Resolved(labels, _) => self.recycle_labels(labels),
+            DomainName::Pointer(_) => {},
+        }
+    }
+
+    fn recycle_labels(&mut self, labels: List<Label>) {
+        if let Some(labels) = list_into_vec(labels) {
+            reuse(labels, &mut self.labels);
+        }
+    }
+
+    fn queries<'a>(&mut self) -> Vec<Query<'a>> {
+        pop(&mut self.queries)
+    }
+
+    fn records<'a>(&mut self) -> Vec<ResourceRecord<'a>> {
+        pop(&mut self.records)
+    }
+
+    fn labels<'a>(&mut self) -> List<Label<'a>> {
+        list_from_vec(pop(&mut self.labels))
+    }
+
+    fn strings<'a>(&mut self) -> Vec<CharacterString<'a>> {
+        pop(&mut self.strings)
+    }
+
+    fn options<'a>(&mut self) -> Vec<EdnsOption<'a>> {
+        pop(&mut self.options)
+    }
+}
+
+// Pointer targets already followed in one message, with the wire length of
+// the rest of the name there, or None if it's malformed. Names point back
+// into the message instead of copying labels, so this is all that's kept.
+struct Names<'a> {
+    message: &'a [u8],
+    seen: Vec<(u16, Option<usize>)>,
+    too_long: bool,
+}
+
+impl <'a> Names<'a> {
+    fn new(message: &'a [u8], seen: Vec<(u16, Option<usize>)>) -> Names<'a> {
+        Names {
+            message,
+            seen,
+            too_long: false,
+        }
+    }
+
+    // names whose pointers lead nowhere are left as they are
+    fn resolve(&mut self, name: &mut DomainName<'a>) {
+        let off = match *name {
+            DomainName::Pointer(off) | DomainName::LabelWithPointer(_, off) => off,
+            DomainName::Labels(_) | DomainName::Resolved(..) => return,
+        };
+        let suffix_len = match self.suffix_len(off) {
+            Some(len) => len,
+            None => return,
+        };
+        let labels = match std::mem::replace(name, DomainName::Pointer(off)) {
+            DomainName::LabelWithPointer(labels, _) => labels,
+            _ => List::new(),
+        };
+        // only now are names that ended in pointers whole
+        if labels.iter().map(|label| 1 + label.len()).sum::<usize>() + suffix_len > MAX_NAME_LEN {
+            self.too_long = true;
+        }
+        *name = DomainName::Resolved(labels, Suffix { message: self.message, offset: off });
+    }
+
+    fn suffix_len(&mut self, off: u16) -> Option<usize> {
+        if let Some(&(_, len)) = self.seen.iter().find(|&&(seen, _)| seen == off) {
+            return len;
+        }
+        let mut walk = SuffixIter { message: self.message, pos: off as usize, steps: 0 };
+        let mut len = 1;
+        let len = loop {
+            match walk.step() {
+                Ok(Some(label)) => len += 1 + label.len(),
+                Ok(None) => break Some(len),
+                Err(()) => break None,
             }
-            msg
-        })
+        };
+        self.seen.push((off, len));
+        len
+    }
+}
+
+// names are written as they are, so ones from a message that wasn't
+// parsed with parse_dns_message_full keep pointers into that message
+impl <'a> ToBytes for Message<'a> {
+    fn write_with(&self, fixup: Fixup, out: &mut Vec<u8>) {
+        let mut header = self.header;
+        if fixup.lengths {
+            header.qdcount = self.questions.len() as u16;
+            header.ancount = self.answers.len() as u16;
+            header.nscount = self.authorities.len() as u16;
+            header.arcount = self.additional.len() as u16;
+        }
+        header.write_with(fixup, out);
+        for query in &self.questions {
+            query.write_with(fixup, out);
+        }
+        for record in self.answers.iter().chain(&self.authorities).chain(&self.additional) {
+            record.write_with(fixup, out);
+        }
+    }
+}
+
+/// Decodes DNS over TCP, where each message carries a two byte length
+/// prefix, for use with `stream::Decoder`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TcpDecoder {
+    pub mode: Mode,
+}
+
+impl Decode for TcpDecoder {
+    type Output = OwnedMessage;
+
+    fn decode<'a>(&mut self, bs: &'a [u8]) -> IResult<'a, Option<OwnedMessage>> {
+        let (bs, len) = nom::number::streaming::be_u16(bs)?;
+        let (rest, body) = nom::bytes::streaming::take(len)(bs)?;
+        let (_, msg) = all_consuming(|bs| parse_dns_message_full_with(self.mode, bs))(body)?;
+        Ok((rest, Some(msg.into_owned())))
+    }
+}
+
+/// A response that didn't fit in its UDP datagram, so the server left
+/// records out and set the TC bit. The query should be asked again over TCP.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Truncated {
+    /// The ID of the query it's a response to
+    pub id: u16,
+}
+
+impl Truncated {
+    /// `query` behind its two byte length, ready to write to a TCP
+    /// connection, or `None` if it isn't the query this is a response to
+    /// or is too long for the length
+    pub fn tcp_retry(&self, query: &Message) -> Option<Vec<u8>> {
+        if query.header.id != self.id {
+            return None;
+        }
+        let mut out = vec![0; 2];
+        query.write_with(Fixup::default(), &mut out);
+        let len = u16::try_from(out.len() - 2).ok()?;
+        out[..2].copy_from_slice(&len.to_be_bytes());
+        Some(out)
+    }
 }
 
 pub struct RawHeader {
@@ -188,10 +729,13 @@ struct Bits {
     tc: u8,
     rd: u8,
     ra: u8,
+    z: u8,
     rcode: u8,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub struct Header {
     pub id: u16,
     pub qr: QR,
@@ -209,32 +753,38 @@ pub struct Header {
 
 impl Header {
     pub fn from(raw: RawHeader) -> Option<Header> {
+        Header::from_mode(raw, Mode::Strict)
+    }
+
+    /// Like `from`, but a lenient `mode` ignores the reserved Z bits and
+    /// accepts every rcode
+    pub fn from_mode(raw: RawHeader, mode: Mode) -> Option<Header> {
         let qr = QR::from(raw.fields.qr);
         let opcode = Opcode::from(raw.fields.opcode);
         let aa = raw.fields.aa & 1 == 1;
         let tc = raw.fields.tc & 1 == 1;
         let rd = raw.fields.rd & 1 == 1;
         let ra = raw.fields.ra & 1 == 1;
-        let rcode = Rcode::from(raw.fields.rcode);
+        let rcode = match mode {
+            Mode::Strict => Rcode::from(raw.fields.rcode),
+            Mode::Lenient => Rcode::from(raw.fields.rcode).or(Some(Rcode::Reserved(raw.fields.rcode))),
+        };
 
-        if !qr.is_some() {
-            return None;
-        }
-        if !opcode.is_some() {
-            return None;
-        }
-        if !rcode.is_some() {
+        if mode == Mode::Strict && raw.fields.z != 0 {
             return None;
         }
+        qr?;
+        opcode?;
+        rcode?;
 
         Some(Header {
             id: raw.id,
             qr: qr.unwrap(),
             opcode: opcode.unwrap(),
-            aa: aa,
-            tc: tc,
-            rd: rd,
-            ra: ra,
+            aa,
+            tc,
+            rd,
+            ra,
             rcode: rcode.unwrap(),
             qdcount: raw.qdcount,
             ancount: raw.ancount,
@@ -242,52 +792,81 @@ impl Header {
             arcount: raw.arcount,
         })
     }
+
+    /// `Some` if this is a response with the TC bit set
+    pub fn truncated(&self) -> Option<Truncated> {
+        if self.qr == QR::Response && self.tc {
+            Some(Truncated { id: self.id })
+        } else {
+            None
+        }
+    }
+}
+
+impl ToBytes for Header {
+    fn write_with(&self, _fixup: Fixup, out: &mut Vec<u8>) {
+        let qr = match self.qr {
+            QR::Query => 0,
+            QR::Response => 1,
+        };
+        let flags = (qr << 15) | ((self.opcode.to_u8() as u16) << 11) | ((self.aa as u16) << 10)
+            | ((self.tc as u16) << 9) | ((self.rd as u16) << 8) | ((self.ra as u16) << 7)
+            | self.rcode.to_u8() as u16;
+        for v in &[self.id, flags, self.qdcount, self.ancount, self.nscount, self.arcount] {
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+    }
 }
 
+fn parse_bits(bs: (&[u8], usize)) -> nom::IResult<(&[u8], usize), Bits> {
+    let (bs, qr) = take_bits(1usize)(bs)?;
+    let (bs, opcode) = take_bits(4usize)(bs)?;
+    let (bs, aa) = take_bits(1usize)(bs)?;
+    let (bs, tc) = take_bits(1usize)(bs)?;
+    let (bs, rd) = take_bits(1usize)(bs)?;
+    let (bs, ra) = take_bits(1usize)(bs)?;
+    let (bs, z) = take_bits(3usize)(bs)?;
+    let (bs, rcode) = take_bits(4usize)(bs)?;
+    Ok((bs, Bits {
+        qr,
+        opcode,
+        aa,
+        tc,
+        rd,
+        ra,
+        z,
+        rcode,
+    }))
+}
+
+fn parse_raw_header(bs: &[u8]) -> IResult<'_, RawHeader> {
+    let (bs, id) = be_u16(bs)?;
+    let (bs, fields) = bits(parse_bits)(bs)?;
+    let (bs, qdcount) = be_u16(bs)?;
+    let (bs, ancount) = be_u16(bs)?;
+    let (bs, nscount) = be_u16(bs)?;
+    let (bs, arcount) = be_u16(bs)?;
+    Ok((bs, RawHeader {
+        id,
+        fields,
+        qdcount,
+        ancount,
+        nscount,
+        arcount,
+    }))
+}
+
+pub fn parse_dns_header(bs: &[u8]) -> IResult<'_, Header> {
+    context("dns", |bs| dns_header(Mode::Strict, bs))(bs)
+}
 
-named!(pub parse_dns_header< Header >,
-    map_opt!(
-        do_parse!(
-            id: be_u16 >>
-            fields: bits!(
-                do_parse!(
-                    qr: take_bits!(u8, 1) >>
-                    opcode: take_bits!(u8, 4) >>
-                    aa: take_bits!(u8, 1) >>
-                    tc: take_bits!(u8, 1) >>
-                    rd: take_bits!(u8, 1) >>
-                    ra: take_bits!(u8, 1) >>
-                    _z: tag_bits!(u8, 3, 0) >>
-                    rcode: take_bits!(u8, 4) >>
-                    (Bits {
-                        qr: qr,
-                        opcode: opcode,
-                        aa: aa,
-                        tc: tc,
-                        rd: rd,
-                        ra: ra,
-                        rcode: rcode,
-                    })
-                )
-            ) >>
-            qdcount: be_u16 >>
-            ancount: be_u16 >>
-            nscount: be_u16 >>
-            arcount: be_u16 >>
-            (RawHeader {
-                id: id,
-                fields: fields,
-                qdcount: qdcount,
-                ancount: ancount,
-                nscount: nscount,
-                arcount: arcount,
-            })
-        ),
-        Header::from
-    )
-);
+fn dns_header(mode: Mode, bs: &[u8]) -> IResult<'_, Header> {
+    context("dns.flags", map_opt(parse_raw_header, |raw| Header::from_mode(raw, mode)))(bs)
+}
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub enum QR {
     Query,
     Response,
@@ -304,6 +883,8 @@ impl QR {
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub enum Opcode {
     Query,
     InverseQuery,
@@ -319,16 +900,27 @@ impl Opcode {
             2 => Some(Opcode::Status),
             _ => {
                 if i < 16 {
-                    Some(Opcode::Reserved(i as u8))
+                    Some(Opcode::Reserved(i))
                 } else {
                     None
                 }
             },
         }
     }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            Opcode::Query => 0,
+            Opcode::InverseQuery => 1,
+            Opcode::Status => 2,
+            Opcode::Reserved(i) => i,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub enum Rcode {
     NoError,
     FormatError,
@@ -348,122 +940,436 @@ impl Rcode {
             4 => Some(Rcode::NotImplemented),
             _ => {
                 if i < 15 {
-                    Some(Rcode::Reserved(i as u8))
+                    Some(Rcode::Reserved(i))
                 } else {
                     None
                 }
             },
         }
     }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            Rcode::NoError => 0,
+            Rcode::FormatError => 1,
+            Rcode::ServerFailure => 2,
+            Rcode::NameError => 3,
+            Rcode::NotImplemented => 4,
+            Rcode::Reserved(i) => i,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub struct Query<'a> {
     pub qname: Qname<'a>,
     pub qtype: Qtype,
     pub qclass: Qclass,
 }
-named!(query<Query>,
-    do_parse!(
-        qname: domain_name >>
-        qtype: qtype >>
-        qclass: qclass >>
-        (Query {
-            qname: qname,
-            qtype: qtype,
-            qclass: qclass,
-        })
-    )
-);
+impl <'a> ToBytes for Query<'a> {
+    fn write_with(&self, fixup: Fixup, out: &mut Vec<u8>) {
+        self.qname.write_with(fixup, out);
+        out.extend_from_slice(&self.qtype.to_u16().to_be_bytes());
+        out.extend_from_slice(&self.qclass.to_u16().to_be_bytes());
+    }
+}
+
+fn query<'a>(mode: Mode, arena: &mut Arena, bs: &'a [u8]) -> IResult<'a, Query<'a>> {
+    let (bs, qname) = domain_name_in(arena, bs)?;
+    let (bs, qtype) = qtype(mode, bs)?;
+    let (bs, qclass) = qclass(mode, bs)?;
+    Ok((bs, Query {
+        qname,
+        qtype,
+        qclass,
+    }))
+}
 
 pub type Qname<'a> = DomainName<'a>;
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum DomainName<'a> {
-    Labels(Vec<Label<'a>>),
+    Labels(List<Label<'a>>),
     Pointer(u16),
-    LabelWithPointer(Vec<Label<'a>>, u16),
+    LabelWithPointer(List<Label<'a>>, u16),
+    /// A `Pointer` or `LabelWithPointer` whose pointer was followed by
+    /// `parse_dns_message_full`
+    Resolved(List<Label<'a>>, Suffix<'a>),
 }
 
-named!(domain_name<DomainName>,
-    alt!(labels | pointer | label_with_pointer)
-);
+impl <'a> DomainName<'a> {
+    /// The labels of the name. For ones still ending in a compression
+    /// pointer, that's only the labels written out before it.
+    pub fn labels<'b>(&'b self) -> impl Iterator<Item=Label<'a>> + 'b {
+        let (labels, suffix): (&'b [Label<'a>], _) = match *self {
+            DomainName::Labels(ref labels) | DomainName::LabelWithPointer(ref labels, _) => (labels, None),
+            DomainName::Pointer(_) => (&[], None),
+            DomainName::Resolved(ref labels, suffix) => (labels, Some(suffix.labels())),
+        };
+        labels.iter().cloned().chain(suffix.into_iter().flatten())
+    }
 
-named!(labels<DomainName>,
-    map!(
-        many_till!(call!(label), char!(0 as char) ),
-        |(x, y)| DomainName::Labels(x)
-    )
-);
+    pub fn label_count(&self) -> usize {
+        self.labels().count()
+    }
 
-named!(pointer<DomainName>,
-    map!(
-        verify!(be_u16, |x| x > (0b11000000 << 8)),
-        |x| DomainName::Pointer(x & !(0b11000000 << 8))
-    )
-);
-
-named!(label_with_pointer<DomainName>,
-    map!(
-        many_till!(call!(label), call!(pointer)),
-        |(x, y)| {
-            if let DomainName::Pointer(y) = y {
-                return DomainName::LabelWithPointer(x, y);
-            }
-            unreachable!("label_with_pointer: pointer returned non-pointer value");
+    /// Where a compression pointer points, if the name ends in one
+    pub fn pointer(&self) -> Option<u16> {
+        match *self {
+            DomainName::Labels(_) | DomainName::Resolved(..) => None,
+            DomainName::Pointer(off) | DomainName::LabelWithPointer(_, off) => Some(off),
         }
-    )
-);
+    }
 
-pub type Label<'a> = &'a [u8];
-named!(label,
-    do_parse!(
-        len: verify!(be_u8, |x| x < 0b11000000) >>
-        label: take!(len) >>
-        (label)
-    )
-);
+    /// Bytes the name takes up on the wire, as it's written: a length byte
+    /// per label, then either the root label or a two byte pointer
+    pub fn wire_len(&self) -> usize {
+        let end = if self.pointer().is_some() { 2 } else { 1 };
+        self.labels().map(|label| 1 + label.len()).sum::<usize>() + end
+    }
+}
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub enum Qtype {
-    Type(Type),
-    Axfr,
-    MailB,
-    MailA,
-    Wildcard,
+// names are the same if they have the same labels, whether or not they
+// were resolved
+impl <'a, 'b> PartialEq<DomainName<'b>> for DomainName<'a> {
+    fn eq(&self, other: &DomainName<'b>) -> bool {
+        self.pointer() == other.pointer() && self.labels().eq(other.labels())
+    }
 }
 
-impl Qtype {
-    pub fn from(v: u16) -> Option<Qtype> {
-        let typ = Type::from(v);
-        if let Some(typ) = typ {
-            return Some(Qtype::Type(typ));
-        }
+impl <'a> Eq for DomainName<'a> {}
 
-        match v {
-            252 => Some(Qtype::Axfr),
-            253 => Some(Qtype::MailB),
-            254 => Some(Qtype::MailA),
-            255 => Some(Qtype::Wildcard),
-            _ => None,
+impl <'a> ToBytes for DomainName<'a> {
+    fn write_with(&self, _fixup: Fixup, out: &mut Vec<u8>) {
+        for label in self.labels() {
+            out.push(label.len() as u8);
+            out.extend_from_slice(label);
+        }
+        match self.pointer() {
+            Some(off) => out.extend_from_slice(&(0xc000 | off).to_be_bytes()),
+            None => out.push(0),
         }
     }
 }
-named!(qtype<Qtype>,
-    map_opt!(
-        be_u16,
-        Qtype::from
-    )
-);
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub enum Qclass {
-    Class(Class),
-    Wildcard,
+// plain labels, since pointers need a message to point into, and few
+// enough of them to stay under MAX_NAME_LEN
+#[cfg(feature = "arbitrary")]
+impl <'a> Arbitrary<'a> for DomainName<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<DomainName<'a>> {
+        let mut labels = List::new();
+        for _ in 0..u.int_in_range(0..=3)? {
+            let len = u.int_in_range(1..=63)?.min(u.len());
+            if len == 0 {
+                break;
+            }
+            labels.push(u.bytes(len)?);
+        }
+        Ok(DomainName::Labels(labels))
+    }
 }
 
-impl Qclass {
-    pub fn from(v: u16) -> Option<Qclass> {
+impl <'a> fmt::Display for DomainName<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn write_labels<'a, I: Iterator<Item=Label<'a>>>(f: &mut fmt::Formatter, labels: I) -> fmt::Result {
+            for (i, label) in labels.enumerate() {
+                if i > 0 {
+                    write!(f, ".")?;
+                }
+                write!(f, "{}", String::from_utf8_lossy(label))?;
+            }
+            Ok(())
+        }
+        match *self {
+            DomainName::Labels(_) | DomainName::Resolved(..) if self.label_count() == 0 => write!(f, "<Root>"),
+            DomainName::Labels(_) | DomainName::Resolved(..) => write_labels(f, self.labels()),
+            // only left over if the message wasn't parsed with parse_dns_message_full
+            DomainName::Pointer(off) => write!(f, "<pointer {}>", off),
+            DomainName::LabelWithPointer(ref labels, off) => {
+                write_labels(f, labels.iter().cloned())?;
+                write!(f, ".<pointer {}>", off)
+            },
+        }
+    }
+}
+
+/// Longest label RFC 1035 allows; longer ones fail to parse with
+/// `ErrorKind::TooLarge` in the "dns.label" context
+pub const MAX_LABEL_LEN: usize = 63;
+/// Longest name on the wire, counting length bytes and the root label;
+/// longer ones fail with `ErrorKind::TooLarge` in the "dns.name" context
+pub const MAX_NAME_LEN: usize = 255;
+
+fn too_large<O>(bs: &[u8]) -> IResult<'_, O> {
+    Err(nom::Err::Failure(ParseError::new(bs, ErrorKind::TooLarge)))
+}
+
+fn domain_name(bs: &[u8]) -> IResult<'_, DomainName<'_>> {
+    let (rest, name) = alt((labels, pointer, label_with_pointer))(bs)?;
+    // names ending in a pointer are checked again once it's followed
+    if name.wire_len() > MAX_NAME_LEN {
+        return context("dns.name", too_large)(bs);
+    }
+    Ok((rest, name))
+}
+
+// domain_name, with the labels in a Vec from `arena`. Names that don't
+// parse go through domain_name again for the same error.
+fn domain_name_in<'a>(arena: &mut Arena, bs: &'a [u8]) -> IResult<'a, DomainName<'a>> {
+    let mut labels = arena.labels();
+    let mut rest = bs;
+    let name = loop {
+        if let Some((&0, after)) = rest.split_first() {
+            rest = after;
+            break DomainName::Labels(labels);
+        }
+        if let Ok((after, DomainName::Pointer(off))) = pointer(rest) {
+            rest = after;
+            if labels.is_empty() {
+                arena.recycle_labels(labels);
+                break DomainName::Pointer(off);
+            }
+            break DomainName::LabelWithPointer(labels, off);
+        }
+        match label(rest) {
+            Ok((after, label)) => {
+                labels.push(label);
+                rest = after;
+            },
+            Err(_) => {
+                arena.recycle_labels(labels);
+                return domain_name(bs);
+            },
+        }
+    };
+    if name.wire_len() > MAX_NAME_LEN {
+        arena.recycle_name(name);
+        return context("dns.name", too_large)(bs);
+    }
+    Ok((rest, name))
+}
+
+fn labels(bs: &[u8]) -> IResult<'_, DomainName<'_>> {
+    map(many_till_list(label, tag([0])), |(labels, _)| DomainName::Labels(labels))(bs)
+}
+
+fn pointer(bs: &[u8]) -> IResult<'_, DomainName<'_>> {
+    map(
+        verify(be_u16, |&x| x > (0b11000000 << 8)),
+        |x| DomainName::Pointer(x & !(0b11000000 << 8))
+    )(bs)
+}
+
+fn label_with_pointer(bs: &[u8]) -> IResult<'_, DomainName<'_>> {
+    let (bs, (labels, pointer)) = many_till_list(label, pointer)(bs)?;
+    match pointer {
+        DomainName::Pointer(off) => Ok((bs, DomainName::LabelWithPointer(labels, off))),
+        _ => unreachable!("label_with_pointer: pointer returned non-pointer value"),
+    }
+}
+
+pub type Label<'a> = &'a [u8];
+fn label(bs: &[u8]) -> IResult<'_, Label<'_>> {
+    let (rest, len) = verify(be_u8, |&x| x < 0b11000000)(bs)?;
+    if len as usize > MAX_LABEL_LEN {
+        return context("dns.label", too_large)(bs);
+    }
+    take(len)(rest)
+}
+
+/// Walks the labels of a domain name in place, following compression
+/// pointers, for when collecting them into a `Vec` costs too much. Stops at
+/// the root label, or at the first label that's malformed, doesn't fit or
+/// would make the name longer than `MAX_NAME_LEN`.
+#[derive(Clone, Debug)]
+pub struct LabelIter<'a> {
+    message: &'a [u8],
+    pos: usize,
+    // where the labels being read started; pointers have to point before
+    // it, which keeps them from looping
+    start: usize,
+    // wire length of the labels so far
+    len: usize,
+}
+
+impl <'a> LabelIter<'a> {
+    /// Iterates over the labels of the name at `offset`. `message` has to be
+    /// the whole DNS message for pointers to resolve.
+    pub fn new(message: &'a [u8], offset: usize) -> LabelIter<'a> {
+        LabelIter {
+            message,
+            pos: offset,
+            start: offset,
+            len: 0,
+        }
+    }
+}
+
+impl <'a> Iterator for LabelIter<'a> {
+    type Item = Label<'a>;
+
+    fn next(&mut self) -> Option<Label<'a>> {
+        loop {
+            let len = *self.message.get(self.pos)? as usize;
+            if len & 0xc0 == 0xc0 {
+                let lo = *self.message.get(self.pos + 1)? as usize;
+                let target = ((len & 0x3f) << 8) | lo;
+                if target >= self.start {
+                    self.pos = self.message.len();
+                    return None;
+                }
+                self.pos = target;
+                self.start = target;
+            } else if len == 0 || len & 0xc0 != 0 || self.pos + 1 + len > self.message.len()
+                    || self.len + 1 + len + 1 > MAX_NAME_LEN {
+                self.pos = self.message.len();
+                return None;
+            } else {
+                let label = &self.message[self.pos + 1..self.pos + 1 + len];
+                self.pos += 1 + len;
+                self.len += 1 + len;
+                return Some(label);
+            }
+        }
+    }
+}
+
+/// The rest of a name that `parse_dns_message_full` followed a compression
+/// pointer for, left where it is in the message rather than copied out
+#[derive(Clone, Copy)]
+pub struct Suffix<'a> {
+    message: &'a [u8],
+    offset: u16,
+}
+
+impl <'a> Suffix<'a> {
+    /// Where the pointer pointed
+    pub fn offset(&self) -> u16 {
+        self.offset
+    }
+
+    pub fn labels(&self) -> SuffixIter<'a> {
+        SuffixIter {
+            message: self.message,
+            pos: self.offset as usize,
+            steps: 0,
+        }
+    }
+}
+
+impl <'a> fmt::Debug for Suffix<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.labels()).finish()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl <'a> ::serde::Serialize for Suffix<'a> {
+    fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.labels())
+    }
+}
+
+/// The labels of a `Suffix`. Unlike `LabelIter`, pointers can point
+/// forwards, as they could before the pointer was followed.
+#[derive(Clone, Debug)]
+pub struct SuffixIter<'a> {
+    message: &'a [u8],
+    pos: usize,
+    steps: usize,
+}
+
+impl <'a> SuffixIter<'a> {
+    // every step takes at least a byte of the name, so a name that's still
+    // going after MAX_NAME_LEN steps is either too long or a pointer loop
+    fn step(&mut self) -> Result<Option<Label<'a>>, ()> {
+        loop {
+            if self.steps == MAX_NAME_LEN {
+                return Err(());
+            }
+            self.steps += 1;
+            let len = *self.message.get(self.pos).ok_or(())? as usize;
+            if len == 0 {
+                return Ok(None);
+            } else if len & 0b11000000 == 0b11000000 {
+                let lo = *self.message.get(self.pos + 1).ok_or(())? as usize;
+                self.pos = (len & 0b00111111) << 8 | lo;
+            } else if len > MAX_LABEL_LEN {
+                return Err(());
+            } else {
+                let label = self.message.get(self.pos + 1..self.pos + 1 + len).ok_or(())?;
+                self.pos += 1 + len;
+                return Ok(Some(label));
+            }
+        }
+    }
+}
+
+impl <'a> Iterator for SuffixIter<'a> {
+    type Item = Label<'a>;
+
+    fn next(&mut self) -> Option<Label<'a>> {
+        self.step().ok().and_then(|label| label)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub enum Qtype {
+    Type(Type),
+    Axfr,
+    MailB,
+    MailA,
+    Wildcard,
+}
+
+impl Qtype {
+    pub fn from(v: u16) -> Option<Qtype> {
+        let typ = Type::from(v);
+        if let Some(typ) = typ {
+            return Some(Qtype::Type(typ));
+        }
+
+        match v {
+            252 => Some(Qtype::Axfr),
+            253 => Some(Qtype::MailB),
+            254 => Some(Qtype::MailA),
+            255 => Some(Qtype::Wildcard),
+            _ => None,
+        }
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        match *self {
+            Qtype::Type(typ) => typ.to_u16(),
+            Qtype::Axfr => 252,
+            Qtype::MailB => 253,
+            Qtype::MailA => 254,
+            Qtype::Wildcard => 255,
+        }
+    }
+}
+fn qtype(mode: Mode, bs: &[u8]) -> IResult<'_, Qtype> {
+    map_opt(be_u16, |v| match mode {
+        Mode::Strict => Qtype::from(v),
+        Mode::Lenient => Qtype::from(v).or(Some(Qtype::Type(Type::Unknown(v)))),
+    })(bs)
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub enum Qclass {
+    Class(Class),
+    Wildcard,
+}
+
+impl Qclass {
+    pub fn from(v: u16) -> Option<Qclass> {
         let class = Class::from(v);
         if let Some(class) = class {
             return Some(Qclass::Class(class));
@@ -475,16 +1381,24 @@ impl Qclass {
         }
 
     }
+
+    pub fn to_u16(&self) -> u16 {
+        match *self {
+            Qclass::Class(class) => class.to_u16(),
+            Qclass::Wildcard => 255,
+        }
+    }
 }
 
-named!(qclass<Qclass>,
-    map_opt!(
-        be_u16,
-        Qclass::from
-    )
-);
+fn qclass(mode: Mode, bs: &[u8]) -> IResult<'_, Qclass> {
+    map_opt(be_u16, |v| match mode {
+        Mode::Strict => Qclass::from(v),
+        Mode::Lenient => Qclass::from(v).or(Some(Qclass::Class(Class::Unknown(v)))),
+    })(bs)
+}
 
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct ResourceRecord<'a> {
     pub name: DomainName<'a>,
     pub typ: Type,
@@ -493,25 +1407,111 @@ pub struct ResourceRecord<'a> {
     pub rdata: Rdata<'a>,
 }
 
-named!(resource_record<ResourceRecord>,
-    do_parse!(
-        name: domain_name >>
-        typ: parse_type >>
-        class: parse_class >>
-        ttl: be_u32 >>
-        rdlen: be_u16 >>
-        rdata: map_opt!(take!(rdlen), |data| Rdata::from(typ, data)) >>
-        (ResourceRecord {
-            name: name,
-            typ: typ,
-            class: class,
-            ttl: ttl,
-            rdata: rdata,
+impl <'a> ResourceRecord<'a> {
+    /// An OPT record for the additional section, RFC 6891, saying the
+    /// sender speaks EDNS and takes UDP payloads up to `udp_payload_size`
+    pub fn opt(udp_payload_size: u16, options: Vec<EdnsOption<'a>>) -> ResourceRecord<'a> {
+        ResourceRecord {
+            name: DomainName::Labels(List::new()),
+            typ: Type::OPT,
+            class: Class::Unknown(udp_payload_size),
+            ttl: 0,
+            rdata: Rdata::Opt(options),
+        }
+    }
+
+    /// Whether the record's names have no compression pointers left in
+    /// them, as after `parse_dns_message_full`
+    pub fn is_resolved(&self) -> bool {
+        self.name.pointer().is_none() && self.rdata.domain_names().iter().all(|name| name.pointer().is_none())
+    }
+}
+
+// a type that matches the rdata, and addresses of the right length
+#[cfg(feature = "arbitrary")]
+impl <'a> Arbitrary<'a> for ResourceRecord<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<ResourceRecord<'a>> {
+        let name = DomainName::arbitrary(u)?;
+        let rdata = match Rdata::arbitrary(u)? {
+            Rdata::A(_) if u.len() >= 4 => Rdata::A(u.bytes(4)?),
+            Rdata::AAAA(_) if u.len() >= 16 => Rdata::AAAA(u.bytes(16)?),
+            Rdata::A(raw) | Rdata::AAAA(raw) => Rdata::Null(raw),
+            rdata => rdata,
+        };
+        let typ = match rdata {
+            Rdata::Cname(_) => Type::Cname,
+            Rdata::Hinfo(_) => Type::Hinfo,
+            Rdata::MB(_) => Type::MB,
+            Rdata::MD(_) => Type::MD,
+            Rdata::MF(_) => Type::MF,
+            Rdata::MG(_) => Type::MG,
+            Rdata::Minfo(_) => Type::Minfo,
+            Rdata::MR(_) => Type::MR,
+            Rdata::MX(_) => Type::MX,
+            Rdata::Srv(_) => Type::SRV,
+            Rdata::Null(_) => Type::Null,
+            Rdata::NS(_) => Type::NS,
+            Rdata::Ptr(_) => Type::Ptr,
+            Rdata::Soa(_) => Type::SOA,
+            Rdata::Txt(_) => Type::Txt,
+            Rdata::A(_) => Type::A,
+            Rdata::Wks(_) => Type::WKS,
+            Rdata::AAAA(_) => Type::AAAA,
+            Rdata::Opt(_) => Type::OPT,
+            Rdata::Unknown(_) => Type::Unknown(u.int_in_range(256..=0xffff)?),
+        };
+        Ok(ResourceRecord {
+            name,
+            typ,
+            class: u.arbitrary()?,
+            ttl: u.arbitrary()?,
+            rdata,
         })
-    )
-);
+    }
+}
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+impl <'a> ToBytes for ResourceRecord<'a> {
+    fn write_with(&self, fixup: Fixup, out: &mut Vec<u8>) {
+        self.name.write_with(fixup, out);
+        out.extend_from_slice(&self.typ.to_u16().to_be_bytes());
+        out.extend_from_slice(&self.class.to_u16().to_be_bytes());
+        out.extend_from_slice(&self.ttl.to_be_bytes());
+        // there's no rdlength to keep, it's always worked out
+        let rdlength = out.len();
+        out.extend_from_slice(&[0, 0]);
+        self.rdata.write_with(fixup, out);
+        let len = (out.len() - rdlength - 2) as u16;
+        out[rdlength..rdlength + 2].copy_from_slice(&len.to_be_bytes());
+    }
+}
+
+fn resource_record<'a>(mode: Mode, arena: &mut Arena, bs: &'a [u8]) -> IResult<'a, ResourceRecord<'a>> {
+    let (bs, name) = domain_name_in(arena, bs)?;
+    let (bs, typ) = parse_type(mode, bs)?;
+    // an OPT record's class is the largest UDP payload its sender takes
+    let (bs, class) = if typ == Type::OPT {
+        map(be_u16, Class::Unknown)(bs)?
+    } else {
+        parse_class(mode, bs)?
+    };
+    let (bs, ttl) = be_u32(bs)?;
+    let (bs, rdlen) = be_u16(bs)?;
+    let (bs, rdata) = map_opt(take(rdlen), |data| match mode {
+        Mode::Strict => rdata(typ, data, arena),
+        Mode::Lenient => rdata(typ, data, arena).or(Some(Rdata::Unknown(data))),
+    })(bs)?;
+    Ok((bs, ResourceRecord {
+        name,
+        typ,
+        class,
+        ttl,
+        rdata,
+    }))
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub enum Type {
     A,
     NS,
@@ -530,6 +1530,10 @@ pub enum Type {
     MX,
     Txt,
     AAAA,
+    SRV,
+    OPT,
+    // only produced by lenient parsing
+    Unknown(u16),
 }
 
 impl Type {
@@ -552,299 +1556,1121 @@ impl Type {
             15 => Some(Type::MX),
             16 => Some(Type::Txt),
             28 => Some(Type::AAAA),
+            33 => Some(Type::SRV),
+            41 => Some(Type::OPT),
+            _ => None,
+        }
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        match *self {
+            Type::A => 1,
+            Type::NS => 2,
+            Type::MD => 3,
+            Type::MF => 4,
+            Type::Cname => 5,
+            Type::SOA => 6,
+            Type::MB => 7,
+            Type::MG => 8,
+            Type::MR => 9,
+            Type::Null => 10,
+            Type::WKS => 11,
+            Type::Ptr => 12,
+            Type::Hinfo => 13,
+            Type::Minfo => 14,
+            Type::MX => 15,
+            Type::Txt => 16,
+            Type::AAAA => 28,
+            Type::SRV => 33,
+            Type::OPT => 41,
+            Type::Unknown(v) => v,
+        }
+    }
+}
+
+fn parse_type(mode: Mode, bs: &[u8]) -> IResult<'_, Type> {
+    map_opt(be_u16, |v| match mode {
+        Mode::Strict => Type::from(v),
+        Mode::Lenient => Type::from(v).or(Some(Type::Unknown(v))),
+    })(bs)
+}
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub enum Class {
+    IN,
+    CS,
+    CH,
+    HS,
+    // only produced by lenient parsing, and for OPT records, where it's a
+    // UDP payload size
+    Unknown(u16),
+}
+
+impl Class {
+    pub fn from(v: u16) -> Option<Class> {
+        match v {
+            1 => Some(Class::IN),
+            2 => Some(Class::CS),
+            3 => Some(Class::CH),
+            4 => Some(Class::HS),
             _ => None,
         }
     }
+
+    pub fn to_u16(&self) -> u16 {
+        match *self {
+            Class::IN => 1,
+            Class::CS => 2,
+            Class::CH => 3,
+            Class::HS => 4,
+            Class::Unknown(v) => v,
+        }
+    }
+}
+
+fn parse_class(mode: Mode, bs: &[u8]) -> IResult<'_, Class> {
+    map_opt(be_u16, |v| match mode {
+        Mode::Strict => Class::from(v),
+        Mode::Lenient => Class::from(v).or(Some(Class::Unknown(v))),
+    })(bs)
+}
+
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub enum Rdata<'a> {
+    Cname(DomainName<'a>),
+    Hinfo(Hinfo<'a>),
+    MB(DomainName<'a>),
+    MD(DomainName<'a>),
+    MF(DomainName<'a>),
+    MG(DomainName<'a>),
+    Minfo(Minfo<'a>),
+    MR(DomainName<'a>),
+    MX(MX<'a>),
+    Srv(Srv<'a>),
+    Null(&'a [u8]),
+    NS(DomainName<'a>),
+    Ptr(DomainName<'a>),
+    Soa(Soa<'a>),
+    Txt(Vec<CharacterString<'a>>),
+    A(&'a [u8]),
+    Wks(Wks<'a>),
+    AAAA(&'a [u8]),
+    Opt(Vec<EdnsOption<'a>>),
+    Unknown(&'a [u8]),
+}
+
+impl <'a> Rdata<'a> {
+    /// The domain names in the rdata, like the exchange of an MX record
+    pub fn domain_names(&self) -> Vec<&DomainName<'a>> {
+        match *self {
+            Rdata::Cname(ref name) | Rdata::MB(ref name) | Rdata::MD(ref name) | Rdata::MF(ref name)
+                | Rdata::MG(ref name) | Rdata::MR(ref name) | Rdata::NS(ref name)
+                | Rdata::Ptr(ref name) => vec![name],
+            Rdata::Minfo(ref minfo) => vec![&minfo.rmailbox, &minfo.emailbox],
+            Rdata::MX(ref mx) => vec![&mx.exchange],
+            Rdata::Srv(ref srv) => vec![&srv.target],
+            Rdata::Soa(ref soa) => vec![&soa.mname, &soa.rname],
+            Rdata::Hinfo(_) | Rdata::Null(_) | Rdata::Txt(_) | Rdata::A(_) | Rdata::Wks(_)
+                | Rdata::AAAA(_) | Rdata::Opt(_) | Rdata::Unknown(_) => vec![],
+        }
+    }
+
+    fn for_each_name_mut<F: FnMut(&mut DomainName<'a>)>(&mut self, mut f: F) {
+        match *self {
+            Rdata::Cname(ref mut name) | Rdata::MB(ref mut name) | Rdata::MD(ref mut name)
+                | Rdata::MF(ref mut name) | Rdata::MG(ref mut name) | Rdata::MR(ref mut name)
+                | Rdata::NS(ref mut name) | Rdata::Ptr(ref mut name) => f(name),
+            Rdata::Minfo(ref mut minfo) => {
+                f(&mut minfo.rmailbox);
+                f(&mut minfo.emailbox);
+            },
+            Rdata::MX(ref mut mx) => f(&mut mx.exchange),
+            Rdata::Srv(ref mut srv) => f(&mut srv.target),
+            Rdata::Soa(ref mut soa) => {
+                f(&mut soa.mname);
+                f(&mut soa.rname);
+            },
+            Rdata::Hinfo(_) | Rdata::Null(_) | Rdata::Txt(_) | Rdata::A(_) | Rdata::Wks(_)
+                | Rdata::AAAA(_) | Rdata::Opt(_) | Rdata::Unknown(_) => {},
+        }
+    }
+
+    pub fn from(typ: Type, raw: &'a [u8]) -> Option<Rdata<'a>> {
+        rdata(typ, raw, &mut Arena::new())
+    }
+}
+
+fn rdata<'a>(typ: Type, raw: &'a [u8], arena: &mut Arena) -> Option<Rdata<'a>> {
+    match typ {
+        Type::A => {
+            if raw.len() >= 4 {
+                Some(Rdata::A(&raw[0..4]))
+            } else {
+                None
+            }
+        },
+        Type::NS => {
+            domain_name_in(arena, raw).ok().map(|(_, x)| Rdata::NS(x))
+        },
+        Type::MD => {
+            domain_name_in(arena, raw).ok().map(|(_, x)| Rdata::MD(x))
+        },
+        Type::MF => {
+            domain_name_in(arena, raw).ok().map(|(_, x)| Rdata::MF(x))
+        },
+        Type::Cname => {
+            domain_name_in(arena, raw).ok().map(|(_, x)| Rdata::Cname(x))
+        },
+        Type::SOA => {
+            parse_soa(arena, raw).ok().map(|(_, x)| Rdata::Soa(x))
+        },
+        Type::MB => {
+            domain_name_in(arena, raw).ok().map(|(_, x)| Rdata::MB(x))
+        },
+        Type::MG => {
+            domain_name_in(arena, raw).ok().map(|(_, x)| Rdata::MG(x))
+        },
+        Type::MR => {
+            domain_name_in(arena, raw).ok().map(|(_, x)| Rdata::MR(x))
+        },
+        Type::Null => {
+            Some(Rdata::Null(raw))
+        },
+        Type::WKS => {
+            parse_wks(raw).ok().map(|(_, x)| Rdata::Wks(x))
+        },
+        Type::Ptr => {
+            domain_name_in(arena, raw).ok().map(|(_, x)| Rdata::Ptr(x))
+        },
+        Type::Hinfo => {
+            hinfo(raw).ok().map(|(_, x)| Rdata::Hinfo(x))
+        },
+        Type::Minfo => {
+            minfo(arena, raw).ok().map(|(_, x)| Rdata::Minfo(x))
+        },
+        Type::MX => {
+            parse_mx(arena, raw).ok().map(|(_, x)| Rdata::MX(x))
+        },
+        Type::SRV => {
+            parse_srv(arena, raw).ok().map(|(_, x)| Rdata::Srv(x))
+        },
+        Type::Txt => {
+            parse_txt(arena, raw).ok().map(|(_, x)| Rdata::Txt(x))
+        },
+        Type::AAAA => {
+            if raw.len() >= 16 {
+                Some(Rdata::AAAA(&raw[0..16]))
+                /* Some(Rdata::AAAA([
+                    raw[15], raw[14], raw[13], raw[12],
+                    raw[11], raw[10], raw[9], raw[8],
+                    raw[7], raw[6], raw[5], raw[4],
+                    raw[3], raw[2], raw[1], raw[0],
+                ])) */
+            } else {
+                None
+            }
+        },
+        Type::OPT => {
+            all_consuming(|bs| parse_opt(arena, bs))(raw).ok().map(|(_, x)| Rdata::Opt(x))
+        },
+        Type::Unknown(_) => Some(Rdata::Unknown(raw)),
+    }
+}
+
+impl <'a> ToBytes for Rdata<'a> {
+    fn write_with(&self, fixup: Fixup, out: &mut Vec<u8>) {
+        match *self {
+            Rdata::Cname(ref name) | Rdata::MB(ref name) | Rdata::MD(ref name) | Rdata::MF(ref name)
+                | Rdata::MG(ref name) | Rdata::MR(ref name) | Rdata::NS(ref name)
+                | Rdata::Ptr(ref name) => name.write_with(fixup, out),
+            Rdata::Hinfo(ref hinfo) => {
+                hinfo.cpu.write_with(fixup, out);
+                hinfo.os.write_with(fixup, out);
+            },
+            Rdata::Minfo(ref minfo) => {
+                minfo.rmailbox.write_with(fixup, out);
+                minfo.emailbox.write_with(fixup, out);
+            },
+            Rdata::MX(ref mx) => {
+                out.extend_from_slice(&mx.preference.to_be_bytes());
+                mx.exchange.write_with(fixup, out);
+            },
+            Rdata::Srv(ref srv) => {
+                for v in &[srv.priority, srv.weight, srv.port] {
+                    out.extend_from_slice(&v.to_be_bytes());
+                }
+                srv.target.write_with(fixup, out);
+            },
+            Rdata::Soa(ref soa) => {
+                soa.mname.write_with(fixup, out);
+                soa.rname.write_with(fixup, out);
+                for v in &[soa.serial, soa.refresh, soa.retry, soa.expire, soa.minimum] {
+                    out.extend_from_slice(&v.to_be_bytes());
+                }
+            },
+            Rdata::Txt(ref strings) => {
+                for s in strings {
+                    s.write_with(fixup, out);
+                }
+            },
+            Rdata::Wks(ref wks) => {
+                // the parser stores the address backwards
+                let a = wks.address;
+                out.extend_from_slice(&[a[3], a[2], a[1], a[0], wks.protocol]);
+                out.extend_from_slice(wks.bitmap);
+            },
+            Rdata::Opt(ref options) => {
+                for option in options {
+                    option.write_with(fixup, out);
+                }
+            },
+            Rdata::Null(bs) | Rdata::A(bs) | Rdata::AAAA(bs) | Rdata::Unknown(bs) => out.extend_from_slice(bs),
+        }
+    }
+}
+
+fn hex(f: &mut fmt::Formatter, bs: &[u8]) -> fmt::Result {
+    for b in bs {
+        write!(f, "{:02x}", b)?;
+    }
+    Ok(())
+}
+
+impl <'a> fmt::Display for Rdata<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Rdata::A(bs) if bs.len() == 4 => write!(f, "{}.{}.{}.{}", bs[0], bs[1], bs[2], bs[3]),
+            Rdata::AAAA(bs) if bs.len() == 16 => write!(f, "{}", ::ipv6::slice2addr(bs)),
+            Rdata::Cname(ref name) | Rdata::MB(ref name) | Rdata::MD(ref name) | Rdata::MF(ref name)
+                | Rdata::MG(ref name) | Rdata::MR(ref name) | Rdata::NS(ref name)
+                | Rdata::Ptr(ref name) => write!(f, "{}", name),
+            Rdata::MX(ref mx) => write!(f, "{} {}", mx.preference, mx.exchange),
+            Rdata::Srv(ref srv) => write!(f, "{} {} {} {}", srv.priority, srv.weight, srv.port, srv.target),
+            Rdata::Soa(ref soa) => write!(f, "{} {} {} {} {} {} {}",
+                soa.mname, soa.rname, soa.serial, soa.refresh, soa.retry, soa.expire, soa.minimum),
+            Rdata::Txt(ref strings) => {
+                for (i, s) in strings.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", String::from_utf8_lossy(s.0))?;
+                }
+                Ok(())
+            },
+            Rdata::Hinfo(ref hinfo) => write!(f, "{} {}",
+                String::from_utf8_lossy(hinfo.cpu.0), String::from_utf8_lossy(hinfo.os.0)),
+            Rdata::Minfo(ref minfo) => write!(f, "{} {}", minfo.rmailbox, minfo.emailbox),
+            Rdata::Opt(ref options) => {
+                for (i, option) in options.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", option)?;
+                }
+                Ok(())
+            },
+            Rdata::A(bs) | Rdata::AAAA(bs) | Rdata::Null(bs) | Rdata::Unknown(bs) => hex(f, bs),
+            Rdata::Wks(ref wks) => hex(f, wks.bitmap),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct Hinfo<'a> {
+    pub cpu: CharacterString<'a>,
+    pub os: CharacterString<'a>,
+}
+fn hinfo(bs: &[u8]) -> IResult<'_, Hinfo<'_>> {
+    let (bs, cpu) = parse_char_string(bs)?;
+    let (bs, os) = parse_char_string(bs)?;
+    Ok((bs, Hinfo {
+        cpu,
+        os,
+    }))
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct Minfo<'a> {
+    pub rmailbox: DomainName<'a>,
+    pub emailbox: DomainName<'a>,
+}
+fn minfo<'a>(arena: &mut Arena, bs: &'a [u8]) -> IResult<'a, Minfo<'a>> {
+    let (bs, rbox) = domain_name_in(arena, bs)?;
+    let (bs, ebox) = domain_name_in(arena, bs)?;
+    Ok((bs, Minfo {
+        rmailbox: rbox,
+        emailbox: ebox,
+    }))
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct MX<'a> {
+    pub preference: u16,
+    pub exchange: DomainName<'a>,
+}
+fn parse_mx<'a>(arena: &mut Arena, bs: &'a [u8]) -> IResult<'a, MX<'a>> {
+    let (bs, preference) = be_u16(bs)?;
+    let (bs, exchange) = domain_name_in(arena, bs)?;
+    Ok((bs, MX {
+        preference,
+        exchange,
+    }))
+}
+
+/// https://tools.ietf.org/html/rfc2782
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct Srv<'a> {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: DomainName<'a>,
+}
+fn parse_srv<'a>(arena: &mut Arena, bs: &'a [u8]) -> IResult<'a, Srv<'a>> {
+    let (bs, priority) = be_u16(bs)?;
+    let (bs, weight) = be_u16(bs)?;
+    let (bs, port) = be_u16(bs)?;
+    let (bs, target) = domain_name_in(arena, bs)?;
+    Ok((bs, Srv {
+        priority,
+        weight,
+        port,
+        target,
+    }))
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct Soa<'a> {
+    pub mname: DomainName<'a>,
+    pub rname: DomainName<'a>,
+    pub serial: u32,
+    pub refresh: u32,
+    pub retry: u32,
+    pub expire: u32,
+    pub minimum: u32,
+}
+fn parse_soa<'a>(arena: &mut Arena, bs: &'a [u8]) -> IResult<'a, Soa<'a>> {
+    let (bs, mname) = domain_name_in(arena, bs)?;
+    let (bs, rname) = domain_name_in(arena, bs)?;
+    let (bs, serial) = be_u32(bs)?;
+    let (bs, refresh) = be_u32(bs)?;
+    let (bs, retry) = be_u32(bs)?;
+    let (bs, expire) = be_u32(bs)?;
+    let (bs, minimum) = be_u32(bs)?;
+    Ok((bs, Soa {
+        mname,
+        rname,
+        serial,
+        refresh,
+        retry,
+        expire,
+        minimum,
+    }))
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct CharacterString<'a>(&'a [u8]);
+
+impl <'a> CharacterString<'a> {
+    /// `None` if `bs` is too long for the length byte
+    pub fn new(bs: &'a [u8]) -> Option<CharacterString<'a>> {
+        if bs.len() <= 0xff {
+            Some(CharacterString(bs))
+        } else {
+            None
+        }
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.0
+    }
+}
+
+impl <'a> ToBytes for CharacterString<'a> {
+    fn write_with(&self, _fixup: Fixup, out: &mut Vec<u8>) {
+        out.push(self.0.len() as u8);
+        out.extend_from_slice(self.0);
+    }
+}
+
+fn parse_char_string(bs: &[u8]) -> IResult<'_, CharacterString<'_>> {
+    let (bs, len) = be_u8(bs)?;
+    map(take(len), CharacterString)(bs)
+}
+
+// many1, into a Vec from `arena`
+fn parse_txt<'a>(arena: &mut Arena, mut bs: &'a [u8]) -> IResult<'a, Vec<CharacterString<'a>>> {
+    let mut strings = arena.strings();
+    loop {
+        match parse_char_string(bs) {
+            Ok((rest, string)) => {
+                strings.push(string);
+                bs = rest;
+            },
+            Err(nom::Err::Error(_)) if !strings.is_empty() => return Ok((bs, strings)),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct Wks<'a> {
+    pub address: [u8; 4],
+    pub protocol: u8,
+    pub bitmap: &'a [u8],
+}
+fn parse_wks(bs: &[u8]) -> IResult<'_, Wks<'_>> {
+    let (bs, address) = take(4usize)(bs)?;
+    let (bs, protocol) = be_u8(bs)?;
+    let (bs, bitmap) = rest(bs)?;
+    Ok((bs, Wks {
+        address: [address[3], address[2], address[1], address[0]],
+        protocol,
+        bitmap,
+    }))
+}
+
+/// An option in an OPT record, RFC 6891
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub enum EdnsOption<'a> {
+    /// RFC 5001, the server's name for itself, so anycast nodes can be told
+    /// apart. It's empty in queries asking for it.
+    Nsid(&'a [u8]),
+    Cookie(Cookie<'a>),
+    /// RFC 7830, filler to hide the length of encrypted messages. It should
+    /// be zeros, but is kept as it came.
+    Padding(&'a [u8]),
+    ExtendedError(ExtendedError<'a>),
+    Unknown(u16, &'a [u8]),
+}
+
+impl <'a> EdnsOption<'a> {
+    /// `None` if `data` isn't right for the option `code` names
+    pub fn from(code: u16, data: &'a [u8]) -> Option<EdnsOption<'a>> {
+        match code {
+            3 => Some(EdnsOption::Nsid(data)),
+            10 => {
+                let client = <[u8; 8]>::try_from(data.get(..8)?).ok()?;
+                let server = if data.len() > 8 { Some(&data[8..]) } else { None };
+                Cookie::new(client, server).map(EdnsOption::Cookie)
+            },
+            12 => Some(EdnsOption::Padding(data)),
+            15 => {
+                let info_code = u16::from_be_bytes([*data.first()?, *data.get(1)?]);
+                Some(EdnsOption::ExtendedError(ExtendedError {
+                    info_code: InfoCode::from(info_code),
+                    extra_text: &data[2..],
+                }))
+            },
+            _ => Some(EdnsOption::Unknown(code, data)),
+        }
+    }
+
+    pub fn code(&self) -> u16 {
+        match *self {
+            EdnsOption::Nsid(_) => 3,
+            EdnsOption::Cookie(_) => 10,
+            EdnsOption::Padding(_) => 12,
+            EdnsOption::ExtendedError(_) => 15,
+            EdnsOption::Unknown(code, _) => code,
+        }
+    }
+}
+
+impl <'a> ToBytes for EdnsOption<'a> {
+    fn write_with(&self, _fixup: Fixup, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.code().to_be_bytes());
+        let len = out.len();
+        out.extend_from_slice(&[0, 0]);
+        match *self {
+            EdnsOption::Cookie(ref cookie) => {
+                out.extend_from_slice(&cookie.client);
+                out.extend_from_slice(cookie.server.unwrap_or(&[]));
+            },
+            EdnsOption::ExtendedError(ref ede) => {
+                out.extend_from_slice(&ede.info_code.to_u16().to_be_bytes());
+                out.extend_from_slice(ede.extra_text);
+            },
+            EdnsOption::Nsid(data) | EdnsOption::Padding(data)
+                | EdnsOption::Unknown(_, data) => out.extend_from_slice(data),
+        }
+        let data_len = (out.len() - len - 2) as u16;
+        out[len..len + 2].copy_from_slice(&data_len.to_be_bytes());
+    }
+}
+
+impl <'a> fmt::Display for EdnsOption<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EdnsOption::Nsid([]) => write!(f, "NSID"),
+            EdnsOption::Nsid(data) => {
+                write!(f, "NSID ")?;
+                hex(f, data)?;
+                write!(f, " ({:?})", String::from_utf8_lossy(data))
+            },
+            EdnsOption::Cookie(ref cookie) => {
+                write!(f, "COOKIE ")?;
+                hex(f, &cookie.client)?;
+                if let Some(server) = cookie.server {
+                    write!(f, " ")?;
+                    hex(f, server)?;
+                }
+                Ok(())
+            },
+            EdnsOption::Padding(data) => write!(f, "PADDING {}", data.len()),
+            EdnsOption::ExtendedError(ref ede) if ede.extra_text.is_empty() => write!(f, "EDE {:?}", ede.info_code),
+            EdnsOption::ExtendedError(ref ede) => {
+                write!(f, "EDE {:?} {}", ede.info_code, String::from_utf8_lossy(ede.extra_text))
+            },
+            EdnsOption::Unknown(code, data) => {
+                write!(f, "{} ", code)?;
+                hex(f, data)
+            },
+        }
+    }
 }
 
-named!(parse_type<Type>,
-    map_opt!(
-        be_u16,
-        Type::from
-    )
-);
+fn edns_option(bs: &[u8]) -> IResult<'_, EdnsOption<'_>> {
+    let (bs, code) = be_u16(bs)?;
+    let (bs, len) = be_u16(bs)?;
+    map_opt(take(len), move |data| EdnsOption::from(code, data))(bs)
+}
 
+// many0, into a Vec from `arena`
+fn parse_opt<'a>(arena: &mut Arena, mut bs: &'a [u8]) -> IResult<'a, Vec<EdnsOption<'a>>> {
+    let mut options = arena.options();
+    while !bs.is_empty() {
+        let (rest, option) = edns_option(bs)?;
+        options.push(option);
+        bs = rest;
+    }
+    Ok((bs, options))
+}
 
+/// DNS cookies, RFC 7873. The client sends its own cookie with each query,
+/// and the server's once it's been given one.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub enum Class {
-    IN,
-    CS,
-    CH,
-    HS,
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct Cookie<'a> {
+    pub client: [u8; 8],
+    /// 8 to 32 bytes
+    pub server: Option<&'a [u8]>,
 }
 
-impl Class {
-    pub fn from(v: u16) -> Option<Class> {
+impl <'a> Cookie<'a> {
+    /// `None` if the server cookie isn't 8 to 32 bytes long
+    pub fn new(client: [u8; 8], server: Option<&'a [u8]>) -> Option<Cookie<'a>> {
+        match server {
+            Some(server) if server.len() < 8 || server.len() > 32 => None,
+            _ => Some(Cookie { client, server }),
+        }
+    }
+}
+
+/// Extended DNS Errors, RFC 8914: why a resolver answered the way it did,
+/// beyond what the rcode says
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct ExtendedError<'a> {
+    pub info_code: InfoCode,
+    /// Meant to be UTF-8 for people to read, and often empty
+    pub extra_text: &'a [u8],
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub enum InfoCode {
+    OtherError,
+    UnsupportedDnskeyAlgorithm,
+    UnsupportedDsDigestType,
+    StaleAnswer,
+    ForgedAnswer,
+    DnssecIndeterminate,
+    DnssecBogus,
+    SignatureExpired,
+    SignatureNotYetValid,
+    DnskeyMissing,
+    RrsigsMissing,
+    NoZoneKeyBitSet,
+    NsecMissing,
+    CachedError,
+    NotReady,
+    Blocked,
+    Censored,
+    Filtered,
+    Prohibited,
+    StaleNxdomainAnswer,
+    NotAuthoritative,
+    NotSupported,
+    NoReachableAuthority,
+    NetworkError,
+    InvalidData,
+    Unassigned(u16),
+}
+
+impl InfoCode {
+    pub fn from(v: u16) -> InfoCode {
         match v {
-            1 => Some(Class::IN),
-            2 => Some(Class::CS),
-            3 => Some(Class::CH),
-            4 => Some(Class::HS),
-            _ => None,
+            0 => InfoCode::OtherError,
+            1 => InfoCode::UnsupportedDnskeyAlgorithm,
+            2 => InfoCode::UnsupportedDsDigestType,
+            3 => InfoCode::StaleAnswer,
+            4 => InfoCode::ForgedAnswer,
+            5 => InfoCode::DnssecIndeterminate,
+            6 => InfoCode::DnssecBogus,
+            7 => InfoCode::SignatureExpired,
+            8 => InfoCode::SignatureNotYetValid,
+            9 => InfoCode::DnskeyMissing,
+            10 => InfoCode::RrsigsMissing,
+            11 => InfoCode::NoZoneKeyBitSet,
+            12 => InfoCode::NsecMissing,
+            13 => InfoCode::CachedError,
+            14 => InfoCode::NotReady,
+            15 => InfoCode::Blocked,
+            16 => InfoCode::Censored,
+            17 => InfoCode::Filtered,
+            18 => InfoCode::Prohibited,
+            19 => InfoCode::StaleNxdomainAnswer,
+            20 => InfoCode::NotAuthoritative,
+            21 => InfoCode::NotSupported,
+            22 => InfoCode::NoReachableAuthority,
+            23 => InfoCode::NetworkError,
+            24 => InfoCode::InvalidData,
+            v => InfoCode::Unassigned(v),
+        }
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        match *self {
+            InfoCode::OtherError => 0,
+            InfoCode::UnsupportedDnskeyAlgorithm => 1,
+            InfoCode::UnsupportedDsDigestType => 2,
+            InfoCode::StaleAnswer => 3,
+            InfoCode::ForgedAnswer => 4,
+            InfoCode::DnssecIndeterminate => 5,
+            InfoCode::DnssecBogus => 6,
+            InfoCode::SignatureExpired => 7,
+            InfoCode::SignatureNotYetValid => 8,
+            InfoCode::DnskeyMissing => 9,
+            InfoCode::RrsigsMissing => 10,
+            InfoCode::NoZoneKeyBitSet => 11,
+            InfoCode::NsecMissing => 12,
+            InfoCode::CachedError => 13,
+            InfoCode::NotReady => 14,
+            InfoCode::Blocked => 15,
+            InfoCode::Censored => 16,
+            InfoCode::Filtered => 17,
+            InfoCode::Prohibited => 18,
+            InfoCode::StaleNxdomainAnswer => 19,
+            InfoCode::NotAuthoritative => 20,
+            InfoCode::NotSupported => 21,
+            InfoCode::NoReachableAuthority => 22,
+            InfoCode::NetworkError => 23,
+            InfoCode::InvalidData => 24,
+            InfoCode::Unassigned(v) => v,
         }
     }
 }
 
-named!(parse_class<Class>,
-    map_opt!(
-        be_u16,
-        Class::from
-    )
-);
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedMessage {
+    pub header: Header,
+    pub questions: Vec<OwnedQuery>,
+    pub answers: Vec<OwnedResourceRecord>,
+    pub authorities: Vec<OwnedResourceRecord>,
+    pub additional: Vec<OwnedResourceRecord>,
+}
 
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedQuery {
+    pub qname: OwnedDomainName,
+    pub qtype: Qtype,
+    pub qclass: Qclass,
+}
 
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub enum Rdata<'a> {
-    Cname(DomainName<'a>),
-    Hinfo(Hinfo<'a>),
-    MB(DomainName<'a>),
-    MD(DomainName<'a>),
-    MF(DomainName<'a>),
-    MG(DomainName<'a>),
-    Minfo(Minfo<'a>),
-    MR(DomainName<'a>),
-    MX(MX<'a>),
-    Null(&'a [u8]),
-    NS(DomainName<'a>),
-    Ptr(DomainName<'a>),
-    Soa(Soa<'a>),
-    Txt(Vec<CharacterString<'a>>),
-    A(&'a [u8]),
-    Wks(Wks<'a>),
-    AAAA(&'a [u8]),
-    Unknown(&'a [u8]),
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OwnedDomainName {
+    Labels(Vec<Vec<u8>>),
+    Pointer(u16),
+    LabelWithPointer(Vec<Vec<u8>>, u16),
 }
 
-impl <'a> Rdata<'a> {
-    pub fn from(typ: Type, raw: &'a [u8]) -> Option<Rdata<'a>> {
-        match typ {
-            Type::A => {
-                if raw.len() >= 4 {
-                    Some(Rdata::A(&raw[0..4]))
-                } else {
-                    None
-                }
-            },
-            Type::NS => {
-                domain_name(raw)
-                    .to_result()
-                    .ok()
-                    .map(Rdata::NS)
-            },
-            Type::MD => {
-                domain_name(raw)
-                    .to_result()
-                    .ok()
-                    .map(Rdata::MD)
-            },
-            Type::MF => {
-                domain_name(raw)
-                    .to_result()
-                    .ok()
-                    .map(Rdata::MF)
-            },
-            Type::Cname => {
-                domain_name(raw)
-                    .to_result()
-                    .ok()
-                    .map(Rdata::Cname)
-            },
-            Type::SOA => {
-                parse_soa(raw)
-                    .to_result()
-                    .ok()
-                    .map(Rdata::Soa)
-            },
-            Type::MB => {
-                domain_name(raw)
-                    .to_result()
-                    .ok()
-                    .map(Rdata::MB)
-            },
-            Type::MG => {
-                domain_name(raw)
-                    .to_result()
-                    .ok()
-                    .map(Rdata::MG)
-            },
-            Type::MR => {
-                domain_name(raw)
-                    .to_result()
-                    .ok()
-                    .map(Rdata::MR)
-            },
-            Type::Null => {
-                Some(Rdata::Null(raw))
-            },
-            Type::WKS => {
-                parse_wks(raw)
-                    .to_result()
-                    .ok()
-                    .map(Rdata::Wks)
-            },
-            Type::Ptr => {
-                domain_name(raw)
-                    .to_result()
-                    .ok()
-                    .map(Rdata::Ptr)
-            },
-            Type::Hinfo => {
-                hinfo(raw)
-                    .to_result()
-                    .ok()
-                    .map(Rdata::Hinfo)
-            },
-            Type::Minfo => {
-                minfo(raw)
-                    .to_result()
-                    .ok()
-                    .map(Rdata::Minfo)
-            },
-            Type::MX => {
-                parse_mx(raw)
-                    .to_result()
-                    .ok()
-                    .map(Rdata::MX)
-            },
-            Type::Txt => {
-                parse_txt(raw)
-                    .to_result()
-                    .ok()
-                    .map(Rdata::Txt)
-            },
-            Type::AAAA => {
-                if raw.len() >= 16 {
-                    Some(Rdata::AAAA(&raw[0..16]))
-                    /* Some(Rdata::AAAA([
-                        raw[15], raw[14], raw[13], raw[12],
-                        raw[11], raw[10], raw[9], raw[8],
-                        raw[7], raw[6], raw[5], raw[4],
-                        raw[3], raw[2], raw[1], raw[0],
-                    ])) */
-                } else {
-                    None
-                }
-            },
-        }
-    }
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedResourceRecord {
+    pub name: OwnedDomainName,
+    pub typ: Type,
+    pub class: Class,
+    pub ttl: u32,
+    pub rdata: OwnedRdata,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Hinfo<'a> {
-    pub cpu: CharacterString<'a>,
-    pub os: CharacterString<'a>,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OwnedRdata {
+    Cname(OwnedDomainName),
+    Hinfo(OwnedHinfo),
+    MB(OwnedDomainName),
+    MD(OwnedDomainName),
+    MF(OwnedDomainName),
+    MG(OwnedDomainName),
+    Minfo(OwnedMinfo),
+    MR(OwnedDomainName),
+    MX(OwnedMX),
+    Srv(OwnedSrv),
+    Null(Vec<u8>),
+    NS(OwnedDomainName),
+    Ptr(OwnedDomainName),
+    Soa(OwnedSoa),
+    Txt(Vec<OwnedCharacterString>),
+    A(Vec<u8>),
+    Wks(OwnedWks),
+    AAAA(Vec<u8>),
+    Opt(Vec<OwnedEdnsOption>),
+    Unknown(Vec<u8>),
 }
-named!(hinfo<Hinfo>,
-    do_parse!(
-        cpu: parse_char_string >>
-        os: parse_char_string >>
-        (Hinfo {
-            cpu: cpu,
-            os: os,
-        })
-    )
-);
 
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Minfo<'a> {
-    pub rmailbox: DomainName<'a>,
-    pub emailbox: DomainName<'a>,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedHinfo {
+    pub cpu: OwnedCharacterString,
+    pub os: OwnedCharacterString,
 }
-named!(minfo<Minfo>,
-    do_parse!(
-        rbox: domain_name >>
-        ebox: domain_name >>
-        (Minfo {
-            rmailbox: rbox,
-            emailbox: ebox,
-        })
-    )
-);
 
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct MX<'a> {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedMinfo {
+    pub rmailbox: OwnedDomainName,
+    pub emailbox: OwnedDomainName,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedMX {
     pub preference: u16,
-    pub exchange: DomainName<'a>,
+    pub exchange: OwnedDomainName,
 }
-named!(parse_mx<MX>,
-    do_parse!(
-        preference: be_u16 >>
-        exchange: domain_name >>
-        (MX {
-            preference: preference,
-            exchange: exchange,
-        })
-    )
-);
 
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Soa<'a> {
-    pub mname: DomainName<'a>,
-    pub rname: DomainName<'a>,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedSrv {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: OwnedDomainName,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedSoa {
+    pub mname: OwnedDomainName,
+    pub rname: OwnedDomainName,
     pub serial: u32,
     pub refresh: u32,
     pub retry: u32,
     pub expire: u32,
     pub minimum: u32,
 }
-named!(parse_soa<Soa>,
-    do_parse!(
-        mname: domain_name >>
-        rname: domain_name >>
-        serial: be_u32 >>
-        refresh: be_u32 >>
-        retry: be_u32 >>
-        expire: be_u32 >>
-        minimum: be_u32 >>
-        (Soa {
-            mname: mname,
-            rname: rname,
-            serial: serial,
-            refresh: refresh,
-            retry: retry,
-            expire: expire,
-            minimum: minimum,
-        })
-    )
-);
 
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct CharacterString<'a>(&'a [u8]);
-named!(parse_char_string<CharacterString>,
-    do_parse!(
-        len: be_u8 >>
-        string: take!(len as usize) >>
-        (CharacterString(string))
-    )
-);
-
-named!(parse_txt< Vec<CharacterString> >,
-    many1!(parse_char_string)
-);
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedCharacterString(Vec<u8>);
+
+impl OwnedCharacterString {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
 
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Wks<'a> {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedWks {
     pub address: [u8; 4],
     pub protocol: u8,
-    pub bitmap: &'a [u8],
+    pub bitmap: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OwnedEdnsOption {
+    Nsid(Vec<u8>),
+    Cookie(OwnedCookie),
+    Padding(Vec<u8>),
+    ExtendedError(OwnedExtendedError),
+    Unknown(u16, Vec<u8>),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedExtendedError {
+    pub info_code: InfoCode,
+    pub extra_text: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedCookie {
+    pub client: [u8; 8],
+    pub server: Option<Vec<u8>>,
+}
+
+impl <'a> IntoOwned for Message<'a> {
+    type Owned = OwnedMessage;
+
+    fn into_owned(self) -> OwnedMessage {
+        OwnedMessage {
+            header: self.header,
+            questions: self.questions.into_owned(),
+            answers: self.answers.into_owned(),
+            authorities: self.authorities.into_owned(),
+            additional: self.additional.into_owned(),
+        }
+    }
+}
+
+impl <'a> IntoOwned for Query<'a> {
+    type Owned = OwnedQuery;
+
+    fn into_owned(self) -> OwnedQuery {
+        OwnedQuery {
+            qname: self.qname.into_owned(),
+            qtype: self.qtype,
+            qclass: self.qclass,
+        }
+    }
+}
+
+impl <'a> IntoOwned for DomainName<'a> {
+    type Owned = OwnedDomainName;
+
+    fn into_owned(self) -> OwnedDomainName {
+        match self {
+            DomainName::Labels(labels) => OwnedDomainName::Labels(labels.into_owned()),
+            DomainName::Pointer(off) => OwnedDomainName::Pointer(off),
+            DomainName::LabelWithPointer(labels, off) => OwnedDomainName::LabelWithPointer(labels.into_owned(), off),
+            name @ DomainName::Resolved(..) => OwnedDomainName::Labels(name.labels().map(<[u8]>::to_vec).collect()),
+        }
+    }
+}
+
+impl <'a> IntoOwned for ResourceRecord<'a> {
+    type Owned = OwnedResourceRecord;
+
+    fn into_owned(self) -> OwnedResourceRecord {
+        OwnedResourceRecord {
+            name: self.name.into_owned(),
+            typ: self.typ,
+            class: self.class,
+            ttl: self.ttl,
+            rdata: self.rdata.into_owned(),
+        }
+    }
+}
+
+impl <'a> IntoOwned for Rdata<'a> {
+    type Owned = OwnedRdata;
+
+    fn into_owned(self) -> OwnedRdata {
+        match self {
+            Rdata::Cname(name) => OwnedRdata::Cname(name.into_owned()),
+            Rdata::Hinfo(hinfo) => OwnedRdata::Hinfo(OwnedHinfo {
+                cpu: hinfo.cpu.into_owned(),
+                os: hinfo.os.into_owned(),
+            }),
+            Rdata::MB(name) => OwnedRdata::MB(name.into_owned()),
+            Rdata::MD(name) => OwnedRdata::MD(name.into_owned()),
+            Rdata::MF(name) => OwnedRdata::MF(name.into_owned()),
+            Rdata::MG(name) => OwnedRdata::MG(name.into_owned()),
+            Rdata::Minfo(minfo) => OwnedRdata::Minfo(OwnedMinfo {
+                rmailbox: minfo.rmailbox.into_owned(),
+                emailbox: minfo.emailbox.into_owned(),
+            }),
+            Rdata::MR(name) => OwnedRdata::MR(name.into_owned()),
+            Rdata::MX(mx) => OwnedRdata::MX(OwnedMX {
+                preference: mx.preference,
+                exchange: mx.exchange.into_owned(),
+            }),
+            Rdata::Srv(srv) => OwnedRdata::Srv(OwnedSrv {
+                priority: srv.priority,
+                weight: srv.weight,
+                port: srv.port,
+                target: srv.target.into_owned(),
+            }),
+            Rdata::Null(data) => OwnedRdata::Null(data.into_owned()),
+            Rdata::NS(name) => OwnedRdata::NS(name.into_owned()),
+            Rdata::Ptr(name) => OwnedRdata::Ptr(name.into_owned()),
+            Rdata::Soa(soa) => OwnedRdata::Soa(OwnedSoa {
+                mname: soa.mname.into_owned(),
+                rname: soa.rname.into_owned(),
+                serial: soa.serial,
+                refresh: soa.refresh,
+                retry: soa.retry,
+                expire: soa.expire,
+                minimum: soa.minimum,
+            }),
+            Rdata::Txt(strings) => OwnedRdata::Txt(strings.into_owned()),
+            Rdata::A(addr) => OwnedRdata::A(addr.into_owned()),
+            Rdata::Wks(wks) => OwnedRdata::Wks(OwnedWks {
+                address: wks.address,
+                protocol: wks.protocol,
+                bitmap: wks.bitmap.into_owned(),
+            }),
+            Rdata::AAAA(addr) => OwnedRdata::AAAA(addr.into_owned()),
+            Rdata::Opt(options) => OwnedRdata::Opt(options.into_owned()),
+            Rdata::Unknown(data) => OwnedRdata::Unknown(data.into_owned()),
+        }
+    }
+}
+
+impl <'a> IntoOwned for EdnsOption<'a> {
+    type Owned = OwnedEdnsOption;
+
+    fn into_owned(self) -> OwnedEdnsOption {
+        match self {
+            EdnsOption::Nsid(data) => OwnedEdnsOption::Nsid(data.into_owned()),
+            EdnsOption::Cookie(cookie) => OwnedEdnsOption::Cookie(OwnedCookie {
+                client: cookie.client,
+                server: cookie.server.map(<[u8]>::to_vec),
+            }),
+            EdnsOption::Padding(data) => OwnedEdnsOption::Padding(data.into_owned()),
+            EdnsOption::ExtendedError(ede) => OwnedEdnsOption::ExtendedError(OwnedExtendedError {
+                info_code: ede.info_code,
+                extra_text: ede.extra_text.into_owned(),
+            }),
+            EdnsOption::Unknown(code, data) => OwnedEdnsOption::Unknown(code, data.into_owned()),
+        }
+    }
+}
+
+impl <'a> IntoOwned for CharacterString<'a> {
+    type Owned = OwnedCharacterString;
+
+    fn into_owned(self) -> OwnedCharacterString {
+        OwnedCharacterString(self.0.into_owned())
+    }
+}
+
+impl OwnedDomainName {
+    /// A borrowed view, for building messages out of stored names
+    pub fn to_borrowed(&self) -> DomainName<'_> {
+        fn labels(labels: &[Vec<u8>]) -> List<Label<'_>> {
+            labels.iter().map(|label| &label[..]).collect()
+        }
+        match *self {
+            OwnedDomainName::Labels(ref l) => DomainName::Labels(labels(l)),
+            OwnedDomainName::Pointer(off) => DomainName::Pointer(off),
+            OwnedDomainName::LabelWithPointer(ref l, off) => DomainName::LabelWithPointer(labels(l), off),
+        }
+    }
+}
+
+impl OwnedCharacterString {
+    pub fn to_borrowed(&self) -> CharacterString<'_> {
+        CharacterString(&self.0)
+    }
+}
+
+impl OwnedRdata {
+    /// A borrowed view, for building messages out of stored records
+    pub fn to_borrowed(&self) -> Rdata<'_> {
+        match *self {
+            OwnedRdata::Cname(ref name) => Rdata::Cname(name.to_borrowed()),
+            OwnedRdata::Hinfo(ref hinfo) => Rdata::Hinfo(Hinfo {
+                cpu: hinfo.cpu.to_borrowed(),
+                os: hinfo.os.to_borrowed(),
+            }),
+            OwnedRdata::MB(ref name) => Rdata::MB(name.to_borrowed()),
+            OwnedRdata::MD(ref name) => Rdata::MD(name.to_borrowed()),
+            OwnedRdata::MF(ref name) => Rdata::MF(name.to_borrowed()),
+            OwnedRdata::MG(ref name) => Rdata::MG(name.to_borrowed()),
+            OwnedRdata::Minfo(ref minfo) => Rdata::Minfo(Minfo {
+                rmailbox: minfo.rmailbox.to_borrowed(),
+                emailbox: minfo.emailbox.to_borrowed(),
+            }),
+            OwnedRdata::MR(ref name) => Rdata::MR(name.to_borrowed()),
+            OwnedRdata::MX(ref mx) => Rdata::MX(MX {
+                preference: mx.preference,
+                exchange: mx.exchange.to_borrowed(),
+            }),
+            OwnedRdata::Srv(ref srv) => Rdata::Srv(Srv {
+                priority: srv.priority,
+                weight: srv.weight,
+                port: srv.port,
+                target: srv.target.to_borrowed(),
+            }),
+            OwnedRdata::Null(ref data) => Rdata::Null(data),
+            OwnedRdata::NS(ref name) => Rdata::NS(name.to_borrowed()),
+            OwnedRdata::Ptr(ref name) => Rdata::Ptr(name.to_borrowed()),
+            OwnedRdata::Soa(ref soa) => Rdata::Soa(Soa {
+                mname: soa.mname.to_borrowed(),
+                rname: soa.rname.to_borrowed(),
+                serial: soa.serial,
+                refresh: soa.refresh,
+                retry: soa.retry,
+                expire: soa.expire,
+                minimum: soa.minimum,
+            }),
+            OwnedRdata::Txt(ref strings) => Rdata::Txt(strings.iter().map(|s| s.to_borrowed()).collect()),
+            OwnedRdata::A(ref addr) => Rdata::A(addr),
+            OwnedRdata::Wks(ref wks) => Rdata::Wks(Wks {
+                address: wks.address,
+                protocol: wks.protocol,
+                bitmap: &wks.bitmap,
+            }),
+            OwnedRdata::AAAA(ref addr) => Rdata::AAAA(addr),
+            OwnedRdata::Opt(ref options) => Rdata::Opt(options.iter().map(|o| o.to_borrowed()).collect()),
+            OwnedRdata::Unknown(ref data) => Rdata::Unknown(data),
+        }
+    }
+}
+
+impl OwnedEdnsOption {
+    pub fn to_borrowed(&self) -> EdnsOption<'_> {
+        match *self {
+            OwnedEdnsOption::Nsid(ref data) => EdnsOption::Nsid(data),
+            OwnedEdnsOption::Cookie(ref cookie) => EdnsOption::Cookie(Cookie {
+                client: cookie.client,
+                server: cookie.server.as_deref(),
+            }),
+            OwnedEdnsOption::Padding(ref data) => EdnsOption::Padding(data),
+            OwnedEdnsOption::ExtendedError(ref ede) => EdnsOption::ExtendedError(ExtendedError {
+                info_code: ede.info_code,
+                extra_text: &ede.extra_text,
+            }),
+            OwnedEdnsOption::Unknown(code, ref data) => EdnsOption::Unknown(code, data),
+        }
+    }
 }
-named!(parse_wks<Wks>,
-    do_parse!(
-        address: take!(4) >>
-        protocol: be_u8 >>
-        bitmap: rest >>
-        (Wks {
-            address: [address[3], address[2], address[1], address[0]],
-            protocol: protocol,
-            bitmap: bitmap
-        })
-    )
-);
 
 #[cfg(test)]
 mod tests {
-    use nom::IResult;
-
     use super::*;
     #[test]
     fn test_query() {
@@ -855,7 +2681,7 @@ mod tests {
             0x63, 0x6f, 0x6d, 0x00, 0x00, 0x01, 0x00, 0x01];
         assert_eq!(
             parse_dns_message(&query),
-            IResult::Done(&b""[..],
+            Ok((&b""[..],
                 Message {
                     header: Header {
                         id: 9242,
@@ -873,7 +2699,7 @@ mod tests {
                     },
                     questions: vec![
                         Query {
-                            qname: DomainName::Labels(vec![
+                            qname: DomainName::Labels(list![
                                        &[119, 119, 119],
                                        &[103, 111, 111, 103, 108, 101],
                                        &[99, 111, 109]]),
@@ -884,10 +2710,27 @@ mod tests {
                     answers: vec![],
                     authorities: vec![],
                     additional: vec![]
-                })
+                }))
         );
     }
 
+    #[test]
+    fn test_lenient() {
+        // a Z bit set, and a query for type 99 in class 254
+        let query = [
+            0x24, 0x1a, 0x01, 0x40, 0x00, 0x01, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x03, 0x77, 0x77, 0x77,
+            0x06, 0x67, 0x6f, 0x6f, 0x67, 0x6c, 0x65, 0x03,
+            0x63, 0x6f, 0x6d, 0x00, 0x00, 0x63, 0x00, 0xfe];
+        assert!(parse_dns_message(&query).is_err());
+
+        let (rest, msg) = parse_dns_message_with(Mode::Lenient, &query).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(msg.header.rcode, Rcode::NoError);
+        assert_eq!(msg.questions[0].qtype, Qtype::Type(Type::Unknown(99)));
+        assert_eq!(msg.questions[0].qclass, Qclass::Class(Class::Unknown(254)));
+    }
+
     #[test]
     fn test_response() {
         let resp = [
@@ -909,7 +2752,7 @@ mod tests {
 
         assert_eq!(
             parse_dns_message_full(&resp),
-            IResult::Done(
+            Ok((
                 &b""[..],
                 Message {
                     header: Header {
@@ -928,7 +2771,7 @@ mod tests {
                     },
                     questions: vec![
                         Query {
-                            qname: DomainName::Labels(vec![
+                            qname: DomainName::Labels(list![
                                           &[119, 119, 119],
                                           &[103, 111, 111, 103, 108, 101],
                                           &[99, 111, 109]
@@ -939,7 +2782,7 @@ mod tests {
                     ],
                     answers: vec![
                         ResourceRecord {
-                            name: DomainName::Labels(vec![
+                            name: DomainName::Labels(list![
                                          &[119, 119, 119],
                                          &[103, 111, 111, 103, 108, 101],
                                          &[99, 111, 109]
@@ -948,7 +2791,7 @@ mod tests {
                             class: Class::IN,
                             ttl: 337977,
                             rdata: Rdata::Cname(
-                                DomainName::Labels(vec![
+                                DomainName::Labels(list![
                                        &[119, 119, 119],
                                        &[108],
                                        &[103, 111, 111, 103, 108, 101],
@@ -956,7 +2799,7 @@ mod tests {
                                 ]))
                         },
                         ResourceRecord {
-                            name: DomainName::Labels(vec![
+                            name: DomainName::Labels(list![
                                          &[119, 119, 119],
                                          &[108],
                                          &[103, 111, 111, 103, 108, 101],
@@ -968,7 +2811,7 @@ mod tests {
                             rdata: Rdata::A(&[66, 249, 89, 99])
                         },
                         ResourceRecord {
-                            name: DomainName::Labels(vec![
+                            name: DomainName::Labels(list![
                                          &[119, 119, 119],
                                          &[108],
                                          &[103, 111, 111, 103, 108, 101],
@@ -983,7 +2826,7 @@ mod tests {
                     authorities: vec![],
                     additional: vec![]
                 }
-            )
+            ))
         );
     }
 
@@ -1004,7 +2847,7 @@ mod tests {
         ];
         assert_eq!(
             parse_dns_message_full(&msg),
-            IResult::Done(
+            Ok((
                 &b""[..],
                 Message {
                     header: Header {
@@ -1023,7 +2866,7 @@ mod tests {
                     },
                     questions: vec![
                         Query {
-                            qname: DomainName::Labels(vec![
+                            qname: DomainName::Labels(list![
                                           &[97, 110, 100, 114, 111, 105, 100],
                                           &[99, 108, 105, 101, 110, 116, 115],
                                           &[103, 111, 111, 103, 108, 101],
@@ -1034,7 +2877,7 @@ mod tests {
                     ],
                     answers: vec![
                         ResourceRecord {
-                            name: DomainName::Labels(vec![
+                            name: DomainName::Labels(list![
                                                      &[97, 110, 100, 114, 111, 105, 100],
                                                      &[99, 108, 105, 101, 110, 116, 115],
                                                      &[103, 111, 111, 103, 108, 101],
@@ -1042,14 +2885,14 @@ mod tests {
                             typ: Type::Cname,
                             class: Class::IN,
                             ttl: 69,
-                            rdata: Rdata::Cname(DomainName::Labels(vec![
+                            rdata: Rdata::Cname(DomainName::Labels(list![
                                                                    &[97, 110, 100, 114, 111, 105, 100],
                                                                    &[108],
                                                                    &[103, 111, 111, 103, 108, 101],
                                                                    &[99, 111, 109]]))
                         },
                         ResourceRecord {
-                            name: DomainName::Labels(vec![
+                            name: DomainName::Labels(list![
                                                      &[97, 110, 100, 114, 111, 105, 100],
                                                      &[108],
                                                      &[103, 111, 111, 103, 108, 101],
@@ -1062,7 +2905,370 @@ mod tests {
                     ],
                     authorities: vec![],
                     additional: vec![]
-                })
+                }))
         );
     }
+
+    #[test]
+    fn test_label_iter() {
+        let resp = [
+            0x24, 0x1a, 0x81, 0x80, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x03, 0x77, 0x77, 0x77,
+            0x06, 0x67, 0x6f, 0x6f, 0x67, 0x6c, 0x65, 0x03, 0x63, 0x6f, 0x6d, 0x00, 0x00, 0x01, 0x00, 0x01,
+            0x01, 0x6c, 0xc0, 0x10, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0xe3, 0x00, 0x04, 0x42, 0xf9,
+            0x59, 0x63,
+        ];
+        let labels: Vec<_> = LabelIter::new(&resp, 32).collect();
+        assert_eq!(labels, vec![&b"l"[..], &b"google"[..], &b"com"[..]]);
+
+        // pointers have to point backwards
+        let looped = [0xc0, 0x02, 0xc0, 0x00];
+        assert_eq!(LabelIter::new(&looped, 0).count(), 0);
+        assert_eq!(LabelIter::new(&looped, 2).count(), 0);
+    }
+
+    #[test]
+    fn dns_deref_bad_pointers() {
+        // a question pointing at itself, then an answer pointing past the end
+        let msg = [
+            0, 1, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0,
+            192, 12, 0, 1, 0, 1,
+            192, 200, 0, 1, 0, 1, 0, 0, 0, 69, 0, 4, 192, 0, 2, 1,
+        ];
+        let (_, msg) = parse_dns_message_full(&msg).unwrap();
+        assert_eq!(msg.questions[0].qname, DomainName::Pointer(12));
+        assert_eq!(msg.answers[0].name, DomainName::Pointer(200));
+    }
+
+    // the DNS message of a testgen response for www.example.com, past the
+    // Ethernet, IPv4 and UDP headers
+    fn example_response() -> Vec<u8> {
+        let (_, response) = ::testgen::dns_exchange(&::testgen::Endpoint::client(), &::testgen::Endpoint::server(),
+                                                    1, "www.example.com", "93.184.216.34".parse().unwrap());
+        response[42..].to_vec()
+    }
+
+    #[test]
+    fn dns_deref_in_place() {
+        let response = example_response();
+        let (_, msg) = parse_dns_message_full(&response).unwrap();
+        let cname = match msg.answers[0].rdata {
+            Rdata::Cname(ref name) => name,
+            ref other => panic!("unexpected rdata {:?}", other),
+        };
+        match *cname {
+            DomainName::Resolved(ref labels, suffix) => {
+                assert_eq!(labels, &vec![&b"cdn"[..]]);
+                assert_eq!(suffix.labels().collect::<Vec<_>>(), vec![&b"example"[..], &b"com"[..]]);
+            },
+            ref other => panic!("unexpected name {:?}", other),
+        }
+        let labels = DomainName::Labels(list![b"cdn", b"example", b"com"]);
+        assert_eq!(cname, &labels);
+        assert_eq!(cname.clone().into_owned(), labels.into_owned());
+        assert_eq!(cname.wire_len(), 17);
+        assert_eq!(cname.to_bytes(), b"\x03cdn\x07example\x03com\x00");
+    }
+
+    #[test]
+    fn dns_arena() {
+        let response = &example_response();
+        let mut arena = Arena::new();
+        let (_, msg) = parse_dns_message_full_in(Mode::Strict, &mut arena, response).unwrap();
+        assert_eq!(msg, parse_dns_message_full(response).unwrap().1);
+        let answers = msg.answers.as_ptr() as usize;
+        arena.recycle(msg);
+
+        let copy = response.to_vec();
+        let (_, msg) = parse_dns_message_full_in(Mode::Strict, &mut arena, &copy).unwrap();
+        assert_eq!(msg, parse_dns_message_full(response).unwrap().1);
+        assert_eq!(msg.answers.as_ptr() as usize, answers);
+    }
+
+    #[test]
+    fn dns_walk() {
+        let response = &example_response();
+        let (_, msg) = parse_dns_message(response).unwrap();
+        let mut arena = Arena::new();
+        let mut records = vec![];
+        let mut header = None;
+        let (rest, ()) = walk_dns_message(Mode::Strict, &mut arena, response, |entry| {
+            match entry {
+                Entry::Header(h) => header = Some(*h),
+                Entry::Question(q) => assert_eq!(q, &msg.questions[0]),
+                Entry::Record(section, rr) => records.push((section, rr.clone())),
+            }
+            true
+        }).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(header, Some(msg.header));
+        assert_eq!(records, msg.answers.iter().map(|rr| (Section::Answer, rr.clone())).collect::<Vec<_>>());
+
+        // stopping at the question leaves the answers unparsed
+        let mut seen = 0;
+        let (rest, ()) = walk_dns_message(Mode::Strict, &mut arena, response, |entry| {
+            seen += 1;
+            !matches!(entry, Entry::Question(_))
+        }).unwrap();
+        assert_eq!(seen, 2);
+        assert_eq!(rest.len(), response.len() - 12 - msg.questions[0].qname.wire_len() - 4);
+
+        let err = walk_dns_message(Mode::Strict, &mut arena, &response[..response.len() - 1], |_| true).unwrap_err();
+        match err {
+            nom::Err::Error(e) | nom::Err::Failure(e) => assert_eq!(e.context, vec!["dns.answers", "dns"]),
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[test]
+    fn dns_case_matches() {
+        let query = Message::query(1, "wWw.ExaMPle.cOm", Qtype::Type(Type::A));
+        let mut response = query.clone();
+        response.header.qr = QR::Response;
+        let bs = response.to_bytes();
+        let (_, response) = parse_dns_message_full(&bs).unwrap();
+        assert!(case_matches(&query, &response));
+        assert!(!case_matches(&Message::query(1, "www.example.com", Qtype::Type(Type::A)), &response));
+        assert!(!case_matches(&query, &Message::query(1, "wWw.ExaMPle.cOm.net", Qtype::Type(Type::A))));
+    }
+
+    #[test]
+    fn dns_truncated() {
+        let query = Message::query(7, "www.example.com", Qtype::Type(Type::A));
+        assert_eq!(query.header.truncated(), None);
+        let mut response = query.clone();
+        response.header.qr = QR::Response;
+        assert_eq!(response.header.truncated(), None);
+        response.header.tc = true;
+        let bs = response.to_bytes();
+        let (_, response) = parse_dns_message_full(&bs).unwrap();
+        let truncated = response.header.truncated().unwrap();
+        assert_eq!(truncated, Truncated { id: 7 });
+
+        let retry = truncated.tcp_retry(&query).unwrap();
+        let mut decoder = TcpDecoder::default();
+        let (rest, decoded) = decoder.decode(&retry).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(decoded, Some(query.clone().into_owned()));
+        assert_eq!(Truncated { id: 8 }.tcp_retry(&query), None);
+    }
+
+    #[test]
+    fn dns_cookie() {
+        let client = [1, 2, 3, 4, 5, 6, 7, 8];
+        let mut query = Message::query(1, "example.com", Qtype::Type(Type::A));
+        let cookie = Cookie::new(client, None).unwrap();
+        query.additional.push(ResourceRecord::opt(1232, vec![EdnsOption::Cookie(cookie)]));
+        let bs = query.to_bytes();
+        assert_eq!(&bs[bs.len() - 23..], &[
+            0, 0, 41, 4, 208, 0, 0, 0, 0, 0, 12,
+            0, 10, 0, 8, 1, 2, 3, 4, 5, 6, 7, 8,
+        ][..]);
+        let (_, parsed) = parse_dns_message(&bs).unwrap();
+        assert_eq!(parsed.additional[0].class, Class::Unknown(1232));
+        assert_eq!(parsed.cookie(), Some(&cookie));
+        assert_eq!(parsed.into_owned().additional[0].rdata.to_borrowed(), query.additional[0].rdata);
+
+        let server = [9; 16];
+        let raw = [&[0, 10, 0, 24][..], &client, &server].concat();
+        let (_, option) = edns_option(&raw).unwrap();
+        assert_eq!(option, EdnsOption::Cookie(Cookie { client, server: Some(&server) }));
+        assert_eq!(option.to_bytes(), raw);
+        assert_eq!(option.to_string(), "COOKIE 0102030405060708 09090909090909090909090909090909");
+
+        // server cookies are 8 to 32 bytes
+        assert_eq!(Cookie::new(client, Some(&server[..7])), None);
+        assert!(edns_option(&[0, 10, 0, 5, 1, 2, 3, 4, 5]).is_err());
+        let bad = ResourceRecord::opt(512, vec![EdnsOption::Unknown(10, &[1, 2, 3, 4, 5])]);
+        let mut query = Message::query(1, "example.com", Qtype::Type(Type::A));
+        query.additional.push(bad);
+        let bs = query.to_bytes();
+        assert!(parse_dns_message(&bs).is_err());
+        let (_, parsed) = parse_dns_message_with(Mode::Lenient, &bs).unwrap();
+        assert_eq!(parsed.additional[0].rdata, Rdata::Unknown(&[0, 10, 0, 5, 1, 2, 3, 4, 5]));
+    }
+
+    #[test]
+    fn dns_padding() {
+        let query = Message::query(1, "example.com", Qtype::Type(Type::A));
+        let bs = query.to_padded_bytes(QUERY_BLOCK);
+        assert_eq!(bs.len(), QUERY_BLOCK);
+        let (_, parsed) = parse_dns_message(&bs).unwrap();
+        assert_eq!(parsed.additional[0].class, Class::Unknown(512));
+        match parsed.edns_options() {
+            [EdnsOption::Padding(zeros)] => assert!(zeros.iter().all(|&b| b == 0)),
+            other => panic!("unexpected options {:?}", other),
+        }
+
+        // old padding is replaced, and other options kept
+        let mut response = parsed.clone();
+        response.header.qr = QR::Response;
+        if let Rdata::Opt(ref mut options) = response.additional[0].rdata {
+            options.insert(0, EdnsOption::Cookie(Cookie::new([1; 8], None).unwrap()));
+        }
+        let bs = response.to_padded_bytes(RESPONSE_BLOCK);
+        assert_eq!(bs.len(), RESPONSE_BLOCK);
+        let (_, parsed) = parse_dns_message(&bs).unwrap();
+        assert_eq!(parsed.edns_options().len(), 2);
+        assert!(parsed.cookie().is_some());
+
+        // already a multiple of the block size
+        assert_eq!(query.to_padded_bytes(1).len(), query.to_bytes().len() + 15);
+    }
+
+    #[test]
+    fn dns_extended_errors() {
+        // SERVFAIL with DNSSEC Bogus and a Stale Answer explained
+        let mut msg = Message::query(1, "example.com", Qtype::Type(Type::A));
+        msg.header.qr = QR::Response;
+        msg.header.rcode = Rcode::ServerFailure;
+        msg.additional.push(ResourceRecord::opt(1232, vec![
+            EdnsOption::Unknown(15, &[0, 6]),
+            EdnsOption::Unknown(15, b"\x00\x03upstream timed out"),
+        ]));
+        let bs = msg.to_bytes();
+        let (_, parsed) = parse_dns_message(&bs).unwrap();
+        let errors = parsed.extended_errors();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(*errors[0], ExtendedError { info_code: InfoCode::DnssecBogus, extra_text: b"" });
+        assert_eq!(errors[1].info_code, InfoCode::StaleAnswer);
+        assert_eq!(errors[1].extra_text, b"upstream timed out");
+        assert_eq!(parsed.to_bytes(), bs);
+        assert_eq!(parsed.additional[0].rdata.to_string(), "EDE DnssecBogus EDE StaleAnswer upstream timed out");
+
+        assert_eq!(EdnsOption::from(15, &[0x12, 0x34]),
+                   Some(EdnsOption::ExtendedError(ExtendedError { info_code: InfoCode::Unassigned(0x1234), extra_text: b"" })));
+        assert_eq!(InfoCode::from(24).to_u16(), 24);
+        assert_eq!(EdnsOption::from(15, &[0]), None);
+    }
+
+    #[test]
+    fn dns_nsid() {
+        let mut query = Message::query(1, "example.com", Qtype::Type(Type::A));
+        query.additional.push(ResourceRecord::opt(1232, vec![EdnsOption::Nsid(&[])]));
+        let bs = query.to_bytes();
+        assert_eq!(&bs[bs.len() - 4..], &[0, 3, 0, 0]);
+        let (_, parsed) = parse_dns_message(&bs).unwrap();
+        assert_eq!(parsed.nsid(), Some(&[][..]));
+
+        let mut response = parsed.clone();
+        response.header.qr = QR::Response;
+        response.additional[0].rdata = Rdata::Opt(vec![EdnsOption::Nsid(b"ams1")]);
+        let bs = response.to_bytes();
+        let (_, parsed) = parse_dns_message(&bs).unwrap();
+        assert_eq!(parsed.nsid(), Some(&b"ams1"[..]));
+        assert_eq!(parsed.additional[0].rdata.to_string(), "NSID 616d7331 (\"ams1\")");
+        let owned = parsed.into_owned();
+        assert_eq!(owned.additional[0].rdata, OwnedRdata::Opt(vec![OwnedEdnsOption::Nsid(b"ams1".to_vec())]));
+        assert_eq!(Message::query(1, "example.com", Qtype::Type(Type::A)).nsid(), None);
+    }
+
+    #[test]
+    fn dns_chaos() {
+        let query = Message::chaos_query(9, VERSION_BIND);
+        let bs = query.to_bytes();
+        assert_eq!(&bs[bs.len() - 4..], &[0, 16, 0, 3]);
+        let (_, parsed) = parse_dns_message_full(&bs).unwrap();
+        assert_eq!(parsed.questions[0].qclass, Qclass::Class(Class::CH));
+        assert!(!parsed.header.rd);
+        assert_eq!(parsed.chaos_text(), None);
+
+        // the answer's name is a pointer back to the question
+        let mut response = query.clone();
+        response.header.qr = QR::Response;
+        let version = [CharacterString::new(b"9.18.").unwrap(), CharacterString::new(b"24").unwrap()];
+        response.answers.push(ResourceRecord {
+            name: DomainName::Pointer(12),
+            typ: Type::Txt,
+            class: Class::CH,
+            ttl: 0,
+            rdata: Rdata::Txt(version.to_vec()),
+        });
+        let bs = response.to_bytes();
+        let (_, parsed) = parse_dns_message_full(&bs).unwrap();
+        assert_eq!(parsed.chaos_text(), Some("9.18.24".to_string()));
+
+        response.answers[0].class = Class::IN;
+        let bs = response.to_bytes();
+        let (_, parsed) = parse_dns_message_full(&bs).unwrap();
+        assert_eq!(parsed.chaos_text(), None);
+    }
+
+    #[test]
+    fn dns_partial() {
+        // three answers, the middle one an A record with two bytes of rdata,
+        // then an additional record cut off partway through its name
+        let msg = [
+            0, 1, 129, 128, 0, 1, 0, 3, 0, 0, 0, 1,
+            1, b'a', 0, 0, 1, 0, 1,
+            192, 12, 0, 1, 0, 1, 0, 0, 0, 69, 0, 4, 192, 0, 2, 1,
+            192, 12, 0, 1, 0, 1, 0, 0, 0, 69, 0, 2, 192, 0,
+            192, 12, 0, 1, 0, 1, 0, 0, 0, 69, 0, 4, 192, 0, 2, 3,
+            3, b'a',
+        ];
+        assert!(parse_dns_message_full(&msg).is_err());
+        let (rest, (msg, errors)) = parse_dns_message_partial(Mode::Strict, &msg).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(msg.answers.len(), 2);
+        assert_eq!(msg.answers[1].rdata, Rdata::A(&[192, 0, 2, 3]));
+        assert!(msg.additional.is_empty());
+        assert_eq!(errors.len(), 2);
+        assert_eq!((errors[0].section, errors[0].index), (Section::Answer, 1));
+        assert_eq!(errors[0].raw, &[192, 12, 0, 1, 0, 1, 0, 0, 0, 69, 0, 2, 192, 0][..]);
+        assert_eq!((errors[1].section, errors[1].index), (Section::Additional, 0));
+        assert_eq!(errors[1].raw, &[3, b'a'][..]);
+        assert!(!errors[0].truncated && errors[1].truncated);
+
+        // a bare header claiming the most records it can stops at the first
+        let header = [0, 1, 129, 128, 0, 0, 255, 255, 255, 255, 255, 255];
+        let (rest, (msg, errors)) = parse_dns_message_partial(Mode::Strict, &header).unwrap();
+        assert!(rest.is_empty());
+        assert!(msg.answers.is_empty() && msg.authorities.is_empty() && msg.additional.is_empty());
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].section, errors[0].index), (Section::Answer, 0));
+        assert!(errors[0].raw.is_empty() && errors[0].truncated);
+        assert_eq!(msg.answers.capacity(), 0);
+    }
+
+    #[test]
+    fn test_name_limits() {
+        let mut long_label = vec![64];
+        long_label.extend_from_slice(&[b'a'; 64]);
+        long_label.push(0);
+        let err = match domain_name(&long_label) {
+            Err(nom::Err::Failure(e)) => e,
+            other => panic!("unexpected result {:?}", other),
+        };
+        assert_eq!(err.kind, ErrorKind::TooLarge);
+        assert_eq!(err.field(), Some("dns.label"));
+
+        // four 63 byte labels come to 257 bytes with the root label
+        let mut long_name = vec![];
+        for _ in 0..4 {
+            long_name.push(63);
+            long_name.extend_from_slice(&[b'a'; 63]);
+        }
+        long_name.push(0);
+        let err = match domain_name(&long_name) {
+            Err(nom::Err::Failure(e)) => e,
+            other => panic!("unexpected result {:?}", other),
+        };
+        assert_eq!(err.field(), Some("dns.name"));
+        assert_eq!(LabelIter::new(&long_name, 0).count(), 3);
+        assert!(domain_name(&long_name[64..]).is_ok());
+    }
+
+    #[test]
+    fn test_domain_name_accessors() {
+        let (_, name) = domain_name(&[0x01, 0x6c, 0xc0, 0x10]).unwrap();
+        assert_eq!(name.labels().collect::<Vec<_>>(), vec![&b"l"[..]]);
+        assert_eq!(name.label_count(), 1);
+        assert_eq!(name.pointer(), Some(0x10));
+        assert_eq!(name.wire_len(), 4);
+
+        let name = DomainName::Labels(list![b"www", b"google", b"com"]);
+        assert_eq!(name.label_count(), 3);
+        assert_eq!(name.pointer(), None);
+        assert_eq!(name.wire_len(), 16);
+        assert_eq!(DomainName::Pointer(12).wire_len(), 2);
+    }
 }
@@ -0,0 +1,434 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::hash_map::{self, DefaultHasher};
+use std::hash::{Hash, Hasher};
+use std::mem;
+
+use dns::{self, DomainName, Qtype, Type, QR};
+
+// below this many queries a domain's score is scaled down, so a handful of
+// lookups of one odd name aren't flagged
+const MIN_QUERIES: u64 = 10;
+
+// distinct strings are counted exactly up to this many, then estimated
+const EXACT_DISTINCT: usize = 256;
+// a HyperLogLog with 2^10 one byte registers, good to about 3%
+const REGISTER_BITS: u32 = 10;
+
+/// How many different strings have been seen, in bounded memory: exactly
+/// while there are only a few, then as a HyperLogLog estimate. Tunnels send
+/// a new subdomain with every query, so keeping them all would let the
+/// traffic being watched decide how much memory the watching takes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DistinctCount {
+    // hashes of the strings, until there are too many to keep
+    exact: HashSet<u64>,
+    // empty until then
+    registers: Vec<u8>,
+}
+
+impl DistinctCount {
+    pub fn new() -> DistinctCount {
+        DistinctCount::default()
+    }
+
+    pub fn insert(&mut self, s: &str) {
+        let mut hasher = DefaultHasher::new();
+        s.hash(&mut hasher);
+        let hash = hasher.finish();
+        if !self.registers.is_empty() {
+            return self.add(hash);
+        }
+        self.exact.insert(hash);
+        if self.exact.len() > EXACT_DISTINCT {
+            self.registers = vec![0; 1 << REGISTER_BITS];
+            for hash in mem::take(&mut self.exact) {
+                self.add(hash);
+            }
+        }
+    }
+
+    // the top bits pick a register, which keeps the longest run of leading
+    // zeros seen in the rest
+    fn add(&mut self, hash: u64) {
+        let register = (hash >> (64 - REGISTER_BITS)) as usize;
+        let rank = ((hash << REGISTER_BITS).leading_zeros() + 1).min(64 - REGISTER_BITS + 1) as u8;
+        self.registers[register] = self.registers[register].max(rank);
+    }
+
+    pub fn len(&self) -> u64 {
+        if self.registers.is_empty() {
+            return self.exact.len() as u64;
+        }
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&rank| 0.5f64.powi(rank as i32)).sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&rank| rank == 0).count();
+        // linear counting is closer while many registers are still empty
+        let estimate = if estimate <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            estimate
+        };
+        estimate.round() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exact.is_empty() && self.registers.is_empty()
+    }
+}
+
+/// What the queries for one registered domain looked like
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DomainStats {
+    pub queries: u64,
+    // summed over every query, for the averages
+    pub total_len: u64,
+    pub total_entropy: f64,
+    // the different things in front of the registered domain, lowercased
+    pub subdomains: DistinctCount,
+    // queries for TXT or NULL records, which carry the most data back
+    pub txt_null: u64,
+}
+
+impl DomainStats {
+    /// Average length of the whole name, dots included
+    pub fn avg_query_len(&self) -> f64 {
+        self.ratio(self.total_len as f64)
+    }
+
+    /// Average Shannon entropy of the subdomain part, in bits per character
+    pub fn avg_entropy(&self) -> f64 {
+        self.ratio(self.total_entropy)
+    }
+
+    /// How many of the queries were for a subdomain not asked for before
+    pub fn unique_rate(&self) -> f64 {
+        // the estimate can come out a little over the number of queries
+        self.ratio(self.subdomains.len().min(self.queries) as f64)
+    }
+
+    pub fn txt_null_rate(&self) -> f64 {
+        self.ratio(self.txt_null as f64)
+    }
+
+    /// How much the queries look like data being tunnelled, from 0 to 1.
+    /// Encoded data gives high entropy, long names and subdomains that are
+    /// never repeated, and the replies come back in TXT or NULL records;
+    /// each of those is scaled to 0..1 and weighted.
+    pub fn score(&self) -> f64 {
+        let clamp = |x: f64| x.clamp(0.0, 1.0);
+        // English hostnames sit around 2.5-3.5 bits, base32 and base64 at 4-5
+        let entropy = clamp((self.avg_entropy() - 2.5) / 2.0);
+        let len = clamp((self.avg_query_len() - 20.0) / 60.0);
+        let score = 0.35*entropy + 0.25*len + 0.25*self.unique_rate() + 0.15*self.txt_null_rate();
+        score * clamp(self.queries as f64 / MIN_QUERIES as f64)
+    }
+
+    fn ratio(&self, total: f64) -> f64 {
+        if self.queries > 0 {
+            total / self.queries as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Shannon entropy of `bs`, in bits per byte
+pub fn entropy(bs: &[u8]) -> f64 {
+    let mut counts = [0u32; 256];
+    for &b in bs {
+        counts[b as usize] += 1;
+    }
+    let len = bs.len() as f64;
+    counts.iter()
+        .filter(|&&n| n > 0)
+        .map(|&n| {
+            let p = n as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+// multi-label public suffixes under which names are registered, the common
+// ones from the public suffix list; anything else is taken to be registered
+// directly under its TLD
+const MULTI_LABEL_SUFFIXES: &[&str] = &[
+    "ac.jp", "ac.nz", "ac.uk", "ac.za", "co.id", "co.il", "co.in", "co.jp",
+    "co.kr", "co.nz", "co.th", "co.uk", "co.za", "com.ar", "com.au", "com.br",
+    "com.cn", "com.co", "com.eg", "com.hk", "com.mx", "com.my", "com.ng", "com.pe",
+    "com.ph", "com.pk", "com.sa", "com.sg", "com.tr", "com.tw", "com.ua", "com.vn",
+    "edu.au", "edu.cn", "gov.au", "gov.cn", "gov.uk", "ltd.uk", "me.uk", "ne.jp",
+    "net.au", "net.br", "net.cn", "net.nz", "or.jp", "or.kr", "org.au", "org.br",
+    "org.cn", "org.nz", "org.uk", "org.za", "plc.uk",
+];
+
+// registered domains tracked at once, past which the least recently queried
+// is dropped
+const DEFAULT_MAX_DOMAINS: usize = 1 << 16;
+
+/// How many labels at the end of `labels` make up the registered domain:
+/// three under one of the common multi-label public suffixes such as
+/// co.uk, otherwise two.
+pub fn registered_len(labels: &[&[u8]]) -> usize {
+    let n = labels.len();
+    if n >= 3 {
+        let (sld, tld) = (labels[n - 2], labels[n - 1]);
+        let multi_label = MULTI_LABEL_SUFFIXES.iter().any(|suffix| {
+            let bs = suffix.as_bytes();
+            bs.len() == sld.len() + 1 + tld.len()
+                && bs[..sld.len()].eq_ignore_ascii_case(sld)
+                && bs[sld.len()] == b'.'
+                && bs[sld.len() + 1..].eq_ignore_ascii_case(tld)
+        });
+        if multi_label {
+            return 3;
+        }
+    }
+    n.min(2)
+}
+
+/// Collects per domain statistics from DNS queries, for spotting DNS
+/// tunnels. Only queries are counted, so a query and its response aren't
+/// counted twice. At most `max_domains` registered domains are kept, the
+/// least recently queried going first.
+pub struct TunnelDetector {
+    // each with when it was last queried, as a count of queries
+    domains: HashMap<String, (u64, DomainStats)>,
+    recent: BTreeMap<u64, String>,
+    queries: u64,
+    max_domains: usize,
+}
+
+impl Default for TunnelDetector {
+    fn default() -> TunnelDetector {
+        TunnelDetector {
+            domains: HashMap::new(),
+            recent: BTreeMap::new(),
+            queries: 0,
+            max_domains: DEFAULT_MAX_DOMAINS,
+        }
+    }
+}
+
+impl TunnelDetector {
+    pub fn new() -> TunnelDetector {
+        TunnelDetector::default()
+    }
+
+    /// Sets how many registered domains are tracked before the least
+    /// recently queried are dropped
+    pub fn set_max_domains(&mut self, max_domains: usize) {
+        self.max_domains = max_domains.max(1);
+        self.evict();
+    }
+
+    pub fn observe(&mut self, msg: &dns::Message) {
+        if msg.header.qr != QR::Query {
+            return;
+        }
+        for query in &msg.questions {
+            self.observe_name(&query.qname, query.qtype);
+        }
+    }
+
+    fn observe_name(&mut self, name: &DomainName, qtype: Qtype) {
+        let labels: Vec<&[u8]> = name.labels().collect();
+        if labels.is_empty() {
+            return;
+        }
+        let split = labels.len() - registered_len(&labels);
+        let lower = |labels: &[&[u8]]| labels.iter()
+            .map(|label| String::from_utf8_lossy(label).to_lowercase())
+            .collect::<Vec<_>>()
+            .join(".");
+        let domain = lower(&labels[split..]);
+        let tick = self.queries;
+        self.queries += 1;
+        let stats = match self.domains.entry(domain.clone()) {
+            hash_map::Entry::Occupied(entry) => {
+                let entry = entry.into_mut();
+                self.recent.remove(&entry.0);
+                entry.0 = tick;
+                &mut entry.1
+            },
+            hash_map::Entry::Vacant(entry) => &mut entry.insert((tick, DomainStats::default())).1,
+        };
+        self.recent.insert(tick, domain);
+
+        let subdomain: Vec<u8> = labels[..split].concat();
+        stats.queries += 1;
+        stats.total_len += name.to_string().len() as u64;
+        stats.total_entropy += entropy(&subdomain);
+        stats.subdomains.insert(&lower(&labels[..split]));
+        if qtype == Qtype::Type(Type::Txt) || qtype == Qtype::Type(Type::Null) {
+            stats.txt_null += 1;
+        }
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.domains.len() > self.max_domains {
+            match self.recent.pop_first() {
+                Some((_, domain)) => self.domains.remove(&domain),
+                None => break,
+            };
+        }
+    }
+
+    pub fn stats(&self, domain: &str) -> Option<&DomainStats> {
+        self.domains.get(&domain.to_lowercase()).map(|entry| &entry.1)
+    }
+
+    /// Every registered domain with its score, most suspicious first
+    pub fn scores(&self) -> Vec<(&str, f64)> {
+        let mut scores: Vec<(&str, f64)> = self.iter()
+            .map(|(domain, stats)| (&domain[..], stats.score()))
+            .collect();
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+        scores
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &DomainStats)> {
+        self.domains.iter().map(|(domain, entry)| (domain, &entry.1))
+    }
+
+    pub fn len(&self) -> usize {
+        self.domains.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.domains.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dns::{Class, Header, Opcode, Qclass, Query, Rcode};
+    use List;
+
+    fn query<'a>(labels: List<&'a [u8]>, typ: Type) -> dns::Message<'a> {
+        dns::Message {
+            header: Header {
+                id: 1,
+                qr: QR::Query,
+                opcode: Opcode::Query,
+                aa: false,
+                tc: false,
+                rd: true,
+                ra: false,
+                rcode: Rcode::NoError,
+                qdcount: 1,
+                ancount: 0,
+                nscount: 0,
+                arcount: 0,
+            },
+            questions: vec![Query {
+                qname: DomainName::Labels(labels),
+                qtype: Qtype::Type(typ),
+                qclass: Qclass::Class(Class::IN),
+            }],
+            answers: vec![],
+            authorities: vec![],
+            additional: vec![],
+        }
+    }
+
+    #[test]
+    fn test_tunnel_scores() {
+        assert_eq!(entropy(b"aaaa"), 0.0);
+        assert_eq!(entropy(b"abcd"), 2.0);
+        assert_eq!(registered_len(&[b"www", b"bbc", b"co", b"uk"]), 3);
+        assert_eq!(registered_len(&[b"www", b"example", b"com"]), 2);
+
+        let mut detector = TunnelDetector::new();
+        let chunks = [
+            &b"mzxw6ytboi2dqnzsgezdgnbvgy3tqojqgezdgnbv"[..],
+            &b"nbswy3dpeb3w64tmmqqhi2dfhfzgkzlmfzxg4zdv"[..],
+            &b"gq2tmnzyhe4dcmrtgq3donzyhe2tcmrzgu3dmobq"[..],
+            &b"ozsxe6lmn5xgozjamfzgkidjnzsw4ztpojwwc5dj"[..],
+            &b"on2g63lfor2gk4dbon2gk3tfmq2tmnzyhezdcmzu"[..],
+            &b"kruguzjaon2g64tfebzxi4tjnztsa3ljnzsx2ylu"[..],
+            &b"mrqxi4tbnbsxg5dsnfxgoidxnf2gq2lsmvzwc3ty"[..],
+            &b"ojxw2zlonzsgc5dbebqw4zbanvxxezlsmvzxizlz"[..],
+            &b"nfzxg2lpnyqgo33pmqqgs3tgn5zg2ylunfxw4idx"[..],
+            &b"nb2gk4tfebuwyzlbojxgkzbanb2gk4tfebuwyzlb"[..],
+        ];
+        for chunk in chunks.iter() {
+            detector.observe(&query(list![chunk, b"t", b"evil", b"net"], Type::Txt));
+        }
+        for _ in 0..20 {
+            detector.observe(&query(list![b"www", b"Example", b"com"], Type::A));
+        }
+
+        let evil = detector.stats("evil.net").unwrap();
+        assert_eq!(evil.queries, 10);
+        assert_eq!(evil.unique_rate(), 1.0);
+        assert_eq!(evil.txt_null_rate(), 1.0);
+        assert!(evil.avg_entropy() > 4.0);
+        let example = detector.stats("example.com").unwrap();
+        assert_eq!(example.subdomains.len(), 1);
+        assert_eq!(example.avg_query_len(), 15.0);
+
+        let scores = detector.scores();
+        assert_eq!(scores[0].0, "evil.net");
+        assert!(scores[0].1 > 0.7);
+        assert_eq!(scores[1].0, "example.com");
+        assert!(scores[1].1 < 0.1);
+    }
+
+    #[test]
+    fn test_tunnel_under_short_domain() {
+        assert_eq!(registered_len(&[b"data", b"abc", b"io"]), 2);
+        assert_eq!(registered_len(&[b"data", b"t", b"co"]), 2);
+        assert_eq!(registered_len(&[b"www", b"example", b"Com", b"AU"]), 3);
+
+        let mut detector = TunnelDetector::new();
+        for i in 0..20 {
+            let chunk = format!("{:x}", 0x9e3779b97f4a7c15u64.wrapping_mul(i + 1));
+            detector.observe(&query(list![chunk.as_bytes(), b"abc", b"io"], Type::Txt));
+        }
+        assert_eq!(detector.len(), 1);
+        let stats = detector.stats("abc.io").unwrap();
+        assert_eq!(stats.queries, 20);
+        assert_eq!(stats.unique_rate(), 1.0);
+        assert!(detector.scores()[0].1 > 0.5);
+    }
+
+    #[test]
+    fn test_max_domains() {
+        let mut detector = TunnelDetector::new();
+        detector.set_max_domains(2);
+        detector.observe(&query(list![b"www", b"a", b"com"], Type::A));
+        detector.observe(&query(list![b"www", b"b", b"com"], Type::A));
+        detector.observe(&query(list![b"mail", b"a", b"com"], Type::A));
+        detector.observe(&query(list![b"www", b"c", b"com"], Type::A));
+        assert_eq!(detector.len(), 2);
+        assert!(detector.stats("b.com").is_none());
+        assert_eq!(detector.stats("a.com").unwrap().queries, 2);
+        assert_eq!(detector.stats("c.com").unwrap().queries, 1);
+    }
+
+    #[test]
+    fn test_distinct_count() {
+        let mut count = DistinctCount::new();
+        assert!(count.is_empty());
+        for i in 0..EXACT_DISTINCT {
+            count.insert(&i.to_string());
+            count.insert(&i.to_string());
+        }
+        assert_eq!(count.len(), EXACT_DISTINCT as u64);
+
+        // past that it's estimated, in the same memory however many come
+        for i in 0..100000 {
+            count.insert(&format!("{:x}.chunk", i));
+        }
+        assert!(count.exact.is_empty());
+        assert_eq!(count.registers.len(), 1 << REGISTER_BITS);
+        let estimate = count.len() as f64;
+        assert!((estimate - 100256.0).abs() / 100256.0 < 0.1, "estimated {}", estimate);
+    }
+}
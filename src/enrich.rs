@@ -0,0 +1,93 @@
+use std::net::IpAddr;
+
+/// What a lookup found out about an address or name. Every field is
+/// optional, so an `Enricher` fills in whatever its source knows.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Enrichment {
+    // ISO 3166 country code
+    pub country: Option<String>,
+    pub city: Option<String>,
+    pub asn: Option<u32>,
+    pub as_org: Option<String>,
+    pub hostname: Option<String>,
+    // anything else, like an owner or role from an inventory
+    pub tags: Vec<(String, String)>,
+}
+
+/// What an `Enrichment` is about
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Subject {
+    Addr(IpAddr),
+    // a name asked about in DNS
+    Name(String),
+}
+
+/// Lookups run on every packet dissected with a `Registry` that has one
+/// set, so GeoIP, ASN or inventory data can be attached to packets without
+/// blosh shipping any of it. Lookups are made for the packet's IP
+/// addresses and the names in its DNS questions, and should be quick or
+/// cached, since they're made per packet.
+pub trait Enricher {
+    fn lookup_addr(&self, _addr: IpAddr) -> Option<Enrichment> {
+        None
+    }
+
+    fn lookup_name(&self, _name: &str) -> Option<Enrichment> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dissect::{parse_packet_with, LinkType, Registry};
+
+    struct Inventory;
+
+    impl Enricher for Inventory {
+        fn lookup_addr(&self, addr: IpAddr) -> Option<Enrichment> {
+            if addr == IpAddr::from([8, 8, 8, 8]) {
+                Some(Enrichment {
+                    country: Some("US".to_string()),
+                    asn: Some(15169),
+                    as_org: Some("GOOGLE".to_string()),
+                    ..Enrichment::default()
+                })
+            } else {
+                None
+            }
+        }
+
+        fn lookup_name(&self, name: &str) -> Option<Enrichment> {
+            Some(Enrichment {
+                tags: vec![("queried".to_string(), name.to_string())],
+                ..Enrichment::default()
+            })
+        }
+    }
+
+    #[test]
+    fn test_enricher() {
+        let packet = [
+            0x45, 0x00, 0x00, 0x3c, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x01,
+            0x08, 0x08, 0x08, 0x08,
+
+            0x10, 0xd8, 0x00, 0x35, 0x00, 0x28, 0x00, 0x00,
+
+            0x24, 0x1a, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x77, 0x77, 0x77,
+            0x06, 0x67, 0x6f, 0x6f, 0x67, 0x6c, 0x65, 0x03, 0x63, 0x6f, 0x6d, 0x00, 0x00, 0x01, 0x00, 0x01,
+        ];
+        let mut registry = Registry::new();
+        registry.set_enricher(Inventory);
+        let packet = parse_packet_with(&registry, LinkType::Raw, &packet);
+
+        assert_eq!(packet.enrichment.len(), 2);
+        let google = packet.enrichment_for(&Subject::Addr(IpAddr::from([8, 8, 8, 8]))).unwrap();
+        assert_eq!(google.asn, Some(15169));
+        assert!(packet.enrichment_for(&Subject::Addr(IpAddr::from([10, 0, 0, 1]))).is_none());
+        let name = packet.enrichment_for(&Subject::Name("www.google.com".to_string())).unwrap();
+        assert_eq!(name.tags[0].1, "www.google.com");
+    }
+}
@@ -0,0 +1,124 @@
+use std::fmt;
+
+use nom::error::{ContextError, ErrorKind};
+use nom::ErrorConvert;
+
+/// What the parsers return; the input is always a byte slice
+pub type IResult<'a, O> = nom::IResult<&'a [u8], O, ParseError<'a>>;
+
+/// Why a parser gave up. `context` names the layers and fields that were
+/// being parsed, using the same names as the JSON export, innermost first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError<'a> {
+    // the input left when parsing failed
+    pub input: &'a [u8],
+    pub kind: ErrorKind,
+    pub context: Vec<&'static str>,
+}
+
+impl <'a> ParseError<'a> {
+    pub fn new(input: &'a [u8], kind: ErrorKind) -> ParseError<'a> {
+        ParseError {
+            input,
+            kind,
+            context: vec![],
+        }
+    }
+
+    /// The outermost layer that failed, like "ipv6"
+    pub fn layer(&self) -> Option<&'static str> {
+        self.context.last().cloned()
+    }
+
+    /// The innermost field that failed, like "ipv6.ah"
+    pub fn field(&self) -> Option<&'static str> {
+        self.context.first().cloned()
+    }
+
+    /// Where parsing failed, counted from the start of `original`, which has
+    /// to be the buffer the parser was handed
+    pub fn offset(&self, original: &[u8]) -> Option<usize> {
+        let start = original.as_ptr() as usize;
+        let at = self.input.as_ptr() as usize;
+        if at >= start && at + self.input.len() <= start + original.len() {
+            Some(at - start)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the input ran out, so a longer capture might have parsed
+    pub fn is_incomplete(&self) -> bool {
+        self.kind == ErrorKind::Eof || self.kind == ErrorKind::Complete
+    }
+}
+
+impl <'a> nom::error::ParseError<&'a [u8]> for ParseError<'a> {
+    fn from_error_kind(input: &'a [u8], kind: ErrorKind) -> ParseError<'a> {
+        ParseError::new(input, kind)
+    }
+
+    fn append(_input: &'a [u8], _kind: ErrorKind, other: ParseError<'a>) -> ParseError<'a> {
+        other
+    }
+}
+
+impl <'a> ContextError<&'a [u8]> for ParseError<'a> {
+    fn add_context(_input: &'a [u8], ctx: &'static str, mut other: ParseError<'a>) -> ParseError<'a> {
+        other.context.push(ctx);
+        other
+    }
+}
+
+// so bit level parsers can be used with `nom::bits::bits`
+impl <'a> ErrorConvert<ParseError<'a>> for nom::error::Error<(&'a [u8], usize)> {
+    fn convert(self) -> ParseError<'a> {
+        ParseError::new(self.input.0, self.code)
+    }
+}
+
+impl <'a> fmt::Display for ParseError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.context.is_empty() {
+            write!(f, "parse error")?;
+        } else {
+            write!(f, "error in ")?;
+            for (i, ctx) in self.context.iter().rev().enumerate() {
+                if i > 0 {
+                    write!(f, " > ")?;
+                }
+                write!(f, "{}", ctx)?;
+            }
+        }
+        write!(f, ": {}", self.kind.description())?;
+        if self.is_incomplete() {
+            write!(f, " (ran out of input)")?;
+        }
+        write!(f, ", {} bytes left", self.input.len())
+    }
+}
+
+impl <'a> ::std::error::Error for ParseError<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use ::udp::parse_udp_packet;
+
+    #[test]
+    fn test_truncated_udp() {
+        // the length field claims 16 bytes but only 12 were captured
+        let packet = [
+            0x30, 0x39, 0x00, 0x35, 0x00, 0x10, 0x00, 0x00,
+            0x01, 0x02, 0x03, 0x04,
+        ];
+        let err = match parse_udp_packet(&packet) {
+            Err(nom::Err::Error(e)) => e,
+            other => panic!("unexpected result {:?}", other),
+        };
+        assert_eq!(err.layer(), Some("udp"));
+        assert_eq!(err.field(), Some("udp.length"));
+        assert_eq!(err.offset(&packet), Some(8));
+        assert!(err.is_incomplete());
+        assert_eq!(format!("{}", err), "error in udp > udp.length: End of file (ran out of input), 4 bytes left");
+    }
+}
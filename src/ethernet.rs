@@ -1,25 +1,536 @@
-use nom::{rest, IResult};
+use std::fmt;
+use std::str::FromStr;
+
+use nom::bytes::complete::take;
+use nom::combinator::{rest, verify};
+use nom::error::context;
+use nom::multi::many0;
+use nom::number::complete::{be_u16, be_u32};
+#[cfg(feature = "arbitrary")]
+use arbitrary::{Arbitrary, Unstructured};
+
+use checksum;
+use error::IResult;
+use {Fixup, IntoOwned, Layer, NextProtocol, ToBytes};
+
+/// A 48-bit IEEE 802 MAC address
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct MacAddr(pub [u8; 6]);
+
+impl MacAddr {
+    pub const BROADCAST: MacAddr = MacAddr([0xff; 6]);
+
+    /// `None` unless `bs` is exactly six bytes long
+    pub fn from_slice(bs: &[u8]) -> Option<MacAddr> {
+        if bs.len() != 6 {
+            return None;
+        }
+        let mut octets = [0; 6];
+        octets.copy_from_slice(bs);
+        Some(MacAddr(octets))
+    }
+
+    pub fn octets(&self) -> [u8; 6] {
+        self.0
+    }
+
+    /// The organizationally unique identifier, the first three bytes
+    pub fn oui(&self) -> [u8; 3] {
+        [self.0[0], self.0[1], self.0[2]]
+    }
+
+    /// The I/G bit is set, which includes the broadcast address
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] & 0x01 != 0
+    }
+
+    pub fn is_unicast(&self) -> bool {
+        !self.is_multicast()
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+        *self == MacAddr::BROADCAST
+    }
+
+    /// The U/L bit is set, so the address was assigned by software rather
+    /// than burned in by the vendor, and the OUI means nothing
+    pub fn is_locally_administered(&self) -> bool {
+        self.0[0] & 0x02 != 0
+    }
+
+    /// The vendor that the OUI is registered to, from a short built in list
+    /// of ones that are common in captures
+    #[cfg(feature = "oui")]
+    pub fn vendor(&self) -> Option<&'static str> {
+        if self.is_locally_administered() {
+            return None;
+        }
+        OUIS.binary_search_by_key(&self.oui(), |&(oui, _)| oui)
+            .ok()
+            .map(|i| OUIS[i].1)
+    }
+}
+
+// sorted by OUI for binary searching
+#[cfg(feature = "oui")]
+const OUIS: &[([u8; 3], &str)] = &[
+    ([0x00, 0x00, 0x0c], "Cisco"),
+    ([0x00, 0x03, 0x93], "Apple"),
+    ([0x00, 0x05, 0x69], "VMware"),
+    ([0x00, 0x0c, 0x29], "VMware"),
+    ([0x00, 0x15, 0x5d], "Microsoft"),
+    ([0x00, 0x16, 0x3e], "Xensource"),
+    ([0x00, 0x1b, 0x21], "Intel"),
+    ([0x00, 0x1c, 0x42], "Parallels"),
+    ([0x00, 0x50, 0x56], "VMware"),
+    ([0x08, 0x00, 0x27], "VirtualBox"),
+    ([0xb8, 0x27, 0xeb], "Raspberry Pi"),
+    ([0xdc, 0xa6, 0x32], "Raspberry Pi"),
+];
+
+/// Lower case and colon separated, like "aa:bb:cc:dd:ee:ff"
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let b = &self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", b[0], b[1], b[2], b[3], b[4], b[5])
+    }
+}
+
+/// Six hex bytes separated by colons or dashes
+impl FromStr for MacAddr {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<MacAddr, ()> {
+        let mut octets = [0; 6];
+        let mut parts = s.split(&[':', '-'][..]);
+        for octet in octets.iter_mut() {
+            let part = parts.next().ok_or(())?;
+            if part.is_empty() || part.len() > 2 {
+                return Err(());
+            }
+            *octet = u8::from_str_radix(part, 16).map_err(|_| ())?;
+        }
+        match parts.next() {
+            Some(_) => Err(()),
+            None => Ok(MacAddr(octets)),
+        }
+    }
+}
+
+impl From<[u8; 6]> for MacAddr {
+    fn from(octets: [u8; 6]) -> MacAddr {
+        MacAddr(octets)
+    }
+}
+
+pub fn parse_mac_addr(bs: &[u8]) -> IResult<'_, MacAddr> {
+    let (bs, mac) = take(6usize)(bs)?;
+    Ok((bs, MacAddr::from_slice(mac).unwrap()))
+}
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct EthernetIIPacket<'a> {
-    pub dest_mac: &'a [u8],
-    pub source_mac: &'a [u8],
+    pub dest_mac: MacAddr,
+    pub source_mac: MacAddr,
+    pub vlans: Vec<VlanTag>,
+    pub ethertype: EtherType,
     pub body: &'a [u8],
 }
 
-pub fn parse_eth2_packet<'a>(bs: &'a [u8]) -> IResult<&'a [u8], EthernetIIPacket<'a>, u32> {
-    do_parse!(
-        bs,
-        dest: take!(6) >>
-        src: take!(6) >>
-        _ethertyp: tag!(b"\x08\x00") >>
-        rest: rest >>
-        ({
-            EthernetIIPacket {
-                dest_mac: dest,
-                source_mac: src,
-                body: rest,
-            }
+// https://en.wikipedia.org/wiki/IEEE_802.1Q
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct VlanTag {
+    pub tpid: u16,
+    pub pcp: u8,
+    pub dei: bool,
+    pub vid: u16,
+}
+
+fn is_vlan_tpid(tpid: u16) -> bool {
+    match tpid {
+        // 802.1Q, 802.1ad, and the pre-standard QinQ value
+        0x8100 | 0x88a8 | 0x9100 => true,
+        _ => false,
+    }
+}
+
+fn parse_vlan_tag(bs: &[u8]) -> IResult<'_, VlanTag> {
+    let (bs, tpid) = verify(be_u16, |&tpid| is_vlan_tpid(tpid))(bs)?;
+    let (bs, tci) = be_u16(bs)?;
+    Ok((bs, VlanTag {
+        tpid,
+        pcp: (tci >> 13) as u8,
+        dei: tci & 0x1000 != 0,
+        vid: tci & 0x0fff,
+    }))
+}
+
+/// The frame check sequence at the end of a frame, for captures that kept it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Fcs {
+    pub value: u32,
+    // whether it matches the rest of the frame
+    pub valid: bool,
+}
+
+/// Splits the FCS off the end of a frame, returning the frame without it.
+/// `None` if the frame is too short to have one.
+pub fn split_fcs(frame: &[u8]) -> Option<(&[u8], Fcs)> {
+    if frame.len() < 18 {
+        return None;
+    }
+    let (frame, fcs) = frame.split_at(frame.len() - 4);
+    // sent least significant byte first
+    let value = u32::from_le_bytes([fcs[0], fcs[1], fcs[2], fcs[3]]);
+    Some((frame, Fcs {
+        value,
+        valid: checksum::crc32(frame) == value,
+    }))
+}
+
+// https://en.wikipedia.org/wiki/IEEE_802.1ah-2008
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ITag {
+    pub pcp: u8,
+    pub dei: bool,
+    // use customer addresses, set if the customer frame's addresses are
+    // what a backbone edge bridge should learn from
+    pub uca: bool,
+    // backbone service instance identifier, 24 bits
+    pub isid: u32,
+}
+
+/// An 802.1ah frame, the body of a backbone frame with an I-tag: the
+/// I-tag's fields, then the customer's frame, addresses and all
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct PbbFrame<'a> {
+    pub itag: ITag,
+    pub customer: EthernetIIPacket<'a>,
+}
+
+/// Parses the body of a frame whose EtherType is `EtherType::Pbb`
+pub fn parse_pbb_frame(bs: &[u8]) -> IResult<'_, PbbFrame<'_>> {
+    context("pbb", pbb_frame)(bs)
+}
+
+fn pbb_frame(bs: &[u8]) -> IResult<'_, PbbFrame<'_>> {
+    let (bs, tci) = be_u32(bs)?;
+    let (bs, customer) = context("pbb.customer", eth2_packet)(bs)?;
+    Ok((bs, PbbFrame {
+        itag: ITag {
+            pcp: (tci >> 29) as u8,
+            dei: tci & 0x1000_0000 != 0,
+            uca: tci & 0x0800_0000 != 0,
+            isid: tci & 0x00ff_ffff,
+        },
+        customer,
+    }))
+}
+
+/// tcpdump -e style summary of the link layer header
+impl <'a> fmt::Display for EthernetIIPacket<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} > {}", self.source_mac, self.dest_mac)?;
+        for vlan in &self.vlans {
+            write!(f, ", vlan {}, p {}", vlan.vid, vlan.pcp)?;
+        }
+        match self.ethertype.name() {
+            Some(name) => write!(f, ", ethertype {} (0x{:04x})", name, self.ethertype.to_u16())?,
+            None => write!(f, ", ethertype 0x{:04x}", self.ethertype.to_u16())?,
+        }
+        write!(f, ", length {}", self.body.len())
+    }
+}
+
+// https://www.iana.org/assignments/ieee-802-numbers
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub enum EtherType {
+    Ipv4,
+    Arp,
+    WakeOnLan,
+    // transparent Ethernet bridging, Ethernet inside GRE or Geneve
+    Teb,
+    Rarp,
+    AppleTalk,
+    Aarp,
+    // 802.1Q
+    Vlan,
+    Ipx,
+    Ipv6,
+    // 802.3x pause frames
+    FlowControl,
+    // LACP and the other slow protocols
+    SlowProtocols,
+    Mpls,
+    MplsMulticast,
+    PppoeDiscovery,
+    PppoeSession,
+    Eapol,
+    // 802.1ad
+    QinQ,
+    Lldp,
+    MacSec,
+    // 802.1ah
+    Pbb,
+    Ptp,
+    Fcoe,
+    RoCE,
+    // the pre-standard QinQ value
+    LegacyQinQ,
+    // including values below 0x0600, which are 802.3 lengths
+    Other(u16),
+}
+
+impl EtherType {
+    pub fn from_u16(v: u16) -> EtherType {
+        use self::EtherType::*;
+        match v {
+            0x0800 => Ipv4,
+            0x0806 => Arp,
+            0x0842 => WakeOnLan,
+            0x6558 => Teb,
+            0x8035 => Rarp,
+            0x809b => AppleTalk,
+            0x80f3 => Aarp,
+            0x8100 => Vlan,
+            0x8137 => Ipx,
+            0x86dd => Ipv6,
+            0x8808 => FlowControl,
+            0x8809 => SlowProtocols,
+            0x8847 => Mpls,
+            0x8848 => MplsMulticast,
+            0x8863 => PppoeDiscovery,
+            0x8864 => PppoeSession,
+            0x888e => Eapol,
+            0x88a8 => QinQ,
+            0x88cc => Lldp,
+            0x88e5 => MacSec,
+            0x88e7 => Pbb,
+            0x88f7 => Ptp,
+            0x8906 => Fcoe,
+            0x8915 => RoCE,
+            0x9100 => LegacyQinQ,
+            x => Other(x),
+        }
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        use self::EtherType::*;
+        match *self {
+            Ipv4 => 0x0800,
+            Arp => 0x0806,
+            WakeOnLan => 0x0842,
+            Teb => 0x6558,
+            Rarp => 0x8035,
+            AppleTalk => 0x809b,
+            Aarp => 0x80f3,
+            Vlan => 0x8100,
+            Ipx => 0x8137,
+            Ipv6 => 0x86dd,
+            FlowControl => 0x8808,
+            SlowProtocols => 0x8809,
+            Mpls => 0x8847,
+            MplsMulticast => 0x8848,
+            PppoeDiscovery => 0x8863,
+            PppoeSession => 0x8864,
+            Eapol => 0x888e,
+            QinQ => 0x88a8,
+            Lldp => 0x88cc,
+            MacSec => 0x88e5,
+            Pbb => 0x88e7,
+            Ptp => 0x88f7,
+            Fcoe => 0x8906,
+            RoCE => 0x8915,
+            LegacyQinQ => 0x9100,
+            Other(x) => x,
+        }
+    }
+
+    /// Common name, like Wireshark shows it
+    pub fn name(&self) -> Option<&'static str> {
+        use self::EtherType::*;
+        Some(match *self {
+            Ipv4 => "IPv4",
+            Arp => "ARP",
+            WakeOnLan => "Wake-on-LAN",
+            Teb => "Transparent Ethernet Bridging",
+            Rarp => "RARP",
+            AppleTalk => "AppleTalk",
+            Aarp => "AARP",
+            Vlan => "802.1Q",
+            Ipx => "IPX",
+            Ipv6 => "IPv6",
+            FlowControl => "Ethernet flow control",
+            SlowProtocols => "Slow Protocols",
+            Mpls => "MPLS",
+            MplsMulticast => "MPLS multicast",
+            PppoeDiscovery => "PPPoE Discovery",
+            PppoeSession => "PPPoE Session",
+            Eapol => "802.1X",
+            QinQ => "802.1ad",
+            Lldp => "LLDP",
+            MacSec => "MACsec",
+            Pbb => "802.1ah",
+            Ptp => "PTP",
+            Fcoe => "FCoE",
+            RoCE => "RoCE",
+            LegacyQinQ => "QinQ",
+            Other(_) => return None,
         })
-    )
+    }
+}
+
+/// Common name of an EtherType, if it's one blosh knows
+pub fn ethertype_name(ethertype: u16) -> Option<&'static str> {
+    EtherType::from_u16(ethertype).name()
+}
+
+pub fn parse_eth2_packet(bs: &[u8]) -> IResult<'_, EthernetIIPacket<'_>> {
+    context("eth", eth2_packet)(bs)
+}
+
+fn eth2_packet(bs: &[u8]) -> IResult<'_, EthernetIIPacket<'_>> {
+    let (bs, dest) = parse_mac_addr(bs)?;
+    let (bs, src) = parse_mac_addr(bs)?;
+    let (bs, vlans) = many0(parse_vlan_tag)(bs)?;
+    let (bs, ethertype) = be_u16(bs)?;
+    let (bs, body) = rest(bs)?;
+    let ethertype = EtherType::from_u16(ethertype);
+    Ok((bs, EthernetIIPacket {
+        dest_mac: dest,
+        source_mac: src,
+        vlans,
+        ethertype,
+        body,
+    }))
+}
+
+impl <'a> EthernetIIPacket<'a> {
+    /// An untagged frame, ready to be written out with `ToBytes`
+    pub fn new(dest_mac: MacAddr, source_mac: MacAddr, ethertype: EtherType, body: &'a [u8]) -> EthernetIIPacket<'a> {
+        EthernetIIPacket {
+            dest_mac,
+            source_mac,
+            vlans: vec![],
+            ethertype,
+            body,
+        }
+    }
+}
+
+impl ToBytes for VlanTag {
+    fn write_with(&self, _fixup: Fixup, out: &mut Vec<u8>) {
+        let tci = ((self.pcp as u16) << 13) | ((self.dei as u16) << 12) | (self.vid & 0x0fff);
+        out.extend_from_slice(&self.tpid.to_be_bytes());
+        out.extend_from_slice(&tci.to_be_bytes());
+    }
+}
+
+impl <'a> Layer<'a> for EthernetIIPacket<'a> {
+    fn header_len(&self) -> usize {
+        14 + 4*self.vlans.len()
+    }
+
+    fn payload(&self) -> &'a [u8] {
+        self.body
+    }
+
+    fn next_protocol(&self) -> Option<NextProtocol> {
+        Some(NextProtocol::EtherType(self.ethertype))
+    }
+}
+
+impl <'a> ToBytes for EthernetIIPacket<'a> {
+    fn write_with(&self, fixup: Fixup, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.dest_mac.0);
+        out.extend_from_slice(&self.source_mac.0);
+        for vlan in &self.vlans {
+            vlan.write_with(fixup, out);
+        }
+        out.extend_from_slice(&self.ethertype.to_u16().to_be_bytes());
+        out.extend_from_slice(self.body);
+    }
+}
+
+// tags with a VLAN TPID and an ethertype that isn't one
+#[cfg(feature = "arbitrary")]
+impl <'a> Arbitrary<'a> for EthernetIIPacket<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<EthernetIIPacket<'a>> {
+        let dest_mac = MacAddr::arbitrary(u)?;
+        let source_mac = MacAddr::arbitrary(u)?;
+        let mut vlans = vec![];
+        for _ in 0..u.int_in_range(0..=2)? {
+            let mut vlan = VlanTag::arbitrary(u)?;
+            vlan.tpid = *u.choose(&[0x8100, 0x88a8, 0x9100])?;
+            vlan.pcp &= 0x07;
+            vlan.vid &= 0x0fff;
+            vlans.push(vlan);
+        }
+        let mut ethertype = u16::arbitrary(u)?;
+        if is_vlan_tpid(ethertype) {
+            ethertype = 0x0800;
+        }
+        let ethertype = EtherType::from_u16(ethertype);
+        Ok(EthernetIIPacket {
+            dest_mac,
+            source_mac,
+            vlans,
+            ethertype,
+            body: u.arbitrary()?,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedEthernetIIPacket {
+    pub dest_mac: MacAddr,
+    pub source_mac: MacAddr,
+    pub vlans: Vec<VlanTag>,
+    pub ethertype: EtherType,
+    pub body: Vec<u8>,
+}
+
+impl <'a> IntoOwned for EthernetIIPacket<'a> {
+    type Owned = OwnedEthernetIIPacket;
+
+    fn into_owned(self) -> OwnedEthernetIIPacket {
+        OwnedEthernetIIPacket {
+            dest_mac: self.dest_mac,
+            source_mac: self.source_mac,
+            vlans: self.vlans,
+            ethertype: self.ethertype,
+            body: self.body.into_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mac_addr() {
+        let mac: MacAddr = "01:00:5E:00:00:FB".parse().unwrap();
+        assert_eq!(mac.to_string(), "01:00:5e:00:00:fb");
+        assert!(mac.is_multicast());
+        assert!(!mac.is_broadcast());
+        assert!(MacAddr::BROADCAST.is_multicast() && MacAddr::BROADCAST.is_broadcast());
+        assert_eq!("02-42-ac-11-00-02".parse(), Ok(MacAddr([0x02, 0x42, 0xac, 0x11, 0x00, 0x02])));
+        assert!(MacAddr([0x02, 0x42, 0xac, 0x11, 0x00, 0x02]).is_locally_administered());
+        assert!("00:11:22:33:44".parse::<MacAddr>().is_err());
+        assert!("00:11:22:33:44:55:66".parse::<MacAddr>().is_err());
+        #[cfg(feature = "oui")]
+        assert_eq!(MacAddr([0x00, 0x50, 0x56, 0xc0, 0x00, 0x01]).vendor(), Some("VMware"));
+    }
 }
@@ -0,0 +1,466 @@
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::slice;
+
+use dns::{self, Mode, OwnedMessage, OwnedResourceRecord};
+use {IntoOwned, ToBytes, ipv4, ipv6, tcp, udp};
+
+// return codes, mirrored in include/blosh.h
+pub const BLOSH_OK: c_int = 0;
+pub const BLOSH_EINVAL: c_int = -1;
+pub const BLOSH_EPARSE: c_int = -2;
+
+pub const BLOSH_SECTION_ANSWER: c_int = 0;
+pub const BLOSH_SECTION_AUTHORITY: c_int = 1;
+pub const BLOSH_SECTION_ADDITIONAL: c_int = 2;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BloshIpv4Header {
+    // in bytes, so it's also where the body starts
+    pub header_len: u8,
+    pub dscp: u8,
+    pub ecn: u8,
+    pub df: u8,
+    pub mf: u8,
+    pub ttl: u8,
+    pub proto: u8,
+    pub total_len: u16,
+    pub id: u16,
+    pub fragment_off: u16,
+    pub checksum: u16,
+    pub src: [u8; 4],
+    pub dst: [u8; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BloshIpv6Header {
+    pub traffic_class: u8,
+    pub next_header: u8,
+    pub hop_limit: u8,
+    pub flow_label: u32,
+    pub payload_len: u16,
+    // after any extension headers
+    pub body_offset: u16,
+    pub src: [u8; 16],
+    pub dst: [u8; 16],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BloshTcpHeader {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub seq: u32,
+    pub ack: u32,
+    // in bytes, options included
+    pub header_len: u8,
+    // CWR down to FIN, as on the wire
+    pub flags: u8,
+    pub ns: u8,
+    pub window: u16,
+    pub checksum: u16,
+    pub urgent: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BloshUdpHeader {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub len: u16,
+    pub checksum: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BloshDnsQuestion {
+    pub qtype: u16,
+    pub qclass: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BloshDnsRecord {
+    pub rtype: u16,
+    pub rclass: u16,
+    pub ttl: u32,
+}
+
+/// A parsed DNS message, opaque to C
+pub struct BloshDnsMessage(OwnedMessage);
+
+unsafe fn input<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    if data.is_null() {
+        None
+    } else {
+        Some(slice::from_raw_parts(data, len))
+    }
+}
+
+// snprintf style: writes as much as fits with a NUL after it, and returns
+// the full length
+unsafe fn write_str(s: &str, buf: *mut c_char, buf_len: usize) -> c_int {
+    if !buf.is_null() && buf_len > 0 {
+        let n = s.len().min(buf_len - 1);
+        ptr::copy_nonoverlapping(s.as_ptr() as *const c_char, buf, n);
+        *buf.add(n) = 0;
+    }
+    s.len() as c_int
+}
+
+/// Parses an IPv4 packet into `out`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, and `out` to a writable
+/// `BloshIpv4Header`.
+#[no_mangle]
+pub unsafe extern "C" fn blosh_parse_ipv4(data: *const u8, len: usize, out: *mut BloshIpv4Header) -> c_int {
+    let bs = match input(data, len) {
+        Some(bs) if !out.is_null() => bs,
+        _ => return BLOSH_EINVAL,
+    };
+    let header = match ipv4::parse_ipv4_packet(bs) {
+        Ok((_, packet)) => packet.header,
+        Err(_) => return BLOSH_EPARSE,
+    };
+    *out = BloshIpv4Header {
+        header_len: 4*header.len,
+        dscp: header.dscp,
+        ecn: header.ecn,
+        df: header.flags.df as u8,
+        mf: header.flags.mf as u8,
+        ttl: header.ttl,
+        proto: header.proto.to_u8(),
+        total_len: header.total_len,
+        id: header.id,
+        fragment_off: header.fragment_off,
+        checksum: header.checksum,
+        src: header.src_ip.octets(),
+        dst: header.dst_ip.octets(),
+    };
+    BLOSH_OK
+}
+
+/// Parses an IPv6 packet, extension headers and all, into `out`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, and `out` to a writable
+/// `BloshIpv6Header`.
+#[no_mangle]
+pub unsafe extern "C" fn blosh_parse_ipv6(data: *const u8, len: usize, out: *mut BloshIpv6Header) -> c_int {
+    let bs = match input(data, len) {
+        Some(bs) if !out.is_null() => bs,
+        _ => return BLOSH_EINVAL,
+    };
+    let packet = match ipv6::parse_ipv6_packet(bs) {
+        Ok((_, packet)) => packet,
+        Err(_) => return BLOSH_EPARSE,
+    };
+    let next_header = match packet.extensions.last() {
+        Some(extension) => extension.next_header,
+        None => packet.header.next_header,
+    };
+    *out = BloshIpv6Header {
+        traffic_class: packet.header.traffic_class,
+        next_header: next_header.to_u8(),
+        hop_limit: packet.header.hop_limit,
+        flow_label: packet.header.flow_label,
+        payload_len: packet.header.payload_length,
+        body_offset: (packet.body.as_ptr() as usize - bs.as_ptr() as usize) as u16,
+        src: packet.header.src_ip.octets(),
+        dst: packet.header.dst_ip.octets(),
+    };
+    BLOSH_OK
+}
+
+/// Parses a TCP segment into `out`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, and `out` to a writable
+/// `BloshTcpHeader`.
+#[no_mangle]
+pub unsafe extern "C" fn blosh_parse_tcp(data: *const u8, len: usize, out: *mut BloshTcpHeader) -> c_int {
+    let bs = match input(data, len) {
+        Some(bs) if !out.is_null() => bs,
+        _ => return BLOSH_EINVAL,
+    };
+    let header = match tcp::parse_tcp_packet(bs) {
+        Ok((_, packet)) => packet.header,
+        Err(_) => return BLOSH_EPARSE,
+    };
+    *out = BloshTcpHeader {
+        src_port: header.src,
+        dst_port: header.dst,
+        seq: header.seq,
+        ack: header.ack,
+        header_len: 4*header.flags.offset,
+        flags: header.flags.to_u8(),
+        ns: header.flags.ns as u8,
+        window: header.window_sz,
+        checksum: header.checksum,
+        urgent: header.urgent,
+    };
+    BLOSH_OK
+}
+
+/// Parses a UDP datagram into `out`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, and `out` to a writable
+/// `BloshUdpHeader`.
+#[no_mangle]
+pub unsafe extern "C" fn blosh_parse_udp(data: *const u8, len: usize, out: *mut BloshUdpHeader) -> c_int {
+    let bs = match input(data, len) {
+        Some(bs) if !out.is_null() => bs,
+        _ => return BLOSH_EINVAL,
+    };
+    let header = match udp::parse_udp_packet(bs) {
+        Ok((_, packet)) => packet.header,
+        Err(_) => return BLOSH_EPARSE,
+    };
+    *out = BloshUdpHeader {
+        src_port: header.src,
+        dst_port: header.dst,
+        len: header.len,
+        checksum: header.checksum,
+    };
+    BLOSH_OK
+}
+
+/// Parses a DNS message, strictly unless `lenient` is non-zero. Returns
+/// NULL if it doesn't parse; otherwise the message has to be freed with
+/// `blosh_dns_free`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn blosh_parse_dns(data: *const u8, len: usize, lenient: c_int) -> *mut BloshDnsMessage {
+    let mode = if lenient != 0 { Mode::Lenient } else { Mode::Strict };
+    let msg = input(data, len).and_then(|bs| dns::parse_dns_message_full_with(mode, bs).ok());
+    match msg {
+        Some((_, msg)) => Box::into_raw(Box::new(BloshDnsMessage(msg.into_owned()))),
+        None => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `msg` must be NULL or have come from `blosh_parse_dns`, and not have
+/// been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn blosh_dns_free(msg: *mut BloshDnsMessage) {
+    if !msg.is_null() {
+        drop(Box::from_raw(msg));
+    }
+}
+
+/// The message ID, or -1 if `msg` is NULL.
+///
+/// # Safety
+///
+/// `msg` must be NULL or a live message from `blosh_parse_dns`.
+#[no_mangle]
+pub unsafe extern "C" fn blosh_dns_id(msg: *const BloshDnsMessage) -> c_int {
+    match msg.as_ref() {
+        Some(msg) => msg.0.header.id as c_int,
+        None => BLOSH_EINVAL,
+    }
+}
+
+/// The second 16 bits of the header, QR down to the rcode, or -1 if `msg`
+/// is NULL.
+///
+/// # Safety
+///
+/// `msg` must be NULL or a live message from `blosh_parse_dns`.
+#[no_mangle]
+pub unsafe extern "C" fn blosh_dns_flags(msg: *const BloshDnsMessage) -> c_int {
+    match msg.as_ref() {
+        Some(msg) => {
+            let bs = msg.0.header.to_bytes();
+            u16::from_be_bytes([bs[2], bs[3]]) as c_int
+        },
+        None => BLOSH_EINVAL,
+    }
+}
+
+/// # Safety
+///
+/// `msg` must be NULL or a live message from `blosh_parse_dns`.
+#[no_mangle]
+pub unsafe extern "C" fn blosh_dns_question_count(msg: *const BloshDnsMessage) -> c_int {
+    match msg.as_ref() {
+        Some(msg) => msg.0.questions.len() as c_int,
+        None => BLOSH_EINVAL,
+    }
+}
+
+/// Fills in the type and class of question `i`.
+///
+/// # Safety
+///
+/// `msg` must be NULL or a live message from `blosh_parse_dns`, and `out`
+/// NULL or a writable `BloshDnsQuestion`.
+#[no_mangle]
+pub unsafe extern "C" fn blosh_dns_question(msg: *const BloshDnsMessage, i: usize, out: *mut BloshDnsQuestion)
+    -> c_int
+{
+    match (msg.as_ref().and_then(|msg| msg.0.questions.get(i)), out.as_mut()) {
+        (Some(q), Some(out)) => {
+            *out = BloshDnsQuestion {
+                qtype: q.qtype.to_u16(),
+                qclass: q.qclass.to_u16(),
+            };
+            BLOSH_OK
+        },
+        _ => BLOSH_EINVAL,
+    }
+}
+
+/// Writes the name of question `i` into `buf`, like snprintf: as much as
+/// fits with a NUL after it. Returns the name's full length.
+///
+/// # Safety
+///
+/// `msg` must be NULL or a live message from `blosh_parse_dns`, and `buf`
+/// NULL or `buf_len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn blosh_dns_question_name(msg: *const BloshDnsMessage, i: usize, buf: *mut c_char,
+                                                 buf_len: usize) -> c_int
+{
+    match msg.as_ref().and_then(|msg| msg.0.questions.get(i)) {
+        Some(q) => write_str(&q.qname.to_borrowed().to_string(), buf, buf_len),
+        None => BLOSH_EINVAL,
+    }
+}
+
+unsafe fn section<'a>(msg: *const BloshDnsMessage, section: c_int) -> Option<&'a [OwnedResourceRecord]> {
+    let msg = &msg.as_ref()?.0;
+    match section {
+        BLOSH_SECTION_ANSWER => Some(&msg.answers),
+        BLOSH_SECTION_AUTHORITY => Some(&msg.authorities),
+        BLOSH_SECTION_ADDITIONAL => Some(&msg.additional),
+        _ => None,
+    }
+}
+
+/// The number of records in one of the `BLOSH_SECTION_*` sections.
+///
+/// # Safety
+///
+/// `msg` must be NULL or a live message from `blosh_parse_dns`.
+#[no_mangle]
+pub unsafe extern "C" fn blosh_dns_record_count(msg: *const BloshDnsMessage, sect: c_int) -> c_int {
+    match section(msg, sect) {
+        Some(records) => records.len() as c_int,
+        None => BLOSH_EINVAL,
+    }
+}
+
+/// Fills in the type, class and TTL of record `i` of a section.
+///
+/// # Safety
+///
+/// `msg` must be NULL or a live message from `blosh_parse_dns`, and `out`
+/// NULL or a writable `BloshDnsRecord`.
+#[no_mangle]
+pub unsafe extern "C" fn blosh_dns_record(msg: *const BloshDnsMessage, sect: c_int, i: usize,
+                                          out: *mut BloshDnsRecord) -> c_int
+{
+    match (section(msg, sect).and_then(|records| records.get(i)), out.as_mut()) {
+        (Some(rr), Some(out)) => {
+            *out = BloshDnsRecord {
+                rtype: rr.typ.to_u16(),
+                rclass: rr.class.to_u16(),
+                ttl: rr.ttl,
+            };
+            BLOSH_OK
+        },
+        _ => BLOSH_EINVAL,
+    }
+}
+
+/// Writes the owner name of record `i` of a section into `buf`, the same
+/// way as `blosh_dns_question_name`.
+///
+/// # Safety
+///
+/// `msg` must be NULL or a live message from `blosh_parse_dns`, and `buf`
+/// NULL or `buf_len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn blosh_dns_record_name(msg: *const BloshDnsMessage, sect: c_int, i: usize,
+                                               buf: *mut c_char, buf_len: usize) -> c_int
+{
+    match section(msg, sect).and_then(|records| records.get(i)) {
+        Some(rr) => write_str(&rr.name.to_borrowed().to_string(), buf, buf_len),
+        None => BLOSH_EINVAL,
+    }
+}
+
+/// Writes the data of record `i` of a section into `buf` as text, like
+/// "192.0.2.1" or "10 mail.example.com", the same way as
+/// `blosh_dns_question_name`.
+///
+/// # Safety
+///
+/// `msg` must be NULL or a live message from `blosh_parse_dns`, and `buf`
+/// NULL or `buf_len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn blosh_dns_record_data(msg: *const BloshDnsMessage, sect: c_int, i: usize,
+                                               buf: *mut c_char, buf_len: usize) -> c_int
+{
+    match section(msg, sect).and_then(|records| records.get(i)) {
+        Some(rr) => write_str(&rr.rdata.to_borrowed().to_string(), buf, buf_len),
+        None => BLOSH_EINVAL,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+    use std::net::Ipv4Addr;
+    use testgen::{self, Endpoint};
+
+    #[test]
+    fn test_ffi() {
+        let (client, server) = (Endpoint::client(), Endpoint::server());
+        let (_, response) = testgen::dns_exchange(&client, &server, 0x1234, "www.example.com", Ipv4Addr::new(203, 0, 113, 9));
+        let ip = &response[14..];
+        unsafe {
+            let mut ip4 = BloshIpv4Header::default();
+            assert_eq!(blosh_parse_ipv4(ip.as_ptr(), ip.len(), &mut ip4), BLOSH_OK);
+            assert_eq!((ip4.header_len, ip4.proto, ip4.src), (20, 17, [198, 51, 100, 1]));
+            let mut udp = BloshUdpHeader::default();
+            assert_eq!(blosh_parse_udp(ip[20..].as_ptr(), ip.len() - 20, &mut udp), BLOSH_OK);
+            assert_eq!(udp.src_port, 53);
+            assert_eq!(blosh_parse_tcp(ip.as_ptr(), 3, &mut BloshTcpHeader::default()), BLOSH_EPARSE);
+
+            let dns = &ip[28..];
+            let msg = blosh_parse_dns(dns.as_ptr(), dns.len(), 0);
+            assert!(!msg.is_null());
+            assert_eq!(blosh_dns_id(msg), 0x1234);
+            assert_eq!(blosh_dns_flags(msg) & 0x8000, 0x8000);
+            assert_eq!(blosh_dns_record_count(msg, BLOSH_SECTION_ANSWER), 2);
+
+            let mut rr = BloshDnsRecord::default();
+            assert_eq!(blosh_dns_record(msg, BLOSH_SECTION_ANSWER, 1, &mut rr), BLOSH_OK);
+            assert_eq!((rr.rtype, rr.ttl), (1, 60));
+            let mut buf = [0 as c_char; 16];
+            assert_eq!(blosh_dns_record_data(msg, BLOSH_SECTION_ANSWER, 1, buf.as_mut_ptr(), buf.len()), 11);
+            assert_eq!(CStr::from_ptr(buf.as_ptr()).to_str(), Ok("203.0.113.9"));
+            // too small, so it's cut short but says how much room it needed
+            assert_eq!(blosh_dns_question_name(msg, 0, buf.as_mut_ptr(), 4), 15);
+            assert_eq!(CStr::from_ptr(buf.as_ptr()).to_str(), Ok("www"));
+            assert_eq!(blosh_dns_record_count(msg, 7), BLOSH_EINVAL);
+            blosh_dns_free(msg);
+        }
+    }
+}
@@ -0,0 +1,531 @@
+use std::error;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use dissect::Packet;
+use {IpPacket, TransportLayerPacket};
+
+/// A compiled tcpdump style filter, like "udp and port 53 and host 10.0.0.1".
+///
+/// Supported primitives are `ip`, `ip6`, `tcp`, `udp`, `icmp`, `vlan [id]`,
+/// and `[src|dst] host ADDR`, `[src|dst] net ADDR/LEN`, `[src|dst] port N`
+/// and `[src|dst] portrange N-M`. `ip`, `ip6`, `tcp` or `udp` can go in
+/// front of the address and port primitives, so "tcp port 53" is short for
+/// "tcp and port 53". Ports can also be given by their well-known service
+/// name, from a short built-in list rather than /etc/services, but port
+/// ranges only by number. Primitives can be combined with `and`/`&&`,
+/// `or`/`||`, `not`/`!` and parentheses. As in tcpdump, `not` binds tightest
+/// and `and` and `or` are equal, grouped left to right, so "udp or tcp and
+/// port 80" means "(udp or tcp) and port 80".
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Filter {
+    expr: Expr,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr {
+    // runs of the same operator are kept flat, so long lists of hosts or
+    // ports don't nest
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    Primitive(Primitive),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Dir {
+    Src,
+    Dst,
+    Either,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Primitive {
+    Ip,
+    Ip6,
+    Tcp,
+    Udp,
+    Icmp,
+    Vlan(Option<u16>),
+    Host(Dir, IpAddr),
+    Net(Dir, IpAddr, u8),
+    PortRange(Dir, u16, u16),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    // byte offset into the filter string
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.position)
+    }
+}
+
+impl error::Error for ParseError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token<'a> {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Word(&'a str),
+}
+
+fn tokenize<'a>(s: &'a str) -> Vec<(usize, Token<'a>)> {
+    let mut tokens = vec![];
+    let mut chars = s.char_indices().peekable();
+    while let Some(&(i, c)) = chars.peek() {
+        let (len, token) = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            },
+            '(' => (1, Token::LParen),
+            ')' => (1, Token::RParen),
+            '!' => (1, Token::Not),
+            '&' if s[i..].starts_with("&&") => (2, Token::And),
+            '|' if s[i..].starts_with("||") => (2, Token::Or),
+            _ => {
+                let end = s[i..]
+                    .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                    .map_or(s.len(), |n| i + n);
+                let token = match &s[i..end] {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    word => Token::Word(word),
+                };
+                (end - i, token)
+            },
+        };
+        tokens.push((i, token));
+        while chars.peek().is_some_and(|&(j, _)| j < i + len) {
+            chars.next();
+        }
+    }
+    tokens
+}
+
+// How deeply filters can nest, as parsing, matching and dropping them all
+// recurse
+const MAX_DEPTH: usize = 256;
+
+struct Parser<'a> {
+    tokens: Vec<(usize, Token<'a>)>,
+    pos: usize,
+    end: usize,
+}
+
+impl <'a> Parser<'a> {
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.pos).map(|&(_, t)| t)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |&(i, _)| i)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, ParseError> {
+        Err(ParseError {
+            position: self.offset(),
+            message: message.to_string(),
+        })
+    }
+
+    fn word(&mut self, what: &str) -> Result<&'a str, ParseError> {
+        match self.peek() {
+            Some(Token::Word(w)) => {
+                self.pos += 1;
+                Ok(w)
+            },
+            _ => self.error(&format!("expected {}", what)),
+        }
+    }
+
+    // `and` and `or` have the same precedence and group left to right, as
+    // in pcap-filter(7). `depth` is how far down the expression is, and its
+    // height comes back with it.
+    fn parse_expr(&mut self, depth: usize) -> Result<(Expr, usize), ParseError> {
+        let (mut expr, mut height) = self.parse_not(depth)?;
+        loop {
+            let and = match self.peek() {
+                Some(Token::And) => true,
+                Some(Token::Or) => false,
+                _ => return Ok((expr, height)),
+            };
+            self.pos += 1;
+            let (right, right_height) = self.parse_not(depth)?;
+            height = height.max(right_height);
+            expr = match (and, expr) {
+                (true, Expr::And(mut exprs)) | (false, Expr::Or(mut exprs)) => {
+                    exprs.push(right);
+                    if and { Expr::And(exprs) } else { Expr::Or(exprs) }
+                },
+                (_, left) => {
+                    height += 1;
+                    if and { Expr::And(vec![left, right]) } else { Expr::Or(vec![left, right]) }
+                },
+            };
+            if depth + height > MAX_DEPTH {
+                return self.error("filter nested too deeply");
+            }
+        }
+    }
+
+    fn parse_not(&mut self, depth: usize) -> Result<(Expr, usize), ParseError> {
+        match self.peek() {
+            Some(Token::Not) | Some(Token::LParen) if depth >= MAX_DEPTH => self.error("filter nested too deeply"),
+            Some(Token::Not) => {
+                self.pos += 1;
+                let (expr, height) = self.parse_not(depth + 1)?;
+                Ok((Expr::Not(Box::new(expr)), height + 1))
+            },
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let (expr, height) = self.parse_expr(depth + 1)?;
+                if self.peek() != Some(Token::RParen) {
+                    return self.error("expected )");
+                }
+                self.pos += 1;
+                Ok((expr, height))
+            },
+            Some(Token::Word(_)) => self.parse_qualified(),
+            _ => self.error("expected a primitive"),
+        }
+    }
+
+    // a protocol in front of an address or port primitive is anded with it
+    fn parse_qualified(&mut self) -> Result<(Expr, usize), ParseError> {
+        let primitive = self.parse_primitive()?;
+        let qualifiable = matches!(primitive, Primitive::Ip | Primitive::Ip6 | Primitive::Tcp | Primitive::Udp);
+        match self.peek() {
+            Some(Token::Word("src")) | Some(Token::Word("dst")) | Some(Token::Word("host"))
+            | Some(Token::Word("net")) | Some(Token::Word("port")) | Some(Token::Word("portrange"))
+                if qualifiable => {
+                let qualified = self.parse_primitive()?;
+                Ok((Expr::And(vec![Expr::Primitive(primitive), Expr::Primitive(qualified)]), 1))
+            },
+            _ => Ok((Expr::Primitive(primitive), 0)),
+        }
+    }
+
+    fn parse_primitive(&mut self) -> Result<Primitive, ParseError> {
+        let start = self.offset();
+        let mut keyword = self.word("a primitive")?;
+        let dir = match keyword {
+            "src" => Dir::Src,
+            "dst" => Dir::Dst,
+            _ => Dir::Either,
+        };
+        if dir != Dir::Either {
+            keyword = self.word("host, net, port or portrange")?;
+        }
+
+        let primitive = match keyword {
+            "ip" => Primitive::Ip,
+            "ip6" => Primitive::Ip6,
+            "tcp" => Primitive::Tcp,
+            "udp" => Primitive::Udp,
+            "icmp" => Primitive::Icmp,
+            "vlan" => {
+                // the id is optional
+                let id = match self.peek() {
+                    Some(Token::Word(w)) => match w.parse() {
+                        Ok(id) => {
+                            self.pos += 1;
+                            Some(id)
+                        },
+                        Err(_) => None,
+                    },
+                    _ => None,
+                };
+                Primitive::Vlan(id)
+            },
+            "host" => {
+                let addr = self.word("an address")?;
+                match addr.parse() {
+                    Ok(addr) => Primitive::Host(dir, addr),
+                    Err(_) => return self.back().error("invalid address"),
+                }
+            },
+            "net" => {
+                let net = self.word("a network")?;
+                match parse_net(net) {
+                    Some((addr, len)) => Primitive::Net(dir, addr, len),
+                    None => return self.back().error("invalid network"),
+                }
+            },
+            "port" => {
+                let port = self.word("a port")?;
+                match port.parse().ok().or_else(|| service_port(port)) {
+                    Some(port) => Primitive::PortRange(dir, port, port),
+                    None => return self.back().error("invalid port"),
+                }
+            },
+            "portrange" => {
+                let range = self.word("a port range")?;
+                let mut ends = range.splitn(2, '-').map(|p| p.parse::<u16>());
+                match (ends.next(), ends.next()) {
+                    (Some(Ok(lo)), Some(Ok(hi))) if lo <= hi => Primitive::PortRange(dir, lo, hi),
+                    _ => return self.back().error("invalid port range"),
+                }
+            },
+            _ => return Err(ParseError {
+                position: start,
+                message: format!("unknown primitive {}", keyword),
+            }),
+        };
+
+        match primitive {
+            Primitive::Host(..) | Primitive::Net(..) | Primitive::PortRange(..) => {},
+            _ if dir != Dir::Either => return Err(ParseError {
+                position: start,
+                message: format!("{} can't take a direction", keyword),
+            }),
+            _ => {},
+        }
+        Ok(primitive)
+    }
+
+    // steps back to the last token, for errors about its value
+    fn back(&mut self) -> &mut Parser<'a> {
+        self.pos -= 1;
+        self
+    }
+}
+
+// the common names from /etc/services
+fn service_port(name: &str) -> Option<u16> {
+    let port = match name {
+        "ftp-data" => 20,
+        "ftp" => 21,
+        "ssh" => 22,
+        "telnet" => 23,
+        "smtp" => 25,
+        "domain" => 53,
+        "bootps" => 67,
+        "bootpc" => 68,
+        "tftp" => 69,
+        "http" => 80,
+        "pop3" => 110,
+        "ntp" => 123,
+        "imap" => 143,
+        "snmp" => 161,
+        "bgp" => 179,
+        "ldap" => 389,
+        "https" => 443,
+        "syslog" => 514,
+        "submission" => 587,
+        "imaps" => 993,
+        "pop3s" => 995,
+        "mdns" => 5353,
+        _ => return None,
+    };
+    Some(port)
+}
+
+fn parse_net(s: &str) -> Option<(IpAddr, u8)> {
+    let mut parts = s.splitn(2, '/');
+    let addr: IpAddr = parts.next()?.parse().ok()?;
+    let max_len = match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    };
+    let len = match parts.next() {
+        Some(len) => len.parse().ok()?,
+        None => max_len,
+    };
+    if len > max_len {
+        None
+    } else {
+        Some((addr, len))
+    }
+}
+
+fn in_net(addr: IpAddr, net: IpAddr, len: u8) -> bool {
+    fn prefix_eq(a: &[u8], b: &[u8], len: u8) -> bool {
+        let bytes = (len / 8) as usize;
+        let bits = len % 8;
+        a[..bytes] == b[..bytes] && (bits == 0 || (a[bytes] ^ b[bytes]) >> (8 - bits) == 0)
+    }
+    match (addr, net) {
+        (IpAddr::V4(a), IpAddr::V4(n)) => prefix_eq(&a.octets(), &n.octets(), len),
+        (IpAddr::V6(a), IpAddr::V6(n)) => prefix_eq(&a.octets(), &n.octets(), len),
+        _ => false,
+    }
+}
+
+fn matches_dir<T: Copy, F: Fn(T) -> bool>(dir: Dir, src: T, dst: T, f: F) -> bool {
+    match dir {
+        Dir::Src => f(src),
+        Dir::Dst => f(dst),
+        Dir::Either => f(src) || f(dst),
+    }
+}
+
+impl Primitive {
+    fn matches(&self, packet: &Packet) -> bool {
+        let ports = match packet.transport {
+            Some(TransportLayerPacket::Tcp(ref tcp)) => Some((tcp.header.src, tcp.header.dst)),
+            Some(TransportLayerPacket::Udp(ref udp)) => Some((udp.header.src, udp.header.dst)),
+            _ => None,
+        };
+        match *self {
+            Primitive::Ip => matches!(packet.ip, Some(IpPacket::V4(_))),
+            Primitive::Ip6 => matches!(packet.ip, Some(IpPacket::V6(_))),
+            Primitive::Tcp => matches!(packet.transport, Some(TransportLayerPacket::Tcp(_))),
+            Primitive::Udp => matches!(packet.transport, Some(TransportLayerPacket::Udp(_))),
+            Primitive::Icmp => matches!(packet.transport, Some(TransportLayerPacket::Icmp(_))),
+            Primitive::Vlan(id) => match packet.ethernet {
+                Some(ref eth) => match id {
+                    Some(id) => eth.vlans.iter().any(|v| v.vid == id),
+                    None => !eth.vlans.is_empty(),
+                },
+                None => false,
+            },
+            Primitive::Host(dir, addr) => match packet.ip {
+                Some(ref ip) => matches_dir(dir, ip.src_ip(), ip.dst_ip(), |a| a == addr),
+                None => false,
+            },
+            Primitive::Net(dir, net, len) => match packet.ip {
+                Some(ref ip) => matches_dir(dir, ip.src_ip(), ip.dst_ip(), |a| in_net(a, net, len)),
+                None => false,
+            },
+            Primitive::PortRange(dir, lo, hi) => match ports {
+                Some((src, dst)) => matches_dir(dir, src, dst, |p| lo <= p && p <= hi),
+                None => false,
+            },
+        }
+    }
+}
+
+impl Expr {
+    fn matches(&self, packet: &Packet) -> bool {
+        match *self {
+            Expr::And(ref exprs) => exprs.iter().all(|e| e.matches(packet)),
+            Expr::Or(ref exprs) => exprs.iter().any(|e| e.matches(packet)),
+            Expr::Not(ref a) => !a.matches(packet),
+            Expr::Primitive(ref p) => p.matches(packet),
+        }
+    }
+}
+
+impl Filter {
+    pub fn parse(s: &str) -> Result<Filter, ParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(s),
+            pos: 0,
+            end: s.len(),
+        };
+        let (expr, _) = parser.parse_expr(0)?;
+        if parser.pos != parser.tokens.len() {
+            return parser.error("unexpected trailing input");
+        }
+        Ok(Filter { expr })
+    }
+
+    /// Whether a dissected packet passes the filter
+    pub fn matches(&self, packet: &Packet) -> bool {
+        self.expr.matches(packet)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Filter, ParseError> {
+        Filter::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dissect::{parse_packet, LinkType};
+
+    // VLAN 100, 10.0.0.1:4312 -> 8.8.8.8:53 over UDP, no payload
+    const FRAME: [u8; 46] = [
+        0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0x81, 0x00, 0x20, 0x64,
+        0x08, 0x00,
+
+        0x45, 0x00, 0x00, 0x1c, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x01,
+        0x08, 0x08, 0x08, 0x08,
+
+        0x10, 0xd8, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00,
+    ];
+
+    fn check(filter: &str) -> bool {
+        let packet = parse_packet(LinkType::Ethernet, &FRAME);
+        Filter::parse(filter).unwrap().matches(&packet)
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(check("udp and port 53 and host 10.0.0.1"));
+        assert!(check("dst port 53 && src host 10.0.0.1"));
+        assert!(!check("src port 53"));
+        assert!(check("tcp or (udp and not port 80)"));
+        assert!(!check("!udp"));
+        assert!(check("net 10.0.0.0/8 and dst net 8.8.0.0/16"));
+        assert!(!check("src net 10.0.0.128/25"));
+        assert!(check("portrange 4000-4400"));
+        assert!(check("vlan 100 and ip and not ip6"));
+        assert!(!check("vlan 200"));
+        assert!(!check("icmp"));
+    }
+
+    #[test]
+    fn test_qualified() {
+        assert!(check("udp port 53"));
+        assert!(!check("tcp port 53"));
+        assert!(check("udp dst port domain"));
+        assert!(check("ip host 10.0.0.1 and ip net 8.0.0.0/8"));
+        assert!(!check("ip6 host 10.0.0.1"));
+        assert!(check("udp portrange 50-60"));
+        assert!(!check("port http"));
+        assert!(check("tcp port 53 or udp and host 10.0.0.1"));
+        assert_eq!(Filter::parse("tcp port 53 or udp and host 1.2.3.4"),
+                   Filter::parse("((tcp and port 53) or udp) and host 1.2.3.4"));
+        assert_eq!(Filter::parse("tcp src port https"), Filter::parse("tcp and src port 443"));
+        assert_eq!(Filter::parse("port bogus").unwrap_err().message, "invalid port");
+        assert!(Filter::parse("icmp port 53").is_err());
+        assert!(Filter::parse("tcp udp").is_err());
+    }
+
+    #[test]
+    fn test_precedence() {
+        // and and or are grouped left to right
+        assert!(check("tcp and port 80 or udp"));
+        assert!(!check("udp or tcp and port 80"));
+        assert!(check("udp or (tcp and port 80)"));
+        assert!(!check("not udp or tcp"));
+        assert_eq!(Filter::parse("udp or tcp and port 80"), Filter::parse("(udp or tcp) and port 80"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Filter::parse("udp and").unwrap_err().position, 7);
+        assert_eq!(Filter::parse("host 10.0.0.300").unwrap_err().position, 5);
+        assert_eq!(Filter::parse("(udp").unwrap_err().message, "expected )");
+        assert_eq!(Filter::parse("src udp").unwrap_err().position, 0);
+        assert!(Filter::parse("bogus").is_err());
+        assert!(Filter::parse("udp tcp").is_err());
+
+        let parens = "(".repeat(200000);
+        assert_eq!(Filter::parse(&parens).unwrap_err().message, "filter nested too deeply");
+        assert!(Filter::parse(&"not ".repeat(1000)).is_err());
+        let alternating = format!("{}udp", "udp and tcp or ".repeat(1000));
+        assert_eq!(Filter::parse(&alternating).unwrap_err().message, "filter nested too deeply");
+        let nested = format!("{}udp{}", "(".repeat(100), ")".repeat(100));
+        assert!(Filter::parse(&nested).is_ok());
+        // long lists of the same operator don't nest
+        assert!(Filter::parse(&format!("{}udp", "tcp or ".repeat(10000))).is_ok());
+    }
+}
@@ -0,0 +1,267 @@
+use std::fmt;
+
+use dhcp::{DhcpMessage, MessageType};
+use tcp::{TcpHeader, TcpOption};
+use {IpPacket, TransportLayerPacket};
+
+/// What a SYN gives away about the stack that sent it, along the lines of
+/// p0f's TCP signatures
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SynFingerprint {
+    pub ttl: u8,
+    pub window: u16,
+    pub mss: Option<u16>,
+    pub wscale: Option<u8>,
+    // the option kinds in the order they were sent, like
+    // "mss,sok,ts,nop,ws"
+    pub layout: String,
+}
+
+impl SynFingerprint {
+    /// Fingerprints a segment given the TTL or hop limit of the IP header
+    /// it came in
+    pub fn new(ttl: u8, tcp: &TcpHeader) -> SynFingerprint {
+        let mut fingerprint = SynFingerprint {
+            ttl,
+            window: tcp.window_sz,
+            mss: None,
+            wscale: None,
+            layout: String::new(),
+        };
+        let mut kinds = vec![];
+        for option in &tcp.options {
+            kinds.push(match *option {
+                TcpOption::DummyOption => continue,
+                TcpOption::EndOfOptionList => "eol".to_string(),
+                TcpOption::NoOperation => "nop".to_string(),
+                TcpOption::MaximumSegmentSize(mss) => {
+                    fingerprint.mss = Some(mss);
+                    "mss".to_string()
+                },
+                TcpOption::WindowScale(wscale) => {
+                    fingerprint.wscale = Some(wscale);
+                    "ws".to_string()
+                },
+                TcpOption::Timestamps(_, _) => "ts".to_string(),
+                TcpOption::MD5(_) => "md5".to_string(),
+                TcpOption::Other(4, _, _) => "sok".to_string(),
+                TcpOption::Other(5, _, _) => "sack".to_string(),
+                TcpOption::Other(kind, _, _) => format!("?{}", kind),
+            });
+        }
+        fingerprint.layout = kinds.join(",");
+        fingerprint
+    }
+
+    /// Fingerprints an IP packet if it's a TCP SYN, not a SYN+ACK
+    pub fn from_packet(packet: &IpPacket) -> Option<SynFingerprint> {
+        let ttl = match packet {
+            IpPacket::V4(ip4) => ip4.header.ttl,
+            IpPacket::V6(ip6) => ip6.header.hop_limit,
+        };
+        match packet.parse_inner() {
+            Some(TransportLayerPacket::Tcp(ref tcp)) if tcp.header.flags.syn && !tcp.header.flags.ack =>
+                Some(SynFingerprint::new(ttl, &tcp.header)),
+            _ => None,
+        }
+    }
+
+    /// The TTL the sender most likely started from: the smallest of the
+    /// usual defaults that's at least the one seen
+    pub fn initial_ttl(&self) -> u8 {
+        [32, 64, 128, 255].iter().cloned().find(|&ttl| ttl >= self.ttl).unwrap_or(255)
+    }
+
+    /// How many hops the packet took, going by `initial_ttl`
+    pub fn distance(&self) -> u8 {
+        self.initial_ttl() - self.ttl
+    }
+
+    /// The best match in the built-in signatures. A signature whose window
+    /// or window scale doesn't match can still match loosely, on the
+    /// initial TTL and option layout alone.
+    pub fn identify(&self) -> Option<Guess> {
+        let loose = |sig: &&Signature| sig.ttl == self.initial_ttl() && sig.layout == self.layout;
+        let exact = |sig: &&Signature| loose(sig) && sig.window.matches(self.window, self.mss)
+            && sig.wscale.is_none_or(|wscale| Some(wscale) == self.wscale);
+        SIGNATURES.iter().find(exact)
+            .map(|sig| Guess { label: sig.label, fuzzy: false })
+            .or_else(|| SIGNATURES.iter().find(loose).map(|sig| Guess { label: sig.label, fuzzy: true }))
+    }
+}
+
+/// Like p0f's raw signatures, "64:mss*20,7:mss,sok,ts,nop,ws"
+impl fmt::Display for SynFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:", self.initial_ttl())?;
+        match self.mss {
+            Some(mss) if mss > 0 && self.window.is_multiple_of(mss) => write!(f, "mss*{}", self.window / mss)?,
+            _ => write!(f, "{}", self.window)?,
+        }
+        match self.wscale {
+            Some(wscale) => write!(f, ",{}", wscale)?,
+            None => write!(f, ",*")?,
+        }
+        write!(f, ":{}", self.layout)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Guess {
+    pub label: &'static str,
+    // only the initial TTL and option layout matched
+    pub fuzzy: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowSig {
+    Fixed(u16),
+    // a multiple of the MSS
+    Mss(u16),
+    Any,
+}
+
+impl WindowSig {
+    pub fn matches(&self, window: u16, mss: Option<u16>) -> bool {
+        match *self {
+            WindowSig::Fixed(w) => w == window,
+            WindowSig::Mss(n) => mss.is_some_and(|mss| mss as u32 * n as u32 == window as u32),
+            WindowSig::Any => true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Signature {
+    pub label: &'static str,
+    pub ttl: u8,
+    pub window: WindowSig,
+    pub wscale: Option<u8>,
+    pub layout: &'static str,
+}
+
+/// A small set of common stacks, more specific ones first, taken from p0f
+/// and captures of current releases
+pub const SIGNATURES: &[Signature] = &[
+    Signature { label: "Linux 3.11 and newer", ttl: 64, window: WindowSig::Mss(44), wscale: None, layout: "mss,sok,ts,nop,ws" },
+    Signature { label: "Linux 3.11 and newer", ttl: 64, window: WindowSig::Mss(20), wscale: None, layout: "mss,sok,ts,nop,ws" },
+    Signature { label: "Linux 2.6.x", ttl: 64, window: WindowSig::Mss(10), wscale: None, layout: "mss,sok,ts,nop,ws" },
+    Signature { label: "Linux", ttl: 64, window: WindowSig::Any, wscale: None, layout: "mss,sok,ts,nop,ws" },
+    Signature { label: "Windows 10 and newer", ttl: 128, window: WindowSig::Fixed(64240), wscale: Some(8), layout: "mss,nop,ws,nop,nop,sok" },
+    Signature { label: "Windows 7 or 8", ttl: 128, window: WindowSig::Fixed(8192), wscale: Some(8), layout: "mss,nop,ws,nop,nop,sok" },
+    Signature { label: "Windows", ttl: 128, window: WindowSig::Any, wscale: None, layout: "mss,nop,ws,nop,nop,sok" },
+    Signature { label: "Windows XP", ttl: 128, window: WindowSig::Any, wscale: None, layout: "mss,nop,nop,sok" },
+    Signature { label: "macOS or iOS", ttl: 64, window: WindowSig::Fixed(65535), wscale: None, layout: "mss,nop,ws,nop,nop,ts,sok,eol" },
+    Signature { label: "FreeBSD", ttl: 64, window: WindowSig::Fixed(65535), wscale: Some(6), layout: "mss,nop,ws,sok,ts" },
+    Signature { label: "OpenBSD", ttl: 64, window: WindowSig::Fixed(16384), wscale: Some(3), layout: "mss,nop,nop,sok,nop,ws,nop,nop,ts" },
+    Signature { label: "Solaris", ttl: 64, window: WindowSig::Any, wscale: None, layout: "nop,nop,ts,mss,nop,ws,nop,nop,sok" },
+];
+
+/// What a DHCP client's requests give away about it, the way fingerbank
+/// classifies devices: the options it asks for, in the order it asks, and
+/// its vendor class
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DhcpFingerprint {
+    /// Option 55, like "1,3,6,15,31,33,43,44,46,47,119,121,249,252"
+    pub parameter_list: String,
+    /// Option 60, like "MSFT 5.0"
+    pub vendor_class: Option<String>,
+}
+
+impl DhcpFingerprint {
+    /// Fingerprints a message from a client. Servers' replies and messages
+    /// without a parameter request list have nothing to go on.
+    pub fn new(message: &DhcpMessage) -> Option<DhcpFingerprint> {
+        match message.message_type() {
+            Some(MessageType::Offer) | Some(MessageType::Ack) | Some(MessageType::Nak) => return None,
+            _ => (),
+        }
+        let parameters = message.parameter_request_list()?;
+        Some(DhcpFingerprint {
+            parameter_list: parameters.iter().map(|code| code.to_string()).collect::<Vec<_>>().join(","),
+            vendor_class: message.vendor_class().map(|class| String::from_utf8_lossy(class).into_owned()),
+        })
+    }
+}
+
+/// The parameter list, then the vendor class after a "|" if there is one:
+/// "1,3,6,15|MSFT 5.0"
+impl fmt::Display for DhcpFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.parameter_list)?;
+        match self.vendor_class {
+            Some(ref class) => write!(f, "|{}", class),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use dhcp::{DhcpOption, Op};
+    use tcp::parse_tcp_packet;
+
+    #[test]
+    fn test_linux_syn() {
+        // a SYN from curl on Linux 6.x
+        let segment = [
+            0xa4, 0x4e, 0x00, 0x50, 0x5b, 0x2f, 0x3a, 0x10, 0x00, 0x00, 0x00, 0x00, 0xa0, 0x02, 0xfa, 0xf0,
+            0x00, 0x00, 0x00, 0x00, 0x02, 0x04, 0x05, 0xb4, 0x04, 0x02, 0x08, 0x0a, 0x6d, 0x1b, 0x2c, 0x41,
+            0x00, 0x00, 0x00, 0x00, 0x01, 0x03, 0x03, 0x07,
+        ];
+        let (_, tcp) = parse_tcp_packet(&segment).unwrap();
+        let fingerprint = SynFingerprint::new(57, &tcp.header);
+        assert_eq!(fingerprint.to_string(), "64:mss*44,7:mss,sok,ts,nop,ws");
+        assert_eq!(fingerprint.distance(), 7);
+        assert_eq!(fingerprint.identify(), Some(Guess { label: "Linux 3.11 and newer", fuzzy: false }));
+
+        let mut windows = fingerprint.clone();
+        windows.ttl = 120;
+        windows.layout = "mss,nop,ws,nop,nop,sok".to_string();
+        windows.window = 65535;
+        assert_eq!(windows.identify(), Some(Guess { label: "Windows", fuzzy: false }));
+        windows.ttl = 60;
+        assert_eq!(windows.identify(), None);
+    }
+
+    #[test]
+    fn test_dhcp_fingerprint() {
+        let options = [
+            DhcpOption { code: 53, data: &[1] },
+            DhcpOption { code: 55, data: &[1, 121, 3, 6, 15, 119, 252] },
+            DhcpOption { code: 60, data: b"android-dhcp-13" },
+        ];
+        let mut discover = DhcpMessage {
+            op: Op::Request,
+            htype: 1,
+            hops: 0,
+            xid: 1,
+            secs: 0,
+            broadcast: false,
+            ciaddr: Ipv4Addr::UNSPECIFIED,
+            yiaddr: Ipv4Addr::UNSPECIFIED,
+            siaddr: Ipv4Addr::UNSPECIFIED,
+            giaddr: Ipv4Addr::UNSPECIFIED,
+            chaddr: &[0x02, 0x00, 0x00, 0x00, 0x00, 0x01],
+            sname: &[],
+            file: &[],
+            options: options.to_vec(),
+        };
+        let fingerprint = DhcpFingerprint::new(&discover).unwrap();
+        assert_eq!(fingerprint.parameter_list, "1,121,3,6,15,119,252");
+        assert_eq!(fingerprint.to_string(), "1,121,3,6,15,119,252|android-dhcp-13");
+
+        discover.options.pop();
+        assert_eq!(DhcpFingerprint::new(&discover).unwrap().to_string(), "1,121,3,6,15,119,252");
+        discover.options[0].data = &[2];
+        assert_eq!(DhcpFingerprint::new(&discover), None);
+        discover.options.remove(0);
+        discover.options.pop();
+        assert_eq!(DhcpFingerprint::new(&discover), None);
+    }
+}
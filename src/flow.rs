@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+use std::collections::hash_map;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use ipv4::Ipv4Protocol;
+use {Direction, IpPacket, Timestamp, TransportLayerPacket};
+
+/// The usual 5-tuple identifying a transport layer flow, in the direction
+/// the packet it was taken from was travelling
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FlowKey {
+    pub src_ip: IpAddr,
+    pub dst_ip: IpAddr,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub proto: Ipv4Protocol,
+}
+
+impl FlowKey {
+    pub fn from_packets(ip: &IpPacket, transport: &TransportLayerPacket) -> FlowKey {
+        let (src_port, dst_port, proto) = match transport {
+            TransportLayerPacket::Tcp(tcp) => (tcp.header.src, tcp.header.dst, Ipv4Protocol::Tcp),
+            TransportLayerPacket::Udp(udp) => (udp.header.src, udp.header.dst, Ipv4Protocol::Udp),
+            &TransportLayerPacket::Icmp(_) => (0, 0, Ipv4Protocol::Icmp),
+        };
+        FlowKey {
+            src_ip: ip.src_ip(),
+            dst_ip: ip.dst_ip(),
+            src_port,
+            dst_port,
+            proto,
+        }
+    }
+
+    /// The key for packets going the other way
+    pub fn reversed(&self) -> FlowKey {
+        FlowKey {
+            src_ip: self.dst_ip,
+            dst_ip: self.src_ip,
+            src_port: self.dst_port,
+            dst_port: self.src_port,
+            proto: self.proto,
+        }
+    }
+
+    /// Orders the endpoints so that both directions of a conversation give
+    /// the same key, and says which direction this key was in
+    pub fn canonical(&self) -> (FlowKey, Direction) {
+        if (self.src_ip, self.src_port) <= (self.dst_ip, self.dst_port) {
+            (*self, Direction::Forward)
+        } else {
+            (self.reversed(), Direction::Reverse)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FlowCounters {
+    pub packets: u64,
+    pub bytes: u64,
+    // every TCP flag seen, ORed together as `TcpFlags::to_u8` lays them out
+    pub tcp_flags: u8,
+}
+
+/// A conversation between two endpoints; `key` is oriented the way the
+/// first packet seen was travelling, which is the forward direction
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Flow {
+    pub key: FlowKey,
+    pub first_seen: Timestamp,
+    pub last_seen: Timestamp,
+    pub forward: FlowCounters,
+    pub reverse: FlowCounters,
+}
+
+impl Flow {
+    pub fn counters(&self, dir: Direction) -> &FlowCounters {
+        match dir {
+            Direction::Forward => &self.forward,
+            Direction::Reverse => &self.reverse,
+        }
+    }
+
+    pub fn direction_of(&self, key: &FlowKey) -> Direction {
+        if *key == self.key {
+            Direction::Forward
+        } else {
+            Direction::Reverse
+        }
+    }
+
+    /// The flow as NetFlow v5 style records, which only go one way: the
+    /// forward direction, then the reverse one if it saw any packets
+    pub fn records(&self) -> Vec<FlowRecord> {
+        let mut records = vec![FlowRecord::new(self, Direction::Forward)];
+        if self.reverse.packets > 0 {
+            records.push(FlowRecord::new(self, Direction::Reverse));
+        }
+        records
+    }
+}
+
+/// One direction of a flow, with the fields of a NetFlow v5 record. Times
+/// are capture times rather than the exporter's uptime, and addresses can
+/// be IPv6, so these don't fit the v5 wire format as they are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FlowRecord {
+    pub src_ip: IpAddr,
+    pub dst_ip: IpAddr,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub proto: Ipv4Protocol,
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub packets: u64,
+    pub bytes: u64,
+    pub tcp_flags: u8,
+}
+
+impl FlowRecord {
+    /// The record for one direction of `flow`. Flows aren't timestamped per
+    /// direction, so both directions get the flow's first and last times.
+    pub fn new(flow: &Flow, dir: Direction) -> FlowRecord {
+        let key = match dir {
+            Direction::Forward => flow.key,
+            Direction::Reverse => flow.key.reversed(),
+        };
+        let counters = flow.counters(dir);
+        FlowRecord {
+            src_ip: key.src_ip,
+            dst_ip: key.dst_ip,
+            src_port: key.src_port,
+            dst_port: key.dst_port,
+            proto: key.proto,
+            start: flow.first_seen,
+            end: flow.last_seen,
+            packets: counters.packets,
+            bytes: counters.bytes,
+            tcp_flags: counters.tcp_flags,
+        }
+    }
+}
+
+/// Tracks flows in both directions under one entry, evicting those that
+/// have been idle too long
+pub struct FlowTable {
+    flows: HashMap<FlowKey, Flow>,
+    idle_timeout: Duration,
+}
+
+impl FlowTable {
+    pub fn new(idle_timeout: Duration) -> FlowTable {
+        FlowTable {
+            flows: HashMap::new(),
+            idle_timeout,
+        }
+    }
+
+    /// Accounts a packet of `len` bytes to its flow, creating the flow if
+    /// needed, and returns the flow along with the packet's direction in it
+    pub fn update(&mut self, key: FlowKey, len: usize, now: Timestamp) -> (&Flow, Direction) {
+        let (flow, dir) = self.account(key, len, 0, now);
+        (flow, dir)
+    }
+
+    fn account(&mut self, key: FlowKey, len: usize, tcp_flags: u8, now: Timestamp) -> (&mut Flow, Direction) {
+        let (canonical, _) = key.canonical();
+        let flow = match self.flows.entry(canonical) {
+            hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            hash_map::Entry::Vacant(entry) => entry.insert(Flow {
+                key,
+                first_seen: now,
+                last_seen: now,
+                forward: FlowCounters::default(),
+                reverse: FlowCounters::default(),
+            }),
+        };
+        let dir = flow.direction_of(&key);
+        {
+            let counters = match dir {
+                Direction::Forward => &mut flow.forward,
+                Direction::Reverse => &mut flow.reverse,
+            };
+            counters.packets += 1;
+            counters.bytes += len as u64;
+            counters.tcp_flags |= tcp_flags;
+        }
+        if now > flow.last_seen {
+            flow.last_seen = now;
+        }
+        (flow, dir)
+    }
+
+    /// Accounts an IP packet carrying TCP, UDP or ICMP, along with its TCP
+    /// flags; other packets are ignored
+    pub fn track(&mut self, packet: &IpPacket, now: Timestamp) -> Option<Direction> {
+        let len = match packet {
+            IpPacket::V4(ip4) => ip4.header.total_len as usize,
+            IpPacket::V6(ip6) => ip6.header.payload_length as usize + 40,
+        };
+        let inner = packet.parse_inner()?;
+        let tcp_flags = match inner {
+            TransportLayerPacket::Tcp(ref tcp) => tcp.header.flags.to_u8(),
+            _ => 0,
+        };
+        let key = FlowKey::from_packets(packet, &inner);
+        Some(self.account(key, len, tcp_flags, now).1)
+    }
+
+    /// Looks up a flow by its key in either direction
+    pub fn get(&self, key: &FlowKey) -> Option<&Flow> {
+        self.flows.get(&key.canonical().0)
+    }
+
+    pub fn remove(&mut self, key: &FlowKey) -> Option<Flow> {
+        self.flows.remove(&key.canonical().0)
+    }
+
+    /// Removes and returns every flow that hasn't seen a packet within the
+    /// idle timeout as of `now`
+    pub fn expire(&mut self, now: Timestamp) -> Vec<Flow> {
+        let idle_timeout = self.idle_timeout;
+        let expired: Vec<FlowKey> = self.flows.iter()
+            .filter(|&(_, flow)| now >= flow.last_seen + idle_timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        expired.iter()
+            .filter_map(|key| self.flows.remove(key))
+            .collect()
+    }
+
+    /// Expires idle flows like `expire`, and returns them as records
+    pub fn export(&mut self, now: Timestamp) -> Vec<FlowRecord> {
+        self.expire(now).iter()
+            .flat_map(|flow| flow.records())
+            .collect()
+    }
+
+    pub fn iter<'a>(&'a self) -> hash_map::Values<'a, FlowKey, Flow> {
+        self.flows.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(src: &str, src_port: u16, dst: &str, dst_port: u16) -> FlowKey {
+        FlowKey {
+            src_ip: src.parse().unwrap(),
+            dst_ip: dst.parse().unwrap(),
+            src_port,
+            dst_port,
+            proto: Ipv4Protocol::Tcp,
+        }
+    }
+
+    #[test]
+    fn test_canonical() {
+        let a = key("10.0.0.2", 40000, "10.0.0.1", 80);
+        let b = a.reversed();
+        assert_eq!(a.canonical().0, b.canonical().0);
+        assert!(a.canonical().1 != b.canonical().1);
+    }
+
+    #[test]
+    fn test_flow_table() {
+        let mut table = FlowTable::new(Duration::from_secs(30));
+        let client = key("10.0.0.2", 40000, "10.0.0.1", 80);
+        let other = key("10.0.0.3", 40001, "10.0.0.1", 80);
+
+        assert_eq!(table.update(client, 60, Timestamp::new(1, 0)).1, Direction::Forward);
+        assert_eq!(table.update(client.reversed(), 1500, Timestamp::new(2, 0)).1, Direction::Reverse);
+        assert_eq!(table.update(client, 52, Timestamp::new(3, 0)).1, Direction::Forward);
+        table.update(other, 60, Timestamp::new(20, 0));
+        assert_eq!(table.len(), 2);
+
+        {
+            let flow = table.get(&client.reversed()).unwrap();
+            assert_eq!(flow.key, client);
+            assert_eq!(flow.forward, FlowCounters { packets: 2, bytes: 112, tcp_flags: 0 });
+            assert_eq!(flow.reverse, FlowCounters { packets: 1, bytes: 1500, tcp_flags: 0 });
+            assert_eq!(flow.first_seen, Timestamp::new(1, 0));
+            assert_eq!(flow.last_seen, Timestamp::new(3, 0));
+        }
+
+        let expired = table.expire(Timestamp::new(40, 0));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].key, client);
+        assert_eq!(table.len(), 1);
+        assert!(table.get(&other).is_some());
+
+        // a capture time so late the timeout can't be added to it
+        let end = Timestamp::new(u64::MAX, 0);
+        table.update(client, 60, end);
+        assert_eq!(table.expire(end).len(), 1);
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn test_export() {
+        use std::net::Ipv4Addr;
+        use ipv4::{Header, Ipv4Packet};
+        use tcp::{TcpHeader, TcpPacket};
+        use ToBytes;
+
+        let client = Ipv4Addr::new(10, 0, 0, 2);
+        let server = Ipv4Addr::new(10, 0, 0, 1);
+        let segment = |src, dst, syn, ack| {
+            let mut header = TcpHeader::new(src, dst);
+            header.flags.syn = syn;
+            header.flags.ack = ack;
+            TcpPacket::new(header, &[]).to_bytes()
+        };
+        let syn = segment(40000, 80, true, false);
+        let syn_ack = segment(80, 40000, true, true);
+        let ack = segment(40000, 80, false, true);
+
+        let mut table = FlowTable::new(Duration::from_secs(30));
+        let packets = [(client, server, &syn), (server, client, &syn_ack), (client, server, &ack)];
+        for (i, &(src, dst, body)) in packets.iter().enumerate() {
+            let ip = IpPacket::V4(Ipv4Packet::new(Header::new(Ipv4Protocol::Tcp, src, dst), body));
+            table.track(&ip, Timestamp::from_micros(100_000*i as u64));
+        }
+
+        let records = table.export(Timestamp::new(60, 0));
+        assert!(table.is_empty());
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0], FlowRecord {
+            src_ip: client.into(),
+            dst_ip: server.into(),
+            src_port: 40000,
+            dst_port: 80,
+            proto: Ipv4Protocol::Tcp,
+            start: Timestamp::new(0, 0),
+            end: Timestamp::from_micros(200_000),
+            packets: 2,
+            bytes: 80,
+            tcp_flags: 0x12,
+        });
+        assert_eq!(records[1].src_port, 80);
+        assert_eq!(records[1].packets, 1);
+        assert_eq!(records[1].tcp_flags, 0x12);
+    }
+}
@@ -0,0 +1,361 @@
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use nom::bytes::complete::take;
+use nom::combinator::{all_consuming, cond, verify};
+use nom::error::context;
+use nom::multi::many0;
+use nom::number::complete::{be_u8, be_u16, be_u24, be_u32};
+
+use error::IResult;
+use ipv6::slice2addr;
+
+// https://www.3gpp.org/DynaReport/29274.htm, version 2 only
+
+pub const PORT: u16 = 2123;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct GtpcMessage<'a> {
+    pub message_type: MessageType,
+    /// Set in a piggybacked message's carrier, which the other message
+    /// follows
+    pub piggybacked: bool,
+    /// Missing only from echo and a few node-level messages
+    pub teid: Option<u32>,
+    /// 24 bits, matching responses to requests
+    pub sequence: u32,
+    /// Only sent when the MP flag is
+    pub priority: Option<u8>,
+    pub ies: Vec<InformationElement<'a>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MessageType {
+    EchoRequest,
+    EchoResponse,
+    VersionNotSupported,
+    CreateSessionRequest,
+    CreateSessionResponse,
+    ModifyBearerRequest,
+    ModifyBearerResponse,
+    DeleteSessionRequest,
+    DeleteSessionResponse,
+    CreateBearerRequest,
+    CreateBearerResponse,
+    DeleteBearerRequest,
+    DeleteBearerResponse,
+    ReleaseAccessBearersRequest,
+    ReleaseAccessBearersResponse,
+    DownlinkDataNotification,
+    Other(u8),
+}
+
+impl MessageType {
+    pub fn from_u8(v: u8) -> MessageType {
+        match v {
+            1 => MessageType::EchoRequest,
+            2 => MessageType::EchoResponse,
+            3 => MessageType::VersionNotSupported,
+            32 => MessageType::CreateSessionRequest,
+            33 => MessageType::CreateSessionResponse,
+            34 => MessageType::ModifyBearerRequest,
+            35 => MessageType::ModifyBearerResponse,
+            36 => MessageType::DeleteSessionRequest,
+            37 => MessageType::DeleteSessionResponse,
+            95 => MessageType::CreateBearerRequest,
+            96 => MessageType::CreateBearerResponse,
+            99 => MessageType::DeleteBearerRequest,
+            100 => MessageType::DeleteBearerResponse,
+            170 => MessageType::ReleaseAccessBearersRequest,
+            171 => MessageType::ReleaseAccessBearersResponse,
+            176 => MessageType::DownlinkDataNotification,
+            x => MessageType::Other(x),
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            MessageType::EchoRequest => 1,
+            MessageType::EchoResponse => 2,
+            MessageType::VersionNotSupported => 3,
+            MessageType::CreateSessionRequest => 32,
+            MessageType::CreateSessionResponse => 33,
+            MessageType::ModifyBearerRequest => 34,
+            MessageType::ModifyBearerResponse => 35,
+            MessageType::DeleteSessionRequest => 36,
+            MessageType::DeleteSessionResponse => 37,
+            MessageType::CreateBearerRequest => 95,
+            MessageType::CreateBearerResponse => 96,
+            MessageType::DeleteBearerRequest => 99,
+            MessageType::DeleteBearerResponse => 100,
+            MessageType::ReleaseAccessBearersRequest => 170,
+            MessageType::ReleaseAccessBearersResponse => 171,
+            MessageType::DownlinkDataNotification => 176,
+            MessageType::Other(x) => x,
+        }
+    }
+}
+
+/// An information element as sent. The same type can turn up more than
+/// once in a message with different instance numbers meaning different
+/// things, like the sender's and the PGW's F-TEIDs in a Create Session
+/// Request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct InformationElement<'a> {
+    pub typ: u8,
+    pub instance: u8,
+    pub value: &'a [u8],
+}
+
+pub const IMSI: u8 = 1;
+pub const CAUSE: u8 = 2;
+pub const RECOVERY: u8 = 3;
+pub const APN: u8 = 71;
+pub const AMBR: u8 = 72;
+pub const EBI: u8 = 73;
+pub const MEI: u8 = 75;
+pub const MSISDN: u8 = 76;
+pub const RAT_TYPE: u8 = 82;
+pub const F_TEID: u8 = 87;
+pub const BEARER_CONTEXT: u8 = 93;
+
+/// The information elements this parser knows how to decode
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Ie<'a> {
+    Imsi(String),
+    /// 16 is request accepted; the offending IE can follow, left out here
+    Cause(u8),
+    /// The sender's restart counter
+    Recovery(u8),
+    /// Like "internet" or "ims.mnc001.mcc001.gprs"
+    Apn(String),
+    /// Aggregate maximum bit rates, in kbps
+    Ambr { uplink: u32, downlink: u32 },
+    /// EPS bearer ID
+    Ebi(u8),
+    Mei(String),
+    Msisdn(String),
+    /// 6 for E-UTRAN, 10 for NR
+    RatType(u8),
+    Fteid(Fteid),
+    /// A grouped IE, holding the bearer's own IEs
+    BearerContext(Vec<InformationElement<'a>>),
+}
+
+/// A fully qualified tunnel endpoint: where the sender wants a tunnel's
+/// traffic sent
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Fteid {
+    /// Which interface and node, like 10 for the S11 MME or 7 for the
+    /// S5/S8 PGW GTP-C side
+    pub interface_type: u8,
+    pub teid: u32,
+    pub ipv4: Option<Ipv4Addr>,
+    pub ipv6: Option<Ipv6Addr>,
+}
+
+impl <'a> InformationElement<'a> {
+    /// `None` for types this parser doesn't know, and for malformed values
+    pub fn decode(&self) -> Option<Ie<'a>> {
+        let value = self.value;
+        let ie = match self.typ {
+            IMSI => Ie::Imsi(tbcd(value)),
+            CAUSE => Ie::Cause(*value.first()?),
+            RECOVERY => Ie::Recovery(*value.first()?),
+            APN => Ie::Apn(apn(value)?),
+            AMBR if value.len() == 8 => Ie::Ambr {
+                uplink: u32::from_be_bytes([value[0], value[1], value[2], value[3]]),
+                downlink: u32::from_be_bytes([value[4], value[5], value[6], value[7]]),
+            },
+            EBI => Ie::Ebi(*value.first()? & 0x0f),
+            MEI => Ie::Mei(tbcd(value)),
+            MSISDN => Ie::Msisdn(tbcd(value)),
+            RAT_TYPE => Ie::RatType(*value.first()?),
+            F_TEID => Ie::Fteid(all_consuming(fteid)(value).ok()?.1),
+            BEARER_CONTEXT => Ie::BearerContext(all_consuming(many0(information_element))(value).ok()?.1),
+            _ => return None,
+        };
+        Some(ie)
+    }
+}
+
+// telephony BCD: two digits a byte, low nibble first, padded with 0xf
+fn tbcd(bs: &[u8]) -> String {
+    bs.iter()
+        .flat_map(|b| [b & 0x0f, b >> 4])
+        .take_while(|&d| d != 0x0f)
+        .map(|d| ::std::char::from_digit(d as u32, 10).unwrap_or('?'))
+        .collect()
+}
+
+// DNS style labels, without the root label at the end
+fn apn(bs: &[u8]) -> Option<String> {
+    let mut labels = vec![];
+    let mut bs = bs;
+    while let Some((&len, rest)) = bs.split_first() {
+        if rest.len() < len as usize {
+            return None;
+        }
+        let (label, rest) = rest.split_at(len as usize);
+        labels.push(String::from_utf8_lossy(label));
+        bs = rest;
+    }
+    Some(labels.join("."))
+}
+
+fn fteid(bs: &[u8]) -> IResult<'_, Fteid> {
+    let (bs, flags) = be_u8(bs)?;
+    let (bs, teid) = be_u32(bs)?;
+    let (bs, ipv4) = cond(flags & 0x80 != 0, be_u32)(bs)?;
+    let (bs, ipv6) = cond(flags & 0x40 != 0, take(16usize))(bs)?;
+    Ok((bs, Fteid {
+        interface_type: flags & 0x3f,
+        teid,
+        ipv4: ipv4.map(Ipv4Addr::from),
+        ipv6: ipv6.map(slice2addr),
+    }))
+}
+
+pub fn parse_gtpc_message(bs: &[u8]) -> IResult<'_, GtpcMessage<'_>> {
+    context("gtpc", gtpc_message)(bs)
+}
+
+/// A piggybacked message is left in the remaining input
+fn gtpc_message(bs: &[u8]) -> IResult<'_, GtpcMessage<'_>> {
+    let (bs, flags) = verify(be_u8, |&f| f >> 5 == 2)(bs)?;
+    let (bs, message_type) = be_u8(bs)?;
+    let (bs, length) = be_u16(bs)?;
+    // the length counts everything after the first four bytes
+    let (rest, bs) = context("gtpc.length", take(length))(bs)?;
+    let (bs, teid) = cond(flags & 0x08 != 0, be_u32)(bs)?;
+    let (bs, sequence) = be_u24(bs)?;
+    let (bs, spare) = be_u8(bs)?;
+    let (_, ies) = context("gtpc.ies", all_consuming(many0(information_element)))(bs)?;
+    Ok((rest, GtpcMessage {
+        message_type: MessageType::from_u8(message_type),
+        piggybacked: flags & 0x10 != 0,
+        teid,
+        sequence,
+        priority: if flags & 0x04 != 0 { Some(spare >> 4) } else { None },
+        ies,
+    }))
+}
+
+fn information_element(bs: &[u8]) -> IResult<'_, InformationElement<'_>> {
+    let (bs, typ) = be_u8(bs)?;
+    let (bs, length) = be_u16(bs)?;
+    let (bs, instance) = be_u8(bs)?;
+    let (bs, value) = take(length)(bs)?;
+    Ok((bs, InformationElement {
+        typ,
+        instance: instance & 0x0f,
+        value,
+    }))
+}
+
+impl <'a> GtpcMessage<'a> {
+    /// The IE of the given type and instance
+    pub fn ie(&self, typ: u8, instance: u8) -> Option<Ie<'a>> {
+        self.ies.iter().find(|ie| ie.typ == typ && ie.instance == instance).and_then(|ie| ie.decode())
+    }
+
+    pub fn imsi(&self) -> Option<String> {
+        match self.ie(IMSI, 0) {
+            Some(Ie::Imsi(imsi)) => Some(imsi),
+            _ => None,
+        }
+    }
+
+    pub fn apn(&self) -> Option<String> {
+        match self.ie(APN, 0) {
+            Some(Ie::Apn(apn)) => Some(apn),
+            _ => None,
+        }
+    }
+
+    pub fn cause(&self) -> Option<u8> {
+        match self.ie(CAUSE, 0) {
+            Some(Ie::Cause(cause)) => Some(cause),
+            _ => None,
+        }
+    }
+
+    /// The F-TEIDs at the top level of the message, with their instances
+    pub fn fteids(&self) -> Vec<(u8, Fteid)> {
+        self.ies.iter().filter_map(|ie| match ie.decode() {
+            Some(Ie::Fteid(fteid)) => Some((ie.instance, fteid)),
+            _ => None,
+        }).collect()
+    }
+}
+
+impl <'a> fmt::Display for GtpcMessage<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "GTPv2-C {:?}", self.message_type)?;
+        if let Some(teid) = self.teid {
+            write!(f, ", teid {:#x}", teid)?;
+        }
+        write!(f, ", seq {}", self.sequence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_session() {
+        // from an MME, for IMSI 001010123456789 on the "internet" APN, with
+        // the MME's S11 F-TEID and a bearer context for EBI 5
+        let request = [
+            0x48, 0x20, 0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00,
+            0x01, 0x00, 0x08, 0x00, 0x00, 0x01, 0x01, 0x21, 0x43, 0x65, 0x87, 0xf9,
+            0x47, 0x00, 0x09, 0x00, 0x08, b'i', b'n', b't', b'e', b'r', b'n', b'e', b't',
+            0x57, 0x00, 0x09, 0x00, 0x8a, 0x11, 0x22, 0x33, 0x44, 0xc0, 0x00, 0x02, 0x01,
+            0x57, 0x00, 0x05, 0x01, 0x07, 0x00, 0x00, 0x00, 0x00,
+            0x5d, 0x00, 0x05, 0x00, 0x49, 0x00, 0x01, 0x00, 0x05,
+        ];
+        let (rest, message) = parse_gtpc_message(&request).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!((message.message_type, message.teid, message.sequence), (MessageType::CreateSessionRequest, Some(0), 1));
+        assert_eq!(message.imsi().unwrap(), "001010123456789");
+        assert_eq!(message.apn().unwrap(), "internet");
+        assert_eq!(message.fteids(), vec![
+            (0, Fteid { interface_type: 10, teid: 0x11223344, ipv4: Some(Ipv4Addr::new(192, 0, 2, 1)), ipv6: None }),
+            (1, Fteid { interface_type: 7, teid: 0, ipv4: None, ipv6: None }),
+        ]);
+        let bearer = match message.ie(BEARER_CONTEXT, 0) {
+            Some(Ie::BearerContext(ies)) => ies,
+            other => panic!("expected a bearer context, got {:?}", other),
+        };
+        assert_eq!(bearer[0].decode(), Some(Ie::Ebi(5)));
+        assert_eq!(message.to_string(), "GTPv2-C CreateSessionRequest, teid 0x0, seq 1");
+    }
+
+    #[test]
+    fn test_delete_session() {
+        // a response accepting the request, then an echo request carrying
+        // a recovery counter piggybacked after it
+        let response = [
+            0x58, 0x25, 0x00, 0x0e, 0x11, 0x22, 0x33, 0x44, 0x00, 0x00, 0x02, 0x00,
+            0x02, 0x00, 0x02, 0x00, 0x10, 0x00,
+            0x40, 0x01, 0x00, 0x09, 0x00, 0x00, 0x03, 0x00, 0x03, 0x00, 0x01, 0x00, 0x07,
+        ];
+        let (rest, message) = parse_gtpc_message(&response).unwrap();
+        assert_eq!((message.message_type, message.piggybacked), (MessageType::DeleteSessionResponse, true));
+        assert_eq!(message.cause(), Some(16));
+        let (_, echo) = parse_gtpc_message(rest).unwrap();
+        assert_eq!((echo.message_type, echo.teid), (MessageType::EchoRequest, None));
+        assert_eq!(echo.ie(RECOVERY, 0), Some(Ie::Recovery(7)));
+        assert_eq!(echo.to_string(), "GTPv2-C EchoRequest, seq 3");
+
+        // GTPv1, and an IE running past the end of the message
+        assert!(parse_gtpc_message(&[0x32, 0x01, 0x00, 0x04, 0, 0, 0, 0]).is_err());
+        assert!(parse_gtpc_message(&[0x40, 0x01, 0x00, 0x09, 0, 0, 3, 0, 0x03, 0x00, 0x02, 0x00, 0x07]).is_err());
+    }
+}
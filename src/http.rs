@@ -0,0 +1,688 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt;
+
+use nom::bytes::streaming::{tag, take, take_until};
+use nom::character::is_digit;
+use nom::error::{context, ErrorKind};
+use nom::Needed;
+
+use error::{IResult, ParseError};
+use stream::{Decode, Decoder, DecodeError};
+use {Fixup, IntoOwned, ToBytes};
+
+// https://tools.ietf.org/html/rfc9112
+//
+// HTTP/1.x over the output of TCP reassembly, one direction at a time. The
+// parsers are streaming like the SMTP ones: a head that hasn't been seen up
+// to its blank line yet fails with `nom::Err::Incomplete`. Bodies are
+// returned with their transfer coding undone, but not their content coding,
+// so a gzipped body is still gzipped.
+
+// longer heads are taken for garbage rather than waited for
+const MAX_HEAD_LENGTH: usize = 65536;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Header<'a> {
+    pub name: &'a [u8],
+    /// Without the whitespace around it
+    pub value: &'a [u8],
+}
+
+// the value of the first header called `name`
+fn find_header<'a>(headers: &[Header<'a>], name: &[u8]) -> Option<&'a [u8]> {
+    headers.iter().find(|h| h.name.eq_ignore_ascii_case(name)).map(|h| h.value)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Request<'a> {
+    pub method: &'a [u8],
+    pub target: &'a [u8],
+    pub version: &'a [u8],
+    pub headers: Vec<Header<'a>>,
+}
+
+impl <'a> Request<'a> {
+    /// The value of the first header called `name`, ignoring case
+    pub fn header(&self, name: &[u8]) -> Option<&'a [u8]> {
+        find_header(&self.headers, name)
+    }
+}
+
+impl <'a> fmt::Display for Request<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = String::from_utf8_lossy;
+        write!(f, "{} {} {}", s(self.method), s(self.target), s(self.version))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Response<'a> {
+    pub version: &'a [u8],
+    pub status: u16,
+    pub reason: &'a [u8],
+    pub headers: Vec<Header<'a>>,
+}
+
+impl <'a> Response<'a> {
+    /// The value of the first header called `name`, ignoring case
+    pub fn header(&self, name: &[u8]) -> Option<&'a [u8]> {
+        find_header(&self.headers, name)
+    }
+}
+
+impl <'a> fmt::Display for Response<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = String::from_utf8_lossy;
+        write!(f, "{} {} {}", s(self.version), self.status, s(self.reason))
+    }
+}
+
+/// Parses a request line and its headers, up to and including the blank
+/// line after them
+pub fn parse_request(bs: &[u8]) -> IResult<'_, Request<'_>> {
+    context("http", request)(bs)
+}
+
+/// Parses a status line and its headers, up to and including the blank
+/// line after them
+pub fn parse_response(bs: &[u8]) -> IResult<'_, Response<'_>> {
+    context("http", response)(bs)
+}
+
+fn request(bs: &[u8]) -> IResult<'_, Request<'_>> {
+    let (bs, line) = line(bs)?;
+    let mut parts = line.splitn(3, |&b| b == b' ');
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) if !method.is_empty() && version.starts_with(b"HTTP/") => {
+            (method, target, version)
+        },
+        _ => return Err(error(line, ErrorKind::Verify, "http.request_line")),
+    };
+    let (bs, headers) = headers(bs, line.len() + 2)?;
+    Ok((bs, Request { method, target, version, headers }))
+}
+
+fn response(bs: &[u8]) -> IResult<'_, Response<'_>> {
+    let (bs, line) = line(bs)?;
+    let mut parts = line.splitn(3, |&b| b == b' ');
+    let (version, status, reason) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(version), Some(status), reason) if version.starts_with(b"HTTP/")
+            && status.len() == 3 && status.iter().all(|&b| is_digit(b)) => {
+            (version, status.iter().fold(0, |acc, &b| 10*acc + (b - b'0') as u16), reason.unwrap_or(b""))
+        },
+        _ => return Err(error(line, ErrorKind::Verify, "http.status_line")),
+    };
+    let (bs, headers) = headers(bs, line.len() + 2)?;
+    Ok((bs, Response { version, status, reason, headers }))
+}
+
+// header lines up to the blank one; folded lines, which RFC 9112 did away
+// with, are an error. `length` is how much of the head came before them,
+// and a head that runs past MAX_HEAD_LENGTH is too large.
+fn headers(mut bs: &[u8], mut length: usize) -> IResult<'_, Vec<Header<'_>>> {
+    let mut headers = vec![];
+    loop {
+        let (rest, line) = match line(bs) {
+            Err(nom::Err::Incomplete(_)) if length + bs.len() > MAX_HEAD_LENGTH => {
+                return Err(error(bs, ErrorKind::TooLarge, "http.head"));
+            },
+            result => result?,
+        };
+        length += bs.len() - rest.len();
+        if length > MAX_HEAD_LENGTH {
+            return Err(error(line, ErrorKind::TooLarge, "http.head"));
+        }
+        bs = rest;
+        if line.is_empty() {
+            return Ok((bs, headers));
+        }
+        let colon = match line.iter().position(|&b| b == b':') {
+            Some(i) if i > 0 && !line[i - 1].is_ascii_whitespace() && !line[0].is_ascii_whitespace() => i,
+            _ => return Err(error(line, ErrorKind::Verify, "http.header")),
+        };
+        let value = line[colon + 1..].trim_ascii();
+        headers.push(Header { name: &line[..colon], value });
+    }
+}
+
+fn line(bs: &[u8]) -> IResult<'_, &[u8]> {
+    let (bs, line) = match take_until("\r\n")(bs) {
+        Err(nom::Err::Incomplete(_)) if bs.len() > MAX_HEAD_LENGTH => {
+            return Err(error(bs, ErrorKind::TooLarge, "http.line"));
+        },
+        result => result?,
+    };
+    let (bs, _) = tag("\r\n")(bs)?;
+    Ok((bs, line))
+}
+
+fn error<'a>(bs: &'a [u8], kind: ErrorKind, field: &'static str) -> nom::Err<ParseError<'a>> {
+    let mut e = ParseError::new(bs, kind);
+    e.context.push(field);
+    nom::Err::Error(e)
+}
+
+fn write_head(start_line: &[&[u8]], headers: &[Header], out: &mut Vec<u8>) {
+    out.extend_from_slice(&start_line.join(&b' '));
+    out.extend_from_slice(b"\r\n");
+    for header in headers {
+        out.extend_from_slice(header.name);
+        out.extend_from_slice(b": ");
+        out.extend_from_slice(header.value);
+        out.extend_from_slice(b"\r\n");
+    }
+    out.extend_from_slice(b"\r\n");
+}
+
+/// Writes the head back out, with one space after each colon
+impl <'a> ToBytes for Request<'a> {
+    fn write_with(&self, _fixup: Fixup, out: &mut Vec<u8>) {
+        write_head(&[self.method, self.target, self.version], &self.headers, out);
+    }
+}
+
+/// Writes the head back out, with one space after each colon
+impl <'a> ToBytes for Response<'a> {
+    fn write_with(&self, _fixup: Fixup, out: &mut Vec<u8>) {
+        let status = self.status.to_string();
+        write_head(&[self.version, status.as_bytes(), self.reason], &self.headers, out);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedHeader {
+    pub name: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedRequest {
+    pub method: Vec<u8>,
+    pub target: Vec<u8>,
+    pub version: Vec<u8>,
+    pub headers: Vec<OwnedHeader>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedResponse {
+    pub version: Vec<u8>,
+    pub status: u16,
+    pub reason: Vec<u8>,
+    pub headers: Vec<OwnedHeader>,
+}
+
+fn owned_headers(headers: Vec<Header>) -> Vec<OwnedHeader> {
+    headers.into_iter().map(|h| OwnedHeader { name: h.name.into_owned(), value: h.value.into_owned() }).collect()
+}
+
+fn borrowed_headers(headers: &[OwnedHeader]) -> Vec<Header<'_>> {
+    headers.iter().map(|h| Header { name: &h.name, value: &h.value }).collect()
+}
+
+impl <'a> IntoOwned for Request<'a> {
+    type Owned = OwnedRequest;
+
+    fn into_owned(self) -> OwnedRequest {
+        OwnedRequest {
+            method: self.method.into_owned(),
+            target: self.target.into_owned(),
+            version: self.version.into_owned(),
+            headers: owned_headers(self.headers),
+        }
+    }
+}
+
+impl <'a> IntoOwned for Response<'a> {
+    type Owned = OwnedResponse;
+
+    fn into_owned(self) -> OwnedResponse {
+        OwnedResponse {
+            version: self.version.into_owned(),
+            status: self.status,
+            reason: self.reason.into_owned(),
+            headers: owned_headers(self.headers),
+        }
+    }
+}
+
+impl OwnedRequest {
+    pub fn to_borrowed(&self) -> Request<'_> {
+        Request {
+            method: &self.method,
+            target: &self.target,
+            version: &self.version,
+            headers: borrowed_headers(&self.headers),
+        }
+    }
+}
+
+impl OwnedResponse {
+    pub fn to_borrowed(&self) -> Response<'_> {
+        Response {
+            version: &self.version,
+            status: self.status,
+            reason: &self.reason,
+            headers: borrowed_headers(&self.headers),
+        }
+    }
+}
+
+/// How the end of a message body is found, RFC 9112 section 6.3
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BodyLength {
+    /// No body at all
+    Empty,
+    Length(u64),
+    Chunked,
+    /// A response body that runs until the server closes the connection
+    UntilClose,
+}
+
+// Content-Length, if it's there and every copy of it agrees
+fn content_length<'a>(headers: &[Header<'a>]) -> Result<Option<u64>, nom::Err<ParseError<'a>>> {
+    let mut length = None;
+    for header in headers.iter().filter(|h| h.name.eq_ignore_ascii_case(b"content-length")) {
+        // a list of the same length is allowed too
+        for value in header.value.split(|&b| b == b',') {
+            let value = value.trim_ascii();
+            let parsed = ::std::str::from_utf8(value).ok()
+                .filter(|v| !v.is_empty() && v.bytes().all(is_digit))
+                .and_then(|v| v.parse::<u64>().ok());
+            match (parsed, length) {
+                (Some(parsed), None) => length = Some(parsed),
+                (Some(parsed), Some(length)) if parsed == length => {},
+                _ => return Err(error(header.value, ErrorKind::Verify, "http.content_length")),
+            }
+        }
+    }
+    Ok(length)
+}
+
+// whether the last transfer coding is chunked, if there are any
+fn chunked(headers: &[Header]) -> Option<bool> {
+    let mut codings = headers.iter()
+        .filter(|h| h.name.eq_ignore_ascii_case(b"transfer-encoding"))
+        .flat_map(|h| h.value.split(|&b| b == b','))
+        .map(|coding| coding.trim_ascii())
+        .filter(|coding| !coding.is_empty())
+        .peekable();
+    codings.peek()?;
+    Some(codings.last().is_some_and(|coding| coding.eq_ignore_ascii_case(b"chunked")))
+}
+
+impl BodyLength {
+    pub fn of_request<'a>(request: &Request<'a>) -> Result<BodyLength, nom::Err<ParseError<'a>>> {
+        match chunked(&request.headers) {
+            Some(true) => Ok(BodyLength::Chunked),
+            // the server can't tell where such a request ends
+            Some(false) => Err(error(request.method, ErrorKind::Verify, "http.transfer_encoding")),
+            None => Ok(content_length(&request.headers)?.map_or(BodyLength::Empty, BodyLength::Length)),
+        }
+    }
+
+    /// `method` is the method of the request being answered, if it's known
+    pub fn of_response<'a>(response: &Response<'a>, method: Option<&[u8]>) -> Result<BodyLength, nom::Err<ParseError<'a>>> {
+        let successful_connect = method == Some(b"CONNECT") && response.status / 100 == 2;
+        if method == Some(b"HEAD") || successful_connect || response.status / 100 == 1
+            || response.status == 204 || response.status == 304 {
+            return Ok(BodyLength::Empty);
+        }
+        match chunked(&response.headers) {
+            Some(true) => Ok(BodyLength::Chunked),
+            Some(false) => Ok(BodyLength::UntilClose),
+            None => Ok(content_length(&response.headers)?.map_or(BodyLength::UntilClose, BodyLength::Length)),
+        }
+    }
+}
+
+/// Parses a chunked body up to and including its trailer section, and
+/// returns its chunks put together. Chunk extensions and trailer fields are
+/// skipped.
+pub fn parse_chunked_body(bs: &[u8]) -> IResult<'_, Vec<u8>> {
+    context("http.chunked", chunked_body)(bs)
+}
+
+fn chunked_body(mut bs: &[u8]) -> IResult<'_, Vec<u8>> {
+    let mut body = vec![];
+    loop {
+        match chunk(bs)? {
+            (rest, Some(data)) => {
+                body.extend_from_slice(data);
+                bs = rest;
+            },
+            (rest, None) => return Ok((rest, body)),
+        }
+    }
+}
+
+// one chunk's data, or nothing for the last chunk and the trailer section
+fn chunk(bs: &[u8]) -> IResult<'_, Option<&[u8]>> {
+    let (rest, line) = line(bs)?;
+    let size = match line.iter().position(|&b| b == b';') {
+        Some(i) => &line[..i],
+        None => line,
+    }.trim_ascii();
+    let size = ::std::str::from_utf8(size).ok()
+        .filter(|s| !s.is_empty() && s.bytes().all(|b| b.is_ascii_hexdigit()))
+        .and_then(|s| usize::from_str_radix(s, 16).ok())
+        .ok_or_else(|| error(line, ErrorKind::HexDigit, "http.chunk_size"))?;
+    if size == 0 {
+        let (rest, _) = headers(rest, 0)?;
+        return Ok((rest, None));
+    }
+    let (rest, data) = take(size)(rest)?;
+    let (rest, _) = tag("\r\n")(rest)?;
+    Ok((rest, Some(data)))
+}
+
+/// A whole message, with its head as sent and its body decoded
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Message {
+    /// The start line and headers, including the blank line after them
+    pub head: Vec<u8>,
+    pub body: Vec<u8>,
+}
+
+impl Message {
+    /// The head, if this is a request
+    pub fn request(&self) -> Option<Request<'_>> {
+        parse_request(&self.head).ok().map(|(_, request)| request)
+    }
+
+    /// The head, if this is a response
+    pub fn response(&self) -> Option<Response<'_>> {
+        parse_response(&self.head).ok().map(|(_, response)| response)
+    }
+}
+
+/// Decodes the requests or the responses of a connection into `Message`s.
+/// Responses to HEAD and CONNECT are framed differently, so a response
+/// decoder needs to be told about requests with `request_sent`.
+///
+/// Progress on the message being decoded is kept between calls, so a
+/// message arriving a piece at a time is only read through once. A head is
+/// parsed once its blank line is in, so one that's malformed is only
+/// reported then, or once it's longer than a head is allowed to be.
+pub struct MessageDecoder {
+    responses: bool,
+    // methods of the requests that haven't had a final response yet
+    methods: VecDeque<Vec<u8>>,
+    // how much of the input has been searched for the end of the head
+    scanned: usize,
+    // the message whose head has been parsed, while its body comes in
+    partial: Option<Partial>,
+}
+
+struct Partial {
+    head_length: usize,
+    length: BodyLength,
+    is_final: bool,
+    // how far into the input the body has been read, and for a chunked
+    // body what its chunks held
+    offset: usize,
+    body: Vec<u8>,
+}
+
+impl MessageDecoder {
+    pub fn requests() -> MessageDecoder {
+        MessageDecoder { responses: false, methods: VecDeque::new(), scanned: 0, partial: None }
+    }
+
+    pub fn responses() -> MessageDecoder {
+        MessageDecoder { responses: true, methods: VecDeque::new(), scanned: 0, partial: None }
+    }
+
+    /// Records the method of a request the server will answer, in order
+    pub fn request_sent(&mut self, method: &[u8]) {
+        self.methods.push_back(method.to_vec());
+    }
+
+    // the head, its length and how its body ends
+    fn head<'a>(&self, bs: &'a [u8]) -> IResult<'a, (usize, BodyLength, bool)> {
+        if self.responses {
+            let (rest, response) = parse_response(bs)?;
+            let method = self.methods.front().map(|m| &m[..]);
+            let length = BodyLength::of_response(&response, method)?;
+            Ok((rest, (bs.len() - rest.len(), length, response.status >= 200)))
+        } else {
+            let (rest, request) = parse_request(bs)?;
+            Ok((rest, (bs.len() - rest.len(), BodyLength::of_request(&request)?, false)))
+        }
+    }
+}
+
+impl Decode for MessageDecoder {
+    type Output = Message;
+
+    fn decode<'a>(&mut self, bs: &'a [u8]) -> IResult<'a, Option<Message>> {
+        let mut partial = match self.partial.take() {
+            Some(partial) => partial,
+            None => {
+                // only what arrived since last time can hold the blank line
+                let from = self.scanned.saturating_sub(3);
+                let ended = bs[from..].windows(4).any(|w| w == b"\r\n\r\n");
+                self.scanned = bs.len();
+                if !ended && bs.len() <= MAX_HEAD_LENGTH {
+                    return Err(nom::Err::Incomplete(Needed::Unknown));
+                }
+                let (_, (head_length, length, is_final)) = self.head(bs)?;
+                Partial { head_length, length, is_final, offset: head_length, body: vec![] }
+            },
+        };
+        let result = match partial.length {
+            BodyLength::Empty => Ok(()),
+            BodyLength::Length(length) => {
+                // more than fits in memory on 32 bit targets
+                let length = usize::try_from(length)
+                    .map_err(|_| error(&bs[..partial.head_length], ErrorKind::TooLarge, "http.content_length"))?;
+                take(length)(&bs[partial.offset..]).map(|(_, body): (_, &[u8])| {
+                    partial.body = body.to_vec();
+                    partial.offset += length;
+                })
+            },
+            BodyLength::Chunked => loop {
+                match context("http.chunked", chunk)(&bs[partial.offset..]) {
+                    Ok((rest, Some(data))) => {
+                        partial.body.extend_from_slice(data);
+                        partial.offset = bs.len() - rest.len();
+                    },
+                    Ok((rest, None)) => {
+                        partial.offset = bs.len() - rest.len();
+                        break Ok(());
+                    },
+                    Err(e) => break Err(e),
+                }
+            },
+            // only `MessageReader::close` can end these
+            BodyLength::UntilClose => Err(nom::Err::Incomplete(Needed::Unknown)),
+        };
+        if let Err(e) = result {
+            self.partial = Some(partial);
+            return Err(e);
+        }
+        self.scanned = 0;
+        if partial.is_final {
+            self.methods.pop_front();
+        }
+        let message = Message { head: bs[..partial.head_length].to_vec(), body: partial.body };
+        Ok((&bs[partial.offset..], Some(message)))
+    }
+}
+
+/// Reads the messages one side of a connection sent, as its bytes arrive
+/// in order
+pub struct MessageReader {
+    messages: Decoder<MessageDecoder>,
+    closed: bool,
+}
+
+impl MessageReader {
+    pub fn requests() -> MessageReader {
+        MessageReader { messages: Decoder::new(MessageDecoder::requests()), closed: false }
+    }
+
+    pub fn responses() -> MessageReader {
+        MessageReader { messages: Decoder::new(MessageDecoder::responses()), closed: false }
+    }
+
+    pub fn push(&mut self, bs: &[u8]) {
+        if !self.closed {
+            self.messages.push(bs);
+        }
+    }
+
+    /// See `MessageDecoder::request_sent`
+    pub fn request_sent(&mut self, method: &[u8]) {
+        self.messages.get_mut().request_sent(method);
+    }
+
+    /// Ends the stream, once the sender has closed the connection. Returns
+    /// the response it ended, if its body ran until then, and nothing
+    /// after that.
+    pub fn close(&mut self) -> Option<Message> {
+        if self.closed {
+            return None;
+        }
+        self.closed = true;
+        let bs = self.messages.buffered();
+        match self.messages.get_ref().head(bs) {
+            Ok((rest, (head_length, BodyLength::UntilClose, _))) => {
+                Some(Message { head: bs[..head_length].to_vec(), body: rest.to_vec() })
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Iterator for MessageReader {
+    type Item = Result<Message, DecodeError>;
+
+    fn next(&mut self) -> Option<Result<Message, DecodeError>> {
+        self.messages.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let bs = b"GET /index.html HTTP/1.1\r\nHost: example.com\r\nAccept:  */* \r\n\r\nrest";
+        let (rest, request) = parse_request(bs).unwrap();
+        assert_eq!(rest, b"rest");
+        assert_eq!(request.to_string(), "GET /index.html HTTP/1.1");
+        assert_eq!(request.header(b"host"), Some(&b"example.com"[..]));
+        assert_eq!(request.header(b"Accept"), Some(&b"*/*"[..]));
+        assert_eq!(BodyLength::of_request(&request), Ok(BodyLength::Empty));
+        assert_eq!(request.to_bytes(), b"GET /index.html HTTP/1.1\r\nHost: example.com\r\nAccept: */*\r\n\r\n");
+        assert_eq!(request.clone().into_owned().to_borrowed(), request);
+
+        // not up to the blank line yet
+        assert!(parse_request(&bs[..40]).unwrap_err().is_incomplete());
+        assert!(parse_request(b"GET /\r\n\r\n").is_err());
+        assert!(parse_request(b"GET / HTTP/1.1\r\nHost : example.com\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_chunked_body() {
+        let bs = b"5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nExpires: never\r\n\r\nrest";
+        let (rest, body) = parse_chunked_body(bs).unwrap();
+        assert_eq!((rest, &body[..]), (&b"rest"[..], &b"hello, world"[..]));
+        for length in 0..bs.len() - 4 {
+            assert!(parse_chunked_body(&bs[..length]).unwrap_err().is_incomplete());
+        }
+        assert!(parse_chunked_body(b"zz\r\nhello\r\n0\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn test_message_reader() {
+        let mut requests = MessageReader::requests();
+        let mut responses = MessageReader::responses();
+        let sent = b"POST /upload HTTP/1.1\r\nContent-Length: 5\r\n\r\nhelloHEAD / HTTP/1.1\r\n\r\n\
+                     GET / HTTP/1.1\r\n\r\n";
+        // a byte at a time, the way a slow connection might deliver it
+        let mut methods = vec![];
+        for b in sent.iter() {
+            requests.push(&[*b]);
+            for message in &mut requests {
+                let message = message.unwrap();
+                let request = message.request().unwrap();
+                responses.request_sent(request.method);
+                methods.push((String::from_utf8_lossy(request.method).into_owned(), message.body.clone()));
+            }
+        }
+        assert_eq!(methods, vec![
+            ("POST".to_string(), b"hello".to_vec()),
+            ("HEAD".to_string(), vec![]),
+            ("GET".to_string(), vec![]),
+        ]);
+
+        // an interim response, a chunked one, the HEAD response that
+        // announces a length it doesn't send, then one that runs until close
+        responses.push(b"HTTP/1.1 100 Continue\r\n\r\n\
+                         HTTP/1.1 201 Created\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nok\r\n0\r\n\r\n\
+                         HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\n\
+                         HTTP/1.0 200 OK\r\n\r\nall of ");
+        let statuses: Vec<(u16, Vec<u8>)> = (&mut responses)
+            .map(|m| m.unwrap())
+            .map(|m| (m.response().unwrap().status, m.body))
+            .collect();
+        assert_eq!(statuses, vec![(100, vec![]), (201, b"ok".to_vec()), (200, vec![])]);
+        responses.push(b"it");
+        assert!(responses.next().is_none());
+        let last = responses.close().unwrap();
+        assert_eq!(last.response().unwrap().version, b"HTTP/1.0");
+        assert_eq!(last.body, b"all of it");
+        assert!(responses.close().is_none());
+
+        // a chunked body a few bytes at a time
+        let mut responses = MessageReader::responses();
+        let sent = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n7\r\n, world\r\n0\r\n\r\n";
+        let mut bodies = vec![];
+        for piece in sent.chunks(3) {
+            responses.push(piece);
+            bodies.extend((&mut responses).map(|m| m.unwrap().body));
+        }
+        assert_eq!(bodies, vec![b"hello, world".to_vec()]);
+
+        let mut bad = MessageReader::requests();
+        bad.push(b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\n");
+        assert!(bad.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_head_too_large() {
+        let mut head = b"GET / HTTP/1.1\r\n".to_vec();
+        while head.len() <= MAX_HEAD_LENGTH {
+            head.extend_from_slice(b"X-Pad: 1\r\n");
+        }
+        let e = match parse_request(&head) {
+            Err(nom::Err::Error(e)) => e,
+            x => panic!("expected an error, got {:?}", x),
+        };
+        assert_eq!(e.kind, ErrorKind::TooLarge);
+
+        // short lines that never end the head aren't buffered forever
+        let mut requests = MessageReader::requests();
+        requests.push(b"GET / HTTP/1.1\r\n");
+        let mut error = None;
+        for _ in 0..MAX_HEAD_LENGTH / 10 + 1 {
+            requests.push(b"X-Pad: 1\r\n");
+            if let Some(result) = requests.next() {
+                error = Some(result.unwrap_err());
+                break;
+            }
+        }
+        assert_eq!(error.unwrap().kind, ErrorKind::TooLarge);
+    }
+}
@@ -0,0 +1,288 @@
+use std::fmt;
+
+use nom::combinator::rest;
+use nom::error::context;
+use nom::number::complete::{be_u8, be_u16, be_u32};
+
+use checksum;
+use error::IResult;
+use flow::FlowKey;
+use ipv4;
+use {Fixup, IntoOwned, Layer, NextProtocol, ToBytes};
+
+// https://tools.ietf.org/html/rfc792
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct IcmpPacket<'a> {
+    pub header: IcmpHeader,
+    pub body: &'a [u8],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct IcmpHeader {
+    pub typ: IcmpType,
+    pub code: u8,
+    pub checksum: u16,
+    // meaning depends on the type; identifier and sequence number for echo
+    // messages, gateway address for redirects, unused for most errors
+    pub rest_of_header: u32,
+}
+
+impl IcmpHeader {
+    /// Identifier and sequence number of an echo request or reply
+    pub fn echo_id_seq(&self) -> Option<(u16, u16)> {
+        match self.typ {
+            IcmpType::EchoRequest | IcmpType::EchoReply =>
+                Some(((self.rest_of_header >> 16) as u16, self.rest_of_header as u16)),
+            _ => None,
+        }
+    }
+}
+
+impl <'a> IcmpPacket<'a> {
+    /// The datagram an error message was sent about, if this is an error
+    /// and enough of it was quoted to parse the IP header
+    pub fn quoted(&self) -> Option<QuotedDatagram<'a>> {
+        if self.header.typ.is_error() {
+            parse_quoted_datagram(self.body).ok().map(|(_, quoted)| quoted)
+        } else {
+            None
+        }
+    }
+}
+
+pub fn parse_icmp_packet(bs: &[u8]) -> IResult<'_, IcmpPacket<'_>> {
+    context("icmp", icmp_packet)(bs)
+}
+
+fn icmp_packet(bs: &[u8]) -> IResult<'_, IcmpPacket<'_>> {
+    let (bs, typ) = be_u8(bs)?;
+    let (bs, code) = be_u8(bs)?;
+    let (bs, checksum) = be_u16(bs)?;
+    let (bs, rest_of_header) = be_u32(bs)?;
+    let (bs, body) = rest(bs)?;
+    Ok((bs, IcmpPacket {
+        header: IcmpHeader {
+            typ: IcmpType::from_u8(typ),
+            code,
+            checksum,
+            rest_of_header,
+        },
+        body,
+    }))
+}
+
+impl <'a> Layer<'a> for IcmpPacket<'a> {
+    fn header_len(&self) -> usize {
+        8
+    }
+
+    fn payload(&self) -> &'a [u8] {
+        self.body
+    }
+
+    // the quoted datagram in error messages is found with `quoted`
+    fn next_protocol(&self) -> Option<NextProtocol> {
+        None
+    }
+}
+
+impl <'a> ToBytes for IcmpPacket<'a> {
+    fn write_with(&self, fixup: Fixup, out: &mut Vec<u8>) {
+        let h = &self.header;
+        let start = out.len();
+        out.push(h.typ.to_u8());
+        out.push(h.code);
+        out.extend_from_slice(&h.checksum.to_be_bytes());
+        out.extend_from_slice(&h.rest_of_header.to_be_bytes());
+        out.extend_from_slice(self.body);
+        if fixup.checksums {
+            out[start + 2..start + 4].copy_from_slice(&[0, 0]);
+            let sum = checksum::internet_checksum(&out[start..]);
+            out[start + 2..start + 4].copy_from_slice(&sum.to_be_bytes());
+        }
+    }
+}
+
+impl <'a> fmt::Display for IcmpPacket<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let h = &self.header;
+        match (h.typ, h.echo_id_seq()) {
+            (IcmpType::EchoRequest, Some((id, seq))) => write!(f, "ICMP echo request, id {}, seq {}", id, seq)?,
+            (IcmpType::EchoReply, Some((id, seq))) => write!(f, "ICMP echo reply, id {}, seq {}", id, seq)?,
+            (typ, _) => write!(f, "ICMP {:?}, code {}", typ, h.code)?,
+        }
+        write!(f, ", length {}", self.body.len())
+    }
+}
+
+/// The start of the datagram that caused an error message: its IP header
+/// and at least the first 8 bytes of its payload, which is enough for the
+/// ports of a TCP or UDP segment. `header.total_len` is the length of the
+/// original datagram, not of what was quoted.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct QuotedDatagram<'a> {
+    pub header: ipv4::Header<'a>,
+    pub body: &'a [u8],
+}
+
+impl <'a> QuotedDatagram<'a> {
+    /// Source and destination ports of a quoted TCP or UDP segment
+    pub fn ports(&self) -> Option<(u16, u16)> {
+        match self.header.proto {
+            ipv4::Ipv4Protocol::Tcp | ipv4::Ipv4Protocol::Udp if self.body.len() >= 4 => Some((
+                u16::from_be_bytes([self.body[0], self.body[1]]),
+                u16::from_be_bytes([self.body[2], self.body[3]]),
+            )),
+            _ => None,
+        }
+    }
+
+    /// The flow the original datagram belonged to, in the direction it was
+    /// travelling, so away from whoever sent the error
+    pub fn flow_key(&self) -> Option<FlowKey> {
+        let (src_port, dst_port) = match self.header.proto {
+            ipv4::Ipv4Protocol::Icmp => (0, 0),
+            _ => self.ports()?,
+        };
+        Some(FlowKey {
+            src_ip: self.header.src_ip.into(),
+            dst_ip: self.header.dst_ip.into(),
+            src_port,
+            dst_port,
+            proto: self.header.proto,
+        })
+    }
+}
+
+pub fn parse_quoted_datagram(bs: &[u8]) -> IResult<'_, QuotedDatagram<'_>> {
+    context("icmp.quoted", |bs| {
+        let (rest, packet) = ipv4::parse_ipv4_packet(bs)?;
+        Ok((rest, QuotedDatagram {
+            header: packet.header,
+            body: packet.body,
+        }))
+    })(bs)
+}
+
+/// Checks the checksum of a whole ICMP message
+pub fn verify_checksum(message: &[u8]) -> bool {
+    message.len() >= 8 && checksum::internet_checksum(message) == 0
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub enum IcmpType {
+    EchoReply,
+    DestinationUnreachable,
+    SourceQuench,
+    Redirect,
+    EchoRequest,
+    RouterAdvertisement,
+    RouterSolicitation,
+    TimeExceeded,
+    ParameterProblem,
+    Timestamp,
+    TimestampReply,
+    InformationRequest,
+    InformationReply,
+    Other(u8),
+}
+
+impl IcmpType {
+    /// Whether messages of this type quote the datagram that caused them
+    pub fn is_error(&self) -> bool {
+        matches!(*self,
+            IcmpType::DestinationUnreachable | IcmpType::SourceQuench | IcmpType::Redirect
+            | IcmpType::TimeExceeded | IcmpType::ParameterProblem)
+    }
+
+    pub fn from_u8(v: u8) -> IcmpType {
+        match v {
+            0 => IcmpType::EchoReply,
+            3 => IcmpType::DestinationUnreachable,
+            4 => IcmpType::SourceQuench,
+            5 => IcmpType::Redirect,
+            8 => IcmpType::EchoRequest,
+            9 => IcmpType::RouterAdvertisement,
+            10 => IcmpType::RouterSolicitation,
+            11 => IcmpType::TimeExceeded,
+            12 => IcmpType::ParameterProblem,
+            13 => IcmpType::Timestamp,
+            14 => IcmpType::TimestampReply,
+            15 => IcmpType::InformationRequest,
+            16 => IcmpType::InformationReply,
+            x => IcmpType::Other(x),
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            IcmpType::EchoReply => 0,
+            IcmpType::DestinationUnreachable => 3,
+            IcmpType::SourceQuench => 4,
+            IcmpType::Redirect => 5,
+            IcmpType::EchoRequest => 8,
+            IcmpType::RouterAdvertisement => 9,
+            IcmpType::RouterSolicitation => 10,
+            IcmpType::TimeExceeded => 11,
+            IcmpType::ParameterProblem => 12,
+            IcmpType::Timestamp => 13,
+            IcmpType::TimestampReply => 14,
+            IcmpType::InformationRequest => 15,
+            IcmpType::InformationReply => 16,
+            IcmpType::Other(x) => x,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedIcmpPacket {
+    pub header: IcmpHeader,
+    pub body: Vec<u8>,
+}
+
+impl <'a> IntoOwned for IcmpPacket<'a> {
+    type Owned = OwnedIcmpPacket;
+
+    fn into_owned(self) -> OwnedIcmpPacket {
+        OwnedIcmpPacket {
+            header: self.header,
+            body: self.body.into_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_exceeded() {
+        // a router's reply to a traceroute probe to 8.8.8.8 port 33435
+        let message = [
+            0x0b, 0x00, 0xf4, 0xff, 0x00, 0x00, 0x00, 0x00,
+            0x45, 0x00, 0x00, 0x3c, 0x1c, 0x46, 0x00, 0x00,
+            0x01, 0x11, 0x00, 0x00, 0xc0, 0xa8, 0x01, 0x64,
+            0x08, 0x08, 0x08, 0x08, 0x82, 0x9b, 0x82, 0x9b,
+            0x00, 0x28, 0x00, 0x00,
+        ];
+        let (_, packet) = parse_icmp_packet(&message).unwrap();
+        assert_eq!(packet.header.typ, IcmpType::TimeExceeded);
+        let quoted = packet.quoted().unwrap();
+        assert_eq!(quoted.header.ttl, 1);
+        assert_eq!(quoted.header.total_len, 60);
+        assert_eq!(quoted.body.len(), 8);
+        assert_eq!(quoted.ports(), Some((33435, 33435)));
+        let key = quoted.flow_key().unwrap();
+        assert_eq!(key.src_ip, "192.168.1.100".parse::<::std::net::IpAddr>().unwrap());
+        assert_eq!(key.dst_ip, "8.8.8.8".parse::<::std::net::IpAddr>().unwrap());
+        assert_eq!(key.proto, ipv4::Ipv4Protocol::Udp);
+    }
+}
@@ -0,0 +1,732 @@
+use std::fmt;
+use std::net::Ipv6Addr;
+
+use nom::bytes::complete::take;
+use nom::combinator::{all_consuming, rest};
+use nom::error::{context, ErrorKind};
+use nom::multi::{count, many0};
+use nom::number::complete::{be_u8, be_u16, be_u32};
+
+use checksum;
+use error::{IResult, ParseError};
+use ethernet::MacAddr;
+use ipv6::slice2addr;
+use {Fixup, IntoOwned, IpHeader, Layer, NextProtocol, ToBytes};
+
+// https://tools.ietf.org/html/rfc4443
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct Icmpv6Packet<'a> {
+    pub header: Icmpv6Header,
+    pub body: &'a [u8],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct Icmpv6Header {
+    pub typ: Icmpv6Type,
+    pub code: u8,
+    pub checksum: u16,
+    // meaning depends on the type, like ICMP's; the MTU for packet too big,
+    // the maximum response code of MLD queries, unused for most errors
+    pub rest_of_header: u32,
+}
+
+impl <'a> Icmpv6Packet<'a> {
+    /// The Multicast Listener Discovery message, if this is one
+    pub fn mld(&self) -> Option<Mld<'a>> {
+        all_consuming(|bs| mld(&self.header, bs))(self.body).ok().map(|(_, mld)| mld)
+    }
+}
+
+pub fn parse_icmpv6_packet(bs: &[u8]) -> IResult<'_, Icmpv6Packet<'_>> {
+    context("icmpv6", icmpv6_packet)(bs)
+}
+
+fn icmpv6_packet(bs: &[u8]) -> IResult<'_, Icmpv6Packet<'_>> {
+    let (bs, typ) = be_u8(bs)?;
+    let (bs, code) = be_u8(bs)?;
+    let (bs, checksum) = be_u16(bs)?;
+    let (bs, rest_of_header) = be_u32(bs)?;
+    let (bs, body) = rest(bs)?;
+    Ok((bs, Icmpv6Packet {
+        header: Icmpv6Header {
+            typ: Icmpv6Type::from_u8(typ),
+            code,
+            checksum,
+            rest_of_header,
+        },
+        body,
+    }))
+}
+
+impl <'a> Layer<'a> for Icmpv6Packet<'a> {
+    fn header_len(&self) -> usize {
+        8
+    }
+
+    fn payload(&self) -> &'a [u8] {
+        self.body
+    }
+
+    fn next_protocol(&self) -> Option<NextProtocol> {
+        None
+    }
+}
+
+// the checksum covers a pseudo-header, so it's fixed up by the IPv6
+// packet it's written in rather than here
+impl <'a> ToBytes for Icmpv6Packet<'a> {
+    fn write_with(&self, _fixup: Fixup, out: &mut Vec<u8>) {
+        let h = &self.header;
+        out.push(h.typ.to_u8());
+        out.push(h.code);
+        out.extend_from_slice(&h.checksum.to_be_bytes());
+        out.extend_from_slice(&h.rest_of_header.to_be_bytes());
+        out.extend_from_slice(self.body);
+    }
+}
+
+impl <'a> fmt::Display for Icmpv6Packet<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mld() {
+            Some(Mld::Query(ref query)) if query.group.is_unspecified() => write!(f, "MLD general query")?,
+            Some(Mld::Query(ref query)) => write!(f, "MLD query for {}", query.group)?,
+            Some(Mld::Report(group)) => write!(f, "MLD report for {}", group)?,
+            Some(Mld::Done(group)) => write!(f, "MLD done for {}", group)?,
+            Some(Mld::ReportV2(ref records)) => write!(f, "MLDv2 report, {} records", records.len())?,
+            None if self.header.typ == Icmpv6Type::RouterAdvertisement => match self.router_advertisement() {
+                Some(ref ra) => write!(f, "router advertisement, {} options", ra.options.len())?,
+                None => write!(f, "malformed router advertisement")?,
+            },
+            None => write!(f, "ICMPv6 {:?}, code {}", self.header.typ, self.header.code)?,
+        }
+        write!(f, ", length {}", self.body.len())
+    }
+}
+
+/// Computes the checksum for an ICMPv6 message sent with the given IP
+/// header, ignoring whatever is currently in its checksum field
+pub fn compute_checksum(ip: &IpHeader, message: &[u8]) -> Option<u16> {
+    if message.len() < 8 {
+        return None;
+    }
+    Some(checksum::transport_checksum(ip, 58, message, 2))
+}
+
+/// Checks an ICMPv6 message's checksum, including the pseudo-header
+pub fn verify_checksum(ip: &IpHeader, message: &[u8]) -> bool {
+    message.len() >= 8 && checksum::verify_transport_checksum(ip, 58, message)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub enum Icmpv6Type {
+    DestinationUnreachable,
+    PacketTooBig,
+    TimeExceeded,
+    ParameterProblem,
+    EchoRequest,
+    EchoReply,
+    MulticastListenerQuery,
+    MulticastListenerReport,
+    MulticastListenerDone,
+    RouterSolicitation,
+    RouterAdvertisement,
+    NeighborSolicitation,
+    NeighborAdvertisement,
+    Redirect,
+    MulticastListenerReportV2,
+    Other(u8),
+}
+
+impl Icmpv6Type {
+    /// Whether messages of this type quote the packet that caused them
+    pub fn is_error(&self) -> bool {
+        self.to_u8() < 128
+    }
+
+    pub fn from_u8(v: u8) -> Icmpv6Type {
+        match v {
+            1 => Icmpv6Type::DestinationUnreachable,
+            2 => Icmpv6Type::PacketTooBig,
+            3 => Icmpv6Type::TimeExceeded,
+            4 => Icmpv6Type::ParameterProblem,
+            128 => Icmpv6Type::EchoRequest,
+            129 => Icmpv6Type::EchoReply,
+            130 => Icmpv6Type::MulticastListenerQuery,
+            131 => Icmpv6Type::MulticastListenerReport,
+            132 => Icmpv6Type::MulticastListenerDone,
+            133 => Icmpv6Type::RouterSolicitation,
+            134 => Icmpv6Type::RouterAdvertisement,
+            135 => Icmpv6Type::NeighborSolicitation,
+            136 => Icmpv6Type::NeighborAdvertisement,
+            137 => Icmpv6Type::Redirect,
+            143 => Icmpv6Type::MulticastListenerReportV2,
+            x => Icmpv6Type::Other(x),
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            Icmpv6Type::DestinationUnreachable => 1,
+            Icmpv6Type::PacketTooBig => 2,
+            Icmpv6Type::TimeExceeded => 3,
+            Icmpv6Type::ParameterProblem => 4,
+            Icmpv6Type::EchoRequest => 128,
+            Icmpv6Type::EchoReply => 129,
+            Icmpv6Type::MulticastListenerQuery => 130,
+            Icmpv6Type::MulticastListenerReport => 131,
+            Icmpv6Type::MulticastListenerDone => 132,
+            Icmpv6Type::RouterSolicitation => 133,
+            Icmpv6Type::RouterAdvertisement => 134,
+            Icmpv6Type::NeighborSolicitation => 135,
+            Icmpv6Type::NeighborAdvertisement => 136,
+            Icmpv6Type::Redirect => 137,
+            Icmpv6Type::MulticastListenerReportV2 => 143,
+            Icmpv6Type::Other(x) => x,
+        }
+    }
+}
+
+/// Multicast Listener Discovery, MLDv1 (RFC 2710) and MLDv2 (RFC 3810):
+/// routers asking which groups have listeners, and hosts answering
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Mld<'a> {
+    Query(MldQuery),
+    /// An MLDv1 host listening to the group
+    Report(Ipv6Addr),
+    /// An MLDv1 host that stopped listening to the group
+    Done(Ipv6Addr),
+    ReportV2(Vec<MulticastAddressRecord<'a>>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct MldQuery {
+    pub max_response_code: u16,
+    /// Unspecified for a general query about every group
+    pub group: Ipv6Addr,
+    /// The rest of an MLDv2 query, `None` for MLDv1
+    pub v2: Option<MldV2Query>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct MldV2Query {
+    /// The S flag, telling routers not to update their timers
+    pub suppress_router_processing: bool,
+    /// The querier's robustness variable, QRV
+    pub robustness: u8,
+    /// The querier's query interval code, QQIC
+    pub query_interval_code: u8,
+    /// Set for a query about only these sources in the group
+    pub sources: Vec<Ipv6Addr>,
+}
+
+impl MldQuery {
+    /// How long listeners have to answer, in milliseconds. MLDv2 codes from
+    /// 32768 up are a mantissa and exponent.
+    pub fn max_response_delay(&self) -> u32 {
+        let code = self.max_response_code as u32;
+        match self.v2 {
+            Some(_) if code >= 0x8000 => ((code & 0xfff) | 0x1000) << (((code >> 12) & 0x7) + 3),
+            _ => code,
+        }
+    }
+}
+
+impl MldV2Query {
+    /// The querier's query interval in seconds, decoded like
+    /// `max_response_delay` but from 8 bits
+    pub fn query_interval(&self) -> u32 {
+        let code = self.query_interval_code as u32;
+        if code >= 0x80 {
+            ((code & 0xf) | 0x10) << (((code >> 4) & 0x7) + 3)
+        } else {
+            code
+        }
+    }
+}
+
+/// A group's state in an MLDv2 report, RFC 3810 section 5.2.4
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct MulticastAddressRecord<'a> {
+    pub typ: RecordType,
+    pub group: Ipv6Addr,
+    pub sources: Vec<Ipv6Addr>,
+    pub aux_data: &'a [u8],
+}
+
+/// What a multicast address record says about its group, shared by MLDv2
+/// and IGMPv3
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub enum RecordType {
+    /// Current state: listening to only the sources listed
+    ModeIsInclude,
+    /// Current state: listening to all but the sources listed
+    ModeIsExclude,
+    ChangeToInclude,
+    ChangeToExclude,
+    AllowNewSources,
+    BlockOldSources,
+    Other(u8),
+}
+
+impl RecordType {
+    pub fn from_u8(v: u8) -> RecordType {
+        match v {
+            1 => RecordType::ModeIsInclude,
+            2 => RecordType::ModeIsExclude,
+            3 => RecordType::ChangeToInclude,
+            4 => RecordType::ChangeToExclude,
+            5 => RecordType::AllowNewSources,
+            6 => RecordType::BlockOldSources,
+            x => RecordType::Other(x),
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            RecordType::ModeIsInclude => 1,
+            RecordType::ModeIsExclude => 2,
+            RecordType::ChangeToInclude => 3,
+            RecordType::ChangeToExclude => 4,
+            RecordType::AllowNewSources => 5,
+            RecordType::BlockOldSources => 6,
+            RecordType::Other(x) => x,
+        }
+    }
+}
+
+fn ipv6_addr(bs: &[u8]) -> IResult<'_, Ipv6Addr> {
+    let (bs, addr) = take(16usize)(bs)?;
+    Ok((bs, slice2addr(addr)))
+}
+
+fn addrs(n: u16, bs: &[u8]) -> IResult<'_, Vec<Ipv6Addr>> {
+    count(ipv6_addr, n as usize)(bs)
+}
+
+fn mld<'a>(header: &Icmpv6Header, bs: &'a [u8]) -> IResult<'a, Mld<'a>> {
+    match header.typ {
+        Icmpv6Type::MulticastListenerQuery => context("mld.query", |bs| mld_query(header, bs))(bs),
+        Icmpv6Type::MulticastListenerReport => context("mld.report", ipv6_addr)(bs).map(|(bs, a)| (bs, Mld::Report(a))),
+        Icmpv6Type::MulticastListenerDone => context("mld.done", ipv6_addr)(bs).map(|(bs, a)| (bs, Mld::Done(a))),
+        Icmpv6Type::MulticastListenerReportV2 => {
+            let records = header.rest_of_header as u16;
+            let (bs, records) = context("mld.report", count(address_record, records as usize))(bs)?;
+            Ok((bs, Mld::ReportV2(records)))
+        },
+        _ => Err(nom::Err::Error(ParseError::new(bs, ErrorKind::Tag))),
+    }
+}
+
+fn mld_query<'a>(header: &Icmpv6Header, bs: &'a [u8]) -> IResult<'a, Mld<'a>> {
+    let (bs, group) = ipv6_addr(bs)?;
+    let (bs, v2) = if bs.is_empty() {
+        (bs, None)
+    } else {
+        let (bs, flags) = be_u8(bs)?;
+        let (bs, query_interval_code) = be_u8(bs)?;
+        let (bs, n) = be_u16(bs)?;
+        let (bs, sources) = addrs(n, bs)?;
+        (bs, Some(MldV2Query {
+            suppress_router_processing: flags & 0x08 != 0,
+            robustness: flags & 0x07,
+            query_interval_code,
+            sources,
+        }))
+    };
+    Ok((bs, Mld::Query(MldQuery {
+        max_response_code: (header.rest_of_header >> 16) as u16,
+        group,
+        v2,
+    })))
+}
+
+fn address_record(bs: &[u8]) -> IResult<'_, MulticastAddressRecord<'_>> {
+    let (bs, typ) = be_u8(bs)?;
+    let (bs, aux_len) = be_u8(bs)?;
+    let (bs, n) = be_u16(bs)?;
+    let (bs, group) = ipv6_addr(bs)?;
+    let (bs, sources) = addrs(n, bs)?;
+    // the aux data length is in 32 bit words
+    let (bs, aux_data) = take(aux_len as usize * 4)(bs)?;
+    Ok((bs, MulticastAddressRecord {
+        typ: RecordType::from_u8(typ),
+        group,
+        sources,
+        aux_data,
+    }))
+}
+
+/// A router advertisement, RFC 4861 section 4.2: what hosts on the link
+/// need for stateless address autoconfiguration
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct RouterAdvertisement<'a> {
+    /// Zero if the router leaves it to hosts
+    pub cur_hop_limit: u8,
+    /// The M flag, addresses are available over DHCPv6
+    pub managed: bool,
+    /// The O flag, other configuration is available over DHCPv6
+    pub other_config: bool,
+    /// In seconds, zero if the router isn't a default router
+    pub router_lifetime: u16,
+    /// In milliseconds, zero if unspecified
+    pub reachable_time: u32,
+    /// In milliseconds, zero if unspecified
+    pub retrans_timer: u32,
+    pub options: Vec<NdpOption<'a>>,
+}
+
+/// Lifetimes in options that never run out
+pub const INFINITE_LIFETIME: u32 = 0xffff_ffff;
+
+/// A Neighbor Discovery option
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum NdpOption<'a> {
+    /// The sender's link-layer address, six bytes on Ethernet. Other link
+    /// layers may leave padding on the end.
+    SourceLinkLayerAddress(&'a [u8]),
+    PrefixInformation(PrefixInformation),
+    Mtu(u32),
+    /// Recursive DNS servers, RFC 8106
+    Rdnss(Rdnss),
+    /// DNS search list, RFC 8106
+    Dnssl(Dnssl),
+    Other { typ: u8, data: &'a [u8] },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct PrefixInformation {
+    pub prefix_length: u8,
+    /// The L flag, addresses in the prefix are on this link
+    pub on_link: bool,
+    /// The A flag, hosts can make up their own addresses in the prefix
+    pub autonomous: bool,
+    /// In seconds, or `INFINITE_LIFETIME`
+    pub valid_lifetime: u32,
+    /// In seconds, or `INFINITE_LIFETIME`
+    pub preferred_lifetime: u32,
+    pub prefix: Ipv6Addr,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Rdnss {
+    /// In seconds, or `INFINITE_LIFETIME`; zero withdraws the servers
+    pub lifetime: u32,
+    pub servers: Vec<Ipv6Addr>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Dnssl {
+    /// In seconds, or `INFINITE_LIFETIME`; zero withdraws the domains
+    pub lifetime: u32,
+    /// Without the trailing dot
+    pub domains: Vec<String>,
+}
+
+impl <'a> Icmpv6Packet<'a> {
+    /// The router advertisement, if this is one
+    pub fn router_advertisement(&self) -> Option<RouterAdvertisement<'a>> {
+        if self.header.typ != Icmpv6Type::RouterAdvertisement {
+            return None;
+        }
+        let rest_of_header = self.header.rest_of_header;
+        let (_, (reachable_time, retrans_timer, options)) = all_consuming(|bs| {
+            let (bs, reachable_time) = be_u32(bs)?;
+            let (bs, retrans_timer) = be_u32(bs)?;
+            let (bs, options) = context("ndp.options", ndp_options)(bs)?;
+            Ok((bs, (reachable_time, retrans_timer, options)))
+        })(self.body).ok()?;
+        Some(RouterAdvertisement {
+            cur_hop_limit: (rest_of_header >> 24) as u8,
+            managed: rest_of_header & 0x0080_0000 != 0,
+            other_config: rest_of_header & 0x0040_0000 != 0,
+            router_lifetime: rest_of_header as u16,
+            reachable_time,
+            retrans_timer,
+            options,
+        })
+    }
+}
+
+impl <'a> RouterAdvertisement<'a> {
+    /// The router's MAC address, from its source link-layer address option
+    pub fn source_mac(&self) -> Option<MacAddr> {
+        self.options.iter().filter_map(|option| match *option {
+            NdpOption::SourceLinkLayerAddress(addr) => MacAddr::from_slice(addr),
+            _ => None,
+        }).next()
+    }
+
+    pub fn prefixes(&self) -> impl Iterator<Item = &PrefixInformation> {
+        self.options.iter().filter_map(|option| match *option {
+            NdpOption::PrefixInformation(ref prefix) => Some(prefix),
+            _ => None,
+        })
+    }
+
+    pub fn mtu(&self) -> Option<u32> {
+        self.options.iter().filter_map(|option| match *option {
+            NdpOption::Mtu(mtu) => Some(mtu),
+            _ => None,
+        }).next()
+    }
+}
+
+fn ndp_options(mut bs: &[u8]) -> IResult<'_, Vec<NdpOption<'_>>> {
+    let mut options = vec![];
+    while !bs.is_empty() {
+        let (rest, option) = ndp_option(bs)?;
+        options.push(option);
+        bs = rest;
+    }
+    Ok((bs, options))
+}
+
+fn ndp_option(input: &[u8]) -> IResult<'_, NdpOption<'_>> {
+    let (bs, typ) = be_u8(input)?;
+    let (bs, len) = be_u8(bs)?;
+    // the length is in eight byte units, counting the type and length, and
+    // a zero length would never move on
+    if len == 0 {
+        return Err(nom::Err::Error(ParseError::new(input, ErrorKind::Verify)));
+    }
+    let (rest, data) = take(len as usize * 8 - 2)(bs)?;
+    let option = match typ {
+        1 => NdpOption::SourceLinkLayerAddress(data),
+        3 => all_consuming(prefix_information)(data)?.1,
+        5 => {
+            let (_, (_reserved, mtu)) = all_consuming(|bs| {
+                let (bs, reserved) = be_u16(bs)?;
+                let (bs, mtu) = be_u32(bs)?;
+                Ok((bs, (reserved, mtu)))
+            })(data)?;
+            NdpOption::Mtu(mtu)
+        },
+        25 => {
+            let (bs, _reserved) = be_u16(data)?;
+            let (bs, lifetime) = be_u32(bs)?;
+            let (_, servers) = all_consuming(many0(ipv6_addr))(bs)?;
+            NdpOption::Rdnss(Rdnss { lifetime, servers })
+        },
+        31 => {
+            let (bs, _reserved) = be_u16(data)?;
+            let (bs, lifetime) = be_u32(bs)?;
+            let (_, domains) = search_list(bs)?;
+            NdpOption::Dnssl(Dnssl { lifetime, domains })
+        },
+        _ => NdpOption::Other { typ, data },
+    };
+    Ok((rest, option))
+}
+
+fn prefix_information(bs: &[u8]) -> IResult<'_, NdpOption<'_>> {
+    let (bs, prefix_length) = be_u8(bs)?;
+    let (bs, flags) = be_u8(bs)?;
+    let (bs, valid_lifetime) = be_u32(bs)?;
+    let (bs, preferred_lifetime) = be_u32(bs)?;
+    let (bs, _reserved) = be_u32(bs)?;
+    let (bs, prefix) = ipv6_addr(bs)?;
+    Ok((bs, NdpOption::PrefixInformation(PrefixInformation {
+        prefix_length,
+        on_link: flags & 0x80 != 0,
+        autonomous: flags & 0x40 != 0,
+        valid_lifetime,
+        preferred_lifetime,
+        prefix,
+    })))
+}
+
+// uncompressed DNS names back to back, then zeros out to the end of the
+// option
+fn search_list(mut bs: &[u8]) -> IResult<'_, Vec<String>> {
+    let mut domains = vec![];
+    while bs.iter().any(|&b| b != 0) {
+        let mut labels = vec![];
+        loop {
+            let (rest, len) = be_u8(bs)?;
+            let (rest, label) = take(len)(rest)?;
+            bs = rest;
+            if label.is_empty() {
+                break;
+            }
+            labels.push(String::from_utf8_lossy(label));
+        }
+        if !labels.is_empty() {
+            domains.push(labels.join("."));
+        }
+    }
+    Ok((&bs[bs.len()..], domains))
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedIcmpv6Packet {
+    pub header: Icmpv6Header,
+    pub body: Vec<u8>,
+}
+
+impl <'a> IntoOwned for Icmpv6Packet<'a> {
+    type Owned = OwnedIcmpv6Packet;
+
+    fn into_owned(self) -> OwnedIcmpv6Packet {
+        OwnedIcmpv6Packet {
+            header: self.header,
+            body: self.body.into_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipv6::parse_ipv6_packet;
+
+    // an MLDv2 report from fe80::1, joining ff02::fb from any source and
+    // leaving ff05::2 for 2001:db8::5
+    const REPORT: [u8; 104] = [
+        0x60, 0x00, 0x00, 0x00, 0x00, 0x40, 0x3a, 0x01,
+        0xfe, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+        0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x16,
+
+        0x8f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+        0x04, 0x00, 0x00, 0x00,
+        0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xfb,
+        0x06, 0x00, 0x00, 0x01,
+        0xff, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+        0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05,
+    ];
+
+    #[test]
+    fn test_mldv2_report() {
+        let (_, ip) = parse_ipv6_packet(&REPORT).unwrap();
+        let (_, packet) = parse_icmpv6_packet(ip.body).unwrap();
+        assert_eq!(packet.header.typ, Icmpv6Type::MulticastListenerReportV2);
+        let records = match packet.mld() {
+            Some(Mld::ReportV2(records)) => records,
+            other => panic!("expected an MLDv2 report, got {:?}", other),
+        };
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].typ, RecordType::ChangeToExclude);
+        assert_eq!(records[0].group, "ff02::fb".parse::<Ipv6Addr>().unwrap());
+        assert!(records[0].sources.is_empty());
+        assert_eq!(records[1].typ, RecordType::BlockOldSources);
+        assert_eq!(records[1].sources, vec!["2001:db8::5".parse::<Ipv6Addr>().unwrap()]);
+        assert_eq!(packet.to_string(), "MLDv2 report, 2 records, length 56");
+
+        // the IPv6 packet fills in the checksum, pseudo-header and all
+        let bs = ip.to_bytes();
+        let header = IpHeader::V6(&ip.header);
+        assert!(verify_checksum(&header, &bs[40..]));
+        assert_eq!(compute_checksum(&header, &bs[40..]), Some(u16::from_be_bytes([bs[42], bs[43]])));
+        assert!(!verify_checksum(&header, ip.body));
+    }
+
+    #[test]
+    fn test_mld_queries() {
+        let group: Ipv6Addr = "ff02::1:3".parse().unwrap();
+        let mut message = vec![0x82, 0x00, 0x00, 0x00, 0x27, 0x10, 0x00, 0x00];
+        message.extend_from_slice(&group.octets());
+        let (_, packet) = parse_icmpv6_packet(&message).unwrap();
+        let query = match packet.mld() {
+            Some(Mld::Query(query)) => query,
+            other => panic!("expected an MLD query, got {:?}", other),
+        };
+        assert_eq!((query.group, query.v2.clone()), (group, None));
+        assert_eq!(query.max_response_delay(), 10000);
+
+        // an MLDv2 general query with an exponential response code, QRV 2
+        // and a 125 second interval
+        let mut message = vec![0x82, 0x00, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00];
+        message.extend_from_slice(&Ipv6Addr::UNSPECIFIED.octets());
+        message.extend_from_slice(&[0x02, 125, 0x00, 0x00]);
+        let (_, packet) = parse_icmpv6_packet(&message).unwrap();
+        let query = match packet.mld() {
+            Some(Mld::Query(query)) => query,
+            other => panic!("expected an MLD query, got {:?}", other),
+        };
+        assert_eq!(query.max_response_delay(), 0x1000 << 3);
+        let v2 = query.v2.unwrap();
+        assert_eq!((v2.suppress_router_processing, v2.robustness, v2.query_interval()), (false, 2, 125));
+        assert_eq!(packet.to_string(), "MLD general query, length 20");
+
+        // a done message that's too short, and a message that isn't MLD
+        let (_, packet) = parse_icmpv6_packet(&[0x84, 0, 0, 0, 0, 0, 0, 0, 0xff, 0x02]).unwrap();
+        assert_eq!(packet.mld(), None);
+        let (_, packet) = parse_icmpv6_packet(&[0x80, 0, 0, 0, 0, 1, 0, 1]).unwrap();
+        assert_eq!(packet.mld(), None);
+        assert_eq!(packet.to_string(), "ICMPv6 EchoRequest, code 0, length 0");
+    }
+
+    #[test]
+    fn test_router_advertisement() {
+        // from fe80::1 with M and O set and a 30 minute router lifetime
+        let mut message = vec![0x86, 0x00, 0x00, 0x00, 64, 0xc0, 0x07, 0x08, 0, 0, 0, 0, 0, 0, 0, 0];
+        message.extend_from_slice(&[0x01, 0x01, 0x02, 0x00, 0x5e, 0x00, 0x53, 0x01]);
+        message.extend_from_slice(&[0x05, 0x01, 0x00, 0x00, 0x00, 0x00, 0x05, 0xdc]);
+        // 2001:db8:1::/64, on link and autonomous, valid for 30 days and
+        // preferred for 7
+        message.extend_from_slice(&[0x03, 0x04, 64, 0xc0, 0x00, 0x27, 0x8d, 0x00, 0x00, 0x09, 0x3a, 0x80, 0, 0, 0, 0]);
+        message.extend_from_slice(&"2001:db8:1::".parse::<Ipv6Addr>().unwrap().octets());
+        message.extend_from_slice(&[0x19, 0x03, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff]);
+        message.extend_from_slice(&"2001:db8::53".parse::<Ipv6Addr>().unwrap().octets());
+        message.extend_from_slice(&[0x1f, 0x03, 0x00, 0x00, 0x00, 0x00, 0x0e, 0x10]);
+        message.extend_from_slice(b"\x07example\x03com\x00\x00\x00\x00");
+        message.extend_from_slice(&[0x26, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+
+        let (_, packet) = parse_icmpv6_packet(&message).unwrap();
+        let ra = packet.router_advertisement().unwrap();
+        assert_eq!((ra.cur_hop_limit, ra.managed, ra.other_config, ra.router_lifetime), (64, true, true, 1800));
+        assert_eq!(ra.source_mac(), Some(MacAddr([0x02, 0x00, 0x5e, 0x00, 0x53, 0x01])));
+        assert_eq!(ra.mtu(), Some(1500));
+        assert_eq!(ra.prefixes().collect::<Vec<_>>(), vec![&PrefixInformation {
+            prefix_length: 64,
+            on_link: true,
+            autonomous: true,
+            valid_lifetime: 2_592_000,
+            preferred_lifetime: 604_800,
+            prefix: "2001:db8:1::".parse().unwrap(),
+        }]);
+        assert_eq!(ra.options[3], NdpOption::Rdnss(Rdnss {
+            lifetime: INFINITE_LIFETIME,
+            servers: vec!["2001:db8::53".parse().unwrap()],
+        }));
+        assert_eq!(ra.options[4], NdpOption::Dnssl(Dnssl { lifetime: 3600, domains: vec!["example.com".to_string()] }));
+        assert_eq!(ra.options[5], NdpOption::Other { typ: 0x26, data: &[0; 6] });
+        assert_eq!(packet.to_string(), "router advertisement, 6 options, length 112");
+
+        // no options at all, a zero length option, and an MTU option that's
+        // too long
+        let (_, packet) = parse_icmpv6_packet(&message[..16]).unwrap();
+        assert_eq!(packet.router_advertisement().unwrap().options, vec![]);
+        let mut bad = message[..16].to_vec();
+        bad.extend_from_slice(&[0x01, 0x00, 0, 0, 0, 0, 0, 0]);
+        let (_, packet) = parse_icmpv6_packet(&bad).unwrap();
+        assert_eq!(packet.router_advertisement(), None);
+        assert_eq!(packet.to_string(), "malformed router advertisement, length 16");
+        bad[16..18].copy_from_slice(&[0x05, 0x02]);
+        bad.extend_from_slice(&[0; 8]);
+        let (_, packet) = parse_icmpv6_packet(&bad).unwrap();
+        assert_eq!(packet.router_advertisement(), None);
+    }
+}
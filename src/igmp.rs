@@ -0,0 +1,196 @@
+use std::fmt;
+use std::net::Ipv4Addr;
+
+use nom::bytes::complete::take;
+use nom::error::{context, ErrorKind};
+use nom::multi::count;
+use nom::number::complete::{be_u8, be_u16, be_u32};
+
+use checksum;
+use error::{IResult, ParseError};
+use icmpv6::RecordType;
+
+/// An IGMP message, versions 1 and 2 (RFC 2236) and 3 (RFC 3376): routers
+/// asking which groups have members, and hosts answering
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Igmp<'a> {
+    Query(IgmpQuery),
+    /// An IGMPv1 host joining the group
+    ReportV1(Ipv4Addr),
+    /// An IGMPv2 host joining the group
+    ReportV2(Ipv4Addr),
+    /// An IGMPv2 host leaving the group
+    Leave(Ipv4Addr),
+    ReportV3(Vec<GroupRecord<'a>>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct IgmpQuery {
+    /// In tenths of a second, zero from IGMPv1 queriers
+    pub max_response_code: u8,
+    /// Unspecified for a general query about every group
+    pub group: Ipv4Addr,
+    /// The rest of an IGMPv3 query, `None` for earlier versions
+    pub v3: Option<IgmpV3Query>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct IgmpV3Query {
+    /// The S flag, telling routers not to update their timers
+    pub suppress_router_processing: bool,
+    /// The querier's robustness variable, QRV
+    pub robustness: u8,
+    /// The querier's query interval code, QQIC
+    pub query_interval_code: u8,
+    /// Set for a query about only these sources in the group
+    pub sources: Vec<Ipv4Addr>,
+}
+
+impl IgmpQuery {
+    /// How long members have to answer, in tenths of a second. IGMPv3 codes
+    /// from 128 up are a mantissa and exponent.
+    pub fn max_response_time(&self) -> u32 {
+        let code = self.max_response_code as u32;
+        match self.v3 {
+            Some(_) if code >= 0x80 => ((code & 0xf) | 0x10) << (((code >> 4) & 0x7) + 3),
+            _ => code,
+        }
+    }
+}
+
+/// A group's state in an IGMPv3 report, RFC 3376 section 4.2.4
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct GroupRecord<'a> {
+    pub typ: RecordType,
+    pub group: Ipv4Addr,
+    pub sources: Vec<Ipv4Addr>,
+    pub aux_data: &'a [u8],
+}
+
+pub fn parse_igmp_message(bs: &[u8]) -> IResult<'_, Igmp<'_>> {
+    context("igmp", igmp)(bs)
+}
+
+fn ipv4_addr(bs: &[u8]) -> IResult<'_, Ipv4Addr> {
+    let (bs, addr) = be_u32(bs)?;
+    Ok((bs, Ipv4Addr::from(addr)))
+}
+
+fn igmp(input: &[u8]) -> IResult<'_, Igmp<'_>> {
+    let (bs, typ) = be_u8(input)?;
+    let (bs, max_response_code) = be_u8(bs)?;
+    let (bs, _checksum) = be_u16(bs)?;
+    match typ {
+        0x11 => {
+            let (bs, group) = ipv4_addr(bs)?;
+            let (bs, v3) = if bs.is_empty() {
+                (bs, None)
+            } else {
+                let (bs, flags) = be_u8(bs)?;
+                let (bs, query_interval_code) = be_u8(bs)?;
+                let (bs, n) = be_u16(bs)?;
+                let (bs, sources) = count(ipv4_addr, n as usize)(bs)?;
+                (bs, Some(IgmpV3Query {
+                    suppress_router_processing: flags & 0x08 != 0,
+                    robustness: flags & 0x07,
+                    query_interval_code,
+                    sources,
+                }))
+            };
+            Ok((bs, Igmp::Query(IgmpQuery {
+                max_response_code,
+                group,
+                v3,
+            })))
+        },
+        0x12 => ipv4_addr(bs).map(|(bs, group)| (bs, Igmp::ReportV1(group))),
+        0x16 => ipv4_addr(bs).map(|(bs, group)| (bs, Igmp::ReportV2(group))),
+        0x17 => ipv4_addr(bs).map(|(bs, group)| (bs, Igmp::Leave(group))),
+        0x22 => {
+            let (bs, _reserved) = be_u16(bs)?;
+            let (bs, n) = be_u16(bs)?;
+            let (bs, records) = count(group_record, n as usize)(bs)?;
+            Ok((bs, Igmp::ReportV3(records)))
+        },
+        _ => Err(nom::Err::Error(ParseError::new(input, ErrorKind::Tag))),
+    }
+}
+
+fn group_record(bs: &[u8]) -> IResult<'_, GroupRecord<'_>> {
+    let (bs, typ) = be_u8(bs)?;
+    let (bs, aux_len) = be_u8(bs)?;
+    let (bs, n) = be_u16(bs)?;
+    let (bs, group) = ipv4_addr(bs)?;
+    let (bs, sources) = count(ipv4_addr, n as usize)(bs)?;
+    // the aux data length is in 32 bit words
+    let (bs, aux_data) = take(aux_len as usize * 4)(bs)?;
+    Ok((bs, GroupRecord {
+        typ: RecordType::from_u8(typ),
+        group,
+        sources,
+        aux_data,
+    }))
+}
+
+impl <'a> fmt::Display for Igmp<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Igmp::Query(ref query) if query.group.is_unspecified() => write!(f, "IGMP general query"),
+            Igmp::Query(ref query) => write!(f, "IGMP query for {}", query.group),
+            Igmp::ReportV1(group) | Igmp::ReportV2(group) => write!(f, "IGMP report for {}", group),
+            Igmp::Leave(group) => write!(f, "IGMP leave for {}", group),
+            Igmp::ReportV3(ref records) => write!(f, "IGMPv3 report, {} records", records.len()),
+        }
+    }
+}
+
+/// Checks the checksum of a whole IGMP message
+pub fn verify_checksum(message: &[u8]) -> bool {
+    message.len() >= 8 && checksum::internet_checksum(message) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_igmp() {
+        // a v2 membership report for 239.1.2.3, then a leave
+        let report = [0x16, 0x00, 0xf8, 0xfa, 0xef, 0x01, 0x02, 0x03];
+        assert!(verify_checksum(&report));
+        let group = Ipv4Addr::new(239, 1, 2, 3);
+        assert_eq!(parse_igmp_message(&report).unwrap().1, Igmp::ReportV2(group));
+        let leave = [0x17, 0x00, 0xf7, 0xfa, 0xef, 0x01, 0x02, 0x03];
+        assert_eq!(parse_igmp_message(&leave).unwrap().1, Igmp::Leave(group));
+
+        // a v3 general query, with a 12.8 second response time
+        let query = [0x11, 0x80, 0x00, 0x00, 0, 0, 0, 0, 0x02, 125, 0x00, 0x00];
+        let query = match parse_igmp_message(&query).unwrap().1 {
+            Igmp::Query(query) => query,
+            other => panic!("expected a query, got {:?}", other),
+        };
+        assert_eq!(query.max_response_time(), 128);
+        assert_eq!(query.v3.unwrap().robustness, 2);
+
+        // a v3 report allowing one source for 232.1.1.1
+        let report = [
+            0x22, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+            0x05, 0x00, 0x00, 0x01, 0xe8, 0x01, 0x01, 0x01,
+            0x0a, 0x00, 0x00, 0x01,
+        ];
+        let (rest, msg) = parse_igmp_message(&report).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(msg, Igmp::ReportV3(vec![GroupRecord {
+            typ: RecordType::AllowNewSources,
+            group: Ipv4Addr::new(232, 1, 1, 1),
+            sources: vec![Ipv4Addr::new(10, 0, 0, 1)],
+            aux_data: &[],
+        }]));
+        assert_eq!(msg.to_string(), "IGMPv3 report, 1 records");
+        assert!(parse_igmp_message(&[0x13, 0, 0, 0, 0, 0, 0, 0]).is_err());
+    }
+}
@@ -0,0 +1,292 @@
+use std::convert::TryFrom;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use etherparse::{self as ep, LinkSlice, NetSlice, SlicedPacket};
+
+use dissect::{self, LinkType, Packet};
+use ethernet::{EtherType, EthernetIIPacket};
+use ipv4::{self, Ipv4Packet, Ipv4Protocol};
+use ipv6::{self, Ipv6HeaderType, Ipv6Packet};
+use tcp::{self, TcpFlags, TcpHeader};
+use udp::UdpHeader;
+use {IpPacket, ToBytes};
+
+impl From<EtherType> for ep::EtherType {
+    fn from(ethertype: EtherType) -> ep::EtherType {
+        ep::EtherType(ethertype.to_u16())
+    }
+}
+
+impl From<ep::EtherType> for EtherType {
+    fn from(ethertype: ep::EtherType) -> EtherType {
+        EtherType::from_u16(ethertype.0)
+    }
+}
+
+impl From<Ipv4Protocol> for ep::IpNumber {
+    fn from(proto: Ipv4Protocol) -> ep::IpNumber {
+        ep::IpNumber(proto.to_u8())
+    }
+}
+
+impl From<ep::IpNumber> for Ipv4Protocol {
+    fn from(proto: ep::IpNumber) -> Ipv4Protocol {
+        Ipv4Protocol::from_u8(proto.0)
+    }
+}
+
+/// The outer Ethernet header; its EtherType is the first VLAN tag's TPID
+/// if there are any, see `vlan_headers`
+impl <'a> From<&EthernetIIPacket<'a>> for ep::Ethernet2Header {
+    fn from(eth: &EthernetIIPacket<'a>) -> ep::Ethernet2Header {
+        ep::Ethernet2Header {
+            source: eth.source_mac.octets(),
+            destination: eth.dest_mac.octets(),
+            ether_type: match eth.vlans.first() {
+                Some(tag) => ep::EtherType(tag.tpid),
+                None => eth.ethertype.into(),
+            },
+        }
+    }
+}
+
+/// The frame's VLAN tags, outermost first. etherparse keeps the EtherType
+/// that follows each tag in the tag itself, rather than the tag's TPID.
+pub fn vlan_headers(eth: &EthernetIIPacket) -> Vec<ep::SingleVlanHeader> {
+    eth.vlans.iter().enumerate().map(|(i, tag)| {
+        let next = eth.vlans.get(i + 1).map(|next| next.tpid).unwrap_or_else(|| eth.ethertype.to_u16());
+        ep::SingleVlanHeader {
+            pcp: ep::VlanPcp::try_new(tag.pcp & 0x07).unwrap_or_default(),
+            drop_eligible_indicator: tag.dei,
+            vlan_id: ep::VlanId::try_new(tag.vid & 0x0fff).unwrap_or_default(),
+            ether_type: ep::EtherType(next),
+        }
+    }).collect()
+}
+
+/// Options past the 40 bytes IPv4 has room for are left off
+impl <'a> From<&ipv4::Header<'a>> for ep::Ipv4Header {
+    fn from(header: &ipv4::Header<'a>) -> ep::Ipv4Header {
+        let mut options = vec![];
+        for option in &header.options {
+            option.write_bytes(&mut options);
+        }
+        options.resize(options.len().div_ceil(4)*4, 0);
+        ep::Ipv4Header {
+            dscp: ep::Ipv4Dscp::try_new(header.dscp & 0x3f).unwrap_or_default(),
+            ecn: ep::Ipv4Ecn::try_new(header.ecn & 0x03).unwrap_or_default(),
+            total_len: header.total_len,
+            identification: header.id,
+            dont_fragment: header.flags.df,
+            more_fragments: header.flags.mf,
+            fragment_offset: ep::IpFragOffset::try_new(header.fragment_off & 0x1fff).unwrap_or_default(),
+            time_to_live: header.ttl,
+            protocol: header.proto.into(),
+            header_checksum: header.checksum,
+            source: header.src_ip.octets(),
+            destination: header.dst_ip.octets(),
+            options: ep::Ipv4Options::try_from(&options[..]).unwrap_or_default(),
+        }
+    }
+}
+
+impl <'a> From<&'a ep::Ipv4Header> for ipv4::Header<'a> {
+    fn from(header: &'a ep::Ipv4Header) -> ipv4::Header<'a> {
+        ipv4::Header {
+            len: header.ihl(),
+            dscp: header.dscp.value(),
+            ecn: header.ecn.value(),
+            total_len: header.total_len,
+            id: header.identification,
+            flags: ipv4::Flags {
+                df: header.dont_fragment,
+                mf: header.more_fragments,
+            },
+            fragment_off: header.fragment_offset.value(),
+            ttl: header.time_to_live,
+            proto: header.protocol.into(),
+            checksum: header.header_checksum,
+            src_ip: Ipv4Addr::from(header.source),
+            dst_ip: Ipv4Addr::from(header.destination),
+            options: ipv4::OptionIter::new(header.options.as_slice()).collect(),
+        }
+    }
+}
+
+impl From<&ipv6::Ipv6Header> for ep::Ipv6Header {
+    fn from(header: &ipv6::Ipv6Header) -> ep::Ipv6Header {
+        ep::Ipv6Header {
+            traffic_class: header.traffic_class,
+            flow_label: ep::Ipv6FlowLabel::try_new(header.flow_label & 0xfffff).unwrap_or_default(),
+            payload_length: header.payload_length,
+            next_header: ep::IpNumber(header.next_header.to_u8()),
+            hop_limit: header.hop_limit,
+            source: header.src_ip.octets(),
+            destination: header.dst_ip.octets(),
+        }
+    }
+}
+
+impl From<&ep::Ipv6Header> for ipv6::Ipv6Header {
+    fn from(header: &ep::Ipv6Header) -> ipv6::Ipv6Header {
+        ipv6::Ipv6Header {
+            traffic_class: header.traffic_class,
+            flow_label: header.flow_label.value(),
+            payload_length: header.payload_length,
+            next_header: Ipv6HeaderType::from_u8(header.next_header.0),
+            hop_limit: header.hop_limit,
+            src_ip: Ipv6Addr::from(header.source),
+            dst_ip: Ipv6Addr::from(header.destination),
+        }
+    }
+}
+
+/// Options past the 40 bytes TCP has room for are left off
+impl <'a> From<&TcpHeader<'a>> for ep::TcpHeader {
+    fn from(header: &TcpHeader<'a>) -> ep::TcpHeader {
+        let mut options = vec![];
+        for option in &header.options {
+            option.write_bytes(&mut options);
+        }
+        let mut out = ep::TcpHeader::new(header.src, header.dst, header.seq, header.window_sz);
+        out.acknowledgment_number = header.ack;
+        out.ns = header.flags.ns;
+        out.cwr = header.flags.cwr;
+        out.ece = header.flags.ece;
+        out.urg = header.flags.urg;
+        out.ack = header.flags.ack;
+        out.psh = header.flags.psh;
+        out.rst = header.flags.rst;
+        out.syn = header.flags.syn;
+        out.fin = header.flags.fin;
+        out.checksum = header.checksum;
+        out.urgent_pointer = header.urgent;
+        let _ = out.set_options_raw(&options);
+        out
+    }
+}
+
+impl <'a> From<&'a ep::TcpHeader> for TcpHeader<'a> {
+    fn from(header: &'a ep::TcpHeader) -> TcpHeader<'a> {
+        TcpHeader {
+            src: header.source_port,
+            dst: header.destination_port,
+            seq: header.sequence_number,
+            ack: header.acknowledgment_number,
+            flags: TcpFlags {
+                offset: header.data_offset(),
+                ns: header.ns,
+                cwr: header.cwr,
+                ece: header.ece,
+                urg: header.urg,
+                ack: header.ack,
+                psh: header.psh,
+                rst: header.rst,
+                syn: header.syn,
+                fin: header.fin,
+            },
+            window_sz: header.window_size,
+            checksum: header.checksum,
+            urgent: header.urgent_pointer,
+            options: tcp::OptionIter::new(header.options.as_slice()).collect(),
+        }
+    }
+}
+
+impl From<UdpHeader> for ep::UdpHeader {
+    fn from(header: UdpHeader) -> ep::UdpHeader {
+        ep::UdpHeader {
+            source_port: header.src,
+            destination_port: header.dst,
+            length: header.len,
+            checksum: header.checksum,
+        }
+    }
+}
+
+impl From<ep::UdpHeader> for UdpHeader {
+    fn from(header: ep::UdpHeader) -> UdpHeader {
+        UdpHeader {
+            src: header.source_port,
+            dst: header.destination_port,
+            len: header.length,
+            checksum: header.checksum,
+        }
+    }
+}
+
+/// Dissects a packet etherparse has sliced up. Ethernet frames are run
+/// through `parse_packet` whole; otherwise dissection starts at the IP
+/// layer, so DNS and the rest still get parsed behind Linux cooked
+/// captures and anything else etherparse knows that blosh doesn't.
+pub fn from_sliced<'a>(sliced: &'a SlicedPacket<'a>) -> Packet<'a> {
+    if let Some(LinkSlice::Ethernet2(ref eth)) = sliced.link {
+        return dissect::parse_packet(LinkType::Ethernet, eth.slice());
+    }
+    let ip = match sliced.net {
+        Some(NetSlice::Ipv4(ref ip)) => ipv4::parse_ipv4_header(ip.header().slice()).ok()
+            .map(|(_, header)| IpPacket::V4(Ipv4Packet {
+                header,
+                body: ip.payload().payload,
+            })),
+        Some(NetSlice::Ipv6(ref ip)) => ipv6::parse_ipv6_header(ip.header().slice()).ok()
+            .and_then(|(_, header)| {
+                let (_, extensions) = ipv6::parse_extensions(ip.extensions().slice(), header.next_header).ok()?;
+                Some(IpPacket::V6(Ipv6Packet {
+                    header,
+                    extensions,
+                    body: ip.payload().payload,
+                }))
+            }),
+        None => None,
+    };
+    match ip {
+        Some(ip) => dissect::parse_ip_layers(ip),
+        None => Packet::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dissect::ApplicationLayer;
+    use testgen::{self, Endpoint};
+    use TransportLayerPacket;
+
+    #[test]
+    fn test_etherparse() {
+        let (client, server) = (Endpoint::client(), Endpoint::server());
+        let (query, _) = testgen::dns_exchange(&client, &server, 1, "example.com", Ipv4Addr::new(203, 0, 113, 1));
+        let sliced = SlicedPacket::from_ethernet(&query).unwrap();
+        match from_sliced(&sliced).application {
+            Some(ApplicationLayer::Dns(msg)) => assert_eq!(msg.to_string(), "query A example.com"),
+            x => panic!("expected DNS, got {:?}", x),
+        }
+        // without the link layer, starting from etherparse's IP slice
+        let sliced = SlicedPacket::from_ip(&query[14..]).unwrap();
+        assert!(from_sliced(&sliced).application.is_some());
+
+        let syn = &testgen::tcp_handshake(&client, &server, 40000, 443)[0];
+        let packet = dissect::parse_packet(LinkType::Ethernet, syn);
+        let ip = match packet.ip {
+            Some(IpPacket::V4(ref ip)) => &ip.header,
+            ref x => panic!("expected IPv4, got {:?}", x),
+        };
+        let tcp = match packet.transport {
+            Some(TransportLayerPacket::Tcp(ref tcp)) => &tcp.header,
+            ref x => panic!("expected TCP, got {:?}", x),
+        };
+        let sliced = SlicedPacket::from_ethernet(syn).unwrap();
+        let (ep_ip, ep_tcp) = match (sliced.net, sliced.transport) {
+            (Some(NetSlice::Ipv4(ip)), Some(ep::TransportSlice::Tcp(tcp))) =>
+                (ip.header().to_header(), tcp.to_header()),
+            x => panic!("expected IPv4 and TCP, got {:?}", x),
+        };
+        assert_eq!(ep::Ipv4Header::from(ip), ep_ip);
+        assert_eq!(ep::TcpHeader::from(tcp), ep_tcp);
+        let back = TcpHeader::from(&ep_tcp);
+        assert_eq!(back.to_bytes(), tcp.to_bytes());
+        assert_eq!(ipv4::Header::from(&ep_ip).to_bytes(), ip.to_bytes());
+        assert_eq!(ep::Ethernet2Header::from(packet.ethernet.as_ref().unwrap()).ether_type, ep::EtherType::IPV4);
+    }
+}
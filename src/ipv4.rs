@@ -1,28 +1,37 @@
 use std::net::Ipv4Addr;
 
-use nom::{be_u8, be_u16, IResult};
+use nom::bits::bits;
+use nom::bits::complete::{tag as tag_bits, take as take_bits};
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take};
+use nom::combinator::{cond, eof, value, verify};
+use nom::error::context;
+use nom::number::complete::{be_u8, be_u16};
+#[cfg(feature = "arbitrary")]
+use arbitrary::{Arbitrary, Unstructured};
+
+use checksum;
+use error::IResult;
+use {many_till_list, Fixup, IntoOwned, IpHeader, Layer, List, NextProtocol, ToBytes};
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct Ipv4Packet<'a> {
     pub header: Header<'a>,
     pub body: &'a [u8],
 }
 
-pub fn parse_ipv4_packet<'a>(bs: &'a [u8]) -> IResult<&'a [u8], Ipv4Packet<'a>, u32> {
+pub fn parse_ipv4_packet(bs: &[u8]) -> IResult<'_, Ipv4Packet<'_>> {
     use std::cmp::min;
-    match parse_ipv4_header(bs) {
-        IResult::Done(_, header) => {
-            IResult::Done(&b""[..], Ipv4Packet {
-                body: &bs[min(4*header.len as usize, bs.len())..min(header.total_len as usize, bs.len())],
-                header: header,
-            })
-        },
-        IResult::Incomplete(x) => IResult::Incomplete(x),
-        IResult::Error(x) => IResult::Error(x),
-    }
+    let (_, header) = parse_ipv4_header(bs)?;
+    Ok((&b""[..], Ipv4Packet {
+        body: &bs[min(4*header.len as usize, bs.len())..min(header.total_len as usize, bs.len())],
+        header,
+    }))
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct Header<'a> {
     pub len: u8,
     pub dscp: u8,
@@ -34,20 +43,22 @@ pub struct Header<'a> {
     pub ttl: u8,
     pub proto: Ipv4Protocol,
     pub checksum: u16,
-    // NOTE: network order; MSB first
     pub src_ip: Ipv4Addr,
-    // NOTE: network order; MSB first
     pub dst_ip: Ipv4Addr,
-    pub options: Vec<Ipv4Option<'a>>,
+    pub options: List<Ipv4Option<'a>>,
 }
 
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub struct Flags {
     pub df: bool,
     pub mf: bool,
 }
 
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub enum Ipv4Option<'a> {
     EndOfOption,
     NoOperation,
@@ -55,111 +66,292 @@ pub enum Ipv4Option<'a> {
     Dummy
 }
 
-fn test_eof<'a>(bs: &'a [u8]) -> IResult<&'a [u8], Ipv4Option<'a>, u32> {
-    cond_reduce!(bs, bs.len() == 0, value!(Ipv4Option::Dummy))
-}
-
-
-named!(parse_options<Vec<Ipv4Option> >,
-   do_parse!(
-        options: many_till!(
-            alt!(
-                call!(test_eof) |
-                do_parse!(
-                    _a: char!(0x01 as char) >>
-                    (Ipv4Option::NoOperation)
-                ) |
-                do_parse!(
-                    class: be_u8 >>
-                    length: be_u8 >>
-                    data: take!(length - 2) >>
-                    (Ipv4Option::Other(class, length, data))
-                )
-            ),
-            alt!(
-                call!(test_eof) |
-                do_parse!(
-                    _a: char!(0x00 as char) >>
-                    (Ipv4Option::EndOfOption)
-                )
-            )
-        ) >> 
-        ({
-            let (mut options, last) = options;
-            options.push(last);
-            options
-                .into_iter()
-                .filter(|o| match o {
-                    &Ipv4Option::Dummy => false,
-                    _ => true,
-                })
-                .collect()
-        })
-    )
-);
-
-
-pub fn parse_ipv4_header<'a>(bs: &'a [u8]) -> IResult<&'a [u8], Header<'a>, u32> {
-    do_parse!(
-        bs,
-        first_bits: bits!(
-            do_parse!(
-                _a: tag_bits!(u8, 4, 0b0100) >>
-                len: take_bits!(u8, 4) >>
-                dscp: take_bits!(u8, 6) >>
-                ecn: take_bits!(u8, 2) >>
-                ((len, dscp, ecn))
-            )
-        ) >>
-        total_len: be_u16 >>
-        id: be_u16 >>
-        second_bits: bits!(
-            do_parse!(
-                _reserved: tag_bits!(u8, 1, 0) >>
-                df: take_bits!(u8, 1) >>
-                mf: take_bits!(u8, 1) >>
-                fragment_off: take_bits!(u16, 13) >>
-                ((df, mf, fragment_off))
-            )
-        ) >>
-        ttl: be_u8 >>
-        proto: be_u8 >>
-        checksum: be_u16 >>
-        src: take!(4) >>
-        dst: take!(4) >>
-        options: cond!(first_bits.0 > 5,
-            parse_options
-        ) >>
-        ({
-            let unwrapped_options = match options {
-                Some(options) => {
-                    options
-                },
-                None => vec![],
-            };
-            Header {
-                len: first_bits.0,
-                dscp: first_bits.1,
-                ecn: first_bits.2,
-                total_len: total_len,
-                id: id,
-                flags: Flags {
-                    df: second_bits.0 == 1,
-                    mf: second_bits.1 == 1,
-                },
-                fragment_off: second_bits.2,
-                ttl: ttl,
-                proto: Ipv4Protocol::from_u8(proto),
-                checksum: checksum,
-                src_ip: Ipv4Addr::new(src[0], src[1], src[2], src[3]),
-                dst_ip: Ipv4Addr::new(dst[0], dst[1], dst[2], dst[3]),
-                options: unwrapped_options,
-            }
-        })
-    )
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+fn option(bs: &[u8]) -> IResult<'_, Ipv4Option<'_>> {
+    let (bs, class) = be_u8(bs)?;
+    let (bs, length) = verify(be_u8, |&length| length >= 2)(bs)?;
+    let (bs, data) = take(length - 2)(bs)?;
+    Ok((bs, Ipv4Option::Other(class, length, data)))
+}
+
+fn end_of_options(bs: &[u8]) -> IResult<'_, Option<Ipv4Option<'_>>> {
+    alt((
+        value(None, eof),
+        value(Some(Ipv4Option::EndOfOption), tag([0x00])),
+    ))(bs)
+}
+
+fn known_option(bs: &[u8]) -> IResult<'_, Ipv4Option<'_>> {
+    alt((value(Ipv4Option::NoOperation, tag([0x01])), option))(bs)
+}
+
+fn parse_options(bs: &[u8]) -> IResult<'_, List<Ipv4Option<'_>>> {
+    let (bs, (mut options, end)) = many_till_list(known_option, end_of_options)(bs)?;
+    options.extend(end);
+    Ok((bs, options))
+}
+
+/// Walks an IPv4 header's options in place, for when collecting them into
+/// a `Vec` costs too much. Stops after the end of option list, or at the
+/// first option that's malformed or doesn't fit.
+#[derive(Clone, Debug)]
+pub struct OptionIter<'a> {
+    bs: &'a [u8],
+}
+
+impl <'a> OptionIter<'a> {
+    /// Iterates over raw option bytes, everything in the header after its
+    /// first 20 bytes
+    pub fn new(bs: &'a [u8]) -> OptionIter<'a> {
+        OptionIter { bs }
+    }
+
+    /// Iterates over the options of a packet's header, without parsing
+    /// anything else in it
+    pub fn from_packet(packet: &'a [u8]) -> OptionIter<'a> {
+        use std::cmp::min;
+        let end = match packet.first() {
+            Some(&b) => min(4*(b & 0x0f) as usize, packet.len()),
+            None => 0,
+        };
+        OptionIter::new(if end > 20 { &packet[20..end] } else { &[] })
+    }
+}
+
+impl <'a> Iterator for OptionIter<'a> {
+    type Item = Ipv4Option<'a>;
+
+    fn next(&mut self) -> Option<Ipv4Option<'a>> {
+        let bs = self.bs;
+        self.bs = &[];
+        match bs.first() {
+            None => None,
+            Some(&0x00) => Some(Ipv4Option::EndOfOption),
+            Some(_) => {
+                let (rest, option) = known_option(bs).ok()?;
+                self.bs = rest;
+                Some(option)
+            },
+        }
+    }
+}
+
+
+fn first_bits(bs: (&[u8], usize)) -> nom::IResult<(&[u8], usize), (u8, u8, u8)> {
+    let (bs, _version) = tag_bits(0b0100u8, 4usize)(bs)?;
+    let (bs, len) = take_bits(4usize)(bs)?;
+    let (bs, dscp) = take_bits(6usize)(bs)?;
+    let (bs, ecn) = take_bits(2usize)(bs)?;
+    Ok((bs, (len, dscp, ecn)))
+}
+
+fn second_bits(bs: (&[u8], usize)) -> nom::IResult<(&[u8], usize), (u8, u8, u16)> {
+    let (bs, _reserved) = tag_bits(0u8, 1usize)(bs)?;
+    let (bs, df) = take_bits(1usize)(bs)?;
+    let (bs, mf) = take_bits(1usize)(bs)?;
+    let (bs, fragment_off) = take_bits(13usize)(bs)?;
+    Ok((bs, (df, mf, fragment_off)))
+}
+
+pub fn parse_ipv4_header(bs: &[u8]) -> IResult<'_, Header<'_>> {
+    context("ip", ipv4_header)(bs)
+}
+
+fn ipv4_header(bs: &[u8]) -> IResult<'_, Header<'_>> {
+    // the header is at least five words, and inside the packet
+    let (bs, (len, dscp, ecn)) = verify(bits(first_bits), |&(len, _, _)| len >= 5)(bs)?;
+    let (bs, total_len) = verify(be_u16, |&total_len| total_len >= 4*len as u16)(bs)?;
+    let (bs, id) = be_u16(bs)?;
+    let (bs, (df, mf, fragment_off)) = bits(second_bits)(bs)?;
+    let (bs, ttl) = be_u8(bs)?;
+    let (bs, proto) = be_u8(bs)?;
+    let (bs, checksum) = be_u16(bs)?;
+    let (bs, src) = take(4usize)(bs)?;
+    let (bs, dst) = take(4usize)(bs)?;
+    let (bs, options) = cond(len > 5, context("ip.options", |bs| {
+        let (rest, bs) = take(4*len as usize - 20)(bs)?;
+        let (_, options) = parse_options(bs)?;
+        Ok((rest, options))
+    }))(bs)?;
+    Ok((bs, Header {
+        len,
+        dscp,
+        ecn,
+        total_len,
+        id,
+        flags: Flags {
+            df: df == 1,
+            mf: mf == 1,
+        },
+        fragment_off,
+        ttl,
+        proto: Ipv4Protocol::from_u8(proto),
+        checksum,
+        src_ip: Ipv4Addr::new(src[0], src[1], src[2], src[3]),
+        dst_ip: Ipv4Addr::new(dst[0], dst[1], dst[2], dst[3]),
+        options: options.unwrap_or_default(),
+    }))
+}
+
+impl <'a> Header<'a> {
+    /// A header without options, for a packet with an empty body. The
+    /// checksum is left zero for `ToBytes` to fill in.
+    pub fn new(proto: Ipv4Protocol, src_ip: Ipv4Addr, dst_ip: Ipv4Addr) -> Header<'a> {
+        Header {
+            len: 5,
+            dscp: 0,
+            ecn: 0,
+            total_len: 20,
+            id: 0,
+            flags: Flags {
+                df: false,
+                mf: false,
+            },
+            fragment_off: 0,
+            ttl: 64,
+            proto,
+            checksum: 0,
+            src_ip,
+            dst_ip,
+            options: List::new(),
+        }
+    }
+}
+
+impl <'a> ToBytes for Ipv4Option<'a> {
+    fn write_with(&self, _fixup: Fixup, out: &mut Vec<u8>) {
+        match *self {
+            Ipv4Option::EndOfOption => out.push(0x00),
+            Ipv4Option::NoOperation => out.push(0x01),
+            Ipv4Option::Other(class, length, data) => {
+                out.push(class);
+                out.push(length);
+                out.extend_from_slice(data);
+            },
+            Ipv4Option::Dummy => {},
+        }
+    }
+}
+
+// header length in 32 bit words that fits these options
+fn header_len(options: &[Ipv4Option]) -> u8 {
+    let mut bs = vec![];
+    for option in options {
+        option.write_bytes(&mut bs);
+    }
+    5 + bs.len().div_ceil(4) as u8
+}
+
+impl <'a> ToBytes for Header<'a> {
+    fn write_with(&self, fixup: Fixup, out: &mut Vec<u8>) {
+        let start = out.len();
+        let len = if fixup.lengths { header_len(&self.options) } else { self.len };
+        out.push(0x40 | (len & 0x0f));
+        out.push((self.dscp << 2) | (self.ecn & 0x03));
+        out.extend_from_slice(&self.total_len.to_be_bytes());
+        out.extend_from_slice(&self.id.to_be_bytes());
+        let frag = ((self.flags.df as u16) << 14) | ((self.flags.mf as u16) << 13) | (self.fragment_off & 0x1fff);
+        out.extend_from_slice(&frag.to_be_bytes());
+        out.push(self.ttl);
+        out.push(self.proto.to_u8());
+        out.extend_from_slice(&self.checksum.to_be_bytes());
+        out.extend_from_slice(&self.src_ip.octets());
+        out.extend_from_slice(&self.dst_ip.octets());
+        for option in &self.options {
+            option.write_with(fixup, out);
+        }
+        // pad the options out to the header length
+        while out.len() - start < 4*len as usize {
+            out.push(0);
+        }
+        if fixup.checksums {
+            out[start + 10..start + 12].copy_from_slice(&[0, 0]);
+            let sum = checksum::internet_checksum(&out[start..]);
+            out[start + 10..start + 12].copy_from_slice(&sum.to_be_bytes());
+        }
+    }
+}
+
+impl <'a> Ipv4Packet<'a> {
+    /// Puts `body` behind `header`, setting the header's length and total
+    /// length to match
+    pub fn new(mut header: Header<'a>, body: &'a [u8]) -> Ipv4Packet<'a> {
+        header.len = header_len(&header.options);
+        header.total_len = 4*header.len as u16 + body.len() as u16;
+        Ipv4Packet {
+            header,
+            body,
+        }
+    }
+}
+
+impl <'a> Layer<'a> for Ipv4Packet<'a> {
+    fn header_len(&self) -> usize {
+        4*self.header.len as usize
+    }
+
+    fn payload(&self) -> &'a [u8] {
+        self.body
+    }
+
+    fn next_protocol(&self) -> Option<NextProtocol> {
+        Some(NextProtocol::Ip(self.header.proto))
+    }
+}
+
+impl <'a> ToBytes for Ipv4Packet<'a> {
+    fn write_with(&self, fixup: Fixup, out: &mut Vec<u8>) {
+        let mut header = self.header.clone();
+        if fixup.lengths {
+            header.len = header_len(&header.options);
+            header.total_len = 4*header.len as u16 + self.body.len() as u16;
+        }
+        header.write_with(fixup, out);
+        let body = out.len();
+        out.extend_from_slice(self.body);
+        if fixup.checksums {
+            checksum::fix_transport_checksum(&IpHeader::V4(&header), header.proto, &mut out[body..]);
+        }
+    }
+}
+
+// fields in range, and only options that parse back the same way
+#[cfg(feature = "arbitrary")]
+impl <'a> Arbitrary<'a> for Header<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Header<'a>> {
+        let proto = Ipv4Protocol::from_u8(u.arbitrary()?);
+        let mut header = Header::new(proto, u.arbitrary()?, u.arbitrary()?);
+        header.dscp = u.int_in_range(0..=0x3f)?;
+        header.ecn = u.int_in_range(0..=0x03)?;
+        header.id = u.arbitrary()?;
+        header.flags = u.arbitrary()?;
+        header.fragment_off = u.int_in_range(0..=0x1fff)?;
+        header.ttl = u.arbitrary()?;
+        header.checksum = u.arbitrary()?;
+        for _ in 0..u.int_in_range(0..=4)? {
+            header.options.push(if u.arbitrary()? {
+                Ipv4Option::NoOperation
+            } else {
+                let len = u.int_in_range(0..=6)?;
+                let data = u.bytes(len)?;
+                Ipv4Option::Other(u.int_in_range(2..=0xff)?, data.len() as u8 + 2, data)
+            });
+        }
+        header.len = header_len(&header.options);
+        Ok(header)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl <'a> Arbitrary<'a> for Ipv4Packet<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Ipv4Packet<'a>> {
+        let header = Header::arbitrary(u)?;
+        Ok(Ipv4Packet::new(header, u.arbitrary()?))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub enum Ipv4Protocol {
     Icmp,
     Igmp,
@@ -351,4 +543,181 @@ impl Ipv4Protocol {
             x => Ipv4Protocol::Other(x),
         }
     }
+
+    pub fn to_u8(&self) -> u8 {
+        use self::Ipv4Protocol::*;
+        match *self {
+            Icmp => 1,
+            Igmp => 2,
+            Ggp => 3,
+            Ip => 4,
+            St => 5,
+            Tcp => 6,
+            Ucl => 7,
+            Egp => 8,
+            Igp => 9,
+            BbnRccMon => 10,
+            NvpII => 11,
+            Pup => 12,
+            Argus => 13,
+            Emcon => 14,
+            Xnet => 15,
+            Chaos => 16,
+            Udp => 17,
+            Mux => 18,
+            DcnMeas => 19,
+            Hmp => 20,
+            Prm => 21,
+            XndIdp => 22,
+            Trunk1 => 23,
+            Trunk2 => 24,
+            Leaf1 => 25,
+            Leaf2 => 26,
+            Rdp => 27,
+            Irtp => 28,
+            IsoTp4 => 29,
+            Netblt => 30,
+            MfeNsp => 31,
+            MeritInp => 32,
+            Sep => 33,
+            ThreePC => 34,
+            Idpr => 35,
+            Xtp => 36,
+            Ddp => 37,
+            IdprCmtp => 38,
+            TpPlusPlus => 39,
+            Il => 40,
+            Sip => 41,
+            Sdrp => 42,
+            SipSr => 43,
+            SipFrag => 44,
+            Idrp => 45,
+            Rsvp => 46,
+            Gre => 47,
+            Mhrp => 48,
+            Bna => 49,
+            SippEsp => 50,
+            SippAh => 51,
+            INlsp => 52,
+            Swipe => 53,
+            Nhrp => 54,
+            Cftp => 62,
+            SatExpak => 64,
+            Kryptolan => 65,
+            Rvd => 66,
+            Ippc => 67,
+            SatMon => 69,
+            Visa => 70,
+            Ipcv => 71,
+            Cpnx => 72,
+            Cphb => 73,
+            Wsn => 74,
+            Pvp => 75,
+            BrSatMon => 76,
+            SunNd => 77,
+            WbMon => 78,
+            WbExpak => 79,
+            IsoIp => 80,
+            Vmtp => 81,
+            SecureVmtp => 82,
+            Vines => 83,
+            Ttp => 84,
+            NsfnetIgp => 85,
+            Dgp => 86,
+            Tcf => 87,
+            Igrp => 88,
+            Ospfigp => 89,
+            SpriteRpc => 90,
+            Larp => 91,
+            Mtp => 92,
+            Ax25 => 93,
+            Ipip => 94,
+            Micp => 95,
+            SccSp => 96,
+            Etherip => 97,
+            Encap => 98,
+            Gmtp => 100,
+            Other(x) => x,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedIpv4Packet {
+    pub header: OwnedHeader,
+    pub body: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedHeader {
+    pub len: u8,
+    pub dscp: u8,
+    pub ecn: u8,
+    pub total_len: u16,
+    pub id: u16,
+    pub flags: Flags,
+    pub fragment_off: u16,
+    pub ttl: u8,
+    pub proto: Ipv4Protocol,
+    pub checksum: u16,
+    pub src_ip: Ipv4Addr,
+    pub dst_ip: Ipv4Addr,
+    pub options: Vec<OwnedIpv4Option>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OwnedIpv4Option {
+    EndOfOption,
+    NoOperation,
+    Other(u8, u8, Vec<u8>),
+    Dummy
+}
+
+impl <'a> IntoOwned for Ipv4Packet<'a> {
+    type Owned = OwnedIpv4Packet;
+
+    fn into_owned(self) -> OwnedIpv4Packet {
+        OwnedIpv4Packet {
+            header: self.header.into_owned(),
+            body: self.body.into_owned(),
+        }
+    }
+}
+
+impl <'a> IntoOwned for Header<'a> {
+    type Owned = OwnedHeader;
+
+    fn into_owned(self) -> OwnedHeader {
+        OwnedHeader {
+            len: self.len,
+            dscp: self.dscp,
+            ecn: self.ecn,
+            total_len: self.total_len,
+            id: self.id,
+            flags: self.flags,
+            fragment_off: self.fragment_off,
+            ttl: self.ttl,
+            proto: self.proto,
+            checksum: self.checksum,
+            src_ip: self.src_ip,
+            dst_ip: self.dst_ip,
+            options: self.options.into_owned(),
+        }
+    }
+}
+
+impl <'a> IntoOwned for Ipv4Option<'a> {
+    type Owned = OwnedIpv4Option;
+
+    fn into_owned(self) -> OwnedIpv4Option {
+        match self {
+            Ipv4Option::EndOfOption => OwnedIpv4Option::EndOfOption,
+            Ipv4Option::NoOperation => OwnedIpv4Option::NoOperation,
+            Ipv4Option::Other(class, len, data) => OwnedIpv4Option::Other(class, len, data.into_owned()),
+            Ipv4Option::Dummy => OwnedIpv4Option::Dummy,
+        }
+    }
 }
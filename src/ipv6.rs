@@ -2,74 +2,68 @@ use std::net::Ipv6Addr;
 
 use std::cmp::min;
 
-use nom::{be_u8, be_u16, be_u32, rest, IResult};
+use nom::bits::bits;
+use nom::bits::complete::{tag as tag_bits, take as take_bits};
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take};
+use nom::combinator::{eof, map, rest, value, verify};
+use nom::error::{context, ErrorKind};
+use nom::multi::{count, many_till};
+use nom::number::complete::{be_u8, be_u16, be_u32, be_u64};
+#[cfg(feature = "arbitrary")]
+use arbitrary::{Arbitrary, Unstructured};
 
 use ::ipv4::Ipv4Protocol;
+use checksum;
+use error::{IResult, ParseError};
+use {many_till_list, Fixup, IntoOwned, IpHeader, Layer, List, NextProtocol, ToBytes};
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct Ipv6Packet<'a> {
     pub header: Ipv6Header,
     pub extensions: Vec<Ipv6Extension<'a>>,
     pub body: &'a [u8],
 }
 
-struct PacketBody<'a> {
-    extensions: Vec<Ipv6Extension<'a>>,
-    body: &'a [u8],
+// TODO: handle Jumbo Packets correctly
+pub fn parse_ipv6_packet(bs: &[u8]) -> IResult<'_, Ipv6Packet<'_>> {
+    context("ipv6", ipv6_packet)(bs)
 }
 
-// TODO: handle Jumbo Packets correctly
-named!(pub parse_ipv6_packet<Ipv6Packet>,
-    do_parse!(
-        header: parse_ipv6_header >>
-        packet_body: flat_map!(
-            take!(header.payload_length),
-            do_parse!(
-                extensions: call!(parse_extensions, header.next_header) >>
-                payload: rest >>
-                (PacketBody {
-                    body: payload,
-                    extensions: extensions,
-                })
-            )
-        ) >>
-        (Ipv6Packet {
-            header: header,
-            extensions: packet_body.extensions,
-            body: packet_body.body
-        })
-    )
-);
+fn ipv6_packet(bs: &[u8]) -> IResult<'_, Ipv6Packet<'_>> {
+    let (bs, header) = ipv6_header(bs)?;
+    let (bs, payload) = context("ipv6.plen", take(header.payload_length))(bs)?;
+    let (body, extensions) = parse_extensions(payload, header.next_header)?;
+    Ok((bs, Ipv6Packet {
+        header,
+        extensions,
+        body,
+    }))
+}
 
 
 fn has_next_header(ht: Ipv6HeaderType) -> bool {
-    match ht {
-        Ipv6HeaderType::Ipv4(_) => false,
-        Ipv6HeaderType::NoNext => false,
-        _ => true,
-    }
+    !matches!(ht, Ipv6HeaderType::Ipv4(_) | Ipv6HeaderType::NoNext)
 }
 
-fn parse_extensions<'a>(mut bs: &'a [u8], mut header_type: Ipv6HeaderType) -> IResult<&'a [u8], Vec<Ipv6Extension<'a>>, u32> {
+/// Parses the chain of extension headers that starts with `header_type`,
+/// up to the first header that isn't one
+pub fn parse_extensions(mut bs: &[u8], mut header_type: Ipv6HeaderType) -> IResult<'_, Vec<Ipv6Extension<'_>>> {
     let mut ret = Vec::new();
     while has_next_header(header_type) {
-        let extensions_ret = parse_ipv6_extension(bs, header_type);
-        match extensions_ret {
-            IResult::Done(new_bs, extension) => {
-                header_type = extension.next_header;
-                ret.push(extension);
-                bs = new_bs;
-            },
-            IResult::Incomplete(x) => return IResult::Incomplete(x),
-            IResult::Error(x) => return IResult::Error(x),
-        }
+        let (new_bs, extension) = parse_ipv6_extension(bs, header_type)?;
+        header_type = extension.next_header;
+        ret.push(extension);
+        bs = new_bs;
     }
-    IResult::Done(bs, ret)
+    Ok((bs, ret))
 }
 
 
 // TODO: wrap IP addresses in a struct to allow Deref to std::net::IpAddr 
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Ipv6Header {
     pub traffic_class: u8,
     pub flow_label: u32,
@@ -80,61 +74,60 @@ pub struct Ipv6Header {
     pub dst_ip: Ipv6Addr,
 }
 
-struct Bitfields {
-    traffic_class: u8,
-    flow_label: u32,
-}
-
 pub fn slice2addr(ip: &[u8]) -> Ipv6Addr {
     let pair = |x, y| ((x as u16) << 8) | (y as u16);
     Ipv6Addr::new(
         pair(ip[0], ip[1]),
         pair(ip[2], ip[3]),
         pair(ip[4], ip[5]),
-        pair(ip[6], ip[6]),
+        pair(ip[6], ip[7]),
         pair(ip[8], ip[9]),
         pair(ip[10], ip[11]),
         pair(ip[12], ip[13]),
         pair(ip[14], ip[15]))
 }
 
-named!(pub parse_ipv6_header<Ipv6Header>,
-    do_parse!(
-        bitfields: bits!(
-            do_parse!(
-                tag_bits!(u8, 4, 6) >>
-                traffic_class: take_bits!(u8, 8) >>
-                flow_label: take_bits!(u32, 20) >>
-                (Bitfields {
-                    traffic_class: traffic_class,
-                    flow_label: flow_label,
-                })
-            )
-        ) >>
-        payload_length: be_u16 >>
-        next_header: be_u8 >>
-        hop_limit: be_u8 >>
-        src: take!(16) >>
-        dst: take!(16) >>
-        (Ipv6Header {
-            traffic_class: bitfields.traffic_class,
-            flow_label: bitfields.flow_label,
-            payload_length: payload_length,
-            next_header: Ipv6HeaderType::from_u8(next_header),
-            hop_limit: hop_limit,
-            src_ip: slice2addr(src),
-            dst_ip: slice2addr(dst),
-        })
-    )
-);
+fn parse_bitfields(bs: (&[u8], usize)) -> nom::IResult<(&[u8], usize), (u8, u32)> {
+    let (bs, _version) = tag_bits(6u8, 4usize)(bs)?;
+    let (bs, traffic_class) = take_bits(8usize)(bs)?;
+    let (bs, flow_label) = take_bits(20usize)(bs)?;
+    Ok((bs, (traffic_class, flow_label)))
+}
+
+pub fn parse_ipv6_header(bs: &[u8]) -> IResult<'_, Ipv6Header> {
+    context("ipv6", ipv6_header)(bs)
+}
+
+fn ipv6_header(bs: &[u8]) -> IResult<'_, Ipv6Header> {
+    let (bs, (traffic_class, flow_label)) = bits(parse_bitfields)(bs)?;
+    let (bs, payload_length) = be_u16(bs)?;
+    let (bs, next_header) = be_u8(bs)?;
+    let (bs, hop_limit) = be_u8(bs)?;
+    let (bs, src) = take(16usize)(bs)?;
+    let (bs, dst) = take(16usize)(bs)?;
+    Ok((bs, Ipv6Header {
+        traffic_class,
+        flow_label,
+        payload_length,
+        next_header: Ipv6HeaderType::from_u8(next_header),
+        hop_limit,
+        src_ip: slice2addr(src),
+        dst_ip: slice2addr(dst),
+    }))
+}
 
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub enum Ipv6HeaderType {
     HopByHopOptions,
     Routing,
     Fragment,
     DestinationOptions,
+    AuthenticationHeader,
+    EncapsulatingSecurityPayload,
+    Mobility,
     NoNext,
     Ipv4(::ipv4::Ipv4Protocol),
 }
@@ -145,163 +138,1012 @@ impl Ipv6HeaderType {
             0 => Ipv6HeaderType::HopByHopOptions,
             43 => Ipv6HeaderType::Routing,
             44 => Ipv6HeaderType::Fragment,
+            50 => Ipv6HeaderType::EncapsulatingSecurityPayload,
+            51 => Ipv6HeaderType::AuthenticationHeader,
             60 => Ipv6HeaderType::DestinationOptions,
+            135 => Ipv6HeaderType::Mobility,
             59 => Ipv6HeaderType::NoNext,
             _ => Ipv6HeaderType::Ipv4(Ipv4Protocol::from_u8(v)),
         }
     }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            Ipv6HeaderType::HopByHopOptions => 0,
+            Ipv6HeaderType::Routing => 43,
+            Ipv6HeaderType::Fragment => 44,
+            Ipv6HeaderType::EncapsulatingSecurityPayload => 50,
+            Ipv6HeaderType::AuthenticationHeader => 51,
+            Ipv6HeaderType::DestinationOptions => 60,
+            Ipv6HeaderType::Mobility => 135,
+            Ipv6HeaderType::NoNext => 59,
+            Ipv6HeaderType::Ipv4(proto) => proto.to_u8(),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub struct Ipv6Extension<'a> {
     pub inner: Ipv6HeaderData<'a>,
     pub len: u8,
     pub next_header: Ipv6HeaderType,
 }
 
-named_args!(parse_hop(header_type:Ipv6HeaderType)<Ipv6Extension>,
-    cond_reduce!(header_type == Ipv6HeaderType::HopByHopOptions,
-        do_parse!(
-            next_header: be_u8 >>
-            len: be_u8 >>
-            options: peek!(call!(parse_options, (8*len + 6) as usize)) >>
-            take!((8*len + 6) as usize) >>
-            (Ipv6Extension {
-                inner: Ipv6HeaderData::HopByHopOptions(options),
-                len: len,
-                next_header: Ipv6HeaderType::from_u8(next_header),
-            })
-        )
-    )
-);
-
-named_args!(parse_routing(header_type:Ipv6HeaderType)<Ipv6Extension>,
-    cond_reduce!(header_type == Ipv6HeaderType::Routing,
-        do_parse!(
-            next_header: be_u8 >>
-            len: be_u8 >>
-            routing_type: be_u8 >>
-            segments_left: be_u8 >>
-            routing_data: take!((8*len + 4) as usize) >>
-            (Ipv6Extension {
-                inner: Ipv6HeaderData::Routing(routing_type, segments_left, routing_data),
-                len: len,
-                next_header: Ipv6HeaderType::from_u8(next_header),
-            })
-        )
-    )
-);
-
-struct FragmentBitfield {
-    frag_offset: u16,
-    last_frag: bool,
-}
-
-named_args!(parse_fragment(header_type:Ipv6HeaderType)<Ipv6Extension>,
-    cond_reduce!(header_type == Ipv6HeaderType::Fragment,
-        do_parse!(
-            next_header: be_u8 >>
-            be_u8 >>
-            bitfield: bits!(
-                do_parse!(
-                    fragment_offset: take_bits!(u16, 13) >>
-                    take_bits!(u8, 2) >>
-                    last_frag: take_bits!(u8, 1) >>
-                    (FragmentBitfield {
-                        frag_offset: fragment_offset,
-                        last_frag: last_frag == 1,
-                    })
-                )
-            ) >>
-            id: be_u32 >>
-            (Ipv6Extension {
-                inner: Ipv6HeaderData::Fragment(bitfield.frag_offset, bitfield.last_frag, id),
-                len: 2,
-                next_header: Ipv6HeaderType::from_u8(next_header),
-            })
-        )
-    )
-);
-
-named_args!(parse_destination(header_type:Ipv6HeaderType)<Ipv6Extension>,
-    cond_reduce!(header_type == Ipv6HeaderType::DestinationOptions,
-        do_parse!(
-            next_header: be_u8 >>
-            len: be_u8 >>
-            options: peek!(call!(parse_options, (8*len + 6) as usize)) >>
-            take!((8*len + 6) as usize) >>
-            (Ipv6Extension {
-                inner: Ipv6HeaderData::DestinationOptions(options),
-                len: len,
-                next_header: Ipv6HeaderType::from_u8(next_header),
-            })
-        )
-    )
-);
-
-fn parse_ipv6_extension<'a>(bs: &'a [u8], header_type: Ipv6HeaderType) -> IResult<&'a [u8], Ipv6Extension<'a>, u32> {
-    alt!(
-        bs,
-        call!(parse_hop, header_type) |
-        call!(parse_routing, header_type) |
-        call!(parse_fragment, header_type) |
-        call!(parse_destination, header_type)
-    )
+// hop-by-hop and destination options headers share a layout
+fn parse_options_header(bs: &[u8]) -> IResult<'_, (u8, u8, List<Ipv6Option<'_>>)> {
+    let (bs, next_header) = be_u8(bs)?;
+    let (bs, len) = be_u8(bs)?;
+    let (bs, data) = take(8*(len as usize) + 6)(bs)?;
+    let (_, options) = parse_options(data)?;
+    Ok((bs, (next_header, len, options)))
+}
+
+fn parse_hop(bs: &[u8]) -> IResult<'_, Ipv6Extension<'_>> {
+    let (bs, (next_header, len, options)) = parse_options_header(bs)?;
+    Ok((bs, Ipv6Extension {
+        inner: Ipv6HeaderData::HopByHopOptions(options),
+        len,
+        next_header: Ipv6HeaderType::from_u8(next_header),
+    }))
+}
+
+fn parse_routing(bs: &[u8]) -> IResult<'_, Ipv6Extension<'_>> {
+    let (bs, next_header) = be_u8(bs)?;
+    let (bs, len) = be_u8(bs)?;
+    let (bs, routing_type) = be_u8(bs)?;
+    let (bs, segments_left) = be_u8(bs)?;
+    let (bs, routing_data) = take(8*(len as usize) + 4)(bs)?;
+    Ok((bs, Ipv6Extension {
+        inner: routing_data_from(routing_type, segments_left, routing_data),
+        len,
+        next_header: Ipv6HeaderType::from_u8(next_header),
+    }))
+}
+
+fn routing_data_from<'a>(routing_type: u8, segments_left: u8, data: &'a [u8]) -> Ipv6HeaderData<'a> {
+    match routing_type {
+        4 => match parse_segment_routing(data, segments_left) {
+            Ok((_, srh)) => Ipv6HeaderData::SegmentRouting(srh),
+            _ => Ipv6HeaderData::Routing(routing_type, segments_left, data),
+        },
+        _ => Ipv6HeaderData::Routing(routing_type, segments_left, data),
+    }
+}
+
+// https://tools.ietf.org/html/rfc8754
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct SegmentRoutingHeader<'a> {
+    pub segments_left: u8,
+    pub last_entry: u8,
+    pub flags: u8,
+    pub tag: u16,
+    // NOTE: stored in reverse order; segments[0] is the final destination
+    pub segments: Vec<Ipv6Addr>,
+    pub tlvs: &'a [u8],
+}
+
+fn parse_segment_routing(bs: &[u8], segments_left: u8) -> IResult<'_, SegmentRoutingHeader<'_>> {
+    let (bs, last_entry) = be_u8(bs)?;
+    let (bs, flags) = be_u8(bs)?;
+    let (bs, tag) = be_u16(bs)?;
+    let (bs, segments) = count(map(take(16usize), slice2addr), last_entry as usize + 1)(bs)?;
+    let (bs, tlvs) = rest(bs)?;
+    Ok((bs, SegmentRoutingHeader {
+        segments_left,
+        last_entry,
+        flags,
+        tag,
+        segments,
+        tlvs,
+    }))
+}
+
+fn parse_fragment_bits(bs: (&[u8], usize)) -> nom::IResult<(&[u8], usize), (u16, u8)> {
+    let (bs, fragment_offset) = take_bits(13usize)(bs)?;
+    let (bs, _reserved): (_, u8) = take_bits(2usize)(bs)?;
+    let (bs, last_frag) = take_bits(1usize)(bs)?;
+    Ok((bs, (fragment_offset, last_frag)))
+}
+
+fn parse_fragment(bs: &[u8]) -> IResult<'_, Ipv6Extension<'_>> {
+    let (bs, next_header) = be_u8(bs)?;
+    let (bs, _reserved) = be_u8(bs)?;
+    let (bs, (frag_offset, last_frag)) = bits(parse_fragment_bits)(bs)?;
+    let (bs, id) = be_u32(bs)?;
+    Ok((bs, Ipv6Extension {
+        inner: Ipv6HeaderData::Fragment(frag_offset, last_frag == 1, id),
+        len: 2,
+        next_header: Ipv6HeaderType::from_u8(next_header),
+    }))
+}
+
+fn parse_destination(bs: &[u8]) -> IResult<'_, Ipv6Extension<'_>> {
+    let (bs, (next_header, len, options)) = parse_options_header(bs)?;
+    Ok((bs, Ipv6Extension {
+        inner: Ipv6HeaderData::DestinationOptions(options),
+        len,
+        next_header: Ipv6HeaderType::from_u8(next_header),
+    }))
+}
+
+// https://tools.ietf.org/html/rfc4302
+fn parse_authentication(bs: &[u8]) -> IResult<'_, Ipv6Extension<'_>> {
+    let (bs, next_header) = be_u8(bs)?;
+    let (bs, len) = verify(be_u8, |&len| len > 0)(bs)?;
+    let (bs, _reserved) = be_u16(bs)?;
+    let (bs, spi) = be_u32(bs)?;
+    let (bs, seq) = be_u32(bs)?;
+    let (bs, icv) = take(4*(len as usize) - 4)(bs)?;
+    Ok((bs, Ipv6Extension {
+        inner: Ipv6HeaderData::AuthenticationHeader(spi, seq, icv),
+        len,
+        next_header: Ipv6HeaderType::from_u8(next_header),
+    }))
+}
+
+// https://tools.ietf.org/html/rfc4303
+// Everything after the sequence number is encrypted, so the chain can't be
+// walked any further; the ciphertext is left as the packet body.
+fn parse_esp(bs: &[u8]) -> IResult<'_, Ipv6Extension<'_>> {
+    let (bs, spi) = be_u32(bs)?;
+    let (bs, seq) = be_u32(bs)?;
+    Ok((bs, Ipv6Extension {
+        inner: Ipv6HeaderData::EncapsulatingSecurityPayload(spi, seq),
+        len: 0,
+        next_header: Ipv6HeaderType::NoNext,
+    }))
+}
+
+// https://tools.ietf.org/html/rfc6275#section-6.1
+fn parse_mobility(bs: &[u8]) -> IResult<'_, Ipv6Extension<'_>> {
+    let (bs, next_header) = be_u8(bs)?;
+    let (bs, len) = be_u8(bs)?;
+    let (bs, mh_type) = be_u8(bs)?;
+    let (bs, _reserved) = be_u8(bs)?;
+    let (bs, checksum) = be_u16(bs)?;
+    let (bs, data) = take(8*(len as usize) + 2)(bs)?;
+    Ok((bs, Ipv6Extension {
+        inner: Ipv6HeaderData::Mobility(MobilityHeader::from(mh_type, checksum, data)),
+        len,
+        next_header: Ipv6HeaderType::from_u8(next_header),
+    }))
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct MobilityHeader<'a> {
+    pub mh_type: u8,
+    pub checksum: u16,
+    pub message: MobilityMessage<'a>,
+    pub options: Vec<MobilityOption<'a>>,
+}
+
+impl <'a> MobilityHeader<'a> {
+    pub fn from(mh_type: u8, checksum: u16, data: &'a [u8]) -> MobilityHeader<'a> {
+        let parsed = parse_mobility_message(data, mh_type).and_then(|(bs, message)| {
+            let (bs, (options, _)) = many_till(parse_mobility_option, eof)(bs)?;
+            Ok((bs, (message, options)))
+        });
+        let (message, options) = match parsed {
+            Ok((_, (message, options))) => (message, options),
+            _ => (MobilityMessage::Unknown(mh_type, data), vec![]),
+        };
+        MobilityHeader {
+            mh_type,
+            checksum,
+            message,
+            options,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub enum MobilityMessage<'a> {
+    BindingRefreshRequest,
+    // home init cookie
+    HomeTestInit(u64),
+    // care-of init cookie
+    CareOfTestInit(u64),
+    // nonce index, init cookie, keygen token
+    HomeTest(u16, u64, u64),
+    CareOfTest(u16, u64, u64),
+    BindingUpdate(BindingUpdate),
+    BindingAcknowledgement(BindingAcknowledgement),
+    // status, home address
+    BindingError(u8, Ipv6Addr),
+    Unknown(u8, &'a [u8]),
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct BindingUpdate {
+    pub sequence: u16,
+    pub acknowledge: bool,
+    pub home_registration: bool,
+    pub link_local_compatibility: bool,
+    pub key_management_compatibility: bool,
+    pub lifetime: u16,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub struct BindingAcknowledgement {
+    pub status: u8,
+    pub key_management_compatibility: bool,
+    pub sequence: u16,
+    pub lifetime: u16,
+}
+
+fn parse_mobility_message(bs: &[u8], mh_type: u8) -> IResult<'_, MobilityMessage<'_>> {
+    match mh_type {
+        0 => {
+            let (bs, _reserved) = be_u16(bs)?;
+            Ok((bs, MobilityMessage::BindingRefreshRequest))
+        },
+        1 => {
+            let (bs, _reserved) = be_u16(bs)?;
+            let (bs, cookie) = be_u64(bs)?;
+            Ok((bs, MobilityMessage::HomeTestInit(cookie)))
+        },
+        2 => {
+            let (bs, _reserved) = be_u16(bs)?;
+            let (bs, cookie) = be_u64(bs)?;
+            Ok((bs, MobilityMessage::CareOfTestInit(cookie)))
+        },
+        3 => {
+            let (bs, index) = be_u16(bs)?;
+            let (bs, cookie) = be_u64(bs)?;
+            let (bs, token) = be_u64(bs)?;
+            Ok((bs, MobilityMessage::HomeTest(index, cookie, token)))
+        },
+        4 => {
+            let (bs, index) = be_u16(bs)?;
+            let (bs, cookie) = be_u64(bs)?;
+            let (bs, token) = be_u64(bs)?;
+            Ok((bs, MobilityMessage::CareOfTest(index, cookie, token)))
+        },
+        5 => {
+            let (bs, sequence) = be_u16(bs)?;
+            let (bs, flags) = be_u16(bs)?;
+            let (bs, lifetime) = be_u16(bs)?;
+            Ok((bs, MobilityMessage::BindingUpdate(BindingUpdate {
+                sequence,
+                acknowledge: flags & 0x8000 != 0,
+                home_registration: flags & 0x4000 != 0,
+                link_local_compatibility: flags & 0x2000 != 0,
+                key_management_compatibility: flags & 0x1000 != 0,
+                lifetime,
+            })))
+        },
+        6 => {
+            let (bs, status) = be_u8(bs)?;
+            let (bs, flags) = be_u8(bs)?;
+            let (bs, sequence) = be_u16(bs)?;
+            let (bs, lifetime) = be_u16(bs)?;
+            Ok((bs, MobilityMessage::BindingAcknowledgement(BindingAcknowledgement {
+                status,
+                key_management_compatibility: flags & 0x80 != 0,
+                sequence,
+                lifetime,
+            })))
+        },
+        7 => {
+            let (bs, status) = be_u8(bs)?;
+            let (bs, _reserved) = be_u8(bs)?;
+            let (bs, home_address) = take(16usize)(bs)?;
+            Ok((bs, MobilityMessage::BindingError(status, slice2addr(home_address))))
+        },
+        _ => Ok((&bs[bs.len()..], MobilityMessage::Unknown(mh_type, bs))),
+    }
+}
+
+// https://tools.ietf.org/html/rfc6275#section-6.2
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub enum MobilityOption<'a> {
+    Padding0,
+    Padding1,
+    BindingRefreshAdvice(u16),
+    AlternateCareOfAddress(Ipv6Addr),
+    // home nonce index, care-of nonce index
+    NonceIndices(u16, u16),
+    BindingAuthorizationData(&'a [u8]),
+    Unknown(u8, u8, &'a [u8]),
+}
+
+impl <'a> MobilityOption<'a> {
+    pub fn from(typ: u8, len: u8, data: &'a [u8]) -> MobilityOption<'a> {
+        match (typ, len) {
+            (1, _) => MobilityOption::Padding1,
+            (2, 2) => MobilityOption::BindingRefreshAdvice(((data[0] as u16) << 8) | (data[1] as u16)),
+            (3, 16) => MobilityOption::AlternateCareOfAddress(slice2addr(data)),
+            (4, 4) => MobilityOption::NonceIndices(
+                ((data[0] as u16) << 8) | (data[1] as u16),
+                ((data[2] as u16) << 8) | (data[3] as u16)),
+            (5, _) => MobilityOption::BindingAuthorizationData(data),
+            _ => MobilityOption::Unknown(typ, len, data),
+        }
+    }
+}
+
+fn parse_mobility_option(bs: &[u8]) -> IResult<'_, MobilityOption<'_>> {
+    alt((
+        value(MobilityOption::Padding0, tag([0x00])),
+        |bs| {
+            let (bs, typ) = be_u8(bs)?;
+            let (bs, len) = be_u8(bs)?;
+            let (bs, data) = take(len)(bs)?;
+            Ok((bs, MobilityOption::from(typ, len, data)))
+        },
+    ))(bs)
+}
+
+fn parse_ipv6_extension(bs: &[u8], header_type: Ipv6HeaderType) -> IResult<'_, Ipv6Extension<'_>> {
+    match header_type {
+        Ipv6HeaderType::HopByHopOptions => context("ipv6.hopopts", parse_hop)(bs),
+        Ipv6HeaderType::Routing => context("ipv6.routing", parse_routing)(bs),
+        Ipv6HeaderType::Fragment => context("ipv6.fragment", parse_fragment)(bs),
+        Ipv6HeaderType::DestinationOptions => context("ipv6.dstopts", parse_destination)(bs),
+        Ipv6HeaderType::AuthenticationHeader => context("ipv6.ah", parse_authentication)(bs),
+        Ipv6HeaderType::EncapsulatingSecurityPayload => context("ipv6.esp", parse_esp)(bs),
+        Ipv6HeaderType::Mobility => context("ipv6.mipv6", parse_mobility)(bs),
+        _ => Err(nom::Err::Error(ParseError::new(bs, ErrorKind::Switch))),
+    }
 }
 
 // TODO: use type synonyms to give these nicer type names
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub enum Ipv6HeaderData<'a> {
-    HopByHopOptions(Vec<Ipv6Option<'a>>),
+    HopByHopOptions(List<Ipv6Option<'a>>),
     Routing(u8, u8, &'a [u8]),
+    SegmentRouting(SegmentRoutingHeader<'a>),
     Fragment(u16, bool, u32),
-    DestinationOptions(Vec<Ipv6Option<'a>>),
+    DestinationOptions(List<Ipv6Option<'a>>),
+    AuthenticationHeader(u32, u32, &'a [u8]),
+    EncapsulatingSecurityPayload(u32, u32),
+    Mobility(MobilityHeader<'a>),
     NoNext,
 }
 
+// https://tools.ietf.org/html/rfc8200#section-4.2
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub enum Ipv6Option<'a> {
-    Opt(u8, u8, &'a [u8]),
     Padding0,
     Padding1,
+    RouterAlert(RouterAlert),
+    JumboPayload(u32),
+    TunnelEncapsulationLimit(u8),
+    HomeAddress(Ipv6Addr),
+    Unknown(u8, u8, &'a [u8]),
+    Dummy,
+}
+
+impl <'a> Ipv6Option<'a> {
+    pub fn from(typ: u8, len: u8, data: &'a [u8]) -> Ipv6Option<'a> {
+        match (typ, len) {
+            (0x01, _) => Ipv6Option::Padding1,
+            (0x05, 2) => Ipv6Option::RouterAlert(
+                RouterAlert::from_u16(((data[0] as u16) << 8) | (data[1] as u16))),
+            (0xc2, 4) => Ipv6Option::JumboPayload(
+                ((data[0] as u32) << 24) | ((data[1] as u32) << 16) |
+                ((data[2] as u32) << 8) | (data[3] as u32)),
+            (0x04, 1) => Ipv6Option::TunnelEncapsulationLimit(data[0]),
+            (0xc9, 16) => Ipv6Option::HomeAddress(slice2addr(data)),
+            _ => Ipv6Option::Unknown(typ, len, data),
+        }
+    }
+}
+
+// https://tools.ietf.org/html/rfc2711
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+pub enum RouterAlert {
+    Mld,
+    Rsvp,
+    ActiveNetworks,
+    Other(u16),
+}
+
+impl RouterAlert {
+    pub fn from_u16(v: u16) -> RouterAlert {
+        match v {
+            0 => RouterAlert::Mld,
+            1 => RouterAlert::Rsvp,
+            2 => RouterAlert::ActiveNetworks,
+            x => RouterAlert::Other(x),
+        }
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        match *self {
+            RouterAlert::Mld => 0,
+            RouterAlert::Rsvp => 1,
+            RouterAlert::ActiveNetworks => 2,
+            RouterAlert::Other(x) => x,
+        }
+    }
+}
+
+fn ipv6_option(bs: &[u8]) -> IResult<'_, Ipv6Option<'_>> {
+    alt((
+        value(Ipv6Option::Padding0, tag([0x00])),
+        |bs| {
+            let (bs, typ) = be_u8(bs)?;
+            let (bs, len) = be_u8(bs)?;
+            let (bs, data) = take(len)(bs)?;
+            Ok((bs, Ipv6Option::from(typ, len, data)))
+        },
+    ))(bs)
+}
+
+fn parse_options(bs: &[u8]) -> IResult<'_, List<Ipv6Option<'_>>> {
+    map(many_till_list(ipv6_option, eof), |(options, _)| options)(bs)
+}
+
+/// Walks the options of a hop-by-hop or destination options header in
+/// place, for when collecting them into a `Vec` costs too much. Stops at
+/// the first option that's malformed or doesn't fit.
+#[derive(Clone, Debug)]
+pub struct OptionIter<'a> {
+    bs: &'a [u8],
+}
+
+impl <'a> OptionIter<'a> {
+    /// Iterates over raw option bytes, everything in the extension header
+    /// after its next header and length fields
+    pub fn new(bs: &'a [u8]) -> OptionIter<'a> {
+        OptionIter { bs }
+    }
+
+    /// Iterates over the hop-by-hop options of a packet, if it starts with
+    /// them, without parsing anything else in it
+    pub fn hop_by_hop(packet: &'a [u8]) -> Option<OptionIter<'a>> {
+        if packet.len() < 42 || packet[6] != 0 {
+            return None;
+        }
+        let end = min(40 + 8*(packet[41] as usize + 1), packet.len());
+        Some(OptionIter::new(&packet[42..end]))
+    }
+}
+
+impl <'a> Iterator for OptionIter<'a> {
+    type Item = Ipv6Option<'a>;
+
+    fn next(&mut self) -> Option<Ipv6Option<'a>> {
+        match ipv6_option(self.bs) {
+            Ok((rest, option)) => {
+                self.bs = rest;
+                Some(option)
+            },
+            Err(_) => {
+                self.bs = &[];
+                None
+            },
+        }
+    }
+}
+
+impl Ipv6Header {
+    /// A header for a packet with an empty payload
+    pub fn new(next_header: Ipv6HeaderType, src_ip: Ipv6Addr, dst_ip: Ipv6Addr) -> Ipv6Header {
+        Ipv6Header {
+            traffic_class: 0,
+            flow_label: 0,
+            payload_length: 0,
+            next_header,
+            hop_limit: 64,
+            src_ip,
+            dst_ip,
+        }
+    }
+}
+
+impl ToBytes for Ipv6Header {
+    fn write_with(&self, _fixup: Fixup, out: &mut Vec<u8>) {
+        let first = (6 << 28) | ((self.traffic_class as u32) << 20) | (self.flow_label & 0xfffff);
+        out.extend_from_slice(&first.to_be_bytes());
+        out.extend_from_slice(&self.payload_length.to_be_bytes());
+        out.push(self.next_header.to_u8());
+        out.push(self.hop_limit);
+        out.extend_from_slice(&self.src_ip.octets());
+        out.extend_from_slice(&self.dst_ip.octets());
+    }
+}
+
+impl <'a> Ipv6HeaderData<'a> {
+    /// The next header value that announces an extension like this one
+    pub fn header_type(&self) -> Ipv6HeaderType {
+        match *self {
+            Ipv6HeaderData::HopByHopOptions(_) => Ipv6HeaderType::HopByHopOptions,
+            Ipv6HeaderData::Routing(_, _, _) | Ipv6HeaderData::SegmentRouting(_) => Ipv6HeaderType::Routing,
+            Ipv6HeaderData::Fragment(_, _, _) => Ipv6HeaderType::Fragment,
+            Ipv6HeaderData::DestinationOptions(_) => Ipv6HeaderType::DestinationOptions,
+            Ipv6HeaderData::AuthenticationHeader(_, _, _) => Ipv6HeaderType::AuthenticationHeader,
+            Ipv6HeaderData::EncapsulatingSecurityPayload(_, _) => Ipv6HeaderType::EncapsulatingSecurityPayload,
+            Ipv6HeaderData::Mobility(_) => Ipv6HeaderType::Mobility,
+            Ipv6HeaderData::NoNext => Ipv6HeaderType::NoNext,
+        }
+    }
+}
+
+// zero fills what was written since `start` out to `len` bytes
+fn pad_to(out: &mut Vec<u8>, start: usize, len: usize) {
+    while out.len() - start < len {
+        out.push(0);
+    }
+}
+
+impl <'a> ToBytes for Ipv6Option<'a> {
+    fn write_with(&self, _fixup: Fixup, out: &mut Vec<u8>) {
+        match *self {
+            Ipv6Option::Padding0 => out.push(0x00),
+            // NOTE: a PadN's length isn't kept, so it's written without any
+            // padding; the header is zero filled to its length afterwards
+            Ipv6Option::Padding1 => out.extend_from_slice(&[0x01, 0]),
+            Ipv6Option::RouterAlert(alert) => {
+                out.extend_from_slice(&[0x05, 2]);
+                out.extend_from_slice(&alert.to_u16().to_be_bytes());
+            },
+            Ipv6Option::JumboPayload(len) => {
+                out.extend_from_slice(&[0xc2, 4]);
+                out.extend_from_slice(&len.to_be_bytes());
+            },
+            Ipv6Option::TunnelEncapsulationLimit(limit) => out.extend_from_slice(&[0x04, 1, limit]),
+            Ipv6Option::HomeAddress(addr) => {
+                out.extend_from_slice(&[0xc9, 16]);
+                out.extend_from_slice(&addr.octets());
+            },
+            Ipv6Option::Unknown(typ, len, data) => {
+                out.extend_from_slice(&[typ, len]);
+                out.extend_from_slice(data);
+            },
+            Ipv6Option::Dummy => {},
+        }
+    }
+}
+
+impl <'a> ToBytes for MobilityOption<'a> {
+    fn write_with(&self, _fixup: Fixup, out: &mut Vec<u8>) {
+        match *self {
+            MobilityOption::Padding0 => out.push(0),
+            MobilityOption::Padding1 => out.extend_from_slice(&[1, 0]),
+            MobilityOption::BindingRefreshAdvice(interval) => {
+                out.extend_from_slice(&[2, 2]);
+                out.extend_from_slice(&interval.to_be_bytes());
+            },
+            MobilityOption::AlternateCareOfAddress(addr) => {
+                out.extend_from_slice(&[3, 16]);
+                out.extend_from_slice(&addr.octets());
+            },
+            MobilityOption::NonceIndices(home, care_of) => {
+                out.extend_from_slice(&[4, 4]);
+                out.extend_from_slice(&home.to_be_bytes());
+                out.extend_from_slice(&care_of.to_be_bytes());
+            },
+            MobilityOption::BindingAuthorizationData(data) => {
+                out.extend_from_slice(&[5, data.len() as u8]);
+                out.extend_from_slice(data);
+            },
+            MobilityOption::Unknown(typ, len, data) => {
+                out.extend_from_slice(&[typ, len]);
+                out.extend_from_slice(data);
+            },
+        }
+    }
+}
+
+impl <'a> ToBytes for MobilityMessage<'a> {
+    fn write_with(&self, _fixup: Fixup, out: &mut Vec<u8>) {
+        match *self {
+            MobilityMessage::BindingRefreshRequest => out.extend_from_slice(&[0, 0]),
+            MobilityMessage::HomeTestInit(cookie) | MobilityMessage::CareOfTestInit(cookie) => {
+                out.extend_from_slice(&[0, 0]);
+                out.extend_from_slice(&cookie.to_be_bytes());
+            },
+            MobilityMessage::HomeTest(index, cookie, token) | MobilityMessage::CareOfTest(index, cookie, token) => {
+                out.extend_from_slice(&index.to_be_bytes());
+                out.extend_from_slice(&cookie.to_be_bytes());
+                out.extend_from_slice(&token.to_be_bytes());
+            },
+            MobilityMessage::BindingUpdate(bu) => {
+                let flags = ((bu.acknowledge as u16) << 15) | ((bu.home_registration as u16) << 14) |
+                    ((bu.link_local_compatibility as u16) << 13) | ((bu.key_management_compatibility as u16) << 12);
+                out.extend_from_slice(&bu.sequence.to_be_bytes());
+                out.extend_from_slice(&flags.to_be_bytes());
+                out.extend_from_slice(&bu.lifetime.to_be_bytes());
+            },
+            MobilityMessage::BindingAcknowledgement(ba) => {
+                out.push(ba.status);
+                out.push((ba.key_management_compatibility as u8) << 7);
+                out.extend_from_slice(&ba.sequence.to_be_bytes());
+                out.extend_from_slice(&ba.lifetime.to_be_bytes());
+            },
+            MobilityMessage::BindingError(status, addr) => {
+                out.extend_from_slice(&[status, 0]);
+                out.extend_from_slice(&addr.octets());
+            },
+            MobilityMessage::Unknown(_, data) => out.extend_from_slice(data),
+        }
+    }
+}
+
+impl <'a> ToBytes for Ipv6Extension<'a> {
+    fn write_with(&self, fixup: Fixup, out: &mut Vec<u8>) {
+        let start = out.len();
+        let next_header = self.next_header.to_u8();
+        // most extensions are 8 byte units long, not counting the first
+        let len = 8*(self.len as usize + 1);
+        match self.inner {
+            Ipv6HeaderData::HopByHopOptions(ref options) | Ipv6HeaderData::DestinationOptions(ref options) => {
+                out.extend_from_slice(&[next_header, self.len]);
+                for option in options {
+                    option.write_with(fixup, out);
+                }
+                pad_to(out, start, len);
+            },
+            Ipv6HeaderData::Routing(routing_type, segments_left, data) => {
+                out.extend_from_slice(&[next_header, self.len, routing_type, segments_left]);
+                out.extend_from_slice(data);
+                pad_to(out, start, len);
+            },
+            Ipv6HeaderData::SegmentRouting(ref srh) => {
+                out.extend_from_slice(&[next_header, self.len, 4, srh.segments_left, srh.last_entry, srh.flags]);
+                out.extend_from_slice(&srh.tag.to_be_bytes());
+                for segment in &srh.segments {
+                    out.extend_from_slice(&segment.octets());
+                }
+                out.extend_from_slice(srh.tlvs);
+                pad_to(out, start, len);
+            },
+            Ipv6HeaderData::Fragment(offset, more, id) => {
+                out.extend_from_slice(&[next_header, 0]);
+                out.extend_from_slice(&((offset << 3) | more as u16).to_be_bytes());
+                out.extend_from_slice(&id.to_be_bytes());
+            },
+            Ipv6HeaderData::AuthenticationHeader(spi, seq, icv) => {
+                out.extend_from_slice(&[next_header, self.len, 0, 0]);
+                out.extend_from_slice(&spi.to_be_bytes());
+                out.extend_from_slice(&seq.to_be_bytes());
+                out.extend_from_slice(icv);
+                // in 4 byte units, not counting the first two
+                pad_to(out, start, 4*(self.len as usize + 2));
+            },
+            Ipv6HeaderData::EncapsulatingSecurityPayload(spi, seq) => {
+                out.extend_from_slice(&spi.to_be_bytes());
+                out.extend_from_slice(&seq.to_be_bytes());
+            },
+            Ipv6HeaderData::Mobility(ref mh) => {
+                out.extend_from_slice(&[next_header, self.len, mh.mh_type, 0]);
+                out.extend_from_slice(&mh.checksum.to_be_bytes());
+                mh.message.write_with(fixup, out);
+                for option in &mh.options {
+                    option.write_with(fixup, out);
+                }
+                pad_to(out, start, len);
+            },
+            Ipv6HeaderData::NoNext => {},
+        }
+    }
+}
+
+impl <'a> Ipv6Packet<'a> {
+    /// Puts `extensions` and `body` behind `header`, setting the header's
+    /// next header and payload length to match
+    pub fn new(mut header: Ipv6Header, extensions: Vec<Ipv6Extension<'a>>, body: &'a [u8]) -> Ipv6Packet<'a> {
+        if let Some(first) = extensions.first() {
+            header.next_header = first.inner.header_type();
+        }
+        let extensions_len: usize = extensions.iter().map(|e| e.to_bytes().len()).sum();
+        header.payload_length = (extensions_len + body.len()) as u16;
+        Ipv6Packet {
+            header,
+            extensions,
+            body,
+        }
+    }
+}
+
+impl <'a> Layer<'a> for Ipv6Packet<'a> {
+    fn header_len(&self) -> usize {
+        // the extensions are whatever of the payload isn't body
+        40 + (self.header.payload_length as usize).saturating_sub(self.body.len())
+    }
+
+    fn payload(&self) -> &'a [u8] {
+        self.body
+    }
+
+    fn next_protocol(&self) -> Option<NextProtocol> {
+        let last = self.extensions.last().map_or(self.header.next_header, |e| e.next_header);
+        match last {
+            Ipv6HeaderType::Ipv4(proto) => Some(NextProtocol::Ip(proto)),
+            _ => None,
+        }
+    }
+}
+
+impl <'a> ToBytes for Ipv6Packet<'a> {
+    fn write_with(&self, fixup: Fixup, out: &mut Vec<u8>) {
+        let mut extensions = vec![];
+        for extension in &self.extensions {
+            extension.write_with(fixup, &mut extensions);
+        }
+        let mut header = self.header;
+        if fixup.lengths {
+            header.payload_length = (extensions.len() + self.body.len()) as u16;
+        }
+        header.write_with(fixup, out);
+        out.extend_from_slice(&extensions);
+        let body = out.len();
+        out.extend_from_slice(self.body);
+        let last = self.extensions.last().map(|e| e.next_header).unwrap_or(header.next_header);
+        if let (true, Ipv6HeaderType::Ipv4(proto)) = (fixup.checksums, last) {
+            checksum::fix_transport_checksum(&IpHeader::V6(&header), proto, &mut out[body..]);
+        }
+    }
+}
+
+// a next header that isn't an extension, since none follow
+#[cfg(feature = "arbitrary")]
+impl <'a> Arbitrary<'a> for Ipv6Header {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Ipv6Header> {
+        let mut next_header = Ipv6HeaderType::from_u8(u.arbitrary()?);
+        if has_next_header(next_header) {
+            next_header = Ipv6HeaderType::NoNext;
+        }
+        let mut header = Ipv6Header::new(next_header, u.arbitrary()?, u.arbitrary()?);
+        header.traffic_class = u.arbitrary()?;
+        header.flow_label = u.int_in_range(0..=0xfffff)?;
+        header.payload_length = u.arbitrary()?;
+        header.hop_limit = u.arbitrary()?;
+        Ok(header)
+    }
+}
+
+// sometimes fragmented, otherwise without extensions
+#[cfg(feature = "arbitrary")]
+impl <'a> Arbitrary<'a> for Ipv6Packet<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Ipv6Packet<'a>> {
+        let header = Ipv6Header::arbitrary(u)?;
+        let mut extensions = vec![];
+        if u.arbitrary()? {
+            extensions.push(Ipv6Extension {
+                inner: Ipv6HeaderData::Fragment(u.int_in_range(0..=0x1fff)?, u.arbitrary()?, u.arbitrary()?),
+                len: 2,
+                next_header: header.next_header,
+            });
+        }
+        Ok(Ipv6Packet::new(header, extensions, u.arbitrary()?))
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedIpv6Packet {
+    pub header: Ipv6Header,
+    pub extensions: Vec<OwnedIpv6Extension>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedIpv6Extension {
+    pub inner: OwnedIpv6HeaderData,
+    pub len: u8,
+    pub next_header: Ipv6HeaderType,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OwnedIpv6HeaderData {
+    HopByHopOptions(Vec<OwnedIpv6Option>),
+    Routing(u8, u8, Vec<u8>),
+    SegmentRouting(OwnedSegmentRoutingHeader),
+    Fragment(u16, bool, u32),
+    DestinationOptions(Vec<OwnedIpv6Option>),
+    AuthenticationHeader(u32, u32, Vec<u8>),
+    EncapsulatingSecurityPayload(u32, u32),
+    Mobility(OwnedMobilityHeader),
+    NoNext,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedSegmentRoutingHeader {
+    pub segments_left: u8,
+    pub last_entry: u8,
+    pub flags: u8,
+    pub tag: u16,
+    pub segments: Vec<Ipv6Addr>,
+    pub tlvs: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedMobilityHeader {
+    pub mh_type: u8,
+    pub checksum: u16,
+    pub message: OwnedMobilityMessage,
+    pub options: Vec<OwnedMobilityOption>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OwnedMobilityMessage {
+    BindingRefreshRequest,
+    HomeTestInit(u64),
+    CareOfTestInit(u64),
+    HomeTest(u16, u64, u64),
+    CareOfTest(u16, u64, u64),
+    BindingUpdate(BindingUpdate),
+    BindingAcknowledgement(BindingAcknowledgement),
+    BindingError(u8, Ipv6Addr),
+    Unknown(u8, Vec<u8>),
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OwnedMobilityOption {
+    Padding0,
+    Padding1,
+    BindingRefreshAdvice(u16),
+    AlternateCareOfAddress(Ipv6Addr),
+    NonceIndices(u16, u16),
+    BindingAuthorizationData(Vec<u8>),
+    Unknown(u8, u8, Vec<u8>),
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OwnedIpv6Option {
+    Padding0,
+    Padding1,
+    RouterAlert(RouterAlert),
+    JumboPayload(u32),
+    TunnelEncapsulationLimit(u8),
+    HomeAddress(Ipv6Addr),
+    Unknown(u8, u8, Vec<u8>),
     Dummy,
 }
 
-fn eoo_check<'a>(bs: &'a [u8]) -> IResult<&'a [u8], Ipv6Option<'a>, u32> {
-    cond_reduce!(bs, bs.len() == 0, value!(Ipv6Option::Dummy))
-}
-
-fn parse_options<'a>(bs: &'a [u8], len: usize) -> IResult<&'a [u8], Vec<Ipv6Option<'a>>, u32> {
-    do_parse!(
-        &bs[..min(bs.len(), len)],
-        options: many_till!(
-            alt!(
-                call!(eoo_check) |
-                map!(char!(0x00 as char), |_| Ipv6Option::Padding0) |
-                do_parse!(
-                    char!(0x01 as char) >>
-                    len: be_u8 >>
-                    take!((len-2) as usize) >>
-                    (Ipv6Option::Padding1)) |
-                do_parse!(
-                    typ: be_u8 >>
-                    len: be_u8 >>
-                    data: take!((len-2) as usize) >>
-                    (Ipv6Option::Opt(typ, len, data)))
-            ),
-            call!(eoo_check)
-        ) >>
-        ({
-            let (mut opts, last) = options;
-            opts.push(last);
-            opts.into_iter()
-                .filter(|x| match x {
-                    &Ipv6Option::Dummy => false,
-                    _ => true,
-                })
-                .collect()
-        })
-    )
+impl <'a> IntoOwned for Ipv6Packet<'a> {
+    type Owned = OwnedIpv6Packet;
+
+    fn into_owned(self) -> OwnedIpv6Packet {
+        OwnedIpv6Packet {
+            header: self.header,
+            extensions: self.extensions.into_owned(),
+            body: self.body.into_owned(),
+        }
+    }
+}
+
+impl <'a> IntoOwned for Ipv6Extension<'a> {
+    type Owned = OwnedIpv6Extension;
+
+    fn into_owned(self) -> OwnedIpv6Extension {
+        OwnedIpv6Extension {
+            inner: self.inner.into_owned(),
+            len: self.len,
+            next_header: self.next_header,
+        }
+    }
+}
+
+impl <'a> IntoOwned for Ipv6HeaderData<'a> {
+    type Owned = OwnedIpv6HeaderData;
+
+    fn into_owned(self) -> OwnedIpv6HeaderData {
+        use self::Ipv6HeaderData::*;
+        match self {
+            HopByHopOptions(options) => OwnedIpv6HeaderData::HopByHopOptions(options.into_owned()),
+            Routing(typ, left, data) => OwnedIpv6HeaderData::Routing(typ, left, data.into_owned()),
+            SegmentRouting(srh) => OwnedIpv6HeaderData::SegmentRouting(srh.into_owned()),
+            Fragment(off, more, id) => OwnedIpv6HeaderData::Fragment(off, more, id),
+            DestinationOptions(options) => OwnedIpv6HeaderData::DestinationOptions(options.into_owned()),
+            AuthenticationHeader(spi, seq, icv) => OwnedIpv6HeaderData::AuthenticationHeader(spi, seq, icv.into_owned()),
+            EncapsulatingSecurityPayload(spi, seq) => OwnedIpv6HeaderData::EncapsulatingSecurityPayload(spi, seq),
+            Mobility(mh) => OwnedIpv6HeaderData::Mobility(mh.into_owned()),
+            NoNext => OwnedIpv6HeaderData::NoNext,
+        }
+    }
+}
+
+impl <'a> IntoOwned for SegmentRoutingHeader<'a> {
+    type Owned = OwnedSegmentRoutingHeader;
+
+    fn into_owned(self) -> OwnedSegmentRoutingHeader {
+        OwnedSegmentRoutingHeader {
+            segments_left: self.segments_left,
+            last_entry: self.last_entry,
+            flags: self.flags,
+            tag: self.tag,
+            segments: self.segments,
+            tlvs: self.tlvs.into_owned(),
+        }
+    }
+}
+
+impl <'a> IntoOwned for MobilityHeader<'a> {
+    type Owned = OwnedMobilityHeader;
+
+    fn into_owned(self) -> OwnedMobilityHeader {
+        OwnedMobilityHeader {
+            mh_type: self.mh_type,
+            checksum: self.checksum,
+            message: self.message.into_owned(),
+            options: self.options.into_owned(),
+        }
+    }
+}
+
+impl <'a> IntoOwned for MobilityMessage<'a> {
+    type Owned = OwnedMobilityMessage;
+
+    fn into_owned(self) -> OwnedMobilityMessage {
+        use self::MobilityMessage::*;
+        match self {
+            BindingRefreshRequest => OwnedMobilityMessage::BindingRefreshRequest,
+            HomeTestInit(cookie) => OwnedMobilityMessage::HomeTestInit(cookie),
+            CareOfTestInit(cookie) => OwnedMobilityMessage::CareOfTestInit(cookie),
+            HomeTest(index, cookie, token) => OwnedMobilityMessage::HomeTest(index, cookie, token),
+            CareOfTest(index, cookie, token) => OwnedMobilityMessage::CareOfTest(index, cookie, token),
+            BindingUpdate(bu) => OwnedMobilityMessage::BindingUpdate(bu),
+            BindingAcknowledgement(ba) => OwnedMobilityMessage::BindingAcknowledgement(ba),
+            BindingError(status, addr) => OwnedMobilityMessage::BindingError(status, addr),
+            Unknown(typ, data) => OwnedMobilityMessage::Unknown(typ, data.into_owned()),
+        }
+    }
+}
+
+impl <'a> IntoOwned for MobilityOption<'a> {
+    type Owned = OwnedMobilityOption;
+
+    fn into_owned(self) -> OwnedMobilityOption {
+        use self::MobilityOption::*;
+        match self {
+            Padding0 => OwnedMobilityOption::Padding0,
+            Padding1 => OwnedMobilityOption::Padding1,
+            BindingRefreshAdvice(interval) => OwnedMobilityOption::BindingRefreshAdvice(interval),
+            AlternateCareOfAddress(addr) => OwnedMobilityOption::AlternateCareOfAddress(addr),
+            NonceIndices(home, care_of) => OwnedMobilityOption::NonceIndices(home, care_of),
+            BindingAuthorizationData(data) => OwnedMobilityOption::BindingAuthorizationData(data.into_owned()),
+            Unknown(typ, len, data) => OwnedMobilityOption::Unknown(typ, len, data.into_owned()),
+        }
+    }
+}
+
+impl <'a> IntoOwned for Ipv6Option<'a> {
+    type Owned = OwnedIpv6Option;
+
+    fn into_owned(self) -> OwnedIpv6Option {
+        use self::Ipv6Option::*;
+        match self {
+            Padding0 => OwnedIpv6Option::Padding0,
+            Padding1 => OwnedIpv6Option::Padding1,
+            RouterAlert(alert) => OwnedIpv6Option::RouterAlert(alert),
+            JumboPayload(len) => OwnedIpv6Option::JumboPayload(len),
+            TunnelEncapsulationLimit(limit) => OwnedIpv6Option::TunnelEncapsulationLimit(limit),
+            HomeAddress(addr) => OwnedIpv6Option::HomeAddress(addr),
+            Unknown(typ, len, data) => OwnedIpv6Option::Unknown(typ, len, data.into_owned()),
+            Dummy => OwnedIpv6Option::Dummy,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -320,4 +1162,133 @@ mod test {
         assert_eq!(left.len(), 0);
         println!("{:?}", &ip_packet);
     }
+
+    #[test]
+    fn test_srv6() {
+        let packet = [
+            0x60, 0x00, 0x00, 0x00, 0x00, 0x28, 0x2b, 0x40, 0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, 0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x3b, 0x04, 0x04, 0x01, 0x01, 0x00, 0x00, 0x07,
+            0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+            0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+        ];
+        let (left, ip_packet) = parse_ipv6_packet(&packet).unwrap();
+        assert_eq!(left.len(), 0);
+        assert_eq!(ip_packet.extensions.len(), 1);
+        assert_eq!(ip_packet.extensions[0].next_header, Ipv6HeaderType::NoNext);
+        match ip_packet.extensions[0].inner {
+            Ipv6HeaderData::SegmentRouting(ref srh) => {
+                assert_eq!(srh.segments_left, 1);
+                assert_eq!(srh.last_entry, 1);
+                assert_eq!(srh.tag, 7);
+                assert_eq!(srh.segments, vec![
+                    "2001:db8::2".parse::<Ipv6Addr>().unwrap(),
+                    "2001:db8::1".parse::<Ipv6Addr>().unwrap(),
+                ]);
+                assert_eq!(srh.tlvs.len(), 0);
+            },
+            ref x => panic!("expected segment routing header, got {:?}", x),
+        }
+        assert_eq!(ip_packet.to_bytes(), &packet[..]);
+    }
+
+    #[test]
+    fn test_hop_by_hop_router_alert() {
+        let packet = [
+            0x60, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x01, 0xfe, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x16, 0x3b, 0x00, 0x05, 0x02, 0x00, 0x00, 0x01, 0x00,
+        ];
+        let (left, ip_packet) = parse_ipv6_packet(&packet).unwrap();
+        assert_eq!(left.len(), 0);
+        assert_eq!(ip_packet.extensions.len(), 1);
+        match ip_packet.extensions[0].inner {
+            Ipv6HeaderData::HopByHopOptions(ref opts) => {
+                assert_eq!(opts.len(), 2);
+                match opts[0] {
+                    Ipv6Option::RouterAlert(RouterAlert::Mld) => {},
+                    x => panic!("expected MLD router alert, got {:?}", x),
+                }
+                match opts[1] {
+                    Ipv6Option::Padding1 => {},
+                    x => panic!("expected PadN, got {:?}", x),
+                }
+            },
+            ref x => panic!("expected hop-by-hop options, got {:?}", x),
+        }
+
+        let opts: Vec<_> = OptionIter::hop_by_hop(&packet).unwrap().collect();
+        assert_eq!(opts.len(), 2);
+        match (opts[0], opts[1]) {
+            (Ipv6Option::RouterAlert(RouterAlert::Mld), Ipv6Option::Padding1) => {},
+            x => panic!("expected MLD router alert and PadN, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_ah_esp_chain() {
+        let packet = [
+            0x60, 0x00, 0x00, 0x00, 0x00, 0x24, 0x33, 0x40, 0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x32, 0x04, 0x00, 0x00, 0x00, 0x00, 0x10, 0x01,
+            0x00, 0x00, 0x00, 0x05, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa,
+            0x00, 0x00, 0x20, 0x02, 0x00, 0x00, 0x00, 0x05, 0xde, 0xad, 0xbe, 0xef,
+        ];
+        let (left, ip_packet) = parse_ipv6_packet(&packet).unwrap();
+        assert_eq!(left.len(), 0);
+        assert_eq!(ip_packet.extensions.len(), 2);
+        match ip_packet.extensions[0].inner {
+            Ipv6HeaderData::AuthenticationHeader(spi, seq, icv) => {
+                assert_eq!(spi, 0x1001);
+                assert_eq!(seq, 5);
+                assert_eq!(icv.len(), 12);
+            },
+            ref x => panic!("expected authentication header, got {:?}", x),
+        }
+        assert_eq!(ip_packet.extensions[0].next_header, Ipv6HeaderType::EncapsulatingSecurityPayload);
+        match ip_packet.extensions[1].inner {
+            Ipv6HeaderData::EncapsulatingSecurityPayload(spi, seq) => {
+                assert_eq!(spi, 0x2002);
+                assert_eq!(seq, 5);
+            },
+            ref x => panic!("expected ESP header, got {:?}", x),
+        }
+        assert_eq!(ip_packet.body, &[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(ip_packet.to_bytes(), &packet[..]);
+    }
+
+    #[test]
+    fn test_binding_update() {
+        let packet = [
+            0x60, 0x00, 0x00, 0x00, 0x00, 0x10, 0x87, 0x40, 0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x3b, 0x01, 0x05, 0x00, 0x12, 0x34, 0x00, 0x2a,
+            0xc0, 0x00, 0x00, 0x10, 0x02, 0x02, 0x00, 0x3c,
+        ];
+        let (left, ip_packet) = parse_ipv6_packet(&packet).unwrap();
+        assert_eq!(left.len(), 0);
+        assert_eq!(ip_packet.extensions.len(), 1);
+        match ip_packet.extensions[0].inner {
+            Ipv6HeaderData::Mobility(ref mh) => {
+                assert_eq!(mh.checksum, 0x1234);
+                match mh.message {
+                    MobilityMessage::BindingUpdate(bu) => {
+                        assert_eq!(bu.sequence, 42);
+                        assert!(bu.acknowledge);
+                        assert!(bu.home_registration);
+                        assert!(!bu.link_local_compatibility);
+                        assert_eq!(bu.lifetime, 16);
+                    },
+                    ref x => panic!("expected binding update, got {:?}", x),
+                }
+                assert_eq!(mh.options.len(), 1);
+                match mh.options[0] {
+                    MobilityOption::BindingRefreshAdvice(60) => {},
+                    x => panic!("expected binding refresh advice, got {:?}", x),
+                }
+            },
+            ref x => panic!("expected mobility header, got {:?}", x),
+        }
+        assert_eq!(ip_packet.to_bytes(), &packet[..]);
+    }
 }
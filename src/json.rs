@@ -0,0 +1,482 @@
+use std::fmt;
+use std::fmt::Write;
+
+use dissect::{ApplicationLayer, Packet};
+use dhcp;
+use dns;
+use ethernet;
+use http;
+use icmp;
+use ipv4;
+use ipv6;
+use tcp;
+use udp;
+use {IpPacket, TransportLayerPacket};
+
+/// A JSON value. Objects keep their keys in insertion order, so dissections
+/// come out in the same order as the fields on the wire.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(u64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Value {
+        Value::Bool(v)
+    }
+}
+
+macro_rules! number_from {
+    ($($t:ty),*) => {
+        $(impl From<$t> for Value {
+            fn from(v: $t) -> Value {
+                Value::Number(v as u64)
+            }
+        })*
+    };
+}
+number_from!(u8, u16, u32, u64, usize);
+
+impl From<String> for Value {
+    fn from(v: String) -> Value {
+        Value::String(v)
+    }
+}
+
+impl <'a> From<&'a str> for Value {
+    fn from(v: &'a str) -> Value {
+        Value::String(v.to_string())
+    }
+}
+
+impl Value {
+    /// Looks up a key in an object
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match *self {
+            Value::Object(ref fields) => fields.iter().find(|f| f.0 == key).map(|f| &f.1),
+            _ => None,
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(ref s) => write_string(f, s),
+            Value::Array(ref values) => {
+                f.write_char('[')?;
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                f.write_char(']')
+            },
+            Value::Object(ref fields) => {
+                f.write_char('{')?;
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, k)?;
+                    write!(f, ":{}", v)?;
+                }
+                f.write_char('}')
+            },
+        }
+    }
+}
+
+/// Builds up one protocol layer's fields
+struct Layer {
+    prefix: &'static str,
+    fields: Vec<(String, Value)>,
+}
+
+impl Layer {
+    fn new(prefix: &'static str) -> Layer {
+        Layer {
+            prefix,
+            fields: vec![],
+        }
+    }
+
+    fn field<V: Into<Value>>(&mut self, name: &str, value: V) -> &mut Layer {
+        self.fields.push((format!("{}.{}", self.prefix, name), value.into()));
+        self
+    }
+
+    // a raw value along with its human readable interpretation, which goes
+    // in a sibling field with a _show suffix, like tshark's showname
+    fn shown<V: Into<Value>, S: fmt::Display>(&mut self, name: &str, value: V, show: S) -> &mut Layer {
+        self.field(name, value);
+        self.fields.push((format!("{}.{}_show", self.prefix, name), Value::String(show.to_string())));
+        self
+    }
+
+    fn nested(&mut self, name: &str, value: Value) -> &mut Layer {
+        self.fields.push((name.to_string(), value));
+        self
+    }
+
+    fn into_value(self) -> Value {
+        Value::Object(self.fields)
+    }
+}
+
+fn hex(bs: &[u8]) -> String {
+    let mut s = String::with_capacity(2*bs.len());
+    for b in bs {
+        let _ = write!(s, "{:02x}", b);
+    }
+    s
+}
+
+fn ethernet_layer(eth: &ethernet::EthernetIIPacket) -> Value {
+    let mut layer = Layer::new("eth");
+    layer.field("dst", eth.dest_mac.to_string())
+        .field("src", eth.source_mac.to_string());
+    if !eth.vlans.is_empty() {
+        let vlans = eth.vlans.iter().map(|v| {
+            let mut vlan = Layer::new("vlan");
+            vlan.field("etype", v.tpid)
+                .field("priority", v.pcp)
+                .field("dei", v.dei)
+                .field("id", v.vid);
+            vlan.into_value()
+        }).collect();
+        layer.nested("vlan", Value::Array(vlans));
+    }
+    let ethertype = eth.ethertype.to_u16();
+    let name = eth.ethertype.name()
+        .map_or_else(|| format!("0x{:04x}", ethertype), |n| n.to_string());
+    layer.shown("type", ethertype, name);
+    layer.into_value()
+}
+
+fn ipv4_layer(ip: &ipv4::Ipv4Packet) -> Value {
+    let h = &ip.header;
+    let mut layer = Layer::new("ip");
+    layer.field("version", 4u8)
+        .field("hdr_len", 4*h.len)
+        .field("dsfield.dscp", h.dscp)
+        .field("dsfield.ecn", h.ecn)
+        .field("len", h.total_len)
+        .field("id", h.id)
+        .field("flags.df", h.flags.df)
+        .field("flags.mf", h.flags.mf)
+        .field("frag_offset", h.fragment_off)
+        .field("ttl", h.ttl)
+        .shown("proto", h.proto.to_u8(), format!("{:?}", h.proto))
+        .field("checksum", h.checksum)
+        .field("src", h.src_ip.to_string())
+        .field("dst", h.dst_ip.to_string());
+    layer.into_value()
+}
+
+fn next_header_show(typ: ipv6::Ipv6HeaderType) -> String {
+    match typ {
+        ipv6::Ipv6HeaderType::Ipv4(proto) => format!("{:?}", proto),
+        typ => format!("{:?}", typ),
+    }
+}
+
+fn ipv6_layer(ip: &ipv6::Ipv6Packet) -> Value {
+    let h = &ip.header;
+    let mut layer = Layer::new("ipv6");
+    layer.field("version", 6u8)
+        .field("tclass", h.traffic_class)
+        .field("flow", h.flow_label)
+        .field("plen", h.payload_length)
+        .shown("nxt", h.next_header.to_u8(), next_header_show(h.next_header))
+        .field("hlim", h.hop_limit)
+        .field("src", h.src_ip.to_string())
+        .field("dst", h.dst_ip.to_string());
+    if !ip.extensions.is_empty() {
+        let extensions = ip.extensions.iter().map(|e| {
+            let mut ext = Layer::new("ipv6.ext");
+            ext.shown("nxt", e.next_header.to_u8(), next_header_show(e.next_header));
+            ext.into_value()
+        }).collect();
+        layer.nested("ipv6.extensions", Value::Array(extensions));
+    }
+    layer.into_value()
+}
+
+fn tcp_flags(flags: &tcp::TcpFlags) -> String {
+    let names = [
+        (flags.ns, "NS"), (flags.cwr, "CWR"), (flags.ece, "ECE"), (flags.urg, "URG"),
+        (flags.ack, "ACK"), (flags.psh, "PSH"), (flags.rst, "RST"), (flags.syn, "SYN"),
+        (flags.fin, "FIN"),
+    ];
+    names.iter().filter(|n| n.0).map(|n| n.1).collect::<Vec<_>>().join(", ")
+}
+
+fn tcp_layer(packet: &tcp::TcpPacket) -> Value {
+    let h = &packet.header;
+    let f = &h.flags;
+    let bits = [f.ns, f.cwr, f.ece, f.urg, f.ack, f.psh, f.rst, f.syn, f.fin]
+        .iter()
+        .fold(0u16, |acc, &b| acc << 1 | b as u16);
+    let mut layer = Layer::new("tcp");
+    layer.field("srcport", h.src)
+        .field("dstport", h.dst)
+        .field("seq", h.seq)
+        .field("ack", h.ack)
+        .field("hdr_len", 4*f.offset)
+        .shown("flags", bits, tcp_flags(f))
+        .field("window_size", h.window_sz)
+        .field("checksum", h.checksum)
+        .field("urgent_pointer", h.urgent)
+        .field("len", packet.body.len());
+    layer.into_value()
+}
+
+fn udp_layer(packet: &udp::UdpPacket) -> Value {
+    let h = &packet.header;
+    let mut layer = Layer::new("udp");
+    layer.field("srcport", h.src)
+        .field("dstport", h.dst)
+        .field("length", h.len)
+        .field("checksum", h.checksum);
+    layer.into_value()
+}
+
+fn icmp_layer(packet: &icmp::IcmpPacket) -> Value {
+    let h = &packet.header;
+    let mut layer = Layer::new("icmp");
+    layer.shown("type", h.typ.to_u8(), format!("{:?}", h.typ))
+        .field("code", h.code)
+        .field("checksum", h.checksum);
+    if let Some((id, seq)) = h.echo_id_seq() {
+        layer.field("ident", id).field("seq", seq);
+    }
+    layer.into_value()
+}
+
+/// Renders a DNS message on its own, as the "dns" layer of `to_json` would
+pub fn dns_to_json(msg: &dns::Message) -> Value {
+    dns_layer(msg)
+}
+
+fn dns_layer(msg: &dns::Message) -> Value {
+    let h = &msg.header;
+    let mut layer = Layer::new("dns");
+    layer.field("id", h.id)
+        .field("flags.response", h.qr == dns::QR::Response)
+        .shown("flags.opcode", h.opcode.to_u8(), format!("{:?}", h.opcode))
+        .field("flags.authoritative", h.aa)
+        .field("flags.truncated", h.tc)
+        .field("flags.recdesired", h.rd)
+        .field("flags.recavail", h.ra)
+        .shown("flags.rcode", h.rcode.to_u8(), format!("{:?}", h.rcode))
+        .field("count.queries", h.qdcount)
+        .field("count.answers", h.ancount)
+        .field("count.auth_rr", h.nscount)
+        .field("count.add_rr", h.arcount);
+
+    let queries = msg.questions.iter().map(|q| {
+        let mut query = Layer::new("dns.qry");
+        query.field("name", q.qname.to_string())
+            .shown("type", q.qtype.to_u16(), format!("{:?}", q.qtype))
+            .shown("class", q.qclass.to_u16(), format!("{:?}", q.qclass));
+        query.into_value()
+    }).collect();
+    layer.nested("Queries", Value::Array(queries));
+
+    let records = |rrs: &[dns::ResourceRecord]| Value::Array(rrs.iter().map(|rr| {
+        let mut record = Layer::new("dns.resp");
+        record.field("name", rr.name.to_string())
+            .shown("type", rr.typ.to_u16(), format!("{:?}", rr.typ))
+            .shown("class", rr.class.to_u16(), format!("{:?}", rr.class))
+            .field("ttl", rr.ttl)
+            .field("data", rr.rdata.to_string());
+        record.into_value()
+    }).collect());
+    layer.nested("Answers", records(&msg.answers))
+        .nested("Authoritative nameservers", records(&msg.authorities))
+        .nested("Additional records", records(&msg.additional));
+    layer.into_value()
+}
+
+fn dhcp_layer(msg: &dhcp::DhcpMessage) -> Value {
+    let mut layer = Layer::new("dhcp");
+    layer.shown("type", match msg.op {
+            dhcp::Op::Request => 1,
+            dhcp::Op::Reply => 2,
+            dhcp::Op::Other(x) => x,
+        }, format!("{:?}", msg.op))
+        .field("hw.type", msg.htype)
+        .field("hops", msg.hops)
+        .field("id", format!("{:#010x}", msg.xid))
+        .field("secs", msg.secs)
+        .field("flags.bc", msg.broadcast)
+        .field("ip.client", msg.ciaddr.to_string())
+        .field("ip.your", msg.yiaddr.to_string())
+        .field("ip.server", msg.siaddr.to_string())
+        .field("ip.relay", msg.giaddr.to_string());
+    if let Some(mac) = msg.client_mac() {
+        layer.field("hw.mac_addr", mac.to_string());
+    }
+    if let Some(typ) = msg.message_type() {
+        layer.shown("option.dhcp", typ.to_u8(), format!("{:?}", typ));
+    }
+    layer.into_value()
+}
+
+fn http_headers(layer: &mut Layer, headers: &[http::Header], body: &[u8]) {
+    let s = |bs| String::from_utf8_lossy(bs).into_owned();
+    for &(name, field) in &[(&b"host"[..], "host"), (b"user-agent", "user_agent"), (b"content-type", "content_type"),
+                            (b"content-length", "content_length_header"), (b"server", "server")] {
+        if let Some(header) = headers.iter().find(|h| h.name.eq_ignore_ascii_case(name)) {
+            layer.field(field, s(header.value));
+        }
+    }
+    if !body.is_empty() {
+        layer.field("file_data", hex(body));
+    }
+}
+
+fn http_request_layer(request: &http::Request, body: &[u8]) -> Value {
+    let s = |bs| String::from_utf8_lossy(bs).into_owned();
+    let mut layer = Layer::new("http");
+    layer.field("request.method", s(request.method))
+        .field("request.uri", s(request.target))
+        .field("request.version", s(request.version));
+    http_headers(&mut layer, &request.headers, body);
+    layer.into_value()
+}
+
+fn http_response_layer(response: &http::Response, body: &[u8]) -> Value {
+    let s = |bs| String::from_utf8_lossy(bs).into_owned();
+    let mut layer = Layer::new("http");
+    layer.field("response.version", s(response.version))
+        .field("response.code", response.status)
+        .field("response.phrase", s(response.reason));
+    http_headers(&mut layer, &response.headers, body);
+    layer.into_value()
+}
+
+/// Renders a dissected packet as nested JSON in the shape `tshark -T json`
+/// uses: `{"_source": {"layers": {"eth": {...}, "ip": {...}, ...}}}`, with
+/// fields named after their Wireshark display filter names. Fields with an
+/// enumerated meaning get a human readable `_show` sibling, and any payload
+/// left undissected goes in a `data` layer as hex.
+pub fn to_json(packet: &Packet) -> Value {
+    let mut layers = vec![];
+    if let Some(timestamp) = packet.timestamp {
+        let mut frame = Layer::new("frame");
+        frame.field("time_epoch", timestamp.to_string());
+        layers.push(("frame".to_string(), frame.into_value()));
+    }
+    if let Some(ref eth) = packet.ethernet {
+        layers.push(("eth".to_string(), ethernet_layer(eth)));
+    }
+    match packet.ip {
+        Some(IpPacket::V4(ref ip)) => layers.push(("ip".to_string(), ipv4_layer(ip))),
+        Some(IpPacket::V6(ref ip)) => layers.push(("ipv6".to_string(), ipv6_layer(ip))),
+        None => {},
+    }
+    match packet.transport {
+        Some(TransportLayerPacket::Tcp(ref tcp)) => layers.push(("tcp".to_string(), tcp_layer(tcp))),
+        Some(TransportLayerPacket::Udp(ref udp)) => layers.push(("udp".to_string(), udp_layer(udp))),
+        Some(TransportLayerPacket::Icmp(ref icmp)) => layers.push(("icmp".to_string(), icmp_layer(icmp))),
+        None => {},
+    }
+    match packet.application {
+        Some(ApplicationLayer::Dns(ref msg)) => layers.push(("dns".to_string(), dns_layer(msg))),
+        Some(ApplicationLayer::Dhcp(ref msg)) => layers.push(("dhcp".to_string(), dhcp_layer(msg))),
+        Some(ApplicationLayer::HttpRequest(ref request)) => {
+            layers.push(("http".to_string(), http_request_layer(request, packet.payload)));
+        },
+        Some(ApplicationLayer::HttpResponse(ref response)) => {
+            layers.push(("http".to_string(), http_response_layer(response, packet.payload)));
+        },
+        None => if !packet.payload.is_empty() {
+            let mut data = Layer::new("data");
+            data.field("data", hex(packet.payload))
+                .field("len", packet.payload.len());
+            layers.push(("data".to_string(), data.into_value()));
+        },
+    }
+    Value::Object(vec![
+        ("_source".to_string(), Value::Object(vec![
+            ("layers".to_string(), Value::Object(layers)),
+        ])),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dissect::{parse_packet, LinkType};
+
+    #[test]
+    fn test_dns_query() {
+        let frame = [
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0x08, 0x00,
+
+            0x45, 0x00, 0x00, 0x3c, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x01,
+            0x08, 0x08, 0x08, 0x08,
+
+            0x10, 0xd8, 0x00, 0x35, 0x00, 0x28, 0x00, 0x00,
+
+            0x24, 0x1a, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x77, 0x77, 0x77,
+            0x06, 0x67, 0x6f, 0x6f, 0x67, 0x6c, 0x65, 0x03, 0x63, 0x6f, 0x6d, 0x00, 0x00, 0x01, 0x00, 0x01,
+        ];
+        let json = to_json(&parse_packet(LinkType::Ethernet, &frame));
+        let layers = json.get("_source").and_then(|s| s.get("layers")).unwrap();
+
+        let eth = layers.get("eth").unwrap();
+        assert_eq!(eth.get("eth.src"), Some(&Value::from("66:77:88:99:aa:bb")));
+        assert_eq!(eth.get("eth.type_show"), Some(&Value::from("IPv4")));
+        let ip = layers.get("ip").unwrap();
+        assert_eq!(ip.get("ip.proto"), Some(&Value::Number(17)));
+        assert_eq!(ip.get("ip.proto_show"), Some(&Value::from("Udp")));
+        assert_eq!(ip.get("ip.dst"), Some(&Value::from("8.8.8.8")));
+        let dns = layers.get("dns").unwrap();
+        match dns.get("Queries") {
+            Some(Value::Array(queries)) => {
+                assert_eq!(queries[0].get("dns.qry.name"), Some(&Value::from("www.google.com")));
+            },
+            x => panic!("expected a list of queries, got {:?}", x),
+        }
+        assert!(layers.get("data").is_none());
+
+        let text = json.to_string();
+        assert!(text.starts_with("{\"_source\":{\"layers\":{\"eth\":{\"eth.dst\":\"00:11:22:33:44:55\""));
+    }
+
+    #[test]
+    fn test_escaping() {
+        let value = Value::Object(vec![
+            ("a\"b".to_string(), Value::Array(vec![Value::from("x\ny\u{1}"), Value::Null, Value::Bool(true)])),
+        ]);
+        assert_eq!(value.to_string(), "{\"a\\\"b\":[\"x\\ny\\u0001\",null,true]}");
+    }
+}
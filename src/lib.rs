@@ -1,79 +1,615 @@
-#[macro_use]
 extern crate nom;
+#[cfg(all(feature = "live", target_os = "linux"))]
+extern crate libc;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "arbitrary")]
+#[macro_use]
+extern crate arbitrary;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(feature = "codec")]
+extern crate tokio_util;
+#[cfg(feature = "etherparse")]
+extern crate etherparse;
+#[cfg(feature = "wasm")]
+extern crate js_sys;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+#[cfg(feature = "python")]
+extern crate pyo3;
+#[cfg(feature = "unstable-smallvec")]
+extern crate smallvec;
+// pyo3's macros expand to ::core paths, which 2015 edition crates need to
+// bring in by hand
+#[cfg(feature = "python")]
+extern crate core;
+
+use std::fmt;
+use std::net::IpAddr;
+
+use nom::branch::alt;
+use nom::combinator::{all_consuming, map};
+use nom::error::ErrorKind;
+use nom::Parser;
+
+use error::IResult;
+
+/// Lists that are almost always a few items long: TCP, IPv4 and IPv6
+/// options, and the labels of DNS names. With the experimental
+/// unstable-smallvec feature they're `SmallVec`s that only go on the heap
+/// past four items, otherwise `Vec`s.
+#[cfg(feature = "unstable-smallvec")]
+pub type List<T> = smallvec::SmallVec<T, 4>;
+#[cfg(not(feature = "unstable-smallvec"))]
+pub type List<T> = Vec<T>;
+
+// vec! for a List
+macro_rules! list {
+    ($($x:tt)*) => { $crate::list_from_vec(vec![$($x)*]) };
+}
+
+// a List with the heap buffer of `items`
+#[cfg(feature = "unstable-smallvec")]
+fn list_from_vec<T>(items: Vec<T>) -> List<T> {
+    List::from_vec(items)
+}
+#[cfg(not(feature = "unstable-smallvec"))]
+fn list_from_vec<T>(items: Vec<T>) -> List<T> {
+    items
+}
+
+// the heap buffer of `items`, if it has one
+#[cfg(feature = "unstable-smallvec")]
+fn list_into_vec<T>(items: List<T>) -> Option<Vec<T>> {
+    if items.spilled() {
+        Some(items.into())
+    } else {
+        None
+    }
+}
+#[cfg(not(feature = "unstable-smallvec"))]
+fn list_into_vec<T>(items: List<T>) -> Option<Vec<T>> {
+    Some(items)
+}
+
+// nom's many_till, collecting into a List
+fn many_till_list<'a, T, E, F, G>(mut f: F, mut g: G) -> impl FnMut(&'a [u8]) -> IResult<'a, (List<T>, E)>
+    where F: Parser<&'a [u8], T, ParseError<'a>>,
+          G: Parser<&'a [u8], E, ParseError<'a>>
+{
+    move |mut bs| {
+        let mut items = List::new();
+        loop {
+            match g.parse(bs) {
+                Ok((rest, end)) => return Ok((rest, (items, end))),
+                Err(nom::Err::Error(_)) => {
+                    let (rest, item) = f.parse(bs)?;
+                    if rest.len() == bs.len() {
+                        return Err(nom::Err::Error(ParseError::new(rest, ErrorKind::ManyTill)));
+                    }
+                    items.push(item);
+                    bs = rest;
+                },
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
 
 // data link level parsers
 pub mod ethernet;
+pub mod ppp;
 
 // internet level parsers
+pub mod arp;
 pub mod ipv4;
 pub mod ipv6;
 
 // transport level parsers
+pub mod icmp;
+pub mod icmpv6;
+pub mod igmp;
 pub mod tcp;
 pub mod udp;
 
+// layered dissection of whole captured packets
+pub mod dissect;
+pub use dissect::{parse_packet, parse_packet_with, LinkType, Packet, Registry};
+
+// dissection through per layer callbacks, for when a few fields are enough
+pub mod visit;
+
+// lookups attached to dissected packets
+pub mod enrich;
+
+// capture file readers
+pub mod pcap;
+pub mod pcapng;
+
+// live capture and injection, AF_PACKET only for now
+#[cfg(all(feature = "live", target_os = "linux"))]
+pub mod live;
+
+// sampling and rate limiting of packet streams
+pub mod sample;
+
+// timed playback of captured packets, which needs a clock and threads
+// that bare wasm32 doesn't have
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub mod replay;
+
+// packets holding reference counted slices of their capture buffer
+#[cfg(feature = "bytes")]
+pub mod shared;
+
+// byte ranges of parsed fields
+pub mod annotate;
+
+// JSON export of dissected packets
+pub mod json;
+
+// field by field comparison of packets
+pub mod diff;
+
+// packet filtering
+pub mod filter;
+
+// tunnel decapsulation
+pub mod decap;
+
+// flow tracking
+pub mod flow;
+
+// TCP connection tracking
+pub mod conn;
+
+// ICMP echo matching
+pub mod ping;
+
+// multicast group membership tracking
+pub mod multicast;
+
+// passive OS fingerprinting
+pub mod fingerprint;
+
+// passive DNS collection
+pub mod pdns;
+
+// DNS tunnel detection
+pub mod dns_tunnel;
+
+// protocol detection from payloads rather than ports
+pub mod classify;
+
+// checksum helpers shared by the parsers
+pub mod checksum;
+
+// errors from the parsers
+pub mod error;
+pub use error::ParseError;
+
+// capture times
+pub mod timestamp;
+pub use timestamp::Timestamp;
+
+// incremental parsing of chunked input
+pub mod stream;
+
+// DNS stub resolver, which needs sockets that bare wasm32 doesn't have
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub mod resolver;
+
+// authoritative DNS answers from a zone
+pub mod zone;
+
+// mDNS and DNS-SD announcements and browsing
+pub mod mdns;
+
+// subjects, issuers and validity of certificates seen in TLS handshakes
+#[cfg(feature = "x509")]
+pub mod x509;
+
+// canned traffic for tests
+pub mod testgen;
+
+// conversions to and from etherparse
+#[cfg(feature = "etherparse")]
+pub mod interop;
+
+// C bindings, see include/blosh.h
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+// wasm-bindgen wrappers for dissecting in the browser
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+// Python bindings
+#[cfg(feature = "python")]
+pub mod python;
+
+// tokio-util codecs
+#[cfg(feature = "codec")]
+pub mod codec;
+
 // application level parsers
+pub mod dhcp;
 pub mod dns;
-// pub mod smtp;
+pub mod gtpc;
+pub mod http;
+pub mod openflow;
+pub mod rtp;
+pub mod smtp;
+pub mod socks;
+pub mod ssh;
+pub mod stun;
+pub mod tls;
+pub mod wol;
+
+/// Which way a packet is travelling within a connection or flow, relative to
+/// whichever side was seen first
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Direction {
+    Forward,
+    Reverse,
+}
+
+impl Direction {
+    pub fn reverse(self) -> Direction {
+        match self {
+            Direction::Forward => Direction::Reverse,
+            Direction::Reverse => Direction::Forward,
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub enum IpPacket<'a> {
     V4(ipv4::Ipv4Packet<'a>),
     V6(ipv6::Ipv6Packet<'a>),
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum IpHeader<'a> {
     V4(&'a ipv4::Header<'a>),
     V6(&'a ipv6::Ipv6Header),
 }
 
-pub fn parse_ip_packet<'a>(bs: &'a [u8]) -> Result<IpPacket<'a>, nom::IError> {
-    alt!(
-        bs,
-        map!(ipv4::parse_ipv4_packet, |p| IpPacket::V4(p)) | 
-        map!(ipv6::parse_ipv6_packet, |p| IpPacket::V6(p))
-    ).to_full_result()
+pub fn parse_ip_packet(bs: &[u8]) -> Result<IpPacket<'_>, nom::Err<ParseError<'_>>> {
+    all_consuming(alt((
+        map(ipv4::parse_ipv4_packet, IpPacket::V4),
+        map(ipv6::parse_ipv6_packet, IpPacket::V6),
+    )))(bs).map(|(_, packet)| packet)
 }
 
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub enum TransportLayerPacket<'a> {
     Tcp(tcp::TcpPacket<'a>),
     Udp(udp::UdpPacket<'a>),
+    Icmp(icmp::IcmpPacket<'a>),
 }
 
 impl <'a> IpPacket<'a> {
-    pub fn parse_inner(&self) -> Option<TransportLayerPacket<'a>> {
+    /// Protocol of the payload, after any IPv6 extension headers
+    pub fn protocol(&self) -> Option<ipv4::Ipv4Protocol> {
         match self {
-            &IpPacket::V4(ref ip4) => Some((ip4.header.proto, ip4.body)),
-            &IpPacket::V6(ref ip6) => {
-                let proto = if ip6.extensions.len() == 0 {
+            IpPacket::V4(ip4) => Some(ip4.header.proto),
+            IpPacket::V6(ip6) => {
+                let proto = if ip6.extensions.is_empty() {
                     ip6.header.next_header
                 } else {
                     ip6.extensions.last().unwrap().next_header
                 };
                 match proto {
-                    ipv6::Ipv6HeaderType::Ipv4(ref proto) => Some((*proto, ip6.body)),
+                    ipv6::Ipv6HeaderType::Ipv4(ref proto) => Some(*proto),
                     _ => None,
                 }
             }
         }
+    }
+
+    pub fn body(&self) -> &'a [u8] {
+        match self {
+            IpPacket::V4(ip4) => ip4.body,
+            IpPacket::V6(ip6) => ip6.body,
+        }
+    }
+
+    pub fn parse_inner(&self) -> Option<TransportLayerPacket<'a>> {
+        self.protocol()
+        .map(|proto| (proto, self.body()))
         .and_then(|(proto, body)| {
             match proto {
-                ipv4::Ipv4Protocol::Tcp => tcp::parse_tcp_packet(body)
-                    .to_full_result().ok().map(TransportLayerPacket::Tcp),
-                ipv4::Ipv4Protocol::Udp => udp::parse_udp_packet(body)
-                    .to_full_result().ok().map(TransportLayerPacket::Udp),
+                ipv4::Ipv4Protocol::Tcp => all_consuming(tcp::parse_tcp_packet)(body)
+                    .ok().map(|(_, tcp)| TransportLayerPacket::Tcp(tcp)),
+                ipv4::Ipv4Protocol::Udp => all_consuming(udp::parse_udp_packet)(body)
+                    .ok().map(|(_, udp)| TransportLayerPacket::Udp(udp)),
+                ipv4::Ipv4Protocol::Icmp => all_consuming(icmp::parse_icmp_packet)(body)
+                    .ok().map(|(_, icmp)| TransportLayerPacket::Icmp(icmp)),
                 _ => None,
             }
         })
     }
 
+    pub fn src_ip(&self) -> IpAddr {
+        match self {
+            IpPacket::V4(ip4) => IpAddr::V4(ip4.header.src_ip),
+            IpPacket::V6(ip6) => IpAddr::V6(ip6.header.src_ip),
+        }
+    }
+
+    pub fn dst_ip(&self) -> IpAddr {
+        match self {
+            IpPacket::V4(ip4) => IpAddr::V4(ip4.header.dst_ip),
+            IpPacket::V6(ip6) => IpAddr::V6(ip6.header.dst_ip),
+        }
+    }
+
+    /// The 5-tuple of the TCP or UDP packet carried inside, if there is one
+    pub fn flow_key(&self) -> Option<flow::FlowKey> {
+        self.parse_inner().map(|inner| flow::FlowKey::from_packets(self, &inner))
+    }
+
     pub fn header(&'a self) -> IpHeader<'a> {
         match self {
-            &IpPacket::V4(ref ip4) => IpHeader::V4(&ip4.header),
-            &IpPacket::V6(ref ip6) => IpHeader::V6(&ip6.header),
+            IpPacket::V4(ip4) => IpHeader::V4(&ip4.header),
+            IpPacket::V6(ip6) => IpHeader::V6(&ip6.header),
+        }
+    }
+}
+
+impl <'a> fmt::Display for IpPacket<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let version = match *self {
+            IpPacket::V4(_) => "IP",
+            IpPacket::V6(_) => "IP6",
+        };
+        let (src, dst) = (self.src_ip(), self.dst_ip());
+        match self.parse_inner() {
+            Some(TransportLayerPacket::Tcp(tcp)) => {
+                write!(f, "{} {}.{} > {}.{}: {}", version, src, tcp.header.src, dst, tcp.header.dst, tcp)
+            },
+            Some(TransportLayerPacket::Udp(udp)) => {
+                write!(f, "{} {}.{} > {}.{}: {}", version, src, udp.header.src, dst, udp.header.dst, udp)
+            },
+            Some(TransportLayerPacket::Icmp(icmp)) => write!(f, "{} {} > {}: {}", version, src, dst, icmp),
+            None => {
+                write!(f, "{} {} > {}: ", version, src, dst)?;
+                match self.protocol() {
+                    Some(proto) => write!(f, "{}", format!("{:?}", proto).to_uppercase())?,
+                    None => write!(f, "unknown")?,
+                }
+                write!(f, ", length {}", self.body().len())
+            },
+        }
+    }
+}
+
+/// Converts a packet that borrows from its capture buffer into an owned
+/// counterpart that can outlive it
+pub trait IntoOwned {
+    type Owned;
+
+    fn into_owned(self) -> Self::Owned;
+}
+
+impl IntoOwned for &[u8] {
+    type Owned = Vec<u8>;
+
+    fn into_owned(self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl <T: IntoOwned> IntoOwned for Vec<T> {
+    type Owned = Vec<T::Owned>;
+
+    fn into_owned(self) -> Vec<T::Owned> {
+        self.into_iter().map(IntoOwned::into_owned).collect()
+    }
+}
+
+#[cfg(feature = "unstable-smallvec")]
+impl <T: IntoOwned> IntoOwned for List<T> {
+    type Owned = Vec<T::Owned>;
+
+    fn into_owned(self) -> Vec<T::Owned> {
+        self.into_iter().map(IntoOwned::into_owned).collect()
+    }
+}
+
+impl <T: IntoOwned> IntoOwned for Option<T> {
+    type Owned = Option<T::Owned>;
+
+    fn into_owned(self) -> Option<T::Owned> {
+        self.map(IntoOwned::into_owned)
+    }
+}
+
+/// Which fields `ToBytes` recomputes on the way out, instead of writing
+/// them as they are
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fixup {
+    // IPv4 header and total lengths, IPv6 payload length, TCP data offset
+    // and UDP length
+    pub lengths: bool,
+    // the IPv4 header checksum, ICMP checksums, and TCP and UDP checksums
+    // in IP payloads, pseudo-header included
+    pub checksums: bool,
+}
+
+impl Fixup {
+    pub fn all() -> Fixup {
+        Fixup {
+            lengths: true,
+            checksums: true,
+        }
+    }
+
+    /// Writes every field as it is, for replaying packets byte for byte or
+    /// crafting broken ones
+    pub fn none() -> Fixup {
+        Fixup {
+            lengths: false,
+            checksums: false,
+        }
+    }
+}
+
+impl Default for Fixup {
+    fn default() -> Fixup {
+        Fixup::all()
+    }
+}
+
+/// Writes a packet back out in wire format, the inverse of its parser.
+/// Lengths and checksums are fixed up to match unless a `Fixup` says
+/// otherwise.
+pub trait ToBytes {
+    fn write_with(&self, fixup: Fixup, out: &mut Vec<u8>);
+
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        self.write_with(Fixup::all(), out);
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.to_bytes_with(Fixup::all())
+    }
+
+    fn to_bytes_with(&self, fixup: Fixup) -> Vec<u8> {
+        let mut out = vec![];
+        self.write_with(fixup, &mut out);
+        out
+    }
+}
+
+/// What a layer says its payload is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum NextProtocol {
+    EtherType(ethernet::EtherType),
+    Ip(ipv4::Ipv4Protocol),
+    // TCP and UDP only have their source and destination ports to go on
+    Ports(u16, u16),
+}
+
+/// What all the protocol layers have in common, for code that walks a
+/// packet without caring which protocols are in it
+pub trait Layer<'a> {
+    /// Bytes taken up by the header, including any options, VLAN tags or
+    /// IPv6 extension headers
+    fn header_len(&self) -> usize;
+
+    fn payload(&self) -> &'a [u8];
+
+    fn next_protocol(&self) -> Option<NextProtocol>;
+}
+
+impl <'a> Layer<'a> for IpPacket<'a> {
+    fn header_len(&self) -> usize {
+        match *self {
+            IpPacket::V4(ref p) => p.header_len(),
+            IpPacket::V6(ref p) => p.header_len(),
+        }
+    }
+
+    fn payload(&self) -> &'a [u8] {
+        self.body()
+    }
+
+    fn next_protocol(&self) -> Option<NextProtocol> {
+        self.protocol().map(NextProtocol::Ip)
+    }
+}
+
+impl <'a> Layer<'a> for TransportLayerPacket<'a> {
+    fn header_len(&self) -> usize {
+        match *self {
+            TransportLayerPacket::Tcp(ref p) => p.header_len(),
+            TransportLayerPacket::Udp(ref p) => p.header_len(),
+            TransportLayerPacket::Icmp(ref p) => p.header_len(),
+        }
+    }
+
+    fn payload(&self) -> &'a [u8] {
+        match *self {
+            TransportLayerPacket::Tcp(ref p) => p.payload(),
+            TransportLayerPacket::Udp(ref p) => p.payload(),
+            TransportLayerPacket::Icmp(ref p) => p.payload(),
+        }
+    }
+
+    fn next_protocol(&self) -> Option<NextProtocol> {
+        match *self {
+            TransportLayerPacket::Tcp(ref p) => p.next_protocol(),
+            TransportLayerPacket::Udp(ref p) => p.next_protocol(),
+            TransportLayerPacket::Icmp(ref p) => p.next_protocol(),
+        }
+    }
+}
+
+impl <'a> ToBytes for IpPacket<'a> {
+    fn write_with(&self, fixup: Fixup, out: &mut Vec<u8>) {
+        match *self {
+            IpPacket::V4(ref p) => p.write_with(fixup, out),
+            IpPacket::V6(ref p) => p.write_with(fixup, out),
+        }
+    }
+}
+
+impl <'a> ToBytes for TransportLayerPacket<'a> {
+    fn write_with(&self, fixup: Fixup, out: &mut Vec<u8>) {
+        match *self {
+            TransportLayerPacket::Tcp(ref p) => p.write_with(fixup, out),
+            TransportLayerPacket::Udp(ref p) => p.write_with(fixup, out),
+            TransportLayerPacket::Icmp(ref p) => p.write_with(fixup, out),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OwnedIpPacket {
+    V4(ipv4::OwnedIpv4Packet),
+    V6(ipv6::OwnedIpv6Packet),
+}
+
+impl <'a> IntoOwned for IpPacket<'a> {
+    type Owned = OwnedIpPacket;
+
+    fn into_owned(self) -> OwnedIpPacket {
+        match self {
+            IpPacket::V4(p) => OwnedIpPacket::V4(p.into_owned()),
+            IpPacket::V6(p) => OwnedIpPacket::V6(p.into_owned()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OwnedTransportLayerPacket {
+    Tcp(tcp::OwnedTcpPacket),
+    Udp(udp::OwnedUdpPacket),
+    Icmp(icmp::OwnedIcmpPacket),
+}
+
+impl <'a> IntoOwned for TransportLayerPacket<'a> {
+    type Owned = OwnedTransportLayerPacket;
+
+    fn into_owned(self) -> OwnedTransportLayerPacket {
+        match self {
+            TransportLayerPacket::Tcp(p) => OwnedTransportLayerPacket::Tcp(p.into_owned()),
+            TransportLayerPacket::Udp(p) => OwnedTransportLayerPacket::Udp(p.into_owned()),
+            TransportLayerPacket::Icmp(p) => OwnedTransportLayerPacket::Icmp(p.into_owned()),
         }
     }
 }
@@ -107,6 +643,100 @@ mod tests {
         let (left, tcp_packet) = tcp::parse_tcp_packet(ipv4_packet.body).unwrap();
         println!("{:?}", &tcp_packet);
         assert_eq!(left.len(), 0);
+
+        assert_eq!(tcp_packet.to_bytes_with(Fixup::none()), ipv4_packet.body);
+        assert_eq!(ipv4_packet.to_bytes_with(Fixup::none()), eth_packet.body);
+        assert_eq!(eth_packet.to_bytes_with(Fixup::none()), &packet[..]);
+
+        // captured with checksum offload, so the TCP checksum is wrong
+        let ip_header = IpHeader::V4(&ipv4_packet.header);
+        assert!(!tcp::verify_checksum(&ip_header, ipv4_packet.body));
+        let fixed = ipv4_packet.to_bytes();
+        assert!(tcp::verify_checksum(&ip_header, &fixed[20..]));
+        assert_eq!(checksum::internet_checksum(&fixed[..20]), 0);
+    }
+
+    #[test]
+    fn test_build_packet() {
+        let payload = b"hello";
+        let udp = udp::UdpPacket::new(udp::UdpHeader::new(4312, 53), payload);
+        let udp_bytes = udp.to_bytes();
+        let src = "10.0.0.1".parse().unwrap();
+        let dst = "10.0.0.2".parse().unwrap();
+        let ip = ipv4::Ipv4Packet::new(ipv4::Header::new(ipv4::Ipv4Protocol::Udp, src, dst), &udp_bytes);
+        let ip_bytes = ip.to_bytes();
+        let mac = ethernet::MacAddr([0x02, 0, 0, 0, 0, 1]);
+        let frame = ethernet::EthernetIIPacket::new(mac, mac, ethernet::EtherType::Ipv4, &ip_bytes).to_bytes();
+        assert_eq!(frame.len(), 14 + 20 + 8 + 5);
+
+        let packet = parse_packet(LinkType::Ethernet, &frame);
+        match packet.ip {
+            Some(IpPacket::V4(ref ip)) => {
+                assert_eq!(ip.header.total_len, 33);
+                assert_eq!(ip.header.dst_ip, dst);
+                assert!(udp::verify_checksum(&IpHeader::V4(&ip.header), ip.body));
+            },
+            ref x => panic!("expected an IPv4 packet, got {:?}", x),
+        }
+        match packet.transport {
+            Some(TransportLayerPacket::Udp(ref udp)) => {
+                assert_eq!(udp.header.len, 13);
+                assert_eq!(udp.body, payload);
+            },
+            ref x => panic!("expected a UDP packet, got {:?}", x),
+        }
+
+        // the same packet walked generically
+        let layers: [&dyn Layer; 3] = [
+            packet.ethernet.as_ref().unwrap(),
+            packet.ip.as_ref().unwrap(),
+            packet.transport.as_ref().unwrap(),
+        ];
+        let header_lens: Vec<_> = layers.iter().map(|l| l.header_len()).collect();
+        assert_eq!(header_lens, vec![14, 20, 8]);
+        assert_eq!(layers[0].next_protocol(), Some(NextProtocol::EtherType(ethernet::EtherType::Ipv4)));
+        assert_eq!(layers[1].next_protocol(), Some(NextProtocol::Ip(ipv4::Ipv4Protocol::Udp)));
+        assert_eq!(layers[2].next_protocol(), Some(NextProtocol::Ports(4312, 53)));
+        assert_eq!(layers[2].payload(), payload);
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_arbitrary_round_trip() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        // deterministic noise, so failures can be reproduced
+        let mut state = 0x2545f4914f6cdd1du64;
+        let noise: Vec<u8> = (0..1 << 18).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        }).collect();
+        for chunk in noise.chunks(1024) {
+            let mut u = Unstructured::new(chunk);
+            let frame = ethernet::EthernetIIPacket::arbitrary(&mut u).unwrap();
+            let bs = frame.to_bytes();
+            let (_, parsed) = ethernet::parse_eth2_packet(&bs).unwrap();
+            assert_eq!(parsed.to_bytes(), bs);
+
+            let ip = IpPacket::arbitrary(&mut u).unwrap();
+            let bs = ip.to_bytes();
+            assert_eq!(parse_ip_packet(&bs).unwrap().to_bytes(), bs);
+
+            let transport = TransportLayerPacket::arbitrary(&mut u).unwrap();
+            let bs = transport.to_bytes();
+            let parsed = match transport {
+                TransportLayerPacket::Tcp(_) => tcp::parse_tcp_packet(&bs).map(|(_, p)| TransportLayerPacket::Tcp(p)),
+                TransportLayerPacket::Udp(_) => udp::parse_udp_packet(&bs).map(|(_, p)| TransportLayerPacket::Udp(p)),
+                TransportLayerPacket::Icmp(_) => icmp::parse_icmp_packet(&bs).map(|(_, p)| TransportLayerPacket::Icmp(p)),
+            };
+            assert_eq!(parsed.unwrap().to_bytes(), bs);
+
+            let message = dns::Message::arbitrary(&mut u).unwrap();
+            let summary = format!("{}", message);
+            assert!(!summary.is_empty());
+        }
     }
 
     #[test]
@@ -154,6 +784,9 @@ mod tests {
         let (left, tcp_packet) = tcp::parse_tcp_packet(ipv4_packet.body).unwrap();
         println!("{:?}", &tcp_packet);
         assert_eq!(left.len(), 0);
+        let ip_header = IpHeader::V4(&ipv4_packet.header);
+        assert!(tcp::verify_checksum(&ip_header, ipv4_packet.body));
+        assert_eq!(tcp::compute_checksum(&ip_header, ipv4_packet.body), Some(tcp_packet.header.checksum));
     }
 
     #[test]
@@ -187,11 +820,21 @@ mod tests {
         let (left, ip_packet) = ipv6::parse_ipv6_packet(&packet).unwrap();
         println!("{:?}", &ip_packet);
         assert_eq!(left.len(), 0);
-        let (left, udp_packet) = udp::parse_udp_packet(&ip_packet.body).unwrap();
+        let (left, udp_packet) = udp::parse_udp_packet(ip_packet.body).unwrap();
         println!("{:?}", &udp_packet);
         assert_eq!(left.len(), 0);
         assert_eq!(udp_packet.header.src,53);
         assert_eq!(udp_packet.header.dst, 2397);
         assert_eq!(udp_packet.header.len, 304);
+        let ip_header = IpHeader::V6(&ip_packet.header);
+        assert!(udp::verify_checksum(&ip_header, ip_packet.body));
+        assert_eq!(udp::compute_checksum(&ip_header, ip_packet.body), Some(udp_packet.header.checksum));
+
+        let key = IpPacket::V6(ip_packet).flow_key().unwrap();
+        assert_eq!(key.src_ip, "3ffe:501:4819::42".parse::<IpAddr>().unwrap());
+        assert_eq!(key.dst_ip, "3ffe:507:0:1:200:86ff:fe05:80da".parse::<IpAddr>().unwrap());
+        assert_eq!((key.src_port, key.dst_port), (53, 2397));
+        assert_eq!(key.proto, ipv4::Ipv4Protocol::Udp);
+        assert_eq!(key.reversed().reversed(), key);
     }
 }
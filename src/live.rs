@@ -0,0 +1,221 @@
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use libc;
+
+use dissect::{self, LinkType, Packet};
+use Timestamp;
+
+// big enough for anything short of jumbo frames with offloads
+const DEFAULT_SNAP_LEN: usize = 65536;
+
+/// A frame read off the wire
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Frame {
+    pub timestamp: Timestamp,
+    pub link_type: LinkType,
+    // length on the wire, which can be more than data.len() if it got cut
+    // off at the snap length
+    pub original_len: usize,
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    /// Runs the frame through the dissector
+    pub fn packet<'a>(&'a self) -> Packet<'a> {
+        let mut packet = dissect::parse_packet(self.link_type, &self.data);
+        packet.timestamp = Some(self.timestamp);
+        packet
+    }
+}
+
+/// A raw socket receiving every frame seen on one interface, or on all of
+/// them. Needs CAP_NET_RAW.
+pub struct Capture {
+    fd: RawFd,
+    ifindex: libc::c_int,
+    buf: Vec<u8>,
+}
+
+fn check(ret: libc::c_int) -> io::Result<libc::c_int> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+fn ifindex(name: &str) -> io::Result<libc::c_int> {
+    let name = CString::new(name)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "interface name contains a NUL"))?;
+    let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+    if ifindex == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ifindex as libc::c_int)
+}
+
+fn link_addr(ifindex: libc::c_int) -> libc::sockaddr_ll {
+    let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+    addr.sll_family = libc::AF_PACKET as libc::c_ushort;
+    addr.sll_protocol = (libc::ETH_P_ALL as u16).to_be();
+    addr.sll_ifindex = ifindex;
+    addr
+}
+
+impl Capture {
+    /// Opens a capture on the named interface, or on all interfaces if
+    /// `interface` is `None`
+    pub fn open(interface: Option<&str>) -> io::Result<Capture> {
+        let protocol = (libc::ETH_P_ALL as u16).to_be() as libc::c_int;
+        let fd = check(unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW, protocol) })?;
+        let mut capture = Capture {
+            fd,
+            ifindex: 0,
+            buf: vec![0; DEFAULT_SNAP_LEN],
+        };
+
+        if let Some(name) = interface {
+            capture.ifindex = ifindex(name)?;
+            let addr = link_addr(capture.ifindex);
+            check(unsafe {
+                libc::bind(
+                    fd,
+                    &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                    mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+                )
+            })?;
+        }
+
+        Ok(capture)
+    }
+
+    /// Frames longer than this get truncated
+    pub fn set_snap_len(&mut self, snap_len: usize) {
+        self.buf.resize(snap_len, 0);
+    }
+
+    /// Receives frames not addressed to this host too. Only works on a
+    /// capture opened on a specific interface.
+    pub fn set_promiscuous(&mut self, promiscuous: bool) -> io::Result<()> {
+        let mut mreq: libc::packet_mreq = unsafe { mem::zeroed() };
+        mreq.mr_ifindex = self.ifindex;
+        mreq.mr_type = libc::PACKET_MR_PROMISC as libc::c_ushort;
+        let option = if promiscuous {
+            libc::PACKET_ADD_MEMBERSHIP
+        } else {
+            libc::PACKET_DROP_MEMBERSHIP
+        };
+        check(unsafe {
+            libc::setsockopt(
+                self.fd,
+                libc::SOL_PACKET,
+                option,
+                &mreq as *const libc::packet_mreq as *const libc::c_void,
+                mem::size_of::<libc::packet_mreq>() as libc::socklen_t,
+            )
+        }).map(|_| ())
+    }
+
+    /// Blocks until the next frame arrives
+    pub fn next_frame(&mut self) -> io::Result<Frame> {
+        let len = unsafe {
+            libc::recv(
+                self.fd,
+                self.buf.as_mut_ptr() as *mut libc::c_void,
+                self.buf.len(),
+                libc::MSG_TRUNC,
+            )
+        };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let timestamp = Timestamp::now();
+        let original_len = len as usize;
+        Ok(Frame {
+            timestamp,
+            // AF_PACKET raw sockets hand over the whole link layer header,
+            // which is Ethernet for everything but a few odd interfaces
+            link_type: LinkType::Ethernet,
+            original_len,
+            data: self.buf[..original_len.min(self.buf.len())].to_vec(),
+        })
+    }
+}
+
+impl Iterator for Capture {
+    type Item = io::Result<Frame>;
+
+    fn next(&mut self) -> Option<io::Result<Frame>> {
+        Some(self.next_frame())
+    }
+}
+
+impl AsRawFd for Capture {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// A raw socket sending whole Ethernet frames out of one interface, as
+/// they are. Needs CAP_NET_RAW.
+pub struct Injector {
+    fd: RawFd,
+    ifindex: libc::c_int,
+}
+
+impl Injector {
+    pub fn open(interface: &str) -> io::Result<Injector> {
+        let ifindex = ifindex(interface)?;
+        // protocol 0, so frames sent here aren't looped back to it
+        let fd = check(unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW, 0) })?;
+        Ok(Injector {
+            fd,
+            ifindex,
+        })
+    }
+
+    /// Sends one frame, link layer header and all
+    pub fn send(&self, frame: &[u8]) -> io::Result<usize> {
+        let addr = link_addr(self.ifindex);
+        let len = unsafe {
+            libc::sendto(
+                self.fd,
+                frame.as_ptr() as *const libc::c_void,
+                frame.len(),
+                0,
+                &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(len as usize)
+    }
+}
+
+impl AsRawFd for Injector {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for Injector {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
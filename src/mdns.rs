@@ -0,0 +1,324 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use dns::{CharacterString, Class, DomainName, Header, Message, Opcode, Qclass, Qtype, Query,
+          Rcode, Rdata, ResourceRecord, Srv, Type, QR};
+use List;
+
+pub const MDNS_PORT: u16 = 5353;
+pub const MDNS_GROUP_V4: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+pub const MDNS_GROUP_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0xfb);
+
+/// Class IN with the top bit set. On records it's the cache flush bit,
+/// marking records only this host answers for; on questions it asks for a
+/// unicast response. Only lenient parsing accepts it, which is how the
+/// dissector parses the mDNS port.
+pub const IN_FLUSH: Class = Class::Unknown(0x8001);
+
+// RFC 6762 section 10: records naming a host, and everything else
+pub const HOST_TTL: u32 = 120;
+pub const OTHER_TTL: u32 = 4500;
+
+fn labels(name: &str) -> List<&[u8]> {
+    name.split('.').filter(|label| !label.is_empty()).map(str::as_bytes).collect()
+}
+
+fn same_name(a: &DomainName, b: &DomainName) -> bool {
+    a.to_string().eq_ignore_ascii_case(&b.to_string())
+}
+
+fn header(qr: QR) -> Header {
+    Header {
+        id: 0,
+        qr,
+        opcode: Opcode::Query,
+        aa: qr == QR::Response,
+        tc: false,
+        rd: false,
+        ra: false,
+        rcode: Rcode::NoError,
+        qdcount: 0,
+        ancount: 0,
+        nscount: 0,
+        arcount: 0,
+    }
+}
+
+/// A DNS-SD service instance this host advertises, like "Office Printer"
+/// of type "_ipp._tcp.local" on "printer.local", port 631
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Service {
+    instance: String,
+    service: String,
+    host: String,
+    port: u16,
+    txt: Vec<Vec<u8>>,
+    addrs: Vec<Vec<u8>>,
+}
+
+impl Service {
+    pub fn new(instance: &str, service: &str, host: &str, port: u16) -> Service {
+        Service {
+            instance: instance.to_string(),
+            service: service.trim_end_matches('.').to_string(),
+            host: host.trim_end_matches('.').to_string(),
+            port,
+            txt: vec![],
+            addrs: vec![],
+        }
+    }
+
+    /// Adds a "key=value" TXT entry; false if it's over 255 bytes
+    pub fn add_txt(&mut self, entry: &str) -> bool {
+        if entry.len() > 0xff {
+            return false;
+        }
+        self.txt.push(entry.as_bytes().to_vec());
+        true
+    }
+
+    pub fn add_addr(&mut self, addr: IpAddr) {
+        self.addrs.push(match addr {
+            IpAddr::V4(v4) => v4.octets().to_vec(),
+            IpAddr::V6(v6) => v6.octets().to_vec(),
+        });
+    }
+
+    fn service_name(&self) -> DomainName<'_> {
+        DomainName::Labels(labels(&self.service))
+    }
+
+    fn instance_name(&self) -> DomainName<'_> {
+        let mut name: List<_> = list![self.instance.as_bytes()];
+        name.extend(labels(&self.service));
+        DomainName::Labels(name)
+    }
+
+    fn host_name(&self) -> DomainName<'_> {
+        DomainName::Labels(labels(&self.host))
+    }
+
+    fn ptr(&self, ttl: u32) -> ResourceRecord<'_> {
+        // shared, since other hosts can offer the same type of service
+        ResourceRecord {
+            name: self.service_name(),
+            typ: Type::Ptr,
+            class: Class::IN,
+            ttl,
+            rdata: Rdata::Ptr(self.instance_name()),
+        }
+    }
+
+    fn srv(&self, ttl: u32) -> ResourceRecord<'_> {
+        ResourceRecord {
+            name: self.instance_name(),
+            typ: Type::SRV,
+            class: IN_FLUSH,
+            ttl,
+            rdata: Rdata::Srv(Srv {
+                priority: 0,
+                weight: 0,
+                port: self.port,
+                target: self.host_name(),
+            }),
+        }
+    }
+
+    fn txt(&self, ttl: u32) -> ResourceRecord<'_> {
+        // DNS-SD wants at least one string, even if it's empty
+        let mut strings: Vec<CharacterString> = self.txt.iter().filter_map(|s| CharacterString::new(s)).collect();
+        if strings.is_empty() {
+            strings.extend(CharacterString::new(&[]));
+        }
+        ResourceRecord {
+            name: self.instance_name(),
+            typ: Type::Txt,
+            class: IN_FLUSH,
+            ttl,
+            rdata: Rdata::Txt(strings),
+        }
+    }
+
+    fn addr_records(&self, ttl: u32) -> Vec<ResourceRecord<'_>> {
+        self.addrs.iter().map(|addr| {
+            let (typ, rdata) = if addr.len() == 4 {
+                (Type::A, Rdata::A(addr))
+            } else {
+                (Type::AAAA, Rdata::AAAA(addr))
+            };
+            ResourceRecord {
+                name: self.host_name(),
+                typ,
+                class: IN_FLUSH,
+                ttl,
+                rdata,
+            }
+        }).collect()
+    }
+
+    fn all_records(&self, scale: u32) -> Vec<ResourceRecord<'_>> {
+        let mut records = vec![self.ptr(OTHER_TTL*scale), self.srv(HOST_TTL*scale), self.txt(OTHER_TTL*scale)];
+        records.extend(self.addr_records(HOST_TTL*scale));
+        records
+    }
+
+    /// The unsolicited response announcing the service, with every record
+    /// in the answers
+    pub fn announcement(&self) -> Message<'_> {
+        let answers = self.all_records(1);
+        Message {
+            header: Header { ancount: answers.len() as u16, ..header(QR::Response) },
+            questions: vec![],
+            answers,
+            authorities: vec![],
+            additional: vec![],
+        }
+    }
+
+    /// The announcement with every TTL zeroed, sent when the service goes
+    /// away so caches drop it straight away
+    pub fn goodbye(&self) -> Message<'_> {
+        let answers = self.all_records(0);
+        Message {
+            header: Header { ancount: answers.len() as u16, ..header(QR::Response) },
+            questions: vec![],
+            answers,
+            authorities: vec![],
+            additional: vec![],
+        }
+    }
+
+    /// The response to a query, if it asks about the service. Records the
+    /// query already lists as known answers, with at least half their TTL
+    /// left, are left out; the SRV, TXT and addresses go along as
+    /// additional records when they weren't asked for.
+    pub fn respond<'a>(&'a self, query: &Message) -> Option<Message<'a>> {
+        if query.header.qr != QR::Query {
+            return None;
+        }
+        let mut answers = vec![];
+        let mut additional = vec![];
+        for q in &query.questions {
+            let wants = |typ: Type| q.qtype == Qtype::Type(typ) || q.qtype == Qtype::Wildcard;
+            if same_name(&q.qname, &self.service_name()) && wants(Type::Ptr) {
+                answers.push(self.ptr(OTHER_TTL));
+                additional.push(self.srv(HOST_TTL));
+                additional.push(self.txt(OTHER_TTL));
+                additional.extend(self.addr_records(HOST_TTL));
+            }
+            if same_name(&q.qname, &self.instance_name()) {
+                if wants(Type::SRV) {
+                    answers.push(self.srv(HOST_TTL));
+                    additional.extend(self.addr_records(HOST_TTL));
+                }
+                if wants(Type::Txt) {
+                    answers.push(self.txt(OTHER_TTL));
+                }
+            }
+            if same_name(&q.qname, &self.host_name()) {
+                answers.extend(self.addr_records(HOST_TTL).into_iter().filter(|rr| wants(rr.typ)));
+            }
+        }
+
+        let known = |rr: &ResourceRecord| query.answers.iter().any(|k| {
+            k.typ == rr.typ && k.rdata == rr.rdata && same_name(&k.name, &rr.name) && k.ttl >= rr.ttl/2
+        });
+        answers.retain(|rr| !known(rr));
+        if answers.is_empty() {
+            return None;
+        }
+        additional.retain(|rr| !answers.contains(rr));
+        additional.dedup();
+        Some(Message {
+            header: Header {
+                ancount: answers.len() as u16,
+                arcount: additional.len() as u16,
+                ..header(QR::Response)
+            },
+            questions: vec![],
+            answers,
+            authorities: vec![],
+            additional,
+        })
+    }
+}
+
+/// A record already in the browser's cache, along with the TTL it
+/// arrived with; its own `ttl` is what's left of it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KnownAnswer<'a> {
+    pub record: ResourceRecord<'a>,
+    pub original_ttl: u32,
+}
+
+/// A query browsing for instances of `service`, like "_http._tcp.local".
+/// Known answers with more than half their TTL left are listed so that
+/// responders don't repeat them.
+pub fn browse_query<'a>(service: &'a str, known: &[KnownAnswer<'a>]) -> Message<'a> {
+    let answers: Vec<ResourceRecord<'a>> = known.iter()
+        .filter(|k| k.record.ttl > k.original_ttl/2)
+        .map(|k| k.record.clone())
+        .collect();
+    Message {
+        header: Header { qdcount: 1, ancount: answers.len() as u16, ..header(QR::Query) },
+        questions: vec![Query {
+            qname: DomainName::Labels(labels(service)),
+            qtype: Qtype::Type(Type::Ptr),
+            qclass: Qclass::Class(Class::IN),
+        }],
+        answers,
+        authorities: vec![],
+        additional: vec![],
+    }
+}
+
+/// The instances named in the PTR answers to a browse for `service`
+pub fn instances<'a>(service: &str, response: &'a Message<'a>) -> Vec<&'a DomainName<'a>> {
+    let service = DomainName::Labels(labels(service));
+    response.answers.iter().chain(&response.additional)
+        .filter(|rr| rr.typ == Type::Ptr && same_name(&rr.name, &service))
+        .filter_map(|rr| match rr.rdata {
+            Rdata::Ptr(ref name) => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dns;
+    use ToBytes;
+
+    #[test]
+    fn test_advertise_and_browse() {
+        let mut printer = Service::new("Office Printer", "_ipp._tcp.local.", "printer.local", 631);
+        printer.add_txt("rp=ipp/print");
+        printer.add_addr(IpAddr::from([192, 168, 1, 20]));
+
+        let announcement = printer.announcement().to_bytes();
+        let (_, parsed) = dns::parse_dns_message_full_with(dns::Mode::Lenient, &announcement).unwrap();
+        assert_eq!(parsed.to_string(),
+            "response: Ptr Office Printer._ipp._tcp.local, SRV 0 0 631 printer.local, Txt rp=ipp/print, A 192.168.1.20");
+        assert_eq!(parsed.answers[1].class, IN_FLUSH);
+        assert!(printer.goodbye().answers.iter().all(|rr| rr.ttl == 0));
+        assert_eq!(instances("_ipp._tcp.local", &parsed)[0].to_string(), "Office Printer._ipp._tcp.local");
+
+        let query = browse_query("_ipp._tcp.local", &[]);
+        let response = printer.respond(&query).unwrap();
+        assert_eq!(response.answers.len(), 1);
+        assert_eq!(response.additional.len(), 3);
+
+        // known with most of its TTL left, so the responder stays quiet
+        let known = KnownAnswer { record: printer.ptr(4000), original_ttl: OTHER_TTL };
+        let query = browse_query("_ipp._tcp.local", &[known]);
+        assert_eq!(query.answers.len(), 1);
+        assert!(printer.respond(&query).is_none());
+        // but not once it's more than half gone
+        let stale = KnownAnswer { record: printer.ptr(1000), original_ttl: OTHER_TTL };
+        assert!(browse_query("_ipp._tcp.local", &[stale]).answers.is_empty());
+
+        let bs = browse_query("_ipp._tcp.local", &[]).to_bytes();
+        let (_, query) = dns::parse_dns_message_full(&bs).unwrap();
+        assert_eq!(query.to_string(), "query Ptr _ipp._tcp.local");
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use icmpv6::{self, Mld, RecordType};
+use igmp::{self, Igmp};
+use ipv4::Ipv4Protocol;
+use {IpPacket, Timestamp};
+
+/// Identifies one host's membership in one group
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MembershipKey {
+    pub host: IpAddr,
+    pub group: IpAddr,
+}
+
+/// Whether a member wants traffic from only the listed sources, or from
+/// every source but those
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FilterMode {
+    Include,
+    Exclude,
+}
+
+/// A host's membership in a group, as its reports have left it. Hosts on
+/// IGMPv2 or MLDv1 are always `Exclude` with no sources.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Membership {
+    pub mode: FilterMode,
+    /// Sorted, without duplicates
+    pub sources: Vec<IpAddr>,
+    pub last_report: Timestamp,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MembershipEvent {
+    Joined(MembershipKey),
+    /// A member's filter mode or sources changed
+    Changed(MembershipKey),
+    Left(MembershipKey),
+}
+
+/// Group membership on one link, from the IGMP and MLD reports hosts send.
+/// Memberships that go unreported for longer than the timeout end once
+/// `expire` is called; RFC 3376 and RFC 3810 default to 260 seconds. Use
+/// one tracker per interface.
+pub struct MembershipTracker {
+    members: HashMap<MembershipKey, Membership>,
+    timeout: Duration,
+}
+
+impl MembershipTracker {
+    pub fn new(timeout: Duration) -> MembershipTracker {
+        MembershipTracker {
+            members: HashMap::new(),
+            timeout,
+        }
+    }
+
+    /// Feeds an IP packet to the tracker; anything but an IGMP or MLD
+    /// report or leave is ignored
+    pub fn track(&mut self, packet: &IpPacket, now: Timestamp) -> Vec<MembershipEvent> {
+        let host = packet.src_ip();
+        match packet.protocol() {
+            Some(Ipv4Protocol::Igmp) => match igmp::parse_igmp_message(packet.body()) {
+                Ok((_, msg)) => self.igmp(host, &msg, now),
+                Err(_) => vec![],
+            },
+            Some(Ipv4Protocol::Other(58)) => {
+                let mld = icmpv6::parse_icmpv6_packet(packet.body()).ok().and_then(|(_, icmp)| icmp.mld());
+                match mld {
+                    Some(mld) => self.mld(host, &mld, now),
+                    None => vec![],
+                }
+            },
+            _ => vec![],
+        }
+    }
+
+    /// Applies an IGMP message `host` sent
+    pub fn igmp(&mut self, host: IpAddr, msg: &Igmp, now: Timestamp) -> Vec<MembershipEvent> {
+        let records = match *msg {
+            Igmp::Query(_) => vec![],
+            Igmp::ReportV1(group) | Igmp::ReportV2(group) => vec![(RecordType::ModeIsExclude, group.into(), vec![])],
+            Igmp::Leave(group) => vec![(RecordType::ChangeToInclude, group.into(), vec![])],
+            Igmp::ReportV3(ref records) => records.iter().map(|r| {
+                (r.typ, r.group.into(), r.sources.iter().map(|&s| s.into()).collect())
+            }).collect(),
+        };
+        self.apply(host, records, now)
+    }
+
+    /// Applies an MLD message `host` sent
+    pub fn mld(&mut self, host: IpAddr, msg: &Mld, now: Timestamp) -> Vec<MembershipEvent> {
+        let records = match *msg {
+            Mld::Query(_) => vec![],
+            Mld::Report(group) => vec![(RecordType::ModeIsExclude, group.into(), vec![])],
+            Mld::Done(group) => vec![(RecordType::ChangeToInclude, group.into(), vec![])],
+            Mld::ReportV2(ref records) => records.iter().map(|r| {
+                (r.typ, r.group.into(), r.sources.iter().map(|&s| s.into()).collect())
+            }).collect(),
+        };
+        self.apply(host, records, now)
+    }
+
+    fn apply(&mut self, host: IpAddr, records: Vec<(RecordType, IpAddr, Vec<IpAddr>)>, now: Timestamp)
+        -> Vec<MembershipEvent>
+    {
+        records.into_iter().filter_map(|(typ, group, sources)| {
+            self.record(MembershipKey { host, group }, typ, sources, now)
+        }).collect()
+    }
+
+    // RFC 3376 section 6.4.1, from a host's side: include mode with no
+    // sources is the same as not being a member
+    fn record(&mut self, key: MembershipKey, typ: RecordType, mut sources: Vec<IpAddr>, now: Timestamp)
+        -> Option<MembershipEvent>
+    {
+        sources.sort();
+        sources.dedup();
+        let (old_mode, old_sources) = match self.members.get(&key) {
+            Some(m) => (m.mode, &m.sources[..]),
+            None => (FilterMode::Include, &[][..]),
+        };
+        let union = |a: &[IpAddr], b: &[IpAddr]| {
+            let mut union = [a, b].concat();
+            union.sort();
+            union.dedup();
+            union
+        };
+        let minus = |a: &[IpAddr], b: &[IpAddr]| a.iter().filter(|s| !b.contains(s)).cloned().collect();
+        let (mode, sources) = match (typ, old_mode) {
+            (RecordType::ModeIsInclude, _) | (RecordType::ChangeToInclude, _) => (FilterMode::Include, sources),
+            (RecordType::ModeIsExclude, _) | (RecordType::ChangeToExclude, _) => (FilterMode::Exclude, sources),
+            (RecordType::AllowNewSources, FilterMode::Include) => (old_mode, union(old_sources, &sources)),
+            (RecordType::AllowNewSources, FilterMode::Exclude) => (old_mode, minus(old_sources, &sources)),
+            (RecordType::BlockOldSources, FilterMode::Include) => (old_mode, minus(old_sources, &sources)),
+            (RecordType::BlockOldSources, FilterMode::Exclude) => (old_mode, union(old_sources, &sources)),
+            (RecordType::Other(_), _) => return None,
+        };
+        if mode == FilterMode::Include && sources.is_empty() {
+            return self.members.remove(&key).map(|_| MembershipEvent::Left(key));
+        }
+        let membership = Membership { mode, sources, last_report: now };
+        match self.members.insert(key, membership) {
+            None => Some(MembershipEvent::Joined(key)),
+            Some(old) if (old.mode, &old.sources) != (mode, &self.members[&key].sources) => {
+                Some(MembershipEvent::Changed(key))
+            },
+            Some(_) => None,
+        }
+    }
+
+    /// Removes the memberships that haven't been reported for longer than
+    /// the timeout as of `now`, and returns them in order
+    pub fn expire(&mut self, now: Timestamp) -> Vec<MembershipKey> {
+        let timeout = self.timeout;
+        let mut expired: Vec<MembershipKey> = self.members.iter()
+            .filter(|&(_, m)| now >= m.last_report + timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        expired.sort();
+        for key in &expired {
+            self.members.remove(key);
+        }
+        expired
+    }
+
+    pub fn membership(&self, host: IpAddr, group: IpAddr) -> Option<&Membership> {
+        self.members.get(&MembershipKey { host, group })
+    }
+
+    /// The hosts that are members of `group`, in order
+    pub fn members(&self, group: IpAddr) -> Vec<IpAddr> {
+        let mut hosts: Vec<IpAddr> = self.members.keys().filter(|k| k.group == group).map(|k| k.host).collect();
+        hosts.sort();
+        hosts
+    }
+
+    /// The groups `host` is a member of, in order
+    pub fn groups(&self, host: IpAddr) -> Vec<IpAddr> {
+        let mut groups: Vec<IpAddr> = self.members.keys().filter(|k| k.host == host).map(|k| k.group).collect();
+        groups.sort();
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipv4::{Header, Ipv4Packet};
+
+    #[test]
+    fn test_membership_tracker() {
+        let mut tracker = MembershipTracker::new(Duration::from_secs(260));
+        let secs = |secs: u64| Timestamp::new(secs, 0);
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let (host, other, group) = (ip("192.168.1.10"), ip("192.168.1.11"), ip("239.1.2.3"));
+        let key = MembershipKey { host, group };
+
+        // an IGMPv2 join, then the same report again
+        let report = [0x16, 0x00, 0xf8, 0xfa, 0xef, 0x01, 0x02, 0x03];
+        let header = Header::new(Ipv4Protocol::Igmp, "192.168.1.10".parse().unwrap(), "239.1.2.3".parse().unwrap());
+        let packet = IpPacket::V4(Ipv4Packet::new(header, &report));
+        assert_eq!(tracker.track(&packet, secs(0)), vec![MembershipEvent::Joined(key)]);
+        assert_eq!(tracker.track(&packet, secs(60)), vec![]);
+        assert_eq!(tracker.membership(host, group).unwrap().last_report, secs(60));
+
+        // another host joins with IGMPv3, for two sources, then blocks one
+        let other_key = MembershipKey { host: other, group };
+        let records = |typ, sources: &[&str]| Igmp::ReportV3(vec![igmp::GroupRecord {
+            typ,
+            group: "239.1.2.3".parse().unwrap(),
+            sources: sources.iter().map(|s| s.parse().unwrap()).collect(),
+            aux_data: &[],
+        }]);
+        let allow = records(RecordType::AllowNewSources, &["10.0.0.1", "10.0.0.2"]);
+        assert_eq!(tracker.igmp(other, &allow, secs(10)), vec![MembershipEvent::Joined(other_key)]);
+        let block = records(RecordType::BlockOldSources, &["10.0.0.1"]);
+        assert_eq!(tracker.igmp(other, &block, secs(20)), vec![MembershipEvent::Changed(other_key)]);
+        let membership = tracker.membership(other, group).unwrap();
+        assert_eq!((membership.mode, membership.sources.clone()), (FilterMode::Include, vec![ip("10.0.0.2")]));
+        assert_eq!(tracker.members(group), vec![host, other]);
+
+        // blocking the last source leaves the group
+        let block = records(RecordType::BlockOldSources, &["10.0.0.2"]);
+        assert_eq!(tracker.igmp(other, &block, secs(30)), vec![MembershipEvent::Left(other_key)]);
+
+        // MLD, the same way
+        let (host6, group6) = (ip("fe80::1"), ip("ff02::fb"));
+        let key6 = MembershipKey { host: host6, group: group6 };
+        let report = Mld::Report("ff02::fb".parse().unwrap());
+        assert_eq!(tracker.mld(host6, &report, secs(100)), vec![MembershipEvent::Joined(key6)]);
+        assert_eq!(tracker.groups(host6), vec![group6]);
+        let done = Mld::Done("ff02::fb".parse().unwrap());
+        assert_eq!(tracker.mld(host6, &done, secs(110)), vec![MembershipEvent::Left(key6)]);
+        assert_eq!(tracker.mld(host6, &done, secs(111)), vec![]);
+
+        assert_eq!(tracker.expire(secs(319)), vec![]);
+        assert_eq!(tracker.expire(secs(320)), vec![key]);
+        assert!(tracker.members(group).is_empty());
+    }
+}
@@ -0,0 +1,351 @@
+use nom::bytes::complete::take as take_complete;
+use nom::bytes::streaming::take;
+use nom::combinator::verify;
+use nom::error::context;
+use nom::number::complete::{be_u8, be_u16, be_u32, be_u64};
+use nom::number::streaming::be_u16 as be_u16_streaming;
+use nom::sequence::{pair, preceded};
+
+use error::IResult;
+
+// https://opennetworking.org/software-defined-standards/specifications/
+//
+// Messages on the controller channel are framed by the length in their
+// header, so `parse_openflow_message` is streaming and fails with
+// `nom::Err::Incomplete` until a whole message has arrived
+
+pub const VERSION_1_0: u8 = 0x01;
+pub const VERSION_1_3: u8 = 0x04;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Header {
+    pub version: u8,
+    pub typ: u8,
+    pub length: u16,
+    pub xid: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct OpenFlowMessage<'a> {
+    pub header: Header,
+    pub body: Message<'a>,
+}
+
+/// The messages the parser understands. The type numbers of everything
+/// past PACKET_OUT differ between versions, so `header.typ` is only
+/// meaningful together with `header.version`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Message<'a> {
+    Hello,
+    // error type, code, and the start of the message that caused it
+    Error(u16, u16, &'a [u8]),
+    EchoRequest(&'a [u8]),
+    EchoReply(&'a [u8]),
+    FeaturesRequest,
+    PacketIn(PacketIn<'a>),
+    FlowMod(FlowMod<'a>),
+    // stats in 1.0, multipart in 1.3
+    StatsRequest(Stats<'a>),
+    StatsReply(Stats<'a>),
+    BarrierRequest,
+    BarrierReply,
+    // a message of another type, or any message of another version
+    Other(&'a [u8]),
+}
+
+pub fn parse_openflow_message(bs: &[u8]) -> IResult<'_, OpenFlowMessage<'_>> {
+    context("openflow", openflow_message)(bs)
+}
+
+fn openflow_message(bs: &[u8]) -> IResult<'_, OpenFlowMessage<'_>> {
+    let (_, length) = preceded(take(2usize), be_u16_streaming)(bs)?;
+    let (rest, message) = context("openflow.length", verify(take(length), |m: &[u8]| m.len() >= 8))(bs)?;
+    let (body, header) = header(message)?;
+    let body = match (header.version, header.typ) {
+        (_, 0) => Message::Hello,
+        (_, 1) => {
+            let (data, typ) = be_u16(body)?;
+            let (data, code) = be_u16(data)?;
+            Message::Error(typ, code, data)
+        },
+        (_, 2) => Message::EchoRequest(body),
+        (_, 3) => Message::EchoReply(body),
+        (_, 5) => Message::FeaturesRequest,
+        (VERSION_1_0, 10) => Message::PacketIn(context("openflow.packet_in", packet_in_1_0)(body)?.1),
+        (VERSION_1_3, 10) => Message::PacketIn(context("openflow.packet_in", packet_in_1_3)(body)?.1),
+        (VERSION_1_0, 14) => Message::FlowMod(context("openflow.flow_mod", flow_mod_1_0)(body)?.1),
+        (VERSION_1_3, 14) => Message::FlowMod(context("openflow.flow_mod", flow_mod_1_3)(body)?.1),
+        (VERSION_1_0, 16) | (VERSION_1_3, 18) =>
+            Message::StatsRequest(context("openflow.stats", |bs| stats(header.version, bs))(body)?.1),
+        (VERSION_1_0, 17) | (VERSION_1_3, 19) =>
+            Message::StatsReply(context("openflow.stats", |bs| stats(header.version, bs))(body)?.1),
+        (VERSION_1_0, 18) | (VERSION_1_3, 20) => Message::BarrierRequest,
+        (VERSION_1_0, 19) | (VERSION_1_3, 21) => Message::BarrierReply,
+        _ => Message::Other(body),
+    };
+    Ok((rest, OpenFlowMessage {
+        header,
+        body,
+    }))
+}
+
+fn header(bs: &[u8]) -> IResult<'_, Header> {
+    let (bs, version) = be_u8(bs)?;
+    let (bs, typ) = be_u8(bs)?;
+    let (bs, length) = be_u16(bs)?;
+    let (bs, xid) = be_u32(bs)?;
+    Ok((bs, Header {
+        version,
+        typ,
+        length,
+        xid,
+    }))
+}
+
+/// A packet a switch hands to the controller
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct PacketIn<'a> {
+    pub buffer_id: u32,
+    pub total_len: u16,
+    // in 1.3 only if the match carries an IN_PORT field
+    pub in_port: Option<u32>,
+    pub reason: u8,
+    // 1.3 only
+    pub table_id: Option<u8>,
+    pub cookie: Option<u64>,
+    // the raw ofp_match in 1.3, empty in 1.0
+    pub match_fields: &'a [u8],
+    // the start of the packet, usually an Ethernet frame
+    pub data: &'a [u8],
+}
+
+fn packet_in_1_0(bs: &[u8]) -> IResult<'_, PacketIn<'_>> {
+    let (bs, buffer_id) = be_u32(bs)?;
+    let (bs, total_len) = be_u16(bs)?;
+    let (bs, in_port) = be_u16(bs)?;
+    let (bs, reason) = be_u8(bs)?;
+    let (data, _pad) = be_u8(bs)?;
+    Ok((&data[data.len()..], PacketIn {
+        buffer_id,
+        total_len,
+        in_port: Some(in_port as u32),
+        reason,
+        table_id: None,
+        cookie: None,
+        match_fields: &[],
+        data,
+    }))
+}
+
+fn packet_in_1_3(bs: &[u8]) -> IResult<'_, PacketIn<'_>> {
+    let (bs, buffer_id) = be_u32(bs)?;
+    let (bs, total_len) = be_u16(bs)?;
+    let (bs, reason) = be_u8(bs)?;
+    let (bs, table_id) = be_u8(bs)?;
+    let (bs, cookie) = be_u64(bs)?;
+    let (bs, match_fields) = match_1_3(bs)?;
+    let (data, _pad) = take_complete(2usize)(bs)?;
+    Ok((&data[data.len()..], PacketIn {
+        buffer_id,
+        total_len,
+        in_port: oxm_in_port(match_fields),
+        reason,
+        table_id: Some(table_id),
+        cookie: Some(cookie),
+        match_fields,
+        data,
+    }))
+}
+
+// an ofp_match with its header, padded to a multiple of 8 bytes
+fn match_1_3(bs: &[u8]) -> IResult<'_, &[u8]> {
+    let (_, (_typ, length)) = pair(be_u16, be_u16)(bs)?;
+    let (rest, fields) = take_complete((length as usize).div_ceil(8) * 8)(bs)?;
+    Ok((rest, &fields[..(length as usize).min(fields.len())]))
+}
+
+// the OFPXMT_OFB_IN_PORT field of an OpenFlow basic OXM match
+fn oxm_in_port(match_fields: &[u8]) -> Option<u32> {
+    let mut bs = match_fields.get(4..)?;
+    while bs.len() >= 4 {
+        let class = u16::from_be_bytes([bs[0], bs[1]]);
+        let field = bs[2] >> 1;
+        let len = bs[3] as usize;
+        let value = bs.get(4..4 + len)?;
+        if class == 0x8000 && field == 0 && len == 4 {
+            return Some(u32::from_be_bytes([value[0], value[1], value[2], value[3]]));
+        }
+        bs = &bs[4 + len..];
+    }
+    None
+}
+
+/// A controller adding, changing or removing flow table entries
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct FlowMod<'a> {
+    pub cookie: u64,
+    // 1.3 only
+    pub cookie_mask: Option<u64>,
+    pub table_id: Option<u8>,
+    // add, modify, modify strict, delete or delete strict
+    pub command: u16,
+    pub idle_timeout: u16,
+    pub hard_timeout: u16,
+    pub priority: u16,
+    pub buffer_id: u32,
+    pub out_port: u32,
+    // 1.3 only
+    pub out_group: Option<u32>,
+    pub flags: u16,
+    // the raw ofp_match, a fixed 40 byte struct in 1.0 and OXM fields in 1.3
+    pub match_fields: &'a [u8],
+    // actions in 1.0, instructions in 1.3
+    pub instructions: &'a [u8],
+}
+
+fn flow_mod_1_0(bs: &[u8]) -> IResult<'_, FlowMod<'_>> {
+    let (bs, match_fields) = take_complete(40usize)(bs)?;
+    let (bs, cookie) = be_u64(bs)?;
+    let (bs, command) = be_u16(bs)?;
+    let (bs, idle_timeout) = be_u16(bs)?;
+    let (bs, hard_timeout) = be_u16(bs)?;
+    let (bs, priority) = be_u16(bs)?;
+    let (bs, buffer_id) = be_u32(bs)?;
+    let (bs, out_port) = be_u16(bs)?;
+    let (instructions, flags) = be_u16(bs)?;
+    Ok((&instructions[instructions.len()..], FlowMod {
+        cookie,
+        cookie_mask: None,
+        table_id: None,
+        command,
+        idle_timeout,
+        hard_timeout,
+        priority,
+        buffer_id,
+        out_port: out_port as u32,
+        out_group: None,
+        flags,
+        match_fields,
+        instructions,
+    }))
+}
+
+fn flow_mod_1_3(bs: &[u8]) -> IResult<'_, FlowMod<'_>> {
+    let (bs, cookie) = be_u64(bs)?;
+    let (bs, cookie_mask) = be_u64(bs)?;
+    let (bs, table_id) = be_u8(bs)?;
+    let (bs, command) = be_u8(bs)?;
+    let (bs, idle_timeout) = be_u16(bs)?;
+    let (bs, hard_timeout) = be_u16(bs)?;
+    let (bs, priority) = be_u16(bs)?;
+    let (bs, buffer_id) = be_u32(bs)?;
+    let (bs, out_port) = be_u32(bs)?;
+    let (bs, out_group) = be_u32(bs)?;
+    let (bs, flags) = be_u16(bs)?;
+    let (bs, _pad) = take_complete(2usize)(bs)?;
+    let (instructions, match_fields) = match_1_3(bs)?;
+    Ok((&instructions[instructions.len()..], FlowMod {
+        cookie,
+        cookie_mask: Some(cookie_mask),
+        table_id: Some(table_id),
+        command: command as u16,
+        idle_timeout,
+        hard_timeout,
+        priority,
+        buffer_id,
+        out_port,
+        out_group: Some(out_group),
+        flags,
+        match_fields,
+        instructions,
+    }))
+}
+
+/// A stats request or reply in 1.0, a multipart one in 1.3
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Stats<'a> {
+    // description, flow, aggregate, table, port...
+    pub typ: u16,
+    pub flags: u16,
+    pub body: &'a [u8],
+}
+
+fn stats(version: u8, bs: &[u8]) -> IResult<'_, Stats<'_>> {
+    let (bs, typ) = be_u16(bs)?;
+    let (bs, flags) = be_u16(bs)?;
+    let (body, _) = take_complete(if version == VERSION_1_0 { 0usize } else { 4 })(bs)?;
+    Ok((&body[body.len()..], Stats {
+        typ,
+        flags,
+        body,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_in_1_0() {
+        let message = [
+            0x01, 0x0a, 0x00, 0x1c, 0x00, 0x00, 0x00, 0x07,
+            0xff, 0xff, 0xff, 0xff, 0x00, 0x0a, 0x00, 0x03,
+            0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01,
+            0x02, 0x00, 0x00, 0x00,
+            // the next message, cut short
+            0x01, 0x00,
+        ];
+        let (rest, message) = parse_openflow_message(&message).unwrap();
+        assert_eq!(rest, &[0x01, 0x00]);
+        assert_eq!(message.header.xid, 7);
+        let packet_in = match message.body {
+            Message::PacketIn(packet_in) => packet_in,
+            other => panic!("expected a packet-in, got {:?}", other),
+        };
+        assert_eq!(packet_in.buffer_id, 0xffffffff);
+        assert_eq!(packet_in.in_port, Some(3));
+        assert_eq!(packet_in.data.len(), 10);
+        assert!(matches!(parse_openflow_message(rest), Err(nom::Err::Incomplete(_))));
+    }
+
+    #[test]
+    fn test_flow_mod_1_3() {
+        let message = [
+            0x04, 0x0e, 0x00, 0x48, 0x00, 0x00, 0x00, 0x01,
+            // cookie, cookie mask
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2a,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            // table, add, idle and hard timeouts, priority
+            0x00, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x80, 0x00,
+            // buffer, out port, out group, flags, pad
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00,
+            // OXM match on IN_PORT 1, padded
+            0x00, 0x01, 0x00, 0x0c, 0x80, 0x00, 0x00, 0x04,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+            // an instruction header
+            0x00, 0x04, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let (_, message) = parse_openflow_message(&message).unwrap();
+        let flow_mod = match message.body {
+            Message::FlowMod(flow_mod) => flow_mod,
+            other => panic!("expected a flow-mod, got {:?}", other),
+        };
+        assert_eq!(flow_mod.cookie, 42);
+        assert_eq!(flow_mod.idle_timeout, 10);
+        assert_eq!(flow_mod.priority, 0x8000);
+        assert_eq!(flow_mod.match_fields.len(), 12);
+        assert_eq!(oxm_in_port(flow_mod.match_fields), Some(1));
+        assert_eq!(flow_mod.instructions.len(), 8);
+
+        let multipart = [0x04, 0x12, 0x00, 0x10, 0x00, 0x00, 0x00, 0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let (_, message) = parse_openflow_message(&multipart).unwrap();
+        assert_eq!(message.body, Message::StatsRequest(Stats { typ: 1, flags: 0, body: &[] }));
+    }
+}
@@ -0,0 +1,205 @@
+use nom::bytes::complete::take;
+use nom::number::complete::{u16, u32};
+use nom::number::Endianness;
+
+use dissect::LinkType;
+use error::IResult;
+use pcapng::{ByteOrder, CapturedPacket};
+use Timestamp;
+
+// https://tools.ietf.org/html/draft-ietf-opsawg-pcap
+pub const MICROS_MAGIC: u32 = 0xa1b2c3d4;
+pub const NANOS_MAGIC: u32 = 0xa1b23c4d;
+
+// the link type field's FCS flag, with the FCS length in 16 bit words above it
+const FCS_PRESENT: u32 = 0x1000_0000;
+
+/// The header a classic libpcap capture starts with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FileHeader {
+    pub byte_order: ByteOrder,
+    // whether record timestamps are in nanoseconds rather than microseconds
+    pub nanos: bool,
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub snap_len: u32,
+    pub link_type: LinkType,
+    // bytes of FCS at the end of each packet, if the header says
+    pub fcs_len: Option<u8>,
+}
+
+/// A packet record, as it is in the file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Record<'a> {
+    pub ts_secs: u32,
+    // microseconds or nanoseconds, going by the file's magic
+    pub ts_frac: u32,
+    pub original_len: u32,
+    pub data: &'a [u8],
+}
+
+fn endianness(e: ByteOrder) -> Endianness {
+    match e {
+        ByteOrder::Big => Endianness::Big,
+        ByteOrder::Little => Endianness::Little,
+    }
+}
+
+/// The file header at the start of `bs`, or `None` if the magic isn't one
+/// of libpcap's in either byte order
+pub fn parse_file_header(bs: &[u8]) -> Option<FileHeader> {
+    if bs.len() < 24 {
+        return None;
+    }
+    let magic = [bs[0], bs[1], bs[2], bs[3]];
+    let (byte_order, nanos) = if magic == MICROS_MAGIC.to_be_bytes() {
+        (ByteOrder::Big, false)
+    } else if magic == NANOS_MAGIC.to_be_bytes() {
+        (ByteOrder::Big, true)
+    } else if magic == MICROS_MAGIC.to_le_bytes() {
+        (ByteOrder::Little, false)
+    } else if magic == NANOS_MAGIC.to_le_bytes() {
+        (ByteOrder::Little, true)
+    } else {
+        return None;
+    };
+    let e = endianness(byte_order);
+    let (bs, major) = u16::<_, ()>(e)(&bs[4..]).ok()?;
+    let (bs, minor) = u16::<_, ()>(e)(bs).ok()?;
+    // thiszone and sigfigs, which are always 0 in practice
+    let bs = &bs[8..];
+    let (bs, snap_len) = u32::<_, ()>(e)(bs).ok()?;
+    let (_, link_type) = u32::<_, ()>(e)(bs).ok()?;
+    let fcs_len = if link_type & FCS_PRESENT != 0 {
+        Some((link_type >> 29) as u8 * 2)
+    } else {
+        None
+    };
+    Some(FileHeader {
+        byte_order,
+        nanos,
+        major_version: major,
+        minor_version: minor,
+        snap_len,
+        link_type: LinkType::from_u32(link_type & 0x0fff_ffff),
+        fcs_len,
+    })
+}
+
+pub fn parse_record(bs: &[u8], e: ByteOrder) -> IResult<'_, Record<'_>> {
+    let (bs, ts_secs) = u32(endianness(e))(bs)?;
+    let (bs, ts_frac) = u32(endianness(e))(bs)?;
+    let (bs, captured_len) = u32(endianness(e))(bs)?;
+    let (bs, original_len) = u32(endianness(e))(bs)?;
+    let (bs, data) = take(captured_len)(bs)?;
+    Ok((bs, Record {
+        ts_secs,
+        ts_frac,
+        original_len,
+        data,
+    }))
+}
+
+/// Reads a classic libpcap capture, in either byte order and with
+/// microsecond or nanosecond timestamps, and yields its packets the way the
+/// pcapng `Reader` does. There's only the one interface, numbered 0.
+/// Iteration stops at the end of the capture or at a truncated record.
+pub struct Reader<'a> {
+    bs: &'a [u8],
+    header: FileHeader,
+}
+
+impl <'a> Reader<'a> {
+    /// Starts reading a capture, which has to begin with a libpcap header
+    pub fn new(bs: &'a [u8]) -> Option<Reader<'a>> {
+        parse_file_header(bs).map(|header| Reader {
+            bs: &bs[24..],
+            header,
+        })
+    }
+
+    pub fn header(&self) -> &FileHeader {
+        &self.header
+    }
+}
+
+impl <'a> Iterator for Reader<'a> {
+    type Item = CapturedPacket<'a>;
+
+    fn next(&mut self) -> Option<CapturedPacket<'a>> {
+        let (rest, record) = parse_record(self.bs, self.header.byte_order).ok()?;
+        self.bs = rest;
+        let secs = u64::from(record.ts_secs);
+        let timestamp = if self.header.nanos {
+            Timestamp::new(secs, record.ts_frac)
+        } else {
+            Timestamp::new(secs, record.ts_frac.saturating_mul(1000))
+        };
+        Some(CapturedPacket {
+            interface_id: 0,
+            link_type: self.header.link_type,
+            timestamp,
+            original_len: record.original_len,
+            fcs_len: self.header.fcs_len,
+            data: record.data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAPTURE: [u8; 56] = [
+        // file header, little endian, microseconds, ethernet
+        0xd4, 0xc3, 0xb2, 0xa1, 0x02, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0xff, 0xff, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+        // at 1.5s, 4 of 60 bytes
+        0x01, 0x00, 0x00, 0x00, 0x20, 0xa1, 0x07, 0x00, 0x04, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x00, 0x00,
+        0xde, 0xad, 0xbe, 0xef,
+        // truncated
+        0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_read_capture() {
+        let mut reader = Reader::new(&CAPTURE).unwrap();
+        assert_eq!(reader.header().byte_order, ByteOrder::Little);
+        assert_eq!((reader.header().major_version, reader.header().minor_version), (2, 4));
+        let packet = reader.next().unwrap();
+        assert_eq!(packet.link_type, LinkType::Ethernet);
+        assert_eq!(packet.timestamp, Timestamp::new(1, 500_000_000));
+        assert_eq!(packet.original_len, 60);
+        assert_eq!(packet.fcs_len, None);
+        assert_eq!(packet.data, &[0xde, 0xad, 0xbe, 0xef]);
+        assert!(reader.next().is_none());
+
+        assert!(Reader::new(&CAPTURE[..20]).is_none());
+        assert!(Reader::new(&[0x0a, 0x0d, 0x0d, 0x0a, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                              0, 0, 0, 0, 0, 0, 0, 0]).is_none());
+    }
+
+    #[test]
+    fn test_big_endian_nanos() {
+        let capture = [
+            // file header, big endian, nanoseconds, raw IP with a 4 byte FCS
+            0xa1, 0xb2, 0x3c, 0x4d, 0x00, 0x02, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0xff, 0xff, 0x50, 0x00, 0x00, 0x65,
+            // at 2.000000007s
+            0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x02,
+            0x45, 0x00,
+        ];
+        let header = parse_file_header(&capture).unwrap();
+        assert_eq!(header.byte_order, ByteOrder::Big);
+        assert!(header.nanos);
+        assert_eq!(header.link_type, LinkType::Raw);
+        assert_eq!(header.fcs_len, Some(4));
+
+        let packets: Vec<_> = Reader::new(&capture).unwrap().collect();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].timestamp, Timestamp::new(2, 7));
+        assert_eq!(packets[0].data, &[0x45, 0x00]);
+    }
+}
@@ -0,0 +1,674 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str;
+
+use nom::bytes::complete::take;
+use nom::combinator::{all_consuming, map, rest, verify};
+use nom::error::{context, ErrorKind};
+use nom::number::complete::{u16, u32, u64};
+use nom::number::Endianness;
+use nom::Needed;
+
+use dissect::{self, FcsMode, LinkType, Packet, Registry};
+use error::{IResult, ParseError};
+use stream::Decode;
+use {IntoOwned, Timestamp};
+
+// https://tools.ietf.org/html/draft-tuexen-opsawg-pcapng
+pub const SECTION_HEADER: u32 = 0x0a0d0d0a;
+pub const INTERFACE_DESCRIPTION: u32 = 0x00000001;
+pub const NAME_RESOLUTION: u32 = 0x00000004;
+pub const ENHANCED_PACKET: u32 = 0x00000006;
+
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+
+const OPT_END: u16 = 0;
+const IF_NAME: u16 = 2;
+const IF_TSRESOL: u16 = 9;
+const IF_FCSLEN: u16 = 13;
+const IF_TSOFFSET: u16 = 14;
+
+const NRB_END: u16 = 0;
+const NRB_IPV4: u16 = 1;
+const NRB_IPV6: u16 = 2;
+
+/// Byte order of a section, set by the writer of the capture
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ByteOrder {
+    Big,
+    Little,
+}
+
+impl ByteOrder {
+    fn endianness(&self) -> Endianness {
+        match *self {
+            ByteOrder::Big => Endianness::Big,
+            ByteOrder::Little => Endianness::Little,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Block<'a> {
+    SectionHeader(SectionHeader<'a>),
+    InterfaceDescription(InterfaceDescription<'a>),
+    EnhancedPacket(EnhancedPacket<'a>),
+    NameResolution(Vec<NameRecord<'a>>),
+    Unknown(u32, &'a [u8]),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct BlockOption<'a> {
+    pub code: u16,
+    pub value: &'a [u8],
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct SectionHeader<'a> {
+    pub byte_order: ByteOrder,
+    pub major_version: u16,
+    pub minor_version: u16,
+    // -1 if unspecified
+    pub section_len: i64,
+    pub options: Vec<BlockOption<'a>>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct InterfaceDescription<'a> {
+    pub link_type: LinkType,
+    pub snap_len: u32,
+    pub options: Vec<BlockOption<'a>>,
+}
+
+impl <'a> InterfaceDescription<'a> {
+    fn option(&self, code: u16) -> Option<&'a [u8]> {
+        self.options.iter().find(|o| o.code == code).map(|o| o.value)
+    }
+
+    pub fn name(&self) -> Option<&'a str> {
+        self.option(IF_NAME).and_then(|v| str::from_utf8(v).ok())
+    }
+
+    /// The if_tsresol option; 6 (microseconds) if it's absent, or too fine
+    /// for a second's worth of units to fit in 64 bits
+    pub fn ts_resolution(&self) -> u8 {
+        self.option(IF_TSRESOL)
+            .and_then(|v| v.first().cloned())
+            .filter(|&resolution| ts_units(resolution).is_some())
+            .unwrap_or(6)
+    }
+
+    /// The if_fcslen option: how many bytes of frame check sequence the
+    /// interface's packets end with, if the capture says
+    pub fn fcs_len(&self) -> Option<u8> {
+        self.option(IF_FCSLEN).and_then(|v| v.first().cloned())
+    }
+
+    /// Converts a packet timestamp from this interface into a `Timestamp`,
+    /// taking into account the interface's resolution and offset
+    pub fn timestamp(&self, ts: u64, byte_order: ByteOrder) -> Timestamp {
+        to_timestamp(ts, self.ts_resolution(), self.ts_offset(byte_order))
+    }
+
+    // the if_tsoffset option in seconds, 0 if it's absent
+    fn ts_offset(&self, byte_order: ByteOrder) -> u64 {
+        self.option(IF_TSOFFSET)
+            .and_then(|v| all_consuming(u64::<_, ()>(byte_order.endianness()))(v).ok())
+            .map(|(_, offset)| offset)
+            .unwrap_or(0)
+    }
+}
+
+// how many timestamp units there are in a second at an if_tsresol, if that
+// fits in a u64
+fn ts_units(resolution: u8) -> Option<u64> {
+    let exp = (resolution & 0x7f) as u32;
+    if resolution & 0x80 == 0 {
+        // negative power of 10
+        10u64.checked_pow(exp)
+    } else {
+        // negative power of 2
+        1u64.checked_shl(exp)
+    }
+}
+
+fn to_timestamp(ts: u64, resolution: u8, offset: u64) -> Timestamp {
+    let units = ts_units(resolution).unwrap_or(1_000_000);
+    let frac = (ts % units) as u128 * 1_000_000_000 / units as u128;
+    Timestamp::new((ts / units).wrapping_add(offset), frac as u32)
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct EnhancedPacket<'a> {
+    pub interface_id: u32,
+    // in units of the interface's timestamp resolution
+    pub timestamp: u64,
+    pub captured_len: u32,
+    pub original_len: u32,
+    pub data: &'a [u8],
+    pub options: Vec<BlockOption<'a>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct NameRecord<'a> {
+    pub addr: IpAddr,
+    pub names: Vec<&'a str>,
+}
+
+// values in blocks are padded out to 32 bits
+fn padded(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn valid_block_len(len: u32) -> bool {
+    len >= 12 && len & 3 == 0
+}
+
+/// Byte order of the section starting at `bs`, from its Section Header Block
+pub fn section_byte_order(bs: &[u8]) -> Option<ByteOrder> {
+    if bs.len() < 12 {
+        return None;
+    }
+    let magic = &bs[8..12];
+    if magic == [0x1a, 0x2b, 0x3c, 0x4d] {
+        Some(ByteOrder::Big)
+    } else if magic == [0x4d, 0x3c, 0x2b, 0x1a] {
+        Some(ByteOrder::Little)
+    } else {
+        None
+    }
+}
+
+fn parse_option(bs: &[u8], e: ByteOrder) -> IResult<'_, BlockOption<'_>> {
+    let (bs, code) = u16(e.endianness())(bs)?;
+    let (bs, len) = u16(e.endianness())(bs)?;
+    let (bs, value) = take(padded(len as usize))(bs)?;
+    Ok((bs, BlockOption {
+        code,
+        value: &value[..len as usize],
+    }))
+}
+
+fn parse_options<'a>(mut bs: &'a [u8], e: ByteOrder) -> Vec<BlockOption<'a>> {
+    let mut options = vec![];
+    while let Ok((rest, option)) = parse_option(bs, e) {
+        if option.code == OPT_END {
+            break;
+        }
+        options.push(option);
+        bs = rest;
+    }
+    options
+}
+
+fn parse_section_header(bs: &[u8], e: ByteOrder) -> IResult<'_, SectionHeader<'_>> {
+    let (bs, _magic) = verify(u32(e.endianness()), |&m| m == BYTE_ORDER_MAGIC)(bs)?;
+    let (bs, major) = u16(e.endianness())(bs)?;
+    let (bs, minor) = u16(e.endianness())(bs)?;
+    let (bs, section_len) = u64(e.endianness())(bs)?;
+    let (bs, options) = rest(bs)?;
+    Ok((bs, SectionHeader {
+        byte_order: e,
+        major_version: major,
+        minor_version: minor,
+        section_len: section_len as i64,
+        options: parse_options(options, e),
+    }))
+}
+
+fn parse_interface_description(bs: &[u8], e: ByteOrder) -> IResult<'_, InterfaceDescription<'_>> {
+    let (bs, link_type) = u16(e.endianness())(bs)?;
+    let (bs, _reserved) = u16(e.endianness())(bs)?;
+    let (bs, snap_len) = u32(e.endianness())(bs)?;
+    let (bs, options) = rest(bs)?;
+    Ok((bs, InterfaceDescription {
+        link_type: LinkType::from_u32(link_type as u32),
+        snap_len,
+        options: parse_options(options, e),
+    }))
+}
+
+fn parse_enhanced_packet(bs: &[u8], e: ByteOrder) -> IResult<'_, EnhancedPacket<'_>> {
+    let (bs, interface_id) = u32(e.endianness())(bs)?;
+    let (bs, ts_high) = u32(e.endianness())(bs)?;
+    let (bs, ts_low) = u32(e.endianness())(bs)?;
+    let (bs, captured_len) = u32(e.endianness())(bs)?;
+    let (bs, original_len) = u32(e.endianness())(bs)?;
+    let (bs, data) = take(padded(captured_len as usize))(bs)?;
+    let (bs, options) = rest(bs)?;
+    Ok((bs, EnhancedPacket {
+        interface_id,
+        timestamp: ((ts_high as u64) << 32) | ts_low as u64,
+        captured_len,
+        original_len,
+        data: &data[..captured_len as usize],
+        options: parse_options(options, e),
+    }))
+}
+
+fn name_record<'a>(typ: u16, value: &'a [u8]) -> Option<NameRecord<'a>> {
+    let (addr, names) = match typ {
+        NRB_IPV4 if value.len() >= 4 => {
+            let v = value;
+            (IpAddr::V4(Ipv4Addr::new(v[0], v[1], v[2], v[3])), &value[4..])
+        },
+        NRB_IPV6 if value.len() >= 16 => {
+            let mut addr = [0u8; 16];
+            addr.copy_from_slice(&value[..16]);
+            (IpAddr::V6(Ipv6Addr::from(addr)), &value[16..])
+        },
+        _ => return None,
+    };
+    Some(NameRecord {
+        addr,
+        names: names
+            .split(|&b| b == 0)
+            .filter(|n| !n.is_empty())
+            .filter_map(|n| str::from_utf8(n).ok())
+            .collect(),
+    })
+}
+
+fn parse_name_resolution(mut bs: &[u8], e: ByteOrder) -> IResult<'_, Vec<NameRecord<'_>>> {
+    let mut records = vec![];
+    loop {
+        let (rest, typ) = u16(e.endianness())(bs)?;
+        let (rest, len) = u16(e.endianness())(rest)?;
+        let (rest, value) = take(padded(len as usize))(rest)?;
+        let value = &value[..len as usize];
+        bs = rest;
+        if typ == NRB_END {
+            break;
+        }
+        // records of unknown types are skipped
+        if let Some(record) = name_record(typ, value) {
+            records.push(record);
+        }
+    }
+    Ok((bs, records))
+}
+
+/// Parses one block of a section with byte order `e`
+pub fn parse_block(bs: &[u8], e: ByteOrder) -> IResult<'_, Block<'_>> {
+    context("pcapng", |bs| block(bs, e))(bs)
+}
+
+fn block(bs: &[u8], e: ByteOrder) -> IResult<'_, Block<'_>> {
+    let (rest, typ) = u32(e.endianness())(bs)?;
+    let (rest, len) = context("pcapng.block_len", verify(u32(e.endianness()), |&len| valid_block_len(len)))(rest)?;
+    let (rest, body) = take(len - 12)(rest)?;
+    let (rest, _trailing_len) = verify(u32(e.endianness()), |&l| l == len)(rest)?;
+    let (_, block) = match typ {
+        SECTION_HEADER => context("pcapng.shb", map(|bs| parse_section_header(bs, e), Block::SectionHeader))(body)?,
+        INTERFACE_DESCRIPTION =>
+            context("pcapng.idb", map(|bs| parse_interface_description(bs, e), Block::InterfaceDescription))(body)?,
+        ENHANCED_PACKET => context("pcapng.epb", map(|bs| parse_enhanced_packet(bs, e), Block::EnhancedPacket))(body)?,
+        NAME_RESOLUTION => context("pcapng.nrb", map(|bs| parse_name_resolution(bs, e), Block::NameResolution))(body)?,
+        _ => (&b""[..], Block::Unknown(typ, body)),
+    };
+    Ok((rest, block))
+}
+
+/// A packet from an Enhanced Packet Block, with its interface resolved
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct CapturedPacket<'a> {
+    pub interface_id: u32,
+    pub link_type: LinkType,
+    pub timestamp: Timestamp,
+    pub original_len: u32,
+    // bytes of FCS at the end of data, from the interface's if_fcslen
+    pub fcs_len: Option<u8>,
+    pub data: &'a [u8],
+}
+
+impl <'a> CapturedPacket<'a> {
+    /// Runs the packet through the dissector, going by the interface's
+    /// if_fcslen for whether Ethernet frames end with an FCS
+    pub fn packet(&self) -> Packet<'a> {
+        dissect_captured(self.link_type, self.timestamp, self.fcs_len, self.data)
+    }
+}
+
+fn dissect_captured(link_type: LinkType, timestamp: Timestamp, fcs_len: Option<u8>, data: &[u8]) -> Packet<'_> {
+    let mut registry = Registry::new();
+    registry.set_fcs_mode(FcsMode::from_fcs_len(fcs_len));
+    let mut packet = dissect::parse_packet_with(&registry, link_type, data);
+    packet.timestamp = Some(timestamp);
+    packet
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedCapturedPacket {
+    pub interface_id: u32,
+    pub link_type: LinkType,
+    pub timestamp: Timestamp,
+    pub original_len: u32,
+    pub fcs_len: Option<u8>,
+    pub data: Vec<u8>,
+}
+
+impl OwnedCapturedPacket {
+    /// Like `CapturedPacket::packet`
+    pub fn packet(&self) -> Packet<'_> {
+        dissect_captured(self.link_type, self.timestamp, self.fcs_len, &self.data)
+    }
+}
+
+impl <'a> IntoOwned for CapturedPacket<'a> {
+    type Owned = OwnedCapturedPacket;
+
+    fn into_owned(self) -> OwnedCapturedPacket {
+        OwnedCapturedPacket {
+            interface_id: self.interface_id,
+            link_type: self.link_type,
+            timestamp: self.timestamp,
+            original_len: self.original_len,
+            fcs_len: self.fcs_len,
+            data: self.data.into_owned(),
+        }
+    }
+}
+
+/// Walks the blocks of a pcapng capture, keeping track of each section's
+/// byte order and interfaces, and yields the captured packets. Iteration
+/// stops at the end of the capture or at the first malformed block. Packets
+/// from an interface the section hasn't described are skipped.
+pub struct Reader<'a> {
+    bs: &'a [u8],
+    byte_order: ByteOrder,
+    interfaces: Vec<InterfaceDescription<'a>>,
+    names: Vec<NameRecord<'a>>,
+}
+
+impl <'a> Reader<'a> {
+    /// Starts reading a capture, which has to begin with a Section Header
+    pub fn new(bs: &'a [u8]) -> Option<Reader<'a>> {
+        if bs.len() < 4 || bs[..4] != [0x0a, 0x0d, 0x0d, 0x0a] {
+            return None;
+        }
+        section_byte_order(bs).map(|e| Reader {
+            bs,
+            byte_order: e,
+            interfaces: vec![],
+            names: vec![],
+        })
+    }
+
+    /// Interfaces described so far in the current section
+    pub fn interfaces(&self) -> &[InterfaceDescription<'a>] {
+        &self.interfaces
+    }
+
+    /// Name resolution records seen so far
+    pub fn names(&self) -> &[NameRecord<'a>] {
+        &self.names
+    }
+
+    /// First name seen for an address
+    pub fn resolve(&self, addr: &IpAddr) -> Option<&'a str> {
+        self.names.iter()
+            .filter(|r| r.addr == *addr)
+            .filter_map(|r| r.names.first().cloned())
+            .next()
+    }
+}
+
+impl <'a> Iterator for Reader<'a> {
+    type Item = CapturedPacket<'a>;
+
+    fn next(&mut self) -> Option<CapturedPacket<'a>> {
+        loop {
+            if self.bs.len() >= 4 && self.bs[..4] == [0x0a, 0x0d, 0x0d, 0x0a] {
+                // a new section may switch byte order
+                self.byte_order = section_byte_order(self.bs)?;
+            }
+            let (rest, block) = match parse_block(self.bs, self.byte_order) {
+                Ok((rest, block)) => (rest, block),
+                _ => return None,
+            };
+            self.bs = rest;
+            match block {
+                Block::SectionHeader(_) => self.interfaces.clear(),
+                Block::InterfaceDescription(idb) => self.interfaces.push(idb),
+                Block::NameResolution(records) => self.names.extend(records),
+                Block::EnhancedPacket(epb) => {
+                    let interface = match self.interfaces.get(epb.interface_id as usize) {
+                        Some(interface) => interface,
+                        None => continue,
+                    };
+                    return Some(CapturedPacket {
+                        interface_id: epb.interface_id,
+                        link_type: interface.link_type,
+                        timestamp: interface.timestamp(epb.timestamp, self.byte_order),
+                        original_len: epb.original_len,
+                        fcs_len: interface.fcs_len(),
+                        data: epb.data,
+                    });
+                },
+                Block::Unknown(_, _) => {},
+            }
+        }
+    }
+}
+
+// what PacketDecoder keeps of an Interface Description Block
+#[derive(Clone, Copy, Debug)]
+struct Interface {
+    link_type: LinkType,
+    ts_resolution: u8,
+    ts_offset: u64,
+    fcs_len: Option<u8>,
+}
+
+/// Decodes a pcapng capture that arrives in pieces, for use with
+/// `stream::Decoder`. Like `Reader`, it yields the captured packets, keeps
+/// track of sections and interfaces along the way, and skips packets from
+/// interfaces it hasn't seen described.
+#[derive(Clone, Debug, Default)]
+pub struct PacketDecoder {
+    // None until the first Section Header
+    byte_order: Option<ByteOrder>,
+    interfaces: Vec<Interface>,
+}
+
+impl Decode for PacketDecoder {
+    type Output = OwnedCapturedPacket;
+
+    fn decode<'a>(&mut self, bs: &'a [u8]) -> IResult<'a, Option<OwnedCapturedPacket>> {
+        // enough for the block length, and for a section header's byte order
+        if bs.len() < 12 {
+            return Err(nom::Err::Incomplete(Needed::new(12 - bs.len())));
+        }
+        let byte_order = if bs[..4] == [0x0a, 0x0d, 0x0d, 0x0a] {
+            section_byte_order(bs)
+        } else {
+            self.byte_order
+        };
+        let e = match byte_order {
+            Some(e) => e,
+            None => return Err(nom::Err::Error(ParseError {
+                input: bs,
+                kind: ErrorKind::Tag,
+                context: vec!["pcapng.shb", "pcapng"],
+            })),
+        };
+        let (_, len) = u32(e.endianness())(&bs[4..])?;
+        if bs.len() < len as usize {
+            return Err(nom::Err::Incomplete(Needed::new(len as usize - bs.len())));
+        }
+        let (rest, block) = parse_block(bs, e)?;
+        self.byte_order = Some(e);
+        match block {
+            Block::SectionHeader(_) => self.interfaces.clear(),
+            Block::InterfaceDescription(idb) => self.interfaces.push(Interface {
+                link_type: idb.link_type,
+                ts_resolution: idb.ts_resolution(),
+                ts_offset: idb.ts_offset(e),
+                fcs_len: idb.fcs_len(),
+            }),
+            Block::EnhancedPacket(epb) => {
+                let interface = match self.interfaces.get(epb.interface_id as usize) {
+                    Some(interface) => interface,
+                    None => return Ok((rest, None)),
+                };
+                return Ok((rest, Some(OwnedCapturedPacket {
+                    interface_id: epb.interface_id,
+                    link_type: interface.link_type,
+                    timestamp: to_timestamp(epb.timestamp, interface.ts_resolution, interface.ts_offset),
+                    original_len: epb.original_len,
+                    fcs_len: interface.fcs_len,
+                    data: epb.data.into_owned(),
+                })));
+            },
+            Block::NameResolution(_) | Block::Unknown(_, _) => {},
+        }
+        Ok((rest, None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stream::Decoder;
+
+    const CAPTURE: [u8; 128] = [
+        // section header, little endian
+        0x0a, 0x0d, 0x0d, 0x0a, 0x1c, 0x00, 0x00, 0x00, 0x4d, 0x3c, 0x2b, 0x1a, 0x01, 0x00, 0x00, 0x00,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x1c, 0x00, 0x00, 0x00,
+        // interface description, ethernet, nanosecond timestamps
+        0x01, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00,
+        0x09, 0x00, 0x01, 0x00, 0x09, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00,
+        // name resolution, 192.168.0.1 is "host"
+        0x04, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x01, 0x00, 0x09, 0x00, 0xc0, 0xa8, 0x00, 0x01,
+        0x68, 0x6f, 0x73, 0x74, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00,
+        // enhanced packet at 1.5s
+        0x06, 0x00, 0x00, 0x00, 0x24, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x2f, 0x68, 0x59, 0x04, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x00, 0x00, 0xde, 0xad, 0xbe, 0xef,
+        0x24, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_read_capture() {
+        let mut reader = Reader::new(&CAPTURE).unwrap();
+        let packet = reader.next().unwrap();
+        assert_eq!(packet.link_type, LinkType::Ethernet);
+        assert_eq!(packet.timestamp, Timestamp::new(1, 500_000_000));
+        assert_eq!(packet.original_len, 60);
+        assert_eq!(packet.data, &[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(packet.packet().timestamp, Some(Timestamp::new(1, 500_000_000)));
+        assert!(reader.next().is_none());
+
+        assert_eq!(reader.interfaces()[0].ts_resolution(), 9);
+        assert_eq!(reader.resolve(&IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1))), Some("host"));
+    }
+
+    #[test]
+    fn test_binary_ts_resolution() {
+        let idb = InterfaceDescription {
+            link_type: LinkType::Ethernet,
+            snap_len: 0,
+            options: vec![BlockOption { code: IF_TSRESOL, value: &[0x82] }],
+        };
+        // quarter seconds
+        assert_eq!(idb.timestamp(9, ByteOrder::Big), Timestamp::new(2, 250_000_000));
+    }
+
+    #[test]
+    fn test_bad_ts_resolution() {
+        // 10^-30 and 2^-127 seconds don't fit, so they're taken as microseconds
+        for &resolution in &[30, 0xff] {
+            let mut capture = CAPTURE;
+            capture[48] = resolution;
+            let packet = Reader::new(&capture).unwrap().next().unwrap();
+            assert_eq!(packet.timestamp, Timestamp::new(1500, 0));
+
+            let mut decoder = Decoder::new(PacketDecoder::default());
+            decoder.push(&capture);
+            let packet = decoder.next().unwrap().unwrap();
+            assert_eq!(packet.timestamp, Timestamp::new(1500, 0));
+        }
+
+        // the finest that do fit
+        let idb = |value: &'static [u8]| InterfaceDescription {
+            link_type: LinkType::Ethernet,
+            snap_len: 0,
+            options: vec![BlockOption { code: IF_TSRESOL, value }],
+        };
+        assert_eq!(idb(&[19]).timestamp(u64::MAX, ByteOrder::Big), Timestamp::new(1, 844_674_407));
+        assert_eq!(idb(&[0xbf]).timestamp(u64::MAX, ByteOrder::Big), Timestamp::new(1, 999_999_999));
+    }
+
+    #[test]
+    fn test_unknown_interface() {
+        // the packet, again but from interface 1, which isn't described
+        let mut capture = CAPTURE.to_vec();
+        capture.extend_from_slice(&CAPTURE[92..]);
+        capture.extend_from_slice(&CAPTURE[92..]);
+        capture[128 + 8] = 1;
+
+        let packets: Vec<_> = Reader::new(&capture).unwrap().collect();
+        assert_eq!(packets.len(), 2);
+        assert!(packets.iter().all(|packet| packet.interface_id == 0));
+
+        let mut decoder = Decoder::new(PacketDecoder::default());
+        decoder.push(&capture);
+        let packets: Vec<_> = decoder.by_ref().collect();
+        assert_eq!(packets.len(), 2);
+        assert!(packets.iter().all(|packet| packet.as_ref().unwrap().interface_id == 0));
+        assert!(decoder.buffered().is_empty());
+    }
+
+    #[test]
+    fn test_fcs_len() {
+        use std::net::Ipv4Addr;
+        use dissect::ApplicationLayer;
+        use testgen::{self, Endpoint};
+
+        let (query, _) = testgen::dns_exchange(&Endpoint::client(), &Endpoint::server(), 1, "example.com",
+                                               Ipv4Addr::new(192, 0, 2, 1));
+        let mut frame = query.clone();
+        frame.extend_from_slice(&::checksum::crc32(&query).to_le_bytes());
+        let captured = CapturedPacket {
+            interface_id: 0,
+            link_type: LinkType::Ethernet,
+            timestamp: Timestamp::new(1, 0),
+            original_len: frame.len() as u32,
+            fcs_len: Some(4),
+            data: &frame,
+        };
+        let packet = captured.packet();
+        assert_eq!(packet.fcs.map(|fcs| fcs.valid), Some(true));
+        assert!(matches!(packet.application, Some(ApplicationLayer::Dns(_))));
+        let owned = captured.clone().into_owned();
+        assert!(matches!(owned.packet().application, Some(ApplicationLayer::Dns(_))));
+
+        // without an if_fcslen it's looked for, and with 0 it's not there
+        let packet = CapturedPacket { fcs_len: None, ..captured.clone() }.packet();
+        assert_eq!(packet.fcs.map(|fcs| fcs.valid), Some(true));
+        let packet = CapturedPacket { fcs_len: Some(0), ..captured }.packet();
+        assert!(packet.fcs.is_none());
+    }
+
+    #[test]
+    fn test_packet_decoder() {
+        let mut decoder = Decoder::new(PacketDecoder::default());
+        let mut packets = vec![];
+        for chunk in CAPTURE.chunks(5) {
+            decoder.push(chunk);
+            packets.extend(&mut decoder);
+        }
+        assert_eq!(packets.len(), 1);
+        let packet = packets[0].as_ref().unwrap();
+        assert_eq!(packet.timestamp, Timestamp::new(1, 500_000_000));
+        assert_eq!(packet.data, vec![0xde, 0xad, 0xbe, 0xef]);
+        assert!(decoder.buffered().is_empty());
+    }
+}
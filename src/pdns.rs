@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::collections::hash_map;
+use std::time::Duration;
+
+use dns::{self, Type, QR};
+use Timestamp;
+
+// a lowercased name and a record type
+type Key = (String, Type);
+
+/// One name to data mapping seen in DNS responses. `name` is lowercased,
+/// and `data` is the rdata as `Rdata`'s Display writes it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PdnsEntry {
+    pub name: String,
+    pub typ: Type,
+    pub data: String,
+    // the TTL the last response gave it
+    pub ttl: u32,
+    pub first_seen: Timestamp,
+    pub last_seen: Timestamp,
+    // how many responses carried it
+    pub count: u64,
+}
+
+impl PdnsEntry {
+    /// When the last response's TTL runs out
+    pub fn expires(&self) -> Timestamp {
+        self.last_seen + Duration::from_secs(self.ttl as u64)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PdnsEvent {
+    // a mapping that wasn't in the cache
+    New(PdnsEntry),
+    // a response whose records for a name and type don't match the ones
+    // cached, given as the cached data and then the response's
+    Changed {
+        name: String,
+        typ: Type,
+        before: Vec<String>,
+        after: Vec<String>,
+    },
+    Expired(PdnsEntry),
+}
+
+/// Passive DNS collection: builds a cache of the mappings in the answer
+/// sections of DNS responses, which expire once their TTL has run out.
+/// Authority and additional records are left out, since nothing checks
+/// that a server was entitled to send them.
+#[derive(Default)]
+pub struct PdnsCache {
+    entries: HashMap<Key, Vec<PdnsEntry>>,
+}
+
+impl PdnsCache {
+    pub fn new() -> PdnsCache {
+        PdnsCache::default()
+    }
+
+    /// Adds the answers in a response to the cache, and returns what
+    /// changed. Queries and failed responses are ignored. Names have to be
+    /// whole, as `parse_dns_message_full` and the dissector leave them;
+    /// answers still holding compression pointers are skipped.
+    pub fn observe(&mut self, msg: &dns::Message, now: Timestamp) -> Vec<PdnsEvent> {
+        let mut events = vec![];
+        if msg.header.qr != QR::Response || msg.header.rcode != dns::Rcode::NoError {
+            return events;
+        }
+
+        // the answers for each name and type, in the order they came
+        let mut rrsets: Vec<(Key, Vec<(String, u32)>)> = vec![];
+        for rr in msg.answers.iter().filter(|rr| rr.is_resolved()) {
+            let key = (rr.name.to_string().to_lowercase(), rr.typ);
+            let data = (rr.rdata.to_string(), rr.ttl);
+            match rrsets.iter_mut().find(|set| set.0 == key) {
+                Some(set) => set.1.push(data),
+                None => rrsets.push((key, vec![data])),
+            }
+        }
+
+        for (key, records) in rrsets {
+            let cached = self.entries.entry(key.clone()).or_default();
+            let (expired, live) = cached.drain(..).partition(|entry| entry.expires() <= now);
+            *cached = live;
+            events.extend(expired.into_iter().map(PdnsEvent::Expired));
+            let before: Vec<String> = cached.iter().map(|entry| entry.data.clone()).collect();
+            let mut after: Vec<String> = vec![];
+            for (data, ttl) in records {
+                if after.contains(&data) {
+                    continue;
+                }
+                after.push(data.clone());
+                match cached.iter_mut().find(|entry| entry.data == data) {
+                    Some(entry) => {
+                        entry.ttl = ttl;
+                        entry.last_seen = now;
+                        entry.count += 1;
+                    },
+                    None => {
+                        let entry = PdnsEntry {
+                            name: key.0.clone(),
+                            typ: key.1,
+                            data,
+                            ttl,
+                            first_seen: now,
+                            last_seen: now,
+                            count: 1,
+                        };
+                        events.push(PdnsEvent::New(entry.clone()));
+                        cached.push(entry);
+                    },
+                }
+            }
+            let same = before.len() == after.len() && before.iter().all(|data| after.contains(data));
+            if !before.is_empty() && !same {
+                events.push(PdnsEvent::Changed {
+                    name: key.0,
+                    typ: key.1,
+                    before,
+                    after,
+                });
+            }
+        }
+        events
+    }
+
+    /// The unexpired mappings for a name and type
+    pub fn lookup(&self, name: &str, typ: Type, now: Timestamp) -> Vec<&PdnsEntry> {
+        self.entries.get(&(name.to_lowercase(), typ))
+            .map(|cached| cached.iter().filter(|entry| entry.expires() > now).collect())
+            .unwrap_or_default()
+    }
+
+    /// Removes and returns every mapping whose TTL has run out as of `now`
+    pub fn expire(&mut self, now: Timestamp) -> Vec<PdnsEvent> {
+        let mut events = vec![];
+        for cached in self.entries.values_mut() {
+            let (expired, live) = cached.drain(..).partition(|entry| entry.expires() <= now);
+            *cached = live;
+            events.extend(expired.into_iter().map(PdnsEvent::Expired));
+        }
+        self.entries.retain(|_, cached| !cached.is_empty());
+        events
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            sets: self.entries.values(),
+            entries: [].iter(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.values().map(|cached| cached.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Every mapping in a `PdnsCache`, expired or not, in no particular order
+pub struct Iter<'a> {
+    sets: hash_map::Values<'a, Key, Vec<PdnsEntry>>,
+    entries: ::std::slice::Iter<'a, PdnsEntry>,
+}
+
+impl <'a> Iterator for Iter<'a> {
+    type Item = &'a PdnsEntry;
+
+    fn next(&mut self) -> Option<&'a PdnsEntry> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Some(entry);
+            }
+            self.entries = self.sets.next()?.iter();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dns::{parse_dns_message_full, Class, Rdata, ResourceRecord};
+
+    #[test]
+    fn test_pdns_cache() {
+        // a query for www.google.com, turned into responses below
+        let query = [
+            0x24, 0x1a, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x77, 0x77, 0x77,
+            0x06, 0x67, 0x6f, 0x6f, 0x67, 0x6c, 0x65, 0x03, 0x63, 0x6f, 0x6d, 0x00, 0x00, 0x01, 0x00, 0x01,
+        ];
+        const A: &[u8] = &[1, 2, 3, 4];
+        const B: &[u8] = &[5, 6, 7, 8];
+        let (_, msg) = parse_dns_message_full(&query).unwrap();
+        let response = |addrs: &[&'static [u8]], ttl| {
+            let mut response = msg.clone();
+            response.header.qr = QR::Response;
+            for addr in addrs {
+                response.answers.push(ResourceRecord {
+                    name: msg.questions[0].qname.clone(),
+                    typ: Type::A,
+                    class: Class::IN,
+                    ttl,
+                    rdata: Rdata::A(addr),
+                });
+            }
+            response
+        };
+
+        let mut cache = PdnsCache::new();
+        assert!(cache.observe(&msg, Timestamp::new(0, 0)).is_empty());
+        let events = cache.observe(&response(&[A], 60), Timestamp::new(0, 0));
+        assert_eq!(events.len(), 1);
+        match events[0] {
+            PdnsEvent::New(ref entry) => {
+                assert_eq!(entry.name, "www.google.com");
+                assert_eq!(entry.data, "1.2.3.4");
+            },
+            ref x => panic!("expected a new entry, got {:?}", x),
+        }
+        assert!(cache.observe(&response(&[A], 60), Timestamp::new(10, 0)).is_empty());
+
+        let events = cache.observe(&response(&[B], 30), Timestamp::new(20, 0));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1], PdnsEvent::Changed {
+            name: "www.google.com".to_string(),
+            typ: Type::A,
+            before: vec!["1.2.3.4".to_string()],
+            after: vec!["5.6.7.8".to_string()],
+        });
+
+        let live = cache.lookup("WWW.Google.com", Type::A, Timestamp::new(20, 0));
+        assert_eq!(live.len(), 2);
+        assert_eq!(live[0].first_seen, Timestamp::new(0, 0));
+        assert_eq!(live[0].last_seen, Timestamp::new(10, 0));
+        assert_eq!(live[0].count, 2);
+
+        // 1.2.3.4 runs out at 70s, 5.6.7.8 at 50s
+        let expired = cache.expire(Timestamp::new(60, 0));
+        assert_eq!(expired.len(), 1);
+        match expired[0] {
+            PdnsEvent::Expired(ref entry) => assert_eq!(entry.data, "5.6.7.8"),
+            ref x => panic!("expected an expired entry, got {:?}", x),
+        }
+        assert_eq!(cache.iter().count(), 1);
+        assert!(cache.lookup("www.google.com", Type::A, Timestamp::new(70, 0)).is_empty());
+
+        // seeing the name again after 1.2.3.4 ran out still reports it
+        let events = cache.observe(&response(&[B], 30), Timestamp::new(80, 0));
+        match events[0] {
+            PdnsEvent::Expired(ref entry) => assert_eq!(entry.data, "1.2.3.4"),
+            ref x => panic!("expected an expired entry, got {:?}", x),
+        }
+        assert!(matches!(events[1], PdnsEvent::New(_)));
+        assert_eq!(events.len(), 2);
+
+        // names that weren't resolved aren't cached under "<pointer 12>"
+        let mut compressed = response(&[A], 60);
+        compressed.answers[0].name = dns::DomainName::Pointer(12);
+        assert!(cache.observe(&compressed, Timestamp::new(90, 0)).is_empty());
+        assert_eq!(cache.len(), 1);
+    }
+}
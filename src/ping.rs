@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use icmp::IcmpType;
+use {IpPacket, Timestamp, TransportLayerPacket};
+
+/// Identifies one echo request and its reply. `peer` is the host being
+/// pinged, so the destination of the request and the source of the reply.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EchoKey {
+    pub peer: IpAddr,
+    pub id: u16,
+    pub seq: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum EchoEvent {
+    Request(EchoKey),
+    Reply(EchoKey, Duration),
+    // a reply to a request that wasn't seen, was already answered or expired
+    Unmatched(EchoKey),
+}
+
+/// Round trip times and losses for one peer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EchoStats {
+    pub sent: u64,
+    pub received: u64,
+    pub lost: u64,
+    pub min_rtt: Option<Duration>,
+    pub max_rtt: Option<Duration>,
+    pub total_rtt: Duration,
+}
+
+impl EchoStats {
+    pub fn avg_rtt(&self) -> Option<Duration> {
+        if self.received > 0 {
+            let nanos = self.total_rtt.as_nanos() / self.received as u128;
+            Some(Duration::new((nanos / 1_000_000_000) as u64, (nanos % 1_000_000_000) as u32))
+        } else {
+            None
+        }
+    }
+
+    /// Fraction of the requests that timed out, ignoring those still
+    /// waiting for a reply
+    pub fn loss(&self) -> f64 {
+        let done = self.received + self.lost;
+        if done > 0 {
+            self.lost as f64 / done as f64
+        } else {
+            0.0
+        }
+    }
+
+    fn record(&mut self, rtt: Duration) {
+        self.received += 1;
+        // stuck at the most a Duration holds, rather than wrapping
+        self.total_rtt = self.total_rtt.saturating_add(rtt);
+        self.min_rtt = Some(self.min_rtt.map_or(rtt, |min| min.min(rtt)));
+        self.max_rtt = Some(self.max_rtt.map_or(rtt, |max| max.max(rtt)));
+    }
+}
+
+/// Pairs ICMP echo requests with their replies. Requests that go
+/// unanswered for longer than the timeout count as lost once `expire` is
+/// called.
+pub struct EchoTracker {
+    outstanding: HashMap<EchoKey, Timestamp>,
+    stats: HashMap<IpAddr, EchoStats>,
+    timeout: Duration,
+}
+
+impl EchoTracker {
+    pub fn new(timeout: Duration) -> EchoTracker {
+        EchoTracker {
+            outstanding: HashMap::new(),
+            stats: HashMap::new(),
+            timeout,
+        }
+    }
+
+    pub fn request(&mut self, key: EchoKey, now: Timestamp) {
+        self.stats.entry(key.peer).or_default().sent += 1;
+        self.outstanding.insert(key, now);
+    }
+
+    /// Matches a reply to its request and returns the round trip time
+    pub fn reply(&mut self, key: EchoKey, now: Timestamp) -> Option<Duration> {
+        self.outstanding.remove(&key).map(|sent| {
+            let rtt = now.saturating_duration_since(sent);
+            self.stats.entry(key.peer).or_default().record(rtt);
+            rtt
+        })
+    }
+
+    /// Feeds an IP packet to the tracker; anything but an ICMP echo request
+    /// or reply is ignored
+    pub fn track(&mut self, packet: &IpPacket, now: Timestamp) -> Option<EchoEvent> {
+        let header = match packet.parse_inner() {
+            Some(TransportLayerPacket::Icmp(icmp)) => icmp.header,
+            _ => return None,
+        };
+        let (id, seq) = header.echo_id_seq()?;
+        if header.typ == IcmpType::EchoRequest {
+            let key = EchoKey { peer: packet.dst_ip(), id, seq };
+            self.request(key, now);
+            Some(EchoEvent::Request(key))
+        } else {
+            let key = EchoKey { peer: packet.src_ip(), id, seq };
+            Some(match self.reply(key, now) {
+                Some(rtt) => EchoEvent::Reply(key, rtt),
+                None => EchoEvent::Unmatched(key),
+            })
+        }
+    }
+
+    /// Removes the requests that have waited longer than the timeout as of
+    /// `now`, counts them as lost and returns them in order
+    pub fn expire(&mut self, now: Timestamp) -> Vec<EchoKey> {
+        let timeout = self.timeout;
+        let mut expired: Vec<EchoKey> = self.outstanding.iter()
+            .filter(|&(_, &sent)| now >= sent + timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        expired.sort();
+        for key in &expired {
+            self.outstanding.remove(key);
+            self.stats.entry(key.peer).or_default().lost += 1;
+        }
+        expired
+    }
+
+    pub fn stats(&self, peer: &IpAddr) -> Option<&EchoStats> {
+        self.stats.get(peer)
+    }
+
+    /// Number of requests still waiting for a reply
+    pub fn outstanding(&self) -> usize {
+        self.outstanding.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use icmp::{IcmpHeader, IcmpPacket};
+    use ipv4::{Header, Ipv4Packet, Ipv4Protocol};
+    use ToBytes;
+
+    fn echo(typ: IcmpType, src: &str, dst: &str, seq: u16) -> (Header<'static>, Vec<u8>) {
+        let icmp = IcmpPacket {
+            header: IcmpHeader {
+                typ,
+                code: 0,
+                checksum: 0,
+                rest_of_header: 0x1234_0000 | seq as u32,
+            },
+            body: b"ping",
+        };
+        let header = Header::new(Ipv4Protocol::Icmp, src.parse().unwrap(), dst.parse().unwrap());
+        (header, icmp.to_bytes())
+    }
+
+    #[test]
+    fn test_echo_tracker() {
+        let mut tracker = EchoTracker::new(Duration::from_secs(2));
+        let peer: IpAddr = "10.0.0.1".parse().unwrap();
+        let ms = Duration::from_millis;
+        let at = |ms: u64| Timestamp::from_micros(ms * 1000);
+
+        for seq in 0..3 {
+            let (header, body) = echo(IcmpType::EchoRequest, "10.0.0.2", "10.0.0.1", seq);
+            let packet = IpPacket::V4(Ipv4Packet::new(header, &body));
+            let key = EchoKey { peer, id: 0x1234, seq };
+            assert_eq!(tracker.track(&packet, at(1000 * seq as u64)), Some(EchoEvent::Request(key)));
+        }
+        let (header, body) = echo(IcmpType::EchoReply, "10.0.0.1", "10.0.0.2", 0);
+        let packet = IpPacket::V4(Ipv4Packet::new(header, &body));
+        let key = EchoKey { peer, id: 0x1234, seq: 0 };
+        assert_eq!(tracker.track(&packet, at(15)), Some(EchoEvent::Reply(key, ms(15))));
+        assert_eq!(tracker.track(&packet, at(16)), Some(EchoEvent::Unmatched(key)));
+        assert_eq!(tracker.reply(EchoKey { peer, id: 0x1234, seq: 2 }, at(2025)), Some(ms(25)));
+
+        let lost = tracker.expire(at(3500));
+        assert_eq!(lost, vec![EchoKey { peer, id: 0x1234, seq: 1 }]);
+        assert_eq!(tracker.outstanding(), 0);
+
+        let stats = tracker.stats(&peer).unwrap();
+        assert_eq!((stats.sent, stats.received, stats.lost), (3, 2, 1));
+        assert_eq!(stats.min_rtt, Some(ms(15)));
+        assert_eq!(stats.max_rtt, Some(ms(25)));
+        assert_eq!(stats.avg_rtt(), Some(ms(20)));
+        assert!((stats.loss() - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_avg_rtt() {
+        let stats = EchoStats { received: 1 << 32, total_rtt: Duration::from_secs(1 << 32), ..EchoStats::default() };
+        assert_eq!(stats.avg_rtt(), Some(Duration::from_secs(1)));
+
+        let mut stats = EchoStats::default();
+        stats.record(Duration::MAX);
+        stats.record(Duration::from_secs(1));
+        assert_eq!(stats.total_rtt, Duration::MAX);
+        assert_eq!(stats.avg_rtt(), Some(Duration::MAX / 2));
+    }
+}
@@ -0,0 +1,450 @@
+use std::fmt;
+use std::net::Ipv4Addr;
+
+use nom::bytes::complete::{tag, take};
+use nom::combinator::{all_consuming, opt, rest};
+use nom::error::context;
+use nom::multi::many0;
+use nom::number::complete::{be_u8, be_u16, be_u32};
+
+use error::IResult;
+use ipv4::parse_ipv4_packet;
+use ipv6::parse_ipv6_packet;
+use IpPacket;
+
+// https://tools.ietf.org/html/rfc1661, with IPCP from
+// https://tools.ietf.org/html/rfc1332 and https://tools.ietf.org/html/rfc1877
+
+/// A PPP frame without its HDLC-like flags and FCS, as PPPoE and L2TP
+/// carry it
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct PppFrame<'a> {
+    pub protocol: Protocol,
+    pub information: &'a [u8],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Protocol {
+    Ipv4,
+    Ipv6,
+    Lcp,
+    Pap,
+    Chap,
+    Ipcp,
+    Ipv6cp,
+    Ccp,
+    Other(u16),
+}
+
+impl Protocol {
+    pub fn from_u16(v: u16) -> Protocol {
+        match v {
+            0x0021 => Protocol::Ipv4,
+            0x0057 => Protocol::Ipv6,
+            0xc021 => Protocol::Lcp,
+            0xc023 => Protocol::Pap,
+            0xc223 => Protocol::Chap,
+            0x8021 => Protocol::Ipcp,
+            0x8057 => Protocol::Ipv6cp,
+            0x80fd => Protocol::Ccp,
+            x => Protocol::Other(x),
+        }
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        match *self {
+            Protocol::Ipv4 => 0x0021,
+            Protocol::Ipv6 => 0x0057,
+            Protocol::Lcp => 0xc021,
+            Protocol::Pap => 0xc023,
+            Protocol::Chap => 0xc223,
+            Protocol::Ipcp => 0x8021,
+            Protocol::Ipv6cp => 0x8057,
+            Protocol::Ccp => 0x80fd,
+            Protocol::Other(x) => x,
+        }
+    }
+
+    /// Whether this protocol's packets use LCP's code, identifier, length
+    /// layout, which every network control protocol borrows
+    pub fn is_control(&self) -> bool {
+        self.to_u16() & 0x8000 != 0
+    }
+}
+
+/// Parses a PPP frame, skipping the 0xff 0x03 address and control fields
+/// if they're there, and taking a one byte protocol field if it was
+/// compressed
+pub fn parse_ppp_frame(bs: &[u8]) -> IResult<'_, PppFrame<'_>> {
+    context("ppp", ppp_frame)(bs)
+}
+
+fn ppp_frame(bs: &[u8]) -> IResult<'_, PppFrame<'_>> {
+    let (bs, _) = opt(tag([0xff, 0x03]))(bs)?;
+    // protocol numbers are odd in their low byte and even in their high
+    // byte, so an odd first byte is all there is
+    let (bs, protocol) = match bs.first() {
+        Some(b) if b & 1 == 1 => be_u8(bs).map(|(bs, p)| (bs, p as u16))?,
+        _ => be_u16(bs)?,
+    };
+    let (bs, information) = rest(bs)?;
+    Ok((bs, PppFrame {
+        protocol: Protocol::from_u16(protocol),
+        information,
+    }))
+}
+
+impl <'a> PppFrame<'a> {
+    /// The LCP, IPCP or other control protocol packet this frame carries
+    pub fn control_packet(&self) -> Option<ControlPacket<'a>> {
+        if !self.protocol.is_control() {
+            return None;
+        }
+        parse_control_packet(self.information).ok().map(|(_, packet)| packet)
+    }
+
+    /// The IP packet this frame carries, for `dissect::parse_ip_layers` to
+    /// take the rest of the way
+    pub fn ip_packet(&self) -> Option<IpPacket<'a>> {
+        match self.protocol {
+            Protocol::Ipv4 => all_consuming(parse_ipv4_packet)(self.information).ok().map(|(_, ip)| IpPacket::V4(ip)),
+            Protocol::Ipv6 => all_consuming(parse_ipv6_packet)(self.information).ok().map(|(_, ip)| IpPacket::V6(ip)),
+            _ => None,
+        }
+    }
+}
+
+impl <'a> fmt::Display for PppFrame<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PPP {:?}", self.protocol)?;
+        if let Some(packet) = self.control_packet() {
+            write!(f, " {:?} id {}", packet.code, packet.identifier)?;
+        }
+        write!(f, ", length {}", self.information.len())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Code {
+    ConfigureRequest,
+    ConfigureAck,
+    ConfigureNak,
+    ConfigureReject,
+    TerminateRequest,
+    TerminateAck,
+    CodeReject,
+    ProtocolReject,
+    EchoRequest,
+    EchoReply,
+    DiscardRequest,
+    Other(u8),
+}
+
+impl Code {
+    pub fn from_u8(v: u8) -> Code {
+        match v {
+            1 => Code::ConfigureRequest,
+            2 => Code::ConfigureAck,
+            3 => Code::ConfigureNak,
+            4 => Code::ConfigureReject,
+            5 => Code::TerminateRequest,
+            6 => Code::TerminateAck,
+            7 => Code::CodeReject,
+            8 => Code::ProtocolReject,
+            9 => Code::EchoRequest,
+            10 => Code::EchoReply,
+            11 => Code::DiscardRequest,
+            x => Code::Other(x),
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            Code::ConfigureRequest => 1,
+            Code::ConfigureAck => 2,
+            Code::ConfigureNak => 3,
+            Code::ConfigureReject => 4,
+            Code::TerminateRequest => 5,
+            Code::TerminateAck => 6,
+            Code::CodeReject => 7,
+            Code::ProtocolReject => 8,
+            Code::EchoRequest => 9,
+            Code::EchoReply => 10,
+            Code::DiscardRequest => 11,
+            Code::Other(x) => x,
+        }
+    }
+
+    /// Configure-Request, -Ack, -Nak and -Reject, whose data is options
+    pub fn is_configure(&self) -> bool {
+        matches!(*self, Code::ConfigureRequest | Code::ConfigureAck | Code::ConfigureNak | Code::ConfigureReject)
+    }
+}
+
+/// An LCP packet, or one of another control protocol laid out like it
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ControlPacket<'a> {
+    pub code: Code,
+    /// Matches replies to requests
+    pub identifier: u8,
+    pub data: &'a [u8],
+}
+
+/// A configuration option, before it's interpreted by the protocol it
+/// belongs to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ConfigOption<'a> {
+    pub typ: u8,
+    pub data: &'a [u8],
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum LcpOption<'a> {
+    /// Maximum receive unit
+    Mru(u16),
+    AsyncControlCharacterMap(u32),
+    /// PAP, or CHAP with the algorithm in the data, 5 for MD5
+    AuthenticationProtocol { protocol: Protocol, data: &'a [u8] },
+    QualityProtocol { protocol: u16, data: &'a [u8] },
+    MagicNumber(u32),
+    ProtocolFieldCompression,
+    AddressControlFieldCompression,
+    Other(ConfigOption<'a>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum IpcpOption<'a> {
+    /// 0x002d for Van Jacobson TCP/IP header compression
+    IpCompressionProtocol { protocol: u16, data: &'a [u8] },
+    /// Zero in a request asks the peer to assign one
+    IpAddress(Ipv4Addr),
+    PrimaryDns(Ipv4Addr),
+    PrimaryNbns(Ipv4Addr),
+    SecondaryDns(Ipv4Addr),
+    SecondaryNbns(Ipv4Addr),
+    Other(ConfigOption<'a>),
+}
+
+pub fn parse_control_packet(bs: &[u8]) -> IResult<'_, ControlPacket<'_>> {
+    context("ppp.control", control_packet)(bs)
+}
+
+// anything past the length is padding
+fn control_packet(bs: &[u8]) -> IResult<'_, ControlPacket<'_>> {
+    let (bs, code) = be_u8(bs)?;
+    let (bs, identifier) = be_u8(bs)?;
+    let (bs, length) = be_u16(bs)?;
+    let (bs, data) = take(length.saturating_sub(4))(bs)?;
+    Ok((bs, ControlPacket {
+        code: Code::from_u8(code),
+        identifier,
+        data,
+    }))
+}
+
+fn config_option(bs: &[u8]) -> IResult<'_, ConfigOption<'_>> {
+    let (bs, typ) = be_u8(bs)?;
+    let (bs, len) = be_u8(bs)?;
+    let (bs, data) = take(len.saturating_sub(2))(bs)?;
+    Ok((bs, ConfigOption { typ, data }))
+}
+
+fn ipv4_addr(bs: &[u8]) -> IResult<'_, Ipv4Addr> {
+    let (bs, addr) = be_u32(bs)?;
+    Ok((bs, Ipv4Addr::from(addr)))
+}
+
+fn lcp_option(option: ConfigOption<'_>) -> IResult<'_, LcpOption<'_>> {
+    let data = option.data;
+    let option = match option.typ {
+        1 => LcpOption::Mru(all_consuming(be_u16)(data)?.1),
+        2 => LcpOption::AsyncControlCharacterMap(all_consuming(be_u32)(data)?.1),
+        3 => {
+            let (data, protocol) = be_u16(data)?;
+            LcpOption::AuthenticationProtocol { protocol: Protocol::from_u16(protocol), data }
+        },
+        4 => {
+            let (data, protocol) = be_u16(data)?;
+            LcpOption::QualityProtocol { protocol, data }
+        },
+        5 => LcpOption::MagicNumber(all_consuming(be_u32)(data)?.1),
+        7 if data.is_empty() => LcpOption::ProtocolFieldCompression,
+        8 if data.is_empty() => LcpOption::AddressControlFieldCompression,
+        _ => LcpOption::Other(option),
+    };
+    Ok((&data[data.len()..], option))
+}
+
+fn ipcp_option(option: ConfigOption<'_>) -> IResult<'_, IpcpOption<'_>> {
+    let data = option.data;
+    let option = match option.typ {
+        2 => {
+            let (data, protocol) = be_u16(data)?;
+            IpcpOption::IpCompressionProtocol { protocol, data }
+        },
+        3 => IpcpOption::IpAddress(all_consuming(ipv4_addr)(data)?.1),
+        129 => IpcpOption::PrimaryDns(all_consuming(ipv4_addr)(data)?.1),
+        130 => IpcpOption::PrimaryNbns(all_consuming(ipv4_addr)(data)?.1),
+        131 => IpcpOption::SecondaryDns(all_consuming(ipv4_addr)(data)?.1),
+        132 => IpcpOption::SecondaryNbns(all_consuming(ipv4_addr)(data)?.1),
+        _ => IpcpOption::Other(option),
+    };
+    Ok((&data[data.len()..], option))
+}
+
+impl <'a> ControlPacket<'a> {
+    /// The options of a Configure-Request, -Ack, -Nak or -Reject
+    pub fn options(&self) -> Option<Vec<ConfigOption<'a>>> {
+        if !self.code.is_configure() {
+            return None;
+        }
+        all_consuming(many0(config_option))(self.data).ok().map(|(_, options)| options)
+    }
+
+    pub fn lcp_options(&self) -> Option<Vec<LcpOption<'a>>> {
+        self.options()?.into_iter().map(|option| lcp_option(option).ok().map(|(_, option)| option)).collect()
+    }
+
+    pub fn ipcp_options(&self) -> Option<Vec<IpcpOption<'a>>> {
+        self.options()?.into_iter().map(|option| ipcp_option(option).ok().map(|(_, option)| option)).collect()
+    }
+
+    /// The sender's magic number, at the start of LCP Echo and Discard
+    /// packets
+    pub fn magic_number(&self) -> Option<u32> {
+        match self.code {
+            Code::EchoRequest | Code::EchoReply | Code::DiscardRequest if self.data.len() >= 4 => {
+                Some(u32::from_be_bytes([self.data[0], self.data[1], self.data[2], self.data[3]]))
+            },
+            _ => None,
+        }
+    }
+}
+
+// https://tools.ietf.org/html/rfc2516
+
+/// The header of a PPPoE packet, in front of a PPP frame in the session
+/// stage or tags in the discovery stage
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct PppoeHeader<'a> {
+    pub version: u8,
+    pub typ: u8,
+    /// Zero in the session stage, or PADI 0x09, PADO 0x07, PADR 0x19,
+    /// PADS 0x65 and PADT 0xa7 in discovery
+    pub code: u8,
+    pub session_id: u16,
+    pub payload: &'a [u8],
+}
+
+/// Parses a PPPoE header and takes its payload, leaving any Ethernet
+/// padding after it
+pub fn parse_pppoe_header(bs: &[u8]) -> IResult<'_, PppoeHeader<'_>> {
+    context("pppoe", pppoe_header)(bs)
+}
+
+fn pppoe_header(bs: &[u8]) -> IResult<'_, PppoeHeader<'_>> {
+    let (bs, version_type) = be_u8(bs)?;
+    let (bs, code) = be_u8(bs)?;
+    let (bs, session_id) = be_u16(bs)?;
+    let (bs, length) = be_u16(bs)?;
+    let (bs, payload) = take(length)(bs)?;
+    Ok((bs, PppoeHeader {
+        version: version_type >> 4,
+        typ: version_type & 0xf,
+        code,
+        session_id,
+        payload,
+    }))
+}
+
+impl <'a> PppoeHeader<'a> {
+    /// The PPP frame of a session stage packet
+    pub fn ppp_frame(&self) -> Option<PppFrame<'a>> {
+        if self.code != 0 {
+            return None;
+        }
+        ppp_frame(self.payload).ok().map(|(_, frame)| frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+    use ipv4::{Header, Ipv4Packet, Ipv4Protocol};
+    use ToBytes;
+
+    #[test]
+    fn test_lcp() {
+        // a PPPoE session packet with an LCP Configure-Request for an MRU
+        // of 1492, PAP and a magic number
+        let packet = [
+            0x11, 0x00, 0x00, 0x2a, 0x00, 0x16,
+            0xc0, 0x21, 0x01, 0x01, 0x00, 0x14,
+            0x01, 0x04, 0x05, 0xd4,
+            0x03, 0x04, 0xc0, 0x23,
+            0x05, 0x06, 0x12, 0x34, 0x56, 0x78,
+            0x07, 0x02,
+            0x00, 0x00,
+        ];
+        let (rest, pppoe) = parse_pppoe_header(&packet).unwrap();
+        assert_eq!(rest, &[0x00, 0x00]);
+        assert_eq!((pppoe.version, pppoe.typ, pppoe.session_id), (1, 1, 0x2a));
+        let frame = pppoe.ppp_frame().unwrap();
+        assert_eq!(frame.protocol, Protocol::Lcp);
+        assert_eq!(frame.to_string(), "PPP Lcp ConfigureRequest id 1, length 20");
+        let lcp = frame.control_packet().unwrap();
+        assert_eq!(lcp.lcp_options().unwrap(), vec![
+            LcpOption::Mru(1492),
+            LcpOption::AuthenticationProtocol { protocol: Protocol::Pap, data: &[] },
+            LcpOption::MagicNumber(0x12345678),
+            LcpOption::ProtocolFieldCompression,
+        ]);
+        assert_eq!(lcp.magic_number(), None);
+
+        // an echo request, with address and control fields as L2TP sends it
+        let (_, frame) = parse_ppp_frame(&[0xff, 0x03, 0xc0, 0x21, 0x09, 0x07, 0x00, 0x08, 0x12, 0x34, 0x56, 0x78]).unwrap();
+        let echo = frame.control_packet().unwrap();
+        assert_eq!((echo.code, echo.magic_number()), (Code::EchoRequest, Some(0x12345678)));
+        assert_eq!(echo.options(), None);
+
+        // IPv4 with a compressed protocol field, and an option that's too
+        // long for its packet
+        let header = Header::new(Ipv4Protocol::Udp, "192.0.2.1".parse().unwrap(), "192.0.2.2".parse().unwrap());
+        let mut bytes = vec![0x21];
+        bytes.extend_from_slice(&Ipv4Packet::new(header, b"").to_bytes());
+        let (_, frame) = parse_ppp_frame(&bytes).unwrap();
+        assert_eq!(frame.protocol, Protocol::Ipv4);
+        assert_eq!(frame.ip_packet().unwrap().src_ip(), "192.0.2.1".parse::<IpAddr>().unwrap());
+        assert_eq!(frame.control_packet(), None);
+        let (_, frame) = parse_ppp_frame(&[0xc0, 0x21, 0x01, 0x02, 0x00, 0x08, 0x01, 0x06, 0x05, 0xd4]).unwrap();
+        assert_eq!(frame.control_packet().unwrap().lcp_options(), None);
+    }
+
+    #[test]
+    fn test_ipcp() {
+        // a Configure-Nak assigning an address and DNS servers
+        let frame = [
+            0x80, 0x21, 0x03, 0x02, 0x00, 0x16,
+            0x03, 0x06, 0xc0, 0x00, 0x02, 0x07,
+            0x81, 0x06, 0xc0, 0x00, 0x02, 0x35,
+            0x83, 0x06, 0xc0, 0x00, 0x02, 0x36,
+        ];
+        let (_, frame) = parse_ppp_frame(&frame).unwrap();
+        let ipcp = frame.control_packet().unwrap();
+        assert_eq!((ipcp.code, ipcp.identifier), (Code::ConfigureNak, 2));
+        assert_eq!(ipcp.ipcp_options().unwrap(), vec![
+            IpcpOption::IpAddress(Ipv4Addr::new(192, 0, 2, 7)),
+            IpcpOption::PrimaryDns(Ipv4Addr::new(192, 0, 2, 53)),
+            IpcpOption::SecondaryDns(Ipv4Addr::new(192, 0, 2, 54)),
+        ]);
+    }
+}
@@ -0,0 +1,116 @@
+// #[pyfunction] converts PyResult errors into themselves, which clippy
+// flags on every function returning one
+#![allow(clippy::useless_conversion)]
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+
+use dissect::{self, LinkType};
+use dns::{self, Mode};
+use json::{self, Value};
+use pcapng::Reader;
+
+fn to_py(py: Python, value: &Value) -> PyResult<PyObject> {
+    Ok(match *value {
+        Value::Null => py.None(),
+        Value::Bool(b) => b.into_py(py),
+        Value::Number(n) => n.into_py(py),
+        Value::String(ref s) => s.into_py(py),
+        Value::Array(ref values) => {
+            let list = PyList::empty_bound(py);
+            for value in values {
+                list.append(to_py(py, value)?)?;
+            }
+            list.into_py(py)
+        },
+        Value::Object(ref fields) => {
+            let dict = PyDict::new_bound(py);
+            for (key, value) in fields {
+                dict.set_item(key, to_py(py, value)?)?;
+            }
+            dict.into_py(py)
+        },
+    })
+}
+
+/// Dissects one packet, with `link_type` a LINKTYPE_ value, into nested
+/// dicts shaped like `tshark -T json` output
+#[pyfunction]
+#[pyo3(name = "dissect", signature = (data, link_type = 1))]
+fn dissect_packet(py: Python, data: &[u8], link_type: u32) -> PyResult<PyObject> {
+    to_py(py, &json::to_json(&dissect::parse_packet(LinkType::from_u32(link_type), data)))
+}
+
+/// A one line summary of a packet
+#[pyfunction]
+#[pyo3(signature = (data, link_type = 1))]
+fn summary(data: &[u8], link_type: u32) -> String {
+    dissect::parse_packet(LinkType::from_u32(link_type), data).to_string()
+}
+
+/// Parses a bare DNS message, raising ValueError if it doesn't parse
+#[pyfunction]
+#[pyo3(signature = (data, lenient = false))]
+fn parse_dns(py: Python, data: &[u8], lenient: bool) -> PyResult<PyObject> {
+    let mode = if lenient { Mode::Lenient } else { Mode::Strict };
+    match dns::parse_dns_message_full_with(mode, data) {
+        Ok((_, msg)) => to_py(py, &json::dns_to_json(&msg)),
+        Err(e) => Err(PyValueError::new_err(format!("not a DNS message: {:?}", e))),
+    }
+}
+
+/// Dissects every packet in a pcapng capture into a list of dicts, each
+/// with the packet's `timestamp` in seconds since the epoch, its `length`
+/// on the wire, its `interface`, and the dissection under `packet`
+#[pyfunction]
+fn read_pcapng(py: Python, data: &[u8]) -> PyResult<PyObject> {
+    let reader = Reader::new(data).ok_or_else(|| PyValueError::new_err("not a pcapng capture"))?;
+    let packets = PyList::empty_bound(py);
+    for captured in reader {
+        let entry = PyDict::new_bound(py);
+        entry.set_item("timestamp", captured.timestamp.as_secs_f64())?;
+        entry.set_item("length", captured.original_len)?;
+        entry.set_item("interface", captured.interface_id)?;
+        let packet = dissect::parse_packet(captured.link_type, captured.data);
+        entry.set_item("packet", to_py(py, &json::to_json(&packet))?)?;
+        packets.append(entry)?;
+    }
+    Ok(packets.into_py(py))
+}
+
+#[pymodule]
+fn blosh(m: &Bound<PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(self::dissect_packet, m)?)?;
+    m.add_function(wrap_pyfunction!(self::summary, m)?)?;
+    m.add_function(wrap_pyfunction!(self::parse_dns, m)?)?;
+    m.add_function(wrap_pyfunction!(self::read_pcapng, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use testgen::{self, Endpoint};
+
+    #[test]
+    fn test_python() {
+        let (query, _) = testgen::dns_exchange(&Endpoint::client(), &Endpoint::server(), 1, "example.com",
+                                               Ipv4Addr::new(203, 0, 113, 1));
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new_bound(py, "blosh").unwrap();
+            blosh(&module).unwrap();
+            let packet = module.getattr("dissect").unwrap().call1((&query[..],)).unwrap();
+            let ttl: u8 = packet.get_item("_source").unwrap()
+                .get_item("layers").unwrap()
+                .get_item("ip").unwrap()
+                .get_item("ip.ttl").unwrap()
+                .extract().unwrap();
+            assert_eq!(ttl, 64);
+            let err = module.getattr("parse_dns").unwrap().call1((&b"\x00"[..],)).unwrap_err();
+            assert!(err.is_instance_of::<PyValueError>(py));
+        });
+    }
+}
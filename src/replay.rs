@@ -0,0 +1,191 @@
+use std::convert::Infallible;
+#[cfg(all(feature = "live", target_os = "linux"))]
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(all(feature = "live", target_os = "linux"))]
+use dissect::LinkType;
+#[cfg(all(feature = "live", target_os = "linux"))]
+use live::Injector;
+use sample::Sample;
+
+/// How fast a `Replayer` hands out packets
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Speed {
+    // the gaps between packets as they were captured
+    Original,
+    // the captured gaps divided by a factor, so 2.0 is twice as fast
+    Scaled(f64),
+    // evenly spaced, whatever the timestamps say
+    PacketsPerSec(u32),
+    // as fast as the callback takes them
+    Unthrottled,
+}
+
+impl Speed {
+    /// How long after the replay starts the `index`th packet is due, given
+    /// it was captured `offset` after the first one. A factor or rate that
+    /// isn't positive doesn't hold anything back, and a slowed down gap too
+    /// long for a `Duration` is `Duration::MAX`.
+    pub fn due(self, index: u64, offset: Duration) -> Duration {
+        match self {
+            Speed::Original => offset,
+            Speed::Scaled(factor) if factor > 0.0 && factor.is_finite() => {
+                Duration::try_from_secs_f64(offset.as_secs_f64()/factor).unwrap_or(Duration::MAX)
+            },
+            Speed::PacketsPerSec(rate) if rate > 0 => {
+                Duration::from_nanos((index as u128*1_000_000_000/rate as u128) as u64)
+            },
+            _ => Duration::from_secs(0),
+        }
+    }
+}
+
+/// What a replay got through
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReplayStats {
+    pub packets: u64,
+    // captured bytes, which can be less than on the wire
+    pub bytes: u64,
+    // packets that couldn't be sent, like non-Ethernet ones to an Injector
+    pub skipped: u64,
+    pub elapsed: Duration,
+    // furthest behind schedule a packet went out, which is how far the
+    // receiving end fell behind the requested rate
+    pub max_lag: Duration,
+}
+
+/// Plays packets back in real time, for load testing whatever's
+/// consuming them. Works on anything the samplers do, so a pcap or pcapng
+/// `Reader` can be thinned out with `SampleExt` before it's replayed.
+pub struct Replayer<I> {
+    iter: I,
+    speed: Speed,
+}
+
+impl <I> Replayer<I> where I: Iterator, I::Item: Sample {
+    pub fn new(iter: I, speed: Speed) -> Replayer<I> {
+        Replayer {
+            iter,
+            speed,
+        }
+    }
+
+    /// Hands each packet to `f` when it's due, blocking until the last
+    /// one has gone
+    pub fn run<F>(self, mut f: F) -> ReplayStats where F: FnMut(&I::Item) {
+        let stats = self.replay(|packet| {
+            f(packet);
+            Ok::<_, Infallible>(true)
+        });
+        match stats {
+            Ok(stats) => stats,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Sends each Ethernet frame out of `injector` when it's due. Packets
+    /// with other link types are skipped, and the replay stops at the
+    /// first error from the socket.
+    #[cfg(all(feature = "live", target_os = "linux"))]
+    pub fn inject(self, injector: &Injector) -> io::Result<ReplayStats> {
+        self.replay(|packet| {
+            if packet.link_type() != LinkType::Ethernet {
+                return Ok(false);
+            }
+            injector.send(packet.data()).map(|_| true)
+        })
+    }
+
+    // f says whether it used the packet
+    fn replay<F, E>(self, mut f: F) -> Result<ReplayStats, E> where F: FnMut(&I::Item) -> Result<bool, E> {
+        let start = Instant::now();
+        let mut stats = ReplayStats::default();
+        let mut first = None;
+        for (index, packet) in self.iter.enumerate() {
+            let first = *first.get_or_insert(packet.timestamp());
+            // timestamps going backwards just make a packet due straight away
+            let offset = packet.timestamp().saturating_duration_since(first);
+            let due = self.speed.due(index as u64, offset);
+            let elapsed = start.elapsed();
+            if due > elapsed {
+                thread::sleep(due - elapsed);
+            } else {
+                stats.max_lag = stats.max_lag.max(elapsed - due);
+            }
+            if f(&packet)? {
+                stats.packets += 1;
+                stats.bytes += packet.data().len() as u64;
+            } else {
+                stats.skipped += 1;
+            }
+        }
+        stats.elapsed = start.elapsed();
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dissect::LinkType;
+    use pcapng::OwnedCapturedPacket;
+    use Timestamp;
+
+    fn packet(ms: u64) -> OwnedCapturedPacket {
+        OwnedCapturedPacket {
+            interface_id: 0,
+            link_type: LinkType::Ethernet,
+            timestamp: Duration::from_millis(1000 + ms).into(),
+            original_len: 60,
+            fcs_len: None,
+            data: vec![0; 60],
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        let gap = Duration::from_millis(100);
+        assert_eq!(Speed::Original.due(1, gap), gap);
+        assert_eq!(Speed::Scaled(4.0).due(1, gap), Duration::from_millis(25));
+        assert_eq!(Speed::PacketsPerSec(50).due(3, gap), Duration::from_millis(60));
+        assert_eq!(Speed::Scaled(0.0).due(1, gap), Duration::from_secs(0));
+        assert_eq!(Speed::Scaled(0.5).due(1, Duration::from_secs(u64::MAX)), Duration::MAX);
+        assert_eq!(Speed::Unthrottled.due(1, gap), Duration::from_secs(0));
+
+        let packets: Vec<_> = [0, 100, 200].iter().map(|&ms| packet(ms)).collect();
+        let mut seen: Vec<Duration> = vec![];
+        let stats = Replayer::new(packets.into_iter(), Speed::Scaled(100.0))
+            .run(|p| seen.push(p.timestamp.into()));
+        assert_eq!(seen, vec![Duration::from_millis(1000), Duration::from_millis(1100), Duration::from_millis(1200)]);
+        assert_eq!((stats.packets, stats.bytes, stats.skipped), (3, 180, 0));
+        // the last packet was due 2ms in
+        assert!(stats.elapsed >= Duration::from_millis(2));
+    }
+
+    #[test]
+    fn test_replay_pcap() {
+        use pcap::Reader;
+
+        let capture = [
+            // file header, little endian, nanoseconds, ethernet
+            0x4d, 0x3c, 0xb2, 0xa1, 0x02, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xff, 0xff, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+            // at 10s and 10.05s, 2 of 60 bytes each
+            0x0a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x00, 0x00,
+            0xab, 0xcd,
+            0x0a, 0x00, 0x00, 0x00, 0x80, 0xf0, 0xfa, 0x02, 0x02, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x00, 0x00,
+            0xef, 0x01,
+        ];
+        let mut seen = vec![];
+        let stats = Replayer::new(Reader::new(&capture).unwrap(), Speed::Scaled(10.0))
+            .run(|p| seen.push((p.timestamp, p.data.to_vec())));
+        assert_eq!(seen, vec![
+            (Timestamp::new(10, 0), vec![0xab, 0xcd]),
+            (Timestamp::new(10, 50_000_000), vec![0xef, 0x01]),
+        ]);
+        assert_eq!((stats.packets, stats.bytes, stats.skipped), (2, 4, 0));
+        assert!(stats.elapsed >= Duration::from_millis(5));
+    }
+}
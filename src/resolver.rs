@@ -0,0 +1,227 @@
+use std::error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use nom::combinator::all_consuming;
+
+use dns::{self, Message, Mode, OwnedMessage, Qtype, Truncated, QR};
+use {IntoOwned, ToBytes};
+
+// big enough for any UDP response, EDNS or not
+const MAX_UDP_LEN: usize = 65535;
+
+#[derive(Debug)]
+pub enum ResolveError {
+    Io(io::Error),
+    // no matching response came back in time, after every retry
+    Timeout,
+    // the response over TCP didn't parse or didn't answer the query
+    Malformed,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ResolveError::Io(ref e) => write!(f, "{}", e),
+            ResolveError::Timeout => write!(f, "timed out waiting for a response"),
+            ResolveError::Malformed => write!(f, "malformed response"),
+        }
+    }
+}
+
+impl error::Error for ResolveError {}
+
+impl From<io::Error> for ResolveError {
+    fn from(e: io::Error) -> ResolveError {
+        match e.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => ResolveError::Timeout,
+            _ => ResolveError::Io(e),
+        }
+    }
+}
+
+/// A blocking stub resolver that asks one recursive server. Queries go
+/// out over UDP, and are asked again over TCP when the answer comes back
+/// truncated. Responses are only taken if their ID and question match the
+/// query, so stray or spoofed packets are skipped rather than returned.
+pub struct Resolver {
+    server: SocketAddr,
+    timeout: Duration,
+    retries: u32,
+    mode: Mode,
+    // xorshift state for query IDs; not cryptographically random, so this
+    // shouldn't be relied on against off path spoofing
+    id_state: u32,
+}
+
+impl Resolver {
+    pub fn new(server: SocketAddr) -> Resolver {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().subsec_nanos();
+        Resolver {
+            server,
+            timeout: Duration::from_secs(2),
+            retries: 2,
+            mode: Mode::Strict,
+            id_state: seed | 1,
+        }
+    }
+
+    /// How long to wait for each attempt, 2 seconds by default
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// How many more times to send over UDP after a timeout, 2 by default
+    pub fn set_retries(&mut self, retries: u32) {
+        self.retries = retries;
+    }
+
+    /// How strictly responses are parsed, `Mode::Strict` by default
+    pub fn set_dns_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    /// Looks up `name`, returning the whole response, whatever its rcode
+    pub fn query(&mut self, name: &str, qtype: Qtype) -> Result<OwnedMessage, ResolveError> {
+        let id = self.next_id();
+        self.send(&Message::query(id, name, qtype))
+    }
+
+    /// Sends a message as it is and waits for the response to it
+    pub fn send(&mut self, msg: &Message) -> Result<OwnedMessage, ResolveError> {
+        let response = self.send_udp(msg)?;
+        match response.header.truncated() {
+            Some(truncated) => self.send_tcp(truncated, msg),
+            None => Ok(response),
+        }
+    }
+
+    fn next_id(&mut self) -> u16 {
+        let mut x = self.id_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.id_state = x;
+        x as u16
+    }
+
+    fn parse(&self, query: &Message, bs: &[u8]) -> Option<OwnedMessage> {
+        let (_, response) = all_consuming(|bs| dns::parse_dns_message_full_with(self.mode, bs))(bs).ok()?;
+        let matches = response.header.qr == QR::Response && response.header.id == query.header.id
+            && response.questions == query.questions;
+        if matches {
+            Some(response.into_owned())
+        } else {
+            None
+        }
+    }
+
+    fn send_udp(&self, msg: &Message) -> Result<OwnedMessage, ResolveError> {
+        let local: SocketAddr = if self.server.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(self.server)?;
+        let query = msg.to_bytes();
+        let mut buf = vec![0; MAX_UDP_LEN];
+        for _ in 0..=self.retries {
+            socket.send(&query)?;
+            let deadline = Instant::now() + self.timeout;
+            loop {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left == Duration::from_secs(0) {
+                    break;
+                }
+                socket.set_read_timeout(Some(left))?;
+                let len = match socket.recv(&mut buf) {
+                    Ok(len) => len,
+                    Err(e) => match ResolveError::from(e) {
+                        ResolveError::Timeout => break,
+                        e => return Err(e),
+                    },
+                };
+                if let Some(response) = self.parse(msg, &buf[..len]) {
+                    return Ok(response);
+                }
+            }
+        }
+        Err(ResolveError::Timeout)
+    }
+
+    fn send_tcp(&self, truncated: Truncated, msg: &Message) -> Result<OwnedMessage, ResolveError> {
+        let out = truncated.tcp_retry(msg).ok_or(ResolveError::Malformed)?;
+        let mut stream = TcpStream::connect_timeout(&self.server, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        stream.write_all(&out)?;
+
+        let mut len = [0; 2];
+        stream.read_exact(&mut len)?;
+        let mut buf = vec![0; u16::from_be_bytes(len) as usize];
+        stream.read_exact(&mut buf)?;
+        self.parse(msg, &buf).ok_or(ResolveError::Malformed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use dns::{Class, OwnedRdata, Rdata, ResourceRecord, Type};
+
+    // answers over UDP with a stray response and then a truncated one,
+    // and properly over TCP on the same port
+    fn server() -> SocketAddr {
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = udp.local_addr().unwrap();
+        let tcp = TcpListener::bind(addr).unwrap();
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            let (len, peer) = udp.recv_from(&mut buf).unwrap();
+            let (_, query) = dns::parse_dns_message_full(&buf[..len]).unwrap();
+            let mut stray = query.clone();
+            stray.header.qr = QR::Response;
+            stray.header.id = stray.header.id.wrapping_add(1);
+            udp.send_to(&stray.to_bytes(), peer).unwrap();
+            let mut truncated = query.clone();
+            truncated.header.qr = QR::Response;
+            truncated.header.tc = true;
+            udp.send_to(&truncated.to_bytes(), peer).unwrap();
+
+            let (mut stream, _) = tcp.accept().unwrap();
+            let mut len = [0; 2];
+            stream.read_exact(&mut len).unwrap();
+            let mut buf = vec![0; u16::from_be_bytes(len) as usize];
+            stream.read_exact(&mut buf).unwrap();
+            let (_, query) = dns::parse_dns_message_full(&buf).unwrap();
+            let mut response = query.clone();
+            response.header.qr = QR::Response;
+            response.answers.push(ResourceRecord {
+                name: query.questions[0].qname.clone(),
+                typ: Type::A,
+                class: Class::IN,
+                ttl: 60,
+                rdata: Rdata::A(&[192, 0, 2, 1]),
+            });
+            let bs = response.to_bytes();
+            stream.write_all(&(bs.len() as u16).to_be_bytes()).unwrap();
+            stream.write_all(&bs).unwrap();
+        });
+        addr
+    }
+
+    #[test]
+    fn test_resolver() {
+        let mut resolver = Resolver::new(server());
+        resolver.set_timeout(Duration::from_secs(5));
+        let response = resolver.query("www.example.com.", Qtype::Type(Type::A)).unwrap();
+        assert!(!response.header.tc);
+        assert_eq!(response.answers.len(), 1);
+        assert_eq!(response.answers[0].rdata, OwnedRdata::A(vec![192, 0, 2, 1]));
+    }
+}
@@ -0,0 +1,208 @@
+use std::fmt;
+
+use nom::bytes::complete::take;
+use nom::combinator::{rest, verify};
+use nom::error::{context, ErrorKind};
+use nom::multi::count;
+use nom::number::complete::{be_u8, be_u16, be_u32};
+
+use error::{IResult, ParseError};
+use IntoOwned;
+
+// https://tools.ietf.org/html/rfc3550#section-5.1
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct RtpPacket<'a> {
+    pub header: RtpHeader<'a>,
+    // without any padding
+    pub payload: &'a [u8],
+    // bytes of padding after the payload, counting the length byte
+    pub padding: u8,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct RtpHeader<'a> {
+    pub version: u8,
+    pub marker: bool,
+    pub payload_type: u8,
+    pub seq: u16,
+    pub timestamp: u32,
+    pub ssrc: u32,
+    pub csrcs: Vec<u32>,
+    pub extension: Option<HeaderExtension<'a>>,
+}
+
+/// Profile specific data after the CSRCs, like the one and two byte header
+/// extensions of RFC 8285
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct HeaderExtension<'a> {
+    pub profile: u16,
+    pub data: &'a [u8],
+}
+
+/// Parses an RTP packet, usually a whole UDP payload. RTP doesn't have a
+/// well known port, so it isn't dissected by default; register
+/// `parse_rtp_owned` with `Registry::register_udp_port` for the ports in use.
+pub fn parse_rtp_packet(bs: &[u8]) -> IResult<'_, RtpPacket<'_>> {
+    context("rtp", rtp_packet)(bs)
+}
+
+fn rtp_packet(bs: &[u8]) -> IResult<'_, RtpPacket<'_>> {
+    let (bs, first) = verify(be_u8, |&b| b >> 6 == 2)(bs)?;
+    let (bs, second) = be_u8(bs)?;
+    let (bs, seq) = be_u16(bs)?;
+    let (bs, timestamp) = be_u32(bs)?;
+    let (bs, ssrc) = be_u32(bs)?;
+    let (bs, csrcs) = context("rtp.csrc", count(be_u32, (first & 0x0f) as usize))(bs)?;
+    let (bs, extension) = if first & 0x10 != 0 {
+        let (bs, extension) = context("rtp.ext", header_extension)(bs)?;
+        (bs, Some(extension))
+    } else {
+        (bs, None)
+    };
+    let (bs, body) = rest(bs)?;
+    let padding = if first & 0x20 != 0 {
+        context("rtp.padding", padding_len)(body)?.1
+    } else {
+        0
+    };
+    Ok((bs, RtpPacket {
+        header: RtpHeader {
+            version: first >> 6,
+            marker: second & 0x80 != 0,
+            payload_type: second & 0x7f,
+            seq,
+            timestamp,
+            ssrc,
+            csrcs,
+            extension,
+        },
+        payload: &body[..body.len() - padding as usize],
+        padding,
+    }))
+}
+
+// the last byte counts the padding, itself included
+fn padding_len(bs: &[u8]) -> IResult<'_, u8> {
+    match bs.last() {
+        Some(&n) if n > 0 && n as usize <= bs.len() => Ok((bs, n)),
+        _ => Err(nom::Err::Error(ParseError::new(bs, ErrorKind::LengthValue))),
+    }
+}
+
+fn header_extension(bs: &[u8]) -> IResult<'_, HeaderExtension<'_>> {
+    let (bs, profile) = be_u16(bs)?;
+    // in 32 bit words
+    let (bs, len) = be_u16(bs)?;
+    let (bs, data) = take(4*len as usize)(bs)?;
+    Ok((bs, HeaderExtension {
+        profile,
+        data,
+    }))
+}
+
+/// Like `parse_rtp_packet`, but owned, for use as a `Registry` dissector
+pub fn parse_rtp_owned(bs: &[u8]) -> Option<OwnedRtpPacket> {
+    parse_rtp_packet(bs).ok().map(|(_, packet)| packet.into_owned())
+}
+
+impl <'a> fmt::Display for RtpPacket<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let h = &self.header;
+        write!(f, "RTP PT={}, SSRC=0x{:08x}, Seq={}, Time={}", h.payload_type, h.ssrc, h.seq, h.timestamp)?;
+        if h.marker {
+            write!(f, ", Mark")?;
+        }
+        write!(f, ", length {}", self.payload.len())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedRtpPacket {
+    pub header: OwnedRtpHeader,
+    pub payload: Vec<u8>,
+    pub padding: u8,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedRtpHeader {
+    pub version: u8,
+    pub marker: bool,
+    pub payload_type: u8,
+    pub seq: u16,
+    pub timestamp: u32,
+    pub ssrc: u32,
+    pub csrcs: Vec<u32>,
+    pub extension: Option<OwnedHeaderExtension>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedHeaderExtension {
+    pub profile: u16,
+    pub data: Vec<u8>,
+}
+
+impl <'a> IntoOwned for RtpPacket<'a> {
+    type Owned = OwnedRtpPacket;
+
+    fn into_owned(self) -> OwnedRtpPacket {
+        let h = self.header;
+        OwnedRtpPacket {
+            header: OwnedRtpHeader {
+                version: h.version,
+                marker: h.marker,
+                payload_type: h.payload_type,
+                seq: h.seq,
+                timestamp: h.timestamp,
+                ssrc: h.ssrc,
+                csrcs: h.csrcs,
+                extension: h.extension.map(|ext| OwnedHeaderExtension {
+                    profile: ext.profile,
+                    data: ext.data.into_owned(),
+                }),
+            },
+            payload: self.payload.into_owned(),
+            padding: self.padding,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rtp_packet() {
+        let packet = [
+            // V=2, P, X, CC=1, M, PT=96
+            0xb1, 0xe0, 0x12, 0x34, 0x00, 0x01, 0x5f, 0x90,
+            0xde, 0xad, 0xbe, 0xef, 0x01, 0x02, 0x03, 0x04,
+            // one byte header extension, one word
+            0xbe, 0xde, 0x00, 0x01, 0x10, 0xaa, 0x00, 0x00,
+            0x68, 0x69, 0x00, 0x00, 0x03,
+        ];
+        let (rest, rtp) = parse_rtp_packet(&packet).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(rtp.header.version, 2);
+        assert!(rtp.header.marker);
+        assert_eq!(rtp.header.payload_type, 96);
+        assert_eq!(rtp.header.seq, 0x1234);
+        assert_eq!(rtp.header.timestamp, 90000);
+        assert_eq!(rtp.header.ssrc, 0xdeadbeef);
+        assert_eq!(rtp.header.csrcs, vec![0x01020304]);
+        assert_eq!(rtp.header.extension, Some(HeaderExtension { profile: 0xbede, data: &[0x10, 0xaa, 0x00, 0x00] }));
+        assert_eq!(rtp.payload, b"hi");
+        assert_eq!(rtp.padding, 3);
+        assert_eq!(format!("{}", rtp), "RTP PT=96, SSRC=0xdeadbeef, Seq=4660, Time=90000, Mark, length 2");
+
+        // RTP version 1 isn't RTP as we know it
+        let mut old = packet;
+        old[0] = 0x40;
+        assert!(parse_rtp_packet(&old).is_err());
+    }
+}
@@ -0,0 +1,357 @@
+use std::net::IpAddr;
+
+use dissect::LinkType;
+use ethernet::{self, EtherType};
+use flow::FlowKey;
+use pcapng::{CapturedPacket, OwnedCapturedPacket};
+use {parse_ip_packet, Timestamp};
+
+/// What the samplers need to know about a captured packet
+pub trait Sample {
+    fn timestamp(&self) -> Timestamp;
+    // length on the wire, for byte rates
+    fn wire_len(&self) -> usize;
+    fn link_type(&self) -> LinkType;
+    fn data(&self) -> &[u8];
+
+    /// The flow the packet belongs to, oriented as it was travelling. Only
+    /// the link, IP and transport headers are parsed, as samplers run on
+    /// every packet.
+    fn flow_key(&self) -> Option<FlowKey> {
+        let ip = match self.link_type() {
+            LinkType::Ethernet => match ethernet::parse_eth2_packet(self.data()) {
+                Ok((_, ref eth)) if eth.ethertype == EtherType::Ipv4 || eth.ethertype == EtherType::Ipv6 => eth.body,
+                _ => return None,
+            },
+            LinkType::Raw | LinkType::Ipv4 | LinkType::Ipv6 => self.data(),
+            LinkType::Other(_) => return None,
+        };
+        parse_ip_packet(ip).ok()?.flow_key()
+    }
+}
+
+impl <'a> Sample for CapturedPacket<'a> {
+    fn timestamp(&self) -> Timestamp {
+        self.timestamp
+    }
+
+    fn wire_len(&self) -> usize {
+        self.original_len as usize
+    }
+
+    fn link_type(&self) -> LinkType {
+        self.link_type
+    }
+
+    fn data(&self) -> &[u8] {
+        self.data
+    }
+}
+
+impl Sample for OwnedCapturedPacket {
+    fn timestamp(&self) -> Timestamp {
+        self.timestamp
+    }
+
+    fn wire_len(&self) -> usize {
+        self.original_len as usize
+    }
+
+    fn link_type(&self) -> LinkType {
+        self.link_type
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl Sample for ::shared::SharedCapturedPacket {
+    fn timestamp(&self) -> Timestamp {
+        self.timestamp
+    }
+
+    fn wire_len(&self) -> usize {
+        self.original_len as usize
+    }
+
+    fn link_type(&self) -> LinkType {
+        self.link_type
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[cfg(all(feature = "live", target_os = "linux"))]
+impl Sample for ::live::Frame {
+    fn timestamp(&self) -> Timestamp {
+        self.timestamp
+    }
+
+    fn wire_len(&self) -> usize {
+        self.original_len
+    }
+
+    fn link_type(&self) -> LinkType {
+        self.link_type
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Keeps every `n`th packet, starting with the first
+pub struct Every<I> {
+    iter: I,
+    n: u64,
+    seen: u64,
+}
+
+impl <I: Iterator> Every<I> {
+    pub fn new(iter: I, n: u64) -> Every<I> {
+        Every {
+            iter,
+            n: n.max(1),
+            seen: 0,
+        }
+    }
+}
+
+impl <I: Iterator> Iterator for Every<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        loop {
+            let item = self.iter.next()?;
+            self.seen += 1;
+            if (self.seen - 1).is_multiple_of(self.n) {
+                return Some(item);
+            }
+        }
+    }
+}
+
+/// What `RateLimited` caps, per second of capture time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Rate {
+    Packets(u64),
+    Bytes(u64),
+}
+
+/// Drops packets beyond a packet or byte rate, measured against capture
+/// timestamps so a saved capture is cut down the same way the live
+/// traffic would have been. Bursts of up to a second's worth are let
+/// through.
+pub struct RateLimited<I> {
+    iter: I,
+    rate: Rate,
+    // what can still be let through, in packets or bytes times 10^9 so
+    // nanoseconds of credit add up exactly
+    allowance: u128,
+    last: Option<Timestamp>,
+    dropped: u64,
+}
+
+impl <I> RateLimited<I> where I: Iterator, I::Item: Sample {
+    pub fn new(iter: I, rate: Rate) -> RateLimited<I> {
+        RateLimited {
+            iter,
+            rate,
+            allowance: 0,
+            last: None,
+            dropped: 0,
+        }
+    }
+
+    /// How many packets have been dropped so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+impl <I> Iterator for RateLimited<I> where I: Iterator, I::Item: Sample {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        const NANOS: u128 = 1_000_000_000;
+        let per_sec = match self.rate {
+            Rate::Packets(n) | Rate::Bytes(n) => n as u128,
+        };
+        loop {
+            let item = self.iter.next()?;
+            let now = item.timestamp();
+            let elapsed = match self.last {
+                // a full bucket to start with
+                None => NANOS,
+                Some(last) => now.saturating_duration_since(last).as_nanos(),
+            };
+            if self.last.is_none_or(|last| now > last) {
+                self.last = Some(now);
+            }
+            // a gap of centuries at terabytes a second still fills the bucket
+            self.allowance = self.allowance.saturating_add(elapsed.saturating_mul(per_sec)).min(per_sec*NANOS);
+            let cost = match self.rate {
+                Rate::Packets(_) => NANOS,
+                Rate::Bytes(_) => item.wire_len() as u128 * NANOS,
+            };
+            if cost <= self.allowance {
+                self.allowance -= cost;
+                return Some(item);
+            }
+            self.dropped += 1;
+        }
+    }
+}
+
+/// Keeps whole flows, roughly one in `n` of them, so that what's left can
+/// still be followed connection by connection. Both directions of a flow
+/// are kept or dropped together, and the choice doesn't depend on what was
+/// seen before, so separate captures of the same traffic agree. Packets
+/// that aren't part of a flow are sampled one in `n` like `Every` does.
+pub struct FlowSampled<I> {
+    iter: I,
+    n: u64,
+    other: u64,
+}
+
+impl <I> FlowSampled<I> where I: Iterator, I::Item: Sample {
+    pub fn new(iter: I, n: u64) -> FlowSampled<I> {
+        FlowSampled {
+            iter,
+            n: n.max(1),
+            other: 0,
+        }
+    }
+
+    fn keep(&mut self, item: &I::Item) -> bool {
+        match item.flow_key() {
+            Some(key) => {
+                flow_hash(&key.canonical().0).is_multiple_of(self.n)
+            },
+            None => {
+                self.other += 1;
+                (self.other - 1).is_multiple_of(self.n)
+            },
+        }
+    }
+}
+
+// FNV-1a over the addresses, ports and protocol, which unlike std's
+// hashers is pinned down, so every build picks the same flows
+fn flow_hash(key: &FlowKey) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut feed = |bs: &[u8]| {
+        for &b in bs {
+            hash = (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    for ip in &[key.src_ip, key.dst_ip] {
+        match *ip {
+            IpAddr::V4(ip) => feed(&ip.octets()),
+            IpAddr::V6(ip) => feed(&ip.octets()),
+        }
+    }
+    feed(&key.src_port.to_be_bytes());
+    feed(&key.dst_port.to_be_bytes());
+    feed(&[key.proto.to_u8()]);
+    // the low bits only depend on the low bits of each byte, so fold the
+    // rest in for the modulo
+    hash ^ (hash >> 32)
+}
+
+impl <I> Iterator for FlowSampled<I> where I: Iterator, I::Item: Sample {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        loop {
+            let item = self.iter.next()?;
+            if self.keep(&item) {
+                return Some(item);
+            }
+        }
+    }
+}
+
+/// The samplers as iterator methods
+pub trait SampleExt: Iterator + Sized {
+    fn sample_every(self, n: u64) -> Every<Self> {
+        Every::new(self, n)
+    }
+
+    fn rate_limit(self, rate: Rate) -> RateLimited<Self> where Self::Item: Sample {
+        RateLimited::new(self, rate)
+    }
+
+    fn sample_flows(self, n: u64) -> FlowSampled<Self> where Self::Item: Sample {
+        FlowSampled::new(self, n)
+    }
+}
+
+impl <I: Iterator> SampleExt for I {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn packet(ms: u64, data: &[u8]) -> OwnedCapturedPacket {
+        OwnedCapturedPacket {
+            interface_id: 0,
+            link_type: LinkType::Raw,
+            timestamp: Duration::from_millis(ms).into(),
+            original_len: data.len() as u32,
+            fcs_len: None,
+            data: data.to_vec(),
+        }
+    }
+
+    // a UDP packet from 10.0.0.1 to 10.0.0.2 from a given source port, or
+    // back the other way
+    fn udp(port: u8, reply: bool) -> Vec<u8> {
+        let (a, b) = if reply { (2, 1) } else { (1, 2) };
+        let mut bs = vec![
+            0x45, 0x00, 0x00, 0x1c, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, 0x0a, 0x00, 0x00, a,
+            0x0a, 0x00, 0x00, b,
+            0x10, port, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00,
+        ];
+        if reply {
+            bs.swap(20, 22);
+            bs.swap(21, 23);
+        }
+        bs
+    }
+
+    #[test]
+    fn test_samplers() {
+        let every: Vec<u64> = (0..10u64).sample_every(4).collect();
+        assert_eq!(every, vec![0, 4, 8]);
+
+        // 101 packets of 100 bytes over a second, capped to 20 packets or
+        // 1000 bytes a second, with a second's worth let through up front
+        let packets: Vec<OwnedCapturedPacket> = (0..101).map(|i| packet(10*i, &[0; 100])).collect();
+        let mut limited = packets.clone().into_iter().rate_limit(Rate::Packets(20));
+        assert_eq!(limited.by_ref().count(), 40);
+        assert_eq!(limited.dropped(), 61);
+        assert_eq!(packets.into_iter().rate_limit(Rate::Bytes(1000)).count(), 20);
+        let late = OwnedCapturedPacket { timestamp: Timestamp::new(u64::MAX, 0), ..packet(0, &[0; 100]) };
+        assert_eq!(vec![packet(0, &[0; 100]), late].into_iter().rate_limit(Rate::Bytes(u64::MAX)).count(), 2);
+
+        let flows: Vec<OwnedCapturedPacket> = (0..64)
+            .flat_map(|port| vec![packet(0, &udp(port, false)), packet(1, &udp(port, true))])
+            .collect();
+        let kept: Vec<OwnedCapturedPacket> = flows.into_iter().sample_flows(4).collect();
+        assert!(!kept.is_empty() && kept.len() < 64);
+        // both directions of a flow go together
+        for pair in kept.chunks(2) {
+            assert_eq!(pair.len(), 2);
+            assert_eq!(pair[0].flow_key().unwrap().reversed(), pair[1].flow_key().unwrap());
+        }
+        // the same flows whatever the build
+        assert_eq!(flow_hash(&packet(0, &udp(1, false)).flow_key().unwrap()), 4612733285784940404);
+    }
+}
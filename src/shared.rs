@@ -0,0 +1,312 @@
+use bytes::Bytes;
+
+use dissect::{self, ApplicationLayer, FcsMode, LinkType, OwnedApplicationLayer, Packet, Registry};
+use enrich::{Enrichment, Subject};
+use ethernet::{EtherType, EthernetIIPacket, Fcs, MacAddr, VlanTag};
+use icmp::{IcmpHeader, IcmpPacket};
+use ipv4::{self, Ipv4Packet};
+use ipv6::{Ipv6Header, Ipv6Packet, OwnedIpv6Extension};
+use pcapng::CapturedPacket;
+use tcp::{OwnedTcpHeader, TcpPacket};
+use udp::{UdpHeader, UdpPacket};
+use {IntoOwned, IpPacket, Timestamp, TransportLayerPacket};
+
+/// Converts a packet that borrows from `buf` into a counterpart holding
+/// reference counted slices of it, which is cheap, `Send`, and has no
+/// lifetime to thread through queues and tasks. Headers are copied out;
+/// bodies and payloads aren't.
+///
+/// Panics if the packet's data doesn't point into `buf`, so parse from
+/// the `Bytes` itself rather than a copy of it.
+pub trait IntoShared {
+    type Shared;
+
+    fn into_shared(self, buf: &Bytes) -> Self::Shared;
+}
+
+impl IntoShared for &[u8] {
+    type Shared = Bytes;
+
+    fn into_shared(self, buf: &Bytes) -> Bytes {
+        buf.slice_ref(self)
+    }
+}
+
+impl <T: IntoShared> IntoShared for Option<T> {
+    type Shared = Option<T::Shared>;
+
+    fn into_shared(self, buf: &Bytes) -> Option<T::Shared> {
+        self.map(|x| x.into_shared(buf))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SharedEthernetIIPacket {
+    pub dest_mac: MacAddr,
+    pub source_mac: MacAddr,
+    pub vlans: Vec<VlanTag>,
+    pub ethertype: EtherType,
+    pub body: Bytes,
+}
+
+impl <'a> IntoShared for EthernetIIPacket<'a> {
+    type Shared = SharedEthernetIIPacket;
+
+    fn into_shared(self, buf: &Bytes) -> SharedEthernetIIPacket {
+        SharedEthernetIIPacket {
+            dest_mac: self.dest_mac,
+            source_mac: self.source_mac,
+            vlans: self.vlans,
+            ethertype: self.ethertype,
+            body: self.body.into_shared(buf),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SharedIpv4Packet {
+    pub header: ipv4::OwnedHeader,
+    pub body: Bytes,
+}
+
+impl <'a> IntoShared for Ipv4Packet<'a> {
+    type Shared = SharedIpv4Packet;
+
+    fn into_shared(self, buf: &Bytes) -> SharedIpv4Packet {
+        SharedIpv4Packet {
+            header: self.header.into_owned(),
+            body: self.body.into_shared(buf),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SharedIpv6Packet {
+    pub header: Ipv6Header,
+    pub extensions: Vec<OwnedIpv6Extension>,
+    pub body: Bytes,
+}
+
+impl <'a> IntoShared for Ipv6Packet<'a> {
+    type Shared = SharedIpv6Packet;
+
+    fn into_shared(self, buf: &Bytes) -> SharedIpv6Packet {
+        SharedIpv6Packet {
+            header: self.header,
+            extensions: self.extensions.into_owned(),
+            body: self.body.into_shared(buf),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum SharedIpPacket {
+    V4(SharedIpv4Packet),
+    V6(SharedIpv6Packet),
+}
+
+impl <'a> IntoShared for IpPacket<'a> {
+    type Shared = SharedIpPacket;
+
+    fn into_shared(self, buf: &Bytes) -> SharedIpPacket {
+        match self {
+            IpPacket::V4(p) => SharedIpPacket::V4(p.into_shared(buf)),
+            IpPacket::V6(p) => SharedIpPacket::V6(p.into_shared(buf)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SharedTcpPacket {
+    pub header: OwnedTcpHeader,
+    pub body: Bytes,
+}
+
+impl <'a> IntoShared for TcpPacket<'a> {
+    type Shared = SharedTcpPacket;
+
+    fn into_shared(self, buf: &Bytes) -> SharedTcpPacket {
+        SharedTcpPacket {
+            header: self.header.into_owned(),
+            body: self.body.into_shared(buf),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SharedUdpPacket {
+    pub header: UdpHeader,
+    pub body: Bytes,
+}
+
+impl <'a> IntoShared for UdpPacket<'a> {
+    type Shared = SharedUdpPacket;
+
+    fn into_shared(self, buf: &Bytes) -> SharedUdpPacket {
+        SharedUdpPacket {
+            header: self.header,
+            body: self.body.into_shared(buf),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SharedIcmpPacket {
+    pub header: IcmpHeader,
+    pub body: Bytes,
+}
+
+impl <'a> IntoShared for IcmpPacket<'a> {
+    type Shared = SharedIcmpPacket;
+
+    fn into_shared(self, buf: &Bytes) -> SharedIcmpPacket {
+        SharedIcmpPacket {
+            header: self.header,
+            body: self.body.into_shared(buf),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum SharedTransportLayerPacket {
+    Tcp(SharedTcpPacket),
+    Udp(SharedUdpPacket),
+    Icmp(SharedIcmpPacket),
+}
+
+impl <'a> IntoShared for TransportLayerPacket<'a> {
+    type Shared = SharedTransportLayerPacket;
+
+    fn into_shared(self, buf: &Bytes) -> SharedTransportLayerPacket {
+        match self {
+            TransportLayerPacket::Tcp(p) => SharedTransportLayerPacket::Tcp(p.into_shared(buf)),
+            TransportLayerPacket::Udp(p) => SharedTransportLayerPacket::Udp(p.into_shared(buf)),
+            TransportLayerPacket::Icmp(p) => SharedTransportLayerPacket::Icmp(p.into_shared(buf)),
+        }
+    }
+}
+
+/// A `Packet` holding slices of its capture buffer. The application layer
+/// is owned, since DNS messages are mostly small pieces, and a user
+/// dissector's output is dropped, since it isn't `Send`.
+#[derive(Clone, Debug, Default)]
+pub struct SharedPacket {
+    pub timestamp: Option<Timestamp>,
+    pub ethernet: Option<SharedEthernetIIPacket>,
+    pub fcs: Option<Fcs>,
+    pub ip: Option<SharedIpPacket>,
+    pub transport: Option<SharedTransportLayerPacket>,
+    pub application: Option<OwnedApplicationLayer>,
+    pub payload: Bytes,
+    pub enrichment: Vec<(Subject, Enrichment)>,
+}
+
+impl SharedPacket {
+    /// Dissects `buf` with the default `Registry`
+    pub fn parse(link_type: LinkType, buf: Bytes) -> SharedPacket {
+        dissect::parse_packet(link_type, &buf).into_shared(&buf)
+    }
+
+    pub fn parse_with(registry: &Registry, link_type: LinkType, buf: Bytes) -> SharedPacket {
+        dissect::parse_packet_with(registry, link_type, &buf).into_shared(&buf)
+    }
+}
+
+impl <'a> IntoShared for Packet<'a> {
+    type Shared = SharedPacket;
+
+    fn into_shared(self, buf: &Bytes) -> SharedPacket {
+        SharedPacket {
+            timestamp: self.timestamp,
+            ethernet: self.ethernet.into_shared(buf),
+            fcs: self.fcs,
+            ip: self.ip.into_shared(buf),
+            transport: self.transport.into_shared(buf),
+            application: self.application.map(ApplicationLayer::into_owned),
+            payload: self.payload.into_shared(buf),
+            enrichment: self.enrichment,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SharedCapturedPacket {
+    pub interface_id: u32,
+    pub link_type: LinkType,
+    pub timestamp: Timestamp,
+    pub original_len: u32,
+    pub fcs_len: Option<u8>,
+    pub data: Bytes,
+}
+
+impl SharedCapturedPacket {
+    /// Runs the packet through the dissector, sharing the same buffer, and
+    /// going by the interface's if_fcslen like `CapturedPacket::packet`
+    pub fn packet(&self) -> SharedPacket {
+        let mut registry = Registry::new();
+        registry.set_fcs_mode(FcsMode::from_fcs_len(self.fcs_len));
+        let mut packet = SharedPacket::parse_with(&registry, self.link_type, self.data.clone());
+        packet.timestamp = Some(self.timestamp);
+        packet
+    }
+}
+
+impl <'a> IntoShared for CapturedPacket<'a> {
+    type Shared = SharedCapturedPacket;
+
+    fn into_shared(self, buf: &Bytes) -> SharedCapturedPacket {
+        SharedCapturedPacket {
+            interface_id: self.interface_id,
+            link_type: self.link_type,
+            timestamp: self.timestamp,
+            original_len: self.original_len,
+            fcs_len: self.fcs_len,
+            data: self.data.into_shared(buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use testgen::{self, Endpoint};
+
+    #[test]
+    fn test_shared_packet() {
+        let frame = testgen::udp_frame(&Endpoint::client(), &Endpoint::server(), 4000, 5000, b"hello");
+        let buf = Bytes::from(frame);
+        let packet = SharedPacket::parse(LinkType::Ethernet, buf.clone());
+
+        // the payload is a view into the frame, not a copy
+        let start = buf.as_ptr() as usize;
+        let payload = packet.payload.as_ptr() as usize;
+        assert_eq!(payload - start, 14 + 20 + 8);
+
+        let handle = thread::spawn(move || match packet.transport {
+            Some(SharedTransportLayerPacket::Udp(udp)) => (udp.header.dst, udp.body),
+            x => panic!("expected UDP, got {:?}", x),
+        });
+        let (port, body) = handle.join().unwrap();
+        assert_eq!(port, 5000);
+        assert_eq!(&body[..], b"hello");
+    }
+
+    #[test]
+    fn test_shared_captured_packet() {
+        let mut frame = testgen::udp_frame(&Endpoint::client(), &Endpoint::server(), 4000, 5000, b"hello");
+        let crc = ::checksum::crc32(&frame);
+        frame.extend_from_slice(&crc.to_le_bytes());
+        let captured = SharedCapturedPacket {
+            interface_id: 0,
+            link_type: LinkType::Ethernet,
+            timestamp: Timestamp::new(1, 0),
+            original_len: frame.len() as u32,
+            fcs_len: Some(4),
+            data: Bytes::from(frame),
+        };
+        let packet = captured.packet();
+        assert_eq!(packet.fcs, Some(Fcs { value: crc, valid: true }));
+        assert_eq!(&packet.payload[..], b"hello");
+    }
+}
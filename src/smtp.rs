@@ -0,0 +1,269 @@
+use std::fmt;
+
+use nom::bytes::complete::take;
+use nom::bytes::streaming::{tag, take_until};
+use nom::character::is_digit;
+use nom::combinator::{map, verify};
+use nom::error::{context, ErrorKind};
+
+use error::{IResult, ParseError};
+
+// https://tools.ietf.org/html/rfc5321
+//
+// The parsers are streaming: a line that hasn't been seen up to its CRLF
+// yet fails with `nom::Err::Incomplete`, so they can be run over the output
+// of TCP reassembly as it grows
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Command<'a> {
+    Helo(&'a [u8]),
+    Ehlo(&'a [u8]),
+    // the reverse path without its angle brackets, then any parameters
+    MailFrom(&'a [u8], &'a [u8]),
+    // the forward path without its angle brackets, then any parameters
+    RcptTo(&'a [u8], &'a [u8]),
+    Data,
+    StartTls,
+    Auth(&'a [u8]),
+    Rset,
+    Noop,
+    Quit,
+    // the verb and its arguments
+    Other(&'a [u8], &'a [u8]),
+}
+
+impl <'a> fmt::Display for Command<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = String::from_utf8_lossy;
+        match *self {
+            Command::Helo(domain) => write!(f, "HELO {}", s(domain)),
+            Command::Ehlo(domain) => write!(f, "EHLO {}", s(domain)),
+            Command::MailFrom(path, _) => write!(f, "MAIL FROM:<{}>", s(path)),
+            Command::RcptTo(path, _) => write!(f, "RCPT TO:<{}>", s(path)),
+            Command::Data => write!(f, "DATA"),
+            Command::StartTls => write!(f, "STARTTLS"),
+            // credentials are left out
+            Command::Auth(_) => write!(f, "AUTH"),
+            Command::Rset => write!(f, "RSET"),
+            Command::Noop => write!(f, "NOOP"),
+            Command::Quit => write!(f, "QUIT"),
+            Command::Other(verb, _) => write!(f, "{}", s(verb)),
+        }
+    }
+}
+
+/// Parses one command line sent by a client
+pub fn parse_command(bs: &[u8]) -> IResult<'_, Command<'_>> {
+    context("smtp", command)(bs)
+}
+
+fn command(bs: &[u8]) -> IResult<'_, Command<'_>> {
+    let (bs, line) = line(bs)?;
+    let (verb, args) = match line.iter().position(|&b| b == b' ') {
+        Some(i) => (&line[..i], &line[i + 1..]),
+        None => (line, &b""[..]),
+    };
+    let verb_is = |name: &[u8]| verb.eq_ignore_ascii_case(name);
+    let command = if verb_is(b"HELO") {
+        Command::Helo(args)
+    } else if verb_is(b"EHLO") {
+        Command::Ehlo(args)
+    } else if verb_is(b"MAIL") && starts_with_ignore_case(args, b"FROM:") {
+        let (path, params) = path(&args[5..]);
+        Command::MailFrom(path, params)
+    } else if verb_is(b"RCPT") && starts_with_ignore_case(args, b"TO:") {
+        let (path, params) = path(&args[3..]);
+        Command::RcptTo(path, params)
+    } else if verb_is(b"DATA") {
+        Command::Data
+    } else if verb_is(b"STARTTLS") {
+        Command::StartTls
+    } else if verb_is(b"AUTH") {
+        Command::Auth(args)
+    } else if verb_is(b"RSET") {
+        Command::Rset
+    } else if verb_is(b"NOOP") {
+        Command::Noop
+    } else if verb_is(b"QUIT") {
+        Command::Quit
+    } else {
+        Command::Other(verb, args)
+    };
+    Ok((bs, command))
+}
+
+fn starts_with_ignore_case(bs: &[u8], prefix: &[u8]) -> bool {
+    bs.len() >= prefix.len() && bs[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+// splits "<user@example.com> SIZE=1000" into the path and its parameters;
+// some clients leave out the brackets or put a space after the colon
+fn path(bs: &[u8]) -> (&[u8], &[u8]) {
+    let bs = match bs.iter().position(|&b| b != b' ') {
+        Some(i) => &bs[i..],
+        None => &[],
+    };
+    let (path, rest) = match bs.iter().position(|&b| b == b' ') {
+        Some(i) => (&bs[..i], &bs[i + 1..]),
+        None => (bs, &b""[..]),
+    };
+    if path.len() >= 2 && path[0] == b'<' && path[path.len() - 1] == b'>' {
+        (&path[1..path.len() - 1], rest)
+    } else {
+        (path, rest)
+    }
+}
+
+fn line(bs: &[u8]) -> IResult<'_, &[u8]> {
+    let (bs, line) = take_until("\r\n")(bs)?;
+    let (bs, _) = tag("\r\n")(bs)?;
+    Ok((bs, line))
+}
+
+/// A server reply, with the text of each of its lines
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Reply<'a> {
+    pub code: u16,
+    pub lines: Vec<&'a [u8]>,
+}
+
+impl <'a> Reply<'a> {
+    pub fn is_positive(&self) -> bool {
+        self.code < 400
+    }
+
+    /// The extensions a server announced in reply to EHLO, which are every
+    /// line but the greeting
+    pub fn extensions(&self) -> &[&'a [u8]] {
+        if self.lines.is_empty() {
+            &[]
+        } else {
+            &self.lines[1..]
+        }
+    }
+}
+
+impl <'a> fmt::Display for Reply<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.code)?;
+        if let Some(first) = self.lines.first() {
+            write!(f, " {}", String::from_utf8_lossy(first))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a whole reply, all of its lines if it spans several
+pub fn parse_reply(bs: &[u8]) -> IResult<'_, Reply<'_>> {
+    context("smtp", reply)(bs)
+}
+
+fn reply(mut bs: &[u8]) -> IResult<'_, Reply<'_>> {
+    let mut code = None;
+    let mut lines = vec![];
+    loop {
+        let (rest, line) = line(bs)?;
+        let (text, (this_code, last)) = context("smtp.reply", reply_line_start)(line)?;
+        // every line of a reply has to carry the same code
+        if *code.get_or_insert(this_code) != this_code {
+            let mut e = ParseError::new(line, ErrorKind::Verify);
+            e.context.push("smtp.reply");
+            return Err(nom::Err::Error(e));
+        }
+        lines.push(text);
+        bs = rest;
+        if last {
+            break;
+        }
+    }
+    Ok((bs, Reply {
+        code: code.unwrap_or_default(),
+        lines,
+    }))
+}
+
+// the code, and whether this is the last line, which is followed by a
+// space or nothing instead of a hyphen
+fn reply_line_start(bs: &[u8]) -> IResult<'_, (u16, bool)> {
+    let (bs, code) = map(verify(take(3usize), |d: &[u8]| d.iter().all(|&b| is_digit(b))),
+        |d: &[u8]| d.iter().fold(0, |acc, &b| 10*acc + (b - b'0') as u16))(bs)?;
+    match bs.first() {
+        Some(b'-') => Ok((&bs[1..], (code, false))),
+        Some(b' ') => Ok((&bs[1..], (code, true))),
+        _ => Ok((bs, (code, true))),
+    }
+}
+
+/// Parses the message a client sends after DATA and a 354 reply, up to and
+/// including the line with a single dot. The content is returned as sent,
+/// with leading dots still doubled.
+pub fn parse_data(bs: &[u8]) -> IResult<'_, &[u8]> {
+    context("smtp", data)(bs)
+}
+
+fn data(bs: &[u8]) -> IResult<'_, &[u8]> {
+    if bs.starts_with(b".\r\n") {
+        return Ok((&bs[3..], &bs[..0]));
+    }
+    let (rest, content) = take_until("\r\n.\r\n")(bs)?;
+    Ok((&rest[5..], &bs[..content.len() + 2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands() {
+        let session = b"EHLO client.example.org\r\nMAIL FROM:<alice@example.org> SIZE=1024\r\n\
+            rcpt to: <bob@example.com>\r\nDATA\r\nQUIT\r\nNOOP";
+        let mut bs = &session[..];
+        let mut commands = vec![];
+        loop {
+            match parse_command(bs) {
+                Ok((rest, command)) => {
+                    commands.push(command);
+                    bs = rest;
+                },
+                Err(nom::Err::Incomplete(_)) => break,
+                Err(e) => panic!("unexpected error {:?}", e),
+            }
+        }
+        assert_eq!(commands, vec![
+            Command::Ehlo(b"client.example.org"),
+            Command::MailFrom(b"alice@example.org", b"SIZE=1024"),
+            Command::RcptTo(b"bob@example.com", b""),
+            Command::Data,
+            Command::Quit,
+        ]);
+        assert_eq!(bs, b"NOOP");
+
+        let (rest, content) = parse_data(b"Subject: hi\r\n\r\n..dots\r\n.\r\nQUIT\r\n").unwrap();
+        assert_eq!(content, b"Subject: hi\r\n\r\n..dots\r\n");
+        assert_eq!(rest, b"QUIT\r\n");
+    }
+
+    #[test]
+    fn test_multiline_reply() {
+        // from the server side of the capture in test_stmp_packet2
+        let reply = b"250-mx.google.com at your service, [108.39.81.51]\r\n250-SIZE 35882577\r\n\
+            250-8BITMIME\r\n250-STARTTLS\r\n250-ENHANCEDSTATUSCODES\r\n250-PIPELINING\r\n\
+            250-CHUNKING\r\n250 SMTPUTF8\r\n";
+        let (rest, reply) = parse_reply(reply).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(reply.code, 250);
+        assert!(reply.is_positive());
+        assert_eq!(reply.lines.len(), 8);
+        assert_eq!(reply.extensions()[2], b"STARTTLS");
+        assert_eq!(format!("{}", reply), "250 mx.google.com at your service, [108.39.81.51]");
+
+        assert!(matches!(parse_reply(b"250-first\r\n250 sec"), Err(nom::Err::Incomplete(_))));
+        let err = match parse_reply(b"250-first\r\n550 second\r\n") {
+            Err(nom::Err::Error(e)) => e,
+            other => panic!("unexpected result {:?}", other),
+        };
+        assert_eq!(err.field(), Some("smtp.reply"));
+    }
+}
@@ -0,0 +1,376 @@
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use nom::bytes::complete::{tag, take, take_until};
+use nom::combinator::map;
+use nom::error::{context, ErrorKind};
+use nom::multi::length_count;
+use nom::number::complete::{be_u8, be_u16};
+
+use error::{IResult, ParseError};
+use ipv6::slice2addr;
+
+// SOCKS is a handful of messages that can only be told apart by which side
+// sent them and where in the handshake they are, so each gets its own parser
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Address<'a> {
+    V4(Ipv4Addr),
+    V6(Ipv6Addr),
+    Domain(&'a [u8]),
+}
+
+impl <'a> fmt::Display for Address<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Address::V4(addr) => write!(f, "{}", addr),
+            Address::V6(addr) => write!(f, "{}", addr),
+            Address::Domain(name) => write!(f, "{}", String::from_utf8_lossy(name)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Command {
+    Connect,
+    Bind,
+    // SOCKS5 only
+    UdpAssociate,
+    Other(u8),
+}
+
+impl Command {
+    pub fn from_u8(v: u8) -> Command {
+        match v {
+            1 => Command::Connect,
+            2 => Command::Bind,
+            3 => Command::UdpAssociate,
+            x => Command::Other(x),
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            Command::Connect => 1,
+            Command::Bind => 2,
+            Command::UdpAssociate => 3,
+            Command::Other(x) => x,
+        }
+    }
+}
+
+// https://www.openssh.com/txt/socks4.protocol, and socks4a.protocol for
+// requests that name a host instead of an address
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Socks4Request<'a> {
+    pub command: Command,
+    pub port: u16,
+    pub address: Address<'a>,
+    pub user_id: &'a [u8],
+}
+
+pub fn parse_socks4_request(bs: &[u8]) -> IResult<'_, Socks4Request<'_>> {
+    context("socks", socks4_request)(bs)
+}
+
+fn socks4_request(bs: &[u8]) -> IResult<'_, Socks4Request<'_>> {
+    let (bs, _) = tag([4])(bs)?;
+    let (bs, command) = be_u8(bs)?;
+    let (bs, port) = be_u16(bs)?;
+    let (bs, ip) = take(4usize)(bs)?;
+    let (bs, user_id) = null_terminated(bs)?;
+    // SOCKS4a: 0.0.0.x, with x nonzero, means a host name follows
+    let (bs, address) = if ip[..3] == [0, 0, 0] && ip[3] != 0 {
+        let (bs, name) = null_terminated(bs)?;
+        (bs, Address::Domain(name))
+    } else {
+        (bs, Address::V4(Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3])))
+    };
+    Ok((bs, Socks4Request {
+        command: Command::from_u8(command),
+        port,
+        address,
+        user_id,
+    }))
+}
+
+fn null_terminated(bs: &[u8]) -> IResult<'_, &[u8]> {
+    let (bs, s) = take_until(&[0u8][..])(bs)?;
+    let (bs, _) = tag([0])(bs)?;
+    Ok((bs, s))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Socks4Reply {
+    // 90 if the request was granted, 91 to 93 if not
+    pub status: u8,
+    pub port: u16,
+    pub ip: Ipv4Addr,
+}
+
+pub fn parse_socks4_reply(bs: &[u8]) -> IResult<'_, Socks4Reply> {
+    context("socks", socks4_reply)(bs)
+}
+
+fn socks4_reply(bs: &[u8]) -> IResult<'_, Socks4Reply> {
+    let (bs, _) = tag([0])(bs)?;
+    let (bs, status) = be_u8(bs)?;
+    let (bs, port) = be_u16(bs)?;
+    let (bs, ip) = take(4usize)(bs)?;
+    Ok((bs, Socks4Reply {
+        status,
+        port,
+        ip: Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3]),
+    }))
+}
+
+// https://tools.ietf.org/html/rfc1928
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AuthMethod {
+    NoAuthentication,
+    Gssapi,
+    UsernamePassword,
+    NoAcceptableMethods,
+    Other(u8),
+}
+
+impl AuthMethod {
+    pub fn from_u8(v: u8) -> AuthMethod {
+        match v {
+            0x00 => AuthMethod::NoAuthentication,
+            0x01 => AuthMethod::Gssapi,
+            0x02 => AuthMethod::UsernamePassword,
+            0xff => AuthMethod::NoAcceptableMethods,
+            x => AuthMethod::Other(x),
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            AuthMethod::NoAuthentication => 0x00,
+            AuthMethod::Gssapi => 0x01,
+            AuthMethod::UsernamePassword => 0x02,
+            AuthMethod::NoAcceptableMethods => 0xff,
+            AuthMethod::Other(x) => x,
+        }
+    }
+}
+
+/// The methods a SOCKS5 client offers, the first thing it sends
+pub fn parse_socks5_greeting(bs: &[u8]) -> IResult<'_, Vec<AuthMethod>> {
+    context("socks", socks5_greeting)(bs)
+}
+
+fn socks5_greeting(bs: &[u8]) -> IResult<'_, Vec<AuthMethod>> {
+    let (bs, _) = tag([5])(bs)?;
+    length_count(be_u8, map(be_u8, AuthMethod::from_u8))(bs)
+}
+
+/// The method the server picked in reply to the greeting
+pub fn parse_socks5_method_selection(bs: &[u8]) -> IResult<'_, AuthMethod> {
+    context("socks", socks5_method_selection)(bs)
+}
+
+fn socks5_method_selection(bs: &[u8]) -> IResult<'_, AuthMethod> {
+    let (bs, _) = tag([5])(bs)?;
+    let (bs, method) = be_u8(bs)?;
+    Ok((bs, AuthMethod::from_u8(method)))
+}
+
+// https://tools.ietf.org/html/rfc1929
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct UsernamePassword<'a> {
+    pub username: &'a [u8],
+    pub password: &'a [u8],
+}
+
+pub fn parse_username_password(bs: &[u8]) -> IResult<'_, UsernamePassword<'_>> {
+    context("socks", username_password)(bs)
+}
+
+fn username_password(bs: &[u8]) -> IResult<'_, UsernamePassword<'_>> {
+    let (bs, _) = tag([1])(bs)?;
+    let (bs, len) = be_u8(bs)?;
+    let (bs, username) = take(len)(bs)?;
+    let (bs, len) = be_u8(bs)?;
+    let (bs, password) = take(len)(bs)?;
+    Ok((bs, UsernamePassword {
+        username,
+        password,
+    }))
+}
+
+/// The server's answer to a username and password, zero for success
+pub fn parse_username_password_reply(bs: &[u8]) -> IResult<'_, u8> {
+    context("socks", username_password_reply)(bs)
+}
+
+fn username_password_reply(bs: &[u8]) -> IResult<'_, u8> {
+    let (bs, _) = tag([1])(bs)?;
+    be_u8(bs)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Socks5Request<'a> {
+    pub command: Command,
+    pub address: Address<'a>,
+    pub port: u16,
+}
+
+pub fn parse_socks5_request(bs: &[u8]) -> IResult<'_, Socks5Request<'_>> {
+    context("socks", socks5_request)(bs)
+}
+
+fn socks5_request(bs: &[u8]) -> IResult<'_, Socks5Request<'_>> {
+    let (bs, _) = tag([5])(bs)?;
+    let (bs, command) = be_u8(bs)?;
+    let (bs, _reserved) = be_u8(bs)?;
+    let (bs, address) = address(bs)?;
+    let (bs, port) = be_u16(bs)?;
+    Ok((bs, Socks5Request {
+        command: Command::from_u8(command),
+        address,
+        port,
+    }))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Socks5Reply<'a> {
+    // zero for success, otherwise why the request failed
+    pub reply: u8,
+    pub address: Address<'a>,
+    pub port: u16,
+}
+
+pub fn parse_socks5_reply(bs: &[u8]) -> IResult<'_, Socks5Reply<'_>> {
+    context("socks", socks5_reply)(bs)
+}
+
+fn socks5_reply(bs: &[u8]) -> IResult<'_, Socks5Reply<'_>> {
+    let (bs, _) = tag([5])(bs)?;
+    let (bs, reply) = be_u8(bs)?;
+    let (bs, _reserved) = be_u8(bs)?;
+    let (bs, address) = address(bs)?;
+    let (bs, port) = be_u16(bs)?;
+    Ok((bs, Socks5Reply {
+        reply,
+        address,
+        port,
+    }))
+}
+
+/// The header on datagrams relayed after a UDP ASSOCIATE
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct UdpDatagram<'a> {
+    pub fragment: u8,
+    pub address: Address<'a>,
+    pub port: u16,
+    pub data: &'a [u8],
+}
+
+pub fn parse_socks5_udp(bs: &[u8]) -> IResult<'_, UdpDatagram<'_>> {
+    context("socks", socks5_udp)(bs)
+}
+
+fn socks5_udp(bs: &[u8]) -> IResult<'_, UdpDatagram<'_>> {
+    let (bs, _reserved) = tag([0, 0])(bs)?;
+    let (bs, fragment) = be_u8(bs)?;
+    let (bs, address) = address(bs)?;
+    let (bs, port) = be_u16(bs)?;
+    Ok((&bs[bs.len()..], UdpDatagram {
+        fragment,
+        address,
+        port,
+        data: bs,
+    }))
+}
+
+fn address(bs: &[u8]) -> IResult<'_, Address<'_>> {
+    context("socks.addr", typed_address)(bs)
+}
+
+fn typed_address(bs: &[u8]) -> IResult<'_, Address<'_>> {
+    let (bs, typ) = be_u8(bs)?;
+    match typ {
+        1 => {
+            let (bs, ip) = take(4usize)(bs)?;
+            Ok((bs, Address::V4(Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3]))))
+        },
+        3 => {
+            let (bs, len) = be_u8(bs)?;
+            let (bs, name) = take(len)(bs)?;
+            Ok((bs, Address::Domain(name)))
+        },
+        4 => {
+            let (bs, ip) = take(16usize)(bs)?;
+            Ok((bs, Address::V6(slice2addr(ip))))
+        },
+        _ => Err(nom::Err::Error(ParseError::new(bs, ErrorKind::Switch))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_socks4a() {
+        let request = [
+            0x04, 0x01, 0x00, 0x50, 0x00, 0x00, 0x00, 0x01,
+            0x62, 0x6f, 0x62, 0x00, 0x65, 0x78, 0x61, 0x6d,
+            0x70, 0x6c, 0x65, 0x2e, 0x63, 0x6f, 0x6d, 0x00,
+        ];
+        let (rest, request) = parse_socks4_request(&request).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(request.command, Command::Connect);
+        assert_eq!(request.port, 80);
+        assert_eq!(request.user_id, b"bob");
+        assert_eq!(request.address, Address::Domain(b"example.com"));
+
+        let (_, reply) = parse_socks4_reply(&[0x00, 0x5a, 0x00, 0x50, 0x5d, 0xb8, 0xd8, 0x22]).unwrap();
+        assert_eq!(reply.status, 90);
+        assert_eq!(reply.ip, Ipv4Addr::new(93, 184, 216, 34));
+    }
+
+    #[test]
+    fn test_socks5_handshake() {
+        let (_, methods) = parse_socks5_greeting(&[0x05, 0x02, 0x00, 0x02]).unwrap();
+        assert_eq!(methods, vec![AuthMethod::NoAuthentication, AuthMethod::UsernamePassword]);
+        let (_, method) = parse_socks5_method_selection(&[0x05, 0x02]).unwrap();
+        assert_eq!(method, AuthMethod::UsernamePassword);
+        let (_, auth) = parse_username_password(&[0x01, 0x01, 0x61, 0x02, 0x70, 0x77]).unwrap();
+        assert_eq!((auth.username, auth.password), (&b"a"[..], &b"pw"[..]));
+
+        let request = [
+            0x05, 0x01, 0x00, 0x03, 0x0b, 0x65, 0x78, 0x61,
+            0x6d, 0x70, 0x6c, 0x65, 0x2e, 0x63, 0x6f, 0x6d,
+            0x01, 0xbb,
+        ];
+        let (_, request) = parse_socks5_request(&request).unwrap();
+        assert_eq!(request.command, Command::Connect);
+        assert_eq!(format!("{}", request.address), "example.com");
+        assert_eq!(request.port, 443);
+
+        let reply = [0x05, 0x00, 0x00, 0x01, 0x0a, 0x00, 0x00, 0x01, 0x9c, 0x40];
+        let (_, reply) = parse_socks5_reply(&reply).unwrap();
+        assert_eq!(reply.reply, 0);
+        assert_eq!(reply.address, Address::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(reply.port, 40000);
+
+        // unknown address type
+        let err = match parse_socks5_request(&[0x05, 0x01, 0x00, 0x07, 0x00]) {
+            Err(nom::Err::Error(e)) => e,
+            other => panic!("unexpected result {:?}", other),
+        };
+        assert_eq!(err.field(), Some("socks.addr"));
+    }
+}
@@ -0,0 +1,279 @@
+use std::fmt;
+
+use nom::bytes::complete::take as take_complete;
+use nom::bytes::streaming::{tag, take, take_until};
+use nom::combinator::{map, verify};
+use nom::error::{context, ErrorKind};
+use nom::number::complete::{be_u8, be_u32 as be_u32_complete};
+use nom::number::streaming::be_u32;
+
+use error::{IResult, ParseError};
+
+// https://tools.ietf.org/html/rfc4253
+//
+// Only the start of a connection can be parsed from a capture: the banners
+// and the unencrypted packets up to NEWKEYS. Like the SMTP parsers these
+// are streaming, and fail with `nom::Err::Incomplete` on a partial line or
+// packet.
+
+/// The identification string each side sends first, like
+/// "SSH-2.0-OpenSSH_8.9p1 Ubuntu-3"
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Banner<'a> {
+    pub proto_version: &'a [u8],
+    pub software_version: &'a [u8],
+    pub comments: Option<&'a [u8]>,
+}
+
+impl <'a> fmt::Display for Banner<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SSH-{}-{}", String::from_utf8_lossy(self.proto_version),
+            String::from_utf8_lossy(self.software_version))?;
+        if let Some(comments) = self.comments {
+            write!(f, " {}", String::from_utf8_lossy(comments))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the identification string, skipping any other lines a server
+/// sends before it
+pub fn parse_banner(bs: &[u8]) -> IResult<'_, Banner<'_>> {
+    context("ssh", banner)(bs)
+}
+
+fn banner(mut bs: &[u8]) -> IResult<'_, Banner<'_>> {
+    loop {
+        let (rest, line) = take_until("\n")(bs)?;
+        let (rest, _) = tag("\n")(rest)?;
+        bs = rest;
+        let line = if line.ends_with(b"\r") { &line[..line.len() - 1] } else { line };
+        if !line.starts_with(b"SSH-") {
+            continue;
+        }
+        let line = &line[4..];
+        let (proto_version, rest) = split_at_byte(line, b'-')
+            .ok_or_else(|| banner_error(line))?;
+        let (software_version, comments) = match split_at_byte(rest, b' ') {
+            Some((software_version, comments)) => (software_version, Some(comments)),
+            None => (rest, None),
+        };
+        return Ok((bs, Banner {
+            proto_version,
+            software_version,
+            comments,
+        }));
+    }
+}
+
+fn split_at_byte(bs: &[u8], b: u8) -> Option<(&[u8], &[u8])> {
+    bs.iter().position(|&x| x == b).map(|i| (&bs[..i], &bs[i + 1..]))
+}
+
+// an identification string without a software version
+fn banner_error(bs: &[u8]) -> nom::Err<ParseError<'_>> {
+    let mut e = ParseError::new(bs, ErrorKind::Char);
+    e.context.push("ssh.banner");
+    nom::Err::Error(e)
+}
+
+/// A packet sent before keys are exchanged, so without encryption or a MAC
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct BinaryPacket<'a> {
+    pub payload: &'a [u8],
+    pub padding: &'a [u8],
+}
+
+pub fn parse_binary_packet(bs: &[u8]) -> IResult<'_, BinaryPacket<'_>> {
+    context("ssh", binary_packet)(bs)
+}
+
+fn binary_packet(bs: &[u8]) -> IResult<'_, BinaryPacket<'_>> {
+    // RFC 4253 has implementations accept packets of at least 35000 bytes,
+    // anything far bigger is more likely encrypted
+    let (bs, len) = context("ssh.packet_length", verify(be_u32, |len| (5..=0x40000).contains(len)))(bs)?;
+    let (bs, packet) = take(len)(bs)?;
+    let (packet, padding_len) = be_u8(packet)?;
+    let (padding, payload) = context("ssh.padding_length",
+        take_complete((len - 1).saturating_sub(padding_len as u32)))(packet)?;
+    let (_, padding) = context("ssh.padding_length", take_complete(padding_len))(padding)?;
+    Ok((bs, BinaryPacket {
+        payload,
+        padding,
+    }))
+}
+
+pub const MSG_KEXINIT: u8 = 20;
+pub const MSG_NEWKEYS: u8 = 21;
+
+/// The payload of a packet; everything after NEWKEYS is encrypted
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Message<'a> {
+    KexInit(KexInit<'a>),
+    NewKeys,
+    // the message number and the rest of the payload
+    Other(u8, &'a [u8]),
+}
+
+pub fn parse_message(payload: &[u8]) -> IResult<'_, Message<'_>> {
+    context("ssh", message)(payload)
+}
+
+fn message(bs: &[u8]) -> IResult<'_, Message<'_>> {
+    let (bs, number) = be_u8(bs)?;
+    match number {
+        MSG_KEXINIT => map(context("ssh.kexinit", kex_init), Message::KexInit)(bs),
+        MSG_NEWKEYS => Ok((bs, Message::NewKeys)),
+        _ => Ok((&bs[bs.len()..], Message::Other(number, bs))),
+    }
+}
+
+/// A comma separated list of algorithm names
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct NameList<'a>(&'a [u8]);
+
+impl <'a> NameList<'a> {
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&'a [u8]> {
+        let bs = self.0;
+        bs.split(|&b| b == b',').filter(move |_| !bs.is_empty())
+    }
+}
+
+impl <'a> fmt::Display for NameList<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(self.0))
+    }
+}
+
+fn name_list(bs: &[u8]) -> IResult<'_, NameList<'_>> {
+    let (bs, len) = be_u32_complete(bs)?;
+    map(take_complete(len), NameList)(bs)
+}
+
+// https://tools.ietf.org/html/rfc4253#section-7.1
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct KexInit<'a> {
+    pub cookie: &'a [u8],
+    pub kex_algorithms: NameList<'a>,
+    pub server_host_key_algorithms: NameList<'a>,
+    pub encryption_client_to_server: NameList<'a>,
+    pub encryption_server_to_client: NameList<'a>,
+    pub mac_client_to_server: NameList<'a>,
+    pub mac_server_to_client: NameList<'a>,
+    pub compression_client_to_server: NameList<'a>,
+    pub compression_server_to_client: NameList<'a>,
+    pub languages_client_to_server: NameList<'a>,
+    pub languages_server_to_client: NameList<'a>,
+    pub first_kex_packet_follows: bool,
+}
+
+impl <'a> KexInit<'a> {
+    /// The string a client's HASSH fingerprint is the MD5 hash of
+    pub fn hassh_string(&self) -> String {
+        format!("{};{};{};{}", self.kex_algorithms, self.encryption_client_to_server,
+            self.mac_client_to_server, self.compression_client_to_server)
+    }
+
+    /// The string a server's HASSHServer fingerprint is the MD5 hash of
+    pub fn hassh_server_string(&self) -> String {
+        format!("{};{};{};{}", self.kex_algorithms, self.encryption_server_to_client,
+            self.mac_server_to_client, self.compression_server_to_client)
+    }
+}
+
+fn kex_init(bs: &[u8]) -> IResult<'_, KexInit<'_>> {
+    let (bs, cookie) = take_complete(16usize)(bs)?;
+    let (bs, kex_algorithms) = name_list(bs)?;
+    let (bs, server_host_key_algorithms) = name_list(bs)?;
+    let (bs, encryption_client_to_server) = name_list(bs)?;
+    let (bs, encryption_server_to_client) = name_list(bs)?;
+    let (bs, mac_client_to_server) = name_list(bs)?;
+    let (bs, mac_server_to_client) = name_list(bs)?;
+    let (bs, compression_client_to_server) = name_list(bs)?;
+    let (bs, compression_server_to_client) = name_list(bs)?;
+    let (bs, languages_client_to_server) = name_list(bs)?;
+    let (bs, languages_server_to_client) = name_list(bs)?;
+    let (bs, first_kex_packet_follows) = be_u8(bs)?;
+    let (bs, _reserved) = be_u32_complete(bs)?;
+    Ok((bs, KexInit {
+        cookie,
+        kex_algorithms,
+        server_host_key_algorithms,
+        encryption_client_to_server,
+        encryption_server_to_client,
+        mac_client_to_server,
+        mac_server_to_client,
+        compression_client_to_server,
+        compression_server_to_client,
+        languages_client_to_server,
+        languages_server_to_client,
+        first_kex_packet_follows: first_kex_packet_follows != 0,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_banner() {
+        let (rest, banner) = parse_banner(b"hello there\r\nSSH-2.0-OpenSSH_8.9p1 Ubuntu-3\r\n\x00").unwrap();
+        assert_eq!(rest, b"\x00");
+        assert_eq!(banner.proto_version, b"2.0");
+        assert_eq!(banner.software_version, b"OpenSSH_8.9p1");
+        assert_eq!(banner.comments, Some(&b"Ubuntu-3"[..]));
+        assert_eq!(format!("{}", banner), "SSH-2.0-OpenSSH_8.9p1 Ubuntu-3");
+        assert!(matches!(parse_banner(b"SSH-2.0-Open"), Err(nom::Err::Incomplete(_))));
+    }
+
+    #[test]
+    fn test_kexinit() {
+        fn list(out: &mut Vec<u8>, names: &str) {
+            out.extend_from_slice(&(names.len() as u32).to_be_bytes());
+            out.extend_from_slice(names.as_bytes());
+        }
+        let mut payload = vec![MSG_KEXINIT];
+        payload.extend_from_slice(&[0x42; 16]);
+        list(&mut payload, "curve25519-sha256,ext-info-c");
+        list(&mut payload, "ssh-ed25519");
+        list(&mut payload, "aes128-ctr,aes256-gcm@openssh.com");
+        list(&mut payload, "aes128-ctr");
+        list(&mut payload, "hmac-sha2-256");
+        list(&mut payload, "hmac-sha2-256");
+        list(&mut payload, "none,zlib@openssh.com");
+        list(&mut payload, "none");
+        list(&mut payload, "");
+        list(&mut payload, "");
+        payload.extend_from_slice(&[0, 0, 0, 0, 0]);
+        let mut packet = vec![];
+        let padding = 4;
+        packet.extend_from_slice(&((payload.len() + padding + 1) as u32).to_be_bytes());
+        packet.push(padding as u8);
+        packet.extend_from_slice(&payload);
+        packet.extend_from_slice(&[0; 4]);
+
+        assert!(matches!(parse_binary_packet(&packet[..20]), Err(nom::Err::Incomplete(_))));
+        let (rest, binary) = parse_binary_packet(&packet).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(binary.padding.len(), 4);
+        let kex = match parse_message(binary.payload).unwrap().1 {
+            Message::KexInit(kex) => kex,
+            other => panic!("expected KEXINIT, got {:?}", other),
+        };
+        assert_eq!(kex.kex_algorithms.iter().count(), 2);
+        assert_eq!(kex.languages_client_to_server.iter().count(), 0);
+        assert!(!kex.first_kex_packet_follows);
+        assert_eq!(kex.hassh_string(),
+            "curve25519-sha256,ext-info-c;aes128-ctr,aes256-gcm@openssh.com;hmac-sha2-256;none,zlib@openssh.com");
+        assert_eq!(kex.hassh_server_string(), "curve25519-sha256,ext-info-c;aes128-ctr;hmac-sha2-256;none");
+    }
+}
@@ -0,0 +1,160 @@
+use std::fmt;
+
+use nom::error::ErrorKind;
+
+use error::IResult;
+
+/// Something that can be parsed out of the front of a byte stream, one item
+/// at a time. Implementations can keep state between items, like the byte
+/// order of a pcapng section.
+pub trait Decode {
+    type Output;
+
+    /// Parses one item from the front of `bs`. Has to fail with
+    /// `nom::Err::Incomplete` when `bs` doesn't hold a whole item yet, and can
+    /// return `None` for items that only update the decoder's state. Those
+    /// still have to consume input; a `Decoder` fails if nothing was.
+    fn decode<'a>(&mut self, bs: &'a [u8]) -> IResult<'a, Option<Self::Output>>;
+}
+
+/// Why a `Decoder` stopped
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    // counted from the first byte ever pushed
+    pub offset: usize,
+    pub kind: ErrorKind,
+    pub context: Vec<&'static str>,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error at offset {}", self.offset)?;
+        if let Some(field) = self.context.first() {
+            write!(f, " in {}", field)?;
+        }
+        write!(f, ": {}", self.kind.description())
+    }
+}
+
+impl ::std::error::Error for DecodeError {}
+
+/// Buffers input that arrives in arbitrary chunks, from TCP reassembly or a
+/// file read a block at a time, and hands out items as soon as they're
+/// complete. Iterating stops when more input is needed, and resumes once it
+/// has been pushed. After an error nothing more is decoded.
+pub struct Decoder<D> {
+    decoder: D,
+    buf: Vec<u8>,
+    // start of the unparsed bytes in buf
+    pos: usize,
+    // stream offset of buf[0]
+    base: usize,
+    failed: bool,
+}
+
+impl <D: Decode> Decoder<D> {
+    pub fn new(decoder: D) -> Decoder<D> {
+        Decoder {
+            decoder,
+            buf: vec![],
+            pos: 0,
+            base: 0,
+            failed: false,
+        }
+    }
+
+    /// Appends a chunk of input
+    pub fn push(&mut self, bs: &[u8]) {
+        // only shuffle bytes down when it saves a reallocation
+        if self.pos > 0 && self.buf.len() + bs.len() > self.buf.capacity() {
+            self.buf.drain(..self.pos);
+            self.base += self.pos;
+            self.pos = 0;
+        }
+        self.buf.extend_from_slice(bs);
+    }
+
+    /// Input that was pushed but hasn't been decoded yet
+    pub fn buffered(&self) -> &[u8] {
+        &self.buf[self.pos..]
+    }
+
+    pub fn get_ref(&self) -> &D {
+        &self.decoder
+    }
+
+    pub fn get_mut(&mut self) -> &mut D {
+        &mut self.decoder
+    }
+}
+
+impl <D: Decode> Iterator for Decoder<D> {
+    type Item = Result<D::Output, DecodeError>;
+
+    fn next(&mut self) -> Option<Result<D::Output, DecodeError>> {
+        while !self.failed {
+            let bs = &self.buf[self.pos..];
+            match self.decoder.decode(bs) {
+                Ok((rest, item)) => {
+                    if item.is_none() && rest.len() == bs.len() {
+                        // it would be asked for the same thing forever, so
+                        // fail like nom's many0 does on a parser that
+                        // doesn't consume
+                        self.failed = true;
+                        return Some(Err(DecodeError {
+                            offset: self.base + self.pos,
+                            kind: ErrorKind::Many0,
+                            context: vec![],
+                        }));
+                    }
+                    self.pos += bs.len() - rest.len();
+                    if item.is_some() {
+                        return item.map(Ok);
+                    }
+                },
+                Err(nom::Err::Incomplete(_)) => return None,
+                Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+                    self.failed = true;
+                    return Some(Err(DecodeError {
+                        offset: self.base + self.pos + e.offset(bs).unwrap_or(0),
+                        kind: e.kind,
+                        context: e.context,
+                    }));
+                },
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // skips a byte at a time until it sees a zero, which it never consumes
+    struct StuckAtZero;
+
+    impl Decode for StuckAtZero {
+        type Output = u8;
+
+        fn decode<'a>(&mut self, bs: &'a [u8]) -> IResult<'a, Option<u8>> {
+            match bs.first() {
+                Some(0) => Ok((bs, None)),
+                Some(&b) => Ok((&bs[1..], Some(b))),
+                None => Err(nom::Err::Incomplete(nom::Needed::new(1))),
+            }
+        }
+    }
+
+    #[test]
+    fn test_no_progress() {
+        let mut decoder = Decoder::new(StuckAtZero);
+        decoder.push(&[1, 2]);
+        decoder.push(&[0, 3]);
+        assert_eq!(decoder.next(), Some(Ok(1)));
+        assert_eq!(decoder.next(), Some(Ok(2)));
+        let err = decoder.next().unwrap().unwrap_err();
+        assert_eq!((err.offset, err.kind), (2, ErrorKind::Many0));
+        assert_eq!(decoder.next(), None);
+    }
+}
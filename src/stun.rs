@@ -0,0 +1,368 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use nom::bytes::complete::{tag, take};
+use nom::combinator::{all_consuming, verify};
+use nom::error::context;
+use nom::multi::many0;
+use nom::number::complete::{be_u8, be_u16, be_u32, be_u64};
+
+use checksum;
+use error::IResult;
+use ipv6::slice2addr;
+
+// https://tools.ietf.org/html/rfc8489
+
+pub const MAGIC_COOKIE: u32 = 0x2112a442;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct StunMessage<'a> {
+    pub method: Method,
+    pub class: Class,
+    // of the attributes, not counting the 20 byte header
+    pub length: u16,
+    pub transaction_id: &'a [u8],
+    pub attributes: Vec<Attribute<'a>>,
+}
+
+impl <'a> fmt::Display for StunMessage<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "STUN {:?} {:?}, length {}", self.method, self.class, self.length)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Method {
+    Binding,
+    // TURN, https://tools.ietf.org/html/rfc8656#section-17
+    Allocate,
+    Refresh,
+    Send,
+    Data,
+    CreatePermission,
+    ChannelBind,
+    Other(u16),
+}
+
+impl Method {
+    pub fn from_u16(v: u16) -> Method {
+        match v {
+            0x001 => Method::Binding,
+            0x003 => Method::Allocate,
+            0x004 => Method::Refresh,
+            0x006 => Method::Send,
+            0x007 => Method::Data,
+            0x008 => Method::CreatePermission,
+            0x009 => Method::ChannelBind,
+            x => Method::Other(x),
+        }
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        match *self {
+            Method::Binding => 0x001,
+            Method::Allocate => 0x003,
+            Method::Refresh => 0x004,
+            Method::Send => 0x006,
+            Method::Data => 0x007,
+            Method::CreatePermission => 0x008,
+            Method::ChannelBind => 0x009,
+            Method::Other(x) => x,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Class {
+    Request,
+    Indication,
+    SuccessResponse,
+    ErrorResponse,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Attribute<'a> {
+    MappedAddress(SocketAddr),
+    // already XORed back to the real address
+    XorMappedAddress(SocketAddr),
+    Username(&'a [u8]),
+    MessageIntegrity(&'a [u8]),
+    MessageIntegritySha256(&'a [u8]),
+    // error class and number combined, like 401, then the reason
+    ErrorCode(u16, &'a [u8]),
+    Realm(&'a [u8]),
+    Nonce(&'a [u8]),
+    Software(&'a [u8]),
+    Fingerprint(u32),
+    // ICE, https://tools.ietf.org/html/rfc8445#section-16.1
+    Priority(u32),
+    UseCandidate,
+    IceControlled(u64),
+    IceControlling(u64),
+    // TURN
+    ChannelNumber(u16),
+    // in seconds
+    Lifetime(u32),
+    XorPeerAddress(SocketAddr),
+    Data(&'a [u8]),
+    XorRelayedAddress(SocketAddr),
+    // an IP protocol number, 17 for UDP
+    RequestedTransport(u8),
+    // the type and value of anything else
+    Other(u16, &'a [u8]),
+}
+
+impl <'a> StunMessage<'a> {
+    /// The application data carried by a TURN Send or Data indication
+    pub fn relayed_payload(&self) -> Option<&'a [u8]> {
+        match (self.method, self.class) {
+            (Method::Send, Class::Indication) | (Method::Data, Class::Indication) => {
+                self.attributes.iter().filter_map(|a| match *a {
+                    Attribute::Data(data) => Some(data),
+                    _ => None,
+                }).next()
+            },
+            _ => None,
+        }
+    }
+
+    /// The peer a TURN Send or Data indication is relayed to or from
+    pub fn peer(&self) -> Option<SocketAddr> {
+        self.attributes.iter().filter_map(|a| match *a {
+            Attribute::XorPeerAddress(addr) => Some(addr),
+            _ => None,
+        }).next()
+    }
+}
+
+/// TURN's lighter framing for data sent over a bound channel
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ChannelData<'a> {
+    // 0x4000 to 0x4fff
+    pub channel: u16,
+    pub data: &'a [u8],
+}
+
+/// What a TURN client and server exchange: STUN messages, or ChannelData
+/// once a channel is bound. The two are told apart by the first two bits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum TurnMessage<'a> {
+    Stun(StunMessage<'a>),
+    ChannelData(ChannelData<'a>),
+}
+
+impl <'a> TurnMessage<'a> {
+    /// The relayed application data, if this message carries any
+    pub fn payload(&self) -> Option<&'a [u8]> {
+        match *self {
+            TurnMessage::Stun(ref m) => m.relayed_payload(),
+            TurnMessage::ChannelData(ref c) => Some(c.data),
+        }
+    }
+}
+
+/// Parses a ChannelData message. Over TCP the data is padded to a multiple
+/// of 4 bytes, which is left in the remaining input for the caller to skip.
+pub fn parse_channel_data(bs: &[u8]) -> IResult<'_, ChannelData<'_>> {
+    context("turn.channel_data", channel_data)(bs)
+}
+
+fn channel_data(bs: &[u8]) -> IResult<'_, ChannelData<'_>> {
+    let (bs, channel) = verify(be_u16, |&c| c & 0xc000 == 0x4000)(bs)?;
+    let (bs, len) = be_u16(bs)?;
+    let (bs, data) = take(len)(bs)?;
+    Ok((bs, ChannelData { channel, data }))
+}
+
+pub fn parse_turn_message(bs: &[u8]) -> IResult<'_, TurnMessage<'_>> {
+    match bs.first() {
+        Some(b) if b & 0xc0 == 0x40 => {
+            let (bs, c) = parse_channel_data(bs)?;
+            Ok((bs, TurnMessage::ChannelData(c)))
+        },
+        _ => {
+            let (bs, m) = parse_stun_message(bs)?;
+            Ok((bs, TurnMessage::Stun(m)))
+        },
+    }
+}
+
+/// Whether a UDP or TCP payload looks like a STUN message: the top two
+/// bits clear, the magic cookie in place and a length that fits
+pub fn is_stun(bs: &[u8]) -> bool {
+    bs.len() >= 20 && bs[0] & 0xc0 == 0
+        && bs[4..8] == MAGIC_COOKIE.to_be_bytes()
+        && u16::from_be_bytes([bs[2], bs[3]]) as usize + 20 <= bs.len()
+}
+
+pub fn parse_stun_message(bs: &[u8]) -> IResult<'_, StunMessage<'_>> {
+    context("stun", stun_message)(bs)
+}
+
+fn stun_message(bs: &[u8]) -> IResult<'_, StunMessage<'_>> {
+    let (bs, typ) = verify(be_u16, |&t| t & 0xc000 == 0)(bs)?;
+    let (bs, length) = be_u16(bs)?;
+    let (bs, _) = tag(MAGIC_COOKIE.to_be_bytes())(bs)?;
+    let (bs, transaction_id) = take(12usize)(bs)?;
+    let (bs, attrs) = context("stun.length", take(length))(bs)?;
+    let (_, attributes) = context("stun.attributes",
+        all_consuming(many0(|bs| attribute(transaction_id, bs))))(attrs)?;
+    // the method's bits are split around the two class bits
+    let method = (typ & 0x000f) | ((typ & 0x00e0) >> 1) | ((typ & 0x3e00) >> 2);
+    let class = match ((typ >> 7) & 0x2) | ((typ >> 4) & 0x1) {
+        0 => Class::Request,
+        1 => Class::Indication,
+        2 => Class::SuccessResponse,
+        _ => Class::ErrorResponse,
+    };
+    Ok((bs, StunMessage {
+        method: Method::from_u16(method),
+        class,
+        length,
+        transaction_id,
+        attributes,
+    }))
+}
+
+fn attribute<'a>(transaction_id: &[u8], bs: &'a [u8]) -> IResult<'a, Attribute<'a>> {
+    let (bs, typ) = be_u16(bs)?;
+    let (bs, len) = be_u16(bs)?;
+    let (bs, value) = take(len)(bs)?;
+    // values are padded to a multiple of 4 bytes
+    let (bs, _) = take((4 - len % 4) % 4)(bs)?;
+    let attribute = match typ {
+        0x0001 => Attribute::MappedAddress(address(value, None)?.1),
+        0x0006 => Attribute::Username(value),
+        0x0008 => Attribute::MessageIntegrity(value),
+        0x0009 => {
+            let (reason, _) = take(2usize)(value)?;
+            let (reason, class) = be_u8(reason)?;
+            let (reason, number) = be_u8(reason)?;
+            Attribute::ErrorCode((class & 0x07) as u16 * 100 + number as u16, reason)
+        },
+        0x000c => Attribute::ChannelNumber(be_u16(value)?.1),
+        0x000d => Attribute::Lifetime(all_consuming(be_u32)(value)?.1),
+        0x0012 => Attribute::XorPeerAddress(address(value, Some(transaction_id))?.1),
+        0x0013 => Attribute::Data(value),
+        0x0014 => Attribute::Realm(value),
+        0x0016 => Attribute::XorRelayedAddress(address(value, Some(transaction_id))?.1),
+        0x0019 => Attribute::RequestedTransport(be_u8(value)?.1),
+        0x0015 => Attribute::Nonce(value),
+        0x001c => Attribute::MessageIntegritySha256(value),
+        0x0020 => Attribute::XorMappedAddress(address(value, Some(transaction_id))?.1),
+        0x0024 => Attribute::Priority(all_consuming(be_u32)(value)?.1),
+        0x0025 => Attribute::UseCandidate,
+        0x8022 => Attribute::Software(value),
+        0x8028 => Attribute::Fingerprint(all_consuming(be_u32)(value)?.1),
+        0x8029 => Attribute::IceControlled(all_consuming(be_u64)(value)?.1),
+        0x802a => Attribute::IceControlling(all_consuming(be_u64)(value)?.1),
+        _ => Attribute::Other(typ, value),
+    };
+    Ok((bs, attribute))
+}
+
+// MAPPED-ADDRESS, or XOR-MAPPED-ADDRESS and the like if a transaction ID is
+// given to undo the XOR with
+fn address<'a>(bs: &'a [u8], transaction_id: Option<&[u8]>) -> IResult<'a, SocketAddr> {
+    let cookie = MAGIC_COOKIE.to_be_bytes();
+    let (bs, _) = be_u8(bs)?;
+    let (bs, family) = be_u8(bs)?;
+    let (bs, port) = be_u16(bs)?;
+    let (bs, ip) = take(if family == 0x02 { 16usize } else { 4 })(bs)?;
+    let mut ip = ip.to_vec();
+    let port = match transaction_id {
+        Some(id) => {
+            let key: Vec<u8> = cookie.iter().chain(id).cloned().collect();
+            for (b, k) in ip.iter_mut().zip(key) {
+                *b ^= k;
+            }
+            port ^ (MAGIC_COOKIE >> 16) as u16
+        },
+        None => port,
+    };
+    let ip = match family {
+        0x02 => IpAddr::V6(slice2addr(&ip)),
+        _ => IpAddr::V4(Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3])),
+    };
+    Ok((bs, SocketAddr::new(ip, port)))
+}
+
+/// Checks the FINGERPRINT attribute at the end of a whole message, which
+/// is how STUN is told apart from other protocols on the same port
+pub fn verify_fingerprint(message: &[u8]) -> bool {
+    if message.len() < 28 || message[message.len() - 8..message.len() - 4] != [0x80, 0x28, 0x00, 0x04] {
+        return false;
+    }
+    let (covered, sent) = message.split_at(message.len() - 8);
+    let sent = u32::from_be_bytes([sent[4], sent[5], sent[6], sent[7]]);
+    checksum::crc32(covered) ^ 0x5354554e == sent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binding_response() {
+        // the IPv4 sample response from RFC 5769, section 2.2
+        let response = [
+            0x01, 0x01, 0x00, 0x3c, 0x21, 0x12, 0xa4, 0x42,
+            0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86,
+            0xfa, 0x87, 0xdf, 0xae, 0x80, 0x22, 0x00, 0x0b,
+            0x74, 0x65, 0x73, 0x74, 0x20, 0x76, 0x65, 0x63,
+            0x74, 0x6f, 0x72, 0x20, 0x00, 0x20, 0x00, 0x08,
+            0x00, 0x01, 0xa1, 0x47, 0xe1, 0x12, 0xa6, 0x43,
+            0x00, 0x08, 0x00, 0x14, 0x2b, 0x91, 0xf5, 0x99,
+            0xfd, 0x9e, 0x90, 0xc3, 0x8c, 0x74, 0x89, 0xf9,
+            0x2a, 0xf9, 0xba, 0x53, 0xf0, 0x6b, 0xe7, 0xd7,
+            0x80, 0x28, 0x00, 0x04, 0xc0, 0x7d, 0x4c, 0x96,
+        ];
+        assert!(is_stun(&response));
+        assert!(verify_fingerprint(&response));
+        let (rest, message) = parse_stun_message(&response).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(message.method, Method::Binding);
+        assert_eq!(message.class, Class::SuccessResponse);
+        assert_eq!(message.attributes[0], Attribute::Software(b"test vector"));
+        assert_eq!(message.attributes[1], Attribute::XorMappedAddress("192.0.2.1:32853".parse().unwrap()));
+        assert_eq!(message.attributes[3], Attribute::Fingerprint(0xc07d4c96));
+        assert_eq!(format!("{}", message), "STUN Binding SuccessResponse, length 60");
+
+        let mut corrupted = response;
+        corrupted[30] ^= 1;
+        assert!(!verify_fingerprint(&corrupted));
+    }
+
+    #[test]
+    fn test_turn() {
+        // a Data indication from 192.0.2.1:32853 carrying "hi"
+        let indication = [
+            0x00, 0x17, 0x00, 0x14, 0x21, 0x12, 0xa4, 0x42,
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08,
+            0x09, 0x0a, 0x0b, 0x0c, 0x00, 0x12, 0x00, 0x08,
+            0x00, 0x01, 0xa1, 0x47, 0xe1, 0x12, 0xa6, 0x43,
+            0x00, 0x13, 0x00, 0x02, 0x68, 0x69, 0x00, 0x00,
+        ];
+        let (_, message) = parse_turn_message(&indication).unwrap();
+        match message {
+            TurnMessage::Stun(ref m) => {
+                assert_eq!(m.method, Method::Data);
+                assert_eq!(m.class, Class::Indication);
+                assert_eq!(m.peer(), Some("192.0.2.1:32853".parse().unwrap()));
+            },
+            _ => panic!("expected a STUN message"),
+        }
+        assert_eq!(message.payload(), Some(&b"hi"[..]));
+
+        let channel_data = [0x40, 0x01, 0x00, 0x03, 0x61, 0x62, 0x63, 0x00];
+        let (rest, message) = parse_turn_message(&channel_data).unwrap();
+        assert_eq!(message, TurnMessage::ChannelData(ChannelData { channel: 0x4001, data: b"abc" }));
+        assert_eq!(rest, &[0x00]);
+    }
+}
@@ -1,13 +1,32 @@
-use nom::{be_u8, be_u16, be_u32, IResult};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use nom::bits::bits;
+use nom::bits::complete::{tag as tag_bits, take as take_bits};
+use nom::bytes::complete::{tag, take};
+use nom::branch::alt;
+use nom::combinator::{cond, eof, map, value, verify};
+use nom::error::{context, ErrorKind};
+use nom::number::complete::{be_u8, be_u16, be_u32};
+use nom::sequence::{pair, preceded};
+use nom::Needed;
+#[cfg(feature = "arbitrary")]
+use arbitrary::{Arbitrary, Unstructured};
+
+use checksum;
+use error::{IResult, ParseError};
+use {many_till_list, Direction, Fixup, IntoOwned, IpHeader, Layer, List, NextProtocol, ToBytes};
 
 // https://tools.ietf.org/html/rfc793
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct TcpPacket<'a> {
     pub header: TcpHeader<'a>,
     pub body: &'a [u8],
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct TcpHeader<'a> {
     pub src: u16,
     pub dst: u16,
@@ -17,7 +36,7 @@ pub struct TcpHeader<'a> {
     pub window_sz: u16,
     pub checksum: u16,
     pub urgent: u16,
-    pub options: Vec<TcpOption<'a>>,
+    pub options: List<TcpOption<'a>>,
 }
 
 struct Bits {
@@ -33,72 +52,87 @@ struct Bits {
     pub fin: u8,
 }
 
-pub fn parse_tcp_packet<'a>(bs: &'a [u8]) -> IResult<&'a [u8], TcpPacket<'a>, u32> {
-    do_parse!(
-        bs,
-        src: be_u16 >>
-        dst: be_u16 >>
-        seq: be_u32 >>
-        ack: be_u32 >>
-        bits: bits!(
-            do_parse!(
-                offset: take_bits!(u8, 4) >>
-                _reserved: tag_bits!(u8, 3, 0) >>
-                ns: take_bits!(u8, 1) >>
-                cwr: take_bits!(u8, 1) >>
-                ece: take_bits!(u8, 1) >>
-                urg: take_bits!(u8, 1) >>
-                ack: take_bits!(u8, 1) >>
-                psh: take_bits!(u8, 1) >>
-                rst: take_bits!(u8, 1) >>
-                syn: take_bits!(u8, 1) >>
-                fin: take_bits!(u8, 1) >>
-                (Bits {
-                    offset: offset,
-                    ns: ns,
-                    cwr: cwr,
-                    ece: ece,
-                    urg: urg,
-                    ack: ack,
-                    psh: psh,
-                    rst: rst,
-                    syn: syn,
-                    fin: fin,
-                })
-            )
-        ) >>
-        sz: be_u16 >>
-        sum: be_u16 >>
-        urgent: be_u16 >>
-        options: cond!(bits.offset > 5, apply!(parse_options, (4*bits.offset-20) as usize)) >>
-        ({
-            use std::cmp::min;
-            let mut packet = TcpPacket {
-                header: TcpHeader {
-                    src: src,
-                    dst: dst,
-                    seq: seq,
-                    ack: ack,
-                    flags: TcpFlags::from_bits(&bits),
-                    window_sz: sz,
-                    checksum: sum,
-                    urgent: urgent,
-                    options: vec![],
-                },
-                body: &bs[min(4*bits.offset as usize, bs.len())..],
-            };
+fn parse_bits(bs: (&[u8], usize)) -> nom::IResult<(&[u8], usize), Bits> {
+    let (bs, offset) = take_bits(4usize)(bs)?;
+    let (bs, _reserved) = tag_bits(0u8, 3usize)(bs)?;
+    let (bs, ns) = take_bits(1usize)(bs)?;
+    let (bs, cwr) = take_bits(1usize)(bs)?;
+    let (bs, ece) = take_bits(1usize)(bs)?;
+    let (bs, urg) = take_bits(1usize)(bs)?;
+    let (bs, ack) = take_bits(1usize)(bs)?;
+    let (bs, psh) = take_bits(1usize)(bs)?;
+    let (bs, rst) = take_bits(1usize)(bs)?;
+    let (bs, syn) = take_bits(1usize)(bs)?;
+    let (bs, fin) = take_bits(1usize)(bs)?;
+    Ok((bs, Bits {
+        offset,
+        ns,
+        cwr,
+        ece,
+        urg,
+        ack,
+        psh,
+        rst,
+        syn,
+        fin,
+    }))
+}
 
-            match options {
-                Some(options) => packet.header.options = options,
-                None => {},
-            }
+pub fn parse_tcp_packet(input: &[u8]) -> IResult<'_, TcpPacket<'_>> {
+    context("tcp", tcp_packet)(input)
+}
+
+fn tcp_packet(input: &[u8]) -> IResult<'_, TcpPacket<'_>> {
+    use std::cmp::min;
+    let (bs, src) = be_u16(input)?;
+    let (bs, dst) = be_u16(bs)?;
+    let (bs, seq) = be_u32(bs)?;
+    let (bs, ack) = be_u32(bs)?;
+    let (bs, bits) = bits(parse_bits)(bs)?;
+    let (bs, sz) = be_u16(bs)?;
+    let (bs, sum) = be_u16(bs)?;
+    let (bs, urgent) = be_u16(bs)?;
+    let (_, options) = cond(bits.offset > 5,
+        context("tcp.options", |bs| parse_options(bs, (4*bits.offset-20) as usize)))(bs)?;
+    // the body runs to the end of the input
+    Ok((&input[input.len()..], TcpPacket {
+        header: TcpHeader {
+            src,
+            dst,
+            seq,
+            ack,
+            flags: TcpFlags::from_bits(&bits),
+            window_sz: sz,
+            checksum: sum,
+            urgent,
+            options: options.unwrap_or_default(),
+        },
+        body: &input[min(4*bits.offset as usize, input.len())..],
+    }))
+}
 
-            packet
-        })
-    )
+/// Parses a TCP segment whose length is known from the IP layer, so that
+/// link layer padding or trailing data isn't mistaken for payload. The
+/// remainder is whatever follows the segment.
+pub fn parse_tcp_segment(bs: &[u8], len: usize) -> IResult<'_, TcpPacket<'_>> {
+    if bs.len() < len {
+        return Err(nom::Err::Incomplete(Needed::new(len - bs.len())));
+    }
+    let (segment, remainder) = bs.split_at(len);
+    let (_, packet) = parse_tcp_packet(segment)?;
+    if 4*packet.header.flags.offset as usize > len {
+        return Err(nom::Err::Error(ParseError {
+            input: bs,
+            kind: ErrorKind::Eof,
+            context: vec!["tcp.hdr_len", "tcp"],
+        }));
+    }
+    Ok((remainder, packet))
 }
 
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub struct TcpFlags {
     pub offset: u8,
     pub ns: bool,
@@ -127,83 +161,125 @@ impl TcpFlags {
             ns: bits.ns == 1,
         }
     }
+
+    /// The flags as they're laid out in the header's thirteenth byte, which
+    /// is also how NetFlow and IPFIX record them
+    pub fn to_u8(&self) -> u8 {
+        (self.cwr as u8) << 7 | (self.ece as u8) << 6 | (self.urg as u8) << 5 | (self.ack as u8) << 4 |
+        (self.psh as u8) << 3 | (self.rst as u8) << 2 | (self.syn as u8) << 1 | self.fin as u8
+    }
+}
+
+// tcpdump's flag letters, with . for ACK
+impl fmt::Display for TcpFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let letters = [
+            (self.fin, 'F'), (self.syn, 'S'), (self.rst, 'R'), (self.psh, 'P'),
+            (self.urg, 'U'), (self.ece, 'E'), (self.cwr, 'W'), (self.ack, '.'),
+        ];
+        for &(set, letter) in letters.iter() {
+            if set {
+                write!(f, "{}", letter)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// tcpdump style summary, like "Flags [S.], seq 1, ack 2, win 512, length 0"
+impl <'a> fmt::Display for TcpPacket<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let h = &self.header;
+        write!(f, "Flags [{}], seq {}", h.flags, h.seq)?;
+        if h.flags.ack {
+            write!(f, ", ack {}", h.ack)?;
+        }
+        write!(f, ", win {}, length {}", h.window_sz, self.body.len())
+    }
+}
+
+
+fn known_options(bs: &[u8]) -> IResult<'_, TcpOption<'_>> {
+    alt((
+        value(TcpOption::NoOperation, tag([0x01])),
+        map(preceded(tag([0x02, 0x04]), be_u16), TcpOption::MaximumSegmentSize),
+        map(preceded(tag([0x03, 0x03]), be_u8), TcpOption::WindowScale),
+        map(preceded(tag([0x08, 0x0a]), pair(be_u32, be_u32)),
+            |(ts_val, ts_ecr)| TcpOption::Timestamps(ts_val, ts_ecr)),
+        // https://tools.ietf.org/html/rfc2385
+        map(preceded(tag([0x13, 0x12]), take(16usize)), TcpOption::MD5),
+        other_option,
+    ))(bs)
+}
+
+fn other_option(bs: &[u8]) -> IResult<'_, TcpOption<'_>> {
+    let (bs, kind) = be_u8(bs)?;
+    let (bs, len) = verify(be_u8, |&len| len >= 2)(bs)?;
+    let (bs, data) = take(len - 2)(bs)?;
+    Ok((bs, TcpOption::Other(kind, len, data)))
+}
+
+fn end_of_options(bs: &[u8]) -> IResult<'_, Option<TcpOption<'_>>> {
+    alt((
+        value(None, eof),
+        value(Some(TcpOption::EndOfOptionList), tag([0x00])),
+    ))(bs)
+}
+
+fn parse_options(bs: &[u8], len: usize) -> IResult<'_, List<TcpOption<'_>>> {
+    let (rest, bs) = take(len)(bs)?;
+    let (_, (mut options, end)) = many_till_list(known_options, end_of_options)(bs)?;
+    options.extend(end);
+    Ok((rest, options))
+}
+
+/// Walks a TCP header's options in place, for when collecting them into a
+/// `Vec` costs too much. Stops after the end of option list, or at the
+/// first option that's malformed or doesn't fit.
+#[derive(Clone, Debug)]
+pub struct OptionIter<'a> {
+    bs: &'a [u8],
+}
+
+impl <'a> OptionIter<'a> {
+    /// Iterates over raw option bytes, everything in the header after its
+    /// first 20 bytes
+    pub fn new(bs: &'a [u8]) -> OptionIter<'a> {
+        OptionIter { bs }
+    }
+
+    /// Iterates over the options of a segment's header, without parsing
+    /// anything else in it
+    pub fn from_segment(segment: &'a [u8]) -> OptionIter<'a> {
+        use std::cmp::min;
+        let end = match segment.get(12) {
+            Some(&offset) => min(4*(offset >> 4) as usize, segment.len()),
+            None => 0,
+        };
+        OptionIter::new(if end > 20 { &segment[20..end] } else { &[] })
+    }
 }
 
+impl <'a> Iterator for OptionIter<'a> {
+    type Item = TcpOption<'a>;
 
-fn known_options<'a>(bs: &'a [u8]) -> IResult<&'a [u8], TcpOption<'a>, u32> {
-    alt!(
-        bs,
-        call!(eof_check) |
-        do_parse!(
-            _a: char!(0x01 as char) >>
-            (TcpOption::NoOperation)
-        ) |
-        do_parse!(
-            _a: char!(0x02 as char) >>
-            _a: char!(0x04 as char) >>
-            seg_size: be_u16 >>
-            (TcpOption::MaximumSegmentSize(seg_size))
-        ) |
-        do_parse!(
-            _a: char!(0x03 as char) >>
-            _a: char!(0x03 as char) >>
-            shift: be_u8 >>
-            (TcpOption::WindowScale(shift))
-        ) |
-        do_parse!(
-            _a: char!(0x08 as char) >>
-            _a: char!(0x0a as char) >>
-            ts_val: be_u32 >>
-            ts_ecr: be_u32 >>
-            (TcpOption::Timestamps(ts_val, ts_ecr))
-        ) |
-        do_parse!(
-            kind: be_u8 >>
-            len: be_u8 >>
-            data: take!(len - 2) >>
-            (TcpOption::Other(kind, len, data))
-        )
-    )
-}
-
-// FIXME: make this nicer
-fn eof_check<'a>(bs: &'a [u8]) -> IResult<&'a [u8], TcpOption<'a>, u32> {
-    cond_reduce!(bs, bs.len() == 0, value!(TcpOption::DummyOption))
-}
-
-fn end_of_options<'a>(bs: &'a [u8]) -> IResult<&'a [u8], TcpOption<'a>, u32> {
-    alt!(
-        bs,
-        call!(eof_check) |
-        do_parse!(
-            _a: char!(0x00 as char) >>
-            (TcpOption::EndOfOptionList)
-        )
-    )
-}
-
-fn parse_options<'a>(bs: &'a [u8], len: usize) -> IResult<&'a [u8], Vec<TcpOption<'a>>, u32> {
-    do_parse!(
-        &bs[0..len],
-        options: many_till!(
-            call!(known_options),
-            call!(end_of_options)
-        ) >>
-        ({
-            let (mut options, end) = options;
-            options.push(end);
-
-            options
-                .into_iter()
-                .filter(|o| match o {
-                    &TcpOption::DummyOption => false,
-                    _ => true,
-                }).collect()
-        })
-    )
+    fn next(&mut self) -> Option<TcpOption<'a>> {
+        let bs = self.bs;
+        self.bs = &[];
+        match bs.first() {
+            None => return None,
+            Some(&0x00) => return Some(TcpOption::EndOfOptionList),
+            Some(_) => {},
+        }
+        let (rest, option) = known_options(bs).ok()?;
+        self.bs = rest;
+        Some(option)
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub enum TcpOption<'a> {
     DummyOption,
     EndOfOptionList,
@@ -214,3 +290,673 @@ pub enum TcpOption<'a> {
     MD5(&'a [u8]),
     Other(u8, u8, &'a [u8]),
 }
+
+impl <'a> ToBytes for TcpOption<'a> {
+    fn write_with(&self, _fixup: Fixup, out: &mut Vec<u8>) {
+        match *self {
+            TcpOption::DummyOption => {},
+            TcpOption::EndOfOptionList => out.push(0x00),
+            TcpOption::NoOperation => out.push(0x01),
+            TcpOption::MaximumSegmentSize(mss) => {
+                out.extend_from_slice(&[0x02, 0x04]);
+                out.extend_from_slice(&mss.to_be_bytes());
+            },
+            TcpOption::WindowScale(shift) => out.extend_from_slice(&[0x03, 0x03, shift]),
+            TcpOption::Timestamps(ts_val, ts_ecr) => {
+                out.extend_from_slice(&[0x08, 0x0a]);
+                out.extend_from_slice(&ts_val.to_be_bytes());
+                out.extend_from_slice(&ts_ecr.to_be_bytes());
+            },
+            TcpOption::MD5(digest) => {
+                out.extend_from_slice(&[0x13, 0x12]);
+                out.extend_from_slice(digest);
+            },
+            TcpOption::Other(kind, len, data) => {
+                out.extend_from_slice(&[kind, len]);
+                out.extend_from_slice(data);
+            },
+        }
+    }
+}
+
+impl <'a> TcpHeader<'a> {
+    /// A header with no flags or options set, and the checksum left zero for
+    /// the IP layer's `ToBytes` to fill in
+    pub fn new(src: u16, dst: u16) -> TcpHeader<'a> {
+        TcpHeader {
+            src,
+            dst,
+            seq: 0,
+            ack: 0,
+            flags: TcpFlags {
+                offset: 5,
+                ns: false,
+                cwr: false,
+                ece: false,
+                urg: false,
+                ack: false,
+                psh: false,
+                rst: false,
+                syn: false,
+                fin: false,
+            },
+            window_sz: 65535,
+            checksum: 0,
+            urgent: 0,
+            options: List::new(),
+        }
+    }
+}
+
+// data offset in 32 bit words that fits these options
+fn data_offset(options: &[TcpOption]) -> u8 {
+    let mut bs = vec![];
+    for option in options {
+        option.write_bytes(&mut bs);
+    }
+    5 + bs.len().div_ceil(4) as u8
+}
+
+// the checksum needs the IP header, so it's fixed up by the IP layer
+impl <'a> ToBytes for TcpHeader<'a> {
+    fn write_with(&self, fixup: Fixup, out: &mut Vec<u8>) {
+        let start = out.len();
+        let f = &self.flags;
+        let offset = if fixup.lengths { data_offset(&self.options) } else { f.offset };
+        out.extend_from_slice(&self.src.to_be_bytes());
+        out.extend_from_slice(&self.dst.to_be_bytes());
+        out.extend_from_slice(&self.seq.to_be_bytes());
+        out.extend_from_slice(&self.ack.to_be_bytes());
+        out.push((offset << 4) | f.ns as u8);
+        out.push(f.to_u8());
+        out.extend_from_slice(&self.window_sz.to_be_bytes());
+        out.extend_from_slice(&self.checksum.to_be_bytes());
+        out.extend_from_slice(&self.urgent.to_be_bytes());
+        for option in &self.options {
+            option.write_with(fixup, out);
+        }
+        // pad the options out to the data offset
+        while out.len() - start < 4*offset as usize {
+            out.push(0);
+        }
+    }
+}
+
+impl <'a> TcpPacket<'a> {
+    /// Puts `body` behind `header`, setting the data offset to fit the
+    /// header's options
+    pub fn new(mut header: TcpHeader<'a>, body: &'a [u8]) -> TcpPacket<'a> {
+        header.flags.offset = data_offset(&header.options);
+        TcpPacket {
+            header,
+            body,
+        }
+    }
+}
+
+impl <'a> Layer<'a> for TcpPacket<'a> {
+    fn header_len(&self) -> usize {
+        4*self.header.flags.offset as usize
+    }
+
+    fn payload(&self) -> &'a [u8] {
+        self.body
+    }
+
+    fn next_protocol(&self) -> Option<NextProtocol> {
+        Some(NextProtocol::Ports(self.header.src, self.header.dst))
+    }
+}
+
+impl <'a> ToBytes for TcpPacket<'a> {
+    fn write_with(&self, fixup: Fixup, out: &mut Vec<u8>) {
+        self.header.write_with(fixup, out);
+        out.extend_from_slice(self.body);
+    }
+}
+
+// the common options, few enough to fit in the header
+#[cfg(feature = "arbitrary")]
+impl <'a> Arbitrary<'a> for TcpHeader<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<TcpHeader<'a>> {
+        let mut header = TcpHeader::new(u.arbitrary()?, u.arbitrary()?);
+        header.seq = u.arbitrary()?;
+        header.ack = u.arbitrary()?;
+        header.flags = u.arbitrary()?;
+        header.window_sz = u.arbitrary()?;
+        header.checksum = u.arbitrary()?;
+        header.urgent = u.arbitrary()?;
+        for _ in 0..u.int_in_range(0..=3)? {
+            header.options.push(match u.int_in_range(0..=3)? {
+                0 => TcpOption::NoOperation,
+                1 => TcpOption::MaximumSegmentSize(u.arbitrary()?),
+                2 => TcpOption::WindowScale(u.arbitrary()?),
+                _ => TcpOption::Timestamps(u.arbitrary()?, u.arbitrary()?),
+            });
+        }
+        header.flags.offset = data_offset(&header.options);
+        Ok(header)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl <'a> Arbitrary<'a> for TcpPacket<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<TcpPacket<'a>> {
+        let header = TcpHeader::arbitrary(u)?;
+        Ok(TcpPacket::new(header, u.arbitrary()?))
+    }
+}
+
+/// Computes the checksum for a TCP segment (header and payload) sent with
+/// the given IP header, ignoring whatever is currently in its checksum field
+pub fn compute_checksum(ip: &IpHeader, segment: &[u8]) -> Option<u16> {
+    if segment.len() < 20 {
+        return None;
+    }
+    Some(checksum::transport_checksum(ip, 6, segment, 16))
+}
+
+/// Checks a TCP segment's checksum, including the pseudo-header
+pub fn verify_checksum(ip: &IpHeader, segment: &[u8]) -> bool {
+    segment.len() >= 20 && checksum::verify_transport_checksum(ip, 6, segment)
+}
+
+/// Reassembled stream contents handed to a `Reassembler`'s callback
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum StreamEvent<'a> {
+    /// The next contiguous bytes of the stream
+    Data(&'a [u8]),
+    /// This many bytes were never seen and have been skipped over
+    Gap(u32),
+    /// All data up to the FIN has been delivered
+    Fin,
+    Reset,
+}
+
+const DEFAULT_MAX_BUFFERED: usize = 1 << 20;
+
+/// Puts the payloads of a TCP connection's segments back in order. Segments
+/// are fed in per direction, and each direction's byte stream is passed to
+/// the callback as soon as it is contiguous. Retransmitted and overlapping
+/// data is only delivered once; if too much out of order data builds up the
+/// missing range is given up on and reported as a gap.
+pub struct Reassembler<F> {
+    halves: [HalfStream; 2],
+    max_buffered: usize,
+    callback: F,
+}
+
+impl <F> Reassembler<F> where F: FnMut(Direction, StreamEvent) {
+    pub fn new(callback: F) -> Reassembler<F> {
+        Reassembler {
+            halves: [HalfStream::default(), HalfStream::default()],
+            max_buffered: DEFAULT_MAX_BUFFERED,
+            callback,
+        }
+    }
+
+    /// Sets how many out of order bytes a direction may hold before the
+    /// oldest gap is skipped
+    pub fn set_max_buffered(&mut self, max_buffered: usize) {
+        self.max_buffered = max_buffered;
+    }
+
+    pub fn ingest(&mut self, dir: Direction, packet: &TcpPacket) {
+        let half = &mut self.halves[dir as usize];
+        half.ingest(dir, packet, self.max_buffered, &mut self.callback);
+    }
+
+    /// Delivers everything still buffered for a direction, reporting any
+    /// holes as gaps; call this when no more packets are coming
+    pub fn flush(&mut self, dir: Direction) {
+        let half = &mut self.halves[dir as usize];
+        half.flush(dir, &mut self.callback);
+    }
+
+    /// Bytes currently held waiting for earlier data
+    pub fn buffered(&self, dir: Direction) -> usize {
+        self.halves[dir as usize].buffered
+    }
+
+    pub fn into_callback(self) -> F {
+        self.callback
+    }
+}
+
+/// How far sequence number `a` is past `b`, allowing for wraparound
+pub fn seq_diff(a: u32, b: u32) -> i32 {
+    a.wrapping_sub(b) as i32
+}
+
+#[derive(Clone, Debug, Default)]
+struct HalfStream {
+    next_seq: Option<u32>,
+    // how many bytes into the stream `next_seq` is, so pending data can be
+    // keyed without worrying about sequence numbers wrapping
+    offset: u64,
+    fin_seq: Option<u32>,
+    finished: bool,
+    // out of order data by stream offset; the ranges never overlap
+    pending: BTreeMap<u64, Vec<u8>>,
+    buffered: usize,
+}
+
+impl HalfStream {
+    fn ingest<F>(&mut self, dir: Direction, packet: &TcpPacket, max_buffered: usize, cb: &mut F)
+        where F: FnMut(Direction, StreamEvent) {
+        if self.finished {
+            return;
+        }
+
+        let flags = packet.header.flags;
+        if flags.rst {
+            self.finished = true;
+            self.pending.clear();
+            self.buffered = 0;
+            cb(dir, StreamEvent::Reset);
+            return;
+        }
+
+        let mut seq = packet.header.seq;
+        if flags.syn {
+            seq = seq.wrapping_add(1);
+        }
+        // picked up mid-connection; start from whatever we see first. A SYN
+        // after that is a retransmission and doesn't rewind the stream.
+        let next = match self.next_seq {
+            Some(next) => next,
+            None => {
+                self.next_seq = Some(seq);
+                seq
+            },
+        };
+        if flags.fin {
+            self.fin_seq = Some(seq.wrapping_add(packet.body.len() as u32));
+        }
+
+        let data = packet.body;
+        if !data.is_empty() {
+            let diff = seq_diff(seq, next);
+            if diff <= 0 {
+                let skip = next.wrapping_sub(seq) as usize;
+                if skip < data.len() {
+                    self.deliver(dir, &data[skip..], cb);
+                }
+            } else {
+                self.insert(self.offset + diff as u64, data);
+            }
+        }
+
+        self.drain(dir, cb);
+        while self.buffered > max_buffered {
+            self.skip_gap(dir, cb);
+            self.drain(dir, cb);
+        }
+        self.check_fin(dir, cb);
+    }
+
+    /// Buffers whatever part of `data` isn't already pending
+    fn insert(&mut self, mut start: u64, data: &[u8]) {
+        let end = start + data.len() as u64;
+        if let Some((&key, prev)) = self.pending.range(..=start).next_back() {
+            start = start.max(key + prev.len() as u64);
+        }
+        if start >= end {
+            return;
+        }
+
+        let mut pieces = Vec::new();
+        for (&key, held) in self.pending.range(start..end) {
+            if key > start {
+                pieces.push((start, key));
+            }
+            start = start.max(key + held.len() as u64);
+        }
+        if start < end {
+            pieces.push((start, end));
+        }
+
+        let base = end - data.len() as u64;
+        for (from, to) in pieces {
+            let piece = &data[(from - base) as usize..(to - base) as usize];
+            self.buffered += piece.len();
+            self.pending.insert(from, piece.to_vec());
+        }
+    }
+
+    fn deliver<F>(&mut self, dir: Direction, data: &[u8], cb: &mut F)
+        where F: FnMut(Direction, StreamEvent) {
+        let next = self.next_seq.unwrap_or(0);
+        self.next_seq = Some(next.wrapping_add(data.len() as u32));
+        self.offset += data.len() as u64;
+        cb(dir, StreamEvent::Data(data));
+    }
+
+    fn drain<F>(&mut self, dir: Direction, cb: &mut F) where F: FnMut(Direction, StreamEvent) {
+        while let Some(entry) = self.pending.first_entry() {
+            let key = *entry.key();
+            if key > self.offset {
+                return;
+            }
+            let data = entry.remove();
+            self.buffered -= data.len();
+            let skip = (self.offset - key) as usize;
+            if skip < data.len() {
+                self.deliver(dir, &data[skip..], cb);
+            }
+        }
+    }
+
+    fn skip_gap<F>(&mut self, dir: Direction, cb: &mut F) where F: FnMut(Direction, StreamEvent) {
+        let next = match self.next_seq {
+            Some(next) => next,
+            None => return,
+        };
+        if let Some(&key) = self.pending.keys().next() {
+            let gap = (key - self.offset) as u32;
+            self.next_seq = Some(next.wrapping_add(gap));
+            self.offset = key;
+            cb(dir, StreamEvent::Gap(gap));
+        }
+    }
+
+    fn check_fin<F>(&mut self, dir: Direction, cb: &mut F) where F: FnMut(Direction, StreamEvent) {
+        if self.finished {
+            return;
+        }
+        if let (Some(fin), Some(next)) = (self.fin_seq, self.next_seq) {
+            if seq_diff(next, fin) >= 0 {
+                self.finished = true;
+                cb(dir, StreamEvent::Fin);
+            }
+        }
+    }
+
+    fn flush<F>(&mut self, dir: Direction, cb: &mut F) where F: FnMut(Direction, StreamEvent) {
+        while !self.pending.is_empty() {
+            self.skip_gap(dir, cb);
+            self.drain(dir, cb);
+        }
+        self.check_fin(dir, cb);
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedTcpPacket {
+    pub header: OwnedTcpHeader,
+    pub body: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedTcpHeader {
+    pub src: u16,
+    pub dst: u16,
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: TcpFlags,
+    pub window_sz: u16,
+    pub checksum: u16,
+    pub urgent: u16,
+    pub options: Vec<OwnedTcpOption>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OwnedTcpOption {
+    DummyOption,
+    EndOfOptionList,
+    NoOperation,
+    MaximumSegmentSize(u16),
+    WindowScale(u8),
+    Timestamps(u32, u32),
+    MD5(Vec<u8>),
+    Other(u8, u8, Vec<u8>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OwnedStreamEvent {
+    Data(Vec<u8>),
+    Gap(u32),
+    Fin,
+    Reset,
+}
+
+impl <'a> IntoOwned for TcpPacket<'a> {
+    type Owned = OwnedTcpPacket;
+
+    fn into_owned(self) -> OwnedTcpPacket {
+        OwnedTcpPacket {
+            header: self.header.into_owned(),
+            body: self.body.into_owned(),
+        }
+    }
+}
+
+impl <'a> IntoOwned for TcpHeader<'a> {
+    type Owned = OwnedTcpHeader;
+
+    fn into_owned(self) -> OwnedTcpHeader {
+        OwnedTcpHeader {
+            src: self.src,
+            dst: self.dst,
+            seq: self.seq,
+            ack: self.ack,
+            flags: self.flags,
+            window_sz: self.window_sz,
+            checksum: self.checksum,
+            urgent: self.urgent,
+            options: self.options.into_owned(),
+        }
+    }
+}
+
+impl <'a> IntoOwned for TcpOption<'a> {
+    type Owned = OwnedTcpOption;
+
+    fn into_owned(self) -> OwnedTcpOption {
+        match self {
+            TcpOption::DummyOption => OwnedTcpOption::DummyOption,
+            TcpOption::EndOfOptionList => OwnedTcpOption::EndOfOptionList,
+            TcpOption::NoOperation => OwnedTcpOption::NoOperation,
+            TcpOption::MaximumSegmentSize(mss) => OwnedTcpOption::MaximumSegmentSize(mss),
+            TcpOption::WindowScale(shift) => OwnedTcpOption::WindowScale(shift),
+            TcpOption::Timestamps(val, echo) => OwnedTcpOption::Timestamps(val, echo),
+            TcpOption::MD5(digest) => OwnedTcpOption::MD5(digest.into_owned()),
+            TcpOption::Other(kind, len, data) => OwnedTcpOption::Other(kind, len, data.into_owned()),
+        }
+    }
+}
+
+impl <'a> IntoOwned for StreamEvent<'a> {
+    type Owned = OwnedStreamEvent;
+
+    fn into_owned(self) -> OwnedStreamEvent {
+        match self {
+            StreamEvent::Data(data) => OwnedStreamEvent::Data(data.into_owned()),
+            StreamEvent::Gap(len) => OwnedStreamEvent::Gap(len),
+            StreamEvent::Fin => OwnedStreamEvent::Fin,
+            StreamEvent::Reset => OwnedStreamEvent::Reset,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_md5_option() {
+        let segment = [
+            0xc0, 0x01, 0x00, 0xb3, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0xa0, 0x02, 0x40, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x13, 0x12, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09,
+            0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x01, 0x01,
+        ];
+        let (_, packet) = parse_tcp_packet(&segment).unwrap();
+        assert_eq!(packet.header.dst, 179);
+        assert!(packet.header.flags.syn);
+        match packet.header.options[0] {
+            TcpOption::MD5(digest) => assert_eq!(digest, &segment[22..38]),
+            x => panic!("expected MD5 option, got {:?}", x),
+        }
+        assert_eq!(packet.body.len(), 0);
+    }
+
+    #[test]
+    fn test_option_iter() {
+        let segment = [
+            0xd5, 0x1d, 0x00, 0x19, 0x6b, 0x7f, 0xc7, 0x2d, 0x00, 0x00, 0x00, 0x00, 0xa0, 0x02, 0x72, 0x10,
+            0xa2, 0xb5, 0x00, 0x00, 0x02, 0x04, 0x05, 0xb4, 0x04, 0x02, 0x08, 0x0a, 0x0a, 0x99, 0x44, 0x36,
+            0x00, 0x00, 0x00, 0x00, 0x01, 0x03, 0x03, 0x07,
+        ];
+        let options: Vec<_> = OptionIter::from_segment(&segment).collect();
+        assert_eq!(options.len(), 5);
+        match (options[0], options[1], options[2], options[3], options[4]) {
+            (TcpOption::MaximumSegmentSize(1460), TcpOption::Other(4, 2, &[]),
+             TcpOption::Timestamps(0x0a994436, 0), TcpOption::NoOperation, TcpOption::WindowScale(7)) => {},
+            x => panic!("unexpected options {:?}", x),
+        }
+
+        // a zero length would never advance
+        assert_eq!(OptionIter::new(&[0x01, 0x22, 0x00, 0x01]).count(), 1);
+    }
+
+    #[test]
+    fn test_segment_excludes_padding() {
+        let frame = [
+            0x00, 0x19, 0xd5, 0x1d, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x50, 0x18, 0x01, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x68, 0x69, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let (left, packet) = parse_tcp_segment(&frame, 22).unwrap();
+        assert_eq!(packet.body, b"hi");
+        assert_eq!(left, &[0, 0, 0, 0]);
+
+        match parse_tcp_segment(&frame, 12) {
+            Err(nom::Err::Error(_)) => {},
+            x => panic!("expected an error for a truncated header, got {:?}", x),
+        };
+    }
+
+    fn segment<'a>(seq: u32, syn: bool, fin: bool, body: &'a [u8]) -> TcpPacket<'a> {
+        TcpPacket {
+            header: TcpHeader {
+                src: 1234,
+                dst: 80,
+                seq,
+                ack: 0,
+                flags: TcpFlags {
+                    offset: 5,
+                    ns: false,
+                    cwr: false,
+                    ece: false,
+                    urg: false,
+                    ack: !syn,
+                    psh: false,
+                    rst: false,
+                    syn,
+                    fin,
+                },
+                window_sz: 1024,
+                checksum: 0,
+                urgent: 0,
+                options: List::new(),
+            },
+            body,
+        }
+    }
+
+    #[test]
+    fn test_reassembly() {
+        let mut events = Vec::new();
+        {
+            let mut reassembler = Reassembler::new(|dir, event| {
+                let event = match event {
+                    StreamEvent::Data(data) => String::from_utf8_lossy(data).into_owned(),
+                    x => format!("{:?}", x),
+                };
+                events.push((dir, event));
+            });
+            reassembler.ingest(Direction::Forward, &segment(99, true, false, b""));
+            reassembler.ingest(Direction::Forward, &segment(106, false, false, b"world"));
+            reassembler.ingest(Direction::Forward, &segment(100, false, false, b"hello "));
+            // retransmission overlapping already delivered data
+            reassembler.ingest(Direction::Forward, &segment(103, false, false, b"lo wor"));
+            reassembler.ingest(Direction::Forward, &segment(111, false, true, b"!"));
+            assert_eq!(reassembler.buffered(Direction::Forward), 0);
+        }
+        assert_eq!(events, vec![
+            (Direction::Forward, "hello ".to_string()),
+            (Direction::Forward, "world".to_string()),
+            (Direction::Forward, "!".to_string()),
+            (Direction::Forward, "Fin".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_reassembly_gap() {
+        let mut events = Vec::new();
+        {
+            let mut reassembler = Reassembler::new(|_, event| {
+                events.push(match event {
+                    StreamEvent::Data(data) => data.to_vec(),
+                    StreamEvent::Gap(len) => vec![b'?'; len as usize],
+                    _ => vec![],
+                });
+            });
+            reassembler.ingest(Direction::Reverse, &segment(0xfffffffe, false, false, b"ab"));
+            reassembler.ingest(Direction::Reverse, &segment(2, false, false, b"ef"));
+            reassembler.flush(Direction::Reverse);
+        }
+        assert_eq!(events.concat(), b"ab??ef".to_vec());
+    }
+
+    #[test]
+    fn test_reassembly_retransmitted_out_of_order() {
+        let mut events = Vec::new();
+        {
+            let mut reassembler = Reassembler::new(|_, event| {
+                events.push(match event {
+                    StreamEvent::Data(data) => data.to_vec(),
+                    StreamEvent::Gap(len) => vec![b'?'; len as usize],
+                    _ => vec![],
+                });
+            });
+            reassembler.set_max_buffered(8);
+            reassembler.ingest(Direction::Forward, &segment(0, false, false, b"ab"));
+            for _ in 0..10 {
+                reassembler.ingest(Direction::Forward, &segment(4, false, false, b"efgh"));
+            }
+            // already held in full
+            reassembler.ingest(Direction::Forward, &segment(5, false, false, b"f"));
+            reassembler.ingest(Direction::Forward, &segment(4, false, false, b"efgh"));
+            assert_eq!(reassembler.buffered(Direction::Forward), 4);
+            // overlaps what's held on both sides
+            reassembler.ingest(Direction::Forward, &segment(3, false, false, b"defghij"));
+            assert_eq!(reassembler.buffered(Direction::Forward), 7);
+            reassembler.ingest(Direction::Forward, &segment(2, false, false, b"c"));
+            assert_eq!(reassembler.buffered(Direction::Forward), 0);
+        }
+        assert_eq!(events.concat(), b"abcdefghij".to_vec());
+    }
+
+    #[test]
+    fn test_reassembly_syn_retransmitted() {
+        let mut events = Vec::new();
+        {
+            let mut reassembler = Reassembler::new(|_, event| {
+                if let StreamEvent::Data(data) = event {
+                    events.push(data.to_vec());
+                }
+            });
+            reassembler.ingest(Direction::Forward, &segment(99, true, false, b""));
+            reassembler.ingest(Direction::Forward, &segment(100, false, false, b"hello"));
+            reassembler.ingest(Direction::Forward, &segment(99, true, false, b""));
+            reassembler.ingest(Direction::Forward, &segment(100, false, false, b"hello"));
+            reassembler.ingest(Direction::Forward, &segment(105, false, false, b" world"));
+        }
+        assert_eq!(events.concat(), b"hello world".to_vec());
+    }
+}
@@ -0,0 +1,239 @@
+use std::net::Ipv4Addr;
+
+use dns::{Class, DomainName, Header, Message, Opcode, Qclass, Qtype, Query, QR, Rcode, Rdata,
+          ResourceRecord, Type};
+use ethernet::{EtherType, EthernetIIPacket, MacAddr};
+use ipv4::{self, Ipv4Protocol, Ipv4Packet};
+use tcp::{TcpHeader, TcpOption, TcpPacket};
+use udp::{UdpHeader, UdpPacket};
+use {List, ToBytes};
+
+/// One side of the generated traffic. The generators all return whole
+/// Ethernet frames, with lengths and checksums filled in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Endpoint {
+    pub mac: MacAddr,
+    pub ip: Ipv4Addr,
+}
+
+impl Endpoint {
+    pub fn new(mac: MacAddr, ip: Ipv4Addr) -> Endpoint {
+        Endpoint {
+            mac,
+            ip,
+        }
+    }
+
+    /// A locally administered MAC and an address from TEST-NET-1
+    pub fn client() -> Endpoint {
+        Endpoint::new(MacAddr([0x02, 0, 0, 0, 0, 0x01]), Ipv4Addr::new(192, 0, 2, 10))
+    }
+
+    /// A locally administered MAC and an address from TEST-NET-2
+    pub fn server() -> Endpoint {
+        Endpoint::new(MacAddr([0x02, 0, 0, 0, 0, 0x02]), Ipv4Addr::new(198, 51, 100, 1))
+    }
+}
+
+fn ip_frame(src: &Endpoint, dst: &Endpoint, header: ipv4::Header, body: &[u8]) -> Vec<u8> {
+    let ip = Ipv4Packet::new(header, body).to_bytes();
+    EthernetIIPacket::new(dst.mac, src.mac, EtherType::Ipv4, &ip).to_bytes()
+}
+
+/// A frame carrying one TCP segment
+pub fn tcp_frame(src: &Endpoint, dst: &Endpoint, header: TcpHeader, payload: &[u8]) -> Vec<u8> {
+    let segment = TcpPacket::new(header, payload).to_bytes();
+    ip_frame(src, dst, ipv4::Header::new(Ipv4Protocol::Tcp, src.ip, dst.ip), &segment)
+}
+
+/// A frame carrying one UDP datagram
+pub fn udp_frame(src: &Endpoint, dst: &Endpoint, src_port: u16, dst_port: u16, payload: &[u8]) -> Vec<u8> {
+    let datagram = UdpPacket::new(UdpHeader::new(src_port, dst_port), payload).to_bytes();
+    ip_frame(src, dst, ipv4::Header::new(Ipv4Protocol::Udp, src.ip, dst.ip), &datagram)
+}
+
+/// SYN, SYN-ACK and ACK opening a connection from `client_port` to
+/// `server_port`. The SYNs carry the MSS, SACK permitted, timestamp and
+/// window scale options a Linux stack would send.
+pub fn tcp_handshake(client: &Endpoint, server: &Endpoint, client_port: u16, server_port: u16)
+    -> Vec<Vec<u8>>
+{
+    let (client_isn, server_isn) = (0x1000_0000u32, 0x2000_0000u32);
+    let syn_options = |tsval, tsecr| list![
+        TcpOption::MaximumSegmentSize(1460),
+        TcpOption::Other(4, 2, &[]),
+        TcpOption::Timestamps(tsval, tsecr),
+        TcpOption::NoOperation,
+        TcpOption::WindowScale(7),
+    ];
+
+    let mut syn = TcpHeader::new(client_port, server_port);
+    syn.seq = client_isn;
+    syn.flags.syn = true;
+    syn.window_sz = 64240;
+    syn.options = syn_options(100, 0);
+
+    let mut syn_ack = TcpHeader::new(server_port, client_port);
+    syn_ack.seq = server_isn;
+    syn_ack.ack = client_isn.wrapping_add(1);
+    syn_ack.flags.syn = true;
+    syn_ack.flags.ack = true;
+    syn_ack.window_sz = 65160;
+    syn_ack.options = syn_options(200, 100);
+
+    let mut ack = TcpHeader::new(client_port, server_port);
+    ack.seq = client_isn.wrapping_add(1);
+    ack.ack = server_isn.wrapping_add(1);
+    ack.flags.ack = true;
+    ack.window_sz = 502;
+    ack.options = list![TcpOption::NoOperation, TcpOption::NoOperation, TcpOption::Timestamps(101, 200)];
+
+    vec![
+        tcp_frame(client, server, syn, &[]),
+        tcp_frame(server, client, syn_ack, &[]),
+        tcp_frame(client, server, ack, &[]),
+    ]
+}
+
+fn labels(name: &str) -> List<&[u8]> {
+    name.split('.').filter(|label| !label.is_empty()).map(str::as_bytes).collect()
+}
+
+/// An A query for `name` and its answer: a CNAME to "cdn." plus the
+/// parent of `name`, then an A record for that. Both answers use
+/// compression pointers, the first back to the question and the second
+/// into the CNAME's target, the way real servers write them.
+pub fn dns_exchange(client: &Endpoint, server: &Endpoint, id: u16, name: &str, addr: Ipv4Addr)
+    -> (Vec<u8>, Vec<u8>)
+{
+    let query = Message::query(id, name, Qtype::Type(Type::A));
+    let client_port = 49152 + id % 16384;
+    let query_frame = udp_frame(client, server, client_port, 53, &query.to_bytes());
+
+    // the question name starts right after the header, and the CNAME's
+    // target after the first answer's pointer and fixed fields
+    let qname = 12;
+    let parent = qname + 1 + labels(name).first().map_or(0, |label| label.len()) as u16;
+    let target = qname + query.questions[0].qname.wire_len() as u16 + 4 + 2 + 10;
+    let octets = addr.octets();
+    let response = Message {
+        header: Header {
+            id,
+            qr: QR::Response,
+            opcode: Opcode::Query,
+            aa: false,
+            tc: false,
+            rd: true,
+            ra: true,
+            rcode: Rcode::NoError,
+            qdcount: 1,
+            ancount: 2,
+            nscount: 0,
+            arcount: 0,
+        },
+        questions: vec![Query {
+            qname: DomainName::Labels(labels(name)),
+            qtype: Qtype::Type(Type::A),
+            qclass: Qclass::Class(Class::IN),
+        }],
+        answers: vec![
+            ResourceRecord {
+                name: DomainName::Pointer(qname),
+                typ: Type::Cname,
+                class: Class::IN,
+                ttl: 300,
+                rdata: Rdata::Cname(DomainName::LabelWithPointer(list![&b"cdn"[..]], parent)),
+            },
+            ResourceRecord {
+                name: DomainName::Pointer(target),
+                typ: Type::A,
+                class: Class::IN,
+                ttl: 60,
+                rdata: Rdata::A(&octets),
+            },
+        ],
+        authorities: vec![],
+        additional: vec![],
+    };
+    let response_frame = udp_frame(server, client, 53, client_port, &response.to_bytes());
+    (query_frame, response_frame)
+}
+
+/// A UDP datagram carrying `payload`, split into IPv4 fragments that fit
+/// in `mtu` bytes. The UDP checksum covers the whole datagram, so it only
+/// checks out after reassembly.
+pub fn fragmented_datagram(src: &Endpoint, dst: &Endpoint, src_port: u16, dst_port: u16, payload: &[u8],
+                           mtu: usize) -> Vec<Vec<u8>>
+{
+    let header = ipv4::Header::new(Ipv4Protocol::Udp, src.ip, dst.ip);
+    let datagram = {
+        let udp = UdpPacket::new(UdpHeader::new(src_port, dst_port), payload).to_bytes();
+        let bs = Ipv4Packet::new(header.clone(), &udp).to_bytes();
+        bs[20..].to_vec()
+    };
+
+    // fragment offsets count eight byte blocks
+    let step = (mtu.max(28) - 20) & !7;
+    let chunks: Vec<&[u8]> = datagram.chunks(step).collect();
+    chunks.iter().enumerate().map(|(i, chunk)| {
+        let mut header = header.clone();
+        header.id = 0x4242;
+        header.flags.mf = i + 1 < chunks.len();
+        header.fragment_off = (i*step/8) as u16;
+        let mut ip = Ipv4Packet::new(header, chunk).header.to_bytes();
+        // the checksum's already right, and only checks out in one piece
+        ip.extend_from_slice(chunk);
+        EthernetIIPacket::new(dst.mac, src.mac, EtherType::Ipv4, &ip).to_bytes()
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dissect::{parse_packet, ApplicationLayer, LinkType};
+    use {IpHeader, IpPacket, TransportLayerPacket};
+    use {dns, udp};
+
+    #[test]
+    fn test_generated_traffic() {
+        let (client, server) = (Endpoint::client(), Endpoint::server());
+
+        let handshake = tcp_handshake(&client, &server, 40000, 80);
+        let flags: Vec<_> = handshake.iter().map(|frame| {
+            match parse_packet(LinkType::Ethernet, frame).transport {
+                Some(TransportLayerPacket::Tcp(tcp)) => (tcp.header.flags.syn, tcp.header.flags.ack),
+                x => panic!("expected a TCP segment, got {:?}", x),
+            }
+        }).collect();
+        assert_eq!(flags, vec![(true, false), (true, true), (false, true)]);
+
+        let (query, response) = dns_exchange(&client, &server, 7, "www.example.com", Ipv4Addr::new(203, 0, 113, 5));
+        match parse_packet(LinkType::Ethernet, &query).application {
+            Some(ApplicationLayer::Dns(ref msg)) => assert_eq!(msg.to_string(), "query A www.example.com"),
+            ref x => panic!("expected DNS, got {:?}", x),
+        }
+        assert!(parse_packet(LinkType::Ethernet, &response).application.is_some());
+        let (_, msg) = dns::parse_dns_message_full(&response[42..]).unwrap();
+        assert_eq!(msg.to_string(), "response A www.example.com: Cname cdn.example.com, A 203.0.113.5");
+        assert_eq!(msg.answers[0].name.to_string(), "www.example.com");
+        assert_eq!(msg.answers[1].name.to_string(), "cdn.example.com");
+
+        let payload: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+        let fragments = fragmented_datagram(&client, &server, 5000, 5001, &payload, 1500);
+        assert_eq!(fragments.len(), 3);
+        let mut datagram = vec![];
+        for frame in &fragments {
+            match parse_packet(LinkType::Ethernet, frame).ip {
+                Some(IpPacket::V4(ip)) => {
+                    assert_eq!(ip.header.fragment_off as usize*8, datagram.len());
+                    datagram.extend_from_slice(ip.body);
+                },
+                x => panic!("expected IPv4, got {:?}", x),
+            }
+        }
+        assert_eq!(datagram.len(), 8 + payload.len());
+        let ip = ipv4::Header::new(Ipv4Protocol::Udp, client.ip, server.ip);
+        assert!(udp::verify_checksum(&IpHeader::V4(&ip), &datagram));
+        assert_eq!(&datagram[8..], &payload[..]);
+    }
+}
@@ -0,0 +1,129 @@
+use std::fmt;
+use std::ops::Add;
+use std::time::Duration;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// When a packet was captured, in seconds and nanoseconds since the Unix
+/// epoch. The pcap and pcapng readers and live capture fill one in for
+/// every packet, and dissected packets carry it along. It's the one time
+/// base for everything that measures time across packets: the samplers,
+/// replay, and the flow, connection, echo, passive DNS and multicast
+/// trackers all take the packet's `Timestamp` as the current time. Going by
+/// capture time rather than the wall clock, they work the same on live
+/// traffic and on saved captures. Spans of time, like round trips and
+/// timeouts, are `Duration`s.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Timestamp {
+    secs: u64,
+    // always below a second
+    nanos: u32,
+}
+
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+impl Timestamp {
+    /// Carries whole seconds out of `nanos`
+    pub fn new(secs: u64, nanos: u32) -> Timestamp {
+        Timestamp {
+            secs: secs.saturating_add(u64::from(nanos / NANOS_PER_SEC)),
+            nanos: nanos % NANOS_PER_SEC,
+        }
+    }
+
+    pub fn from_micros(micros: u64) -> Timestamp {
+        Timestamp::new(micros / 1_000_000, (micros % 1_000_000) as u32 * 1000)
+    }
+
+    pub fn from_nanos(nanos: u64) -> Timestamp {
+        Timestamp::new(nanos / u64::from(NANOS_PER_SEC), (nanos % u64::from(NANOS_PER_SEC)) as u32)
+    }
+
+    /// The time now, by the system clock, which bare wasm32 doesn't have
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    pub fn now() -> Timestamp {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().into()
+    }
+
+    pub fn secs(&self) -> u64 {
+        self.secs
+    }
+
+    pub fn subsec_nanos(&self) -> u32 {
+        self.nanos
+    }
+
+    pub fn as_secs_f64(&self) -> f64 {
+        self.secs as f64 + f64::from(self.nanos) / f64::from(NANOS_PER_SEC)
+    }
+
+    /// The time from `earlier` to this, or `None` if `earlier` is later,
+    /// which happens in captures merged from several interfaces
+    pub fn checked_duration_since(&self, earlier: Timestamp) -> Option<Duration> {
+        Duration::from(*self).checked_sub(earlier.into())
+    }
+
+    /// Like `checked_duration_since`, but zero if `earlier` is later
+    pub fn saturating_duration_since(&self, earlier: Timestamp) -> Duration {
+        self.checked_duration_since(earlier).unwrap_or_default()
+    }
+}
+
+impl From<Duration> for Timestamp {
+    /// From time since the epoch
+    fn from(d: Duration) -> Timestamp {
+        Timestamp { secs: d.as_secs(), nanos: d.subsec_nanos() }
+    }
+}
+
+impl From<Timestamp> for Duration {
+    /// Time since the epoch
+    fn from(ts: Timestamp) -> Duration {
+        Duration::new(ts.secs, ts.nanos)
+    }
+}
+
+/// Stops at the latest time a `Timestamp` can hold, which a timeout added
+/// to a garbled capture time can run past
+impl Add<Duration> for Timestamp {
+    type Output = Timestamp;
+
+    fn add(self, d: Duration) -> Timestamp {
+        Duration::from(self).checked_add(d).unwrap_or(Duration::MAX).into()
+    }
+}
+
+/// Seconds since the epoch to the nanosecond, like "1700000000.250000000"
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{:09}", self.secs, self.nanos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamp() {
+        let ts = Timestamp::new(1, 1_500_000_000);
+        assert_eq!((ts.secs(), ts.subsec_nanos()), (2, 500_000_000));
+        assert_eq!(ts, Timestamp::from_micros(2_500_000));
+        assert_eq!(ts, Timestamp::from_nanos(2_500_000_000));
+        assert_eq!(ts, Duration::from_millis(2500).into());
+        assert_eq!(ts.to_string(), "2.500000000");
+        assert_eq!(ts.as_secs_f64(), 2.5);
+
+        let later = ts + Duration::from_millis(750);
+        assert_eq!(later, Timestamp::new(3, 250_000_000));
+        assert!(later > ts);
+        assert_eq!(later.checked_duration_since(ts), Some(Duration::from_millis(750)));
+        assert_eq!(ts.checked_duration_since(later), None);
+        assert_eq!(ts.saturating_duration_since(later), Duration::from_secs(0));
+
+        let last = Timestamp::new(u64::MAX, 999_999_999);
+        assert_eq!(Timestamp::new(u64::MAX, 0) + Duration::from_secs(60), last);
+        assert_eq!(Timestamp::new(u64::MAX, NANOS_PER_SEC), Timestamp::new(u64::MAX, 0));
+    }
+}
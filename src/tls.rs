@@ -0,0 +1,781 @@
+use std::fmt;
+
+use nom::bytes::complete::take as take_complete;
+use nom::bytes::streaming::take;
+use nom::combinator::{all_consuming, cond, rest, verify};
+use nom::error::context;
+use nom::multi::many0;
+use nom::number::complete::{be_u8, be_u16, be_u24, be_u32};
+use nom::number::streaming::{be_u8 as be_u8_streaming, be_u16 as be_u16_streaming, be_u24 as be_u24_streaming};
+
+use error::IResult;
+use stream::{Decode, Decoder, DecodeError};
+use IntoOwned;
+
+// https://tools.ietf.org/html/rfc5246 and https://tools.ietf.org/html/rfc8446
+//
+// Records are framed by the length in their header, so `parse_tls_record`
+// is streaming and fails with `nom::Err::Incomplete` until a whole record
+// has arrived. Handshake messages can be split across records, or share
+// one, so they're put back together by `HandshakeReassembler`.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct TlsRecord<'a> {
+    pub content_type: ContentType,
+    /// 0x0303 for TLS 1.2, and for TLS 1.3 too, which only says what it
+    /// really is in the hello extensions
+    pub version: u16,
+    pub fragment: &'a [u8],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ContentType {
+    ChangeCipherSpec,
+    Alert,
+    Handshake,
+    ApplicationData,
+    Heartbeat,
+    Other(u8),
+}
+
+impl ContentType {
+    pub fn from_u8(v: u8) -> ContentType {
+        match v {
+            20 => ContentType::ChangeCipherSpec,
+            21 => ContentType::Alert,
+            22 => ContentType::Handshake,
+            23 => ContentType::ApplicationData,
+            24 => ContentType::Heartbeat,
+            x => ContentType::Other(x),
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            ContentType::ChangeCipherSpec => 20,
+            ContentType::Alert => 21,
+            ContentType::Handshake => 22,
+            ContentType::ApplicationData => 23,
+            ContentType::Heartbeat => 24,
+            ContentType::Other(x) => x,
+        }
+    }
+}
+
+/// "TLS 1.2" and the like, or the version in hex if it isn't one
+pub fn version_name(version: u16) -> String {
+    match version {
+        0x0300 => "SSL 3.0".to_string(),
+        0x0301..=0x0304 => format!("TLS 1.{}", version - 0x0301),
+        x => format!("{:#06x}", x),
+    }
+}
+
+impl <'a> fmt::Display for TlsRecord<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:?}, length {}", version_name(self.version), self.content_type, self.fragment.len())
+    }
+}
+
+pub fn parse_tls_record(bs: &[u8]) -> IResult<'_, TlsRecord<'_>> {
+    context("tls", tls_record)(bs)
+}
+
+fn tls_record(bs: &[u8]) -> IResult<'_, TlsRecord<'_>> {
+    let (bs, content_type) = be_u8_streaming(bs)?;
+    // SSL 3.0 and up, which all share this framing
+    let (bs, version) = verify(be_u16_streaming, |v| v >> 8 == 3)(bs)?;
+    let (bs, length) = be_u16_streaming(bs)?;
+    let (bs, fragment) = context("tls.length", take(length))(bs)?;
+    Ok((bs, TlsRecord {
+        content_type: ContentType::from_u8(content_type),
+        version,
+        fragment,
+    }))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum HandshakeType {
+    HelloRequest,
+    ClientHello,
+    ServerHello,
+    NewSessionTicket,
+    EncryptedExtensions,
+    Certificate,
+    ServerKeyExchange,
+    CertificateRequest,
+    ServerHelloDone,
+    CertificateVerify,
+    ClientKeyExchange,
+    Finished,
+    Other(u8),
+}
+
+impl HandshakeType {
+    pub fn from_u8(v: u8) -> HandshakeType {
+        match v {
+            0 => HandshakeType::HelloRequest,
+            1 => HandshakeType::ClientHello,
+            2 => HandshakeType::ServerHello,
+            4 => HandshakeType::NewSessionTicket,
+            8 => HandshakeType::EncryptedExtensions,
+            11 => HandshakeType::Certificate,
+            12 => HandshakeType::ServerKeyExchange,
+            13 => HandshakeType::CertificateRequest,
+            14 => HandshakeType::ServerHelloDone,
+            15 => HandshakeType::CertificateVerify,
+            16 => HandshakeType::ClientKeyExchange,
+            20 => HandshakeType::Finished,
+            x => HandshakeType::Other(x),
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            HandshakeType::HelloRequest => 0,
+            HandshakeType::ClientHello => 1,
+            HandshakeType::ServerHello => 2,
+            HandshakeType::NewSessionTicket => 4,
+            HandshakeType::EncryptedExtensions => 8,
+            HandshakeType::Certificate => 11,
+            HandshakeType::ServerKeyExchange => 12,
+            HandshakeType::CertificateRequest => 13,
+            HandshakeType::ServerHelloDone => 14,
+            HandshakeType::CertificateVerify => 15,
+            HandshakeType::ClientKeyExchange => 16,
+            HandshakeType::Finished => 20,
+            HandshakeType::Other(x) => x,
+        }
+    }
+}
+
+/// One handshake message, without its type and length
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Handshake<'a> {
+    pub typ: HandshakeType,
+    pub body: &'a [u8],
+}
+
+/// Parses a handshake message that's all in `bs`, like one that fits in a
+/// single record
+pub fn parse_handshake(bs: &[u8]) -> IResult<'_, Handshake<'_>> {
+    context("tls.handshake", handshake)(bs)
+}
+
+fn handshake(bs: &[u8]) -> IResult<'_, Handshake<'_>> {
+    let (bs, typ) = be_u8(bs)?;
+    let (bs, length) = be_u24(bs)?;
+    let (bs, body) = take_complete(length)(bs)?;
+    Ok((bs, Handshake {
+        typ: HandshakeType::from_u8(typ),
+        body,
+    }))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedHandshake {
+    pub typ: HandshakeType,
+    pub body: Vec<u8>,
+}
+
+impl OwnedHandshake {
+    pub fn to_borrowed(&self) -> Handshake<'_> {
+        Handshake {
+            typ: self.typ,
+            body: &self.body,
+        }
+    }
+}
+
+impl <'a> IntoOwned for Handshake<'a> {
+    type Owned = OwnedHandshake;
+
+    fn into_owned(self) -> OwnedHandshake {
+        OwnedHandshake {
+            typ: self.typ,
+            body: self.body.into_owned(),
+        }
+    }
+}
+
+/// A hello extension, before it's interpreted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Extension<'a> {
+    pub typ: u16,
+    pub data: &'a [u8],
+}
+
+pub const SERVER_NAME: u16 = 0;
+pub const ALPN: u16 = 16;
+pub const ENCRYPTED_CLIENT_HELLO: u16 = 0xfe0d;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ClientHello<'a> {
+    /// 0x0303 from TLS 1.3 clients too, which list the versions they
+    /// really support in an extension
+    pub version: u16,
+    pub random: &'a [u8],
+    pub session_id: &'a [u8],
+    pub cipher_suites: Vec<u16>,
+    pub compression_methods: &'a [u8],
+    pub extensions: Vec<Extension<'a>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ServerHello<'a> {
+    pub version: u16,
+    pub random: &'a [u8],
+    pub session_id: &'a [u8],
+    pub cipher_suite: u16,
+    pub compression_method: u8,
+    pub extensions: Vec<Extension<'a>>,
+}
+
+/// What an encrypted_client_hello extension says about itself. GREASE ECH,
+/// which clients send to make real ECH blend in, looks just like the real
+/// thing from outside.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Ech<'a> {
+    /// The ClientHello on the wire, with the real one encrypted inside
+    /// for the server named by `config_id`
+    Outer {
+        kdf_id: u16,
+        aead_id: u16,
+        config_id: u8,
+        /// The HPKE encapsulated key, empty after a HelloRetryRequest
+        enc: &'a [u8],
+        payload: &'a [u8],
+    },
+    /// Only ever seen decrypted, inside an outer ClientHello
+    Inner,
+}
+
+pub fn parse_client_hello(bs: &[u8]) -> IResult<'_, ClientHello<'_>> {
+    context("tls.client_hello", client_hello)(bs)
+}
+
+pub fn parse_server_hello(bs: &[u8]) -> IResult<'_, ServerHello<'_>> {
+    context("tls.server_hello", server_hello)(bs)
+}
+
+fn u8_prefixed(bs: &[u8]) -> IResult<'_, &[u8]> {
+    let (bs, length) = be_u8(bs)?;
+    take_complete(length)(bs)
+}
+
+fn u16_prefixed(bs: &[u8]) -> IResult<'_, &[u8]> {
+    let (bs, length) = be_u16(bs)?;
+    take_complete(length)(bs)
+}
+
+// missing altogether from hellos without any
+fn extensions(bs: &[u8]) -> IResult<'_, Vec<Extension<'_>>> {
+    if bs.is_empty() {
+        return Ok((bs, vec![]));
+    }
+    let (bs, extensions) = u16_prefixed(bs)?;
+    let (_, extensions) = all_consuming(many0(|bs| {
+        let (bs, typ) = be_u16(bs)?;
+        let (bs, data) = u16_prefixed(bs)?;
+        Ok((bs, Extension { typ, data }))
+    }))(extensions)?;
+    Ok((bs, extensions))
+}
+
+fn client_hello(bs: &[u8]) -> IResult<'_, ClientHello<'_>> {
+    let (bs, version) = be_u16(bs)?;
+    let (bs, random) = take_complete(32usize)(bs)?;
+    let (bs, session_id) = u8_prefixed(bs)?;
+    let (bs, cipher_suites) = u16_prefixed(bs)?;
+    let (_, cipher_suites) = all_consuming(many0(be_u16))(cipher_suites)?;
+    let (bs, compression_methods) = u8_prefixed(bs)?;
+    let (bs, extensions) = extensions(bs)?;
+    Ok((bs, ClientHello {
+        version,
+        random,
+        session_id,
+        cipher_suites,
+        compression_methods,
+        extensions,
+    }))
+}
+
+fn server_hello(bs: &[u8]) -> IResult<'_, ServerHello<'_>> {
+    let (bs, version) = be_u16(bs)?;
+    let (bs, random) = take_complete(32usize)(bs)?;
+    let (bs, session_id) = u8_prefixed(bs)?;
+    let (bs, cipher_suite) = be_u16(bs)?;
+    let (bs, compression_method) = be_u8(bs)?;
+    let (bs, extensions) = extensions(bs)?;
+    Ok((bs, ServerHello {
+        version,
+        random,
+        session_id,
+        cipher_suite,
+        compression_method,
+        extensions,
+    }))
+}
+
+fn extension<'a>(extensions: &[Extension<'a>], typ: u16) -> Option<&'a [u8]> {
+    extensions.iter().find(|e| e.typ == typ).map(|e| e.data)
+}
+
+// a list of u8 length prefixed names behind a u16 length
+fn protocol_names(bs: &[u8]) -> IResult<'_, Vec<&[u8]>> {
+    let (bs, list) = u16_prefixed(bs)?;
+    let (_, names) = all_consuming(many0(u8_prefixed))(list)?;
+    Ok((bs, names))
+}
+
+impl <'a> ClientHello<'a> {
+    /// The protocols the client offered with ALPN, most preferred first,
+    /// like "h2" and "http/1.1"
+    pub fn alpn(&self) -> Option<Vec<&'a [u8]>> {
+        let data = extension(&self.extensions, ALPN)?;
+        all_consuming(protocol_names)(data).ok().map(|(_, names)| names)
+    }
+
+    /// The host name from SNI. With ECH, this is the outer ClientHello's
+    /// public name rather than the server really being reached.
+    pub fn server_name(&self) -> Option<&'a [u8]> {
+        let data = extension(&self.extensions, SERVER_NAME)?;
+        let (_, list) = all_consuming(u16_prefixed)(data).ok()?;
+        let (_, names) = all_consuming(many0(|bs| {
+            let (bs, typ) = be_u8(bs)?;
+            let (bs, name) = u16_prefixed(bs)?;
+            Ok((bs, (typ, name)))
+        }))(list).ok()?;
+        // 0 is the only name type there is
+        names.into_iter().find(|&(typ, _)| typ == 0).map(|(_, name)| name)
+    }
+
+    pub fn ech(&self) -> Option<Ech<'a>> {
+        let data = extension(&self.extensions, ENCRYPTED_CLIENT_HELLO)?;
+        let (_, ech) = all_consuming(|bs| {
+            let (bs, typ) = be_u8(bs)?;
+            if typ == 1 {
+                return Ok((bs, Ech::Inner));
+            }
+            let (bs, kdf_id) = be_u16(bs)?;
+            let (bs, aead_id) = be_u16(bs)?;
+            let (bs, config_id) = be_u8(bs)?;
+            let (bs, enc) = u16_prefixed(bs)?;
+            let (bs, payload) = u16_prefixed(bs)?;
+            Ok((bs, Ech::Outer { kdf_id, aead_id, config_id, enc, payload }))
+        })(data).ok()?;
+        Some(ech)
+    }
+}
+
+impl <'a> ServerHello<'a> {
+    /// The protocol the server picked from the client's ALPN offer. TLS 1.3
+    /// servers send it in EncryptedExtensions instead, out of sight.
+    pub fn alpn(&self) -> Option<&'a [u8]> {
+        let data = extension(&self.extensions, ALPN)?;
+        match all_consuming(protocol_names)(data) {
+            Ok((_, ref names)) if names.len() == 1 => Some(names[0]),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes handshake messages from the concatenated fragments of handshake
+/// records, for use with `stream::Decoder`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HandshakeDecoder;
+
+impl Decode for HandshakeDecoder {
+    type Output = OwnedHandshake;
+
+    fn decode<'a>(&mut self, bs: &'a [u8]) -> IResult<'a, Option<OwnedHandshake>> {
+        let (bs, typ) = be_u8_streaming(bs)?;
+        let (bs, length) = be_u24_streaming(bs)?;
+        let (rest, body) = take(length)(bs)?;
+        Ok((rest, Some(Handshake { typ: HandshakeType::from_u8(typ), body }.into_owned())))
+    }
+}
+
+/// Puts handshake messages back together from the records one side of a
+/// connection sent, in order. Everything after a ChangeCipherSpec is
+/// encrypted, so it stops there; in TLS 1.3 that means only the hellos
+/// are seen, and the certificates are out of reach.
+pub struct HandshakeReassembler {
+    handshakes: Decoder<HandshakeDecoder>,
+    encrypted: bool,
+}
+
+impl Default for HandshakeReassembler {
+    fn default() -> HandshakeReassembler {
+        HandshakeReassembler::new()
+    }
+}
+
+impl HandshakeReassembler {
+    pub fn new() -> HandshakeReassembler {
+        HandshakeReassembler {
+            handshakes: Decoder::new(HandshakeDecoder),
+            encrypted: false,
+        }
+    }
+
+    /// Takes the next record; anything but a plaintext handshake record is
+    /// ignored
+    pub fn push(&mut self, record: &TlsRecord) {
+        match record.content_type {
+            ContentType::ChangeCipherSpec => self.encrypted = true,
+            ContentType::Handshake if !self.encrypted => self.handshakes.push(record.fragment),
+            _ => (),
+        }
+    }
+}
+
+impl Iterator for HandshakeReassembler {
+    type Item = Result<OwnedHandshake, DecodeError>;
+
+    fn next(&mut self) -> Option<Result<OwnedHandshake, DecodeError>> {
+        self.handshakes.next()
+    }
+}
+
+/// The DER certificates in a TLS 1.2 or earlier Certificate message, the
+/// sender's own first and then the chain it sends with it
+pub fn parse_certificate_list(bs: &[u8]) -> IResult<'_, Vec<&[u8]>> {
+    context("tls.certificate", certificate_list)(bs)
+}
+
+fn certificate_list(bs: &[u8]) -> IResult<'_, Vec<&[u8]>> {
+    let (bs, length) = be_u24(bs)?;
+    let (bs, list) = take_complete(length)(bs)?;
+    let (_, certificates) = all_consuming(many0(|bs| {
+        let (bs, length) = be_u24(bs)?;
+        take_complete(length)(bs)
+    }))(list)?;
+    Ok((bs, certificates))
+}
+
+/// The certificates a server sent, given the bytes it sent from the start
+/// of the connection. `None` if its Certificate message isn't there, which
+/// is always the case with TLS 1.3. With the x509 feature,
+/// `x509::parse_certificate` reads them.
+pub fn server_certificates(stream: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut reassembler = HandshakeReassembler::new();
+    let mut bs = stream;
+    while let Ok((rest, record)) = parse_tls_record(bs) {
+        reassembler.push(&record);
+        bs = rest;
+    }
+    let certificate = reassembler
+        .map_while(Result::ok)
+        .find(|handshake| handshake.typ == HandshakeType::Certificate)?;
+    let (_, certificates) = all_consuming(parse_certificate_list)(&certificate.body).ok()?;
+    Some(certificates.into_iter().map(|der| der.to_vec()).collect())
+}
+
+// DTLS, https://tools.ietf.org/html/rfc6347 and
+// https://tools.ietf.org/html/rfc9147
+//
+// Records come whole in UDP datagrams, one or more to a datagram, so the
+// DTLS parsers aren't streaming.
+
+/// A DTLS record, as any version sends it before encryption starts and
+/// DTLS 1.2 sends it after
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DtlsPlaintext<'a> {
+    pub content_type: ContentType,
+    /// One's complemented, 0xfefd for DTLS 1.2 and 0xfeff for 1.0
+    pub version: u16,
+    /// Counts cipher state changes
+    pub epoch: u16,
+    /// 48 bits, starting again from zero in each epoch
+    pub sequence: u64,
+    pub fragment: &'a [u8],
+}
+
+/// A DTLS 1.3 encrypted record, with its shortened unified header. The
+/// sequence number bits are encrypted too, so they mean nothing without
+/// the keys.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DtlsCiphertext<'a> {
+    pub connection_id: Option<&'a [u8]>,
+    /// The low two bits of the epoch
+    pub epoch_bits: u8,
+    /// The low 8 or 16 bits of the sequence number, as sent
+    pub sequence_bits: u16,
+    pub encrypted_record: &'a [u8],
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum DtlsRecord<'a> {
+    Plaintext(DtlsPlaintext<'a>),
+    Ciphertext(DtlsCiphertext<'a>),
+}
+
+/// Whether a UDP payload looks like DTLS rather than STUN, RTP or the
+/// others sharing a WebRTC port, going by its first byte as RFC 9443 has
+/// it
+pub fn is_dtls(bs: &[u8]) -> bool {
+    bs.first().is_some_and(|&b| (20..=63).contains(&b))
+}
+
+/// Parses one DTLS record from a datagram, leaving any after it
+pub fn parse_dtls_record(bs: &[u8]) -> IResult<'_, DtlsRecord<'_>> {
+    parse_dtls_record_with(0, bs)
+}
+
+/// Like `parse_dtls_record`, for a connection that negotiated connection
+/// IDs of `cid_len` bytes, which the unified header doesn't give the
+/// length of
+pub fn parse_dtls_record_with(cid_len: usize, bs: &[u8]) -> IResult<'_, DtlsRecord<'_>> {
+    context("dtls", |bs| dtls_record(cid_len, bs))(bs)
+}
+
+/// Parses every record in a datagram
+pub fn parse_dtls_datagram(bs: &[u8]) -> IResult<'_, Vec<DtlsRecord<'_>>> {
+    all_consuming(many0(parse_dtls_record))(bs)
+}
+
+fn dtls_record(cid_len: usize, bs: &[u8]) -> IResult<'_, DtlsRecord<'_>> {
+    match bs.first() {
+        // 001CSLEE
+        Some(&b) if b & 0xe0 == 0x20 => {
+            let (bs, _) = be_u8(bs)?;
+            let (bs, connection_id) = cond(b & 0x10 != 0, take_complete(cid_len))(bs)?;
+            let (bs, sequence_bits) = match b & 0x08 {
+                0 => be_u8(bs).map(|(bs, s)| (bs, s as u16))?,
+                _ => be_u16(bs)?,
+            };
+            // without a length, the record runs to the end of the datagram
+            let (bs, encrypted_record) = match b & 0x04 {
+                0 => rest(bs)?,
+                _ => u16_prefixed(bs)?,
+            };
+            Ok((bs, DtlsRecord::Ciphertext(DtlsCiphertext {
+                connection_id,
+                epoch_bits: b & 0x03,
+                sequence_bits,
+                encrypted_record,
+            })))
+        },
+        _ => {
+            let (bs, content_type) = be_u8(bs)?;
+            let (bs, version) = verify(be_u16, |v| v >> 8 == 0xfe)(bs)?;
+            let (bs, epoch) = be_u16(bs)?;
+            let (bs, sequence_high) = be_u16(bs)?;
+            let (bs, sequence_low) = be_u32(bs)?;
+            let (bs, fragment) = context("dtls.length", u16_prefixed)(bs)?;
+            Ok((bs, DtlsRecord::Plaintext(DtlsPlaintext {
+                content_type: ContentType::from_u8(content_type),
+                version,
+                epoch,
+                sequence: (sequence_high as u64) << 32 | sequence_low as u64,
+                fragment,
+            })))
+        },
+    }
+}
+
+/// A DTLS handshake message or a piece of one. Messages too big for a
+/// datagram are split into fragments that all carry the whole message's
+/// length.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DtlsHandshake<'a> {
+    pub typ: HandshakeType,
+    /// Of the whole message
+    pub length: u32,
+    /// Counts handshake messages, so retransmissions can be spotted
+    pub message_seq: u16,
+    pub fragment_offset: u32,
+    pub fragment: &'a [u8],
+}
+
+impl <'a> DtlsHandshake<'a> {
+    /// The message, if this fragment is all of it
+    pub fn handshake(&self) -> Option<Handshake<'a>> {
+        if self.fragment_offset == 0 && self.fragment.len() == self.length as usize {
+            Some(Handshake { typ: self.typ, body: self.fragment })
+        } else {
+            None
+        }
+    }
+}
+
+/// Parses one handshake fragment from a plaintext handshake record, which
+/// can hold several
+pub fn parse_dtls_handshake(bs: &[u8]) -> IResult<'_, DtlsHandshake<'_>> {
+    context("dtls.handshake", dtls_handshake)(bs)
+}
+
+fn dtls_handshake(bs: &[u8]) -> IResult<'_, DtlsHandshake<'_>> {
+    let (bs, typ) = be_u8(bs)?;
+    let (bs, length) = be_u24(bs)?;
+    let (bs, message_seq) = be_u16(bs)?;
+    let (bs, fragment_offset) = be_u24(bs)?;
+    let (bs, fragment_length) = be_u24(bs)?;
+    let (bs, fragment) = take_complete(fragment_length)(bs)?;
+    Ok((bs, DtlsHandshake {
+        typ: HandshakeType::from_u8(typ),
+        length,
+        message_seq,
+        fragment_offset,
+        fragment,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(content_type: u8, fragment: &[u8]) -> Vec<u8> {
+        let mut record = vec![content_type, 0x03, 0x03];
+        record.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+        record.extend_from_slice(fragment);
+        record
+    }
+
+    fn handshake(typ: u8, body: &[u8]) -> Vec<u8> {
+        let mut handshake = vec![typ];
+        handshake.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        handshake.extend_from_slice(body);
+        handshake
+    }
+
+    #[test]
+    fn test_server_certificates() {
+        // a server's flight with two stand-in certificates, the Certificate
+        // message split over two records and sharing the second with
+        // ServerHelloDone
+        let (leaf, intermediate) = (b"\x30\x03leaf".to_vec(), b"\x30\x05inter".to_vec());
+        let mut list = vec![];
+        for der in [&leaf, &intermediate] {
+            list.extend_from_slice(&(der.len() as u32).to_be_bytes()[1..]);
+            list.extend_from_slice(der);
+        }
+        let mut body = (list.len() as u32).to_be_bytes()[1..].to_vec();
+        body.extend_from_slice(&list);
+        let certificate = handshake(11, &body);
+        let (first, second) = certificate.split_at(10);
+        let mut flight = record(22, &handshake(2, &[0x03, 0x03]));
+        flight.extend_from_slice(&record(22, first));
+        flight.extend_from_slice(&record(22, &[second, &handshake(14, &[])[..]].concat()));
+
+        let (rest, first_record) = parse_tls_record(&flight).unwrap();
+        assert_eq!(first_record.to_string(), "TLS 1.2 Handshake, length 6");
+        assert_eq!(parse_handshake(first_record.fragment).unwrap().1.typ, HandshakeType::ServerHello);
+        assert!(matches!(parse_tls_record(&rest[..12]), Err(nom::Err::Incomplete(_))));
+
+        let mut reassembler = HandshakeReassembler::new();
+        let mut bs = &flight[..];
+        while let Ok((rest, record)) = parse_tls_record(bs) {
+            reassembler.push(&record);
+            bs = rest;
+        }
+        let types: Vec<_> = reassembler.map(|h| h.unwrap().typ).collect();
+        assert_eq!(types, vec![HandshakeType::ServerHello, HandshakeType::Certificate, HandshakeType::ServerHelloDone]);
+        assert_eq!(server_certificates(&flight), Some(vec![leaf, intermediate]));
+
+        // handshake records after a ChangeCipherSpec are encrypted
+        let mut encrypted = record(20, &[1]);
+        encrypted.extend_from_slice(&record(22, &certificate));
+        assert_eq!(server_certificates(&encrypted), None);
+        assert!(parse_tls_record(b"GET / HTTP/1.1\r\n").is_err());
+    }
+
+    #[test]
+    fn test_hello_extensions() {
+        // a ClientHello for example.com offering h2 and http/1.1, with an
+        // ECH extension for config 7
+        let mut extensions = vec![];
+        extensions.extend_from_slice(&[0x00, 0x00, 0x00, 0x10, 0x00, 0x0e, 0x00, 0x00, 0x0b]);
+        extensions.extend_from_slice(b"example.com");
+        extensions.extend_from_slice(&[0x00, 0x10, 0x00, 0x0e, 0x00, 0x0c, 0x02]);
+        extensions.extend_from_slice(b"h2\x08http/1.1");
+        extensions.extend_from_slice(&[0xfe, 0x0d, 0x00, 0x0e, 0x00, 0x00, 0x01, 0x00, 0x01, 0x07, 0x00, 0x02, 0xaa, 0xbb, 0x00, 0x02, 0xcc, 0xdd]);
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0x11; 32]);
+        body.extend_from_slice(&[0x00, 0x00, 0x04, 0x13, 0x01, 0x13, 0x02, 0x01, 0x00]);
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+        let (_, hello) = parse_client_hello(&body).unwrap();
+        assert_eq!(hello.cipher_suites, vec![0x1301, 0x1302]);
+        assert_eq!(hello.server_name(), Some(&b"example.com"[..]));
+        assert_eq!(hello.alpn(), Some(vec![&b"h2"[..], &b"http/1.1"[..]]));
+        assert_eq!(hello.ech(), Some(Ech::Outer {
+            kdf_id: 1,
+            aead_id: 1,
+            config_id: 7,
+            enc: &[0xaa, 0xbb],
+            payload: &[0xcc, 0xdd],
+        }));
+
+        // a TLS 1.2 ServerHello picking h2, and one without extensions
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0x22; 32]);
+        body.extend_from_slice(&[0x00, 0xc0, 0x2f, 0x00]);
+        let (_, hello) = parse_server_hello(&body).unwrap();
+        assert_eq!((hello.cipher_suite, hello.alpn()), (0xc02f, None));
+        body.extend_from_slice(&[0x00, 0x09, 0x00, 0x10, 0x00, 0x05, 0x00, 0x03, 0x02, b'h', b'2']);
+        let (_, hello) = parse_server_hello(&body).unwrap();
+        assert_eq!(hello.alpn(), Some(&b"h2"[..]));
+
+        // an old ClientHello without any extensions
+        let mut body = vec![0x03, 0x01];
+        body.extend_from_slice(&[0x33; 32]);
+        body.extend_from_slice(&[0x00, 0x00, 0x02, 0x00, 0x2f, 0x01, 0x00]);
+        let (_, hello) = parse_client_hello(&body).unwrap();
+        assert_eq!((hello.alpn(), hello.ech(), hello.server_name()), (None, None, None));
+    }
+
+    #[test]
+    fn test_dtls() {
+        // a DTLS 1.2 ClientHello in two fragments over two records in one
+        // datagram, then a DTLS 1.3 record with a connection ID, a 16 bit
+        // sequence number and a length
+        let mut datagram = vec![0x16, 0xfe, 0xfd, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x0e];
+        datagram.extend_from_slice(&[0x01, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0xfe, 0xfd]);
+        datagram.extend_from_slice(&[0x16, 0xfe, 0xfd, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x0e]);
+        datagram.extend_from_slice(&[0x01, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x02, 0xaa, 0xbb]);
+        datagram.extend_from_slice(&[0x3e, 0x01, 0x02, 0x12, 0x34, 0x00, 0x03, 0xc0, 0xff, 0xee]);
+        assert!(is_dtls(&datagram));
+        let (_, record) = parse_dtls_record_with(2, &datagram[54..]).unwrap();
+        assert_eq!(record, DtlsRecord::Ciphertext(DtlsCiphertext {
+            connection_id: Some(&[0x01, 0x02]),
+            epoch_bits: 2,
+            sequence_bits: 0x1234,
+            encrypted_record: &[0xc0, 0xff, 0xee],
+        }));
+        let (_, records) = parse_dtls_datagram(&datagram[..54]).unwrap();
+        let fragments: Vec<_> = records.iter().map(|record| match *record {
+            DtlsRecord::Plaintext(ref record) => {
+                assert_eq!((record.content_type, record.epoch), (ContentType::Handshake, 0));
+                parse_dtls_handshake(record.fragment).unwrap().1
+            },
+            _ => panic!("expected a plaintext record"),
+        }).collect();
+        assert_eq!((fragments[0].typ, fragments[0].length, fragments[0].message_seq), (HandshakeType::ClientHello, 4, 0));
+        assert_eq!((fragments[1].fragment_offset, fragments[1].fragment), (2, &[0xaa, 0xbb][..]));
+        assert_eq!(fragments[0].handshake(), None);
+
+        // a whole ChangeCipherSpec, and a TLS record that isn't DTLS
+        let (_, record) = parse_dtls_record(&[0x14, 0xfe, 0xfd, 0x00, 0x01, 0, 0, 0, 0, 0, 0x05, 0x00, 0x01, 0x01]).unwrap();
+        match record {
+            DtlsRecord::Plaintext(record) => assert_eq!((record.epoch, record.sequence), (1, 5)),
+            _ => panic!("expected a plaintext record"),
+        }
+        assert!(parse_dtls_record(&[0x16, 0x03, 0x03, 0x00, 0x00]).is_err());
+        assert!(!is_dtls(&[0x00, 0x01, 0x00, 0x00]));
+    }
+}
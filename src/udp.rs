@@ -1,6 +1,19 @@
-use nom::{be_u16};
+use std::fmt;
+
+use nom::bytes::complete::take;
+use nom::combinator::rest;
+use nom::error::{context, ErrorKind};
+use nom::number::complete::be_u16;
+#[cfg(feature = "arbitrary")]
+use arbitrary::{Arbitrary, Unstructured};
+
+use checksum;
+use error::{IResult, ParseError};
+use {Fixup, IntoOwned, IpHeader, Layer, NextProtocol, ToBytes};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
 pub struct UdpHeader {
     pub src: u16,
     pub dst: u16,
@@ -8,34 +21,196 @@ pub struct UdpHeader {
     pub checksum: u16,
 }
 
-named!(pub parse_udp_header<UdpHeader>,
-    do_parse!(
-        src: be_u16 >>
-        dst: be_u16 >>
-        len: be_u16 >>
-        checksum: be_u16 >>
-        (UdpHeader {
-            src: src,
-            dst: dst,
-            len: len,
-            checksum: checksum,
-        })
-    )
-);
+pub fn parse_udp_header(bs: &[u8]) -> IResult<'_, UdpHeader> {
+    context("udp", udp_header)(bs)
+}
+
+fn udp_header(bs: &[u8]) -> IResult<'_, UdpHeader> {
+    let (bs, src) = be_u16(bs)?;
+    let (bs, dst) = be_u16(bs)?;
+    let (bs, len) = be_u16(bs)?;
+    let (bs, checksum) = be_u16(bs)?;
+    Ok((bs, UdpHeader {
+        src,
+        dst,
+        len,
+        checksum,
+    }))
+}
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct UdpPacket<'a> {
     pub header: UdpHeader,
     pub body: &'a [u8],
 }
 
-named!(pub parse_udp_packet<UdpPacket>,
-    do_parse!(
-        header: parse_udp_header >>
-        body: take!(header.len-8) >>
-        (UdpPacket {
-            header: header,
-            body: body,
-        })
-    )
-);
+impl <'a> fmt::Display for UdpPacket<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "UDP, length {}", self.body.len())
+    }
+}
+
+pub fn parse_udp_packet(bs: &[u8]) -> IResult<'_, UdpPacket<'_>> {
+    context("udp", udp_packet)(bs)
+}
+
+fn udp_packet(bs: &[u8]) -> IResult<'_, UdpPacket<'_>> {
+    let (bs, header) = udp_header(bs)?;
+    let (bs, body) = context("udp.length", |bs| match header.len {
+        // a jumbogram, whose length comes from the IPv6 jumbo payload option
+        0 => rest(bs),
+        1..=7 => Err(nom::Err::Error(ParseError::new(bs, ErrorKind::LengthValue))),
+        len => take(len - 8)(bs),
+    })(bs)?;
+    Ok((bs, UdpPacket {
+        header,
+        body,
+    }))
+}
+
+impl UdpHeader {
+    /// A header with no checksum, for a datagram with an empty payload
+    pub fn new(src: u16, dst: u16) -> UdpHeader {
+        UdpHeader {
+            src,
+            dst,
+            len: 8,
+            checksum: 0,
+        }
+    }
+}
+
+// the checksum needs the IP header, so it's fixed up by the IP layer
+impl ToBytes for UdpHeader {
+    fn write_with(&self, _fixup: Fixup, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.src.to_be_bytes());
+        out.extend_from_slice(&self.dst.to_be_bytes());
+        out.extend_from_slice(&self.len.to_be_bytes());
+        out.extend_from_slice(&self.checksum.to_be_bytes());
+    }
+}
+
+// zero if it doesn't fit, as in a jumbogram
+fn datagram_len(body: &[u8]) -> u16 {
+    if body.len() + 8 > 0xffff {
+        0
+    } else {
+        8 + body.len() as u16
+    }
+}
+
+impl <'a> UdpPacket<'a> {
+    /// Puts `body` behind `header`, setting the header's length to match
+    pub fn new(mut header: UdpHeader, body: &'a [u8]) -> UdpPacket<'a> {
+        header.len = datagram_len(body);
+        UdpPacket {
+            header,
+            body,
+        }
+    }
+}
+
+impl <'a> Layer<'a> for UdpPacket<'a> {
+    fn header_len(&self) -> usize {
+        8
+    }
+
+    fn payload(&self) -> &'a [u8] {
+        self.body
+    }
+
+    fn next_protocol(&self) -> Option<NextProtocol> {
+        Some(NextProtocol::Ports(self.header.src, self.header.dst))
+    }
+}
+
+impl <'a> ToBytes for UdpPacket<'a> {
+    fn write_with(&self, fixup: Fixup, out: &mut Vec<u8>) {
+        let mut header = self.header;
+        if fixup.lengths {
+            header.len = datagram_len(self.body);
+        }
+        header.write_with(fixup, out);
+        out.extend_from_slice(self.body);
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl <'a> Arbitrary<'a> for UdpPacket<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<UdpPacket<'a>> {
+        let header = UdpHeader::arbitrary(u)?;
+        Ok(UdpPacket::new(header, u.arbitrary()?))
+    }
+}
+
+/// Computes the checksum for a UDP datagram (header and payload) sent with
+/// the given IP header, ignoring whatever is currently in its checksum field
+pub fn compute_checksum(ip: &IpHeader, datagram: &[u8]) -> Option<u16> {
+    if datagram.len() < 8 {
+        return None;
+    }
+    // a computed checksum of zero is transmitted as all ones
+    match checksum::transport_checksum(ip, 17, datagram, 6) {
+        0 => Some(0xffff),
+        x => Some(x),
+    }
+}
+
+/// Checks a UDP datagram's checksum, including the pseudo-header. A zero
+/// checksum means none was sent, which is only allowed over IPv4.
+pub fn verify_checksum(ip: &IpHeader, datagram: &[u8]) -> bool {
+    if datagram.len() < 8 {
+        return false;
+    }
+    let sent = ((datagram[6] as u16) << 8) | (datagram[7] as u16);
+    match (ip, sent) {
+        (&IpHeader::V4(_), 0) => true,
+        (&IpHeader::V6(_), 0) => false,
+        _ => checksum::verify_transport_checksum(ip, 17, datagram),
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedUdpPacket {
+    pub header: UdpHeader,
+    pub body: Vec<u8>,
+}
+
+impl <'a> IntoOwned for UdpPacket<'a> {
+    type Owned = OwnedUdpPacket;
+
+    fn into_owned(self) -> OwnedUdpPacket {
+        OwnedUdpPacket {
+            header: self.header,
+            body: self.body.into_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_length_edge_cases() {
+        // shorter than its own header
+        let short = [0x30, 0x39, 0x00, 0x35, 0x00, 0x04, 0x00, 0x00, 0x01, 0x02];
+        let err = match parse_udp_packet(&short) {
+            Err(nom::Err::Error(e)) => e,
+            other => panic!("unexpected result {:?}", other),
+        };
+        assert_eq!(err.kind, ErrorKind::LengthValue);
+        assert_eq!(err.field(), Some("udp.length"));
+
+        // a jumbogram takes the rest of the input
+        let jumbo = [0x30, 0x39, 0x00, 0x35, 0x00, 0x00, 0x00, 0x00, 0x01, 0x02, 0x03];
+        let (rest, packet) = parse_udp_packet(&jumbo).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(packet.body, &[0x01, 0x02, 0x03]);
+
+        let big = vec![0; 0x10000];
+        assert_eq!(UdpPacket::new(UdpHeader::new(1, 2), &big).header.len, 0);
+    }
+}
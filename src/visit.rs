@@ -0,0 +1,292 @@
+use nom::combinator::all_consuming;
+
+use dissect::{AppProtocol, LinkType, PortMap};
+use dns;
+use ethernet::{self, EtherType};
+use icmp;
+use ipv4;
+use ipv6;
+use tcp;
+use udp;
+use {IpPacket, TransportLayerPacket};
+
+/// What a `Visitor` wants after seeing a layer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Control {
+    /// Go on into the layers inside this one
+    #[default]
+    Continue,
+    /// Skip the rest of the packet
+    Stop,
+}
+
+/// Callbacks for `Walker`, one per layer, all doing nothing unless
+/// overridden. The layers are borrowed from the packet for as long as the
+/// callback runs, so only what the visitor copies out of them is kept.
+pub trait Visitor<'a> {
+    fn on_ethernet(&mut self, _eth: &ethernet::EthernetIIPacket<'a>) -> Control {
+        Control::Continue
+    }
+
+    fn on_ipv4(&mut self, _ip: &ipv4::Ipv4Packet<'a>) -> Control {
+        Control::Continue
+    }
+
+    fn on_ipv6(&mut self, _ip: &ipv6::Ipv6Packet<'a>) -> Control {
+        Control::Continue
+    }
+
+    fn on_tcp(&mut self, _tcp: &tcp::TcpPacket<'a>) -> Control {
+        Control::Continue
+    }
+
+    fn on_udp(&mut self, _udp: &udp::UdpPacket<'a>) -> Control {
+        Control::Continue
+    }
+
+    fn on_icmp(&mut self, _icmp: &icmp::IcmpPacket<'a>) -> Control {
+        Control::Continue
+    }
+
+    fn on_dns_header(&mut self, _header: &dns::Header) -> Control {
+        Control::Continue
+    }
+
+    fn on_dns_question(&mut self, _question: &dns::Query<'a>) -> Control {
+        Control::Continue
+    }
+
+    /// Names are as they were on the wire, compression pointers and all
+    fn on_dns_record(&mut self, _section: dns::Section, _record: &dns::ResourceRecord<'a>) -> Control {
+        Control::Continue
+    }
+
+    /// The bytes inside the innermost layer there's no callback for, like
+    /// the body of a TCP segment that isn't DNS
+    fn on_payload(&mut self, _payload: &'a [u8]) {}
+}
+
+/// Walks packets layer by layer like `parse_packet`, but hands each layer to
+/// a `Visitor` as it's parsed instead of building a `Packet`, and goes no
+/// further than the visitor wants. DNS is walked a record at a time with
+/// memory reused from one message to the next. DHCP and HTTP are left to
+/// `on_payload`, and Ethernet frames are taken to have no FCS.
+#[derive(Debug, Default)]
+pub struct Walker {
+    port_map: PortMap,
+    dns_mode: dns::Mode,
+    arena: dns::Arena,
+}
+
+impl Walker {
+    pub fn new() -> Walker {
+        Walker::default()
+    }
+
+    /// Changes which ports are taken to carry DNS and mDNS
+    pub fn set_port_map(&mut self, port_map: PortMap) {
+        self.port_map = port_map;
+    }
+
+    pub fn set_dns_mode(&mut self, mode: dns::Mode) {
+        self.dns_mode = mode;
+    }
+
+    pub fn walk<'a, V: Visitor<'a>>(&mut self, link_type: LinkType, bs: &'a [u8], visitor: &mut V) {
+        let ip_bytes = match link_type {
+            LinkType::Ethernet => match ethernet::parse_eth2_packet(bs) {
+                Ok((_, eth)) => {
+                    if visitor.on_ethernet(&eth) == Control::Stop {
+                        return;
+                    }
+                    match eth.ethertype {
+                        EtherType::Ipv4 | EtherType::Ipv6 => eth.body,
+                        _ => return visitor.on_payload(eth.body),
+                    }
+                },
+                Err(_) => return,
+            },
+            LinkType::Raw | LinkType::Ipv4 | LinkType::Ipv6 => bs,
+            LinkType::Other(_) => return visitor.on_payload(bs),
+        };
+        self.walk_ip(ip_bytes, visitor);
+    }
+
+    fn walk_ip<'a, V: Visitor<'a>>(&mut self, bs: &'a [u8], visitor: &mut V) {
+        let ip = match bs.first().map(|b| b >> 4) {
+            Some(4) => match all_consuming(ipv4::parse_ipv4_packet)(bs) {
+                Ok((_, ip)) => IpPacket::V4(ip),
+                Err(_) => return,
+            },
+            Some(6) => match all_consuming(ipv6::parse_ipv6_packet)(bs) {
+                Ok((_, ip)) => IpPacket::V6(ip),
+                Err(_) => return,
+            },
+            _ => return,
+        };
+        let control = match ip {
+            IpPacket::V4(ref ip4) => visitor.on_ipv4(ip4),
+            IpPacket::V6(ref ip6) => visitor.on_ipv6(ip6),
+        };
+        if control == Control::Stop {
+            return;
+        }
+        let transport = match ip.parse_inner() {
+            Some(transport) => transport,
+            None => return visitor.on_payload(ip.body()),
+        };
+        let (protocol, body) = match transport {
+            TransportLayerPacket::Tcp(ref tcp) => {
+                if visitor.on_tcp(tcp) == Control::Stop {
+                    return;
+                }
+                (self.port_map.tcp(tcp.header.dst).or_else(|| self.port_map.tcp(tcp.header.src)), tcp.body)
+            },
+            TransportLayerPacket::Udp(ref udp) => {
+                if visitor.on_udp(udp) == Control::Stop {
+                    return;
+                }
+                (self.port_map.udp(udp.header.dst).or_else(|| self.port_map.udp(udp.header.src)), udp.body)
+            },
+            TransportLayerPacket::Icmp(ref icmp) => {
+                if visitor.on_icmp(icmp) == Control::Stop {
+                    return;
+                }
+                (None, icmp.body)
+            },
+        };
+        match protocol {
+            Some(protocol @ AppProtocol::Dns) | Some(protocol @ AppProtocol::Mdns) => {
+                // DNS over TCP carries a two byte length prefix, which has
+                // to cover the rest of the segment like with parse_packet
+                let msg = match transport {
+                    TransportLayerPacket::Tcp(_)
+                        if body.len() > 2 && usize::from(u16::from_be_bytes([body[0], body[1]])) == body.len() - 2 =>
+                        &body[2..],
+                    TransportLayerPacket::Tcp(_) => return visitor.on_payload(body),
+                    _ => body,
+                };
+                let mode = if protocol == AppProtocol::Mdns { dns::Mode::Lenient } else { self.dns_mode };
+                self.walk_dns(mode, msg, visitor);
+            },
+            _ => visitor.on_payload(body),
+        }
+    }
+
+    // a message that turns out not to be DNS after all ends the walk
+    // wherever it stopped parsing
+    fn walk_dns<'a, V: Visitor<'a>>(&mut self, mode: dns::Mode, bs: &'a [u8], visitor: &mut V) {
+        let _ = dns::walk_dns_message(mode, &mut self.arena, bs, |entry| {
+            let control = match entry {
+                dns::Entry::Header(header) => visitor.on_dns_header(header),
+                dns::Entry::Question(question) => visitor.on_dns_question(question),
+                dns::Entry::Record(section, record) => visitor.on_dns_record(section, record),
+            };
+            control == Control::Continue
+        });
+    }
+}
+
+/// Walks one packet with a fresh `Walker`. Reusing a `Walker` saves the
+/// allocations DNS records need.
+pub fn walk_packet<'a, V: Visitor<'a>>(link_type: LinkType, bs: &'a [u8], visitor: &mut V) {
+    Walker::new().walk(link_type, bs, visitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+    use testgen::{self, Endpoint};
+
+    #[derive(Default)]
+    struct Counter {
+        layers: Vec<&'static str>,
+        addrs: Vec<IpAddr>,
+        payload: Option<Vec<u8>>,
+        stop_at_udp: bool,
+    }
+
+    impl <'a> Visitor<'a> for Counter {
+        fn on_ethernet(&mut self, _eth: &ethernet::EthernetIIPacket<'a>) -> Control {
+            self.layers.push("eth");
+            Control::Continue
+        }
+
+        fn on_ipv4(&mut self, _ip: &ipv4::Ipv4Packet<'a>) -> Control {
+            self.layers.push("ipv4");
+            Control::Continue
+        }
+
+        fn on_tcp(&mut self, _tcp: &tcp::TcpPacket<'a>) -> Control {
+            self.layers.push("tcp");
+            Control::Continue
+        }
+
+        fn on_udp(&mut self, _udp: &udp::UdpPacket<'a>) -> Control {
+            self.layers.push("udp");
+            if self.stop_at_udp {
+                Control::Stop
+            } else {
+                Control::Continue
+            }
+        }
+
+        fn on_dns_question(&mut self, _question: &dns::Query<'a>) -> Control {
+            self.layers.push("question");
+            Control::Continue
+        }
+
+        fn on_dns_record(&mut self, _section: dns::Section, record: &dns::ResourceRecord<'a>) -> Control {
+            self.layers.push("record");
+            if let dns::Rdata::A(octets) = record.rdata {
+                self.addrs.push(IpAddr::V4(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3])));
+            }
+            Control::Continue
+        }
+
+        fn on_payload(&mut self, payload: &'a [u8]) {
+            self.payload = Some(payload.to_vec());
+        }
+    }
+
+    #[test]
+    fn test_walk() {
+        let (client, server) = (Endpoint::client(), Endpoint::server());
+        let addr = Ipv4Addr::new(203, 0, 113, 7);
+        let (_, response) = testgen::dns_exchange(&client, &server, 1, "www.example.com", addr);
+        let mut walker = Walker::new();
+        let mut counter = Counter::default();
+        walker.walk(LinkType::Ethernet, &response, &mut counter);
+        assert_eq!(counter.layers, vec!["eth", "ipv4", "udp", "question", "record", "record"]);
+        assert_eq!(counter.addrs, vec![IpAddr::V4(addr)]);
+        assert_eq!(counter.payload, None);
+
+        let mut counter = Counter { stop_at_udp: true, ..Counter::default() };
+        walker.walk(LinkType::Ethernet, &response, &mut counter);
+        assert_eq!(counter.layers, vec!["eth", "ipv4", "udp"]);
+
+        let segment = testgen::tcp_frame(&client, &server, tcp::TcpHeader::new(40000, 443), b"hello");
+        let mut counter = Counter::default();
+        walk_packet(LinkType::Raw, &segment[14..], &mut counter);
+        assert_eq!(counter.layers, vec!["ipv4", "tcp"]);
+        assert_eq!(counter.payload, Some(b"hello".to_vec()));
+
+        // a DNS port, but not a length prefix
+        let segment = testgen::tcp_frame(&client, &server, tcp::TcpHeader::new(40000, 53), b"hello");
+        let mut counter = Counter::default();
+        walk_packet(LinkType::Ethernet, &segment, &mut counter);
+        assert_eq!(counter.layers, vec!["eth", "ipv4", "tcp"]);
+        assert_eq!(counter.payload, Some(b"hello".to_vec()));
+
+        // DNS can be turned off for a port like with parse_packet_with
+        let mut port_map = PortMap::new();
+        port_map.set_udp(53, None);
+        walker.set_port_map(port_map);
+        let mut counter = Counter::default();
+        walker.walk(LinkType::Ethernet, &response, &mut counter);
+        assert_eq!(counter.layers, vec!["eth", "ipv4", "udp"]);
+        assert_eq!(counter.payload.map(|payload| payload.len()), Some(response.len() - 42));
+    }
+}
@@ -0,0 +1,63 @@
+use js_sys::{Array, Object, Reflect};
+use wasm_bindgen::prelude::*;
+
+use dissect::{self, LinkType};
+use json::{self, Value};
+use pcapng::Reader;
+
+fn to_js(value: &Value) -> JsValue {
+    match *value {
+        Value::Null => JsValue::NULL,
+        Value::Bool(b) => JsValue::from_bool(b),
+        // past 2^53 it's rounded, but nothing dissected gets that big
+        Value::Number(n) => JsValue::from_f64(n as f64),
+        Value::String(ref s) => JsValue::from_str(s),
+        Value::Array(ref values) => values.iter().map(to_js).collect::<Array>().into(),
+        Value::Object(ref fields) => {
+            let object = Object::new();
+            for (key, value) in fields {
+                // only fails on frozen objects and proxies
+                let _ = Reflect::set(&object, &JsValue::from_str(key), &to_js(value));
+            }
+            object.into()
+        },
+    }
+}
+
+/// Dissects one packet, with `link_type` a LINKTYPE_ value, into the same
+/// object `json::to_json` describes
+#[wasm_bindgen]
+pub fn dissect(link_type: u32, data: &[u8]) -> JsValue {
+    to_js(&json::to_json(&dissect::parse_packet(LinkType::from_u32(link_type), data)))
+}
+
+/// A one line summary of a packet, as `Packet`'s `Display` writes it
+#[wasm_bindgen]
+pub fn summary(link_type: u32, data: &[u8]) -> String {
+    dissect::parse_packet(LinkType::from_u32(link_type), data).to_string()
+}
+
+/// Dissects every packet in a pcapng capture, for a viewer's packet list.
+/// Each entry has the packet's `timestamp` in milliseconds since the
+/// epoch, its `length` on the wire, a `summary`, and the dissection under
+/// `packet`. A capture that doesn't start with a Section Header Block
+/// gives an empty array.
+#[wasm_bindgen]
+pub fn dissect_pcapng(data: &[u8]) -> Array {
+    let reader = match Reader::new(data) {
+        Some(reader) => reader,
+        None => return Array::new(),
+    };
+    reader.map(|captured| {
+        let packet = dissect::parse_packet(captured.link_type, captured.data);
+        let entry = Value::Object(vec![
+            ("length".to_string(), Value::from(captured.original_len as u64)),
+            ("summary".to_string(), Value::from(packet.to_string())),
+            ("packet".to_string(), json::to_json(&packet)),
+        ]);
+        let entry = to_js(&entry);
+        let _ = Reflect::set(&entry, &JsValue::from_str("timestamp"),
+                             &JsValue::from_f64(captured.timestamp.as_secs_f64()*1000.0));
+        entry
+    }).collect()
+}
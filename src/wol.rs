@@ -0,0 +1,92 @@
+use nom::bytes::complete::{tag, take};
+use nom::combinator::{opt, verify};
+use nom::error::context;
+use nom::multi::count;
+
+use dissect::Packet;
+use error::IResult;
+use ethernet::{parse_mac_addr, EtherType, MacAddr};
+use TransportLayerPacket;
+
+/// A magic packet: six 0xff bytes, then the target's MAC address sixteen
+/// times, then maybe a SecureOn password of four or six bytes
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct MagicPacket<'a> {
+    pub mac: MacAddr,
+    pub password: Option<&'a [u8]>,
+}
+
+/// Parses a magic packet at the start of `bs`. The password is only taken
+/// if exactly four or six bytes follow.
+pub fn parse_magic_packet(bs: &[u8]) -> IResult<'_, MagicPacket<'_>> {
+    context("wol", magic_packet)(bs)
+}
+
+fn magic_packet(bs: &[u8]) -> IResult<'_, MagicPacket<'_>> {
+    let (bs, _) = tag([0xff; 6])(bs)?;
+    let (bs, mac) = parse_mac_addr(bs)?;
+    let (bs, _) = context("wol.mac", count(tag(&mac.0[..]), 15))(bs)?;
+    let (bs, password) = opt(verify(take(bs.len()), |p: &[u8]| p.len() == 4 || p.len() == 6))(bs)?;
+    Ok((bs, MagicPacket {
+        mac,
+        password,
+    }))
+}
+
+/// Looks for a magic packet anywhere in a payload, since some senders put
+/// a header of their own in front of it
+pub fn find_magic_packet(bs: &[u8]) -> Option<MagicPacket<'_>> {
+    (0..bs.len().saturating_sub(101))
+        .filter(|&i| bs[i..i + 6] == [0xff; 6])
+        .filter_map(|i| magic_packet(&bs[i..]).ok())
+        .map(|(_, magic)| magic)
+        .next()
+}
+
+/// Finds a magic packet in the body of a Wake-on-LAN Ethernet frame or in
+/// a UDP payload. They're commonly sent as UDP broadcasts to port 7 or 9,
+/// but can be sent to any other port.
+pub fn detect<'a>(packet: &Packet<'a>) -> Option<MagicPacket<'a>> {
+    if let Some(ref eth) = packet.ethernet {
+        if eth.ethertype == EtherType::WakeOnLan {
+            return find_magic_packet(eth.body);
+        }
+    }
+    match packet.transport {
+        Some(TransportLayerPacket::Udp(ref udp)) => find_magic_packet(udp.body),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dissect::{parse_packet, LinkType};
+    use ethernet::EthernetIIPacket;
+    use ToBytes;
+
+    #[test]
+    fn test_magic_packet() {
+        let mac = MacAddr([0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        let mut magic = vec![0xff; 6];
+        for _ in 0..16 {
+            magic.extend_from_slice(&mac.0);
+        }
+        let (_, parsed) = parse_magic_packet(&magic).unwrap();
+        assert_eq!(parsed.mac, mac);
+        assert_eq!(parsed.password, None);
+
+        magic.extend_from_slice(&[1, 2, 3, 4]);
+        let frame = EthernetIIPacket::new(MacAddr::BROADCAST, mac, EtherType::WakeOnLan, &magic).to_bytes();
+        let found = detect(&parse_packet(LinkType::Ethernet, &frame)).unwrap();
+        assert_eq!(found.mac, mac);
+        assert_eq!(found.password, Some(&[1, 2, 3, 4][..]));
+
+        // one repetition short
+        assert!(parse_magic_packet(&magic[..6 + 15*6]).is_err());
+        let mut prefixed = vec![0x42; 3];
+        prefixed.extend_from_slice(&magic[..102]);
+        assert_eq!(find_magic_packet(&prefixed).map(|m| m.mac), Some(mac));
+    }
+}
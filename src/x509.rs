@@ -0,0 +1,520 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use nom::bytes::complete::take;
+use nom::combinator::all_consuming;
+use nom::error::{context, ErrorKind};
+use nom::multi::many0;
+use nom::number::complete::be_u8;
+
+use error::{IResult, ParseError};
+
+// https://tools.ietf.org/html/rfc5280
+//
+// Only the DER that certificates use: definite lengths, and single byte
+// tags. Signatures aren't checked, and nothing is said about whether the
+// certificate chains up to anything; this is for inventory, not trust.
+
+const BOOLEAN: u8 = 0x01;
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OCTET_STRING: u8 = 0x04;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+
+/// An object identifier, still DER encoded; `Display` writes it dotted
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Oid<'a>(pub &'a [u8]);
+
+pub const COMMON_NAME: Oid<'static> = Oid(&[0x55, 0x04, 0x03]);
+pub const COUNTRY_NAME: Oid<'static> = Oid(&[0x55, 0x04, 0x06]);
+pub const LOCALITY_NAME: Oid<'static> = Oid(&[0x55, 0x04, 0x07]);
+pub const STATE_OR_PROVINCE_NAME: Oid<'static> = Oid(&[0x55, 0x04, 0x08]);
+pub const ORGANIZATION_NAME: Oid<'static> = Oid(&[0x55, 0x04, 0x0a]);
+pub const ORGANIZATIONAL_UNIT_NAME: Oid<'static> = Oid(&[0x55, 0x04, 0x0b]);
+pub const EMAIL_ADDRESS: Oid<'static> = Oid(&[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x01]);
+pub const SUBJECT_ALT_NAME: Oid<'static> = Oid(&[0x55, 0x1d, 0x11]);
+pub const RSA_ENCRYPTION: Oid<'static> = Oid(&[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01]);
+pub const EC_PUBLIC_KEY: Oid<'static> = Oid(&[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01]);
+pub const ED25519: Oid<'static> = Oid(&[0x2b, 0x65, 0x70]);
+pub const ED448: Oid<'static> = Oid(&[0x2b, 0x65, 0x71]);
+
+impl <'a> Oid<'a> {
+    /// The usual short name of the attribute types and key algorithms
+    /// above, like "CN" or "rsaEncryption"
+    pub fn name(&self) -> Option<&'static str> {
+        let names = [
+            (COMMON_NAME, "CN"),
+            (COUNTRY_NAME, "C"),
+            (LOCALITY_NAME, "L"),
+            (STATE_OR_PROVINCE_NAME, "ST"),
+            (ORGANIZATION_NAME, "O"),
+            (ORGANIZATIONAL_UNIT_NAME, "OU"),
+            (EMAIL_ADDRESS, "emailAddress"),
+            (SUBJECT_ALT_NAME, "subjectAltName"),
+            (RSA_ENCRYPTION, "rsaEncryption"),
+            (EC_PUBLIC_KEY, "id-ecPublicKey"),
+            (ED25519, "Ed25519"),
+            (ED448, "Ed448"),
+        ];
+        names.iter().find(|&&(oid, _)| oid == *self).map(|&(_, name)| name)
+    }
+}
+
+impl <'a> fmt::Display for Oid<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut arcs = vec![];
+        let mut arc = 0u64;
+        for &b in self.0 {
+            arc = arc << 7 | u64::from(b & 0x7f);
+            if b & 0x80 == 0 {
+                arcs.push(arc);
+                arc = 0;
+            }
+        }
+        // the first byte holds the first two arcs
+        match arcs.first() {
+            Some(&first) if first < 80 => write!(f, "{}.{}", first / 40, first % 40)?,
+            Some(&first) => write!(f, "2.{}", first - 80)?,
+            None => return Ok(()),
+        }
+        for arc in &arcs[1..] {
+            write!(f, ".{}", arc)?;
+        }
+        Ok(())
+    }
+}
+
+/// One attribute of a distinguished name, like CN=example.com
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Attribute<'a> {
+    pub typ: Oid<'a>,
+    pub value: String,
+}
+
+/// A distinguished name, with its attributes in the order the certificate
+/// has them, which is usually the reverse of the order RFC 4514 writes
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Name<'a>(pub Vec<Attribute<'a>>);
+
+impl <'a> Name<'a> {
+    /// The value of the first attribute of type `typ`
+    pub fn get(&self, typ: Oid) -> Option<&str> {
+        self.0.iter().find(|a| a.typ == typ).map(|a| &a.value[..])
+    }
+
+    pub fn common_name(&self) -> Option<&str> {
+        self.get(COMMON_NAME)
+    }
+}
+
+impl <'a> fmt::Display for Name<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, attribute) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            match attribute.typ.name() {
+                Some(name) => write!(f, "{}={}", name, attribute.value)?,
+                None => write!(f, "{}={}", attribute.typ, attribute.value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A UTC time from the validity period, to the second. Times order
+/// chronologically.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Time {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl Time {
+    /// Seconds since the Unix epoch
+    pub fn unix_time(&self) -> i64 {
+        // days from civil, from http://howardhinnant.github.io/date_algorithms.html
+        let month = i64::from(self.month);
+        let year = i64::from(self.year) - if month <= 2 { 1 } else { 0 };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + i64::from(self.day) - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146097 + day_of_era - 719468;
+        days * 86400 + i64::from(self.hour) * 3600 + i64::from(self.minute) * 60 + i64::from(self.second)
+    }
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+               self.year, self.month, self.day, self.hour, self.minute, self.second)
+    }
+}
+
+/// An entry of the subject alternative name extension
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum GeneralName<'a> {
+    Email(&'a str),
+    Dns(&'a str),
+    Uri(&'a str),
+    Ip(IpAddr),
+    /// Directory names and the rest, with their context specific tag
+    Other { tag: u8, value: &'a [u8] },
+}
+
+impl <'a> fmt::Display for GeneralName<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GeneralName::Email(email) => write!(f, "email:{}", email),
+            GeneralName::Dns(name) => write!(f, "DNS:{}", name),
+            GeneralName::Uri(uri) => write!(f, "URI:{}", uri),
+            GeneralName::Ip(ip) => write!(f, "IP:{}", ip),
+            GeneralName::Other { tag, .. } => write!(f, "[{}]", tag & 0x1f),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Extension<'a> {
+    pub id: Oid<'a>,
+    pub critical: bool,
+    pub value: &'a [u8],
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Certificate<'a> {
+    /// 1 to 3, as people write it rather than as it's encoded
+    pub version: u8,
+    /// Big endian, as encoded, so possibly with a leading zero byte
+    pub serial: &'a [u8],
+    pub signature_algorithm: Oid<'a>,
+    pub issuer: Name<'a>,
+    pub not_before: Time,
+    pub not_after: Time,
+    pub subject: Name<'a>,
+    pub public_key_algorithm: Oid<'a>,
+    /// The curve of EC keys; `None` for other keys
+    pub public_key_curve: Option<Oid<'a>>,
+    pub public_key: &'a [u8],
+    pub extensions: Vec<Extension<'a>>,
+}
+
+impl <'a> Certificate<'a> {
+    pub fn extension(&self, id: Oid) -> Option<&Extension<'a>> {
+        self.extensions.iter().find(|e| e.id == id)
+    }
+
+    /// The subject alternative names, or `None` if the extension is
+    /// missing or malformed
+    pub fn subject_alt_names(&self) -> Option<Vec<GeneralName<'a>>> {
+        let value = self.extension(SUBJECT_ALT_NAME)?.value;
+        all_consuming(element(SEQUENCE))(value).ok()
+            .and_then(|(_, names)| all_consuming(many0(general_name))(names).ok())
+            .map(|(_, names)| names)
+    }
+
+    /// The DNS names the certificate is for: its DNS subject alternative
+    /// names, or its common name if it has none
+    pub fn dns_names(&self) -> Vec<&str> {
+        let names: Vec<&str> = self.subject_alt_names().unwrap_or_default().into_iter()
+            .filter_map(|name| match name {
+                GeneralName::Dns(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+        if names.is_empty() {
+            self.subject.common_name().into_iter().collect()
+        } else {
+            names
+        }
+    }
+
+    /// Whether `unix_time` falls within the validity period
+    pub fn is_valid_at(&self, unix_time: i64) -> bool {
+        self.not_before.unix_time() <= unix_time && unix_time <= self.not_after.unix_time()
+    }
+
+    pub fn is_self_issued(&self) -> bool {
+        self.issuer == self.subject
+    }
+}
+
+impl <'a> fmt::Display for Certificate<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, issued by {}, valid {} to {}", self.subject, self.issuer, self.not_before, self.not_after)
+    }
+}
+
+/// Parses a DER certificate, like the ones `tls::server_certificates`
+/// returns
+pub fn parse_certificate(bs: &[u8]) -> IResult<'_, Certificate<'_>> {
+    context("x509", certificate)(bs)
+}
+
+// a DER element's tag and contents
+fn tlv(bs: &[u8]) -> IResult<'_, (u8, &[u8])> {
+    let (bs, tag) = be_u8(bs)?;
+    let (bs, first) = be_u8(bs)?;
+    let (bs, length) = match first {
+        0x00..=0x7f => (bs, usize::from(first)),
+        0x81..=0x84 => {
+            let (bs, length) = take(first & 0x7f)(bs)?;
+            (bs, length.iter().fold(0, |acc, &b| acc << 8 | usize::from(b)))
+        },
+        _ => return Err(nom::Err::Error(ParseError::new(bs, ErrorKind::LengthValue))),
+    };
+    let (bs, contents) = take(length)(bs)?;
+    Ok((bs, (tag, contents)))
+}
+
+// the contents of an element that has to have tag `tag`
+fn element<'a>(tag: u8) -> impl Fn(&'a [u8]) -> IResult<'a, &'a [u8]> {
+    move |bs| {
+        let (rest, (t, contents)) = tlv(bs)?;
+        if t != tag {
+            return Err(nom::Err::Error(ParseError::new(bs, ErrorKind::Tag)));
+        }
+        Ok((rest, contents))
+    }
+}
+
+// the contents of an element with tag `tag`, if it's the next one
+fn optional(tag: u8, bs: &[u8]) -> IResult<'_, Option<&[u8]>> {
+    match bs.first() {
+        Some(&t) if t == tag => {
+            let (bs, contents) = element(tag)(bs)?;
+            Ok((bs, Some(contents)))
+        },
+        _ => Ok((bs, None)),
+    }
+}
+
+// an AlgorithmIdentifier, and its parameters if they're an OID, like an
+// EC key's curve
+fn algorithm(bs: &[u8]) -> IResult<'_, (Oid<'_>, Option<Oid<'_>>)> {
+    let (bs, contents) = element(SEQUENCE)(bs)?;
+    let (parameters, oid) = element(OBJECT_IDENTIFIER)(contents)?;
+    let parameter = element(OBJECT_IDENTIFIER)(parameters).ok().map(|(_, p)| Oid(p));
+    Ok((bs, (Oid(oid), parameter)))
+}
+
+fn string(tag: u8, value: &[u8]) -> String {
+    match tag {
+        // BMPString
+        0x1e => {
+            let units: Vec<u16> = value.chunks(2).map(|c| u16::from(c[0]) << 8 | u16::from(*c.get(1).unwrap_or(&0))).collect();
+            String::from_utf16_lossy(&units)
+        },
+        // UniversalString
+        0x1c => value.chunks(4)
+            .map(|c| c.iter().fold(0u32, |acc, &b| acc << 8 | u32::from(b)))
+            .map(|c| ::std::char::from_u32(c).unwrap_or('\u{fffd}'))
+            .collect(),
+        // UTF8String, PrintableString, IA5String, and T61String as far as
+        // it's ASCII
+        _ => String::from_utf8_lossy(value).into_owned(),
+    }
+}
+
+fn name(bs: &[u8]) -> IResult<'_, Name<'_>> {
+    let (bs, contents) = element(SEQUENCE)(bs)?;
+    let (_, sets) = all_consuming(many0(element(SET)))(contents)?;
+    let mut attributes = vec![];
+    for set in sets {
+        let (_, pairs) = all_consuming(many0(element(SEQUENCE)))(set)?;
+        for pair in pairs {
+            let (value, typ) = element(OBJECT_IDENTIFIER)(pair)?;
+            let (_, (tag, value)) = all_consuming(tlv)(value)?;
+            attributes.push(Attribute { typ: Oid(typ), value: string(tag, value) });
+        }
+    }
+    Ok((bs, Name(attributes)))
+}
+
+fn time(bs: &[u8]) -> IResult<'_, Time> {
+    let (rest, (tag, value)) = tlv(bs)?;
+    let digits = |s: &[u8]| s.iter().try_fold(0u16, |acc, &b| match b {
+        b'0'..=b'9' => Some(acc * 10 + u16::from(b - b'0')),
+        _ => None,
+    });
+    // RFC 5280 4.1.2.5: UTCTime years from 50 are 19xx, and both are in
+    // UTC to the second
+    let error = || nom::Err::Error(ParseError::new(bs, ErrorKind::Verify));
+    let (year, value) = match (tag, value.len()) {
+        (UTC_TIME, 13) => (digits(&value[..2]).map(|y| if y >= 50 { 1900 + y } else { 2000 + y }), &value[2..]),
+        (GENERALIZED_TIME, 15) => (digits(&value[..4]), &value[4..]),
+        _ => return Err(error()),
+    };
+    let field = |i: usize| digits(&value[i..i + 2]).map(|v| v as u8);
+    match (year, field(0), field(2), field(4), field(6), field(8), value[10]) {
+        (Some(year), Some(month @ 1..=12), Some(day @ 1..=31), Some(hour @ 0..=23), Some(minute @ 0..=59),
+         Some(second @ 0..=60), b'Z') => Ok((rest, Time { year, month, day, hour, minute, second })),
+        _ => Err(error()),
+    }
+}
+
+fn extensions(bs: &[u8]) -> IResult<'_, Vec<Extension<'_>>> {
+    let (_, contents) = all_consuming(element(SEQUENCE))(bs)?;
+    let (_, extensions) = all_consuming(many0(element(SEQUENCE)))(contents)?;
+    let extensions = extensions.into_iter().map(|extension| {
+        let (extension, id) = element(OBJECT_IDENTIFIER)(extension)?;
+        let (extension, critical) = optional(BOOLEAN, extension)?;
+        let (_, value) = all_consuming(element(OCTET_STRING))(extension)?;
+        let critical = critical.is_some_and(|c| c.iter().any(|&b| b != 0));
+        Ok(Extension { id: Oid(id), critical, value })
+    }).collect::<Result<_, _>>()?;
+    Ok((bs, extensions))
+}
+
+fn general_name(bs: &[u8]) -> IResult<'_, GeneralName<'_>> {
+    let (bs, (tag, value)) = tlv(bs)?;
+    let text = ::std::str::from_utf8(value).ok();
+    let name = match (tag, text, value.len()) {
+        (0x81, Some(email), _) => GeneralName::Email(email),
+        (0x82, Some(name), _) => GeneralName::Dns(name),
+        (0x86, Some(uri), _) => GeneralName::Uri(uri),
+        (0x87, _, 4) => GeneralName::Ip(IpAddr::V4(Ipv4Addr::new(value[0], value[1], value[2], value[3]))),
+        (0x87, _, 16) => {
+            let mut octets = [0; 16];
+            octets.copy_from_slice(value);
+            GeneralName::Ip(IpAddr::V6(Ipv6Addr::from(octets)))
+        },
+        _ => GeneralName::Other { tag, value },
+    };
+    Ok((bs, name))
+}
+
+fn certificate(bs: &[u8]) -> IResult<'_, Certificate<'_>> {
+    let (bs, contents) = element(SEQUENCE)(bs)?;
+    // the signature algorithm and signature that follow aren't needed
+    let (_, tbs) = element(SEQUENCE)(contents)?;
+    let (tbs, version) = optional(0xa0, tbs)?;
+    let version = match version {
+        Some(version) => {
+            let (_, version) = all_consuming(element(INTEGER))(version)?;
+            version.last().map_or(1, |&v| v.saturating_add(1))
+        },
+        None => 1,
+    };
+    let (tbs, serial) = element(INTEGER)(tbs)?;
+    let (tbs, (signature_algorithm, _)) = algorithm(tbs)?;
+    let (tbs, issuer) = name(tbs)?;
+    let (tbs, validity) = element(SEQUENCE)(tbs)?;
+    let (validity, not_before) = time(validity)?;
+    let (_, not_after) = all_consuming(time)(validity)?;
+    let (tbs, subject) = name(tbs)?;
+    let (tbs, spki) = element(SEQUENCE)(tbs)?;
+    let (spki, (public_key_algorithm, public_key_curve)) = algorithm(spki)?;
+    let (_, public_key) = all_consuming(element(BIT_STRING))(spki)?;
+    // skipping the count of unused bits, which is zero for keys
+    let public_key = public_key.get(1..).unwrap_or(&[]);
+    let public_key_curve = public_key_curve.filter(|_| public_key_algorithm == EC_PUBLIC_KEY);
+    // the issuer and subject unique identifiers nobody uses
+    let (tbs, _) = optional(0x81, tbs)?;
+    let (tbs, _) = optional(0x82, tbs)?;
+    let (_, extensions) = match optional(0xa3, tbs)? {
+        (tbs, Some(contents)) => (tbs, extensions(contents)?.1),
+        (tbs, None) => (tbs, vec![]),
+    };
+    Ok((bs, Certificate {
+        version,
+        serial,
+        signature_algorithm,
+        issuer,
+        not_before,
+        not_after,
+        subject,
+        public_key_algorithm,
+        public_key_curve,
+        public_key,
+        extensions,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_certificate() {
+        // openssl req -x509 with a P-256 key, CN=example.com and a few
+        // subject alternative names
+        let der = [
+            0x30, 0x82, 0x01, 0xab, 0x30, 0x82, 0x01, 0x52, 0xa0, 0x03, 0x02, 0x01, 0x02, 0x02, 0x02, 0x12,
+            0x34, 0x30, 0x0a, 0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02, 0x30, 0x35, 0x31,
+            0x0b, 0x30, 0x09, 0x06, 0x03, 0x55, 0x04, 0x06, 0x13, 0x02, 0x55, 0x53, 0x31, 0x10, 0x30, 0x0e,
+            0x06, 0x03, 0x55, 0x04, 0x0a, 0x0c, 0x07, 0x45, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x31, 0x14,
+            0x30, 0x12, 0x06, 0x03, 0x55, 0x04, 0x03, 0x0c, 0x0b, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65,
+            0x2e, 0x63, 0x6f, 0x6d, 0x30, 0x20, 0x17, 0x0d, 0x32, 0x34, 0x30, 0x31, 0x30, 0x31, 0x30, 0x30,
+            0x30, 0x30, 0x30, 0x30, 0x5a, 0x18, 0x0f, 0x32, 0x30, 0x35, 0x30, 0x30, 0x31, 0x30, 0x31, 0x30,
+            0x30, 0x30, 0x30, 0x30, 0x30, 0x5a, 0x30, 0x35, 0x31, 0x0b, 0x30, 0x09, 0x06, 0x03, 0x55, 0x04,
+            0x06, 0x13, 0x02, 0x55, 0x53, 0x31, 0x10, 0x30, 0x0e, 0x06, 0x03, 0x55, 0x04, 0x0a, 0x0c, 0x07,
+            0x45, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x31, 0x14, 0x30, 0x12, 0x06, 0x03, 0x55, 0x04, 0x03,
+            0x0c, 0x0b, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x2e, 0x63, 0x6f, 0x6d, 0x30, 0x59, 0x30,
+            0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a, 0x86, 0x48, 0xce,
+            0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00, 0x04, 0x84, 0x6a, 0x82, 0xdd, 0x52, 0x44, 0xf2, 0x29,
+            0x9f, 0x44, 0x01, 0x71, 0xb3, 0xa7, 0x3e, 0x89, 0xd4, 0x7d, 0x63, 0x64, 0xbf, 0x78, 0xe3, 0xcb,
+            0x6c, 0x7c, 0x1a, 0xda, 0xa9, 0x70, 0xb6, 0x81, 0x58, 0x5d, 0x14, 0x81, 0xce, 0xc6, 0x56, 0x53,
+            0x1b, 0x96, 0x27, 0x1b, 0xf8, 0xee, 0xa1, 0x3f, 0xfb, 0x3a, 0xbe, 0xb4, 0xb8, 0xa3, 0x45, 0xdb,
+            0x5f, 0x55, 0x04, 0x1f, 0xbd, 0xdd, 0x8b, 0x7c, 0xa3, 0x50, 0x30, 0x4e, 0x30, 0x3e, 0x06, 0x03,
+            0x55, 0x1d, 0x11, 0x04, 0x37, 0x30, 0x35, 0x82, 0x0b, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65,
+            0x2e, 0x63, 0x6f, 0x6d, 0x82, 0x0d, 0x2a, 0x2e, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x2e,
+            0x63, 0x6f, 0x6d, 0x87, 0x04, 0xc0, 0x00, 0x02, 0x01, 0x81, 0x11, 0x61, 0x64, 0x6d, 0x69, 0x6e,
+            0x40, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x2e, 0x63, 0x6f, 0x6d, 0x30, 0x0c, 0x06, 0x03,
+            0x55, 0x1d, 0x13, 0x01, 0x01, 0xff, 0x04, 0x02, 0x30, 0x00, 0x30, 0x0a, 0x06, 0x08, 0x2a, 0x86,
+            0x48, 0xce, 0x3d, 0x04, 0x03, 0x02, 0x03, 0x47, 0x00, 0x30, 0x44, 0x02, 0x20, 0x36, 0xa3, 0x38,
+            0x0c, 0x57, 0x81, 0x42, 0xcf, 0x32, 0x60, 0xe3, 0x3d, 0x9b, 0xdd, 0x9b, 0x23, 0x7b, 0x1e, 0x6a,
+            0xf3, 0x12, 0x2d, 0xf5, 0x7f, 0xfa, 0x43, 0x45, 0x41, 0xc7, 0x10, 0x1e, 0x5e, 0x02, 0x20, 0x3a,
+            0x4b, 0x58, 0x42, 0xbd, 0xf9, 0xfa, 0xa5, 0x5b, 0x4a, 0x58, 0x64, 0xc2, 0xfa, 0x41, 0x51, 0xd9,
+            0xe7, 0x68, 0x20, 0x9f, 0x66, 0x87, 0x48, 0x34, 0x94, 0xae, 0x50, 0x26, 0xf4, 0x6c, 0xb6,
+        ];
+        let (rest, certificate) = parse_certificate(&der).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(certificate.version, 3);
+        assert_eq!(certificate.serial, &[0x12, 0x34]);
+        assert_eq!(certificate.signature_algorithm.to_string(), "1.2.840.10045.4.3.2");
+        assert_eq!(certificate.subject.to_string(), "C=US, O=Example, CN=example.com");
+        assert_eq!(certificate.subject.common_name(), Some("example.com"));
+        assert!(certificate.is_self_issued());
+        assert_eq!(certificate.not_before.to_string(), "2024-01-01T00:00:00Z");
+        assert_eq!(certificate.not_before.unix_time(), 1704067200);
+        // past 2049, so a GeneralizedTime
+        assert_eq!(certificate.not_after.to_string(), "2050-01-01T00:00:00Z");
+        assert!(certificate.is_valid_at(1704067200));
+        assert!(!certificate.is_valid_at(1704067199));
+        assert_eq!(certificate.public_key_algorithm, EC_PUBLIC_KEY);
+        assert_eq!(certificate.public_key_algorithm.name(), Some("id-ecPublicKey"));
+        assert_eq!(certificate.public_key_curve.map(|c| c.to_string()), Some("1.2.840.10045.3.1.7".to_string()));
+        assert_eq!(certificate.public_key.len(), 65);
+        assert_eq!(certificate.subject_alt_names(), Some(vec![
+            GeneralName::Dns("example.com"),
+            GeneralName::Dns("*.example.com"),
+            GeneralName::Ip("192.0.2.1".parse().unwrap()),
+            GeneralName::Email("admin@example.com"),
+        ]));
+        assert_eq!(certificate.dns_names(), vec!["example.com", "*.example.com"]);
+        let constraints = certificate.extension(Oid(&[0x55, 0x1d, 0x13])).unwrap();
+        assert!(constraints.critical);
+
+        // cut short anywhere, it's an error rather than a panic
+        for length in 0..der.len() {
+            assert!(parse_certificate(&der[..length]).is_err());
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use dns::{self, Class, DomainName, Message, OwnedRdata, Qtype, Rcode, ResourceRecord, Type, QR};
+
+// CNAMEs followed within the zone before giving up on a chain
+const MAX_CNAME_CHAIN: usize = 8;
+
+/// A record in a `Zone`. `name` is lowercase and has no trailing dot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ZoneRecord {
+    pub name: String,
+    pub typ: Type,
+    pub ttl: u32,
+    pub rdata: OwnedRdata,
+}
+
+impl ZoneRecord {
+    fn to_rr<'a>(&'a self, name: DomainName<'a>) -> ResourceRecord<'a> {
+        ResourceRecord {
+            name,
+            typ: self.typ,
+            class: Class::IN,
+            ttl: self.ttl,
+            rdata: self.rdata.to_borrowed(),
+        }
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_lowercase()
+}
+
+fn domain_name(name: &str) -> DomainName<'_> {
+    DomainName::Labels(name.split('.').filter(|label| !label.is_empty()).map(str::as_bytes).collect())
+}
+
+// whether `name` is `origin` or below it
+fn in_zone(name: &str, origin: &str) -> bool {
+    origin.is_empty() || name == origin || name.ends_with(&format!(".{}", origin))
+}
+
+/// The records of one zone, answering queries for it the way an
+/// authoritative server would: exact matches, CNAMEs followed within the
+/// zone, wildcards, and NXDOMAIN or NODATA with the SOA otherwise.
+/// Delegations and DNSSEC aren't handled. Handy for test fixtures and
+/// honeypots.
+#[derive(Clone, Debug, Default)]
+pub struct Zone {
+    origin: String,
+    records: Vec<ZoneRecord>,
+}
+
+impl Zone {
+    pub fn new(origin: &str) -> Zone {
+        Zone {
+            origin: normalize(origin),
+            records: vec![],
+        }
+    }
+
+    /// Adds a record; `name` can be relative to the origin, or "@" for the
+    /// origin itself, like in a zone file. Names ending in a dot are taken
+    /// as they are.
+    pub fn add(&mut self, name: &str, typ: Type, ttl: u32, rdata: OwnedRdata) {
+        let name = if name == "@" {
+            self.origin.clone()
+        } else if name.ends_with('.') || self.origin.is_empty() {
+            normalize(name)
+        } else {
+            normalize(&format!("{}.{}", name, self.origin))
+        };
+        self.records.push(ZoneRecord { name, typ, ttl, rdata });
+    }
+
+    pub fn records(&self) -> &[ZoneRecord] {
+        &self.records
+    }
+
+    fn soa(&self) -> Option<&ZoneRecord> {
+        self.records.iter().find(|rr| rr.typ == Type::SOA && rr.name == self.origin)
+    }
+
+    // whether anything is at or below `name`, so it exists even if only as
+    // an empty non-terminal
+    fn exists(&self, name: &str) -> bool {
+        self.records.iter().any(|rr| in_zone(&rr.name, name))
+    }
+
+    /// The records a name resolves to in the zone, which are a wildcard's
+    /// if there's nothing at the name itself
+    fn lookup(&self, name: &str) -> Option<Vec<&ZoneRecord>> {
+        if self.exists(name) {
+            return Some(self.records.iter().filter(|rr| rr.name == name).collect());
+        }
+        // the wildcard under the closest encloser, if there is one
+        let mut ancestor = name;
+        while let Some(dot) = ancestor.find('.') {
+            ancestor = &ancestor[dot + 1..];
+            if !in_zone(ancestor, &self.origin) {
+                break;
+            }
+            if self.exists(ancestor) {
+                let wildcard = format!("*.{}", ancestor);
+                let records: Vec<&ZoneRecord> = self.records.iter().filter(|rr| rr.name == wildcard).collect();
+                return if records.is_empty() { None } else { Some(records) };
+            }
+        }
+        None
+    }
+
+    /// Builds the response to a query, ready to be written out. Queries for
+    /// names outside the zone are refused, and anything but a standard
+    /// query with one question gets NOTIMP or FORMERR.
+    pub fn respond<'a>(&'a self, query: &Message<'a>) -> Message<'a> {
+        let mut response = Message {
+            header: query.header,
+            questions: query.questions.clone(),
+            answers: vec![],
+            authorities: vec![],
+            additional: vec![],
+        };
+        response.header.qr = QR::Response;
+        response.header.tc = false;
+        response.header.ra = false;
+        response.header.rcode = Rcode::NoError;
+        response.header.ancount = 0;
+        response.header.nscount = 0;
+        response.header.arcount = 0;
+
+        if query.header.opcode != dns::Opcode::Query {
+            response.header.rcode = Rcode::NotImplemented;
+            return response;
+        }
+        if query.header.qr != QR::Query || query.questions.len() != 1 {
+            response.header.rcode = Rcode::FormatError;
+            return response;
+        }
+        let question = &query.questions[0];
+        let qname = normalize(&question.qname.to_string());
+        if !in_zone(&qname, &self.origin) {
+            // REFUSED, which Rcode has no name for
+            response.header.rcode = Rcode::Reserved(5);
+            return response;
+        }
+        response.header.aa = true;
+
+        let mut name = question.qname.clone();
+        let mut current = qname;
+        for _ in 0..MAX_CNAME_CHAIN {
+            let records = match self.lookup(&current) {
+                Some(records) => records,
+                None => {
+                    // a dangling CNAME still answers with NOERROR
+                    if response.answers.is_empty() {
+                        response.header.rcode = Rcode::NameError;
+                    }
+                    break;
+                },
+            };
+            let wanted = |typ: Type| match question.qtype {
+                Qtype::Type(t) => t == typ,
+                Qtype::Wildcard => true,
+                _ => false,
+            };
+            let matching: Vec<&ZoneRecord> = records.iter().cloned().filter(|rr| wanted(rr.typ)).collect();
+            if !matching.is_empty() {
+                response.answers.extend(matching.iter().map(|rr| rr.to_rr(name.clone())));
+                break;
+            }
+            let cname = records.iter().find(|rr| rr.typ == Type::Cname);
+            match cname.map(|rr| (rr, &rr.rdata)) {
+                Some((rr, OwnedRdata::Cname(target))) => {
+                    response.answers.push(rr.to_rr(name));
+                    let target = target.to_borrowed();
+                    current = normalize(&target.to_string());
+                    name = target;
+                    if !in_zone(&current, &self.origin) {
+                        break;
+                    }
+                },
+                _ => break,
+            }
+        }
+
+        if response.answers.is_empty() {
+            if let Some(soa) = self.soa() {
+                response.authorities.push(soa.to_rr(domain_name(&soa.name)));
+            }
+        }
+        response.header.ancount = response.answers.len() as u16;
+        response.header.nscount = response.authorities.len() as u16;
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dns::{OwnedDomainName, OwnedSoa};
+    use ToBytes;
+
+    fn name(s: &str) -> OwnedDomainName {
+        OwnedDomainName::Labels(s.split('.').map(|label| label.as_bytes().to_vec()).collect())
+    }
+
+    #[test]
+    fn test_zone() {
+        let mut zone = Zone::new("example.com.");
+        zone.add("@", Type::SOA, 3600, OwnedRdata::Soa(OwnedSoa {
+            mname: name("ns.example.com"),
+            rname: name("hostmaster.example.com"),
+            serial: 1,
+            refresh: 7200,
+            retry: 900,
+            expire: 1209600,
+            minimum: 300,
+        }));
+        zone.add("www", Type::A, 300, OwnedRdata::A(vec![192, 0, 2, 1]));
+        zone.add("alias", Type::Cname, 300, OwnedRdata::Cname(name("www.example.com")));
+        zone.add("*.dyn", Type::A, 60, OwnedRdata::A(vec![192, 0, 2, 2]));
+        zone.add("host.deep.sub", Type::A, 60, OwnedRdata::A(vec![192, 0, 2, 3]));
+
+        let ask = |name: &'static str, typ: Type| {
+            let query = Message::query(7, name, Qtype::Type(typ));
+            let response = zone.respond(&query);
+            // everything has to survive a trip through the wire format
+            let bs = response.to_bytes();
+            let (_, parsed) = dns::parse_dns_message_full(&bs).unwrap();
+            assert_eq!(parsed, response);
+            (response.header.rcode, response.to_string(), response.authorities.len())
+        };
+
+        assert_eq!(ask("WWW.example.com", Type::A), (Rcode::NoError, "response A WWW.example.com: A 192.0.2.1".to_string(), 0));
+        assert_eq!(ask("alias.example.com", Type::A).1, "response A alias.example.com: Cname www.example.com, A 192.0.2.1");
+        assert_eq!(ask("foo.dyn.example.com", Type::A).1, "response A foo.dyn.example.com: A 192.0.2.2");
+        assert_eq!(ask("a.b.dyn.example.com", Type::A).1, "response A a.b.dyn.example.com: A 192.0.2.2");
+        // an empty non-terminal, and a name that doesn't exist
+        assert_eq!(ask("sub.example.com", Type::A), (Rcode::NoError, "response A sub.example.com".to_string(), 1));
+        assert_eq!(ask("nope.example.com", Type::A), (Rcode::NameError, "response A nope.example.com NameError".to_string(), 1));
+        assert_eq!(ask("www.example.com", Type::MX).2, 1);
+        assert_eq!(ask("www.example.org", Type::A).0, Rcode::Reserved(5));
+    }
+}